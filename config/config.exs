@@ -31,7 +31,10 @@ config :bastille,
       target_block_time: 10_000,  # 10 seconds
       difficulty_adjustment_interval: 10,  # blocks
       max_difficulty_change_factor: 4.0,
-      minimum_difficulty: 1
+      minimum_difficulty: 1,
+      # :blake3, or :randomx for the CPU-mining profile (needs the "randomx"
+      # crypto NIF feature and a randomx_key, 1 to 60 bytes, shared by the chain)
+      pow_function: :blake3
     }
   ],
 
@@ -57,7 +60,22 @@ config :bastille,
   storage: [
     base_path: "data/test",
     node_prefix: nil  # Optional prefix for multi-node setups (e.g., "node1", "node2")
-  ]
+  ],
+
+  # Cargo features of the crypto NIF, e.g. ["remote-signer"] for HSM-backed signing.
+  # To build without SIMD, use ["portable"] with crypto_nif_default_features: false:
+  # "portable" and the default "simd" feature are mutually exclusive.
+  # Add "mimalloc" or "jemalloc" to replace the system allocator inside the NIF.
+  # "verkle" adds the experimental Verkle tree (verkle_* functions), "rocksdb" the
+  # storage functions (storage_*; building RocksDB needs clang), "lmdb" a
+  # lighter backend for them, for small nodes and CI, "randomx" the RandomX
+  # proof of work (randomx_*; building RandomX needs CMake), and "wasm" the
+  # WebAssembly contract runtime (wasm_*).
+  crypto_nif_features: [],
+
+  # Threads for batch verification, parallel hashing and async signing.
+  # 0 uses one per CPU; lower it when the node shares a host with other services.
+  crypto_threads: 0
   # Note: Using 4-database architecture (blocks, chain, state, index) - no single bastille.cubdb
 
 # Environment-specific configuration
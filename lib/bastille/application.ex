@@ -9,6 +9,8 @@ defmodule Bastille.Application do
 
   use Application
 
+  require Logger
+
   # New feature-oriented aliases
   alias Bastille.Features.Chain.Chain
   alias Bastille.Features.Mining.{MiningCoordinator, ProofOfWork}
@@ -21,12 +23,17 @@ defmodule Bastille.Application do
   alias Bastille.Infrastructure.Storage.CubDB.{Blocks, State, Index}
   alias Bastille.Infrastructure.Storage.CubDB.Chain, as: ChainStorage
   alias Bastille.Features.Consensus.Engine
+  alias Bastille.Infrastructure.Crypto.CryptoNif
+  alias Bastille.Infrastructure.Storage.CubDB.Paths
 
   @impl true
   def start(_type, _args) do
-    # Set storage base path for Rust NIFs to use same path as Elixir
-    storage_base_path = Application.get_env(:bastille, :storage_base_path, "data/test")
-    System.put_env("BASTILLE_STORAGE_BASE_PATH", storage_base_path)
+    # Point the Rust key cache at this node's storage directory
+    configure_key_cache()
+    # Remember recently verified signatures (off unless :verify_cache_entries is set)
+    :ok = CryptoNif.configure_verify_cache(Application.get_env(:bastille, :verify_cache_entries, 0))
+    # Cap the native crypto threads (0, the default, means one per CPU)
+    :ok = CryptoNif.set_thread_pool(Application.get_env(:bastille, :crypto_threads, 0))
 
     # Extract mining configuration with pipeline
     validator_config =
@@ -105,6 +112,37 @@ defmodule Bastille.Application do
     ]
   end
 
+  defp configure_key_cache do
+    path = Path.join(Paths.base_path(), "key_cache")
+    opts = Application.get_env(:bastille, :key_cache, [])
+
+    case CryptoNif.configure_key_cache(path, opts) do
+      :ok ->
+        :ok
+
+      {:error, reason} ->
+        Logger.info("Persistent key cache disabled: #{reason}")
+        warn_about_legacy_keys(path)
+    end
+  end
+
+  # Plaintext keypairs from before the cache was encrypted wait for a key to be migrated
+  defp warn_about_legacy_keys(path) do
+    case CryptoNif.key_cache_legacy_entries(path) do
+      0 ->
+        :ok
+
+      count when is_integer(count) ->
+        Logger.warning(
+          "#{count} plaintext keypair(s) from an older release remain in #{path}; " <>
+            "configure a key cache key (:key_cache or BASTILLE_KEY_CACHE_KEY) to encrypt them"
+        )
+
+      {:error, reason} ->
+        Logger.warning("Could not check #{path} for plaintext keypairs: #{reason}")
+    end
+  end
+
   # Guard for valid children
   defp valid_child?(child) when child != nil, do: true
   defp valid_child?(_), do: false
@@ -4,12 +4,18 @@ defmodule Bastille.Features.Mining.ProofOfWork do
 
   This implementation follows Bitcoin's PoW model but uses Blake3 instead of SHA-256
   for superior performance while maintaining cryptographic security.
+
+  For the CPU-mining profile, `pow_function: :randomx` in the consensus config
+  checks work with RandomX instead (the crypto NIF's randomx feature), keyed by
+  `randomx_key`; `randomx_mode: :fast` builds the 2 GiB dataset for mining
+  rather than the light-mode cache. Block hashes stay Blake3 either way.
   """
 
   require Logger
 
   alias Bastille.Features.Block.Block
   alias Bastille.Features.Mining.Mining
+  alias Bastille.Infrastructure.Crypto.CryptoNif
 
   @behaviour Bastille.Features.Consensus.Behaviour
 
@@ -22,7 +28,9 @@ defmodule Bastille.Features.Mining.ProofOfWork do
     :max_difficulty_change_factor,
     :minimum_difficulty,
     :current_difficulty,
-    :max_target
+    :max_target,
+    :pow_function,
+    :randomx
   ]
 
   @type t :: %__MODULE__{
@@ -31,7 +39,9 @@ defmodule Bastille.Features.Mining.ProofOfWork do
     max_difficulty_change_factor: float(),
     minimum_difficulty: pos_integer(),
     current_difficulty: pos_integer(),
-    max_target: integer()
+    max_target: integer(),
+    pow_function: :blake3 | :randomx,
+    randomx: reference() | nil
   }
 
   @default_target_block_time 10_000  # 10 seconds target
@@ -42,6 +52,12 @@ defmodule Bastille.Features.Mining.ProofOfWork do
 
   @impl true
   def init(config \\ %{}) do
+    with {:ok, pow_function, randomx} <- init_pow_function(config) do
+      init_state(config, pow_function, randomx)
+    end
+  end
+
+  defp init_state(config, pow_function, randomx) do
     max_target = Map.get(config, :max_target, 0)
 
     state = %__MODULE__{
@@ -50,14 +66,43 @@ defmodule Bastille.Features.Mining.ProofOfWork do
       max_difficulty_change_factor: Map.get(config, :max_difficulty_change_factor, @default_max_difficulty_change_factor),
       minimum_difficulty: Map.get(config, :minimum_difficulty, @default_minimum_difficulty),
       current_difficulty: Map.get(config, :initial_difficulty, @default_initial_difficulty),
-      max_target: max_target
+      max_target: max_target,
+      pow_function: pow_function,
+      randomx: randomx
     }
 
     target_mode = if max_target > 0, do: "TEST (ultra-easy)", else: "PRODUCTION (real)"
-    Logger.info("🔍 Blake3 Proof of Work initialized - #{target_mode} targets")
+    Logger.info("🔍 #{pow_name(state)} Proof of Work initialized - #{target_mode} targets")
     {:ok, state}
   end
 
+  defp init_pow_function(config) do
+    case Map.get(config, :pow_function, :blake3) do
+      :blake3 ->
+        {:ok, :blake3, nil}
+
+      :randomx ->
+        with {:ok, key} <- Map.fetch(config, :randomx_key),
+             cache when is_reference(cache) <- CryptoNif.randomx_init_cache(key),
+             handle when is_reference(handle) <- randomx_handle(cache, Map.get(config, :randomx_mode, :light)) do
+          {:ok, :randomx, handle}
+        else
+          :error -> {:error, :missing_randomx_key}
+          {:error, reason} -> {:error, {:randomx, reason}}
+        end
+
+      other ->
+        {:error, {:unknown_pow_function, other}}
+    end
+  end
+
+  defp randomx_handle(cache, :light), do: cache
+  defp randomx_handle(cache, :fast), do: CryptoNif.randomx_init_dataset(cache)
+  defp randomx_handle(_cache, mode), do: {:error, {:unknown_randomx_mode, mode}}
+
+  defp pow_name(%__MODULE__{pow_function: :randomx}), do: "RandomX"
+  defp pow_name(_state), do: "Blake3"
+
   @impl true
   def mine_block(%Bastille.Features.Block.Block{header: %{difficulty: block_difficulty}} = block, %__MODULE__{} = state) do
     target = if state.max_target > 0 do
@@ -67,8 +112,8 @@ defmodule Bastille.Features.Mining.ProofOfWork do
     end
 
     target_type = if state.max_target > 0, do: "TEST", else: "PRODUCTION"
-    Logger.info("⚡ Mining block #{block.header.index} with Blake3 - #{target_type} difficulty: #{block_difficulty}")
-    mine_block_simple(block, target)
+    Logger.info("⚡ Mining block #{block.header.index} with #{pow_name(state)} - #{target_type} difficulty: #{block_difficulty}")
+    mine_block_simple(block, target, state)
   end
 
   @impl true
@@ -76,7 +121,7 @@ defmodule Bastille.Features.Mining.ProofOfWork do
     if state.max_target > 0 do
       validate_proof_of_work_with_configured_target(block, state)
     else
-      validate_proof_of_work(block)
+      validate_proof_of_work(block, state)
     end
   end
 
@@ -123,7 +168,7 @@ defmodule Bastille.Features.Mining.ProofOfWork do
       current_difficulty: state.current_difficulty,
       target_block_time: state.target_block_time,
       difficulty_adjustment_interval: state.difficulty_adjustment_interval,
-      algorithm: "blake3",
+      algorithm: Atom.to_string(state.pow_function),
       style: "bitcoin_like",
       performance: "high_speed"
     }
@@ -142,8 +187,8 @@ defmodule Bastille.Features.Mining.ProofOfWork do
   @spec mine_block_for_test(Block.t(), %__MODULE__{}) :: {:ok, Block.t()} | {:error, term()}
   def mine_block_for_test(%Bastille.Features.Block.Block{header: %{difficulty: block_difficulty}} = block, %__MODULE__{} = state) do
     target = calculate_configured_target(block_difficulty, state)
-    Logger.info("⚡ TEST Mining block #{block.header.index} with Blake3 - configured difficulty: #{block_difficulty}")
-    mine_block_simple(block, target)
+    Logger.info("⚡ TEST Mining block #{block.header.index} with #{pow_name(state)} - configured difficulty: #{block_difficulty}")
+    mine_block_simple(block, target, state)
   end
 
   @doc """
@@ -156,25 +201,21 @@ defmodule Bastille.Features.Mining.ProofOfWork do
 
   # ===== BITCOIN-LIKE IMPLEMENTATION WITH BLAKE3 =====
 
-  defp validate_proof_of_work(%Bastille.Features.Block.Block{hash: block_hash, header: %{difficulty: difficulty, nonce: nonce}} = block) do
+  defp validate_proof_of_work(%Bastille.Features.Block.Block{hash: block_hash, header: %{difficulty: difficulty, nonce: nonce}} = block, state) do
     case block_hash do
       hash when is_binary(hash) and byte_size(hash) == 32 ->
         # Simple validation: recalculate hash and check if it meets target
-        block_data = serialize_block_for_mining(block)
-        expected_hash = blake3_hash(block_data <> <<nonce::little-64>>)
-
-        cond do
-          expected_hash != hash ->
-            Logger.warning("Hash mismatch for block #{block.header.index}")
-            {:error, :invalid_hash}
+        mining_data = serialize_block_for_mining(block) <> <<nonce::little-64>>
+        expected_hash = blake3_hash(mining_data)
 
-          not hash_meets_difficulty?(hash, difficulty) ->
-            Logger.warning("Hash doesn't meet difficulty for block #{block.header.index}")
-            {:error, :invalid_difficulty}
-
-          true ->
+        with {:ok, pow} <- check_pow_hash(expected_hash, hash, mining_data, block, state) do
+          if hash_meets_difficulty?(pow, difficulty) do
             Logger.debug("Block #{block.header.index} validation successful")
             :ok
+          else
+            Logger.warning("Hash doesn't meet difficulty for block #{block.header.index}")
+            {:error, :invalid_difficulty}
+          end
         end
 
       _ ->
@@ -183,13 +224,13 @@ defmodule Bastille.Features.Mining.ProofOfWork do
     end
   end
 
-  defp mine_block_simple(block, target_int) do
+  defp mine_block_simple(block, target_int, state) do
     block_data = serialize_block_for_mining(block)
     start_time = System.monotonic_time(:millisecond)
 
-    Logger.info("Starting simple Blake3 mining...")
+    Logger.info("Starting simple #{pow_name(state)} mining...")
 
-    case find_valid_nonce(block_data, target_int, 0) do
+    case find_valid_nonce(block_data, target_int, 0, state) do
       {:ok, nonce, _hash} ->
         elapsed = System.monotonic_time(:millisecond) - start_time
         hash_rate = if elapsed > 0, do: round(nonce / elapsed * 1000), else: 0
@@ -210,29 +251,34 @@ defmodule Bastille.Features.Mining.ProofOfWork do
     end
   end
 
-  defp find_valid_nonce(block_data, target_int, nonce) do
+  defp find_valid_nonce(block_data, target_int, nonce, state) do
     # Simple incremental nonce search with batching for performance
-    find_nonce_batch(block_data, target_int, nonce, @batch_size)
+    find_nonce_batch(block_data, target_int, nonce, @batch_size, state)
   end
 
-  defp find_nonce_batch(block_data, target_int, start_nonce, batch_size) do
+  defp find_nonce_batch(block_data, target_int, start_nonce, batch_size, state) do
     Enum.reduce_while(start_nonce..(start_nonce + batch_size - 1), nil, fn nonce, _acc ->
       # Create mining data with nonce
       mining_data = block_data <> <<nonce::little-64>>
 
-      # Single Blake3 hash (Bitcoin-like, but with Blake3)
-      hash = blake3_hash(mining_data)
-
-      # Check if hash meets target
-      if hash_meets_target?(hash, target_int) do
-        {:halt, {:ok, nonce, hash}}
-      else
-        {:cont, nil}
+      # Single Blake3 hash (Bitcoin-like, but with Blake3), or RandomX
+      case pow_hash(mining_data, state) do
+        {:ok, hash} ->
+          # Check if hash meets target
+          if hash_meets_target?(hash, target_int) do
+            {:halt, {:ok, nonce, hash}}
+          else
+            {:cont, nil}
+          end
+
+        {:error, reason} ->
+          {:halt, {:error, {:pow_hash_failed, reason}}}
       end
     end)
     |> case do
       {:ok, nonce, hash} -> {:ok, nonce, hash}
-      nil -> find_nonce_batch(block_data, target_int, start_nonce + batch_size, batch_size)
+      {:error, reason} -> {:error, reason}
+      nil -> find_nonce_batch(block_data, target_int, start_nonce + batch_size, batch_size, state)
     end
   end
 
@@ -241,6 +287,29 @@ defmodule Bastille.Features.Mining.ProofOfWork do
     Mining.blake3_hash(data)
   end
 
+  # The hash checked against the target; the block hash itself stays Blake3
+  defp pow_hash(data, %__MODULE__{pow_function: :randomx, randomx: randomx}) do
+    case CryptoNif.randomx_hash(randomx, data) do
+      hash when is_binary(hash) -> {:ok, hash}
+      {:error, reason} -> {:error, reason}
+    end
+  end
+
+  defp pow_hash(data, _state), do: {:ok, blake3_hash(data)}
+
+  # The proof-of-work hash of a block, once its stored hash matches the recomputed one
+  defp check_pow_hash(expected_hash, hash, _mining_data, block, _state) when expected_hash != hash do
+    Logger.warning("Hash mismatch for block #{block.header.index}")
+    {:error, :invalid_hash}
+  end
+
+  defp check_pow_hash(_expected_hash, _hash, mining_data, block, state) do
+    with {:error, reason} <- pow_hash(mining_data, state) do
+      Logger.warning("Proof-of-work hash failed for block #{block.header.index}: #{inspect(reason)}")
+      {:error, {:pow_hash_failed, reason}}
+    end
+  end
+
   defp hash_meets_target?(hash, target_int) when is_integer(target_int) do
     # Convert target back to binary and use centralized validation
     target_binary = <<target_int::256>>
@@ -274,24 +343,20 @@ defmodule Bastille.Features.Mining.ProofOfWork do
     |> :binary.decode_unsigned(:big)
   end
 
-  defp validate_proof_of_work_with_configured_target(%Bastille.Features.Block.Block{hash: block_hash, header: %{difficulty: difficulty, nonce: nonce}} = block, %__MODULE__{max_target: max_target}) do
+  defp validate_proof_of_work_with_configured_target(%Bastille.Features.Block.Block{hash: block_hash, header: %{difficulty: difficulty, nonce: nonce}} = block, %__MODULE__{max_target: max_target} = state) do
     case block_hash do
       hash when is_binary(hash) and byte_size(hash) == 32 ->
         # Simple validation: recalculate hash and check if it meets configured target
-        block_data = serialize_block_for_mining(block)
-        expected_hash = blake3_hash(block_data <> <<nonce::little-64>>)
-
-        cond do
-          expected_hash != hash ->
-            Logger.warning("Hash mismatch for CONFIGURED block #{block.header.index}")
-            {:error, :invalid_hash}
+        mining_data = serialize_block_for_mining(block) <> <<nonce::little-64>>
+        expected_hash = blake3_hash(mining_data)
 
-          not hash_meets_configured_difficulty?(hash, difficulty, max_target) ->
+        with {:ok, pow} <- check_pow_hash(expected_hash, hash, mining_data, block, state) do
+          if hash_meets_configured_difficulty?(pow, difficulty, max_target) do
+            :ok
+          else
             Logger.warning("Hash does not meet CONFIGURED difficulty #{difficulty} for block #{block.header.index}")
             {:error, :insufficient_difficulty}
-
-          true ->
-            :ok
+          end
         end
 
       _ ->
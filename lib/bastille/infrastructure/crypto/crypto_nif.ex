@@ -4,9 +4,21 @@ defmodule Bastille.Infrastructure.Crypto.CryptoNif do
 
   This module provides direct access to Rust-implemented cryptographic functions
   for maximum performance and security.
+
+  Message and data arguments of the hashing, signing and verification functions
+  are read in place, never copied, so sub-binaries (`binary_part/3`, pattern
+  matches into a larger block) cost nothing extra to pass. The exceptions are
+  small: secret keys passed as binaries are copied into a transient buffer that
+  is wiped after use, public keys and signatures are parsed into fixed-size
+  structures, and `k12_hash/3` copies its customization string and the final
+  chunk of data it overlaps with.
   """
 
-  use Rustler, otp_app: :bastille, crate: "bastille_crypto"
+  use Rustler,
+    otp_app: :bastille,
+    crate: "bastille_crypto",
+    default_features: Application.compile_env(:bastille, :crypto_nif_default_features, true),
+    features: Application.compile_env(:bastille, :crypto_nif_features, [])
 
   # === NIF Status ===
 
@@ -20,6 +32,82 @@ defmodule Bastille.Infrastructure.Crypto.CryptoNif do
   """
   def get_algorithm_info, do: :erlang.nif_error(:nif_not_loaded)
 
+  @doc """
+  The global allocator the native library was built with: `:system`, `:mimalloc`
+  or `:jemalloc` (see the `crypto_nif_features` config).
+  """
+  def allocator_info, do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Report the CPU's SIMD support and the implementation each primitive uses.
+
+  Returns a map with `:avx2`, `:avx512` and `:neon` (detected at runtime), `:simd`
+  (whether the optimized variants were compiled in) and `:backends`, mapping
+  `:blake3`, `:dilithium2`, `:falcon512`, `:sphincsplus` and `:hex` to the
+  implementation in use, such as `:avx2`, `:neon`, `:sse2`, `:clean` or `:portable`.
+
+  To force the portable code paths (for reproducibility testing), build with
+  `config :bastille, crypto_nif_default_features: false, crypto_nif_features: ["portable"]`.
+  """
+  def cpu_features, do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Version of the seed-to-keypair derivation spec used by the `*_keypair_from_seed` NIFs.
+
+  Derived keypairs are kept in a bounded in-memory cache, and cached on disk only once
+  `configure_key_cache/2` has been called.
+  """
+  def keygen_spec_version, do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Enable the persistent keypair cache in directory `path`, or disable it with `nil`.
+  Entries are encrypted with XChaCha20-Poly1305 under the cache key given in `opts`:
+
+    * `:key` - 32 raw bytes
+    * `:passphrase` - stretched with Argon2id and a salt stored in `path`
+    * `:max_entries` - entries kept before evicting the least recently used (default 4096)
+    * `:max_bytes` - total size kept before evicting (default 64 MiB)
+    * `:memory_entries` - size of the in-memory cache in front of the files (default 256)
+
+  Without either option, `BASTILLE_KEY_CACHE_KEY` (64 hex characters) or
+  `BASTILLE_KEY_CACHE_PASSPHRASE` is read from the environment. Returns
+  `{:error, reason}` when no key is available; nothing is ever written in plaintext.
+
+  Plaintext entries left in `path` by releases before the cache was encrypted
+  hold random keypairs that seeded derivation never returns, possibly the only
+  copy of a funded key. Once a key is available, each is sealed under it into
+  a `<name>.legacy` file in the same format, which eviction and
+  `purge_key_cache/0` leave alone, and only then overwritten and deleted.
+  Without a key they are left as they are (see `key_cache_legacy_entries/1`).
+  """
+  def configure_key_cache(_path, _opts), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Count the plaintext entries from before the cache was encrypted still in
+  `path`, which `configure_key_cache/2` migrates once it has a key.
+  """
+  def key_cache_legacy_entries(_path), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Delete every entry of the configured key cache and return how many files were
+  removed. The in-memory cache is cleared too.
+  """
+  def purge_key_cache, do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Key cache counters since the NIF was loaded, as a map with `:memory_hits`,
+  `:disk_hits`, `:misses` and the current `:memory_entries`.
+  """
+  def key_cache_stats, do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Derive a 32-byte child seed from a master seed (16 to 64 bytes) along `path`,
+  a list of indices below 2^31. Every level is hardened, SLIP-0010 style, with
+  the HMAC key `"Bastille PQ seed"`. Pass the result to any `*_keypair_from_seed`
+  function, e.g. `[purpose, account]` for per-account staking or governance keys.
+  """
+  def derive_child_seed(_master_seed, _path), do: :erlang.nif_error(:nif_not_loaded)
+
   # === Dilithium NIFs ===
 
   @doc """
@@ -28,17 +116,27 @@ defmodule Bastille.Infrastructure.Crypto.CryptoNif do
   def dilithium2_keypair, do: :erlang.nif_error(:nif_not_loaded)
 
   @doc """
-  Generate a deterministic Dilithium2 keypair from seed.
+  Derive a deterministic Dilithium2 keypair from a seed of at least 16 bytes.
+  The same seed yields the same keys on every node (see `keygen_spec_version/0`).
   """
   def dilithium2_keypair_from_seed(_seed), do: :erlang.nif_error(:nif_not_loaded)
 
   @doc """
-  Sign a message with Dilithium2.
+  Generate a Dilithium2 keypair, returning `{public_key, handle}`. The secret key stays
+  in native memory (locked where possible, wiped on garbage collection) and the
+  handle can be passed to `dilithium2_sign/2` in place of the secret key bytes.
+  """
+  def dilithium2_keypair_handle, do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Sign a message with Dilithium2. The private key is either the secret key bytes
+  or a handle from `dilithium2_keypair_handle/0`.
   """
   def dilithium2_sign(_message, _private_key), do: :erlang.nif_error(:nif_not_loaded)
 
   @doc """
-  Verify a Dilithium2 signature.
+  Verify a Dilithium2 signature. The public key is either the key bytes or a handle
+  from `load_public_key/2`.
   """
   def dilithium2_verify(_signature, _message, _public_key), do: :erlang.nif_error(:nif_not_loaded)
 
@@ -50,17 +148,27 @@ defmodule Bastille.Infrastructure.Crypto.CryptoNif do
   def falcon512_keypair, do: :erlang.nif_error(:nif_not_loaded)
 
   @doc """
-  Generate a deterministic Falcon-512 keypair from seed.
+  Derive a deterministic Falcon-512 keypair from a seed of at least 16 bytes.
+  The same seed yields the same keys on every node (see `keygen_spec_version/0`).
   """
   def falcon512_keypair_from_seed(_seed), do: :erlang.nif_error(:nif_not_loaded)
 
   @doc """
-  Sign a message with Falcon-512.
+  Generate a Falcon-512 keypair, returning `{public_key, handle}`. The secret key stays
+  in native memory (locked where possible, wiped on garbage collection) and the
+  handle can be passed to `falcon512_sign/2` in place of the secret key bytes.
+  """
+  def falcon512_keypair_handle, do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Sign a message with Falcon-512. The private key is either the secret key bytes
+  or a handle from `falcon512_keypair_handle/0`.
   """
   def falcon512_sign(_message, _private_key), do: :erlang.nif_error(:nif_not_loaded)
 
   @doc """
-  Verify a Falcon-512 signature.
+  Verify a Falcon-512 signature. The public key is either the key bytes or a handle
+  from `load_public_key/2`.
   """
   def falcon512_verify(_signature, _message, _public_key), do: :erlang.nif_error(:nif_not_loaded)
 
@@ -72,24 +180,2302 @@ defmodule Bastille.Infrastructure.Crypto.CryptoNif do
   def sphincsplus_shake_128f_keypair, do: :erlang.nif_error(:nif_not_loaded)
 
   @doc """
-  Generate a deterministic SPHINCS+-SHAKE-128f keypair from seed.
+  Derive a deterministic SPHINCS+-SHAKE-128f keypair from a seed of at least 16 bytes.
+  The same seed yields the same keys on every node (see `keygen_spec_version/0`).
   """
   def sphincsplus_keypair_from_seed(_seed), do: :erlang.nif_error(:nif_not_loaded)
 
   @doc """
-  Sign a message with SPHINCS+-SHAKE-128f.
+  Generate a SPHINCS+-SHAKE-128f keypair, returning `{public_key, handle}`. The secret key stays
+  in native memory (locked where possible, wiped on garbage collection) and the
+  handle can be passed to `sphincsplus_shake_128f_sign/2` in place of the secret key bytes.
+  """
+  def sphincsplus_shake_128f_keypair_handle, do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Sign a message with SPHINCS+-SHAKE-128f. The private key is either the secret key bytes
+  or a handle from `sphincsplus_shake_128f_keypair_handle/0`.
   """
   def sphincsplus_shake_128f_sign(_message, _private_key), do: :erlang.nif_error(:nif_not_loaded)
 
   @doc """
-  Verify a SPHINCS+-SHAKE-128f signature.
+  Verify a SPHINCS+-SHAKE-128f signature. The public key is either the key bytes or a handle
+  from `load_public_key/2`.
   """
   def sphincsplus_shake_128f_verify(_signature, _message, _public_key), do: :erlang.nif_error(:nif_not_loaded)
 
+  # === Public Key Handles ===
+
+  @doc """
+  Parse a public key for `algorithm` (`:dilithium2`, `:falcon512` or `:sphincsplus`)
+  once and return a handle accepted by the `*_verify` and `batch_verify` functions
+  wherever the key bytes are. Meant for validator-set keys reused all epoch.
+  Raises `ArgumentError` if the bytes are not a key of that algorithm.
+  """
+  def load_public_key(_algorithm, _public_key), do: :erlang.nif_error(:nif_not_loaded)
+
+  # === Batch Signatures ===
+
+  @doc """
+  Verify a list of `{signature, message, public_key}` tuples for `algorithm`
+  (`:dilithium2`, `:falcon512` or `:sphincsplus`) on multiple threads. Public
+  keys may be bytes or handles from `load_public_key/2`.
+  Returns `true` only if every signature is valid.
+  """
+  def batch_verify(_algorithm, _items), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Verify a list of `{signature, message, public_key}` tuples with options.
+
+  ## Options
+    * `:failures` - when `true`, return the list of (0-based) indices of the
+      invalid items instead of a boolean; an empty list means all are valid
+  """
+  def batch_verify(_algorithm, _items, _opts), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Sign every message in `messages` with one `private_key` (secret key bytes or a
+  handle) for `algorithm`, returning the signatures in order. The key is parsed
+  once for the whole batch.
+  """
+  def sign_many(_algorithm, _private_key, _messages), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Sign a list of messages with options.
+
+  ## Options
+    * `:parallel` - when `true`, sign on multiple threads (default: `false`)
+  """
+  def sign_many(_algorithm, _private_key, _messages, _opts), do: :erlang.nif_error(:nif_not_loaded)
+
+  # === Aggregate Attestations ===
+
+  @doc """
+  Pack validator signatures over one 32-byte block `digest` into an aggregate
+  attestation: `signatures` is a list of `{validator_index, signature}` (0-based
+  indices into a set of `validator_count` validators, at most 65_535) and the
+  aggregate records the participants as a bitfield.
+
+  Post-quantum signatures can't be merged like BLS ones, so the aggregate keeps
+  every signature and drops the per-attestation digest, validator id and
+  framing. Signatures are not verified here. Raises `ArgumentError` for an empty
+  list, duplicate or out-of-range indices.
+  """
+  def attestation_aggregate(_algorithm, _digest, _validator_count, _signatures),
+    do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  `{digest, participants}` of `aggregate` if every signature in it is valid for
+  the validator's key in `public_keys` (the whole set, in index order; bytes or
+  handles). Signatures are checked on multiple threads.
+
+  Returns `{:error, reason}` for a malformed aggregate, a set of another size,
+  or invalid signatures, naming every validator whose signature failed.
+  """
+  def attestation_verify(_aggregate, _public_keys), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  `{algorithm, digest, validator_count, participants}` of `aggregate` without
+  verifying signatures, or `{:error, reason}`.
+  """
+  def attestation_decode(_aggregate), do: :erlang.nif_error(:nif_not_loaded)
+
+  # === Yielding Verification ===
+
+  @doc """
+  Verify `{signature, message, public_key}` tuples like `batch_verify/2`, but on
+  the calling process's normal scheduler instead of a dirty one.
+
+  The NIF verifies one item at a time and hands control back to the scheduler
+  whenever its ~1ms timeslice is used up, so other processes keep running.
+  Stops at the first invalid signature. Dilithium and Falcon verifications take
+  well under a millisecond each; a single SPHINCS+ verification can take longer,
+  so prefer `batch_verify/2` for SPHINCS+ where latency matters.
+  """
+  def batch_verify_yielding(algorithm, items) when is_list(items) do
+    case batch_verify_slice(algorithm, items) do
+      {:cont, rest} -> batch_verify_yielding(algorithm, rest)
+      valid -> valid
+    end
+  end
+
+  @doc """
+  Verify items until the timeslice runs out. Returns `true` or `false` once
+  every item is verified or one fails, or `{:cont, rest}` with the items still
+  to verify.
+  """
+  def batch_verify_slice(_algorithm, _items), do: :erlang.nif_error(:nif_not_loaded)
+
+  # === Verification Sessions ===
+
+  @doc """
+  Open a verification session pinning `algorithm` and a validator set, given as a
+  list of public keys. Every key is parsed once, here; raises `ArgumentError` if
+  any of them is not a valid key for `algorithm`.
+  """
+  def verify_session_new(_algorithm, _public_keys), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Verify a list of `{message, signature, key_index}` tuples against the session's
+  keys (`key_index` is 0-based into the list given to `verify_session_new/2`), on
+  multiple threads. Returns `true` only if every signature is valid; raises
+  `ArgumentError` for an index outside the validator set.
+
+  A session can be reused for any number of calls, e.g. one per chunk of a block.
+  """
+  def verify_session_verify(_session, _items), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Verify session items with options.
+
+  ## Options
+    * `:failures` - when `true`, return the (0-based) positions of the invalid
+      items instead of a boolean
+  """
+  def verify_session_verify(_session, _items, _opts), do: :erlang.nif_error(:nif_not_loaded)
+
+  # === Async Signing ===
+
+  @doc """
+  Queue a Dilithium2 signature on the native signing threads and return `:ok`
+  right away. The result is sent to `caller_pid` as `{ref, signature}`, or
+  `{ref, {:error, reason}}` if signing fails.
+
+  Returns `{:error, :busy}` when the queue (1024 jobs) is full, so callers can
+  back off. The private key is secret key bytes or a handle.
+  """
+  def dilithium2_sign_async(_message, _private_key, _caller_pid, _ref), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Queue a Falcon-512 signature; see `dilithium2_sign_async/4`.
+  """
+  def falcon512_sign_async(_message, _private_key, _caller_pid, _ref), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Queue a SPHINCS+-SHAKE-128f signature; see `dilithium2_sign_async/4`.
+  """
+  def sphincsplus_shake_128f_sign_async(_message, _private_key, _caller_pid, _ref),
+    do: :erlang.nif_error(:nif_not_loaded)
+
+  # === Verification Cache ===
+
+  @doc """
+  Keep up to `max_entries` (at most 4194304) recently verified valid signatures so
+  the same transaction isn't verified again during gossip, mempool admission,
+  block validation and re-org replay. `0` turns the cache off and empties it
+  (the default). Applies to every `*_verify` and batch function.
+  """
+  def configure_verify_cache(_max_entries), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Forget every cached verification result, e.g. after a key is revoked.
+  """
+  def clear_verify_cache, do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Verification cache counters as a map of `:hits`, `:misses`, `:entries` and
+  `:capacity`. Hits and misses are only counted while the cache is on.
+  """
+  def verify_cache_stats, do: :erlang.nif_error(:nif_not_loaded)
+
+  # === Thread Pool ===
+
+  @doc """
+  Run batch verification, `sign_many/4`, parallel hashing, Merkle roots and the
+  `*_sign_async` jobs on `size` native threads (at most 1024; `0` for one per CPU,
+  the default). Work already running finishes on the previous threads.
+
+  Set at startup from `config :bastille, crypto_threads: n`.
+  """
+  def set_thread_pool(_size), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Number of threads in the native crypto thread pool.
+  """
+  def thread_pool_size, do: :erlang.nif_error(:nif_not_loaded)
+
+  # === Telemetry ===
+
+  @doc """
+  Native call counters since the NIF was loaded, as a map from NIF name to
+  `%{calls: n, failures: n, total_ns: n}`.
+
+  Every NIF has an entry from the start, with zero counts until it is called,
+  and all arities of a function share one entry; wrappers written in Elixir,
+  like `blake3_hash/2`, are counted as the NIFs they call. A failure is a call
+  that returned an error or raised `ArgumentError`; a verification returning
+  `false` is not one.
+  """
+  def crypto_stats, do: :erlang.nif_error(:nif_not_loaded)
+
   # === Blake3 Hash ===
 
   @doc """
   Compute Blake3 hash of input data.
+  Inputs of 128 KiB or more are hashed on multiple threads, on a dirty scheduler.
+  """
+  def blake3_hash(data), do: blake3_hash(data, [])
+
+  @doc """
+  Compute Blake3 hash of input data with options.
+
+  Inputs of 128 KiB or more, and inputs hashed on multiple threads, are hashed on
+  a dirty scheduler; smaller ones on the calling process's scheduler.
+
+  ## Options
+    * `:parallel_threshold` - input size in bytes from which hashing is spread
+      across threads (default: 131072)
+  """
+  def blake3_hash(data, opts) when is_binary(data) and is_list(opts) do
+    threshold =
+      Enum.reduce(opts, 131_072, fn
+        {:parallel_threshold, threshold}, _ when is_integer(threshold) and threshold >= 0 -> threshold
+        option, _ -> raise ArgumentError, "unknown or invalid blake3_hash option: #{inspect(option)}"
+      end)
+
+    cond do
+      byte_size(data) >= threshold -> blake3_hash_dirty(data, true)
+      byte_size(data) >= 131_072 -> blake3_hash_dirty(data, false)
+      true -> blake3_hash_inline(data)
+    end
+  end
+
+  def blake3_hash(_data, _opts), do: raise(ArgumentError, "blake3_hash expects a binary and a keyword list")
+
+  @doc """
+  Blake3 hash of a small input, on the calling process's scheduler (see `blake3_hash/2`).
+  """
+  def blake3_hash_inline(_data), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Blake3 hash on a dirty scheduler, on multiple threads if `parallel` (see `blake3_hash/2`).
+  """
+  def blake3_hash_dirty(_data, _parallel), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Domain-separated Blake3 hash: `blake3(<<byte_size(domain)>> <> domain <> data)`.
+
+  The domain must be a non-empty ASCII label of at most 255 bytes, e.g.
+  `"bastille/tx-id"`, `"bastille/block-id"` or `"bastille/vote"`.
+  """
+  def tagged_hash(_domain, _data), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Compute Blake3 hash of a large binary without blocking the scheduler.
+
+  The native hasher processes the input in slices and returns control to the
+  scheduler about every millisecond, so a 100 MB block won't stall other processes.
+  """
+  def blake3_hash_yielding(data) when is_binary(data) do
+    hasher = blake3_hasher_new()
+    :ok = blake3_hasher_feed(hasher, data, 0)
+    blake3_hasher_finalize(hasher)
+  end
+
+  defp blake3_hasher_feed(hasher, data, offset) do
+    case blake3_hasher_update(hasher, data, offset) do
+      :ok -> :ok
+      {:cont, next_offset} -> blake3_hasher_feed(hasher, data, next_offset)
+    end
+  end
+
+  @doc """
+  Create an incremental Blake3 hasher resource.
+  """
+  def blake3_hasher_new, do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Feed `data` starting at `offset` into the hasher. Returns `:ok` once all data
+  is consumed, or `{:cont, next_offset}` when the timeslice ran out.
+  """
+  def blake3_hasher_update(_hasher, _data, _offset), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Return the 32-byte Blake3 digest of everything fed to the hasher so far.
+  """
+  def blake3_hasher_finalize(_hasher), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Compute a Blake3 digest of arbitrary length (extendable output, up to 1 MiB).
+  The first 32 bytes equal `blake3_hash/1`.
+  """
+  def blake3_hash_xof(_data, _output_len), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Compute a Blake3 keyed hash (MAC) of input data with a 32-byte key.
+  """
+  def blake3_keyed_hash(_key, _data), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Derive a 32-byte subkey from key material under a globally unique context string.
+  """
+  def blake3_derive_key(_context, _material), do: :erlang.nif_error(:nif_not_loaded)
+
+  # === Proof of Work ===
+
+  @doc """
+  Search `max_iterations` nonces from `start_nonce` for one whose hash meets
+  `target` (32 bytes, big-endian): the Blake3 hash of `header` followed by
+  the nonce as a little-endian 64-bit integer, as
+  `Bastille.Features.Mining.Mining.calculate_block_hash/1` computes it, must
+  not exceed it.
+
+  Runs on a dirty scheduler, spread over the thread pool (see
+  `set_thread_pool/1`). Returns the lowest winning nonce, whatever the
+  number of threads, or `:exhausted`; nonces don't wrap past 2^64 - 1.
+  """
+  def pow_mine(_header, _target, _start_nonce, _max_iterations), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Whether `nonce` solves `header` for `target`, as in `pow_mine/4`.
+  """
+  def pow_verify(_header, _nonce, _target), do: :erlang.nif_error(:nif_not_loaded)
+
+  # === Difficulty Targets ===
+
+  @doc """
+  256-bit target (32 bytes, big-endian) of compact `bits`, Bitcoin's nBits:
+  a size byte and a 23-bit mantissa.
+
+  Returns `{:error, reason}` for negative or overflowing values, and for any
+  encoding of a target other than the one `target_to_compact/1` gives.
+  """
+  def target_from_compact(_bits), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Compact bits of a 32-byte `target`, rounded down to its top 23 bits.
+  """
+  def target_to_compact(_target), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Whether the 32-byte `hash` is at most `target`, both big-endian.
+  """
+  def target_meets(_hash, _target), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Target after a window from `first_timestamp` to `last_timestamp` expected
+  to take `target_timespan` (in the same unit); see `next_target/5`.
+  """
+  def next_target(_target, _first_timestamp, _last_timestamp, _target_timespan),
+    do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Target after a window from `first_timestamp` to `last_timestamp` expected
+  to take `target_timespan`: `target` scaled by the ratio of the two
+  timespans, in exact 256-bit integer arithmetic. Options:
+
+    * `:max_factor` - the ratio is clamped to 1/max_factor..max_factor
+      (default 4)
+    * `:max_target` - the easiest target allowed, the result's cap (default
+      2^256 - 1)
+  """
+  def next_target(_target, _first_timestamp, _last_timestamp, _target_timespan, _opts),
+    do: :erlang.nif_error(:nif_not_loaded)
+
+  # === RandomX ===
+  # Needs the NIF built with the randomx feature (see :crypto_nif_features).
+
+  @doc """
+  RandomX handle in light mode for `key` (1 to 60 bytes): a 256 MiB cache,
+  enough for `randomx_hash/2` and `randomx_verify/4` at a few milliseconds
+  per hash, e.g. to validate blocks.
+  """
+  def randomx_init_cache(_key), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  RandomX handle in fast mode, for mining: the 2 GiB dataset expanded from
+  the cache of a `randomx_init_cache/1` handle, which takes about a minute on
+  a dirty scheduler. Hashes are the same as in light mode, many times faster.
+
+  Returns `{:error, reason}` if the dataset can't be allocated.
+  """
+  def randomx_init_dataset(_cache), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  32-byte RandomX hash of a non-empty `input`. Calls from several processes
+  run in parallel, each on its own VM.
+  """
+  def randomx_hash(_handle, _input), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Whether `nonce` solves `header` for `target`, as `pow_verify/3` with
+  RandomX in place of Blake3: the hash of `header` followed by the nonce as a
+  little-endian 64-bit integer must not exceed the 32-byte big-endian target.
+  """
+  def randomx_verify(_handle, _header, _nonce, _target), do: :erlang.nif_error(:nif_not_loaded)
+
+  # === SHA-3 Hash ===
+
+  @doc """
+  Compute the SHA3-256 (FIPS 202) hash of input data.
+  """
+  def sha3_256(_data), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Compute the SHA3-512 (FIPS 202) hash of input data.
+  """
+  def sha3_512(_data), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Compute the Ethereum-style Keccak-256 hash (original Keccak padding, not SHA3-256).
+  """
+  def keccak256(_data), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Compute SHAKE128 output of the requested length (up to 1 MiB).
+  """
+  def shake128(_data, _output_len), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Compute SHAKE256 output of the requested length (up to 1 MiB).
+  """
+  def shake256(_data, _output_len), do: :erlang.nif_error(:nif_not_loaded)
+
+  # === SHA-2 Hash ===
+
+  @doc """
+  Compute the SHA-256 hash of input data.
+  """
+  def sha256(_data), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Compute the double SHA-256 hash of input data (Bitcoin-style).
+  """
+  def sha256d(_data), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Compute the SHA-512 hash of input data.
+  """
+  def sha512(_data), do: :erlang.nif_error(:nif_not_loaded)
+
+  # === KangarooTwelve ===
+
+  @doc """
+  Compute KangarooTwelve (RFC 9861) with a customization string and output length.
+  Large inputs are tree-hashed in parallel; intended for non-consensus integrity checks.
+  """
+  def k12_hash(_data, _customization, _output_len), do: :erlang.nif_error(:nif_not_loaded)
+
+  # === BLAKE2b Hash ===
+
+  @doc """
+  Compute BLAKE2b with a digest length (1-64), key (up to 64 bytes), salt and
+  personalization (up to 16 bytes each). Pass `""` for unused parameters.
+  """
+  def blake2b(_data, _digest_len, _key, _salt, _personal), do: :erlang.nif_error(:nif_not_loaded)
+
+  # === Poseidon Hash (BN254) ===
+
+  @doc """
+  Poseidon hash of 1 to 12 BN254 scalar field elements, each a 32-byte
+  big-endian integer below the field modulus. Uses the circomlib parameters.
+  """
+  def poseidon_hash(_inputs), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Poseidon sponge over arbitrary bytes (31-byte chunks, length-bound initial state).
+  """
+  def poseidon_sponge(_data), do: :erlang.nif_error(:nif_not_loaded)
+
+  # === Merkle Trees ===
+
+  @doc """
+  Merkle root of `leaves` (a list of binaries), using BLAKE3 with domain tags:
+  `blake3(<<0>> <> leaf)` for leaves and `blake3(<<1>> <> left <> right)` for
+  interior nodes. An odd node at the end of a level is promoted unchanged, and
+  the root of an empty list is 32 zero bytes.
+
+  Large trees are hashed in parallel on a dirty scheduler.
+  """
+  def merkle_root(_leaves), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Build the Merkle tree over `leaves` (hashed as in `merkle_root/1`) and keep it
+  in native memory for serving proofs. Returns `{root, tree}`.
+  """
+  def merkle_build(_leaves), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Inclusion proof for the leaf at `index` (0-based) of a tree from
+  `merkle_build/1`. Raises `ArgumentError` if `index` is out of range.
+
+  The proof is a binary of 33-byte steps from the leaf up: a side byte (`0` when
+  the sibling is on the left, `1` when it is on the right) followed by the
+  sibling's hash. Levels where the node has no sibling are skipped.
+  """
+  def merkle_proof(_tree, _index), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Check that `leaf` (the leaf data, not its hash) is included under `root`
+  according to `proof`. Returns a boolean.
+  """
+  def merkle_verify(_root, _leaf, _proof), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  One combined proof for the leaves at `indices` (any order, duplicates
+  ignored) of a tree from `merkle_build/1`, much smaller than separate proofs
+  when the leaves share paths. Raises `ArgumentError` if `indices` is empty or
+  any index is out of range.
+
+  The proof is the leaf count as a 64-bit big-endian integer followed by the
+  32-byte hashes the verifier can't compute itself, level by level from the
+  leaves up, left to right within a level.
+  """
+  def merkle_multiproof(_tree, _indices), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Check a proof from `merkle_multiproof/2`: `leaves` is a list of
+  `{index, leaf}` pairs with distinct indices. Returns `true` only if every
+  leaf is at its index under `root`.
+  """
+  def merkle_verify_multiproof(_root, _leaves, _proof), do: :erlang.nif_error(:nif_not_loaded)
+
+  # === Incremental Merkle Trees ===
+
+  @doc """
+  New, empty incremental Merkle tree of fixed `depth` (1 to 63), in the style of
+  the Ethereum deposit contract: appends and `imt_root/1` cost one hash per
+  level, and only one node per level is kept.
+
+  Leaves and nodes hash as in `merkle_root/1`, empty leaves are 32 zero bytes,
+  and the root mixes in the leaf count as the deposit contract does:
+  `node(tree_root, <<count::little-64, 0::192>>)`.
+  """
+  def imt_new(_depth), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Append `leaf`, returning its 0-based index, or `{:error, reason}` once the
+  tree holds `2^depth - 1` leaves (the deposit contract's capacity).
+  """
+  def imt_append(_tree, _leaf), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Current 32-byte root of the tree.
+  """
+  def imt_root(_tree), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Number of leaves appended so far.
+  """
+  def imt_count(_tree), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Serialize the tree's state (depth, count and one node per level) to a binary
+  for `imt_restore/1`.
+  """
+  def imt_snapshot(_tree), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Rebuild a tree from `imt_snapshot/1`. Raises `ArgumentError` if the binary is
+  not a valid snapshot.
+  """
+  def imt_restore(_snapshot), do: :erlang.nif_error(:nif_not_loaded)
+
+  # === Merkle Mountain Ranges ===
+
+  @doc """
+  New, empty Merkle Mountain Range: an append-only accumulator, e.g. over block
+  headers, whose root proves any earlier leaf part of the history.
+
+  Leaves and nodes hash as in `merkle_root/1`. The root is
+  `blake3(<<2>> <> <<count::64>> <> bag)`, where `bag` folds the peaks right to
+  left with the node hash, and 32 zero bytes while the range is empty.
+  """
+  def mmr_new, do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Append `leaf` to the range. Returns its 0-based index.
+  """
+  def mmr_append(_mmr, _leaf), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Current 32-byte root of the range.
+  """
+  def mmr_root(_mmr), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Proof that the leaf at `index` is part of the range under the current
+  `mmr_root/1`. Raises `ArgumentError` if `index` is out of range.
+
+  The proof is the leaf count as a 64-bit big-endian integer, the 32-byte
+  siblings from the leaf up to its peak, then the other peaks left to right.
+  """
+  def mmr_proof(_mmr, _index), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Check that `leaf` (the leaf data, not its hash) is the leaf at `index` of the
+  range with `root`, according to `proof`. Returns a boolean.
+  """
+  def mmr_verify(_root, _index, _leaf, _proof), do: :erlang.nif_error(:nif_not_loaded)
+
+  # === Sparse Merkle Tree ===
+
+  @doc """
+  Open (or create) the persistent sparse Merkle tree stored in directory `path`.
+
+  Keys are hashed to 256-bit paths with BLAKE3; values are binaries of up to
+  16 MiB. Leaves hash as `blake3(<<0>> <> path <> blake3(value))` and nodes as
+  `blake3(<<1>> <> left <> right)`, with 32 zero bytes for an empty subtree and a
+  lone leaf standing in for its whole subtree. Changes are appended to a log in
+  the directory; call `smt_flush/1` to make them durable (e.g. once per block).
+
+  Only one tree may be open per directory; returns `{:error, reason}` otherwise.
+  """
+  def smt_open(_path), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Open a state tree with options:
+
+    * `:backend` - `:sparse` (the default, as `smt_open/1`) or `:jellyfish`, a
+      versioned tree that keeps the root and contents of every version
+      committed with `smt_commit/2`, so past block heights stay queryable.
+      Versions share unchanged subtrees, so each costs only its changes.
+
+  Both backends produce the same roots and proofs for the same contents, so
+  `smt_verify/4` checks either. With `:jellyfish`, writes become durable when
+  committed (and flushed); uncommitted writes are lost on restart.
+  """
+  def smt_open(_path, _opts), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Value stored under `key`, or `nil`.
+  """
+  def smt_get(_tree, _key), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Value stored under `key` as of `version` (the latest version committed at or
+  before it), or `nil`. Jellyfish trees only; returns `{:error, reason}` for
+  sparse trees or when nothing was committed by `version`.
+  """
+  def smt_get(_tree, _key, _version), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Store `value` under `key`, replacing any previous value.
+  """
+  def smt_put(_tree, _key, _value), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Remove `key` from the tree. Deleting an absent key is a no-op.
+  """
+  def smt_delete(_tree, _key), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Record the tree's current contents as `version` (e.g. a block height), which
+  must be greater than every version committed before. Returns the version's
+  root. Jellyfish trees only; returns `{:error, reason}` for sparse trees.
+  """
+  def smt_commit(_tree, _version), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Sync the tree's log to disk, compacting it first when stale records dominate.
+  """
+  def smt_flush(_tree), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Current 32-byte root of the tree. Only the paths changed since the last call
+  are rehashed.
+  """
+  def smt_root(_tree), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Root as of `version`, as for `smt_get/3`.
+  """
+  def smt_root(_tree, _version), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Proof of the current state of `key` against `smt_root/1`: inclusion of its
+  value if present, non-inclusion otherwise.
+  """
+  def smt_prove(_tree, _key), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Proof of the state of `key` against `smt_root(tree, version)`, as for
+  `smt_get/3`.
+  """
+  def smt_prove(_tree, _key, _version), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Check a proof from `smt_prove/2` that `key` holds `value` under `root`, or,
+  with `value` set to `nil`, that `key` is absent. Returns a boolean.
+  """
+  def smt_verify(_root, _key, _value, _proof), do: :erlang.nif_error(:nif_not_loaded)
+
+  # === Verkle Tree ===
+
+  @doc """
+  New, empty in-memory Verkle tree: an experimental counterpart of the sparse
+  Merkle tree with the same get/put/delete/root/prove/verify functions, for
+  comparing witness sizes. Nodes are width-256 Pedersen vector commitments
+  over BN254 G1, and proofs open them with inner product arguments; neither
+  follows Ethereum's Verkle format, and openings aren't aggregated yet.
+
+  Only available when the NIF is built with the `verkle` feature
+  (`config :bastille, crypto_nif_features: ["verkle"]`).
+  """
+  def verkle_new, do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Value stored under `key`, or `nil`.
+  """
+  def verkle_get(_tree, _key), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Store `value` under `key`. Raises `ArgumentError` above 16 MiB, as `smt_put/3`.
+  """
+  def verkle_put(_tree, _key, _value), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Remove `key`, if present.
+  """
+  def verkle_delete(_tree, _key), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  32-byte root commitment. It depends only on the tree's contents.
+  """
+  def verkle_root(_tree), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Proof of the current state of `key` against `verkle_root/1`: inclusion of its
+  value, or its absence.
+  """
+  def verkle_prove(_tree, _key), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Check a proof from `verkle_prove/2` that `key` holds `value` under `root`, or,
+  with `value` set to `nil`, that `key` is absent. Returns a boolean.
+  """
+  def verkle_verify(_root, _key, _value, _proof), do: :erlang.nif_error(:nif_not_loaded)
+
+  # === Merkle Patricia Trie ===
+
+  @doc """
+  New, empty in-memory Ethereum Merkle Patricia Trie.
+
+  Nodes are RLP-encoded and hashed with Keccak-256 exactly as in Ethereum, so
+  roots and proofs interoperate with EVM chains. Keys are used as given: for
+  state and storage tries, insert `keccak256(address)` / `keccak256(slot)`.
+  """
+  def mpt_new, do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Store `value` under `key` (at most 256 bytes), replacing any previous value.
+  Empty values are rejected: Ethereum treats them as deletions.
+  """
+  def mpt_insert(_trie, _key, _value), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Value stored under `key`, or `nil`.
+  """
+  def mpt_get(_trie, _key), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  32-byte Keccak-256 root of the trie. The empty trie's root is
+  `keccak256(<<0x80>>)`, as in Ethereum. Rehashes the whole trie on each call.
+  """
+  def mpt_root(_trie), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Proof for `key` against `mpt_root/1`: the RLP-encoded nodes on its path, root
+  first, in the format of `eth_getProof`. Also proves absence.
+  """
+  def mpt_proof(_trie, _key), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Check a proof for `key` against a 32-byte `root`, such as an `accountProof`
+  or `storageProof` from `eth_getProof`. Returns the proven value (still
+  RLP-encoded, as stored in the trie), `nil` if the proof shows `key` absent, or
+  `{:error, reason}` if the proof is malformed or doesn't match `root`.
+  """
+  def mpt_verify_proof(_root, _key, _proof), do: :erlang.nif_error(:nif_not_loaded)
+
+  # === File Hashing ===
+
+  @doc """
+  Compute the Blake3 hash of a file without loading it into the BEAM.
+  Returns the 32-byte digest, or `{:error, reason}` if the file can't be read.
+  """
+  def blake3_hash_file(_path), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Compute the SHA-256 hash of a file by streaming it natively.
+  Returns the 32-byte digest, or `{:error, reason}` if the file can't be read.
+  """
+  def sha256_file(_path), do: :erlang.nif_error(:nif_not_loaded)
+
+  # === RLP ===
+
+  @doc """
+  RLP-encode `term`: a binary, a non-negative integer (as its minimal
+  big-endian bytes, so `0` is the empty string), or a list of such terms,
+  nested at most 64 deep. Raises `ArgumentError` otherwise.
+  """
+  def rlp_encode(_term), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Decode RLP into binaries and nested lists; see `rlp_decode/2`.
+  """
+  def rlp_decode(_rlp), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Decode RLP, e.g. an Ethereum block header, receipt or proof node, into
+  binaries and nested lists. Integers come back as binaries
+  (`:binary.decode_unsigned/1` reads them). Options:
+
+    * `:max_size` - inputs over this many bytes are refused
+    * `:max_depth` - lists nested deeper are refused (default and at most 64)
+
+  Returns `{:error, reason}` for input over a limit, truncated, followed by
+  trailing bytes, or not in canonical form (over-long lengths, a single byte
+  below 0x80 wrapped as a string).
+  """
+  def rlp_decode(_rlp, _opts), do: :erlang.nif_error(:nif_not_loaded)
+
+  # === Gossip Messages ===
+
+  @doc """
+  Encode a P2P envelope (`Bastille.Features.P2P.Messaging.Envelope`) holding
+  `message` as `command`, e.g. `gossip_encode(:ping, %{nonce: 1})`, in the
+  same protobuf wire format as the Elixir codec.
+
+  Messages are maps keyed by field name, so the `Bastille.P2P.Proto` structs
+  can be passed as they are; missing or `nil` fields take their default.
+  Nested messages are maps, repeated fields lists and enums their value
+  names (`:BLOCK`, `:TX`) or numbers.
+
+  Raises `ArgumentError` for an unknown command, a field not matching its
+  type, a repeated field over its limit (50,000 inventory items, 1,000
+  addresses, 100,000 transactions per block, 2,000 headers or locator
+  hashes) or an envelope over 2 MB.
+  """
+  def gossip_encode(_command, _message), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Decode a P2P envelope into `{command, message}`, with every field of the
+  message present (unset ones as their defaults, unset nested messages as
+  `nil`). Unknown fields are skipped.
+
+  Returns `{:error, reason}` for malformed protobuf, an empty envelope, a
+  field not matching its type or a message over the limits of
+  `gossip_encode/2`.
+  """
+  def gossip_decode(_envelope), do: :erlang.nif_error(:nif_not_loaded)
+
+  # === Hex ===
+
+  @doc """
+  Lowercase hex of `data`; see `hex_encode/2`.
+  """
+  def hex_encode(_data), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Hex of `data`, 16 bytes at a time with SSE2 on x86_64 (see
+  `cpu_features/0`), e.g. for block and transaction hashes. Options:
+
+    * `:case` - `:lower` (default) or `:upper`
+    * `:prefix` - `true` to start with `0x` (default `false`)
+  """
+  def hex_encode(_data, _opts), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Bytes of `hex`, digits in either case and no `0x` prefix; see
+  `hex_decode/2`.
+  """
+  def hex_decode(_hex), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Bytes of `hex`. Options:
+
+    * `:case` - `:mixed` (default), `:lower` or `:upper`: which letters are
+      digits
+    * `:prefix` - `false` (default) to refuse a `0x` prefix, `true` to
+      require one, `:optional` to take either
+
+  Returns `{:error, reason}` for an odd number of digits, a missing prefix
+  or a character that isn't a digit, naming its position.
+  """
+  def hex_decode(_hex, _opts), do: :erlang.nif_error(:nif_not_loaded)
+
+  # === Base64url ===
+
+  @doc """
+  Padded base64url of `data`, as `Base.url_encode64/1`; see
+  `base64url_encode/2`.
+  """
+  def base64url_encode(_data), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Base64 of `data` in the URL and filename safe alphabet, e.g. for RPC tokens
+  and snapshot chunks. Options:
+
+    * `:padding` - `false` to leave out the trailing `=` (default `true`)
+  """
+  def base64url_encode(_data, _opts), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Bytes of padded, canonical base64url `text`, as `Base.url_decode64/1`
+  returns them; see `base64url_decode/2`.
+  """
+  def base64url_decode(_text), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Bytes of base64url `text`. Options:
+
+    * `:padding` - `true` (default) to require `=` padding, `false` to refuse
+      it, `:optional` to take either
+    * `:strict` - `true` (default) to refuse a last character with unused
+      bits set, so each byte string has one encoding
+
+  Returns `{:error, reason}` for a bad length or padding, or a character
+  outside the alphabet, naming its position.
+  """
+  def base64url_decode(_text, _opts), do: :erlang.nif_error(:nif_not_loaded)
+
+  # === Multiformats ===
+
+  @doc """
+  Multihash of a 32-byte `digest` from `hash` (`:blake3` or `:sha2_256`):
+  hash code and length as varints, then the digest.
+
+  Raises `ArgumentError` for another hash or digest length.
+  """
+  def multihash_encode(_hash, _digest), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  `{hash, digest}` of `multihash`, or `{:error, reason}` for an unknown hash
+  code or a digest of the wrong length.
+  """
+  def multihash_decode(_multihash), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  CIDv1 of content hashed to `digest`, in base32 as IPFS writes them; see
+  `cid_encode/4`.
+  """
+  def cid_encode(_codec, _hash, _digest), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  CIDv1 text of content in `codec` (`:raw`, `:dag_pb` or `:dag_cbor`) hashed
+  to `digest` by `hash`, e.g. a snapshot chunk and its `blake3_hash/1`.
+  Options:
+
+    * `:base` - the multibase: `:base32` (default), `:base58btc`, `:base16`
+      or `:base64url`
+  """
+  def cid_encode(_codec, _hash, _digest, _opts), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  `{codec, hash, digest}` of a CIDv1 in any of the bases `cid_encode/4`
+  writes.
+
+  Returns `{:error, reason}` for a CIDv0, an unknown base, codec or hash, or
+  text that isn't canonical in its base.
+  """
+  def cid_decode(_cid), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Multibase text of `data`: the prefix character of `base` (`:base32`,
+  `:base58btc`, `:base16` or `:base64url`; lowercase and unpadded) then the
+  encoding.
+  """
+  def multibase_encode(_base, _data), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  `{base, data}` of multibase `text`, or `{:error, reason}`.
+  """
+  def multibase_decode(_text), do: :erlang.nif_error(:nif_not_loaded)
+
+  # === Varints ===
+
+  @doc """
+  Unsigned LEB128 encoding of `n` (0 to 2^64 - 1): 7 bits per byte, least
+  significant first.
+  """
+  def varint_encode(_n), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Read an unsigned LEB128 value from the start of `data`, returning
+  `{n, rest}`. Returns `{:error, reason}` if `data` is truncated, the value
+  overflows 64 bits or isn't minimally encoded (e.g. `<<0x80, 0x00>>` for 0).
+  """
+  def varint_decode(_data), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Compact size encoding of `n` (0 to 2^64 - 1): one byte below 0xFD, else a
+  0xFD, 0xFE or 0xFF marker and a little-endian u16, u32 or u64.
+  """
+  def compact_size_encode(_n), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Read a compact size from the start of `data`, returning `{n, rest}`.
+  Returns `{:error, reason}` if `data` is truncated or the value isn't
+  minimally encoded (e.g. `<<0xFD, 0x10, 0x00>>` for 16).
+  """
+  def compact_size_decode(_data), do: :erlang.nif_error(:nif_not_loaded)
+
+  # === SSZ ===
+
+  @doc """
+  SSZ-encode `value` as described by `schema`, e.g. a consensus container:
+
+      {:container, [slot: {:uint, 64}, proposer_index: {:uint, 64},
+                    parent_root: {:bytes, 32}, state_root: {:bytes, 32},
+                    body_root: {:bytes, 32}]}
+
+  Schemas are `:boolean`, `{:uint, bits}` (8, 16, 32, 64 or 128),
+  `{:bytes, n}`, `{:byte_list, limit}`, `{:bitvector, n}`, `{:bitlist, limit}`,
+  `{:vector, schema, n}`, `{:list, schema, limit}` and
+  `{:container, [field: schema, ...]}`. Byte vectors and lists are binaries,
+  bitvectors and bitlists lists of booleans, containers maps keyed by field
+  name (structs work; other keys are ignored). A uint256 is `{:bytes, 32}`
+  holding its little-endian bytes, which encodes and hashes the same.
+
+  Raises `ArgumentError` for an invalid schema or a value not matching it.
+  """
+  def ssz_encode(_schema, _value), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Decode `ssz` as described by `schema` (see `ssz_encode/2`). Decoding is
+  strict, so a value has a single accepted encoding: returns
+  `{:error, reason}` for wrong lengths, out-of-order offsets, set padding
+  bits or lists over their limit.
+  """
+  def ssz_decode(_schema, _ssz), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  The 32-byte SSZ `hash_tree_root` of `value` as described by `schema` (see
+  `ssz_encode/2`): SHA-256 merkleization of its chunks, with list lengths
+  mixed in, as the consensus specs hash attestations and block headers.
+  """
+  def ssz_hash_tree_root(_schema, _value), do: :erlang.nif_error(:nif_not_loaded)
+
+  # === Borsh ===
+
+  @doc """
+  Borsh-encode `value` as described by `schema`, for NEAR and Solana tooling
+  or compact deterministic encoding of internal structs:
+
+      {:struct, [owner: {:bytes, 32}, amount: :u64, memo: {:option, :string}]}
+
+  Schemas are `:u8` to `:u128`, `:i8` to `:i128`, `:bool`, `:unit`,
+  `:string`, `:bytes` (a length-prefixed `Vec<u8>`), `{:bytes, n}` (a
+  `[u8; n]`), `{:array, schema, n}`, `{:vec, schema}`, `{:option, schema}`,
+  `{:struct, [field: schema, ...]}`, `{:enum, [variant: schema, ...]}` and
+  `{:map, key_schema, value_schema}`, fields and variants in declaration
+  order.
+
+  Strings and bytes are binaries, arrays and vecs lists, structs maps keyed
+  by field name (structs work; other keys are ignored), maps maps. `:unit`
+  and `None` are `nil`. Enum values are `{variant, value}`, or just
+  `variant` for a `:unit` variant. Map entries are written sorted by key.
+
+  Raises `ArgumentError` for an invalid schema or a value not matching it.
+  """
+  def borsh_encode(_schema, _value), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Decode `borsh` as described by `schema` (see `borsh_encode/2`). Returns
+  `{:error, reason}` for truncated input, trailing bytes, out-of-range bool,
+  option or enum tags, invalid UTF-8 or map keys not in ascending order.
+  """
+  def borsh_decode(_schema, _borsh), do: :erlang.nif_error(:nif_not_loaded)
+
+  # === Bloom Filters ===
+
+  @doc """
+  New, empty Bloom filter of `bits` bits (up to 2^32) setting `hashes` bits (1
+  to 32) per item, e.g. for a block's log topics and addresses.
+
+  Positions are derived from one BLAKE3 hash of the item by double hashing.
+  Filters are safe to update and query from several processes at once.
+  """
+  def bloom_new(_bits, _hashes), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Add `item` to the filter.
+  """
+  def bloom_insert(_filter, _item), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  `false` if `item` was never inserted, `true` if it probably was.
+  """
+  def bloom_contains(_filter, _item), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Serialize the filter: `<<bits::64, hashes::8>>` followed by the bit array,
+  bit `i` at position `rem(i, 8)` (least significant first) of byte `div(i, 8)`.
+  """
+  def bloom_serialize(_filter), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Rebuild a filter from `bloom_serialize/1`. Raises `ArgumentError` if the
+  binary is not a valid serialized filter.
+  """
+  def bloom_deserialize(_serialized), do: :erlang.nif_error(:nif_not_loaded)
+
+  # === Cuckoo Filters ===
+
+  @doc """
+  New cuckoo filter remembering at least the last `capacity` (up to 2^24)
+  inserted items, e.g. gossip message IDs.
+
+  The filter keeps two generations of `capacity` 16-bit fingerprints: when the
+  current one fills, the older is dropped and a fresh one started, so memory
+  stays bounded and old entries age out without any sweeping. Lookups have a
+  false-positive rate of roughly 1 in 4000 once both generations are full.
+  """
+  def cuckoo_new(_capacity), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Add `item` unless it is (probably) already present, in one step. Returns
+  `true` if the item is new and `false` if it was seen before, so gossip
+  handlers can dedupe with a single call. Seeing an item again keeps it from
+  aging out.
+  """
+  def cuckoo_insert(_filter, _item), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  `false` if `item` is not in the filter, `true` if it probably is.
+  """
+  def cuckoo_contains(_filter, _item), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Remove `item`, returning whether it was found. Only delete items that were
+  inserted: deleting another may remove an entry sharing its fingerprint.
+  """
+  def cuckoo_delete(_filter, _item), do: :erlang.nif_error(:nif_not_loaded)
+
+  # === State Snapshots ===
+
+  @doc """
+  New snapshot encoder packing entries into chunks of about `chunk_size` bytes
+  (up to 64 MiB), for streaming the state to a fast-sync peer.
+
+  Each chunk carries a sequence number and a CRC32C; a final end chunk records
+  the entry count and a BLAKE3 digest of all the data, so the receiving
+  `snapshot_decode/2` catches corrupt, missing, repeated or reordered chunks.
+  """
+  def snapshot_encoder_new(_chunk_size), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Add a batch of `{key, value}` entries (binary keys, any term as value) and
+  return the chunks they completed, possibly none. Feed the state in batches,
+  e.g. from a storage range scan, so it never exists as one term.
+  """
+  def snapshot_encode(_encoder, _entries), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Flush the remaining entries and return the last chunks, ending with the end
+  chunk. The encoder takes no entries afterwards.
+  """
+  def snapshot_encode_finish(_encoder), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  New decoder for the chunks of one snapshot, fed in order.
+  """
+  def snapshot_decoder_new, do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Check the next chunk and return its `{key, value}` entries (`[]` for the end
+  chunk), or `{:error, reason}` if it is corrupt or out of sequence. A rejected
+  chunk leaves the decoder as it was, so the chunk can be fetched again (from
+  another peer, say) and retried.
+  """
+  def snapshot_decode(_decoder, _chunk), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Number of entries in the snapshot once its end chunk has been accepted, or
+  `{:error, reason}` if it hasn't.
+  """
+  def snapshot_decode_finish(_decoder), do: :erlang.nif_error(:nif_not_loaded)
+
+  # === State Diffs ===
+
+  @doc """
+  Diff between two complete snapshots (lists of chunks, end chunk included),
+  itself returned as snapshot chunks: the added or changed entries with their
+  new values, plus a marker for each removed key. Returns `{:error, reason}` if
+  either snapshot is corrupt or incomplete.
+  """
+  def state_diff(_old_snapshot, _new_snapshot), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Apply a `state_diff/2` diff to a complete snapshot and return the resulting
+  snapshot's chunks, entries in key order. Returns `{:error, reason}` if either
+  input is corrupt, or the diff removes a key the snapshot doesn't have.
+  """
+  def state_apply(_snapshot, _diff), do: :erlang.nif_error(:nif_not_loaded)
+
+  # === Storage ===
+
+  @doc """
+  Open (creating if needed) the store in directory `path`, with a column per
+  kind of data: `:blocks`, `:state` and `:tx_index`. Uses RocksDB if the NIF is
+  built with it, LMDB otherwise; see `storage_open/2`. Returns
+  `{:error, reason}` if it can't be opened, e.g. while another handle holds it.
+
+  Only available when the NIF is built with the `rocksdb` or `lmdb` feature
+  (`config :bastille, crypto_nif_features: ["rocksdb"]`).
+  """
+  def storage_open(_path), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Open the store in `path` with options:
+
+    * `:backend` - `:rocksdb` or `:lmdb`. LMDB is a single memory-mapped file
+      with no background threads, lighter for small nodes and CI; it has no
+      compaction and limits keys to 511 bytes. The backend must be built in,
+      or `{:error, reason}` is returned.
+    * `:map_size` - LMDB only: the maximum database size in bytes (default 64 GiB).
+      The file only grows as data is written.
+    * `:encryption_key` - a 32-byte node data key. Every value is encrypted
+      with XChaCha20-Poly1305 before it reaches disk; keys are not, so lookups
+      and iteration are unaffected. Only a new store can be made encrypted, and
+      an encrypted store can't be opened without a key.
+    * `:retired_keys` - earlier data keys, still decrypting values written
+      before `storage_rotate_key/2` finished moving them to the current key.
+
+  Every function takes either kind of store.
+  """
+  def storage_open(_path, _opts), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Flush and close the store. Calls through the handle, or iterators over it,
+  return `{:error, reason}` afterwards. Otherwise the store closes when the
+  handle is garbage collected.
+  """
+  def storage_close(_store), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Value under `key` in `column`, or `nil`.
+  """
+  def storage_get(_store, _column, _key), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Store `value` under `key` in `column`.
+  """
+  def storage_put(_store, _column, _key, _value), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Remove `key` from `column`, if present.
+  """
+  def storage_delete(_store, _column, _key), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Apply a list of `{:put, column, key, value}` and `{:delete, column, key}`
+  operations atomically, across column families: after a crash, either all of
+  them are visible or none is.
+  """
+  def storage_write_batch(_store, _ops), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Compact `column` now, e.g. after pruning old blocks, instead of waiting for
+  background compaction. Does nothing with LMDB, which reuses freed pages.
+  """
+  def storage_compact(_store, _column), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Make the 32-byte `key` the data key of an encrypted store and re-encrypt
+  every value under it, returning how many were re-encrypted. The store stays
+  usable meanwhile, held for one batch of values at a time. Keep the old key
+  in `:retired_keys` until this returns; rerun it after an interruption.
+  """
+  def storage_rotate_key(_store, _key), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Iterator over `column` in ascending key order; see `storage_iterator/3`.
+  """
+  def storage_iterator(_store, _column), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Iterator over `column`. Options:
+
+    * `:from` - first key to include
+    * `:to` - key to stop before
+    * `:reverse` - descending key order (default `false`)
+
+  Entries are read in batches with `storage_iterator_next/2`. Each batch is
+  read consistently, but writes made between batches may show up in later ones.
+  """
+  def storage_iterator(_store, _column, _opts), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Reposition the iterator so its next entry is the first at or past `key` in
+  its direction (at or below it when reversed), within its range. Seeking back
+  to a key already returned reads it again.
+  """
+  def storage_iterator_seek(_iterator, _key), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Next `count` `{key, value}` entries of the iterator; fewer, down to `[]`, once
+  it reaches the end.
+  """
+  def storage_iterator_next(_iterator, _count), do: :erlang.nif_error(:nif_not_loaded)
+
+  # === Block Archive ===
+
+  @doc """
+  Open (or create) the append-only block archive in directory `path`.
+
+  Blocks are stored by height in segment files of 8192 heights, each with a
+  memory-mapped index holding a block's offset, length and CRC32C, so reading a
+  historical block needs no key-value store. An append cut short by a crash is
+  discarded when the archive is next opened.
+
+  Only one archive may be open per directory; returns `{:error, reason}` otherwise.
+  """
+  def archive_open(_path), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Open the archive in `path` with options `:encryption_key` and
+  `:retired_keys`, encrypting blocks as `storage_open/2` does values. Only a
+  new archive can be made encrypted, and an encrypted one can't be opened
+  without a key.
+  """
+  def archive_open(_path, _opts), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Store `block` at `height`, which must be above every height appended so far;
+  gaps are allowed. Call `archive_flush/1` to make appends durable.
+  """
+  def archive_append(_archive, _height, _block), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  The block at `height`, or `nil` if none was appended there. Returns
+  `{:error, reason}` if the stored block fails its checksum.
+  """
+  def archive_read(_archive, _height), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Like `archive_read/2`, but returns a binary viewing the memory-mapped segment
+  file instead of a copy, for serving large historical blocks without copying
+  them through native and process heaps. The checksum is still verified.
+
+  The binary keeps its segment's map alive until it is garbage collected;
+  use `:binary.copy/1` before holding on to a block for long. Blocks of an
+  encrypted archive can't be viewed in place and are decrypted into a copy.
+  """
+  def archive_read_mapped(_archive, _height), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  The highest height appended, or `nil` for an empty archive.
+  """
+  def archive_last_height(_archive), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Sync appended blocks and their index entries to disk.
+  """
+  def archive_flush(_archive), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Make the 32-byte `key` the data key of an encrypted archive and rewrite its
+  segments with every block re-encrypted under it, one segment at a time,
+  returning how many blocks were re-encrypted. Keep the old key in
+  `:retired_keys` until this returns; rerun it after an interruption.
+  """
+  def archive_rotate_key(_archive, _key), do: :erlang.nif_error(:nif_not_loaded)
+
+  # === Pruning ===
+
+  @doc """
+  Prune chain data below `height` according to a retention `mode`, returning
+  the bytes reclaimed as `%{blocks: bytes, state: bytes}`. `targets` names the
+  data to prune, either optional:
+
+    * `:blocks` - a block archive from `archive_open/1`, whose segment files
+      holding only heights below `height` are deleted
+    * `:state` - a state tree from `smt_open/1,2`; a jellyfish tree forgets the
+      versions committed before the latest one at or below `height`, a sparse
+      tree compacts its log
+
+  Modes:
+
+    * `:archive` - keep everything
+    * `:full` - prune state history, keep every block body
+    * `:light` - prune state history and block bodies
+  """
+  def prune_below(_height, _mode, _targets), do: :erlang.nif_error(:nif_not_loaded)
+
+  # === Backups ===
+
+  @doc """
+  Back up chain data up to `up_to_height` to the file at `path`, while the node
+  keeps running, returning `%{height: height, blocks: count, state: boolean}`.
+  Options:
+
+    * `:blocks` - a block archive from `archive_open/1`; its blocks up to the
+      height are saved
+    * `:state` - a state tree from `smt_open/1,2`; a jellyfish tree is saved as
+      of the latest version at or below the height, a sparse tree as it stands
+    * `:compress` - compress the backup with zstd (default `false`)
+
+  Every section is checksummed and the whole file digested; the file only
+  appears at `path` once complete, and is verified before returning.
+  """
+  def backup_create(_path, _up_to_height, _opts), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Check the backup at `path` end to end, returning the same summary as
+  `backup_create/3`, or `{:error, reason}`.
+  """
+  def backup_verify(_path), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Verify the backup at `path`, then restore it. Options:
+
+    * `:blocks` - directory for a new block archive of the backed-up blocks
+    * `:state` - directory for a new state tree (of the backed-up backend)
+
+  Either may be left out to skip that part. Refuses directories that already
+  hold an archive or a tree.
+  """
+  def backup_restore(_path, _opts), do: :erlang.nif_error(:nif_not_loaded)
+
+  # === Write-Ahead Log ===
+
+  @doc """
+  Open (or create) the write-ahead log in directory `path`, syncing every
+  append to disk (see `wal_open/2`).
+
+  Append an intent record before mutating state; after a crash,
+  `wal_replay/1` returns the records not yet covered by `wal_checkpoint/2`, so
+  an interrupted block can be redone and never leaves partial state. A record
+  cut short by the crash is discarded on open.
+
+  Only one handle may be open per directory; returns `{:error, reason}` otherwise.
+  """
+  def wal_open(_path), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Open the write-ahead log with options:
+
+    * `:sync` - when appends reach disk: `:always` (the default, on every
+      append), `{:interval, ms}` (on an append at least `ms` milliseconds after
+      the last sync) or `:manual` (only on `wal_sync/1` and checkpoints).
+      Records not yet synced may be lost in a power failure.
+  """
+  def wal_open(_path, _opts), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Append `record` (at most 64 MiB), returning its log sequence number (LSN).
+  LSNs are consecutive from 1.
+  """
+  def wal_append(_wal, _record), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Sync appended records to disk.
+  """
+  def wal_sync(_wal), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  The records after the last checkpoint as `{lsn, record}` tuples, in order.
+  """
+  def wal_replay(_wal), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Record that state reflects every record up to `lsn`, so they are no longer
+  replayed, and delete log files holding nothing later. `lsn` may not go
+  back or past the last record appended.
+  """
+  def wal_checkpoint(_wal, _lsn), do: :erlang.nif_error(:nif_not_loaded)
+
+  # === Message Authentication ===
+
+  @doc """
+  Compute HMAC-SHA256 of data under key.
+  """
+  def hmac_sha256(_key, _data), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Verify an HMAC-SHA256 tag in constant time.
+  """
+  def hmac_sha256_verify(_key, _data, _tag), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Verify a Blake3 MAC produced by `blake3_keyed_hash/2` in constant time.
+  """
+  def blake3_mac_verify(_key, _data, _tag), do: :erlang.nif_error(:nif_not_loaded)
+
+  # === Checksums ===
+
+  @doc """
+  Compute the 64-bit XXH3 checksum of data as a non-negative integer.
+  Not a cryptographic hash: use only for accidental corruption detection.
+  """
+  def xxh3_64(_data), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Compute the CRC-32C (Castagnoli) checksum of data as a non-negative integer.
+  Not a cryptographic hash: use only for accidental corruption detection.
+  """
+  def crc32c(_data), do: :erlang.nif_error(:nif_not_loaded)
+
+  # === Canonical CBOR ===
+
+  @doc """
+  Encode `term` as deterministic CBOR (RFC 8949, section 4.2.1), the one
+  encoding every node produces for it, for signing payloads:
+
+    * integers from -2^64 to 2^64 - 1
+    * binaries, as byte strings
+    * `{:text, string}`, as a UTF-8 text string
+    * lists, as arrays
+    * maps, their entries sorted by encoded key
+    * `true`, `false` and `nil`
+    * `{:tag, number, term}`, as a tagged item
+
+  Raises `ArgumentError` for anything else, floats included.
+  """
+  def cbor_encode(_term), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Decode deterministic CBOR back into the terms `cbor_encode/1` takes. Returns
+  `{:error, reason}` for anything `cbor_encode/1` would not have produced:
+  indefinite lengths, integers or lengths not in their shortest form, map keys
+  out of order or repeated, floats, other simple values, trailing bytes.
+  """
+  def cbor_decode(_cbor), do: :erlang.nif_error(:nif_not_loaded)
+
+  # === MessagePack ===
+
+  @doc """
+  MessagePack-encode `term`, e.g. an RPC response: integers (-2^63 to
+  2^64 - 1), floats, binaries (as str, so they must be UTF-8),
+  `{:bin, binary}` (as bin), `nil`, `true`, `false`, other atoms (as str),
+  lists, maps and `{:ext, type, data}` extensions, nested at most 128 deep.
+  Each value takes its shortest form.
+
+  Raises `ArgumentError` for anything else.
+  """
+  def msgpack_encode(_term), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Decode MessagePack; see `msgpack_decode/2`.
+  """
+  def msgpack_decode(_msgpack), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Decode MessagePack: str as binaries, bin as `{:bin, binary}` and extensions
+  as `{:ext, type, data}`, so encoding the result gives back the same kinds.
+  Strings and binaries share the input's memory instead of being copied.
+  Options:
+
+    * `:max_depth` - arrays and maps nested deeper are refused (default and
+      at most 128)
+
+  Returns `{:error, reason}` for truncated input, trailing bytes, str that
+  isn't UTF-8, floats that aren't finite, duplicate map keys or nesting over
+  the limit.
+  """
+  def msgpack_decode(_msgpack, _opts), do: :erlang.nif_error(:nif_not_loaded)
+
+  # === Block Headers ===
+
+  @doc """
+  Canonical encoding of a block header map with `:height`, `:previous_hash`
+  and `:merkle_root` (32 bytes each), `:timestamp` (milliseconds), `:bits` (a
+  compact target), `:nonce`, `:producer_algorithm` (`:dilithium2`,
+  `:falcon512`, `:sphincsplus` or `nil` for an unsigned header),
+  `:producer_key` and `:signature` (may be left out until signed).
+
+  The header hash, which the producer signs, is Blake3 of the encoding up to
+  and including the nonce; that prefix is what `pow_mine/4` mines. Raises
+  `ArgumentError` for missing or malformed fields.
+  """
+  def header_encode(_header), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Header map of `bytes` from `header_encode/1`, with its `:hash`, or
+  `{:error, reason}`. Decoding is strict: each header has one encoding.
+  """
+  def header_decode(_bytes), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Hash of an encoded header, or `{:error, reason}`.
+  """
+  def header_hash(_bytes), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Check an encoded header against its encoded parent in one call, for sync:
+  encoding, height, previous hash, timestamp after the parent's and not too
+  far ahead of the clock, proof of work against its bits, then the producer's
+  signature. Returns the header hash, or `{:error, reason}` for the first
+  check that fails.
+
+  ## Options
+    * `:now` - current time in milliseconds (default: the system clock)
+    * `:max_future_drift` - how far ahead of `:now` a timestamp may be, in
+      milliseconds (default 120_000)
+    * `:expected_bits` - the bits the header must carry, from the retarget
+      rule (default: not checked)
+    * `:producer_key` - the public key that must have produced it, such as
+      the slot leader's (default: any)
+    * `:require_signature` - refuse unsigned headers (default `true`)
+  """
+  def validate_header(_header, _parent, _params), do: :erlang.nif_error(:nif_not_loaded)
+
+  # === Finality Proofs ===
+
+  @doc """
+  Merkle root committing to a validator set, given as `{algorithm, public_key,
+  weight}` tuples in set order (weights nonzero). This is the root light
+  clients and bridges trust. Raises `ArgumentError` for an invalid set.
+  """
+  def finality_validator_set_root(_validators), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  The 32 bytes each validator of the set with root `set_root` signs to
+  finalize an encoded header, naming `next_set_root` as the set that takes
+  over after it (`set_root` again when the set doesn't change). Returns
+  `{:error, reason}` if the header doesn't decode.
+  """
+  def finality_message(_header, _set_root, _next_set_root), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Assemble a finality proof for an encoded header from the validator set,
+  the next set root and `{validator_index, signature}` pairs over
+  `finality_message/3`, in any order. The signatures are not verified here.
+  Raises `ArgumentError` for invalid input.
+  """
+  def finality_proof_build(_header, _validators, _next_set_root, _signatures),
+    do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Verify a finality proof against a trusted validator set root: the set in
+  the proof must have that root, and valid signatures from more than 2/3 of
+  its weight must cover the header. Returns `{header, next_set_root,
+  signers}`, the header decoded as by `header_decode/1` and the signers as
+  ascending validator indices, or `{:error, reason}`.
+  """
+  def finality_proof_verify(_proof, _set_root), do: :erlang.nif_error(:nif_not_loaded)
+
+  # === Light Client ===
+
+  @doc """
+  Start a light client that trusts the validator set with root `set_root`
+  (see `finality_validator_set_root/1`). It follows the chain through
+  finality proofs and the headers that extend them, keeping only hashes and
+  Merkle roots, for checkpoint sync and for apps that check inclusion
+  without running a node. Returns a handle, safe to share between processes.
+
+  ## Options
+    * `:max_headers` - how many recent headers to keep for inclusion
+      queries (default 10_000)
+  """
+  def light_client_new(_set_root, _opts), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Feed a finality proof signed by the client's current validator set. Its
+  header becomes the finalized head, replacing any unfinalized headers on
+  another fork, and the set it names becomes the trusted one. Returns the
+  header height, or `{:error, reason}` for a proof that doesn't verify or
+  isn't past the last finalized height.
+  """
+  def light_client_add_finality_proof(_client, _proof), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Feed an encoded header extending the newest one the client holds, checked
+  as by `validate_header/3` with the same options. Returns its hash, or
+  `{:error, reason}`.
+  """
+  def light_client_add_header(_client, _header, _opts), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Check that `leaf` is in the Merkle root of a header the client holds,
+  following a `merkle_proof/2` proof. Returns `{height, finalized?}`, or
+  `{:error, reason}` for an unknown header or a proof that doesn't match.
+  """
+  def light_client_verify_inclusion(_client, _header_hash, _leaf, _proof),
+    do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  The client's trusted `:validator_set_root`, and its `:finalized` and
+  `:head` headers as `{height, hash}` (`nil` before the first proof).
+  """
+  def light_client_status(_client), do: :erlang.nif_error(:nif_not_loaded)
+
+  # === Consensus Votes ===
+
+  @doc """
+  The 32 bytes a validator signs to vote for a 32-byte `block_id` at
+  `height` and `round`.
+  """
+  def vote_message(_height, _round, _block_id), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Canonical encoding of a signed vote, a map with `:height`, `:round`,
+  `:block_id`, `:algorithm` (`:dilithium2`, `:falcon512` or `:sphincsplus`),
+  `:public_key` and `:signature` over `vote_message/3`. The signature is not
+  checked. Raises `ArgumentError` for missing fields or invalid lengths.
+  """
+  def vote_encode(_vote), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Decode a vote encoded by `vote_encode/1`, or `{:error, reason}`.
+  """
+  def vote_decode(_bytes), do: :erlang.nif_error(:nif_not_loaded)
+
+  # === Equivocation Evidence ===
+
+  @doc """
+  Package two conflicting statements from one key as slashing evidence:
+  `kind` is `:header` for two encoded headers at the same height, or `:vote`
+  for two encoded votes at the same height and round, for different blocks.
+  The pair is ordered canonically, so either argument order gives the same
+  evidence. Returns `{:error, reason}` if the statements don't equivocate.
+  """
+  def equivocation_evidence(_kind, _first, _second), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Check slashing evidence from `equivocation_evidence/3`: both statements
+  decode, are validly signed by the same key, and conflict. Returns a map
+  with `:type` (`:header` or `:vote`), `:algorithm`, `:public_key`,
+  `:height`, `:round` (`nil` for headers) and `:block_ids` (the two header
+  hashes or voted block ids), or `{:error, reason}`.
+  """
+  def verify_equivocation(_evidence), do: :erlang.nif_error(:nif_not_loaded)
+
+  # === Quorum Certificates ===
+
+  @doc """
+  Start collecting votes for `block_id` at `height` and `round` from a
+  validator set given as for `finality_validator_set_root/1`. The keys are
+  parsed once, here. Returns a handle; raises `ArgumentError` for an invalid
+  set or key.
+  """
+  def qc_collector_new(_height, _round, _block_id, _validators), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Add `{validator_index, signature}` votes over `vote_message/3`, checked in
+  parallel. The first valid vote of each validator counts. Returns
+  `{weight, quorum?, rejected}`: the weight voted so far, whether it is over
+  2/3 of the set's, and the indices of the votes that were out of range,
+  already counted or invalid.
+  """
+  def qc_add_votes(_collector, _votes), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  The quorum certificate of the votes collected, a canonical binary, or
+  `{:error, reason}` before the quorum is reached.
+  """
+  def qc_certificate(_collector), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Verify a quorum certificate against the validator set that should have
+  signed it. Returns `{height, round, block_id, signers}`, the signers as
+  ascending validator indices, or `{:error, reason}`.
+  """
+  def qc_verify(_certificate, _validators), do: :erlang.nif_error(:nif_not_loaded)
+
+  # === Transactions ===
+
+  @doc """
+  Canonical bytes of transaction `tx`: a map with the fields of a
+  `Bastille.Features.Transaction.Transaction` (other keys, such as `:hash`,
+  are ignored) plus `:chain_id`, the network the signature is bound to.
+
+  The format is versioned (version 1): chain id, addresses and `:data` are
+  length-prefixed, `:amount` and `:fee` are 128-bit, and `:signature` is
+  `nil`, `%{type: :coinbase}` or `%{dilithium: _, falcon: _, sphincs: _}`,
+  matching `:signature_type`. `tx_signing_bytes/1` is a prefix of the result.
+
+  Raises `ArgumentError` for missing fields, values out of range or lengths
+  over the limits (chain id 32, addresses 64, data 65_536 bytes).
+  """
+  def tx_encode(_tx), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Transaction map of `bytes` from `tx_encode/1`, with binaries for
+  `:chain_id`, `:from`, `:to` and `:data`.
+
+  Returns `{:error, reason}` for an unknown version, truncated or trailing
+  bytes, lengths over the limits or a signature that doesn't match its type,
+  so each transaction has exactly one encoding.
+  """
+  def tx_decode(_bytes), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  The bytes a signature over `tx` covers: everything `tx_encode/1` writes but
+  the signature, so `:fee`, `:data`, `:signature_type` and the chain id
+  cannot change without invalidating it.
+  """
+  def tx_signing_bytes(_tx), do: :erlang.nif_error(:nif_not_loaded)
+
+  # === Mempool Admission ===
+
+  @doc """
+  Check encoded transactions for mempool admission in parallel on the crypto
+  thread pool, returning `:ok` or `{:error, reason}` for each, in order.
+
+  A transaction passes if it decodes, is at most `:max_size` bytes (default
+  131_072), matches `:chain_id` (when given), is not a coinbase, pays at least
+  `:min_fee` (default 0), has a nonce from the sender's next nonce in
+  `:nonces` (address => nonce, 0 when absent) to `:max_nonce_gap` (default
+  64) past it, and carries at least 2 valid signatures of 3 over
+  `tx_signing_bytes/1` from the sender's keys in `:public_keys` (address =>
+  `%{dilithium: _, falcon: _, sphincs: _}`). Of transactions from one sender
+  with the same nonce only the first passes. Balances are not checked.
+  """
+  def precheck_transactions(_transactions, _chain_params), do: :erlang.nif_error(:nif_not_loaded)
+
+  # === Canonical JSON ===
+
+  @doc """
+  Canonical JSON (RFC 8785) of `term`, the bytes to sign for a JSON payload:
+  maps (binary or atom keys) as objects with members sorted, lists as arrays,
+  binaries (UTF-8) and atoms other than `nil`, `true` and `false` as strings,
+  and integers within +/-2^53 and floats as ECMAScript writes numbers. Nested
+  at most 128 deep.
+
+  Raises `ArgumentError` for anything else, or a map with both `:a` and
+  `"a"` keys.
+  """
+  def jcs_encode(_term), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Canonical form of `json`, e.g. a payload signed by an external integrator,
+  whatever its whitespace, member order, escapes and number notation.
+
+  Returns `{:error, reason}` for invalid JSON, or JSON outside I-JSON
+  (RFC 7493): duplicate member names, lone surrogates or numbers a double
+  can't hold.
+  """
+  def jcs_canonicalize(_json), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Whether `json` is valid and already in canonical form, so a signature over
+  it is over the bytes any other implementation would produce.
+  """
+  def jcs_verify(_json), do: :erlang.nif_error(:nif_not_loaded)
+
+  # === Compression ===
+
+  @doc """
+  Compress `data` as a zstd frame at `level` (1 to 22, higher is smaller and
+  slower; negative levels trade ratio for speed, 0 means the default of 3).
+  """
+  def zstd_compress(_data, _level), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Decompress zstd `data`. Returns `{:error, reason}` if it is not valid zstd or
+  would decompress to more than `max_size` bytes, which is checked as it
+  decompresses, so untrusted input can't exhaust memory.
+  """
+  def zstd_decompress(_data, _max_size), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Train a dictionary of at most `max_size` bytes (typically 16-112 KiB) on
+  `samples`, a list of binaries representative of the data to compress, such
+  as recent block payloads. Needs a few hundred samples to be worthwhile;
+  returns `{:error, reason}` if there are too few.
+
+  Keep the dictionary with the data: it is needed again to decompress.
+  """
+  def zstd_train_dictionary(_samples, _max_size), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Prepare `dictionary` (e.g. from `zstd_train_dictionary/2`) once for compressing
+  at `level` and for decompressing, returning a handle for
+  `zstd_compress_with_dictionary/2` and `zstd_decompress_with_dictionary/3`.
+  """
+  def zstd_dictionary(_dictionary, _level), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Compress `data` against a prepared dictionary.
+  """
+  def zstd_compress_with_dictionary(_dictionary, _data), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Decompress `data` compressed against the same dictionary, capped at
+  `max_size` bytes as in `zstd_decompress/2`.
+  """
+  def zstd_decompress_with_dictionary(_dictionary, _data, _max_size),
+    do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Compress `data` with LZ4, much faster than zstd at a lower ratio, for
+  latency-sensitive network frames. The result starts with the uncompressed
+  length as a 32-bit little-endian integer, then an LZ4 block.
+  """
+  def lz4_compress(_data), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Decompress a frame from `lz4_compress/1`. Returns `{:error, reason}` if it is
+  malformed or its stated length exceeds `max_size` bytes, which is checked
+  before anything is allocated, so frames from peers can't exhaust memory.
+  """
+  def lz4_decompress(_data, _max_size), do: :erlang.nif_error(:nif_not_loaded)
+
+  # === Randomness ===
+
+  @doc """
+  Return `size` bytes (at most 1 MiB) from the OS CSPRNG. Use this for every
+  nonce, seed and salt the chain needs.
+
+  Returns `{:error, reason}` if the RNG health check run at load (or by the last
+  `rng_selftest/0`) failed.
+  """
+  def secure_random_bytes(_size), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Re-run the RNG health checks (FIPS 140-2 statistical tests on a fresh sample),
+  returning `:ok` or `{:error, reason}`. A pass re-enables `secure_random_bytes/1`
+  after a failure.
+  """
+  def rng_selftest, do: :erlang.nif_error(:nif_not_loaded)
+
+  # === Keypair Validation ===
+
+  @doc """
+  Check that `private_key` (secret key bytes or a handle) belongs to `public_key`
+  for `algorithm` (`:dilithium2`, `:falcon512` or `:sphincsplus`).
+
+  Runs layout checks, then signs and verifies a fixed test message. Returns
+  `false` for corrupted or mismatched keys; raises `ArgumentError` for an unknown
+  algorithm.
+  """
+  def validate_keypair(_algorithm, _public_key, _private_key), do: :erlang.nif_error(:nif_not_loaded)
+
+  # === Benchmarks ===
+
+  @doc """
+  Time `iterations` (1..100_000) runs of `op` (`:keygen`, `:sign` or `:verify`)
+  with `algorithm`, natively and without NIF call overhead.
+
+  Returns `%{iterations: n, min_ns: _, median_ns: _, p99_ns: _, ops_per_sec: _}`.
+  Sign and verify use a throwaway keypair and a 32-byte message. Runs on a dirty
+  scheduler; SPHINCS+ signing takes tens of milliseconds per iteration, so keep
+  counts modest there.
+  """
+  def benchmark(_algorithm, _op, _iterations), do: :erlang.nif_error(:nif_not_loaded)
+
+  # === Addresses ===
+
+  @doc """
+  Derive the 25-byte address of a single public key: the algorithm id
+  (`:dilithium2` 1, `:falcon512` 2, `:sphincsplus` 3), the first 20 bytes of its
+  Blake3 hash, then a 4-byte Blake3 checksum of those 21 bytes. Raises
+  `ArgumentError` when the key length does not match the algorithm.
+
+  Accounts on chain still use the three-key address of `Bastille.Shared.Crypto`.
+  """
+  def derive_address(_algorithm, _public_key), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Check the length, algorithm id and checksum of address bytes from `derive_address/2`.
+  """
+  def valid_address(_address), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Encode data as a Bech32m string (BIP-350) with a network human-readable part,
+  e.g. `bech32m_encode("bast", address)`. Raises `ArgumentError` for an invalid
+  hrp or a result longer than 90 characters.
+  """
+  def bech32m_encode(_hrp, _data), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Decode a Bech32m string into `{hrp, data}` with the hrp lowercased. Returns
+  `{:error, reason}` for a bad checksum (including plain Bech32), mixed case,
+  invalid characters or non-zero padding.
+  """
+  def bech32m_decode(_address), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Encode a payload as Base58Check with a one-byte version prefix, as Bitcoin-style
+  tooling and legacy wallets expect.
+  """
+  def base58check_encode(_version, _payload), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Decode a Base58Check string into `{version, payload}`, or `{:error, reason}` for
+  invalid characters or a bad checksum.
+  """
+  def base58check_decode(_encoded), do: :erlang.nif_error(:nif_not_loaded)
+
+  # === Key Rotation ===
+
+  @doc """
+  Build a key rotation certificate: the old key signs the canonical statement
+  `(algorithm, old_public_key, new_public_key, effective_height, reason)` and the
+  signature is appended. The private key may be raw bytes or a handle. Raises
+  `ArgumentError` for keys that do not match `algorithm` (`:dilithium2`,
+  `:falcon512` or `:sphincsplus`), a reason over 256 bytes, or an old private key
+  that does not belong to `old_public_key`.
+  """
+  def build_rotation_certificate(_algorithm, _old_public_key, _old_private_key, _new_public_key, _effective_height, _reason),
+    do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Verify a rotation certificate and return `{:ok, statement}` with the map keys
+  `:algorithm`, `:old_public_key`, `:new_public_key`, `:effective_height` and
+  `:reason`, or `{:error, reason}`. Callers still have to check that
+  `:old_public_key` is the validator's current key.
+  """
+  def verify_rotation_certificate(_certificate), do: :erlang.nif_error(:nif_not_loaded)
+
+  # === Key Export ===
+
+  @doc """
+  Encrypt a keypair under a passphrase (Argon2id + XChaCha20-Poly1305) into a
+  versioned blob for moving validator identities between machines. Raises
+  `ArgumentError` for keys that are not Dilithium2, Falcon-512 or SPHINCS+.
+  """
+  def export_keypair(_public_key, _secret_key, _passphrase), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Decrypt a blob produced by `export_keypair/3` into `{public_key, secret_key}`, or
+  return `{:error, reason}` for a wrong passphrase or a corrupted blob.
+  """
+  def import_keypair(_blob, _passphrase), do: :erlang.nif_error(:nif_not_loaded)
+
+  # === Distributed Key Generation ===
+
+  @doc """
+  Start a distributed key generation ceremony as party `index` (1-based) of
+  `parties`, where any `threshold` parties (at least 2) can later recover the key.
+
+  All parties must use the same `session_id` (1 to 64 bytes naming the ceremony).
+  Returns `{session, shares}` where `shares` is a list of `{recipient_index, share}`
+  to deliver to each other party over an authenticated, encrypted channel.
+
+  The scheme is plain Shamir sharing without verification of dealers: combine
+  several subsets of key shares at the end of the ceremony and check that they
+  give the same public key.
+  """
+  def dkg_new(_session_id, _threshold, _parties, _index), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Add a share received from another party to `session`. Returns `:ok` or
+  `{:error, reason}` for shares from another ceremony, for another party, or
+  already received.
+  """
+  def dkg_receive(_session, _share), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Return this party's key share once shares from every party have been received,
+  or `{:error, reason}` listing the missing ones. The key share is secret and must
+  be stored securely (see `export_keypair/3` for the same concern with keys).
+  """
+  def dkg_finalize(_session), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Recover the keypair for `algorithm` (`:dilithium2`, `:falcon512` or
+  `:sphincsplus`) from at least `threshold` key shares of the same ceremony,
+  returning `{public_key, secret_key}` or `{:error, reason}`.
+  """
+  def dkg_combine(_algorithm, _key_shares), do: :erlang.nif_error(:nif_not_loaded)
+
+  # === Verifiable Random Function ===
+
+  @doc """
+  Fresh VRF keypair `{public_key, secret_key}`, 32 bytes each.
+
+  The VRF is ECVRF-EDWARDS25519-SHA512-TAI (RFC 9381) and its keys are Ed25519
+  keys: keep the secret key for the VRF only, never for signatures.
+  """
+  def vrf_keypair, do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Public key of a 32-byte VRF `secret_key`.
+  """
+  def vrf_public_key(_secret_key), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  `{output, proof}` for `input` under `secret_key`: a 64-byte output that only
+  the key holder can compute, and an 80-byte proof of it.
+
+  For leader election, `input` is the epoch seed followed by the slot; each
+  validator publishes its proof and the slot leader is decided from the outputs,
+  which nobody can predict or grind.
+  """
+  def vrf_prove(_secret_key, _input), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  The 64-byte output if `proof` was made for `input` with the key of
+  `public_key`, or `{:error, reason}`.
+
+  Small-order public keys are refused. Raises `ArgumentError` unless the key is
+  32 bytes and the proof 80.
+  """
+  def vrf_verify(_public_key, _input, _proof), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  The output `proof` commits to, without verifying it; or `{:error, reason}`
+  for a malformed proof. Only for proofs already checked with `vrf_verify/3`.
+  """
+  def vrf_proof_to_hash(_proof), do: :erlang.nif_error(:nif_not_loaded)
+
+  # === Verifiable Delay Function ===
+
+  @doc """
+  Start a Wesolowski VDF evaluation of `seed` (up to 1024 bytes) over
+  `iterations` sequential squarings (at least 1), for the randomness beacon.
+
+  Runs on its own OS thread, since it is meant to take minutes, and returns a
+  handle for `vdf_progress/1` right away. Dropping the handle cancels it. The
+  group is the class group of a 1024-bit discriminant derived from the seed,
+  so there is no trusted setup.
+  """
+  def vdf_eval(_seed, _iterations), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  `{:running, done, total}` squarings (twice the iterations: the output, then
+  the proof), `{:done, output, proof}` with 130-byte binaries, or
+  `{:error, reason}` once cancelled.
+  """
+  def vdf_progress(_evaluation), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Stop an evaluation; `vdf_progress/1` then returns `{:error, "cancelled"}`.
+  """
+  def vdf_cancel(_evaluation), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Whether `output` and `proof` are the result of `vdf_eval/2` for `seed` and
+  `iterations`. Takes tens of milliseconds whatever the iterations.
+  """
+  def vdf_verify(_seed, _iterations, _output, _proof), do: :erlang.nif_error(:nif_not_loaded)
+
+  # === Randomness Beacon ===
+
+  @doc """
+  Commitment to a 32-byte `reveal` with a 32-byte `salt`, published in the
+  commit phase of the epoch randomness beacon: a domain-separated BLAKE3
+  hash of `reveal <> salt`. Raises `ArgumentError` for other lengths.
+  """
+  def beacon_commit(_reveal, _salt), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Check a batch of `{commitment, reveal, salt}` openings in parallel.
+  Returns the indices of those whose reveal doesn't match the commitment,
+  `[]` when all do.
+  """
+  def beacon_verify_reveals(_items), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  The epoch's randomness: the valid reveals, in any order, mixed into the
+  previous epoch's 32-byte randomness. The reveals are sorted before they
+  are hashed, so every node gets the same bytes whatever order it received
+  them in.
+  """
+  def beacon_mix(_previous, _epoch, _reveals), do: :erlang.nif_error(:nif_not_loaded)
+
+  # === WebAssembly Contracts ===
+  # Needs the NIF built with the wasm feature (see :crypto_nif_features).
+
+  @doc """
+  Compile and instantiate a WebAssembly module (binary, or text format for
+  tests and tooling) for the contract runtime, which is configured so every
+  node gets the same results and gas: NaNs are canonicalized, SIMD and
+  threads are off, and memory and tables are capped. Returns a handle, or
+  `{:error, reason}`.
+
+  A module metered by `wasm_meter/1` is charged the gas it counts itself
+  rather than the engine's.
+
+  Modules may import these functions from `"bastille"`, which take offsets
+  and lengths into the module's exported `"memory"` and charge fixed gas
+  (100 for `blake3`, 100_000 for `verify`, 10_000 for storage) plus one per
+  byte:
+
+    * `blake3(ptr, len, out)` - writes the 32-byte hash at `out`
+    * `verify(algorithm, key, key_len, msg, msg_len, sig, sig_len)` - 1 for
+      a valid signature, 0 otherwise; `algorithm` is 1 for Dilithium2 and 2
+      for Falcon-512
+    * `storage_get(key, key_len, out, out_cap)` - copies at most `out_cap`
+      bytes of the value to `out` and returns its length, or -1 when unset
+    * `storage_put(key, key_len, value, value_len)`
+
+  Keys are at most 256 bytes and values at most 64 KiB. Storage is served by
+  the `:storage` process, which can't be the caller of `wasm_call/4`, busy
+  in the call: a get sends it `{:wasm_storage, :get, reply, key}` and waits
+  for `wasm_storage_reply/2`, a put sends it `{:wasm_storage, :put, key,
+  value}` without waiting. Puts arrive as the contract makes them, so the
+  process should hold them until the caller commits or discards the call.
+
+  A call's result depends only on the code, its arguments, the gas limit and
+  the storage it reads, never on how quickly a node's storage process
+  answers: a get left unanswered for `:storage_timeout`, or a storage
+  process that is gone, aborts the call (see `wasm_call/4`) rather than
+  trapping in the contract. The storage process should answer from memory;
+  a waiting get holds a dirty scheduler.
+
+  ## Options
+    * `:max_memory` - bytes of linear memory; `memory.grow` past it returns
+      -1 (default 16 MiB)
+    * `:max_table_elements` - elements per table (default 10_000)
+    * `:start_gas` - gas for the module's start function (default 1_000_000)
+    * `:storage` - pid of the storage process, not `self()`; storage calls
+      trap without it
+    * `:storage_timeout` - milliseconds a storage get waits for its reply
+      before trapping (default 5_000)
+  """
+  def wasm_instantiate(_code, _opts), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Instrument a contract module (binary, or text format for tests and
+  tooling) with its own gas accounting and return the canonical metered
+  binary, the form to store on chain. Every run of instructions up to a
+  branch or block boundary charges one gas per instruction on entry, and
+  `memory.fill` and `memory.copy` one more per byte, `memory.grow` one per
+  byte of new memory (65_536 per page), so every node charges the same gas
+  whatever engine runs it.
+
+  Floats, SIMD and threads, whose results can differ between nodes, are
+  rejected, as are reference types, memory64 and later proposals, and a
+  module already exporting `bastille_gas`, the global the gas is counted in.
+  Custom sections are dropped. Returns `{:error, reason}` for a rejected
+  module.
+  """
+  def wasm_meter(_code), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Call the exported function `function` (a string) of an instance with
+  `args` (integers for i32/i64 parameters, floats for f32/f64) and at most
+  `gas_limit` gas, about one per instruction. Calls on one instance run one
+  at a time, and its memory persists between them.
+
+  Returns `{results, gas_used}`, or `{:error, reason, gas_used}` when the
+  call traps, runs out of gas (using all of `gas_limit`) or doesn't match
+  the export. Both are outcomes every node reaches alike.
+
+  Returns `{:aborted, reason}` when storage failed the call (see
+  `wasm_instantiate/2`) or the caller is the storage process: the call has
+  no outcome, must not be committed, and its puts must be discarded. The
+  instance refuses any further call; instantiate the contract again.
+  """
+  def wasm_call(_instance, _function, _args, _gas_limit), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Answer a contract's storage get with the value, or `nil` when the key is
+  unset. Returns `:ok`, or `{:error, reason}` if it was already answered; an
+  answer after the timeout is ignored.
+  """
+  def wasm_storage_reply(_reply, _value), do: :erlang.nif_error(:nif_not_loaded)
+
+  # === Secret Hygiene ===
+
+  @doc """
+  Overwrite the bytes of a binary with zeros in place and return `:ok`.
+
+  Best effort only. Small binaries (64 bytes or less) live on the process heap and
+  may already have been copied by garbage collection or message passing, and every
+  term sharing the bytes (including sub-binaries) sees the zeros. Never pass a
+  binary literal from code. Use it on secret keys right before dropping them.
+  """
+  def secure_wipe(_binary), do: :erlang.nif_error(:nif_not_loaded)
+
+  # === Remote Signing ===
+
+  @doc """
+  Handle for a key held by an external signer daemon on the Unix socket at
+  `socket_path`, identified by `key_id` (a non-empty binary).
+
+  The handle can be passed to `dilithium2_sign/2`, `falcon512_sign/2` or
+  `sphincsplus_shake_128f_sign/2` in place of a secret key, so hot keys can stay
+  inside an HSM fronted by the daemon. Signing then returns `{:error, reason}` if
+  the daemon is unreachable or refuses. The algorithm must match the sign function.
+
+  Only available when the NIF is built with the `remote-signer` feature
+  (`config :bastille, crypto_nif_features: ["remote-signer"]`).
   """
-  def blake3_hash(_data), do: :erlang.nif_error(:nif_not_loaded)
+  def remote_signer_key(_socket_path, _algorithm, _key_id), do: :erlang.nif_error(:nif_not_loaded)
 end
@@ -10,6 +10,11 @@ defmodule Bastille.Shared.CryptoUtils do
   For Bastille-specific mining operations, see Bastille.Features.Mining.Mining.
   """
 
+  alias Bastille.Infrastructure.Crypto.CryptoNif
+
+  # Largest request CryptoNif.secure_random_bytes/1 serves in one call
+  @max_random_request 1_048_576
+
   @doc """
   Computes SHA-256 hash of the given data.
   """
@@ -63,10 +68,24 @@ defmodule Bastille.Shared.CryptoUtils do
   end
 
   @doc """
-  Generates a random hash of specified byte length.
+  Generates a random hash of specified byte length from the native CSPRNG.
+
+  Lengths over 1 MiB are drawn in several calls. Raises if the CSPRNG's health
+  check failed rather than returning weak bytes.
   """
-  @spec random(pos_integer()) :: binary()
-  def random(byte_length \\ 32) do
-    :crypto.strong_rand_bytes(byte_length)
+  @spec random(non_neg_integer()) :: binary()
+  def random(byte_length \\ 32) when is_integer(byte_length) and byte_length >= 0 do
+    random_chunks(byte_length, [])
+  end
+
+  defp random_chunks(0, acc), do: IO.iodata_to_binary(acc)
+
+  defp random_chunks(remaining, acc) do
+    size = min(remaining, @max_random_request)
+
+    case CryptoNif.secure_random_bytes(size) do
+      bytes when is_binary(bytes) -> random_chunks(remaining - size, [acc, bytes])
+      {:error, reason} -> raise "secure random bytes unavailable: #{reason}"
+    end
   end
 end
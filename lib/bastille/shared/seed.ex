@@ -5,6 +5,7 @@ defmodule Bastille.Shared.Seed do
   """
 
   alias Bastille.Shared.Crypto
+  alias Bastille.Shared.CryptoUtils
   alias Bastille.Shared.Mnemonic
 
   @doc """
@@ -12,7 +13,7 @@ defmodule Bastille.Shared.Seed do
   """
   def generate_master_seed do
     # Generate 32 bytes of high-quality entropy (BIP39 standard)
-    entropy = :crypto.strong_rand_bytes(32)
+    entropy = CryptoUtils.random(32)
     Mnemonic.to_mnemonic(entropy)
   end
 
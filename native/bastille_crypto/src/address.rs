@@ -0,0 +1,39 @@
+// Canonical single-key address bytes.
+//
+//   version u8 (algorithm id) | BLAKE3(public_key)[..20] | BLAKE3(previous 21 bytes)[..4]
+//
+// The trailing checksum catches corrupted or mistyped addresses before any
+// lookup, whatever text encoding (hex, bech32m, base58) is layered on top.
+
+use crate::keygen::Algorithm;
+
+pub const HASH_LEN: usize = 20;
+pub const CHECKSUM_LEN: usize = 4;
+pub const ADDRESS_LEN: usize = 1 + HASH_LEN + CHECKSUM_LEN;
+
+fn checksum(payload: &[u8]) -> [u8; CHECKSUM_LEN] {
+    let mut checksum = [0u8; CHECKSUM_LEN];
+    checksum.copy_from_slice(&blake3::hash(payload).as_bytes()[..CHECKSUM_LEN]);
+    checksum
+}
+
+/// Derive the address of `public_key`, or None if its length doesn't match `algorithm`.
+pub fn derive_address(algorithm: Algorithm, public_key: &[u8]) -> Option<[u8; ADDRESS_LEN]> {
+    if public_key.len() != algorithm.public_key_bytes() {
+        return None;
+    }
+
+    let mut address = [0u8; ADDRESS_LEN];
+    address[0] = algorithm.id();
+    address[1..1 + HASH_LEN].copy_from_slice(&blake3::hash(public_key).as_bytes()[..HASH_LEN]);
+    let checksum = checksum(&address[..1 + HASH_LEN]);
+    address[1 + HASH_LEN..].copy_from_slice(&checksum);
+    Some(address)
+}
+
+/// Check the length, version byte and checksum of address bytes.
+pub fn valid_address(address: &[u8]) -> bool {
+    address.len() == ADDRESS_LEN
+        && Algorithm::ALL.iter().any(|algorithm| algorithm.id() == address[0])
+        && checksum(&address[..1 + HASH_LEN]) == address[1 + HASH_LEN..]
+}
@@ -0,0 +1,93 @@
+// Mempool admission checks, run on a batch of encoded transactions before any
+// of them reach the Elixir pipeline, so a flood of garbage costs the pool's
+// threads rather than the mempool process. Each transaction gets a verdict:
+// it decodes, fits the size limit, is for this chain, pays the minimum fee,
+// has a nonce the sender can still use and a 2-of-3 post-quantum signature
+// from the sender's keys over its signing bytes. The checks need no state
+// beyond what the caller passes in, so a pass is not a promise that the
+// transaction applies (the balance is not checked).
+
+use crate::keygen::Algorithm;
+use crate::tx::{self, Signature, SignatureType};
+use rayon::prelude::*;
+use std::collections::HashMap;
+
+pub struct Params {
+    pub chain_id: Option<Vec<u8>>,
+    /// Largest encoded transaction, in bytes
+    pub max_size: usize,
+    pub min_fee: u128,
+    /// Next nonce of each sender; senders not in the map start at 0
+    pub nonces: HashMap<Vec<u8>, u64>,
+    /// How far past the sender's next nonce a transaction may be
+    pub max_nonce_gap: u64,
+    /// Dilithium, Falcon and SPHINCS+ public keys of each sender
+    pub public_keys: HashMap<Vec<u8>, [Vec<u8>; 3]>,
+}
+
+fn valid_signatures(keys: &[Vec<u8>; 3], signatures: [&[u8]; 3], message: &[u8]) -> usize {
+    let algorithms = [Algorithm::Dilithium2, Algorithm::Falcon512, Algorithm::SphincsPlus];
+    let mut valid = 0;
+    for ((algorithm, key), signature) in algorithms.into_iter().zip(keys).zip(signatures) {
+        // Two are enough, and SPHINCS+ is by far the slowest to check
+        if valid == 2 {
+            break;
+        }
+        if crate::public_key::verify_bytes(algorithm, key, signature, message) {
+            valid += 1;
+        }
+    }
+    valid
+}
+
+// The sender and nonce of `bytes` if it passes every check
+fn check(bytes: &[u8], params: &Params) -> Result<(Vec<u8>, u64), String> {
+    if bytes.len() > params.max_size {
+        return Err(format!("transaction of {} bytes, over {}", bytes.len(), params.max_size));
+    }
+    let tx = tx::decode(bytes)?;
+    if params.chain_id.as_ref().is_some_and(|chain_id| *chain_id != tx.chain_id) {
+        return Err("transaction for another chain".to_string());
+    }
+    if tx.signature_type == SignatureType::Coinbase {
+        return Err("coinbase transaction outside a block".to_string());
+    }
+    if tx.fee < params.min_fee {
+        return Err(format!("fee {} below the minimum of {}", tx.fee, params.min_fee));
+    }
+    let next = params.nonces.get(&tx.from).copied().unwrap_or(0);
+    if tx.nonce < next {
+        return Err(format!("nonce {} already used, next is {}", tx.nonce, next));
+    }
+    if tx.nonce - next > params.max_nonce_gap {
+        return Err(format!("nonce {} too far past the next nonce {}", tx.nonce, next));
+    }
+
+    let Signature::PostQuantum { dilithium, falcon, sphincs } = &tx.signature else {
+        return Err("unsigned transaction".to_string());
+    };
+    let keys = params.public_keys.get(&tx.from).ok_or("no public keys for the sender")?;
+    let message = tx::signing_bytes(&tx)?;
+    if valid_signatures(keys, [dilithium, falcon, sphincs], &message) < 2 {
+        return Err("fewer than 2 of 3 valid signatures".to_string());
+    }
+    Ok((tx.from, tx.nonce))
+}
+
+/// A verdict per transaction, in order. Of transactions from one sender with
+/// the same nonce, only the first can pass.
+pub fn precheck(transactions: &[&[u8]], params: &Params) -> Vec<Result<(), String>> {
+    let checked: Vec<_> = crate::threads::install(|| transactions.par_iter().map(|bytes| check(bytes, params)).collect());
+
+    let mut seen = std::collections::HashSet::new();
+    checked
+        .into_iter()
+        .map(|result| {
+            let (from, nonce) = result?;
+            if !seen.insert((from, nonce)) {
+                return Err(format!("nonce {} already used earlier in the batch", nonce));
+            }
+            Ok(())
+        })
+        .collect()
+}
@@ -0,0 +1,568 @@
+// Append-only block archive: blocks by height in segment files, so serving
+// historical blocks needs neither the key-value store nor any index in it.
+//
+// Segment n holds heights n * SEGMENT_BLOCKS up to the next segment's first:
+//
+//   <dir>/<n>.blk  magic "BARCHIV1" | blocks, back to back in append order
+//   <dir>/<n>.idx  a slot per height: offset (u64 BE) | length (u32 BE) |
+//                  crc32c of the block (u32 BE); all zero if never appended
+//
+// Index files are created at full size and memory-mapped, so a read is a slot
+// lookup and one positioned read, checked against the slot's CRC. Mapped
+// reads skip the copy: data files are memory-mapped too (remapped as appends
+// outgrow the map), and a block is returned as a view into the map, which
+// stays alive as long as any view does.
+//
+// Heights must increase, with gaps allowed. An append writes the block, then
+// its slot; on open, slots at the end of the last segment whose block is cut
+// short or fails its CRC (a crash mid-append) are cleared and the data file
+// truncated after the last good block. Appends reach the OS at once, and disk
+// on flush() or when the next segment is started.
+//
+// Pruning deletes whole segments, so heights below the pruning point that
+// share a segment with kept ones stay readable. The index goes first: a crash
+// midway leaves only a data file, which is ignored.
+//
+// With a keyring (see at_rest.rs) blocks are sealed before they are written,
+// bound to their height, and slot CRCs cover the sealed bytes; mapped reads
+// are then unavailable, as blocks must be decrypted. Rotating the key rewrites
+// each segment under the new key, next to the old one: the new index file is
+// created first, then the data file, and once both are complete the index is
+// renamed into place, then the data file. On open, a rewrite that left its
+// index unrenamed is discarded and one that left only its data file finished.
+
+use crate::at_rest::{self, Keyring};
+use crate::smt::io_error;
+use lru::LruCache;
+use memmap2::{Mmap, MmapMut};
+use rustler::ResourceArc;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::num::NonZeroUsize;
+use std::ops::Range;
+use std::os::unix::fs::FileExt;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+
+pub const SEGMENT_BLOCKS: u64 = 8192;
+
+const MAGIC: [u8; 8] = *b"BARCHIV1";
+const SLOT_LEN: usize = 8 + 4 + 4;
+const INDEX_LEN: u64 = SEGMENT_BLOCKS * SLOT_LEN as u64;
+// Sealed segments kept open for reads, on top of the one being appended to
+const OPEN_SEGMENTS: usize = 64;
+
+struct Slot {
+    offset: u64,
+    len: u32,
+    crc: u32,
+}
+
+/// A read-only map of a segment's data file, shared by the binaries viewing it.
+pub struct SegmentMap {
+    map: Mmap,
+}
+
+#[rustler::resource_impl]
+impl rustler::Resource for SegmentMap {}
+
+impl SegmentMap {
+    pub fn bytes(&self) -> &[u8] {
+        &self.map
+    }
+}
+
+/// A block as a range of its segment's map.
+pub type MappedBlock = (ResourceArc<SegmentMap>, Range<usize>);
+
+struct Segment {
+    data_path: PathBuf,
+    data: File,
+    data_len: u64,
+    index: MmapMut,
+    map: Option<ResourceArc<SegmentMap>>,
+}
+
+impl Segment {
+    /// None if the segment doesn't exist and `create` is false.
+    fn open(dir: &Path, number: u64, create: bool) -> Result<Option<Self>, String> {
+        let data_path = dir.join(format!("{:010}.blk", number));
+        let index_path = dir.join(format!("{:010}.idx", number));
+        if !create && !index_path.exists() {
+            return Ok(None);
+        }
+        let data = OpenOptions::new()
+            .create(create)
+            .truncate(false)
+            .read(true)
+            .write(true)
+            .open(&data_path)
+            .map_err(|e| io_error("open", &data_path, e))?;
+        let index_file = OpenOptions::new()
+            .create(create)
+            .truncate(false)
+            .read(true)
+            .write(true)
+            .open(&index_path)
+            .map_err(|e| io_error("open", &index_path, e))?;
+
+        let mut data_len = data.metadata().map_err(|e| io_error("read", &data_path, e))?.len();
+        if data_len == 0 {
+            data.write_all_at(&MAGIC, 0).map_err(|e| io_error("write", &data_path, e))?;
+            data_len = MAGIC.len() as u64;
+        } else {
+            let mut magic = [0u8; 8];
+            if data.read_exact_at(&mut magic, 0).is_err() || magic != MAGIC {
+                return Err(format!("'{}' is not a block archive segment", data_path.display()));
+            }
+        }
+        match index_file.metadata().map_err(|e| io_error("read", &index_path, e))?.len() {
+            0 => index_file.set_len(INDEX_LEN).map_err(|e| io_error("resize", &index_path, e))?,
+            INDEX_LEN => {}
+            _ => return Err(format!("'{}' is not a block archive index", index_path.display())),
+        }
+        // SAFETY: the archive's lock file keeps other handles (in this process
+        // or another) from opening the directory, and the index is never
+        // truncated while mapped
+        let index = unsafe { MmapMut::map_mut(&index_file) }.map_err(|e| io_error("map", &index_path, e))?;
+        Ok(Some(Segment { data_path, data, data_len, index, map: None }))
+    }
+
+    fn slot(&self, i: usize) -> Option<Slot> {
+        let raw = &self.index[i * SLOT_LEN..(i + 1) * SLOT_LEN];
+        let offset = u64::from_be_bytes(raw[0..8].try_into().unwrap());
+        if offset == 0 {
+            return None;
+        }
+        let len = u32::from_be_bytes(raw[8..12].try_into().unwrap());
+        let crc = u32::from_be_bytes(raw[12..16].try_into().unwrap());
+        Some(Slot { offset, len, crc })
+    }
+
+    fn set_slot(&mut self, i: usize, slot: Option<&Slot>) {
+        let raw = &mut self.index[i * SLOT_LEN..(i + 1) * SLOT_LEN];
+        match slot {
+            Some(slot) => {
+                raw[0..8].copy_from_slice(&slot.offset.to_be_bytes());
+                raw[8..12].copy_from_slice(&slot.len.to_be_bytes());
+                raw[12..16].copy_from_slice(&slot.crc.to_be_bytes());
+            }
+            None => raw.fill(0),
+        }
+    }
+
+    // None if the block is cut short or fails its CRC
+    fn read(&self, slot: &Slot) -> Result<Option<Vec<u8>>, String> {
+        if slot.offset < MAGIC.len() as u64 || slot.offset + slot.len as u64 > self.data_len {
+            return Ok(None);
+        }
+        let mut block = vec![0u8; slot.len as usize];
+        self.data
+            .read_exact_at(&mut block, slot.offset)
+            .map_err(|e| io_error("read", &self.data_path, e))?;
+        Ok((crc32c::crc32c(&block) == slot.crc).then_some(block))
+    }
+
+    // A map of the data file reaching at least `end`
+    fn map(&mut self, end: u64) -> Result<ResourceArc<SegmentMap>, String> {
+        if let Some(map) = &self.map {
+            if map.map.len() as u64 >= end {
+                return Ok(map.clone());
+            }
+        }
+        // SAFETY: the archive's lock keeps other writers out, and written
+        // blocks are never modified. The file is only truncated on open, past
+        // the last good block, so pages backing a block stay valid for as long
+        // as an older map of them lives, even once the file is pruned.
+        let map = unsafe { Mmap::map(&self.data) }.map_err(|e| io_error("map", &self.data_path, e))?;
+        let map = ResourceArc::new(SegmentMap { map });
+        self.map = Some(map.clone());
+        Ok(map)
+    }
+
+    // Clear trailing slots left by an interrupted append and truncate the data
+    // after the last good block, whose slot index is returned
+    fn recover(&mut self) -> Result<Option<usize>, String> {
+        let mut last = None;
+        let mut end = MAGIC.len() as u64;
+        for i in (0..SEGMENT_BLOCKS as usize).rev() {
+            let Some(slot) = self.slot(i) else { continue };
+            if self.read(&slot)?.is_some() {
+                last = Some(i);
+                end = slot.offset + slot.len as u64;
+                break;
+            }
+            self.set_slot(i, None);
+        }
+        if self.data_len > end {
+            self.data.set_len(end).map_err(|e| io_error("truncate", &self.data_path, e))?;
+            self.data_len = end;
+        }
+        Ok(last)
+    }
+
+    fn sync(&self) -> Result<(), String> {
+        self.data.sync_data().map_err(|e| io_error("sync", &self.data_path, e))?;
+        self.index.flush().map_err(|e| io_error("sync", &self.data_path.with_extension("idx"), e))
+    }
+}
+
+// Finish or discard segment rewrites interrupted by a crash (see above)
+fn finish_rewrites(dir: &Path) -> Result<(), String> {
+    let mut rewrites = Vec::new();
+    for entry in fs::read_dir(dir).map_err(|e| io_error("list", dir, e))? {
+        let path = entry.map_err(|e| io_error("list", dir, e))?.path();
+        if path.extension().is_some_and(|ext| ext == "rekey") {
+            rewrites.push(path);
+        }
+    }
+    // An index not yet renamed: the rewrite may be incomplete
+    for index in rewrites.iter().filter(|path| path.with_extension("").extension().is_some_and(|ext| ext == "idx")) {
+        fs::remove_file(index).map_err(|e| io_error("remove", index, e))?;
+        let data = index.with_extension("").with_extension("blk.rekey");
+        if data.exists() {
+            fs::remove_file(&data).map_err(|e| io_error("remove", &data, e))?;
+        }
+    }
+    for data in rewrites.iter().filter(|path| path.exists()) {
+        fs::rename(data, data.with_extension("")).map_err(|e| io_error("rename", data, e))?;
+    }
+    Ok(())
+}
+
+fn segment_numbers(dir: &Path) -> Result<Vec<u64>, String> {
+    let mut numbers = Vec::new();
+    for entry in fs::read_dir(dir).map_err(|e| io_error("list", dir, e))? {
+        let path = entry.map_err(|e| io_error("list", dir, e))?.path();
+        if path.extension().is_some_and(|ext| ext == "idx") {
+            if let Some(number) = path.file_stem().and_then(|stem| stem.to_str()?.parse::<u64>().ok()) {
+                numbers.push(number);
+            }
+        }
+    }
+    numbers.sort_unstable();
+    Ok(numbers)
+}
+
+struct ArchiveState {
+    dir: PathBuf,
+    // The segment of the last height appended, if any
+    active: Option<(u64, Segment)>,
+    sealed: LruCache<u64, Segment>,
+    last: Option<u64>,
+    keys: Option<Keyring>,
+    _lock: File,
+}
+
+pub struct Archive {
+    state: Mutex<ArchiveState>,
+}
+
+#[rustler::resource_impl]
+impl rustler::Resource for Archive {}
+
+impl ArchiveState {
+    fn open(dir: &Path, keys: Option<Keyring>) -> Result<Self, String> {
+        fs::create_dir_all(dir).map_err(|e| io_error("create archive directory", dir, e))?;
+        let lock_path = dir.join("archive.lock");
+        let lock = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&lock_path)
+            .map_err(|e| io_error("open", &lock_path, e))?;
+        lock.try_lock().map_err(|_| format!("block archive '{}' is already open", dir.display()))?;
+
+        finish_rewrites(dir)?;
+        let numbers = segment_numbers(dir)?;
+        at_rest::check_marker(dir, keys.as_ref(), numbers.is_empty())?;
+        let mut state = ArchiveState {
+            dir: dir.to_path_buf(),
+            active: None,
+            sealed: LruCache::new(NonZeroUsize::new(OPEN_SEGMENTS).unwrap()),
+            last: None,
+            keys,
+            _lock: lock,
+        };
+        // A segment left empty by a crash defers to the one before it
+        for number in numbers.into_iter().rev() {
+            let mut segment = Segment::open(dir, number, false)?.ok_or("archive segment vanished while opening")?;
+            if let Some(i) = segment.recover()? {
+                state.last = Some(number * SEGMENT_BLOCKS + i as u64);
+                state.active = Some((number, segment));
+                break;
+            }
+        }
+        Ok(state)
+    }
+
+    // Segment `number` for reading, if it exists
+    fn segment(&mut self, number: u64) -> Result<Option<&mut Segment>, String> {
+        if let Some((active, segment)) = &mut self.active {
+            if *active == number {
+                return Ok(Some(segment));
+            }
+        }
+        if !self.sealed.contains(&number) {
+            let Some(segment) = Segment::open(&self.dir, number, false)? else {
+                return Ok(None);
+            };
+            self.sealed.put(number, segment);
+        }
+        Ok(self.sealed.get_mut(&number))
+    }
+
+    fn append(&mut self, height: u64, block: &[u8]) -> Result<(), String> {
+        if let Some(last) = self.last {
+            if height <= last {
+                return Err(format!("height {} is not above the last archived height {}", height, last));
+            }
+        }
+        let sealed = match &self.keys {
+            Some(keys) => Some(keys.seal(&height.to_be_bytes(), block)?),
+            None => None,
+        };
+        let block = sealed.as_deref().unwrap_or(block);
+        let len = u32::try_from(block.len()).map_err(|_| "block too large for the archive".to_string())?;
+        let number = height / SEGMENT_BLOCKS;
+        if self.active.as_ref().is_none_or(|(active, _)| *active != number) {
+            let segment = match self.sealed.pop(&number) {
+                Some(segment) => segment,
+                None => Segment::open(&self.dir, number, true)?.unwrap(),
+            };
+            if let Some((previous, sealed)) = self.active.replace((number, segment)) {
+                sealed.sync()?;
+                self.sealed.put(previous, sealed);
+            }
+        }
+        let (_, segment) = self.active.as_mut().unwrap();
+
+        // Written at the recorded end, so a failed write is overwritten by the next
+        let offset = segment.data_len;
+        segment
+            .data
+            .write_all_at(block, offset)
+            .map_err(|e| io_error("write", &segment.data_path, e))?;
+        segment.data_len += len as u64;
+        let slot = Slot { offset, len, crc: crc32c::crc32c(block) };
+        segment.set_slot((height % SEGMENT_BLOCKS) as usize, Some(&slot));
+        self.last = Some(height);
+        Ok(())
+    }
+
+    fn prune_below(&mut self, height: u64) -> Result<u64, String> {
+        let active = self.active.as_ref().map(|(number, _)| *number);
+        let mut reclaimed = 0;
+        for number in segment_numbers(&self.dir)? {
+            if (number + 1).saturating_mul(SEGMENT_BLOCKS) > height || Some(number) == active {
+                continue;
+            }
+            self.sealed.pop(&number);
+            for ext in ["idx", "blk"] {
+                let path = self.dir.join(format!("{:010}.{}", number, ext));
+                reclaimed += fs::metadata(&path).map_err(|e| io_error("read", &path, e))?.len();
+                fs::remove_file(&path).map_err(|e| io_error("remove", &path, e))?;
+            }
+        }
+        Ok(reclaimed)
+    }
+
+    fn read(&mut self, height: u64) -> Result<Option<Vec<u8>>, String> {
+        let Some(segment) = self.segment(height / SEGMENT_BLOCKS)? else {
+            return Ok(None);
+        };
+        let Some(slot) = segment.slot((height % SEGMENT_BLOCKS) as usize) else {
+            return Ok(None);
+        };
+        let block = segment
+            .read(&slot)?
+            .ok_or_else(|| format!("archived block at height {} is corrupt", height))?;
+        match &self.keys {
+            Some(keys) => Ok(Some(keys.open(&height.to_be_bytes(), &block)?.to_vec())),
+            None => Ok(Some(block)),
+        }
+    }
+
+    fn read_mapped(&mut self, height: u64) -> Result<Option<MappedBlock>, String> {
+        if self.keys.is_some() {
+            return Err("mapped reads are unavailable on an encrypted archive".to_string());
+        }
+        let Some(segment) = self.segment(height / SEGMENT_BLOCKS)? else {
+            return Ok(None);
+        };
+        let Some(slot) = segment.slot((height % SEGMENT_BLOCKS) as usize) else {
+            return Ok(None);
+        };
+        let corrupt = || format!("archived block at height {} is corrupt", height);
+        let end = slot.offset + slot.len as u64;
+        if slot.offset < MAGIC.len() as u64 || end > segment.data_len {
+            return Err(corrupt());
+        }
+        let map = segment.map(end)?;
+        let range = slot.offset as usize..end as usize;
+        if crc32c::crc32c(&map.bytes()[range.clone()]) != slot.crc {
+            return Err(corrupt());
+        }
+        Ok(Some((map, range)))
+    }
+
+    // Rewrite segment `number` with every block sealed under the current key,
+    // returning how many were resealed
+    fn rekey_segment(&mut self, number: u64) -> Result<u64, String> {
+        let keys = self.keys.take().ok_or("block archive is not encrypted")?;
+        let result = self.rewrite_segment(number, &keys);
+        self.keys = Some(keys);
+        result
+    }
+
+    fn rewrite_segment(&mut self, number: u64, keys: &Keyring) -> Result<u64, String> {
+        let dir = self.dir.clone();
+        let Some(segment) = self.segment(number)? else {
+            return Ok(0);
+        };
+        let slots: Vec<(usize, Slot)> = (0..SEGMENT_BLOCKS as usize)
+            .filter_map(|i| segment.slot(i).map(|slot| (i, slot)))
+            .collect();
+        // The key id sits in the first bytes of a sealed block
+        let mut stale = false;
+        for (_, slot) in &slots {
+            let mut header = [0u8; 9];
+            segment
+                .data
+                .read_exact_at(&mut header, slot.offset)
+                .map_err(|e| io_error("read", &segment.data_path, e))?;
+            if !keys.is_current(&header) {
+                stale = true;
+                break;
+            }
+        }
+        if !stale {
+            return Ok(0);
+        }
+
+        let index_path = dir.join(format!("{:010}.idx.rekey", number));
+        let data_path = dir.join(format!("{:010}.blk.rekey", number));
+        let index_file = File::create(&index_path).map_err(|e| io_error("create", &index_path, e))?;
+        let mut data = BufWriter::new(File::create(&data_path).map_err(|e| io_error("create", &data_path, e))?);
+        data.write_all(&MAGIC).map_err(|e| io_error("write", &data_path, e))?;
+        let mut index = vec![0u8; INDEX_LEN as usize];
+        let mut offset = MAGIC.len() as u64;
+        let mut resealed = 0;
+        for (i, slot) in slots {
+            let height = number * SEGMENT_BLOCKS + i as u64;
+            let block = segment
+                .read(&slot)?
+                .ok_or_else(|| format!("archived block at height {} is corrupt", height))?;
+            let block = if keys.is_current(&block) {
+                block
+            } else {
+                resealed += 1;
+                keys.reseal(&height.to_be_bytes(), &block)?
+            };
+            data.write_all(&block).map_err(|e| io_error("write", &data_path, e))?;
+            let raw = &mut index[i * SLOT_LEN..(i + 1) * SLOT_LEN];
+            raw[0..8].copy_from_slice(&offset.to_be_bytes());
+            raw[8..12].copy_from_slice(&(block.len() as u32).to_be_bytes());
+            raw[12..16].copy_from_slice(&crc32c::crc32c(&block).to_be_bytes());
+            offset += block.len() as u64;
+        }
+        data.into_inner()
+            .map_err(|e| io_error("write", &data_path, e.into_error()))?
+            .sync_all()
+            .map_err(|e| io_error("sync", &data_path, e))?;
+        (&index_file)
+            .write_all(&index)
+            .and_then(|_| index_file.sync_all())
+            .map_err(|e| io_error("write", &index_path, e))?;
+
+        // Closed before its files are replaced; views of the old data stay valid
+        let active = self.active.take_if(|(active, _)| *active == number).is_some();
+        self.sealed.pop(&number);
+        for path in [&index_path, &data_path] {
+            fs::rename(path, path.with_extension("")).map_err(|e| io_error("rename", path, e))?;
+        }
+        File::open(&dir).and_then(|dir| dir.sync_all()).map_err(|e| io_error("sync", &dir, e))?;
+        let segment = Segment::open(&dir, number, false)?.ok_or("archive segment vanished while rewriting")?;
+        if active {
+            self.active = Some((number, segment));
+        } else {
+            self.sealed.put(number, segment);
+        }
+        Ok(resealed)
+    }
+}
+
+impl Archive {
+    /// Open the archive in `dir`, creating it if needed, and recover from an
+    /// interrupted append. An archive created with a keyring must always be
+    /// opened with one.
+    pub fn open(dir: &Path, keys: Option<Keyring>) -> Result<Self, String> {
+        Ok(Archive { state: Mutex::new(ArchiveState::open(dir, keys)?) })
+    }
+
+    fn state(&self) -> Result<MutexGuard<'_, ArchiveState>, String> {
+        self.state.lock().map_err(|_| "block archive lock poisoned".to_string())
+    }
+
+    /// Store `block` at `height`, which must be above every height stored so far.
+    pub fn append(&self, height: u64, block: &[u8]) -> Result<(), String> {
+        self.state()?.append(height, block)
+    }
+
+    /// The block at `height`, or None if none was appended there.
+    pub fn read(&self, height: u64) -> Result<Option<Vec<u8>>, String> {
+        self.state()?.read(height)
+    }
+
+    /// Like read(), but without copying the block out of its segment's map.
+    pub fn read_mapped(&self, height: u64) -> Result<Option<MappedBlock>, String> {
+        self.state()?.read_mapped(height)
+    }
+
+    /// The first height of the oldest segment; no block is stored below it.
+    pub fn start_height(&self) -> Result<u64, String> {
+        let state = self.state()?;
+        Ok(segment_numbers(&state.dir)?.first().map_or(0, |number| number * SEGMENT_BLOCKS))
+    }
+
+    /// The highest height appended, if any.
+    pub fn last_height(&self) -> Result<Option<u64>, String> {
+        Ok(self.state()?.last)
+    }
+
+    /// Delete the segments holding only heights below `height`, except the
+    /// one appended to last, returning the bytes reclaimed.
+    pub fn prune_below(&self, height: u64) -> Result<u64, String> {
+        self.state()?.prune_below(height)
+    }
+
+    pub fn is_encrypted(&self) -> Result<bool, String> {
+        Ok(self.state()?.keys.is_some())
+    }
+
+    /// Make `key` the data key, then rewrite every segment with its blocks
+    /// sealed under it, one segment at a time, returning how many blocks were
+    /// resealed. Until this returns, the old key must be kept among the
+    /// retired ones the archive is opened with.
+    pub fn rotate_key(&self, key: &[u8]) -> Result<u64, String> {
+        let numbers = {
+            let mut state = self.state()?;
+            let rotated = state.keys.as_ref().ok_or("block archive is not encrypted")?.rotate(key)?;
+            state.keys = Some(rotated);
+            segment_numbers(&state.dir)?
+        };
+        let mut resealed = 0;
+        for number in numbers {
+            resealed += self.state()?.rekey_segment(number)?;
+        }
+        Ok(resealed)
+    }
+
+    /// Sync appended blocks and their index slots to disk.
+    pub fn flush(&self) -> Result<(), String> {
+        match &self.state()?.active {
+            Some((_, segment)) => segment.sync(),
+            None => Ok(()),
+        }
+    }
+}
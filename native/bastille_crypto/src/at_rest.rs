@@ -0,0 +1,140 @@
+// Encryption at rest of storage values and archived blocks, for nodes on disks
+// the operator doesn't trust. Each value is sealed on its own with
+// XChaCha20-Poly1305 under a node data key:
+//
+//   version u8 (1) | key id (8) | nonce 24 | ciphertext and tag
+//
+// The key id is derived from the key, so a sealed value names the key that
+// sealed it: a keyring of the current key and any retired ones reads values
+// sealed before a rotation while sealing new ones under the current key. The
+// place a value is stored at (store column and key, archive height) is bound
+// as associated data, so sealed values can't be swapped around on disk.
+//
+// Whether a directory holds encrypted data is recorded once, in a marker file
+// written before anything else, so it can't be opened the other way later.
+
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use crate::smt::io_error;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use zeroize::Zeroizing;
+
+const VERSION: u8 = 1;
+const KEY_ID_LEN: usize = 8;
+const NONCE_LEN: usize = 24;
+const HEADER_LEN: usize = 1 + KEY_ID_LEN + NONCE_LEN;
+const MARKER_FILE: &str = "encrypted";
+const MARKER: [u8; 8] = *b"BATREST1";
+
+type KeyId = [u8; KEY_ID_LEN];
+
+fn key_id(key: &[u8]) -> KeyId {
+    blake3::derive_key("bastille at-rest data key id", key)[..KEY_ID_LEN].try_into().unwrap()
+}
+
+fn cipher(key: &[u8]) -> Result<(KeyId, XChaCha20Poly1305), String> {
+    let cipher = XChaCha20Poly1305::new_from_slice(key).map_err(|_| "data keys must be 32 bytes".to_string())?;
+    Ok((key_id(key), cipher))
+}
+
+/// The current data key, sealing new values, and retired ones still opening
+/// values sealed before a rotation.
+pub struct Keyring {
+    current: (KeyId, XChaCha20Poly1305),
+    retired: Vec<(KeyId, XChaCha20Poly1305)>,
+}
+
+impl Keyring {
+    pub fn new(current: &[u8], retired: &[Zeroizing<Vec<u8>>]) -> Result<Self, String> {
+        let mut keyring = Keyring { current: cipher(current)?, retired: Vec::new() };
+        for key in retired {
+            let key = cipher(key)?;
+            if key.0 != keyring.current.0 {
+                keyring.retired.push(key);
+            }
+        }
+        Ok(keyring)
+    }
+
+    /// This keyring with `key` as the current key, the current one retired.
+    pub fn rotate(&self, key: &[u8]) -> Result<Self, String> {
+        let current = cipher(key)?;
+        let retired = [&self.current]
+            .into_iter()
+            .chain(&self.retired)
+            .filter(|(id, _)| *id != current.0)
+            .cloned()
+            .collect();
+        Ok(Keyring { current, retired })
+    }
+
+    pub fn seal(&self, aad: &[u8], value: &[u8]) -> Result<Vec<u8>, String> {
+        let (id, cipher) = &self.current;
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = cipher
+            .encrypt(&nonce, Payload { msg: value, aad })
+            .map_err(|_| "encryption failed".to_string())?;
+        let mut sealed = Vec::with_capacity(HEADER_LEN + ciphertext.len());
+        sealed.push(VERSION);
+        sealed.extend_from_slice(id);
+        sealed.extend_from_slice(&nonce);
+        sealed.extend_from_slice(&ciphertext);
+        Ok(sealed)
+    }
+
+    pub fn open(&self, aad: &[u8], sealed: &[u8]) -> Result<Zeroizing<Vec<u8>>, String> {
+        if sealed.len() < HEADER_LEN || sealed[0] != VERSION {
+            return Err("stored value is not sealed".to_string());
+        }
+        let id = &sealed[1..1 + KEY_ID_LEN];
+        let (_, cipher) = [&self.current]
+            .into_iter()
+            .chain(&self.retired)
+            .find(|(key_id, _)| key_id == id)
+            .ok_or("stored value is sealed under a data key not given")?;
+        let nonce = XNonce::from_slice(&sealed[1 + KEY_ID_LEN..HEADER_LEN]);
+        cipher
+            .decrypt(nonce, Payload { msg: &sealed[HEADER_LEN..], aad })
+            .map(Zeroizing::new)
+            .map_err(|_| "stored value fails authentication".to_string())
+    }
+
+    /// Whether `sealed` is sealed under the current key, so a rotation can
+    /// leave it alone.
+    pub fn is_current(&self, sealed: &[u8]) -> bool {
+        sealed.get(1..1 + KEY_ID_LEN) == Some(&self.current.0[..])
+    }
+
+    /// Open `sealed` and seal it again under the current key.
+    pub fn reseal(&self, aad: &[u8], sealed: &[u8]) -> Result<Vec<u8>, String> {
+        self.seal(aad, &self.open(aad, sealed)?)
+    }
+}
+
+/// Check that `dir` is opened the way its data was written: encrypted, with a
+/// keyring, or not. `fresh` says whether `dir` holds no data yet, in which
+/// case a keyring marks it encrypted for good.
+pub fn check_marker(dir: &Path, keys: Option<&Keyring>, fresh: bool) -> Result<(), String> {
+    let path = dir.join(MARKER_FILE);
+    match (std::fs::read(&path), keys) {
+        (Ok(marker), Some(_)) if marker == MARKER => Ok(()),
+        (Ok(marker), None) if marker == MARKER => {
+            Err(format!("'{}' is encrypted: open it with its data key", dir.display()))
+        }
+        (Ok(_), _) => Err(format!("'{}' is not an encryption marker", path.display())),
+        (Err(e), _) if e.kind() != std::io::ErrorKind::NotFound => Err(io_error("read", &path, e)),
+        (Err(_), None) => Ok(()),
+        (Err(_), Some(_)) if !fresh => Err(format!("'{}' holds unencrypted data", dir.display())),
+        (Err(_), Some(_)) => {
+            let mut file = File::create(&path).map_err(|e| io_error("create", &path, e))?;
+            file.write_all(&MARKER)
+                .and_then(|_| file.sync_all())
+                .map_err(|e| io_error("write", &path, e))?;
+            File::open(dir)
+                .and_then(|dir| dir.sync_all())
+                .map_err(|e| io_error("sync", dir, e))
+        }
+    }
+}
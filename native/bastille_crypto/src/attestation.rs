@@ -0,0 +1,119 @@
+// Aggregate attestations: the signatures of a validator set over one block
+// digest, packed once with a bitfield of who signed. Post-quantum signatures
+// don't combine the way BLS ones do, so what an aggregate saves over loose
+// attestations is the repeated digest, validator ids and framing, and it is
+// checked as one parallel batch of verifications of the same message.
+//
+//   version u8 | algorithm u8 | digest 32 | validator count u16 | bitfield
+//     | signatures
+//
+// The bitfield has a bit per validator of the set, least significant bit
+// first, with the unused high bits of the last byte zero. The signatures of
+// the set bits follow in validator order, each prefixed with its length as
+// an unsigned LEB128 varint. Decoding is strict, so an aggregate has exactly
+// one encoding.
+
+use crate::keygen::Algorithm;
+use crate::varint;
+
+pub const VERSION: u8 = 1;
+pub const DIGEST_LEN: usize = 32;
+pub const MAX_VALIDATORS: usize = u16::MAX as usize;
+pub const MAX_SIGNATURE_LEN: usize = crate::tx::MAX_SIGNATURE_LEN;
+
+pub struct Aggregate<'a> {
+    pub algorithm: Algorithm,
+    pub digest: [u8; DIGEST_LEN],
+    pub validator_count: usize,
+    // (validator index, signature), by index
+    pub signatures: Vec<(usize, &'a [u8])>,
+}
+
+fn bitfield_len(validator_count: usize) -> usize {
+    validator_count.div_ceil(8)
+}
+
+/// The aggregate of `signatures`, (validator index, signature) pairs in any
+/// order, which are not verified here.
+pub fn build(
+    algorithm: Algorithm,
+    digest: &[u8; DIGEST_LEN],
+    validator_count: usize,
+    mut signatures: Vec<(usize, &[u8])>,
+) -> Result<Vec<u8>, String> {
+    if validator_count == 0 || validator_count > MAX_VALIDATORS {
+        return Err(format!("validator count {} outside 1..{}", validator_count, MAX_VALIDATORS));
+    }
+    if signatures.is_empty() {
+        return Err("no signatures".to_string());
+    }
+    signatures.sort_by_key(|(index, _)| *index);
+
+    let mut bitfield = vec![0u8; bitfield_len(validator_count)];
+    let mut body = Vec::new();
+    for (i, (index, signature)) in signatures.iter().enumerate() {
+        if *index >= validator_count {
+            return Err(format!("validator {} outside a set of {}", index, validator_count));
+        }
+        if i > 0 && signatures[i - 1].0 == *index {
+            return Err(format!("two signatures from validator {}", index));
+        }
+        if signature.is_empty() || signature.len() > MAX_SIGNATURE_LEN {
+            return Err(format!("signature of validator {} has length {}", index, signature.len()));
+        }
+        bitfield[index / 8] |= 1 << (index % 8);
+        varint::put_uleb128(&mut body, signature.len() as u64);
+        body.extend_from_slice(signature);
+    }
+
+    let mut out = Vec::with_capacity(4 + DIGEST_LEN + bitfield.len() + body.len());
+    out.push(VERSION);
+    out.push(algorithm.id());
+    out.extend_from_slice(digest);
+    out.extend_from_slice(&(validator_count as u16).to_be_bytes());
+    out.extend_from_slice(&bitfield);
+    out.extend_from_slice(&body);
+    Ok(out)
+}
+
+fn take<'a>(data: &'a [u8], pos: &mut usize, len: usize) -> Result<&'a [u8], String> {
+    let bytes = data.get(*pos..*pos + len).ok_or("truncated aggregate")?;
+    *pos += len;
+    Ok(bytes)
+}
+
+pub fn decode(data: &[u8]) -> Result<Aggregate<'_>, String> {
+    let mut pos = 0;
+    let header = take(data, &mut pos, 2)?;
+    if header[0] != VERSION {
+        return Err(format!("unsupported aggregate format version {}", header[0]));
+    }
+    let algorithm = Algorithm::from_id(header[1]).ok_or(format!("unknown signature algorithm {}", header[1]))?;
+    let digest: [u8; DIGEST_LEN] = take(data, &mut pos, DIGEST_LEN)?.try_into().unwrap_or([0; DIGEST_LEN]);
+    let count = take(data, &mut pos, 2)?;
+    let validator_count = u16::from_be_bytes([count[0], count[1]]) as usize;
+    if validator_count == 0 {
+        return Err("empty validator set".to_string());
+    }
+    let bitfield = take(data, &mut pos, bitfield_len(validator_count))?;
+    if !validator_count.is_multiple_of(8) && bitfield[bitfield.len() - 1] >> (validator_count % 8) != 0 {
+        return Err("bits set past the validator set".to_string());
+    }
+
+    let mut signatures = Vec::new();
+    for index in (0..validator_count).filter(|index| bitfield[index / 8] & (1 << (index % 8)) != 0) {
+        let (len, size) = varint::read_uleb128(&data[pos..])?;
+        pos += size;
+        if len == 0 || len > MAX_SIGNATURE_LEN as u64 {
+            return Err(format!("signature of validator {} has length {}", index, len));
+        }
+        signatures.push((index, take(data, &mut pos, len as usize)?));
+    }
+    if signatures.is_empty() {
+        return Err("no signatures".to_string());
+    }
+    if pos != data.len() {
+        return Err("trailing bytes after aggregate".to_string());
+    }
+    Ok(Aggregate { algorithm, digest, validator_count, signatures })
+}
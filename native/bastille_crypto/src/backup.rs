@@ -0,0 +1,240 @@
+// Backups of a node's block archive and state tree to a single file, taken
+// while the node runs and checked end to end before anything is restored.
+//
+//   header  magic "BBACKUP1" | compression (u8: 0 none, 1 zstd) | height (u64 BE)
+//   body    sections, compressed as one zstd stream if so:
+//           tag (u8) | payload length (u64 BE) | payload | crc32c of payload (u32 BE)
+//
+//   block   tag 1: height (u64 BE) | block, in ascending height order
+//   state   tag 2: backend (u8: 0 sparse, 1 jellyfish) | the tree's log
+//   end     tag 0: block count (u64 BE) | blake3 of every section before
+//
+// Archived blocks never change, so reading them one by one while appends go
+// on still yields a consistent prefix up to the height. The state is the
+// jellyfish version at or below the height, exported in one go; a sparse
+// tree, which keeps no versions, is saved as it stands. The file is written
+// under a temporary name and renamed into place once complete.
+
+use crate::archive::Archive;
+use crate::smt::io_error;
+use crate::state_tree::{self, Backend, StateTree};
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+const MAGIC: [u8; 8] = *b"BBACKUP1";
+const COMPRESSION_NONE: u8 = 0;
+const COMPRESSION_ZSTD: u8 = 1;
+const TAG_END: u8 = 0;
+const TAG_BLOCK: u8 = 1;
+const TAG_STATE: u8 = 2;
+const ZSTD_LEVEL: i32 = 3;
+
+pub struct Summary {
+    pub height: u64,
+    pub blocks: u64,
+    pub state: bool,
+}
+
+enum Section {
+    Block(u64, Vec<u8>),
+    State(Backend, Vec<u8>),
+}
+
+struct SectionWriter<W: Write> {
+    out: W,
+    digest: blake3::Hasher,
+}
+
+impl<W: Write> SectionWriter<W> {
+    fn write(&mut self, tag: u8, parts: &[&[u8]]) -> std::io::Result<()> {
+        let len: usize = parts.iter().map(|part| part.len()).sum();
+        let mut header = [0u8; 9];
+        header[0] = tag;
+        header[1..].copy_from_slice(&(len as u64).to_be_bytes());
+        let crc = parts.iter().fold(0, |crc, part| crc32c::crc32c_append(crc, part));
+        for bytes in [&header[..]].into_iter().chain(parts.iter().copied()).chain([&crc.to_be_bytes()[..]]) {
+            self.out.write_all(bytes)?;
+            self.digest.update(bytes);
+        }
+        Ok(())
+    }
+}
+
+fn write_body<W: Write>(
+    out: W,
+    height: u64,
+    archive: Option<&Archive>,
+    state: Option<(Backend, &[u8])>,
+) -> Result<W, String> {
+    let mut writer = SectionWriter { out, digest: blake3::Hasher::new() };
+    let io = |e: std::io::Error| format!("failed to write backup: {}", e);
+    let mut blocks = 0u64;
+    if let Some(archive) = archive {
+        let end = archive.last_height()?.map_or(0, |last| last.min(height).saturating_add(1));
+        for block_height in archive.start_height()?..end {
+            if let Some(block) = archive.read(block_height)? {
+                writer.write(TAG_BLOCK, &[&block_height.to_be_bytes(), &block]).map_err(io)?;
+                blocks += 1;
+            }
+        }
+    }
+    if let Some((backend, log)) = state {
+        let backend = match backend {
+            Backend::Sparse => 0u8,
+            Backend::Jellyfish => 1u8,
+        };
+        writer.write(TAG_STATE, &[&[backend], log]).map_err(io)?;
+    }
+    let digest = *writer.digest.finalize().as_bytes();
+    writer.write(TAG_END, &[&blocks.to_be_bytes(), &digest]).map_err(io)?;
+    Ok(writer.out)
+}
+
+/// Back up the blocks of `archive` up to `height` and the state of `tree` at
+/// `height` to `path`.
+pub fn create(
+    path: &Path,
+    height: u64,
+    archive: Option<&Archive>,
+    tree: Option<&StateTree>,
+    compress: bool,
+) -> Result<Summary, String> {
+    let state = tree.map(|tree| tree.export_log(height)).transpose()?;
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    let tmp_path = PathBuf::from(tmp_path);
+    let result = (|| {
+        let mut out = BufWriter::new(File::create(&tmp_path).map_err(|e| io_error("create", &tmp_path, e))?);
+        let compression = if compress { COMPRESSION_ZSTD } else { COMPRESSION_NONE };
+        let mut header = MAGIC.to_vec();
+        header.push(compression);
+        header.extend_from_slice(&height.to_be_bytes());
+        out.write_all(&header).map_err(|e| io_error("write", &tmp_path, e))?;
+
+        let state = state.as_ref().map(|(backend, log)| (*backend, log.as_slice()));
+        let out = if compress {
+            let encoder = zstd::Encoder::new(out, ZSTD_LEVEL).map_err(|e| io_error("compress", &tmp_path, e))?;
+            let encoder = write_body(encoder, height, archive, state)?;
+            encoder.finish().map_err(|e| io_error("compress", &tmp_path, e))?
+        } else {
+            write_body(out, height, archive, state)?
+        };
+        let file = out.into_inner().map_err(|e| io_error("write", &tmp_path, e.into_error()))?;
+        file.sync_all().map_err(|e| io_error("sync", &tmp_path, e))?;
+        fs::rename(&tmp_path, path).map_err(|e| io_error("rename", &tmp_path, e))
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+    result?;
+    verify(path)
+}
+
+fn read_exact(body: &mut impl Read, buf: &mut [u8]) -> Result<(), String> {
+    body.read_exact(buf).map_err(|_| "backup is truncated".to_string())
+}
+
+// Check every section of the backup at `path`, passing each to `f` as it is
+// read; only a backup that checks out to the end may be acted on, so callers
+// restoring from it read it twice
+fn read_sections(path: &Path, mut f: impl FnMut(Section) -> Result<(), String>) -> Result<Summary, String> {
+    let mut file = BufReader::new(File::open(path).map_err(|e| io_error("open", path, e))?);
+    let mut header = [0u8; 17];
+    read_exact(&mut file, &mut header)?;
+    if header[..8] != MAGIC {
+        return Err(format!("'{}' is not a backup", path.display()));
+    }
+    let height = u64::from_be_bytes(header[9..].try_into().unwrap());
+    let mut body: Box<dyn Read> = match header[8] {
+        COMPRESSION_NONE => Box::new(file),
+        COMPRESSION_ZSTD => Box::new(zstd::Decoder::with_buffer(file).map_err(|e| io_error("decompress", path, e))?),
+        _ => return Err(format!("'{}' uses an unknown compression", path.display())),
+    };
+
+    let mut digest = blake3::Hasher::new();
+    let mut summary = Summary { height, blocks: 0, state: false };
+    let mut last_height = None;
+    loop {
+        let mut section_header = [0u8; 9];
+        read_exact(&mut body, &mut section_header)?;
+        let len = u64::from_be_bytes(section_header[1..].try_into().unwrap());
+        let mut payload = Vec::new();
+        (&mut body)
+            .take(len)
+            .read_to_end(&mut payload)
+            .map_err(|e| format!("failed to read backup: {}", e))?;
+        if payload.len() as u64 != len {
+            return Err("backup is truncated".to_string());
+        }
+        let mut crc = [0u8; 4];
+        read_exact(&mut body, &mut crc)?;
+        if crc32c::crc32c(&payload) != u32::from_be_bytes(crc) {
+            return Err("backup section fails its checksum".to_string());
+        }
+
+        match (section_header[0], payload.len()) {
+            (TAG_END, 40) => {
+                let blocks = u64::from_be_bytes(payload[..8].try_into().unwrap());
+                if blocks != summary.blocks || payload[8..] != *digest.finalize().as_bytes() {
+                    return Err("backup contents don't match its digest".to_string());
+                }
+                let mut rest = [0u8; 1];
+                if body.read(&mut rest).map_err(|e| format!("failed to read backup: {}", e))? != 0 {
+                    return Err("backup has data past its end".to_string());
+                }
+                return Ok(summary);
+            }
+            (TAG_BLOCK, 8..) => {
+                let block_height = u64::from_be_bytes(payload[..8].try_into().unwrap());
+                if block_height > height || last_height.is_some_and(|last| block_height <= last) {
+                    return Err(format!("backup block at height {} is out of order", block_height));
+                }
+                last_height = Some(block_height);
+                summary.blocks += 1;
+                digest.update(&section_header);
+                digest.update(&payload);
+                digest.update(&crc);
+                f(Section::Block(block_height, payload.split_off(8)))?;
+            }
+            (TAG_STATE, 1..) if !summary.state => {
+                let backend = match payload[0] {
+                    0 => Backend::Sparse,
+                    1 => Backend::Jellyfish,
+                    _ => return Err("backup state uses an unknown backend".to_string()),
+                };
+                summary.state = true;
+                digest.update(&section_header);
+                digest.update(&payload);
+                digest.update(&crc);
+                f(Section::State(backend, payload.split_off(1)))?;
+            }
+            _ => return Err("backup has a malformed section".to_string()),
+        }
+    }
+}
+
+/// Check the backup at `path` end to end.
+pub fn verify(path: &Path) -> Result<Summary, String> {
+    read_sections(path, |_| Ok(()))
+}
+
+/// Check the backup at `path`, then restore its blocks into a new archive in
+/// `blocks_dir` and its state into a new tree in `state_dir`, each if given.
+pub fn restore(path: &Path, blocks_dir: Option<&Path>, state_dir: Option<&Path>) -> Result<Summary, String> {
+    verify(path)?;
+    let archive = blocks_dir.map(|dir| Archive::open(dir, None)).transpose()?;
+    if let Some(archive) = &archive {
+        if archive.last_height()?.is_some() {
+            return Err("the blocks directory already holds an archive".to_string());
+        }
+    }
+    let summary = read_sections(path, |section| match section {
+        Section::Block(height, block) => archive.as_ref().map_or(Ok(()), |archive| archive.append(height, &block)),
+        Section::State(backend, log) => state_dir.map_or(Ok(()), |dir| state_tree::import_log(dir, backend, &log)),
+    })?;
+    if let Some(archive) = &archive {
+        archive.flush()?;
+    }
+    Ok(summary)
+}
@@ -0,0 +1,157 @@
+// Hex encoding and strict decoding, for the hashes and addresses converted
+// millions of times during sync. With the `simd` feature on x86_64, 16 bytes
+// (32 digits) at a time go through SSE2, which every x86_64 CPU has, so no
+// runtime detection is needed; the rest, and other targets, use a table.
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Case {
+    Lower,
+    Upper,
+    // Decoding only: either case, even within one string
+    Mixed,
+}
+
+/// Hex of `data`, appended to `out`.
+pub fn encode(data: &[u8], case: Case, out: &mut Vec<u8>) {
+    let start = out.len();
+    out.resize(start + data.len() * 2, 0);
+    let out = &mut out[start..];
+    let done = sse2::encode_blocks(data, out, case);
+    let digits: &[u8; 16] = if case == Case::Upper { b"0123456789ABCDEF" } else { b"0123456789abcdef" };
+    for (byte, pair) in data[done..].iter().zip(out[done * 2..].chunks_exact_mut(2)) {
+        pair[0] = digits[(byte >> 4) as usize];
+        pair[1] = digits[(byte & 0x0f) as usize];
+    }
+}
+
+fn digit(c: u8, case: Case) -> Option<u8> {
+    match c {
+        b'0'..=b'9' => Some(c - b'0'),
+        b'a'..=b'f' if case != Case::Upper => Some(c - b'a' + 10),
+        b'A'..=b'F' if case != Case::Lower => Some(c - b'A' + 10),
+        _ => None,
+    }
+}
+
+fn invalid(hex: &[u8], case: Case) -> String {
+    let pos = hex.iter().position(|c| digit(*c, case).is_none()).unwrap_or(0);
+    format!("invalid hex digit at position {}", pos)
+}
+
+/// Bytes of `hex`, an even number of digits in `case`.
+pub fn decode(hex: &[u8], case: Case) -> Result<Vec<u8>, String> {
+    if !hex.len().is_multiple_of(2) {
+        return Err("odd number of hex digits".to_string());
+    }
+    let mut out = vec![0u8; hex.len() / 2];
+    let done = sse2::decode_blocks(hex, &mut out, case).ok_or_else(|| invalid(hex, case))?;
+    for (pair, byte) in hex[done * 2..].chunks_exact(2).zip(&mut out[done..]) {
+        match (digit(pair[0], case), digit(pair[1], case)) {
+            (Some(high), Some(low)) => *byte = high << 4 | low,
+            _ => return Err(invalid(hex, case)),
+        }
+    }
+    Ok(out)
+}
+
+// Without SSE2, no block is done ahead of the table
+#[cfg(not(all(target_arch = "x86_64", feature = "simd")))]
+mod sse2 {
+    use super::Case;
+
+    pub fn encode_blocks(_data: &[u8], _out: &mut [u8], _case: Case) -> usize {
+        0
+    }
+
+    pub fn decode_blocks(_hex: &[u8], _out: &mut [u8], _case: Case) -> Option<usize> {
+        Some(0)
+    }
+}
+
+#[cfg(all(target_arch = "x86_64", feature = "simd"))]
+mod sse2 {
+    use super::Case;
+    use std::arch::x86_64::*;
+
+    /// Encode the whole 16-byte blocks of `data` into `out`, returning how
+    /// many bytes that was.
+    pub fn encode_blocks(data: &[u8], out: &mut [u8], case: Case) -> usize {
+        let done = data.len() / 16 * 16;
+        for (chunk, digits) in data[..done].chunks_exact(16).zip(out.chunks_exact_mut(32)) {
+            // SAFETY: SSE2 is part of x86_64, and both chunks have the exact sizes read and written
+            unsafe { encode(chunk, digits, case) };
+        }
+        done
+    }
+
+    /// Decode the whole 32-digit blocks of `hex` into `out`, returning how
+    /// many bytes that was, or None if a block holds a non-digit.
+    pub fn decode_blocks(hex: &[u8], out: &mut [u8], case: Case) -> Option<usize> {
+        let done = out.len() / 16 * 16;
+        for (digits, chunk) in hex[..done * 2].chunks_exact(32).zip(out.chunks_exact_mut(16)) {
+            // SAFETY: as in encode_blocks
+            if !unsafe { decode(digits, chunk, case) } {
+                return None;
+            }
+        }
+        Some(done)
+    }
+
+    // Bytes equal to `c` in lo..=hi, as 0xff lanes
+    unsafe fn in_range(c: __m128i, lo: u8, hi: u8) -> __m128i {
+        _mm_and_si128(
+            _mm_cmpgt_epi8(c, _mm_set1_epi8(lo as i8 - 1)),
+            _mm_cmplt_epi8(c, _mm_set1_epi8(hi as i8 + 1)),
+        )
+    }
+
+    // ASCII digits of 16 nibbles
+    unsafe fn digits(nibbles: __m128i, letter_offset: i8) -> __m128i {
+        let letters = _mm_cmpgt_epi8(nibbles, _mm_set1_epi8(9));
+        let ascii = _mm_add_epi8(nibbles, _mm_set1_epi8(b'0' as i8));
+        _mm_add_epi8(ascii, _mm_and_si128(letters, _mm_set1_epi8(letter_offset)))
+    }
+
+    /// 16 bytes of `data` into 32 digits in `out`.
+    unsafe fn encode(data: &[u8], out: &mut [u8], case: Case) {
+        let letter_offset = if case == Case::Upper { b'A' - b'0' - 10 } else { b'a' - b'0' - 10 } as i8;
+        let bytes = _mm_loadu_si128(data.as_ptr() as *const __m128i);
+        let mask = _mm_set1_epi8(0x0f);
+        let high = _mm_and_si128(_mm_srli_epi16(bytes, 4), mask);
+        let low = _mm_and_si128(bytes, mask);
+        let first = digits(_mm_unpacklo_epi8(high, low), letter_offset);
+        let second = digits(_mm_unpackhi_epi8(high, low), letter_offset);
+        _mm_storeu_si128(out.as_mut_ptr() as *mut __m128i, first);
+        _mm_storeu_si128(out.as_mut_ptr().add(16) as *mut __m128i, second);
+    }
+
+    // Values of 16 digits, and whether all are valid
+    unsafe fn values(c: __m128i, case: Case) -> (__m128i, bool) {
+        let decimal = in_range(c, b'0', b'9');
+        let lower = if case == Case::Upper { _mm_setzero_si128() } else { in_range(c, b'a', b'f') };
+        let upper = if case == Case::Lower { _mm_setzero_si128() } else { in_range(c, b'A', b'F') };
+        let valid = _mm_or_si128(decimal, _mm_or_si128(lower, upper));
+        let value = _mm_or_si128(
+            _mm_and_si128(decimal, _mm_sub_epi8(c, _mm_set1_epi8(b'0' as i8))),
+            _mm_or_si128(
+                _mm_and_si128(lower, _mm_sub_epi8(c, _mm_set1_epi8((b'a' - 10) as i8))),
+                _mm_and_si128(upper, _mm_sub_epi8(c, _mm_set1_epi8((b'A' - 10) as i8))),
+            ),
+        );
+        (value, _mm_movemask_epi8(valid) == 0xffff)
+    }
+
+    // Each pair of values, as 16-bit lanes, into one byte in the low half
+    unsafe fn pairs(values: __m128i) -> __m128i {
+        let high = _mm_and_si128(values, _mm_set1_epi16(0x00ff));
+        _mm_or_si128(_mm_slli_epi16(high, 4), _mm_srli_epi16(values, 8))
+    }
+
+    /// 32 digits of `hex` into 16 bytes in `out`; false if any isn't a digit.
+    unsafe fn decode(hex: &[u8], out: &mut [u8], case: Case) -> bool {
+        let (first, first_valid) = values(_mm_loadu_si128(hex.as_ptr() as *const __m128i), case);
+        let (second, second_valid) = values(_mm_loadu_si128(hex.as_ptr().add(16) as *const __m128i), case);
+        _mm_storeu_si128(out.as_mut_ptr() as *mut __m128i, _mm_packus_epi16(pairs(first), pairs(second)));
+        first_valid && second_valid
+    }
+}
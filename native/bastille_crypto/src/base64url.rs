@@ -0,0 +1,114 @@
+// Base64 with the URL and filename safe alphabet (RFC 4648 section 5), for RPC
+// tokens and snapshot chunks. Decoding goes through a 256-entry table; in
+// strict mode it also requires the unused low bits of the last character to
+// be zero, so each byte string has exactly one encoding.
+
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+const INVALID: u8 = 0xff;
+
+const DECODE: [u8; 256] = {
+    let mut table = [INVALID; 256];
+    let mut i = 0;
+    while i < 64 {
+        table[ALPHABET[i] as usize] = i as u8;
+        i += 1;
+    }
+    table
+};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Padding {
+    Required,
+    Refused,
+    Optional,
+}
+
+/// Base64url of `data`, `=`-padded to a multiple of 4 characters if `padded`,
+/// appended to `out`.
+pub fn encode(data: &[u8], padded: bool, out: &mut Vec<u8>) {
+    out.reserve(data.len().div_ceil(3) * 4);
+    let mut chunks = data.chunks_exact(3);
+    for chunk in &mut chunks {
+        let n = (chunk[0] as u32) << 16 | (chunk[1] as u32) << 8 | chunk[2] as u32;
+        out.extend_from_slice(&[
+            ALPHABET[(n >> 18) as usize],
+            ALPHABET[(n >> 12 & 0x3f) as usize],
+            ALPHABET[(n >> 6 & 0x3f) as usize],
+            ALPHABET[(n & 0x3f) as usize],
+        ]);
+    }
+    match *chunks.remainder() {
+        [a] => {
+            out.extend_from_slice(&[ALPHABET[(a >> 2) as usize], ALPHABET[((a & 0x03) << 4) as usize]]);
+            if padded {
+                out.extend_from_slice(b"==");
+            }
+        }
+        [a, b] => {
+            let n = (a as u32) << 8 | b as u32;
+            out.extend_from_slice(&[
+                ALPHABET[(n >> 10) as usize],
+                ALPHABET[(n >> 4 & 0x3f) as usize],
+                ALPHABET[((n & 0x0f) << 2) as usize],
+            ]);
+            if padded {
+                out.push(b'=');
+            }
+        }
+        _ => {}
+    }
+}
+
+fn invalid(pos: usize) -> String {
+    format!("invalid base64url character at position {}", pos)
+}
+
+/// Bytes of `text`.
+pub fn decode(text: &[u8], padding: Padding, strict: bool) -> Result<Vec<u8>, String> {
+    let pads = text.iter().rev().take(2).take_while(|c| **c == b'=').count();
+    let digits = &text[..text.len() - pads];
+    let tail = digits.len() % 4;
+    match (pads, padding) {
+        (0, Padding::Required) if tail != 0 => return Err("base64url padding missing".to_string()),
+        (1.., Padding::Refused) => return Err("base64url padding not allowed".to_string()),
+        (1.., _) if !text.len().is_multiple_of(4) || pads + tail != 4 => return Err("invalid base64url padding".to_string()),
+        _ => {}
+    }
+    if tail == 1 {
+        return Err("invalid base64url length".to_string());
+    }
+
+    let mut out = Vec::with_capacity(digits.len() / 4 * 3 + 2);
+    let mut chunks = digits.chunks_exact(4);
+    let mut pos = 0;
+    for chunk in &mut chunks {
+        let mut n = 0u32;
+        for (i, c) in chunk.iter().enumerate() {
+            let value = DECODE[*c as usize];
+            if value == INVALID {
+                return Err(invalid(pos + i));
+            }
+            n = n << 6 | value as u32;
+        }
+        out.extend_from_slice(&n.to_be_bytes()[1..]);
+        pos += 4;
+    }
+    let rest = chunks.remainder();
+    if !rest.is_empty() {
+        let mut n = 0u32;
+        for (i, c) in rest.iter().enumerate() {
+            let value = DECODE[*c as usize];
+            if value == INVALID {
+                return Err(invalid(pos + i));
+            }
+            n = n << 6 | value as u32;
+        }
+        // 2 characters carry 1 byte and 4 unused bits, 3 carry 2 bytes and 2
+        let (len, unused) = if rest.len() == 2 { (1, 4) } else { (2, 2) };
+        if strict && n & ((1 << unused) - 1) != 0 {
+            return Err("non-canonical base64url: unused bits set".to_string());
+        }
+        out.extend_from_slice(&(n >> unused).to_be_bytes()[4 - len..]);
+    }
+    Ok(out)
+}
@@ -0,0 +1,55 @@
+// The epoch randomness beacon, commit-reveal: each validator commits to a
+// secret 32-byte reveal with a 32-byte salt, reveals both in the next phase,
+// and the epoch's randomness mixes every valid reveal into the previous
+// epoch's. Fixed lengths keep reveal | salt unambiguous.
+//
+//   commitment = blake3_derive_key("bastille beacon v1 commitment", reveal | salt)
+//   randomness = blake3_derive_key("bastille beacon v1 mix",
+//                  previous | epoch u64 | count u32 | reveals sorted)
+//
+// The reveals are sorted so the result doesn't depend on the order a node
+// happened to receive them in, and hashed rather than XORed so two equal
+// reveals don't cancel out. A validator that withholds its reveal can still
+// choose between two outcomes; the VDF (vdf.rs) is the answer to that.
+
+use crate::threads;
+use rayon::prelude::*;
+
+pub const REVEAL_LEN: usize = 32;
+pub const SALT_LEN: usize = 32;
+
+const COMMITMENT_CONTEXT: &str = "bastille beacon v1 commitment";
+const MIX_CONTEXT: &str = "bastille beacon v1 mix";
+
+pub fn commitment(reveal: &[u8; REVEAL_LEN], salt: &[u8; SALT_LEN]) -> [u8; 32] {
+    let mut hasher = blake3::Hasher::new_derive_key(COMMITMENT_CONTEXT);
+    hasher.update(reveal);
+    hasher.update(salt);
+    *hasher.finalize().as_bytes()
+}
+
+/// Indices of the (commitment, reveal, salt) items whose reveal doesn't
+/// open the commitment, checked on the pool.
+pub fn verify_reveals(items: &[(&[u8], &[u8; REVEAL_LEN], &[u8; SALT_LEN])]) -> Vec<usize> {
+    threads::install(|| {
+        items
+            .par_iter()
+            .enumerate()
+            .filter(|(_, (committed, reveal, salt))| commitment(reveal, salt)[..] != **committed)
+            .map(|(index, _)| index)
+            .collect()
+    })
+}
+
+pub fn mix(previous: &[u8; 32], epoch: u64, reveals: &[[u8; REVEAL_LEN]]) -> [u8; 32] {
+    let mut sorted = reveals.to_vec();
+    sorted.sort_unstable();
+    let mut hasher = blake3::Hasher::new_derive_key(MIX_CONTEXT);
+    hasher.update(previous);
+    hasher.update(&epoch.to_be_bytes());
+    hasher.update(&(sorted.len() as u32).to_be_bytes());
+    for reveal in &sorted {
+        hasher.update(reveal);
+    }
+    *hasher.finalize().as_bytes()
+}
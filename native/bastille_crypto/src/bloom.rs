@@ -0,0 +1,86 @@
+// Bloom filters for per-block log topics, so RPC log filters can skip blocks
+// that can't match without reading their receipts.
+//
+// Item positions come from one BLAKE3 hash split into two 64-bit halves and
+// combined by double hashing (Kirsch-Mitzenmacher): position i is
+// (h1 + i * h2) mod bits. Bits are atomic bytes, so inserts and lookups from
+// several processes need no lock.
+//
+// Serialized form: bits (u64 BE) | hashes (u8) | the filter, bit i in byte
+// i / 8 at position i % 8 (least significant first).
+
+use std::sync::atomic::{AtomicU8, Ordering};
+
+pub const MAX_BITS: u64 = 1 << 32;
+pub const MAX_HASHES: u8 = 32;
+
+const HEADER_LEN: usize = 8 + 1;
+
+pub struct BloomFilter {
+    bits: u64,
+    hashes: u8,
+    filter: Vec<AtomicU8>,
+}
+
+#[rustler::resource_impl]
+impl rustler::Resource for BloomFilter {}
+
+fn valid(bits: u64, hashes: u8) -> bool {
+    (1..=MAX_BITS).contains(&bits) && (1..=MAX_HASHES).contains(&hashes)
+}
+
+impl BloomFilter {
+    /// None unless 1 <= bits <= MAX_BITS and 1 <= hashes <= MAX_HASHES.
+    pub fn new(bits: u64, hashes: u8) -> Option<Self> {
+        if !valid(bits, hashes) {
+            return None;
+        }
+        let filter = (0..bits.div_ceil(8)).map(|_| AtomicU8::new(0)).collect();
+        Some(BloomFilter { bits, hashes, filter })
+    }
+
+    fn positions(&self, item: &[u8]) -> impl Iterator<Item = u64> {
+        let hash = blake3::hash(item);
+        let h1 = u64::from_le_bytes(hash.as_bytes()[..8].try_into().unwrap());
+        let h2 = u64::from_le_bytes(hash.as_bytes()[8..16].try_into().unwrap());
+        let bits = self.bits;
+        (0..self.hashes as u64).map(move |i| h1.wrapping_add(i.wrapping_mul(h2)) % bits)
+    }
+
+    pub fn insert(&self, item: &[u8]) {
+        for position in self.positions(item) {
+            self.filter[(position / 8) as usize].fetch_or(1 << (position % 8), Ordering::Relaxed);
+        }
+    }
+
+    /// False means `item` was never inserted; true means it probably was.
+    pub fn contains(&self, item: &[u8]) -> bool {
+        self.positions(item)
+            .all(|position| self.filter[(position / 8) as usize].load(Ordering::Relaxed) & (1 << (position % 8)) != 0)
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut serialized = Vec::with_capacity(HEADER_LEN + self.filter.len());
+        serialized.extend_from_slice(&self.bits.to_be_bytes());
+        serialized.push(self.hashes);
+        serialized.extend(self.filter.iter().map(|byte| byte.load(Ordering::Relaxed)));
+        serialized
+    }
+
+    /// None if `serialized` isn't a filter this module wrote.
+    pub fn deserialize(serialized: &[u8]) -> Option<Self> {
+        let (bits, rest) = serialized.split_first_chunk::<8>()?;
+        let (&hashes, filter) = rest.split_first()?;
+        let bits = u64::from_be_bytes(*bits);
+        if !valid(bits, hashes) || filter.len() as u64 != bits.div_ceil(8) {
+            return None;
+        }
+        // Padding bits past the end must be clear, or equal filters could
+        // serialize differently
+        if !bits.is_multiple_of(8) && filter[filter.len() - 1] >> (bits % 8) != 0 {
+            return None;
+        }
+        let filter = filter.iter().map(|byte| AtomicU8::new(*byte)).collect();
+        Some(BloomFilter { bits, hashes, filter })
+    }
+}
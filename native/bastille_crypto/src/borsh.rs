@@ -0,0 +1,254 @@
+// Borsh (borsh.io), the binary encoding of NEAR and Solana programs, for
+// values described by a schema at run time. Integers are little-endian,
+// lengths u32, options and enum variants a one-byte tag, and map entries
+// sorted by key, so each value has a single encoding; decoding rejects any
+// other (tags out of range, unsorted or duplicate keys, invalid UTF-8,
+// trailing bytes), as borsh-rs does.
+//
+// Floats and sets are left out.
+
+use std::cmp::Ordering;
+
+pub enum Type {
+    // Sizes in bytes: 1, 2, 4, 8 or 16
+    Uint(usize),
+    Int(usize),
+    Bool,
+    Unit,
+    String,
+    Bytes,
+    FixedBytes(usize),
+    Array(Box<Type>, usize),
+    Vec(Box<Type>),
+    Option(Box<Type>),
+    Struct(Vec<(String, Type)>),
+    // At most 256 variants
+    Enum(Vec<(String, Type)>),
+    Map(Box<Type>, Box<Type>),
+}
+
+// Ordered as the Rust values they stand for, which is how map keys are sorted
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+pub enum Value {
+    Uint(u128),
+    Int(i128),
+    Bool(bool),
+    Unit,
+    // Of String, Bytes and FixedBytes
+    Bytes(Vec<u8>),
+    // Of Array, Vec and Struct (its fields, in order)
+    Items(Vec<Value>),
+    Option(Option<Box<Value>>),
+    Enum(u8, Box<Value>),
+    Map(Vec<(Value, Value)>),
+}
+
+impl Type {
+    // Fewest bytes a value of this type encodes to
+    fn min_size(&self) -> usize {
+        match self {
+            Type::Uint(size) | Type::Int(size) | Type::FixedBytes(size) => *size,
+            Type::Bool | Type::Option(_) | Type::Enum(_) => 1,
+            Type::Unit => 0,
+            Type::String | Type::Bytes | Type::Vec(_) | Type::Map(..) => 4,
+            Type::Array(item, len) => item.min_size().saturating_mul(*len),
+            Type::Struct(fields) => fields.iter().map(|(_, field)| field.min_size()).fold(0, usize::saturating_add),
+        }
+    }
+}
+
+fn mismatch() -> String {
+    "value does not match its Borsh type".to_string()
+}
+
+fn put_len(out: &mut Vec<u8>, len: usize) -> Result<(), String> {
+    let len = u32::try_from(len).map_err(|_| "Borsh length over u32".to_string())?;
+    out.extend_from_slice(&len.to_le_bytes());
+    Ok(())
+}
+
+/// Append the Borsh encoding of `value`, of type `ty`, to `out`.
+pub fn encode(ty: &Type, value: &Value, out: &mut Vec<u8>) -> Result<(), String> {
+    match (ty, value) {
+        (Type::Uint(size), Value::Uint(n)) => {
+            if *size < 16 && *n >> (size * 8) != 0 {
+                return Err(format!("{} does not fit in u{}", n, size * 8));
+            }
+            out.extend_from_slice(&n.to_le_bytes()[..*size]);
+        }
+        (Type::Int(size), Value::Int(n)) => {
+            let bits = size * 8;
+            if bits < 128 && (*n < -(1 << (bits - 1)) || *n >= 1 << (bits - 1)) {
+                return Err(format!("{} does not fit in i{}", n, bits));
+            }
+            out.extend_from_slice(&n.to_le_bytes()[..*size]);
+        }
+        (Type::Bool, Value::Bool(value)) => out.push(*value as u8),
+        (Type::Unit, Value::Unit) => {}
+        (Type::String, Value::Bytes(bytes)) => {
+            std::str::from_utf8(bytes).map_err(|_| "Borsh string is not valid UTF-8".to_string())?;
+            put_len(out, bytes.len())?;
+            out.extend_from_slice(bytes);
+        }
+        (Type::Bytes, Value::Bytes(bytes)) => {
+            put_len(out, bytes.len())?;
+            out.extend_from_slice(bytes);
+        }
+        (Type::FixedBytes(len), Value::Bytes(bytes)) if bytes.len() == *len => out.extend_from_slice(bytes),
+        (Type::Array(item, len), Value::Items(items)) if items.len() == *len => {
+            for value in items {
+                encode(item, value, out)?;
+            }
+        }
+        (Type::Vec(item), Value::Items(items)) => {
+            put_len(out, items.len())?;
+            for value in items {
+                encode(item, value, out)?;
+            }
+        }
+        (Type::Option(_), Value::Option(None)) => out.push(0),
+        (Type::Option(item), Value::Option(Some(value))) => {
+            out.push(1);
+            encode(item, value, out)?;
+        }
+        (Type::Struct(fields), Value::Items(values)) if values.len() == fields.len() => {
+            for ((_, field), value) in fields.iter().zip(values) {
+                encode(field, value, out)?;
+            }
+        }
+        (Type::Enum(variants), Value::Enum(index, value)) if (*index as usize) < variants.len() => {
+            out.push(*index);
+            encode(&variants[*index as usize].1, value, out)?;
+        }
+        (Type::Map(key_type, value_type), Value::Map(entries)) => {
+            let mut sorted: Vec<&(Value, Value)> = entries.iter().collect();
+            sorted.sort_by(|(a, _), (b, _)| a.cmp(b));
+            if sorted.windows(2).any(|pair| pair[0].0 == pair[1].0) {
+                return Err("duplicate Borsh map key".to_string());
+            }
+            put_len(out, sorted.len())?;
+            for (key, value) in sorted {
+                encode(key_type, key, out)?;
+                encode(value_type, value, out)?;
+            }
+        }
+        _ => return Err(mismatch()),
+    }
+    Ok(())
+}
+
+struct Decoder<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Decoder<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let end = self.pos.checked_add(len).filter(|end| *end <= self.data.len()).ok_or("truncated Borsh")?;
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn tag(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    // A u32 length of items of `min_item_size` bytes, checked against the
+    // input left so a bogus one allocates nothing
+    fn len(&mut self, min_item_size: usize) -> Result<usize, String> {
+        let len = u32::from_le_bytes(self.take(4)?.try_into().unwrap()) as usize;
+        if min_item_size == 0 && len > 0 {
+            return Err("Borsh collection of zero-sized items".to_string());
+        }
+        if len.saturating_mul(min_item_size) > self.data.len() - self.pos {
+            return Err("truncated Borsh".to_string());
+        }
+        Ok(len)
+    }
+
+    fn items(&mut self, item: &Type, len: usize) -> Result<Vec<Value>, String> {
+        let mut items = Vec::with_capacity(len);
+        for _ in 0..len {
+            items.push(self.value(item)?);
+        }
+        Ok(items)
+    }
+
+    fn value(&mut self, ty: &Type) -> Result<Value, String> {
+        Ok(match ty {
+            Type::Uint(size) => {
+                let mut bytes = [0u8; 16];
+                bytes[..*size].copy_from_slice(self.take(*size)?);
+                Value::Uint(u128::from_le_bytes(bytes))
+            }
+            Type::Int(size) => {
+                let taken = self.take(*size)?;
+                // Sign-extend
+                let mut bytes = if taken[size - 1] & 0x80 != 0 { [0xffu8; 16] } else { [0u8; 16] };
+                bytes[..*size].copy_from_slice(taken);
+                Value::Int(i128::from_le_bytes(bytes))
+            }
+            Type::Bool => match self.tag()? {
+                0 => Value::Bool(false),
+                1 => Value::Bool(true),
+                _ => return Err("Borsh bool other than 0 or 1".to_string()),
+            },
+            Type::Unit => Value::Unit,
+            Type::String => {
+                let len = self.len(1)?;
+                let bytes = self.take(len)?;
+                std::str::from_utf8(bytes).map_err(|_| "Borsh string is not valid UTF-8".to_string())?;
+                Value::Bytes(bytes.to_vec())
+            }
+            Type::Bytes => {
+                let len = self.len(1)?;
+                Value::Bytes(self.take(len)?.to_vec())
+            }
+            Type::FixedBytes(len) => Value::Bytes(self.take(*len)?.to_vec()),
+            Type::Array(item, len) => Value::Items(self.items(item, *len)?),
+            Type::Vec(item) => {
+                let len = self.len(item.min_size())?;
+                Value::Items(self.items(item, len)?)
+            }
+            Type::Option(item) => match self.tag()? {
+                0 => Value::Option(None),
+                1 => Value::Option(Some(Box::new(self.value(item)?))),
+                _ => return Err("Borsh option tag other than 0 or 1".to_string()),
+            },
+            Type::Struct(fields) => {
+                Value::Items(fields.iter().map(|(_, field)| self.value(field)).collect::<Result<_, _>>()?)
+            }
+            Type::Enum(variants) => {
+                let index = self.tag()?;
+                let (_, variant) = variants.get(index as usize).ok_or("Borsh enum variant out of range")?;
+                Value::Enum(index, Box::new(self.value(variant)?))
+            }
+            Type::Map(key_type, value_type) => {
+                let len = self.len(key_type.min_size().saturating_add(value_type.min_size()))?;
+                let mut entries: Vec<(Value, Value)> = Vec::with_capacity(len);
+                for _ in 0..len {
+                    let key = self.value(key_type)?;
+                    match entries.last().map(|(last, _)| last.cmp(&key)) {
+                        Some(Ordering::Equal) => return Err("duplicate Borsh map key".to_string()),
+                        Some(Ordering::Greater) => return Err("Borsh map keys out of order".to_string()),
+                        _ => {}
+                    }
+                    let value = self.value(value_type)?;
+                    entries.push((key, value));
+                }
+                Value::Map(entries)
+            }
+        })
+    }
+}
+
+/// Decode `data`, which must hold exactly one value of type `ty`.
+pub fn decode(ty: &Type, data: &[u8]) -> Result<Value, String> {
+    let mut decoder = Decoder { data, pos: 0 };
+    let value = decoder.value(ty)?;
+    if decoder.pos != data.len() {
+        return Err("trailing bytes after Borsh value".to_string());
+    }
+    Ok(value)
+}
@@ -0,0 +1,211 @@
+// CBOR (RFC 8949) restricted to its core deterministic encoding (section
+// 4.2.1), for signing payloads that every node must encode byte for byte the
+// same: integers and lengths in their shortest form, definite lengths only,
+// and map entries sorted by the bytes of their encoded keys, without
+// duplicates. Floats and simple values other than false, true and null are
+// left out, so no value has a second encoding to normalize.
+//
+// Decoding accepts exactly what encoding produces and rejects anything else:
+// a payload that decodes re-encodes to the same bytes.
+
+// Nesting of arrays, maps and tags, bounding the decoder's recursion
+pub const MAX_DEPTH: usize = 128;
+
+const UNSIGNED: u8 = 0;
+const NEGATIVE: u8 = 1;
+const BYTES: u8 = 2;
+const TEXT: u8 = 3;
+const ARRAY: u8 = 4;
+const MAP: u8 = 5;
+const TAG: u8 = 6;
+const SIMPLE: u8 = 7;
+
+const FALSE: u8 = 20;
+const TRUE: u8 = 21;
+const NULL: u8 = 22;
+
+pub enum Value {
+    // Within -2^64..2^64
+    Integer(i128),
+    Bytes(Vec<u8>),
+    Text(String),
+    Array(Vec<Value>),
+    Map(Vec<(Value, Value)>),
+    Tag(u64, Box<Value>),
+    Bool(bool),
+    Null,
+}
+
+fn head(out: &mut Vec<u8>, major: u8, arg: u64) {
+    let major = major << 5;
+    match arg {
+        0..=23 => out.push(major | arg as u8),
+        24..=0xff => out.extend_from_slice(&[major | 24, arg as u8]),
+        0x100..=0xffff => {
+            out.push(major | 25);
+            out.extend_from_slice(&(arg as u16).to_be_bytes());
+        }
+        0x1_0000..=0xffff_ffff => {
+            out.push(major | 26);
+            out.extend_from_slice(&(arg as u32).to_be_bytes());
+        }
+        _ => {
+            out.push(major | 27);
+            out.extend_from_slice(&arg.to_be_bytes());
+        }
+    }
+}
+
+/// Append the deterministic encoding of `value` to `out`.
+pub fn encode(value: &Value, out: &mut Vec<u8>) -> Result<(), String> {
+    match value {
+        Value::Integer(n) if *n >= 0 => {
+            head(out, UNSIGNED, u64::try_from(*n).map_err(|_| "integer out of CBOR range".to_string())?)
+        }
+        Value::Integer(n) => {
+            head(out, NEGATIVE, u64::try_from(-1 - *n).map_err(|_| "integer out of CBOR range".to_string())?)
+        }
+        Value::Bytes(bytes) => {
+            head(out, BYTES, bytes.len() as u64);
+            out.extend_from_slice(bytes);
+        }
+        Value::Text(text) => {
+            head(out, TEXT, text.len() as u64);
+            out.extend_from_slice(text.as_bytes());
+        }
+        Value::Array(items) => {
+            head(out, ARRAY, items.len() as u64);
+            for item in items {
+                encode(item, out)?;
+            }
+        }
+        Value::Map(entries) => {
+            let mut encoded = Vec::with_capacity(entries.len());
+            for (key, value) in entries {
+                let mut key_bytes = Vec::new();
+                encode(key, &mut key_bytes)?;
+                encoded.push((key_bytes, value));
+            }
+            encoded.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+            if encoded.windows(2).any(|pair| pair[0].0 == pair[1].0) {
+                return Err("duplicate CBOR map key".to_string());
+            }
+            head(out, MAP, entries.len() as u64);
+            for (key, value) in encoded {
+                out.extend_from_slice(&key);
+                encode(value, out)?;
+            }
+        }
+        Value::Tag(tag, value) => {
+            head(out, TAG, *tag);
+            encode(value, out)?;
+        }
+        Value::Bool(false) => head(out, SIMPLE, FALSE as u64),
+        Value::Bool(true) => head(out, SIMPLE, TRUE as u64),
+        Value::Null => head(out, SIMPLE, NULL as u64),
+    }
+    Ok(())
+}
+
+struct Decoder<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Decoder<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let end = self.pos.checked_add(len).filter(|end| *end <= self.data.len()).ok_or("truncated CBOR")?;
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    // (major type, argument) of the next item, in shortest form
+    fn head(&mut self) -> Result<(u8, u64), String> {
+        let initial = self.take(1)?[0];
+        let (major, info) = (initial >> 5, initial & 0x1f);
+        let (arg, min) = match info {
+            0..=23 => return Ok((major, info as u64)),
+            24 => (self.take(1)?[0] as u64, 24),
+            25 => (u16::from_be_bytes(self.take(2)?.try_into().unwrap()) as u64, 0x100),
+            26 => (u32::from_be_bytes(self.take(4)?.try_into().unwrap()) as u64, 0x1_0000),
+            27 => (u64::from_be_bytes(self.take(8)?.try_into().unwrap()), 0x1_0000_0000),
+            31 => return Err("indefinite-length CBOR is not allowed".to_string()),
+            _ => return Err("malformed CBOR".to_string()),
+        };
+        if arg < min {
+            return Err("CBOR argument not in its shortest form".to_string());
+        }
+        Ok((major, arg))
+    }
+
+    // A length, checked against the input left so a bogus one allocates nothing
+    fn len(&self, arg: u64, min_item_len: usize) -> Result<usize, String> {
+        let left = (self.data.len() - self.pos) as u64;
+        if arg.saturating_mul(min_item_len as u64) > left {
+            return Err("truncated CBOR".to_string());
+        }
+        Ok(arg as usize)
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Value, String> {
+        if depth > MAX_DEPTH {
+            return Err("CBOR nested too deeply".to_string());
+        }
+        // Floats and simple values share a major type; only three are allowed
+        if self.data.get(self.pos).is_some_and(|initial| initial >> 5 == SIMPLE) {
+            return match self.take(1)?[0] & 0x1f {
+                FALSE => Ok(Value::Bool(false)),
+                TRUE => Ok(Value::Bool(true)),
+                NULL => Ok(Value::Null),
+                _ => Err("unsupported CBOR simple value or float".to_string()),
+            };
+        }
+        let (major, arg) = self.head()?;
+        Ok(match major {
+            UNSIGNED => Value::Integer(arg as i128),
+            NEGATIVE => Value::Integer(-1 - arg as i128),
+            BYTES => Value::Bytes(self.take(self.len(arg, 1)?)?.to_vec()),
+            TEXT => {
+                let text = self.take(self.len(arg, 1)?)?;
+                Value::Text(String::from_utf8(text.to_vec()).map_err(|_| "CBOR text is not valid UTF-8".to_string())?)
+            }
+            ARRAY => {
+                let len = self.len(arg, 1)?;
+                let mut items = Vec::with_capacity(len);
+                for _ in 0..len {
+                    items.push(self.value(depth + 1)?);
+                }
+                Value::Array(items)
+            }
+            MAP => {
+                let len = self.len(arg, 2)?;
+                let mut entries = Vec::with_capacity(len);
+                let mut last_key: Option<&[u8]> = None;
+                for _ in 0..len {
+                    let start = self.pos;
+                    let key = self.value(depth + 1)?;
+                    let key_bytes = &self.data[start..self.pos];
+                    match last_key {
+                        Some(last) if last == key_bytes => return Err("duplicate CBOR map key".to_string()),
+                        Some(last) if last > key_bytes => return Err("CBOR map keys out of order".to_string()),
+                        _ => last_key = Some(key_bytes),
+                    }
+                    entries.push((key, self.value(depth + 1)?));
+                }
+                Value::Map(entries)
+            }
+            _ => Value::Tag(arg, Box::new(self.value(depth + 1)?)),
+        })
+    }
+}
+
+/// Decode `data`, which must hold exactly one deterministically encoded value.
+pub fn decode(data: &[u8]) -> Result<Value, String> {
+    let mut decoder = Decoder { data, pos: 0 };
+    let value = decoder.value(0)?;
+    if decoder.pos != data.len() {
+        return Err("trailing bytes after CBOR value".to_string());
+    }
+    Ok(value)
+}
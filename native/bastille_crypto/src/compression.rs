@@ -0,0 +1,94 @@
+// zstd compression for block payloads and other bulky binaries. Payloads
+// sharing a structure (blocks, transactions) compress far better against a
+// dictionary trained on samples of them; the same dictionary is then needed
+// to decompress, so callers keep it alongside the data.
+//
+// LZ4 for latency-sensitive network frames, which trades ratio for speed:
+//
+//   uncompressed length (u32 LE) | LZ4 block
+//
+// Both decompress under a size cap, so a small frame claiming or expanding
+// to gigabytes from a peer fails without allocating them.
+
+use std::io::Read;
+use zstd::dict::{DecoderDictionary, EncoderDictionary};
+use zstd::stream::read::Decoder;
+
+pub fn level_in_range(level: i32) -> bool {
+    zstd::compression_level_range().contains(&level)
+}
+
+pub fn compress(data: &[u8], level: i32) -> Result<Vec<u8>, String> {
+    zstd::bulk::compress(data, level).map_err(|e| format!("zstd compression failed: {}", e))
+}
+
+pub fn decompress(data: &[u8], max_size: usize) -> Result<Vec<u8>, String> {
+    let decoder = Decoder::with_buffer(data).map_err(|e| format!("zstd decompression failed: {}", e))?;
+    read_capped(decoder, max_size)
+}
+
+fn read_capped(reader: impl Read, max_size: usize) -> Result<Vec<u8>, String> {
+    let mut output = Vec::new();
+    reader
+        .take(max_size as u64 + 1)
+        .read_to_end(&mut output)
+        .map_err(|e| format!("invalid zstd data: {}", e))?;
+    if output.len() > max_size {
+        return Err(format!("decompressed data exceeds {} bytes", max_size));
+    }
+    Ok(output)
+}
+
+/// A dictionary of at most `max_size` bytes trained on `samples`.
+pub fn train_dictionary(samples: &[&[u8]], max_size: usize) -> Result<Vec<u8>, String> {
+    zstd::dict::from_samples(samples, max_size).map_err(|e| format!("dictionary training failed: {}", e))
+}
+
+/// A dictionary prepared once for compressing at one level and for
+/// decompressing, rather than on every call.
+pub struct Dictionary {
+    encoder: EncoderDictionary<'static>,
+    decoder: DecoderDictionary<'static>,
+}
+
+#[rustler::resource_impl]
+impl rustler::Resource for Dictionary {}
+
+impl Dictionary {
+    pub fn new(dictionary: &[u8], level: i32) -> Self {
+        Dictionary { encoder: EncoderDictionary::copy(dictionary, level), decoder: DecoderDictionary::copy(dictionary) }
+    }
+
+    pub fn compress(&self, data: &[u8]) -> Result<Vec<u8>, String> {
+        zstd::bulk::Compressor::with_prepared_dictionary(&self.encoder)
+            .and_then(|mut compressor| compressor.compress(data))
+            .map_err(|e| format!("zstd compression failed: {}", e))
+    }
+
+    pub fn decompress(&self, data: &[u8], max_size: usize) -> Result<Vec<u8>, String> {
+        let decoder = Decoder::with_prepared_dictionary(data, &self.decoder)
+            .map_err(|e| format!("zstd decompression failed: {}", e))?;
+        read_capped(decoder, max_size)
+    }
+}
+
+pub fn lz4_compress(data: &[u8]) -> Result<Vec<u8>, String> {
+    if u32::try_from(data.len()).is_err() {
+        return Err("data too large for an lz4 frame".to_string());
+    }
+    Ok(lz4_flex::compress_prepend_size(data))
+}
+
+pub fn lz4_decompress(frame: &[u8], max_size: usize) -> Result<Vec<u8>, String> {
+    let (len, block) = frame.split_first_chunk::<4>().ok_or("truncated lz4 frame")?;
+    let len = u32::from_le_bytes(*len) as usize;
+    if len > max_size {
+        return Err(format!("decompressed data exceeds {} bytes", max_size));
+    }
+    let mut output = vec![0u8; len];
+    match lz4_flex::block::decompress_into(block, &mut output) {
+        Ok(written) if written == len => Ok(output),
+        Ok(_) => Err("lz4 frame shorter than its stated length".to_string()),
+        Err(e) => Err(format!("invalid lz4 frame: {}", e)),
+    }
+}
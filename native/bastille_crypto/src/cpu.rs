@@ -0,0 +1,78 @@
+// CPU feature detection and the implementation each primitive runs on.
+//
+// pqcrypto compiles its AVX2 (x86_64) and NEON (aarch64) variants next to the
+// portable C code when the `simd` feature is on, and picks one per call from
+// runtime detection; blake3 does the same for SSE4.1/AVX2/AVX-512. Building
+// with `--no-default-features --features portable` leaves only the portable
+// code paths, for reproducibility testing across machines.
+
+pub struct CpuFeatures {
+    pub avx2: bool,
+    pub avx512: bool,
+    pub neon: bool,
+}
+
+#[cfg(target_arch = "x86_64")]
+pub fn detect() -> CpuFeatures {
+    CpuFeatures {
+        avx2: std::is_x86_feature_detected!("avx2"),
+        avx512: std::is_x86_feature_detected!("avx512f") && std::is_x86_feature_detected!("avx512vl"),
+        neon: false,
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+pub fn detect() -> CpuFeatures {
+    CpuFeatures { avx2: false, avx512: false, neon: std::arch::is_aarch64_feature_detected!("neon") }
+}
+
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+pub fn detect() -> CpuFeatures {
+    CpuFeatures { avx2: false, avx512: false, neon: false }
+}
+
+/// Implementation pqcrypto dispatches to; SPHINCS+ has no NEON variant.
+pub fn pqcrypto_backend(features: &CpuFeatures, has_neon_variant: bool) -> &'static str {
+    if !cfg!(feature = "simd") {
+        "clean"
+    } else if cfg!(target_arch = "x86_64") && features.avx2 {
+        "avx2"
+    } else if cfg!(target_arch = "aarch64") && features.neon && has_neon_variant {
+        "neon"
+    } else {
+        "clean"
+    }
+}
+
+/// Mirrors blake3's own dispatch order.
+pub fn blake3_backend(features: &CpuFeatures) -> &'static str {
+    if cfg!(feature = "portable") {
+        return "portable";
+    }
+    if cfg!(target_arch = "x86_64") {
+        if features.avx512 {
+            return "avx512";
+        }
+        if features.avx2 {
+            return "avx2";
+        }
+        #[cfg(target_arch = "x86_64")]
+        if std::is_x86_feature_detected!("sse4.1") {
+            return "sse41";
+        }
+        return "sse2";
+    }
+    if cfg!(target_arch = "aarch64") {
+        return "neon";
+    }
+    "portable"
+}
+
+/// Hex encoding and decoding (see base16.rs): SSE2 needs no detection.
+pub fn hex_backend() -> &'static str {
+    if cfg!(all(target_arch = "x86_64", feature = "simd")) {
+        "sse2"
+    } else {
+        "portable"
+    }
+}
@@ -0,0 +1,179 @@
+// Cuckoo filter for deduplicating gossip message IDs in bounded memory.
+//
+// Items are reduced to a 16-bit fingerprint stored in one of two buckets of
+// four slots (partial-key cuckoo hashing: the second bucket is the first
+// XOR a hash of the fingerprint, so either is recoverable from the other and
+// a fingerprint can be moved without the item). Unlike a Bloom filter,
+// entries can be deleted.
+//
+// Aging: the filter keeps two generations of `capacity` entries each.
+// Inserts go to the current one; when it is full (or an insert can't find
+// room) the previous generation is dropped and a fresh one started, so the
+// latest `capacity` to `2 * capacity` insertions are always remembered.
+// Re-inserting an item from the previous generation renews it.
+// False positives run at about 2 * 8 / 65536 per lookup with both
+// generations full.
+
+use std::sync::Mutex;
+
+pub const MAX_CAPACITY: usize = 1 << 24;
+
+const SLOTS: usize = 4;
+const MAX_KICKS: usize = 500;
+
+struct Generation {
+    buckets: Vec<[u16; SLOTS]>,
+    len: usize,
+}
+
+impl Generation {
+    fn new(buckets: usize) -> Self {
+        Generation { buckets: vec![[0; SLOTS]; buckets], len: 0 }
+    }
+
+    fn mask(&self) -> usize {
+        self.buckets.len() - 1
+    }
+
+    fn alternate(&self, bucket: usize, fingerprint: u16) -> usize {
+        let hash = (fingerprint as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15) >> 32;
+        (bucket ^ hash as usize) & self.mask()
+    }
+
+    fn contains(&self, bucket: usize, fingerprint: u16) -> bool {
+        let other = self.alternate(bucket, fingerprint);
+        self.buckets[bucket].contains(&fingerprint) || self.buckets[other].contains(&fingerprint)
+    }
+
+    fn place(&mut self, bucket: usize, fingerprint: u16) -> bool {
+        match self.buckets[bucket].iter_mut().find(|slot| **slot == 0) {
+            Some(slot) => {
+                *slot = fingerprint;
+                self.len += 1;
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Err carries a fingerprint left homeless after MAX_KICKS, and its bucket
+    fn insert(&mut self, bucket: usize, fingerprint: u16, seed: &mut u64) -> Result<(), (usize, u16)> {
+        let other = self.alternate(bucket, fingerprint);
+        if self.place(bucket, fingerprint) || self.place(other, fingerprint) {
+            return Ok(());
+        }
+        let (mut bucket, mut fingerprint) = (bucket, fingerprint);
+        for _ in 0..MAX_KICKS {
+            // xorshift: cheap, deterministic choice of the victim slot
+            *seed ^= *seed << 13;
+            *seed ^= *seed >> 7;
+            *seed ^= *seed << 17;
+            let slot = (*seed % SLOTS as u64) as usize;
+            std::mem::swap(&mut fingerprint, &mut self.buckets[bucket][slot]);
+            bucket = self.alternate(bucket, fingerprint);
+            if self.place(bucket, fingerprint) {
+                return Ok(());
+            }
+        }
+        Err((bucket, fingerprint))
+    }
+
+    fn delete(&mut self, bucket: usize, fingerprint: u16) -> bool {
+        let other = self.alternate(bucket, fingerprint);
+        for bucket in [bucket, other] {
+            if let Some(slot) = self.buckets[bucket].iter_mut().find(|slot| **slot == fingerprint) {
+                *slot = 0;
+                self.len -= 1;
+                return true;
+            }
+        }
+        false
+    }
+}
+
+struct State {
+    current: Generation,
+    previous: Generation,
+    seed: u64,
+}
+
+pub struct CuckooFilter {
+    capacity: usize,
+    state: Mutex<State>,
+}
+
+#[rustler::resource_impl]
+impl rustler::Resource for CuckooFilter {}
+
+impl CuckooFilter {
+    /// None unless 1 <= capacity <= MAX_CAPACITY.
+    pub fn new(capacity: usize) -> Option<Self> {
+        if !(1..=MAX_CAPACITY).contains(&capacity) {
+            return None;
+        }
+        // Room for `capacity` entries at a load factor of at most 80%
+        let buckets = (capacity * 5 / 4).div_ceil(SLOTS).next_power_of_two().max(2);
+        let state = State {
+            current: Generation::new(buckets),
+            previous: Generation::new(buckets),
+            seed: 0x2545_f491_4f6c_dd1d,
+        };
+        Some(CuckooFilter { capacity, state: Mutex::new(state) })
+    }
+
+    fn lock(&self) -> Result<std::sync::MutexGuard<'_, State>, String> {
+        self.state.lock().map_err(|_| "cuckoo filter lock poisoned".to_string())
+    }
+
+    // (first bucket, fingerprint); fingerprint 0 marks an empty slot
+    fn locate(state: &State, item: &[u8]) -> (usize, u16) {
+        let hash = blake3::hash(item);
+        let bytes = hash.as_bytes();
+        let fingerprint = u16::from_le_bytes([bytes[8], bytes[9]]).max(1);
+        let bucket = u64::from_le_bytes(bytes[..8].try_into().unwrap()) as usize & state.current.mask();
+        (bucket, fingerprint)
+    }
+
+    /// Add `item` unless it is (probably) already present. Returns whether it
+    /// was added, i.e. whether it is new.
+    pub fn insert(&self, item: &[u8]) -> Result<bool, String> {
+        let mut state = self.lock()?;
+        let (bucket, fingerprint) = Self::locate(&state, item);
+        if state.current.contains(bucket, fingerprint) {
+            return Ok(false);
+        }
+        // Seen in the previous generation: carry it into the current one so
+        // items that keep coming back don't age out
+        let new = !state.previous.contains(bucket, fingerprint);
+        if state.current.len == self.capacity {
+            Self::rotate(&mut state);
+        }
+        let State { current, seed, .. } = &mut *state;
+        if let Err((bucket, fingerprint)) = current.insert(bucket, fingerprint, seed) {
+            // The full generation keeps everything but the evicted entry,
+            // which moves to the fresh one
+            Self::rotate(&mut state);
+            state.current.place(bucket, fingerprint);
+        }
+        Ok(new)
+    }
+
+    fn rotate(state: &mut State) {
+        let buckets = state.current.buckets.len();
+        state.previous = std::mem::replace(&mut state.current, Generation::new(buckets));
+    }
+
+    pub fn contains(&self, item: &[u8]) -> Result<bool, String> {
+        let state = self.lock()?;
+        let (bucket, fingerprint) = Self::locate(&state, item);
+        Ok(state.current.contains(bucket, fingerprint) || state.previous.contains(bucket, fingerprint))
+    }
+
+    /// Remove `item`. Returns whether it was found. Deleting an item that was
+    /// never inserted may remove another item sharing its fingerprint.
+    pub fn delete(&self, item: &[u8]) -> Result<bool, String> {
+        let mut state = self.lock()?;
+        let (bucket, fingerprint) = Self::locate(&state, item);
+        Ok(state.current.delete(bucket, fingerprint) || state.previous.delete(bucket, fingerprint))
+    }
+}
@@ -0,0 +1,240 @@
+// Distributed generation of a threshold-shared keygen seed.
+//
+// Each of the n parties draws a random 32-byte contribution and Shamir-splits
+// it (byte-wise over GF(2^8), threshold t) into one share per party. Sharing
+// is linear, so once every party has XORed together the shares addressed to
+// it, it holds a share of the XOR of all contributions: a seed nobody has
+// seen, recoverable by any t parties and fed to `keypair_from_seed`.
+//
+//   dealer share = "BDKG" | kind 1 | session tag 8 | t | n | dealer | recipient | 32 bytes
+//   key share    = "BDKG" | kind 2 | session tag 8 | t | index | 32 bytes
+//
+// The session tag (blake3 of the ceremony id) keeps shares from different
+// ceremonies apart. There is no verifiable secret sharing: a dishonest dealer
+// can make the reconstructed key depend on which t shares are combined, so
+// the ceremony should combine several subsets and compare public keys.
+
+use std::sync::Mutex;
+use zeroize::Zeroizing;
+
+const MAGIC: [u8; 4] = *b"BDKG";
+const KIND_DEALER_SHARE: u8 = 1;
+const KIND_KEY_SHARE: u8 = 2;
+const TAG_LEN: usize = 8;
+pub const SEED_LEN: usize = 32;
+const DEALER_SHARE_LEN: usize = 4 + 1 + TAG_LEN + 4 + SEED_LEN;
+const KEY_SHARE_LEN: usize = 4 + 1 + TAG_LEN + 2 + SEED_LEN;
+
+pub const MAX_SESSION_ID_LEN: usize = 64;
+
+// (recipient index, dealer share)
+pub type DealerShares = Vec<(u8, Vec<u8>)>;
+
+// --- GF(2^8) with the AES polynomial x^8 + x^4 + x^3 + x + 1, branch-free ---
+
+fn gf_mul(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0u8;
+    for _ in 0..8 {
+        product ^= a & (b & 1).wrapping_neg();
+        let carry = (a >> 7).wrapping_neg();
+        a = (a << 1) ^ (carry & 0x1b);
+        b >>= 1;
+    }
+    product
+}
+
+// a^254 = a^-1 for a != 0
+fn gf_inv(a: u8) -> u8 {
+    let mut result = 1u8;
+    let mut base = a;
+    let mut exponent = 254u8;
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            result = gf_mul(result, base);
+        }
+        base = gf_mul(base, base);
+        exponent >>= 1;
+    }
+    result
+}
+
+fn session_tag(session_id: &[u8]) -> [u8; TAG_LEN] {
+    let hash = blake3::hash(session_id);
+    let mut tag = [0u8; TAG_LEN];
+    tag.copy_from_slice(&hash.as_bytes()[..TAG_LEN]);
+    tag
+}
+
+struct DkgState {
+    // XOR of the shares received so far, this party's own included
+    accumulator: Zeroizing<[u8; SEED_LEN]>,
+    received: Vec<bool>,
+    finalized: bool,
+}
+
+/// One party's view of a ceremony, held between rounds.
+pub struct DkgSession {
+    tag: [u8; TAG_LEN],
+    threshold: u8,
+    parties: u8,
+    index: u8,
+    state: Mutex<DkgState>,
+}
+
+#[rustler::resource_impl]
+impl rustler::Resource for DkgSession {}
+
+impl DkgSession {
+    /// Start a ceremony as party `index` (1-based) of `parties`. Returns the
+    /// session and the dealer shares to send, paired with their recipient.
+    pub fn new(session_id: &[u8], threshold: u8, parties: u8, index: u8) -> Result<(Self, DealerShares), String> {
+        if session_id.is_empty() || session_id.len() > MAX_SESSION_ID_LEN {
+            return Err(format!("session id must be 1..={} bytes", MAX_SESSION_ID_LEN));
+        }
+        if threshold < 2 || threshold > parties {
+            return Err("threshold must be between 2 and the number of parties".to_string());
+        }
+        if index == 0 || index > parties {
+            return Err("party index must be between 1 and the number of parties".to_string());
+        }
+
+        // Row 0 is the contribution, rows 1..t the random polynomial coefficients
+        let mut coefficients = Zeroizing::new(vec![[0u8; SEED_LEN]; threshold as usize]);
+        for row in coefficients.iter_mut() {
+            crate::rng::fill(row)?;
+        }
+
+        let tag = session_tag(session_id);
+        let mut accumulator = Zeroizing::new([0u8; SEED_LEN]);
+        let mut outgoing = Vec::with_capacity(parties as usize - 1);
+        for recipient in 1..=parties {
+            let share = evaluate(&coefficients, recipient);
+            if recipient == index {
+                accumulator.copy_from_slice(&share[..]);
+                continue;
+            }
+            let mut message = Vec::with_capacity(DEALER_SHARE_LEN);
+            message.extend_from_slice(&MAGIC);
+            message.push(KIND_DEALER_SHARE);
+            message.extend_from_slice(&tag);
+            message.extend_from_slice(&[threshold, parties, index, recipient]);
+            message.extend_from_slice(&share[..]);
+            outgoing.push((recipient, message));
+        }
+
+        let mut received = vec![false; parties as usize];
+        received[index as usize - 1] = true;
+        let state = DkgState { accumulator, received, finalized: false };
+        Ok((DkgSession { tag, threshold, parties, index, state: Mutex::new(state) }, outgoing))
+    }
+
+    /// Fold in a dealer share addressed to this party.
+    pub fn receive(&self, message: &[u8]) -> Result<(), String> {
+        if message.len() != DEALER_SHARE_LEN || message[..4] != MAGIC || message[4] != KIND_DEALER_SHARE {
+            return Err("not a dealer share".to_string());
+        }
+        if message[5..5 + TAG_LEN] != self.tag {
+            return Err("share belongs to another session".to_string());
+        }
+        let header = &message[5 + TAG_LEN..5 + TAG_LEN + 4];
+        let (threshold, parties, dealer, recipient) = (header[0], header[1], header[2], header[3]);
+        if threshold != self.threshold || parties != self.parties {
+            return Err("share was dealt with different parameters".to_string());
+        }
+        if recipient != self.index {
+            return Err("share is addressed to another party".to_string());
+        }
+        if dealer == 0 || dealer > self.parties {
+            return Err("invalid dealer index".to_string());
+        }
+
+        let mut state = self.state.lock().map_err(|_| "session lock poisoned".to_string())?;
+        if state.finalized {
+            return Err("session already finalized".to_string());
+        }
+        if state.received[dealer as usize - 1] {
+            return Err(format!("duplicate share from party {}", dealer));
+        }
+        for (byte, share_byte) in state.accumulator.iter_mut().zip(&message[DEALER_SHARE_LEN - SEED_LEN..]) {
+            *byte ^= share_byte;
+        }
+        state.received[dealer as usize - 1] = true;
+        Ok(())
+    }
+
+    /// This party's key share, once every dealer's share has arrived. The
+    /// session is wiped afterwards.
+    pub fn finalize(&self) -> Result<Zeroizing<Vec<u8>>, String> {
+        let mut state = self.state.lock().map_err(|_| "session lock poisoned".to_string())?;
+        if state.finalized {
+            return Err("session already finalized".to_string());
+        }
+        let missing: Vec<String> = (1..=self.parties)
+            .filter(|party| !state.received[*party as usize - 1])
+            .map(|party| party.to_string())
+            .collect();
+        if !missing.is_empty() {
+            return Err(format!("missing shares from parties {}", missing.join(", ")));
+        }
+
+        let mut key_share = Zeroizing::new(Vec::with_capacity(KEY_SHARE_LEN));
+        key_share.extend_from_slice(&MAGIC);
+        key_share.push(KIND_KEY_SHARE);
+        key_share.extend_from_slice(&self.tag);
+        key_share.extend_from_slice(&[self.threshold, self.index]);
+        key_share.extend_from_slice(&state.accumulator[..]);
+        *state.accumulator = [0u8; SEED_LEN];
+        state.finalized = true;
+        Ok(key_share)
+    }
+}
+
+fn evaluate(coefficients: &[[u8; SEED_LEN]], x: u8) -> Zeroizing<[u8; SEED_LEN]> {
+    // Horner's rule, highest coefficient first
+    let mut result = Zeroizing::new([0u8; SEED_LEN]);
+    for row in coefficients.iter().rev() {
+        for (acc, coefficient) in result.iter_mut().zip(row) {
+            *acc = gf_mul(*acc, x) ^ coefficient;
+        }
+    }
+    result
+}
+
+/// Recover the shared seed from at least `threshold` key shares of one session.
+pub fn combine(key_shares: &[&[u8]]) -> Result<Zeroizing<[u8; SEED_LEN]>, String> {
+    let first = key_shares.first().ok_or("no key shares")?;
+    let mut points = Vec::with_capacity(key_shares.len());
+    for share in key_shares {
+        if share.len() != KEY_SHARE_LEN || share[..4] != MAGIC || share[4] != KIND_KEY_SHARE {
+            return Err("not a key share".to_string());
+        }
+        if share[..5 + TAG_LEN + 1] != first[..5 + TAG_LEN + 1] {
+            return Err("key shares belong to different sessions".to_string());
+        }
+        let index = share[5 + TAG_LEN + 1];
+        if index == 0 || points.iter().any(|(x, _)| *x == index) {
+            return Err("duplicate or invalid share index".to_string());
+        }
+        points.push((index, &share[KEY_SHARE_LEN - SEED_LEN..]));
+    }
+    let threshold = first[5 + TAG_LEN] as usize;
+    if points.len() < threshold {
+        return Err(format!("need {} key shares, got {}", threshold, points.len()));
+    }
+    points.truncate(threshold);
+
+    // Lagrange interpolation at x = 0, where subtraction is XOR
+    let mut seed = Zeroizing::new([0u8; SEED_LEN]);
+    for (i, (xi, yi)) in points.iter().enumerate() {
+        let mut basis = 1u8;
+        for (j, (xj, _)) in points.iter().enumerate() {
+            if i != j {
+                basis = gf_mul(basis, gf_mul(*xj, gf_inv(xj ^ xi)));
+            }
+        }
+        for (byte, share_byte) in seed.iter_mut().zip(yi.iter()) {
+            *byte ^= gf_mul(basis, *share_byte);
+        }
+    }
+    Ok(seed)
+}
@@ -0,0 +1,122 @@
+// Equivocation evidence for slashing: two signed statements from one key that
+// can't both be honest, either two headers at the same height (header.rs) or
+// two votes at the same height and round for different blocks (vote.rs).
+//
+//   version u8 | kind u8 | first | second
+//
+// The two statements are length-prefixed (unsigned LEB128 varint) and
+// ordered by their bytes, first strictly below second, so a pair has one
+// encoding and the same offence submitted twice is the same evidence.
+
+use crate::keygen::Algorithm;
+use crate::{header, varint, vote};
+
+pub const VERSION: u8 = 1;
+pub const MAX_STATEMENT_LEN: usize = 2 * crate::tx::MAX_SIGNATURE_LEN;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Kind {
+    Header = 1,
+    Vote = 2,
+}
+
+pub struct Equivocation {
+    pub kind: Kind,
+    pub algorithm: Algorithm,
+    pub public_key: Vec<u8>,
+    pub height: u64,
+    // None for headers, which have no round
+    pub round: Option<u32>,
+    // Header hashes or voted block ids, in evidence order
+    pub block_ids: [[u8; 32]; 2],
+}
+
+/// The evidence that `a` and `b`, two encoded headers or two encoded votes,
+/// equivocate. They are checked as verify() would.
+pub fn build(kind: Kind, a: &[u8], b: &[u8]) -> Result<Vec<u8>, String> {
+    let (first, second) = if a <= b { (a, b) } else { (b, a) };
+    let mut out = Vec::with_capacity(8 + a.len() + b.len());
+    out.push(VERSION);
+    out.push(kind as u8);
+    for statement in [first, second] {
+        varint::put_uleb128(&mut out, statement.len() as u64);
+        out.extend_from_slice(statement);
+    }
+    verify(&out)?;
+    Ok(out)
+}
+
+// (algorithm, public key, height, round, block id) of a signed statement
+type Statement = (Algorithm, Vec<u8>, u64, Option<u32>, [u8; 32]);
+
+fn header_statement(bytes: &[u8]) -> Result<Statement, String> {
+    let (header, hash) = header::decode(bytes)?;
+    let (algorithm, key) = header.producer.ok_or("unsigned header")?;
+    if !crate::public_key::verify_bytes(algorithm, &key, &header.signature, &hash) {
+        return Err("invalid header signature".to_string());
+    }
+    Ok((algorithm, key, header.height, None, hash))
+}
+
+fn vote_statement(bytes: &[u8]) -> Result<Statement, String> {
+    let vote = vote::decode(bytes)?;
+    if !vote.verify() {
+        return Err("invalid vote signature".to_string());
+    }
+    Ok((vote.algorithm, vote.public_key, vote.height, Some(vote.round), vote.block_id))
+}
+
+fn take<'a>(data: &'a [u8], pos: &mut usize) -> Result<&'a [u8], String> {
+    let (len, size) = varint::read_uleb128(data.get(*pos..).unwrap_or_default())?;
+    *pos += size;
+    if len == 0 || len > MAX_STATEMENT_LEN as u64 {
+        return Err(format!("statement length {} outside 1..{}", len, MAX_STATEMENT_LEN));
+    }
+    let bytes = data.get(*pos..*pos + len as usize).ok_or("truncated evidence")?;
+    *pos += len as usize;
+    Ok(bytes)
+}
+
+/// The offence `evidence` proves: both statements are validly signed by the
+/// same key for the same height (and round), but for different blocks.
+pub fn verify(evidence: &[u8]) -> Result<Equivocation, String> {
+    if evidence.len() < 2 {
+        return Err("truncated evidence".to_string());
+    }
+    if evidence[0] != VERSION {
+        return Err(format!("unsupported evidence version {}", evidence[0]));
+    }
+    let kind = match evidence[1] {
+        1 => Kind::Header,
+        2 => Kind::Vote,
+        other => return Err(format!("unknown evidence kind {}", other)),
+    };
+    let mut pos = 2;
+    let first = take(evidence, &mut pos)?;
+    let second = take(evidence, &mut pos)?;
+    if pos != evidence.len() {
+        return Err("trailing bytes after evidence".to_string());
+    }
+    if first >= second {
+        return Err("statements out of order or identical".to_string());
+    }
+
+    let statement = match kind {
+        Kind::Header => header_statement,
+        Kind::Vote => vote_statement,
+    };
+    let (algorithm, public_key, height, round, first_id) = statement(first).map_err(|e| format!("first: {}", e))?;
+    let (other_algorithm, other_key, other_height, other_round, second_id) =
+        statement(second).map_err(|e| format!("second: {}", e))?;
+
+    if other_algorithm != algorithm || other_key != public_key {
+        return Err("statements from different keys".to_string());
+    }
+    if other_height != height || other_round != round {
+        return Err("statements for different heights or rounds".to_string());
+    }
+    if first_id == second_id {
+        return Err("statements for the same block".to_string());
+    }
+    Ok(Equivocation { kind, algorithm, public_key, height, round, block_ids: [first_id, second_id] })
+}
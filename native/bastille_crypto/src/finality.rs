@@ -0,0 +1,226 @@
+// Finality proofs: a block header with the signatures of more than two
+// thirds of a validator set's weight, in one blob a light client or bridge
+// can check knowing only the Merkle root of that set.
+//
+//   version u8 | next set root 32 | header | validator count u16 | validators
+//     | bitfield | signatures
+//
+// The header is length-prefixed (header.rs format). Each validator is an
+// algorithm id, a weight (u64 BE, nonzero) and a length-prefixed public key;
+// the set root is the merkle.rs root of those validator encodings, in order.
+// The bitfield and signatures are laid out as in attestation.rs. Validators
+// sign message(header hash, set root, next set root), so a vote names the
+// set that cast it and the set that takes over after the block (the same
+// root when the set doesn't change), which is how a light client follows
+// the validator set from one proof to the next.
+//
+// Decoding is strict, as for headers and aggregates.
+
+use crate::header::{self, Header};
+use crate::keygen::Algorithm;
+use crate::{merkle, varint};
+use rayon::prelude::*;
+
+pub const VERSION: u8 = 1;
+pub const ROOT_LEN: usize = 32;
+pub const MAX_VALIDATORS: usize = u16::MAX as usize;
+pub const MAX_HEADER_LEN: usize = 2 * crate::tx::MAX_SIGNATURE_LEN;
+pub const MAX_SIGNATURE_LEN: usize = crate::tx::MAX_SIGNATURE_LEN;
+
+const MESSAGE_CONTEXT: &str = "bastille finality v1 vote";
+
+pub struct Validator {
+    pub algorithm: Algorithm,
+    pub public_key: Vec<u8>,
+    pub weight: u64,
+}
+
+pub struct Finalized {
+    pub header: Header,
+    pub hash: [u8; header::HASH_LEN],
+    pub next_set_root: [u8; ROOT_LEN],
+    // Indices of the validators that signed, ascending
+    pub signers: Vec<usize>,
+}
+
+fn put_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    varint::put_uleb128(out, bytes.len() as u64);
+    out.extend_from_slice(bytes);
+}
+
+fn encode_validator(validator: &Validator) -> Vec<u8> {
+    let mut out = Vec::with_capacity(12 + validator.public_key.len());
+    out.push(validator.algorithm.id());
+    out.extend_from_slice(&validator.weight.to_be_bytes());
+    put_bytes(&mut out, &validator.public_key);
+    out
+}
+
+fn check_set(validators: &[Validator]) -> Result<(), String> {
+    if validators.is_empty() || validators.len() > MAX_VALIDATORS {
+        return Err(format!("validator count {} outside 1..{}", validators.len(), MAX_VALIDATORS));
+    }
+    for (index, validator) in validators.iter().enumerate() {
+        if validator.weight == 0 {
+            return Err(format!("validator {} has no weight", index));
+        }
+        if validator.public_key.is_empty() || validator.public_key.len() > header::MAX_PUBLIC_KEY_LEN {
+            return Err(format!("public key of validator {} has length {}", index, validator.public_key.len()));
+        }
+    }
+    Ok(())
+}
+
+pub fn validator_set_root(validators: &[Validator]) -> Result<[u8; ROOT_LEN], String> {
+    check_set(validators)?;
+    let leaves: Vec<Vec<u8>> = validators.iter().map(encode_validator).collect();
+    Ok(merkle::root(&leaves))
+}
+
+/// Whether `signed` is more than 2/3 of `total`, the weight that finalizes.
+pub fn is_quorum(signed: u128, total: u128) -> bool {
+    signed * 3 > total * 2
+}
+
+/// What each validator signs to finalize the header with hash `header_hash`.
+pub fn message(header_hash: &[u8; header::HASH_LEN], set_root: &[u8; ROOT_LEN], next_set_root: &[u8; ROOT_LEN]) -> [u8; 32] {
+    let mut hasher = blake3::Hasher::new_derive_key(MESSAGE_CONTEXT);
+    hasher.update(header_hash);
+    hasher.update(set_root);
+    hasher.update(next_set_root);
+    *hasher.finalize().as_bytes()
+}
+
+/// The proof of `header_bytes` from `signatures`, (validator index,
+/// signature) pairs in any order, which are not verified here.
+pub fn build(
+    header_bytes: &[u8],
+    validators: &[Validator],
+    next_set_root: &[u8; ROOT_LEN],
+    mut signatures: Vec<(usize, &[u8])>,
+) -> Result<Vec<u8>, String> {
+    header::decode(header_bytes)?;
+    if header_bytes.len() > MAX_HEADER_LEN {
+        return Err(format!("header longer than {} bytes", MAX_HEADER_LEN));
+    }
+    check_set(validators)?;
+    if signatures.is_empty() {
+        return Err("no signatures".to_string());
+    }
+    signatures.sort_by_key(|(index, _)| *index);
+
+    let mut out = Vec::new();
+    out.push(VERSION);
+    out.extend_from_slice(next_set_root);
+    put_bytes(&mut out, header_bytes);
+    out.extend_from_slice(&(validators.len() as u16).to_be_bytes());
+    for validator in validators {
+        out.extend_from_slice(&encode_validator(validator));
+    }
+
+    let mut bitfield = vec![0u8; validators.len().div_ceil(8)];
+    let mut body = Vec::new();
+    for (i, (index, signature)) in signatures.iter().enumerate() {
+        if *index >= validators.len() {
+            return Err(format!("validator {} outside a set of {}", index, validators.len()));
+        }
+        if i > 0 && signatures[i - 1].0 == *index {
+            return Err(format!("two signatures from validator {}", index));
+        }
+        if signature.is_empty() || signature.len() > MAX_SIGNATURE_LEN {
+            return Err(format!("signature of validator {} has length {}", index, signature.len()));
+        }
+        bitfield[index / 8] |= 1 << (index % 8);
+        put_bytes(&mut body, signature);
+    }
+    out.extend_from_slice(&bitfield);
+    out.extend_from_slice(&body);
+    Ok(out)
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let bytes = self.data.get(self.pos..self.pos + len).ok_or("truncated finality proof")?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        Ok(self.take(N)?.try_into().unwrap_or([0; N]))
+    }
+
+    fn bytes(&mut self, what: &str, min: usize, max: usize) -> Result<&'a [u8], String> {
+        let (len, size) = varint::read_uleb128(&self.data[self.pos..])?;
+        self.pos += size;
+        if len < min as u64 || len > max as u64 {
+            return Err(format!("{} has length {}", what, len));
+        }
+        self.take(len as usize)
+    }
+}
+
+/// The header finalized by `proof`, if the validator set in it has root
+/// `set_root` and validators with more than 2/3 of its weight signed.
+pub fn verify(proof: &[u8], set_root: &[u8; ROOT_LEN]) -> Result<Finalized, String> {
+    let mut reader = Reader { data: proof, pos: 0 };
+    let version = reader.array::<1>()?[0];
+    if version != VERSION {
+        return Err(format!("unsupported finality proof version {}", version));
+    }
+    let next_set_root = reader.array()?;
+    let (header, hash) = header::decode(reader.bytes("header", 1, MAX_HEADER_LEN)?)?;
+
+    let count = u16::from_be_bytes(reader.array()?) as usize;
+    let mut validators = Vec::with_capacity(count);
+    for index in 0..count {
+        let id = reader.array::<1>()?[0];
+        let algorithm = Algorithm::from_id(id).ok_or(format!("unknown algorithm {} for validator {}", id, index))?;
+        let weight = u64::from_be_bytes(reader.array()?);
+        let public_key = reader.bytes("public key", 1, header::MAX_PUBLIC_KEY_LEN)?.to_vec();
+        validators.push(Validator { algorithm, public_key, weight });
+    }
+    if validator_set_root(&validators)? != *set_root {
+        return Err("validator set does not match the root".to_string());
+    }
+
+    let bitfield = reader.take(count.div_ceil(8))?;
+    if !count.is_multiple_of(8) && bitfield[bitfield.len() - 1] >> (count % 8) != 0 {
+        return Err("bits set past the validator set".to_string());
+    }
+    let mut signatures = Vec::new();
+    for index in (0..count).filter(|index| bitfield[index / 8] & (1 << (index % 8)) != 0) {
+        signatures.push((index, reader.bytes("signature", 1, MAX_SIGNATURE_LEN)?));
+    }
+    if reader.pos != proof.len() {
+        return Err("trailing bytes after finality proof".to_string());
+    }
+
+    let total: u128 = validators.iter().map(|validator| validator.weight as u128).sum();
+    let signed: u128 = signatures.iter().map(|(index, _)| validators[*index].weight as u128).sum();
+    if !is_quorum(signed, total) {
+        return Err(format!("signatures from {} of {} weight, not over 2/3", signed, total));
+    }
+
+    let message = message(&hash, set_root, &next_set_root);
+    let failed: Vec<String> = crate::threads::install(|| {
+        signatures
+            .par_iter()
+            .filter(|(index, signature)| {
+                let validator = &validators[*index];
+                !crate::public_key::verify_bytes(validator.algorithm, &validator.public_key, signature, &message)
+            })
+            .map(|(index, _)| index.to_string())
+            .collect()
+    });
+    if !failed.is_empty() {
+        return Err(format!("invalid signatures from validators {}", failed.join(", ")));
+    }
+
+    let signers = signatures.iter().map(|(index, _)| *index).collect();
+    Ok(Finalized { header, hash, next_set_root, signers })
+}
@@ -0,0 +1,447 @@
+// Protobuf wire format of the P2P envelope and its messages, mirroring the
+// schema in lib/bastille/features/p2p/messaging/envelope.ex field for field,
+// so frames encoded here and by the Elixir codec are interchangeable.
+//
+// Encoding follows proto3: default values are left out and repeated scalars
+// are packed. Decoding accepts what any proto3 encoder may produce (packed or
+// not, fields in any order, unknown fields skipped) but checks every message
+// against the limits below, so a peer can't make us build huge terms.
+
+use crate::varint::put_uleb128;
+
+/// Largest envelope accepted, the same as the P2P frame limit
+pub const MAX_MESSAGE_SIZE: usize = 2_000_000;
+
+// Wire types; the deprecated groups (3 and 4) are refused
+const WIRE_VARINT: u8 = 0;
+const WIRE_FIXED64: u8 = 1;
+const WIRE_LEN: u8 = 2;
+const WIRE_FIXED32: u8 = 5;
+
+pub enum Kind {
+    Uint32,
+    Uint64,
+    Bool,
+    String,
+    Bytes,
+    // Value names by number
+    Enum(&'static [&'static str]),
+    Message(&'static Message),
+}
+
+pub struct Field {
+    pub name: &'static str,
+    number: u32,
+    pub kind: Kind,
+    // Most items of a repeated field
+    pub repeated: Option<usize>,
+}
+
+pub struct Message {
+    pub fields: &'static [Field],
+}
+
+const fn field(name: &'static str, number: u32, kind: Kind) -> Field {
+    Field { name, number, kind, repeated: None }
+}
+
+const fn repeated(name: &'static str, number: u32, kind: Kind, max: usize) -> Field {
+    Field { name, number, kind, repeated: Some(max) }
+}
+
+const ITEM_TYPES: &[&str] = &["BLOCK", "TX"];
+
+const INVENTORY_ITEM: Message = Message {
+    fields: &[field("type", 1, Kind::Enum(ITEM_TYPES)), field("hash", 2, Kind::Bytes)],
+};
+
+const ADDR_ENTRY: Message = Message {
+    fields: &[
+        field("timestamp", 1, Kind::Uint64),
+        field("services", 2, Kind::Uint64),
+        field("ip", 3, Kind::String),
+        field("port", 4, Kind::Uint32),
+    ],
+};
+
+const VERSION: Message = Message {
+    fields: &[
+        field("network", 1, Kind::String),
+        field("magic", 2, Kind::String),
+        field("protocol_version", 3, Kind::Uint32),
+        field("services", 4, Kind::Uint64),
+        field("timestamp", 5, Kind::Uint64),
+        field("recv_services", 6, Kind::Uint64),
+        field("recv_ip", 7, Kind::String),
+        field("recv_port", 8, Kind::Uint32),
+        field("from_services", 9, Kind::Uint64),
+        field("from_ip", 10, Kind::String),
+        field("from_port", 11, Kind::Uint32),
+        field("nonce", 12, Kind::Uint64),
+        field("user_agent", 13, Kind::String),
+        field("start_height", 14, Kind::Uint64),
+        field("relay", 15, Kind::Bool),
+    ],
+};
+
+const EMPTY: Message = Message { fields: &[] };
+
+// Inventory and address limits are Bitcoin's
+const INV: Message = Message {
+    fields: &[repeated("items", 1, Kind::Message(&INVENTORY_ITEM), 50_000)],
+};
+
+const ADDR: Message = Message {
+    fields: &[repeated("entries", 1, Kind::Message(&ADDR_ENTRY), 1_000)],
+};
+
+const NONCE: Message = Message { fields: &[field("nonce", 1, Kind::Uint64)] };
+
+const GET_HEADERS: Message = Message {
+    fields: &[
+        field("version", 1, Kind::Uint32),
+        field("hash_count", 2, Kind::Uint32),
+        repeated("block_locator_hashes", 3, Kind::Uint64, 2_000),
+        field("hash_stop", 4, Kind::Uint64),
+    ],
+};
+
+const HEADERS: Message = Message {
+    fields: &[field("count", 1, Kind::Uint32), repeated("headers", 2, Kind::Bytes, 2_000)],
+};
+
+const BLOCK_HEADER: Message = Message {
+    fields: &[
+        field("index", 1, Kind::Uint64),
+        field("previous_hash", 2, Kind::Bytes),
+        field("timestamp", 3, Kind::Uint64),
+        field("merkle_root", 4, Kind::Bytes),
+        field("nonce", 5, Kind::Uint64),
+        field("difficulty", 6, Kind::Uint32),
+        field("consensus_data", 7, Kind::Bytes),
+    ],
+};
+
+const TRANSACTION: Message = Message {
+    fields: &[
+        field("from", 1, Kind::String),
+        field("to", 2, Kind::String),
+        field("amount", 3, Kind::Uint64),
+        field("fee", 4, Kind::Uint64),
+        field("nonce", 5, Kind::Uint64),
+        field("timestamp", 6, Kind::Uint64),
+        field("data", 7, Kind::Bytes),
+        field("signature", 8, Kind::Bytes),
+        field("signature_type", 9, Kind::String),
+        field("hash", 10, Kind::Bytes),
+    ],
+};
+
+// Transaction limit as in Messaging.Validation
+const BLOCK: Message = Message {
+    fields: &[
+        field("hash", 1, Kind::Bytes),
+        field("header", 2, Kind::Message(&BLOCK_HEADER)),
+        repeated("transactions", 3, Kind::Message(&TRANSACTION), 100_000),
+    ],
+};
+
+const GET_BLOCKS: Message = Message {
+    fields: &[
+        field("version", 1, Kind::Uint32),
+        field("start_height", 2, Kind::Uint64),
+        field("stop_height", 3, Kind::Uint64),
+        field("max_count", 4, Kind::Uint32),
+    ],
+};
+
+const HEIGHT: Message = Message {
+    fields: &[field("height", 1, Kind::Uint64), field("timestamp", 2, Kind::Uint64)],
+};
+
+/// The envelope's `msg` oneof: each command and its message.
+pub const ENVELOPE: Message = Message {
+    fields: &[
+        field("version", 1, Kind::Message(&VERSION)),
+        field("verack", 2, Kind::Message(&EMPTY)),
+        field("inv", 3, Kind::Message(&INV)),
+        field("getdata", 4, Kind::Message(&INV)),
+        field("block", 5, Kind::Message(&BLOCK)),
+        field("tx", 6, Kind::Message(&TRANSACTION)),
+        field("addr", 7, Kind::Message(&ADDR)),
+        field("ping", 8, Kind::Message(&NONCE)),
+        field("pong", 9, Kind::Message(&NONCE)),
+        field("getaddr", 10, Kind::Message(&EMPTY)),
+        field("getheaders", 11, Kind::Message(&GET_HEADERS)),
+        field("headers", 12, Kind::Message(&HEADERS)),
+        field("getblocks", 13, Kind::Message(&GET_BLOCKS)),
+        field("height", 14, Kind::Message(&HEIGHT)),
+    ],
+};
+
+pub enum Value {
+    // Of Uint32, Uint64, Bool and Enum fields
+    Int(u64),
+    // Of String and Bytes fields
+    Bytes(Vec<u8>),
+    // Values of the message's fields, in schema order; None if unset
+    Message(Vec<Option<Value>>),
+    Repeated(Vec<Value>),
+}
+
+fn put_key(out: &mut Vec<u8>, number: u32, wire: u8) {
+    put_uleb128(out, (number as u64) << 3 | wire as u64);
+}
+
+fn put_len(out: &mut Vec<u8>, number: u32, bytes: &[u8]) {
+    put_key(out, number, WIRE_LEN);
+    put_uleb128(out, bytes.len() as u64);
+    out.extend_from_slice(bytes);
+}
+
+fn is_scalar(kind: &Kind) -> bool {
+    matches!(kind, Kind::Uint32 | Kind::Uint64 | Kind::Bool | Kind::Enum(_))
+}
+
+fn check_int(kind: &Kind, n: u64) -> Result<(), String> {
+    match kind {
+        Kind::Uint32 if n > u32::MAX as u64 => Err(format!("{} does not fit in a uint32", n)),
+        Kind::Bool if n > 1 => Err(format!("{} is not a bool", n)),
+        Kind::Enum(_) if n > i32::MAX as u64 => Err(format!("{} is not an enum value", n)),
+        _ => Ok(()),
+    }
+}
+
+fn mismatch(name: &str) -> String {
+    format!("field {} does not match the schema", name)
+}
+
+/// Encode a message of schema `message`, appending it to `out`.
+pub fn encode(message: &Message, value: &Value, out: &mut Vec<u8>) -> Result<(), String> {
+    let Value::Message(values) = value else { return Err("not a message".to_string()) };
+    for (field, value) in message.fields.iter().zip(values) {
+        let Some(value) = value else { continue };
+        match (field.repeated, value) {
+            (Some(max), Value::Repeated(items)) => {
+                if items.len() > max {
+                    return Err(format!("{} holds {} items, over the {} limit", field.name, items.len(), max));
+                }
+                if is_scalar(&field.kind) {
+                    if items.is_empty() {
+                        continue;
+                    }
+                    let mut packed = Vec::new();
+                    for item in items {
+                        let Value::Int(n) = item else { return Err(mismatch(field.name)) };
+                        check_int(&field.kind, *n)?;
+                        put_uleb128(&mut packed, *n);
+                    }
+                    put_len(out, field.number, &packed);
+                } else {
+                    for item in items {
+                        encode_single(field, item, out)?;
+                    }
+                }
+            }
+            (None, Value::Repeated(_)) | (Some(_), _) => return Err(mismatch(field.name)),
+            // Defaults are left out, except a message: set, even if empty, is not its default
+            (None, Value::Int(0)) if is_scalar(&field.kind) => {}
+            (None, Value::Bytes(bytes)) if bytes.is_empty() => {}
+            (None, value) => encode_single(field, value, out)?,
+        }
+    }
+    Ok(())
+}
+
+fn encode_single(field: &Field, value: &Value, out: &mut Vec<u8>) -> Result<(), String> {
+    match (&field.kind, value) {
+        (Kind::Message(message), Value::Message(_)) => {
+            let mut nested = Vec::new();
+            encode(message, value, &mut nested)?;
+            put_len(out, field.number, &nested);
+        }
+        (Kind::String, Value::Bytes(bytes)) => {
+            std::str::from_utf8(bytes).map_err(|_| format!("field {} is not valid UTF-8", field.name))?;
+            put_len(out, field.number, bytes);
+        }
+        (Kind::Bytes, Value::Bytes(bytes)) => put_len(out, field.number, bytes),
+        (kind, Value::Int(n)) if is_scalar(kind) => {
+            check_int(kind, *n)?;
+            put_key(out, field.number, WIRE_VARINT);
+            put_uleb128(out, *n);
+        }
+        _ => return Err(mismatch(field.name)),
+    }
+    Ok(())
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn done(&self) -> bool {
+        self.pos == self.data.len()
+    }
+
+    fn varint(&mut self) -> Result<u64, String> {
+        let mut n = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = *self.data.get(self.pos).ok_or("truncated varint")?;
+            self.pos += 1;
+            if shift == 63 && byte > 1 {
+                return Err("varint overflows 64 bits".to_string());
+            }
+            n |= ((byte & 0x7f) as u64) << shift;
+            if byte < 0x80 {
+                return Ok(n);
+            }
+        }
+        Err("varint longer than 10 bytes".to_string())
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let end = self.pos.checked_add(len).filter(|end| *end <= self.data.len()).ok_or("truncated field")?;
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn len_delimited(&mut self) -> Result<&'a [u8], String> {
+        let len = self.varint()?;
+        self.take(usize::try_from(len).map_err(|_| "truncated field")?)
+    }
+
+    fn skip(&mut self, wire: u8) -> Result<(), String> {
+        match wire {
+            WIRE_VARINT => self.varint().map(|_| ()),
+            WIRE_FIXED64 => self.take(8).map(|_| ()),
+            WIRE_LEN => self.len_delimited().map(|_| ()),
+            WIRE_FIXED32 => self.take(4).map(|_| ()),
+            _ => Err(format!("unsupported wire type {}", wire)),
+        }
+    }
+}
+
+fn push_item(field: &Field, items: &mut Option<Value>, item: Value) -> Result<(), String> {
+    let Value::Repeated(items) = items.get_or_insert_with(|| Value::Repeated(Vec::new())) else { unreachable!() };
+    if items.len() == field.repeated.unwrap_or(usize::MAX) {
+        return Err(format!("{} holds over {} items", field.name, items.len()));
+    }
+    items.push(item);
+    Ok(())
+}
+
+/// Decode a message of schema `message`. Unset fields are left None.
+pub fn decode(message: &Message, data: &[u8]) -> Result<Value, String> {
+    decode_fields(message, data).map(|(values, _)| Value::Message(values))
+}
+
+/// Encode an envelope holding `value` as its command at `index` in
+/// `ENVELOPE`'s fields.
+pub fn encode_envelope(index: usize, value: Value, out: &mut Vec<u8>) -> Result<(), String> {
+    let mut values: Vec<Option<Value>> = ENVELOPE.fields.iter().map(|_| None).collect();
+    values[index] = Some(value);
+    let start = out.len();
+    encode(&ENVELOPE, &Value::Message(values), out)?;
+    if out.len() - start > MAX_MESSAGE_SIZE {
+        return Err(format!("envelope of {} bytes is over the {} byte limit", out.len() - start, MAX_MESSAGE_SIZE));
+    }
+    Ok(())
+}
+
+/// Decode an envelope into the index of its command in `ENVELOPE`'s fields and
+/// its message. Of several commands, the last one counts, as for any oneof.
+pub fn decode_envelope(data: &[u8]) -> Result<(usize, Value), String> {
+    if data.len() > MAX_MESSAGE_SIZE {
+        return Err(format!("envelope of {} bytes is over the {} byte limit", data.len(), MAX_MESSAGE_SIZE));
+    }
+    let (mut values, last) = decode_fields(&ENVELOPE, data)?;
+    let index = last.ok_or("envelope holds no message")?;
+    Ok((index, values[index].take().unwrap()))
+}
+
+// Values of the fields of a message of schema `message`, and the index of the
+// last field seen
+fn decode_fields(message: &Message, data: &[u8]) -> Result<(Vec<Option<Value>>, Option<usize>), String> {
+    let mut last = None;
+    let mut values: Vec<Option<Value>> = message.fields.iter().map(|_| None).collect();
+    let mut reader = Reader { data, pos: 0 };
+    while !reader.done() {
+        let key = reader.varint()?;
+        let (number, wire) = (key >> 3, (key & 7) as u8);
+        if number == 0 || number > u32::MAX as u64 >> 3 {
+            return Err(format!("invalid field number {}", number));
+        }
+        let Some(i) = message.fields.iter().position(|field| field.number as u64 == number) else {
+            reader.skip(wire)?;
+            continue;
+        };
+        let field = &message.fields[i];
+        last = Some(i);
+        match (&field.kind, wire) {
+            (kind, WIRE_VARINT) if is_scalar(kind) => {
+                let n = reader.varint()?;
+                check_int(kind, n)?;
+                match field.repeated {
+                    Some(_) => push_item(field, &mut values[i], Value::Int(n))?,
+                    None => values[i] = Some(Value::Int(n)),
+                }
+            }
+            // Packed repeated scalars
+            (kind, WIRE_LEN) if is_scalar(kind) && field.repeated.is_some() => {
+                let mut packed = Reader { data: reader.len_delimited()?, pos: 0 };
+                while !packed.done() {
+                    let n = packed.varint()?;
+                    check_int(kind, n)?;
+                    push_item(field, &mut values[i], Value::Int(n))?;
+                }
+            }
+            (Kind::String | Kind::Bytes, WIRE_LEN) => {
+                let bytes = reader.len_delimited()?;
+                if matches!(field.kind, Kind::String) && std::str::from_utf8(bytes).is_err() {
+                    return Err(format!("field {} is not valid UTF-8", field.name));
+                }
+                match field.repeated {
+                    Some(_) => push_item(field, &mut values[i], Value::Bytes(bytes.to_vec()))?,
+                    None => values[i] = Some(Value::Bytes(bytes.to_vec())),
+                }
+            }
+            (Kind::Message(nested), WIRE_LEN) => {
+                let bytes = reader.len_delimited()?;
+                match field.repeated {
+                    Some(_) => push_item(field, &mut values[i], decode(nested, bytes)?)?,
+                    None => values[i] = Some(merge(field, values[i].take(), decode(nested, bytes)?)?),
+                }
+            }
+            _ => return Err(format!("field {} has wire type {}", field.name, wire)),
+        }
+    }
+    Ok((values, last))
+}
+
+// A message field seen twice merges the second into the first, as protobuf
+// specifies: set scalars override, repeated fields append (within their limit)
+fn merge(field: &Field, first: Option<Value>, second: Value) -> Result<Value, String> {
+    let Kind::Message(message) = field.kind else { unreachable!() };
+    let (first, second) = match (first, second) {
+        (Some(Value::Message(first)), Value::Message(second)) => (first, second),
+        (_, second) => return Ok(second),
+    };
+    let mut merged = Vec::with_capacity(first.len());
+    for (field, (a, b)) in message.fields.iter().zip(first.into_iter().zip(second)) {
+        merged.push(match (a, b) {
+            (Some(Value::Repeated(mut a)), Some(Value::Repeated(b))) => {
+                a.extend(b);
+                if a.len() > field.repeated.unwrap_or(usize::MAX) {
+                    return Err(format!("{} holds over {} items", field.name, a.len() - 1));
+                }
+                Some(Value::Repeated(a))
+            }
+            (a @ Some(_), Some(b)) if matches!(field.kind, Kind::Message(_)) => Some(merge(field, a, b)?),
+            (a, b) => b.or(a),
+        });
+    }
+    Ok(Value::Message(merged))
+}
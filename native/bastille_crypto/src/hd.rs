@@ -0,0 +1,65 @@
+// Hierarchical deterministic seed derivation, SLIP-0010 style.
+//
+//   master:  I = HMAC-SHA512(key = "Bastille PQ seed", data = master_seed)
+//   child i: I = HMAC-SHA512(key = chain_code, data = 0x00 || key || ser32(i | 2^31))
+//   key = I[0..32], chain_code = I[32..64]
+//
+// Only hardened derivation exists: post-quantum schemes have no public-key
+// arithmetic to derive non-hardened children from. The resulting 32-byte key
+// is used as the seed of the `*_keypair_from_seed` functions, so one backup
+// seed yields independent keypairs per account and purpose for every algorithm.
+
+use hmac::{Hmac, Mac};
+use sha2::Sha512;
+use zeroize::{Zeroize, Zeroizing};
+
+pub const HARDENED_OFFSET: u32 = 1 << 31;
+pub const MAX_DEPTH: usize = 255;
+pub const MAX_SEED_LEN: usize = 64;
+
+const MASTER_KEY: &[u8] = b"Bastille PQ seed";
+
+type HmacSha512 = Hmac<Sha512>;
+
+// (key, chain code)
+type ExtendedKey = (Zeroizing<[u8; 32]>, Zeroizing<[u8; 32]>);
+
+fn hmac_split(key: &[u8], parts: &[&[u8]]) -> ExtendedKey {
+    let mut mac = HmacSha512::new_from_slice(key).expect("HMAC accepts any key length");
+    for part in parts {
+        mac.update(part);
+    }
+    let mut output = mac.finalize().into_bytes();
+
+    let mut child_key = Zeroizing::new([0u8; 32]);
+    let mut chain_code = Zeroizing::new([0u8; 32]);
+    child_key.copy_from_slice(&output[..32]);
+    chain_code.copy_from_slice(&output[32..]);
+    output.as_mut_slice().zeroize();
+    (child_key, chain_code)
+}
+
+/// Derive the child seed at `path` (indices below 2^31, all hardened).
+pub fn derive_child_seed(master_seed: &[u8], path: &[u32]) -> Result<Zeroizing<[u8; 32]>, String> {
+    if master_seed.len() < crate::keygen::MIN_SEED_LEN || master_seed.len() > MAX_SEED_LEN {
+        return Err(format!(
+            "master seed must be {}..={} bytes",
+            crate::keygen::MIN_SEED_LEN,
+            MAX_SEED_LEN
+        ));
+    }
+    if path.len() > MAX_DEPTH {
+        return Err(format!("path deeper than {}", MAX_DEPTH));
+    }
+
+    let (mut key, mut chain_code) = hmac_split(MASTER_KEY, &[master_seed]);
+    for &index in path {
+        if index >= HARDENED_OFFSET {
+            return Err(format!("path index {} out of range", index));
+        }
+        let hardened = (index | HARDENED_OFFSET).to_be_bytes();
+        (key, chain_code) = hmac_split(&chain_code[..], &[&[0u8], &key[..], &hardened]);
+    }
+
+    Ok(key)
+}
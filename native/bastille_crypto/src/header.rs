@@ -0,0 +1,178 @@
+// Canonical block header format, and the checks a syncing node runs on a
+// header against its parent, all in one pass:
+//
+//   version u8 | height u64 | previous hash 32 | timestamp i64 | merkle root 32
+//     | bits u32 | producer algorithm u8 | producer key | nonce u64 | signature
+//
+// The header hash is blake3 of everything up to and including the nonce,
+// which is little-endian so that hash is the proof of work of pow.rs (mine
+// the bytes before the nonce with pow_mine). The producer signs the hash.
+// Other integers are big-endian; the timestamp is in milliseconds, bits a
+// compact target (see target.rs), and the producer key and signature are
+// prefixed with their length as an unsigned LEB128 varint. Algorithm 0 is an
+// unsigned header, whose key and signature are empty.
+//
+// Decoding is strict, as for transactions, so a header has one encoding.
+
+use crate::keygen::Algorithm;
+use crate::{target, varint};
+
+pub const VERSION: u8 = 1;
+pub const HASH_LEN: usize = 32;
+pub const MAX_PUBLIC_KEY_LEN: usize = 4096;
+pub const MAX_SIGNATURE_LEN: usize = crate::tx::MAX_SIGNATURE_LEN;
+
+const UNSIGNED: u8 = 0;
+
+pub struct Header {
+    pub height: u64,
+    pub previous_hash: [u8; HASH_LEN],
+    pub timestamp: i64,
+    pub merkle_root: [u8; HASH_LEN],
+    pub bits: u32,
+    // (algorithm, public key) of the producer, None for unsigned headers
+    pub producer: Option<(Algorithm, Vec<u8>)>,
+    pub nonce: u64,
+    // Empty until signed
+    pub signature: Vec<u8>,
+}
+
+pub struct Params {
+    /// Current time, in milliseconds
+    pub now: i64,
+    /// How far past `now` a timestamp may be, in milliseconds
+    pub max_future_drift: i64,
+    /// The bits the retarget rule gives for this height, when known
+    pub expected_bits: Option<u32>,
+    /// The key that should produce this block, when known (the slot leader)
+    pub producer_key: Option<Vec<u8>>,
+    pub require_signature: bool,
+}
+
+fn put_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    varint::put_uleb128(out, bytes.len() as u64);
+    out.extend_from_slice(bytes);
+}
+
+pub fn encode(header: &Header) -> Result<Vec<u8>, String> {
+    let (algorithm, key): (u8, &[u8]) = match &header.producer {
+        Some((algorithm, key)) if !key.is_empty() && key.len() <= MAX_PUBLIC_KEY_LEN => (algorithm.id(), key),
+        Some((_, key)) => return Err(format!("producer key length {} outside 1..{}", key.len(), MAX_PUBLIC_KEY_LEN)),
+        None if header.signature.is_empty() => (UNSIGNED, &[]),
+        None => return Err("signature on an unsigned header".to_string()),
+    };
+    if header.signature.len() > MAX_SIGNATURE_LEN {
+        return Err(format!("signature longer than {} bytes", MAX_SIGNATURE_LEN));
+    }
+    let mut out = Vec::with_capacity(128 + key.len() + header.signature.len());
+    out.push(VERSION);
+    out.extend_from_slice(&header.height.to_be_bytes());
+    out.extend_from_slice(&header.previous_hash);
+    out.extend_from_slice(&header.timestamp.to_be_bytes());
+    out.extend_from_slice(&header.merkle_root);
+    out.extend_from_slice(&header.bits.to_be_bytes());
+    out.push(algorithm);
+    put_bytes(&mut out, key);
+    out.extend_from_slice(&header.nonce.to_le_bytes());
+    put_bytes(&mut out, &header.signature);
+    Ok(out)
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl Reader<'_> {
+    fn take(&mut self, len: usize) -> Result<&[u8], String> {
+        let bytes = self.data.get(self.pos..self.pos + len).ok_or("truncated header")?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        Ok(self.take(N)?.try_into().unwrap_or([0; N]))
+    }
+
+    fn bytes(&mut self, what: &str, max: usize) -> Result<Vec<u8>, String> {
+        let (len, size) = varint::read_uleb128(&self.data[self.pos..])?;
+        self.pos += size;
+        if len > max as u64 {
+            return Err(format!("{} longer than {} bytes", what, max));
+        }
+        Ok(self.take(len as usize)?.to_vec())
+    }
+}
+
+/// The header in `data` and its hash.
+pub fn decode(data: &[u8]) -> Result<(Header, [u8; HASH_LEN]), String> {
+    let mut reader = Reader { data, pos: 0 };
+    let version = reader.array::<1>()?[0];
+    if version != VERSION {
+        return Err(format!("unsupported header format version {}", version));
+    }
+    let height = u64::from_be_bytes(reader.array()?);
+    let previous_hash = reader.array()?;
+    let timestamp = i64::from_be_bytes(reader.array()?);
+    let merkle_root = reader.array()?;
+    let bits = u32::from_be_bytes(reader.array()?);
+    let algorithm = reader.array::<1>()?[0];
+    let key = reader.bytes("producer key", MAX_PUBLIC_KEY_LEN)?;
+    let nonce = u64::from_le_bytes(reader.array()?);
+    let hash = *blake3::hash(&data[..reader.pos]).as_bytes();
+    let signature = reader.bytes("signature", MAX_SIGNATURE_LEN)?;
+    if reader.pos != data.len() {
+        return Err("trailing bytes after header".to_string());
+    }
+
+    let producer = match algorithm {
+        UNSIGNED if key.is_empty() && signature.is_empty() => None,
+        UNSIGNED => return Err("producer key or signature on an unsigned header".to_string()),
+        _ if key.is_empty() => return Err("empty producer key".to_string()),
+        id => Some((Algorithm::from_id(id).ok_or(format!("unknown producer algorithm {}", id))?, key)),
+    };
+    let header = Header { height, previous_hash, timestamp, merkle_root, bits, producer, nonce, signature };
+    Ok((header, hash))
+}
+
+/// The hash of `header_bytes` if it validly extends `parent_bytes`. Cheap
+/// checks come first, the signature last.
+pub fn validate(header_bytes: &[u8], parent_bytes: &[u8], params: &Params) -> Result<[u8; HASH_LEN], String> {
+    let (header, hash) = decode(header_bytes)?;
+    let (parent, parent_hash) = decode(parent_bytes).map_err(|e| format!("parent: {}", e))?;
+
+    if parent.height.checked_add(1) != Some(header.height) {
+        return Err(format!("height {} does not follow parent height {}", header.height, parent.height));
+    }
+    if header.previous_hash != parent_hash {
+        return Err("previous hash is not the parent's hash".to_string());
+    }
+    if header.timestamp <= parent.timestamp {
+        return Err("timestamp not after the parent's".to_string());
+    }
+    if header.timestamp > params.now.saturating_add(params.max_future_drift) {
+        return Err("timestamp too far in the future".to_string());
+    }
+    if let Some(expected) = params.expected_bits {
+        if header.bits != expected {
+            return Err(format!("bits {:08x}, expected {:08x}", header.bits, expected));
+        }
+    }
+    if hash > target::from_compact(header.bits)? {
+        return Err("hash above the target".to_string());
+    }
+
+    match &header.producer {
+        None if params.require_signature => Err("unsigned header".to_string()),
+        None => Ok(hash),
+        Some((_, key)) if params.producer_key.as_ref().is_some_and(|expected| expected != key) => {
+            Err("header from another producer".to_string())
+        }
+        Some((algorithm, key)) => {
+            if !crate::public_key::verify_bytes(*algorithm, key, &header.signature, &hash) {
+                return Err("invalid producer signature".to_string());
+            }
+            Ok(hash)
+        }
+    }
+}
@@ -0,0 +1,134 @@
+// Fixed-depth incremental Merkle tree in the style of the Ethereum deposit
+// contract, for the staking deposit queue: an append touches one node per
+// level and the root is always a `depth`-step fold away, while only the
+// leftmost filled node of each level is kept.
+//
+// Leaves and nodes hash as in merkle.rs, empty leaves are 32 zero bytes and
+// empty subtrees hash up from them. Like the deposit contract, the root mixes
+// in the leaf count:
+//
+//   root = node(tree_root, count (u64 LE) | 24 zero bytes)
+//
+// A snapshot is depth (u8) | count (u64 BE) | depth branch hashes, enough to
+// resume appending elsewhere.
+
+use crate::merkle::{hash_leaf, hash_node, Hash};
+use std::sync::Mutex;
+
+pub const MAX_DEPTH: usize = 63;
+
+struct State {
+    count: u64,
+    // branch[h]: the last completed left subtree of height h
+    branch: Vec<Hash>,
+}
+
+pub struct IncrementalMerkleTree {
+    depth: usize,
+    zeros: Vec<Hash>,
+    state: Mutex<State>,
+}
+
+#[rustler::resource_impl]
+impl rustler::Resource for IncrementalMerkleTree {}
+
+fn zero_hashes(depth: usize) -> Vec<Hash> {
+    let mut zeros = vec![[0u8; 32]];
+    for height in 0..depth {
+        zeros.push(hash_node(&zeros[height], &zeros[height]));
+    }
+    zeros
+}
+
+impl IncrementalMerkleTree {
+    /// None unless 1 <= depth <= MAX_DEPTH.
+    pub fn new(depth: usize) -> Option<Self> {
+        Self::with_state(depth, 0, vec![[0u8; 32]; depth])
+    }
+
+    fn with_state(depth: usize, count: u64, branch: Vec<Hash>) -> Option<Self> {
+        if !(1..=MAX_DEPTH).contains(&depth) || count >= 1 << depth {
+            return None;
+        }
+        let state = Mutex::new(State { count, branch });
+        Some(IncrementalMerkleTree { depth, zeros: zero_hashes(depth), state })
+    }
+
+    /// None if `snapshot` isn't one this module wrote.
+    pub fn restore(snapshot: &[u8]) -> Option<Self> {
+        let (&depth, rest) = snapshot.split_first()?;
+        let (count, branch) = rest.split_first_chunk::<8>()?;
+        if branch.len() != 32 * depth as usize {
+            return None;
+        }
+        let branch = branch
+            .chunks_exact(32)
+            .map(|chunk| {
+                let mut hash = [0u8; 32];
+                hash.copy_from_slice(chunk);
+                hash
+            })
+            .collect();
+        Self::with_state(depth as usize, u64::from_be_bytes(*count), branch)
+    }
+
+    fn lock(&self) -> Result<std::sync::MutexGuard<'_, State>, String> {
+        self.state.lock().map_err(|_| "merkle accumulator lock poisoned".to_string())
+    }
+
+    /// Append a leaf, returning its index.
+    pub fn append(&self, leaf: &[u8]) -> Result<u64, String> {
+        let mut state = self.lock()?;
+        let index = state.count;
+        // As in the deposit contract, the last leaf can't be filled: its
+        // branch entry would sit one level above the tree
+        if index == (1 << self.depth) - 1 {
+            return Err("merkle accumulator is full".to_string());
+        }
+        state.count += 1;
+
+        let mut node = hash_leaf(leaf);
+        let mut size = state.count;
+        for height in 0..self.depth {
+            if size & 1 == 1 {
+                state.branch[height] = node;
+                break;
+            }
+            node = hash_node(&state.branch[height], &node);
+            size >>= 1;
+        }
+        Ok(index)
+    }
+
+    pub fn count(&self) -> Result<u64, String> {
+        Ok(self.lock()?.count)
+    }
+
+    pub fn root(&self) -> Result<Hash, String> {
+        let state = self.lock()?;
+        let mut node = self.zeros[0];
+        let mut size = state.count;
+        for height in 0..self.depth {
+            node = if size & 1 == 1 {
+                hash_node(&state.branch[height], &node)
+            } else {
+                hash_node(&node, &self.zeros[height])
+            };
+            size >>= 1;
+        }
+        let mut count = [0u8; 32];
+        count[..8].copy_from_slice(&state.count.to_le_bytes());
+        Ok(hash_node(&node, &count))
+    }
+
+    pub fn snapshot(&self) -> Result<Vec<u8>, String> {
+        let state = self.lock()?;
+        let mut snapshot = Vec::with_capacity(1 + 8 + 32 * self.depth);
+        snapshot.push(self.depth as u8);
+        snapshot.extend_from_slice(&state.count.to_be_bytes());
+        for hash in &state.branch {
+            snapshot.extend_from_slice(hash);
+        }
+        Ok(snapshot)
+    }
+}
@@ -0,0 +1,326 @@
+// JSON Canonicalization Scheme (RFC 8785), so that a signature over a JSON
+// payload is over the same bytes whatever produced them: no whitespace,
+// object members sorted by the UTF-16 code units of their names, strings with
+// only the escapes JSON requires, and numbers as IEEE 754 doubles written the
+// way ECMAScript's Number.prototype.toString writes them.
+//
+// Parsing follows I-JSON (RFC 7493): UTF-8 only, no lone surrogates, no
+// duplicate member names, and no number a double can't hold.
+
+// Nesting of arrays and objects, bounding the parser's recursion
+pub const MAX_DEPTH: usize = 128;
+
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+fn put_string(out: &mut Vec<u8>, text: &str) {
+    out.push(b'"');
+    for c in text.chars() {
+        match c {
+            '"' => out.extend_from_slice(b"\\\""),
+            '\\' => out.extend_from_slice(b"\\\\"),
+            '\u{08}' => out.extend_from_slice(b"\\b"),
+            '\u{0c}' => out.extend_from_slice(b"\\f"),
+            '\n' => out.extend_from_slice(b"\\n"),
+            '\r' => out.extend_from_slice(b"\\r"),
+            '\t' => out.extend_from_slice(b"\\t"),
+            '\0'..='\u{1f}' => out.extend_from_slice(format!("\\u{:04x}", c as u32).as_bytes()),
+            _ => out.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+        }
+    }
+    out.push(b'"');
+}
+
+// ECMAScript's Number::toString: the shortest digits that round-trip, in
+// plain notation for exponents from -7 to 20 and scientific notation outside
+fn put_number(out: &mut Vec<u8>, n: f64) {
+    if n == 0.0 {
+        // Negative zero too
+        out.push(b'0');
+        return;
+    }
+    if n < 0.0 {
+        out.push(b'-');
+    }
+    let scientific = format!("{:e}", n.abs());
+    let (mantissa, exponent) = scientific.split_once('e').unwrap_or((&scientific, "0"));
+    let digits = mantissa.replace('.', "");
+    let k = digits.len() as i32;
+    // The value is 0.digits * 10^point
+    let point = exponent.parse::<i32>().unwrap_or(0) + 1;
+    let zeros = |count: i32| "0".repeat(count as usize);
+    let text = if k <= point && point <= 21 {
+        digits + &zeros(point - k)
+    } else if 0 < point && point <= 21 {
+        format!("{}.{}", &digits[..point as usize], &digits[point as usize..])
+    } else if -6 < point && point <= 0 {
+        format!("0.{}{}", zeros(-point), digits)
+    } else {
+        let fraction = if k > 1 { format!(".{}", &digits[1..]) } else { String::new() };
+        let sign = if point > 0 { '+' } else { '-' };
+        format!("{}{}e{}{}", &digits[..1], fraction, sign, (point - 1).abs())
+    };
+    out.extend_from_slice(text.as_bytes());
+}
+
+fn utf16_order(a: &str, b: &str) -> std::cmp::Ordering {
+    a.encode_utf16().cmp(b.encode_utf16())
+}
+
+/// Canonical JSON of `value`, appended to `out`.
+pub fn serialize(value: &Value, out: &mut Vec<u8>) -> Result<(), String> {
+    match value {
+        Value::Null => out.extend_from_slice(b"null"),
+        Value::Bool(true) => out.extend_from_slice(b"true"),
+        Value::Bool(false) => out.extend_from_slice(b"false"),
+        Value::Number(n) if n.is_finite() => put_number(out, *n),
+        Value::Number(_) => return Err("JSON number not finite".to_string()),
+        Value::String(text) => put_string(out, text),
+        Value::Array(items) => {
+            out.push(b'[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(b',');
+                }
+                serialize(item, out)?;
+            }
+            out.push(b']');
+        }
+        Value::Object(members) => {
+            let mut sorted: Vec<&(String, Value)> = members.iter().collect();
+            sorted.sort_by(|a, b| utf16_order(&a.0, &b.0));
+            if sorted.windows(2).any(|pair| pair[0].0 == pair[1].0) {
+                return Err("duplicate JSON object member name".to_string());
+            }
+            out.push(b'{');
+            for (i, (name, member)) in sorted.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(b',');
+                }
+                put_string(out, name);
+                out.push(b':');
+                serialize(member, out)?;
+            }
+            out.push(b'}');
+        }
+    }
+    Ok(())
+}
+
+struct Parser<'a> {
+    text: &'a [u8],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, what: &str) -> String {
+        format!("{} at position {}", what, self.pos)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.text.get(self.pos), Some(b' ' | b'\t' | b'\n' | b'\r')) {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.text.get(self.pos).copied()
+    }
+
+    fn expect(&mut self, literal: &[u8]) -> Result<(), String> {
+        if !self.text[self.pos..].starts_with(literal) {
+            return Err(self.error("invalid JSON"));
+        }
+        self.pos += literal.len();
+        Ok(())
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Value, String> {
+        self.skip_whitespace();
+        let value = match self.peek() {
+            Some(b'n') => self.expect(b"null").map(|_| Value::Null)?,
+            Some(b't') => self.expect(b"true").map(|_| Value::Bool(true))?,
+            Some(b'f') => self.expect(b"false").map(|_| Value::Bool(false))?,
+            Some(b'"') => Value::String(self.string()?),
+            Some(b'-' | b'0'..=b'9') => Value::Number(self.number()?),
+            Some(b'[' | b'{') if depth >= MAX_DEPTH => return Err(self.error("JSON nested too deep")),
+            Some(b'[') => {
+                self.pos += 1;
+                let mut items = Vec::new();
+                self.skip_whitespace();
+                if self.peek() == Some(b']') {
+                    self.pos += 1;
+                } else {
+                    loop {
+                        items.push(self.value(depth + 1)?);
+                        self.skip_whitespace();
+                        match self.peek() {
+                            Some(b',') => self.pos += 1,
+                            Some(b']') => {
+                                self.pos += 1;
+                                break;
+                            }
+                            _ => return Err(self.error("expected , or ] in JSON array")),
+                        }
+                    }
+                }
+                Value::Array(items)
+            }
+            Some(b'{') => {
+                self.pos += 1;
+                let mut members = Vec::new();
+                self.skip_whitespace();
+                if self.peek() == Some(b'}') {
+                    self.pos += 1;
+                } else {
+                    loop {
+                        self.skip_whitespace();
+                        if self.peek() != Some(b'"') {
+                            return Err(self.error("expected JSON object member name"));
+                        }
+                        let name = self.string()?;
+                        self.skip_whitespace();
+                        self.expect(b":")?;
+                        members.push((name, self.value(depth + 1)?));
+                        self.skip_whitespace();
+                        match self.peek() {
+                            Some(b',') => self.pos += 1,
+                            Some(b'}') => {
+                                self.pos += 1;
+                                break;
+                            }
+                            _ => return Err(self.error("expected , or } in JSON object")),
+                        }
+                    }
+                }
+                Value::Object(members)
+            }
+            Some(_) => return Err(self.error("invalid JSON")),
+            None => return Err(self.error("truncated JSON")),
+        };
+        Ok(value)
+    }
+
+    fn hex4(&mut self) -> Result<u32, String> {
+        let digits = self.text.get(self.pos..self.pos + 4).ok_or_else(|| self.error("truncated JSON"))?;
+        let digits = std::str::from_utf8(digits).map_err(|_| self.error("invalid JSON \\u escape"))?;
+        let n = u32::from_str_radix(digits, 16).map_err(|_| self.error("invalid JSON \\u escape"))?;
+        self.pos += 4;
+        Ok(n)
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.pos += 1;
+        let mut text = Vec::new();
+        loop {
+            let start = self.pos;
+            while matches!(self.peek(), Some(c) if c >= 0x20 && c != b'"' && c != b'\\') {
+                self.pos += 1;
+            }
+            text.extend_from_slice(&self.text[start..self.pos]);
+            match self.peek() {
+                Some(b'"') => {
+                    self.pos += 1;
+                    break;
+                }
+                Some(b'\\') => {
+                    self.pos += 1;
+                    let escape = self.peek().ok_or_else(|| self.error("truncated JSON"))?;
+                    self.pos += 1;
+                    let c = match escape {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{08}',
+                        b'f' => '\u{0c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => {
+                            let mut code = self.hex4()?;
+                            if (0xd800..0xdc00).contains(&code) && self.text[self.pos..].starts_with(b"\\u") {
+                                self.pos += 2;
+                                let low = self.hex4()?;
+                                if !(0xdc00..0xe000).contains(&low) {
+                                    return Err(self.error("lone surrogate in JSON string"));
+                                }
+                                code = 0x10000 + ((code - 0xd800) << 10) + (low - 0xdc00);
+                            }
+                            char::from_u32(code).ok_or_else(|| self.error("lone surrogate in JSON string"))?
+                        }
+                        _ => return Err(self.error("invalid JSON escape")),
+                    };
+                    text.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+                }
+                Some(_) => return Err(self.error("control character in JSON string")),
+                None => return Err(self.error("truncated JSON")),
+            }
+        }
+        String::from_utf8(text).map_err(|_| "JSON string not UTF-8".to_string())
+    }
+
+    fn digits(&mut self) -> usize {
+        let start = self.pos;
+        while matches!(self.peek(), Some(b'0'..=b'9')) {
+            self.pos += 1;
+        }
+        self.pos - start
+    }
+
+    fn number(&mut self) -> Result<f64, String> {
+        let start = self.pos;
+        if self.peek() == Some(b'-') {
+            self.pos += 1;
+        }
+        let leading_zero = self.peek() == Some(b'0');
+        let integer = self.digits();
+        if integer == 0 || (leading_zero && integer > 1) {
+            return Err(self.error("invalid JSON number"));
+        }
+        if self.peek() == Some(b'.') {
+            self.pos += 1;
+            if self.digits() == 0 {
+                return Err(self.error("invalid JSON number"));
+            }
+        }
+        if matches!(self.peek(), Some(b'e' | b'E')) {
+            self.pos += 1;
+            if matches!(self.peek(), Some(b'+' | b'-')) {
+                self.pos += 1;
+            }
+            if self.digits() == 0 {
+                return Err(self.error("invalid JSON number"));
+            }
+        }
+        // The grammar above only lets ASCII through
+        let text = std::str::from_utf8(&self.text[start..self.pos]).unwrap_or_default();
+        match text.parse::<f64>() {
+            Ok(n) if n.is_finite() => Ok(n),
+            _ => Err(self.error("JSON number out of range")),
+        }
+    }
+}
+
+/// The single JSON value in `text`.
+pub fn parse(text: &[u8]) -> Result<Value, String> {
+    let mut parser = Parser { text, pos: 0 };
+    let value = parser.value(0)?;
+    parser.skip_whitespace();
+    if parser.pos != text.len() {
+        return Err(parser.error("trailing data after JSON value"));
+    }
+    Ok(value)
+}
+
+/// Canonical form of the JSON in `text`.
+pub fn canonicalize(text: &[u8]) -> Result<Vec<u8>, String> {
+    let mut out = Vec::with_capacity(text.len());
+    serialize(&parse(text)?, &mut out)?;
+    Ok(out)
+}
@@ -0,0 +1,500 @@
+// Jellyfish-style versioned Merkle tree: the state tree backend that keeps
+// every committed version's root queryable, for reads and proofs at past
+// block heights.
+//
+// The tree is 16-ary over the same BLAKE3 key paths as smt.rs, one nibble per
+// level. Nodes are immutable and shared between versions: a write copies the
+// nodes on its key's path and reuses every other subtree, so each version
+// costs only its changes. As in Diem's JMT, a subtree holding a single leaf
+// is that leaf, placed at the shallowest level where it is alone.
+//
+// An internal node hashes its 16 children as a 4-level binary tree, with the
+// same rules as smt.rs (empty subtrees are 32 zero bytes, a subtree with a
+// single leaf hashes as that leaf). Roots and proofs are therefore exactly
+// those of the sparse backend over the same entries, and smt::verify_proof
+// checks both.
+//
+// Writes apply to a working tree and become a version on commit(). <dir>/
+// jmt.log holds one record per commit: version (u64 BE) | op count (u32 BE)
+// | ops, each op (u8) | path | value length (u32 BE) | value, then a CRC32C
+// of the record. It is replayed on open (a torn final record is cut off), so
+// uncommitted writes don't survive a restart. History is kept until pruned:
+// prune_below() rewrites the log to start with a snapshot of the oldest
+// version kept.
+
+use crate::smt::{io_error, key_path, leaf_hash, node_hash, Hash, EMPTY, MAX_VALUE_LEN, TERMINAL_EMPTY, TERMINAL_LEAF};
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+const LOG_MAGIC: [u8; 8] = *b"BJMTLOG1";
+const OP_PUT: u8 = 1;
+const OP_DELETE: u8 = 2;
+
+struct Leaf {
+    path: Hash,
+    value: Vec<u8>,
+    value_hash: Hash,
+    hash: Hash,
+}
+
+struct Internal {
+    children: [Link; 16],
+    hash: Hash,
+}
+
+enum Node {
+    Leaf(Leaf),
+    Internal(Internal),
+}
+
+type Link = Option<Arc<Node>>;
+
+fn make_leaf(path: Hash, value: Vec<u8>) -> Arc<Node> {
+    let value_hash = *blake3::hash(&value).as_bytes();
+    let hash = leaf_hash(&path, &value_hash);
+    Arc::new(Node::Leaf(Leaf { path, value, value_hash, hash }))
+}
+
+fn nibble(path: &Hash, depth: usize) -> usize {
+    let byte = path[depth / 2];
+    (if depth.is_multiple_of(2) { byte >> 4 } else { byte & 0x0f }) as usize
+}
+
+fn link_hash(link: &Link) -> Hash {
+    match link.as_deref() {
+        None => EMPTY,
+        Some(Node::Leaf(leaf)) => leaf.hash,
+        Some(Node::Internal(internal)) => internal.hash,
+    }
+}
+
+// What children[start..start + width] holds, as far as hashing and proofs care
+enum Range<'a> {
+    Empty,
+    Single(&'a Leaf),
+    Branch,
+}
+
+fn classify(children: &[Link; 16], start: usize, width: usize) -> Range<'_> {
+    let mut present = children[start..start + width].iter().flatten();
+    match (present.next(), present.next()) {
+        (None, _) => Range::Empty,
+        (Some(node), None) => match &**node {
+            Node::Leaf(leaf) => Range::Single(leaf),
+            // An internal node always holds at least two leaves
+            Node::Internal(_) => Range::Branch,
+        },
+        (Some(_), Some(_)) => Range::Branch,
+    }
+}
+
+// Hash of children[start..start + width] as a binary subtree
+fn range_hash(children: &[Link; 16], start: usize, width: usize) -> Hash {
+    match classify(children, start, width) {
+        Range::Empty => EMPTY,
+        Range::Single(leaf) => leaf.hash,
+        Range::Branch if width == 1 => link_hash(&children[start]),
+        Range::Branch => {
+            let half = width / 2;
+            node_hash(&range_hash(children, start, half), &range_hash(children, start + half, half))
+        }
+    }
+}
+
+fn make_internal(children: [Link; 16]) -> Arc<Node> {
+    let hash = range_hash(&children, 0, 16);
+    Arc::new(Node::Internal(Internal { children, hash }))
+}
+
+// An internal node at `depth` over two leaves with distinct paths
+fn split(existing: Arc<Node>, existing_path: &Hash, leaf: Arc<Node>, path: &Hash, depth: usize) -> Arc<Node> {
+    let mut children: [Link; 16] = Default::default();
+    let (old, new) = (nibble(existing_path, depth), nibble(path, depth));
+    if old == new {
+        children[old] = Some(split(existing, existing_path, leaf, path, depth + 1));
+    } else {
+        children[old] = Some(existing);
+        children[new] = Some(leaf);
+    }
+    make_internal(children)
+}
+
+fn insert(link: &Link, depth: usize, path: &Hash, leaf: Arc<Node>) -> Arc<Node> {
+    let Some(node) = link else {
+        return leaf;
+    };
+    match &**node {
+        Node::Leaf(existing) if existing.path == *path => leaf,
+        Node::Leaf(existing) => split(node.clone(), &existing.path, leaf, path, depth),
+        Node::Internal(internal) => {
+            let mut children = internal.children.clone();
+            let slot = nibble(path, depth);
+            children[slot] = Some(insert(&children[slot], depth + 1, path, leaf));
+            make_internal(children)
+        }
+    }
+}
+
+// The subtree without `path`, or None if `path` isn't in it
+fn remove(link: &Link, depth: usize, path: &Hash) -> Option<Link> {
+    match &**link.as_ref()? {
+        Node::Leaf(leaf) => (leaf.path == *path).then_some(None),
+        Node::Internal(internal) => {
+            let slot = nibble(path, depth);
+            let child = remove(&internal.children[slot], depth + 1, path)?;
+            let mut children = internal.children.clone();
+            children[slot] = child;
+            let mut present = children.iter().flatten();
+            Some(match (present.next(), present.next()) {
+                (None, _) => None,
+                // A lone leaf moves up to where it is alone
+                (Some(only), None) if matches!(**only, Node::Leaf(_)) => Some(only.clone()),
+                _ => Some(make_internal(children)),
+            })
+        }
+    }
+}
+
+fn lookup<'a>(mut link: &'a Link, path: &Hash) -> Option<&'a Leaf> {
+    let mut depth = 0;
+    loop {
+        match &**link.as_ref()? {
+            Node::Leaf(leaf) => return (leaf.path == *path).then_some(leaf),
+            Node::Internal(internal) => {
+                link = &internal.children[nibble(path, depth)];
+                depth += 1;
+            }
+        }
+    }
+}
+
+fn terminal_leaf(leaf: &Leaf) -> Vec<u8> {
+    let mut terminal = vec![TERMINAL_LEAF];
+    terminal.extend_from_slice(&leaf.path);
+    terminal.extend_from_slice(&leaf.value_hash);
+    terminal
+}
+
+// A proof in smt.rs's format, descending each internal node bit by bit
+fn prove(root: &Link, path: &Hash) -> Vec<u8> {
+    let mut siblings: Vec<Hash> = Vec::new();
+    let mut link = root;
+    let mut depth = 0;
+    let terminal = 'walk: loop {
+        let internal = match link.as_deref() {
+            None => break vec![TERMINAL_EMPTY],
+            Some(Node::Leaf(leaf)) => break terminal_leaf(leaf),
+            Some(Node::Internal(internal)) => internal,
+        };
+        let slot = nibble(path, depth);
+        let (mut start, mut width) = (0, 16);
+        while width > 1 {
+            width /= 2;
+            let (mine, other) = if slot >= start + width { (start + width, start) } else { (start, start + width) };
+            siblings.push(range_hash(&internal.children, other, width));
+            start = mine;
+            match classify(&internal.children, start, width) {
+                Range::Empty => break 'walk vec![TERMINAL_EMPTY],
+                Range::Single(leaf) => break 'walk terminal_leaf(leaf),
+                Range::Branch => {}
+            }
+        }
+        link = &internal.children[slot];
+        depth += 1;
+    };
+
+    let mut proof = Vec::with_capacity(2 + siblings.len() * 32 + terminal.len());
+    proof.extend_from_slice(&(siblings.len() as u16).to_be_bytes());
+    for sibling in &siblings {
+        proof.extend_from_slice(sibling);
+    }
+    proof.extend_from_slice(&terminal);
+    proof
+}
+
+type Op = (u8, Hash, Vec<u8>);
+
+fn apply(root: &Link, (op, path, value): &Op) -> Link {
+    match *op {
+        OP_PUT => Some(insert(root, 0, path, make_leaf(*path, value.clone()))),
+        _ => remove(root, 0, path).unwrap_or_else(|| root.clone()),
+    }
+}
+
+// Puts recreating the tree under `link`
+fn collect_leaves(link: &Link) -> Vec<Op> {
+    fn walk(link: &Link, ops: &mut Vec<Op>) {
+        match link.as_deref() {
+            None => {}
+            Some(Node::Leaf(leaf)) => ops.push((OP_PUT, leaf.path, leaf.value.clone())),
+            Some(Node::Internal(internal)) => internal.children.iter().for_each(|child| walk(child, ops)),
+        }
+    }
+    let mut ops = Vec::new();
+    walk(link, &mut ops);
+    ops
+}
+
+fn encode_record(version: u64, ops: &[Op]) -> Vec<u8> {
+    let mut record = Vec::new();
+    record.extend_from_slice(&version.to_be_bytes());
+    record.extend_from_slice(&(ops.len() as u32).to_be_bytes());
+    for (op, path, value) in ops {
+        record.push(*op);
+        record.extend_from_slice(path);
+        record.extend_from_slice(&(value.len() as u32).to_be_bytes());
+        record.extend_from_slice(value);
+    }
+    let crc = crc32c::crc32c(&record);
+    record.extend_from_slice(&crc.to_be_bytes());
+    record
+}
+
+// Next record and its length, or None at the end of the log or at a torn or
+// corrupt record
+fn read_record(reader: &mut impl Read) -> Option<(u64, Vec<Op>, u64)> {
+    let mut header = [0u8; 12];
+    reader.read_exact(&mut header).ok()?;
+    let mut crc = crc32c::crc32c(&header);
+    let mut len = header.len() as u64 + 4;
+    let version = u64::from_be_bytes(header[..8].try_into().unwrap());
+    let count = u32::from_be_bytes(header[8..].try_into().unwrap());
+
+    let mut ops = Vec::new();
+    for _ in 0..count {
+        let mut op_header = [0u8; 1 + 32 + 4];
+        reader.read_exact(&mut op_header).ok()?;
+        let value_len = u32::from_be_bytes(op_header[33..].try_into().unwrap()) as usize;
+        if value_len > MAX_VALUE_LEN || !matches!(op_header[0], OP_PUT | OP_DELETE) {
+            return None;
+        }
+        let mut value = vec![0u8; value_len];
+        reader.read_exact(&mut value).ok()?;
+        crc = crc32c::crc32c_append(crc32c::crc32c_append(crc, &op_header), &value);
+        len += (op_header.len() + value_len) as u64;
+        ops.push((op_header[0], op_header[1..33].try_into().unwrap(), value));
+    }
+    let mut expected = [0u8; 4];
+    reader.read_exact(&mut expected).ok()?;
+    (u32::from_be_bytes(expected) == crc).then_some((version, ops, len))
+}
+
+struct JmtState {
+    working: Link,
+    pending: Vec<Op>,
+    versions: BTreeMap<u64, Link>,
+    dir: PathBuf,
+    log: File,
+    // Held for the tree's lifetime so no other process writes the same log
+    _lock: File,
+}
+
+impl JmtState {
+    fn open(dir: &Path) -> Result<Self, String> {
+        fs::create_dir_all(dir).map_err(|e| io_error("create state directory", dir, e))?;
+        let lock_path = dir.join("jmt.lock");
+        let lock = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&lock_path)
+            .map_err(|e| io_error("open", &lock_path, e))?;
+        lock.try_lock().map_err(|_| format!("state tree '{}' is already open", dir.display()))?;
+
+        let log_path = dir.join("jmt.log");
+        let mut log = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(&log_path)
+            .map_err(|e| io_error("open", &log_path, e))?;
+
+        let mut working = None;
+        let mut versions = BTreeMap::new();
+        let mut valid_len = LOG_MAGIC.len() as u64;
+        let mut reader = BufReader::new(&log);
+        let mut magic = [0u8; 8];
+        match reader.read_exact(&mut magic) {
+            Ok(()) if magic == LOG_MAGIC => {
+                while let Some((version, ops, len)) = read_record(&mut reader) {
+                    working = ops.iter().fold(working, |root, op| apply(&root, op));
+                    versions.insert(version, working.clone());
+                    valid_len += len;
+                }
+            }
+            Ok(()) => return Err(format!("'{}' is not a state tree log", log_path.display())),
+            // Empty, or cut off before the magic was complete
+            Err(_) => valid_len = 0,
+        }
+
+        let file_len = log.metadata().map_err(|e| io_error("read", &log_path, e))?.len();
+        if file_len > valid_len {
+            log.set_len(valid_len).map_err(|e| io_error("truncate", &log_path, e))?;
+        }
+        if valid_len == 0 {
+            log.write_all(&LOG_MAGIC).map_err(|e| io_error("write", &log_path, e))?;
+        }
+
+        Ok(JmtState { working, pending: Vec::new(), versions, dir: dir.to_path_buf(), log, _lock: lock })
+    }
+
+    fn stage(&mut self, op: Op) {
+        self.working = apply(&self.working, &op);
+        self.pending.push(op);
+    }
+
+    fn commit(&mut self, version: u64) -> Result<Hash, String> {
+        if let Some((latest, _)) = self.versions.last_key_value() {
+            if version <= *latest {
+                return Err(format!("version {} is not after the latest committed version {}", version, latest));
+            }
+        }
+        let record = encode_record(version, &self.pending);
+        self.log
+            .write_all(&record)
+            .map_err(|e| io_error("write", &self.dir.join("jmt.log"), e))?;
+        self.pending.clear();
+        self.versions.insert(version, self.working.clone());
+        Ok(link_hash(&self.working))
+    }
+
+    // Drop the versions before the latest one at or before `version`, which
+    // stays queryable, rewriting the log to start from a snapshot of it.
+    // Returns the bytes the log shrank by.
+    fn prune_below(&mut self, version: u64) -> Result<u64, String> {
+        let Some((&base, base_root)) = self.versions.range(..=version).next_back() else {
+            return Ok(0);
+        };
+        if self.versions.first_key_value().is_some_and(|(&first, _)| first == base) {
+            return Ok(0);
+        }
+        let snapshot = encode_record(base, &collect_leaves(base_root));
+
+        let log_path = self.dir.join("jmt.log");
+        let tmp_path = self.dir.join("jmt.log.tmp");
+        let result = (|| {
+            let old_len = self.log.metadata()?.len();
+            let mut reader = BufReader::new(File::open(&log_path)?);
+            reader.read_exact(&mut [0u8; 8])?;
+            let mut log = LOG_MAGIC.to_vec();
+            log.extend_from_slice(&snapshot);
+            while let Some((record_version, ops, _)) = read_record(&mut reader) {
+                if record_version > base {
+                    log.extend_from_slice(&encode_record(record_version, &ops));
+                }
+            }
+            let mut tmp = File::create(&tmp_path)?;
+            tmp.write_all(&log)?;
+            tmp.sync_all()?;
+            fs::rename(&tmp_path, &log_path)?;
+            File::open(&self.dir)?.sync_all()?;
+            Ok(old_len.saturating_sub(log.len() as u64))
+        })();
+        let reclaimed = result.map_err(|e| {
+            let _ = fs::remove_file(&tmp_path);
+            io_error("prune", &log_path, e)
+        })?;
+        self.log = OpenOptions::new()
+            .append(true)
+            .open(&log_path)
+            .map_err(|e| io_error("open", &log_path, e))?;
+        self.versions = self.versions.split_off(&base);
+        Ok(reclaimed)
+    }
+
+    // The working tree, or the latest version committed at or before `version`
+    fn tree(&self, version: Option<u64>) -> Result<&Link, String> {
+        match version {
+            None => Ok(&self.working),
+            Some(version) => self
+                .versions
+                .range(..=version)
+                .next_back()
+                .map(|(_, root)| root)
+                .ok_or_else(|| format!("no state committed at or before version {}", version)),
+        }
+    }
+}
+
+/// A versioned state tree open on one directory; at most one per directory
+/// at a time.
+pub struct JellyfishMerkleTree {
+    state: Mutex<JmtState>,
+}
+
+impl JellyfishMerkleTree {
+    pub fn open(dir: &Path) -> Result<Self, String> {
+        Ok(JellyfishMerkleTree { state: Mutex::new(JmtState::open(dir)?) })
+    }
+
+    fn state(&self) -> Result<std::sync::MutexGuard<'_, JmtState>, String> {
+        self.state.lock().map_err(|_| "state tree lock poisoned".to_string())
+    }
+
+    /// Run `f` on the value stored under `key` in the working tree (None) or
+    /// at `version`.
+    pub fn get<R>(&self, key: &[u8], version: Option<u64>, f: impl FnOnce(Option<&[u8]>) -> R) -> Result<R, String> {
+        let state = self.state()?;
+        let leaf = lookup(state.tree(version)?, &key_path(key));
+        Ok(f(leaf.map(|leaf| leaf.value.as_slice())))
+    }
+
+    pub fn put(&self, key: &[u8], value: &[u8]) -> Result<(), String> {
+        self.state()?.stage((OP_PUT, key_path(key), value.to_vec()));
+        Ok(())
+    }
+
+    pub fn delete(&self, key: &[u8]) -> Result<(), String> {
+        let mut state = self.state()?;
+        let path = key_path(key);
+        if lookup(&state.working, &path).is_some() {
+            state.stage((OP_DELETE, path, Vec::new()));
+        }
+        Ok(())
+    }
+
+    /// Record the working tree as `version`, which must be later than every
+    /// committed version, and return its root.
+    pub fn commit(&self, version: u64) -> Result<Hash, String> {
+        self.state()?.commit(version)
+    }
+
+    pub fn root(&self, version: Option<u64>) -> Result<Hash, String> {
+        Ok(link_hash(self.state()?.tree(version)?))
+    }
+
+    /// Proof that `key` holds its value, or is absent, in the working tree or
+    /// at `version`.
+    pub fn prove(&self, key: &[u8], version: Option<u64>) -> Result<Vec<u8>, String> {
+        let state = self.state()?;
+        Ok(prove(state.tree(version)?, &key_path(key)))
+    }
+
+    /// Forget the versions before `version`, keeping the latest one at or
+    /// before it so that `version` stays queryable. Returns the bytes reclaimed.
+    pub fn prune_below(&self, version: u64) -> Result<u64, String> {
+        self.state()?.prune_below(version)
+    }
+
+    /// A log recreating the latest version at or before `version` (as that
+    /// version, without history), for backups.
+    pub fn export_log(&self, version: u64) -> Result<Vec<u8>, String> {
+        let state = self.state()?;
+        let (&base, root) = state
+            .versions
+            .range(..=version)
+            .next_back()
+            .ok_or_else(|| format!("no state committed at or before version {}", version))?;
+        let mut log = LOG_MAGIC.to_vec();
+        log.extend_from_slice(&encode_record(base, &collect_leaves(root)));
+        Ok(log)
+    }
+
+    pub fn flush(&self) -> Result<(), String> {
+        let state = self.state()?;
+        state.log.sync_data().map_err(|e| io_error("sync", &state.dir.join("jmt.log"), e))
+    }
+}
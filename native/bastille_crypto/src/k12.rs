@@ -0,0 +1,140 @@
+// KangarooTwelve (RFC 9861) with the tree-hashing leaves computed in parallel.
+//
+// Inputs longer than one 8 KiB chunk are split into chunks whose chaining
+// values are independent, so they are hashed across the rayon pool before
+// being absorbed into the final node.
+
+use rayon::prelude::*;
+use std::borrow::Cow;
+
+const RATE: usize = 168;
+const ROUNDS: usize = 12;
+const CHUNK_SIZE: usize = 8192;
+const CV_SIZE: usize = 32;
+
+// Below this many leaf chunks the rayon overhead outweighs the gain
+const PARALLEL_MIN_CHUNKS: usize = 4;
+
+// TurboSHAKE128 sponge over Keccak-p[1600, 12]
+struct TurboShake128 {
+    state: [u64; 25],
+    offset: usize,
+}
+
+impl TurboShake128 {
+    fn new() -> Self {
+        TurboShake128 { state: [0u64; 25], offset: 0 }
+    }
+
+    fn xor_byte(&mut self, pos: usize, byte: u8) {
+        self.state[pos / 8] ^= (byte as u64) << (8 * (pos % 8));
+    }
+
+    fn absorb(&mut self, mut data: &[u8]) {
+        while !data.is_empty() {
+            let take = (RATE - self.offset).min(data.len());
+            for (i, byte) in data[..take].iter().enumerate() {
+                self.xor_byte(self.offset + i, *byte);
+            }
+            self.offset += take;
+            data = &data[take..];
+            if self.offset == RATE {
+                keccak::p1600(&mut self.state, ROUNDS);
+                self.offset = 0;
+            }
+        }
+    }
+
+    fn finalize(mut self, domain: u8, out: &mut [u8]) {
+        self.xor_byte(self.offset, domain);
+        self.xor_byte(RATE - 1, 0x80);
+        keccak::p1600(&mut self.state, ROUNDS);
+
+        let mut pos = 0;
+        for byte in out.iter_mut() {
+            if pos == RATE {
+                keccak::p1600(&mut self.state, ROUNDS);
+                pos = 0;
+            }
+            *byte = (self.state[pos / 8] >> (8 * (pos % 8))) as u8;
+            pos += 1;
+        }
+    }
+}
+
+// Big-endian encoding without leading zeros, followed by its byte count
+fn length_encode(value: usize) -> Vec<u8> {
+    let bytes = (value as u64).to_be_bytes();
+    let skip = bytes.iter().take_while(|b| **b == 0).count();
+    let mut encoded = bytes[skip..].to_vec();
+    encoded.push((8 - skip) as u8);
+    encoded
+}
+
+fn chaining_value(chunk: &[u8]) -> [u8; CV_SIZE] {
+    let mut sponge = TurboShake128::new();
+    sponge.absorb(chunk);
+    let mut cv = [0u8; CV_SIZE];
+    sponge.finalize(0x0B, &mut cv);
+    cv
+}
+
+// CHUNK_SIZE slices of data || tail
+fn split_chunks<'a>(data: &'a [u8], tail: &[u8]) -> Vec<Cow<'a, [u8]>> {
+    let total_len = data.len() + tail.len();
+    let mut chunks = Vec::with_capacity(total_len.div_ceil(CHUNK_SIZE));
+    let mut start = 0;
+    while start < total_len {
+        let end = (start + CHUNK_SIZE).min(total_len);
+        if end <= data.len() {
+            chunks.push(Cow::Borrowed(&data[start..end]));
+        } else {
+            let mut chunk = Vec::with_capacity(end - start);
+            chunk.extend_from_slice(&data[start.min(data.len())..]);
+            chunk.extend_from_slice(&tail[start.saturating_sub(data.len())..end - data.len()]);
+            chunks.push(Cow::Owned(chunk));
+        }
+        start = end;
+    }
+    chunks
+}
+
+/// Fill `out` with KangarooTwelve(data, customization).
+pub fn k12(data: &[u8], customization: &[u8], out: &mut [u8]) {
+    let suffix = length_encode(customization.len());
+    let total_len = data.len() + customization.len() + suffix.len();
+
+    // The message is logically S = data || customization || suffix and is
+    // never materialized: chunks inside `data` are borrowed, only those
+    // reaching into the tail are copied.
+    if total_len <= CHUNK_SIZE {
+        let mut sponge = TurboShake128::new();
+        sponge.absorb(data);
+        sponge.absorb(customization);
+        sponge.absorb(&suffix);
+        sponge.finalize(0x07, out);
+        return;
+    }
+
+    let mut tail = Vec::with_capacity(customization.len() + suffix.len());
+    tail.extend_from_slice(customization);
+    tail.extend_from_slice(&suffix);
+    let chunks = split_chunks(data, &tail);
+
+    let (first, leaves) = chunks.split_first().expect("input spans several chunks");
+    let cvs: Vec<[u8; CV_SIZE]> = if leaves.len() >= PARALLEL_MIN_CHUNKS {
+        crate::threads::install(|| leaves.par_iter().map(|chunk| chaining_value(chunk)).collect())
+    } else {
+        leaves.iter().map(|chunk| chaining_value(chunk)).collect()
+    };
+
+    let mut sponge = TurboShake128::new();
+    sponge.absorb(first);
+    sponge.absorb(&[0x03, 0, 0, 0, 0, 0, 0, 0]);
+    for cv in &cvs {
+        sponge.absorb(cv);
+    }
+    sponge.absorb(&length_encode(cvs.len()));
+    sponge.absorb(&[0xFF, 0xFF]);
+    sponge.finalize(0x06, out);
+}
@@ -0,0 +1,589 @@
+// Persistent cache of derived keypairs, encrypted at rest.
+//
+// Entries are sealed with XChaCha20-Poly1305 under a node cache key, with the
+// cache entry name bound as associated data so files can't be swapped.
+//
+// The cache is off until configure() is called with a directory. The cache
+// key is passed explicitly or, failing that, read once from the environment:
+//   BASTILLE_KEY_CACHE_KEY         64 hex chars (32 raw bytes), or
+//   BASTILLE_KEY_CACHE_PASSPHRASE  stretched with Argon2id and a per-directory salt.
+// Without a key, nothing is read from or written to disk: keys are derived
+// from the seed anyway, and secret keys must never be stored in plaintext.
+//
+// Before it encrypted anything, the cache kept random keypairs in plaintext
+// ([pk_len:4][pk][sk]), possibly the only copy of a funded key. Once it has a
+// key, configure() seals each such entry of its directory into a <name>.legacy
+// file (same format, never evicted or purged) and only then wipes the
+// plaintext. Without a key they stay as they are; see legacy_entries().
+
+use crate::keygen::Algorithm;
+use argon2::Argon2;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use rand::RngCore;
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, RwLock};
+use std::time::SystemTime;
+use zeroize::Zeroizing;
+
+// (public key, secret key) bytes as stored in the key caches
+pub type KeypairBytes = (Vec<u8>, Zeroizing<Vec<u8>>);
+
+const MAGIC: [u8; 4] = *b"BKYC";
+const FORMAT_VERSION: u8 = 2;
+const HEADER_LEN: usize = 6;
+const NONCE_LEN: usize = 24;
+const CHECKSUM_LEN: usize = 32;
+const SALT_FILE: &str = "cache.salt";
+const DIR_LOCK_FILE: &str = "cache.lock";
+const SALT_LEN: usize = 16;
+const DEFAULT_MAX_ENTRIES: usize = 4096;
+const DEFAULT_MAX_BYTES: u64 = 64 * 1024 * 1024;
+
+const DEFAULT_MEMORY_ENTRIES: usize = 256;
+
+// Global cache for deterministic key generation (in-memory L1 in front of the files)
+lazy_static::lazy_static! {
+    static ref DETERMINISTIC_CACHE: Mutex<MemoryCache> = Mutex::new(MemoryCache::new(DEFAULT_MEMORY_ENTRIES));
+    static ref CONFIG: RwLock<Option<CacheConfig>> = RwLock::new(None);
+}
+
+static MEMORY_HITS: AtomicU64 = AtomicU64::new(0);
+static DISK_HITS: AtomicU64 = AtomicU64::new(0);
+static MISSES: AtomicU64 = AtomicU64::new(0);
+
+// Bounded map evicting the least recently used entry. Capacities are small
+// (hundreds), so a linear scan on eviction beats maintaining a linked list.
+struct MemoryCache {
+    capacity: usize,
+    clock: u64,
+    entries: HashMap<Vec<u8>, (KeypairBytes, u64)>,
+}
+
+impl MemoryCache {
+    fn new(capacity: usize) -> Self {
+        MemoryCache { capacity, clock: 0, entries: HashMap::new() }
+    }
+
+    fn get(&mut self, cache_key: &[u8]) -> Option<KeypairBytes> {
+        self.clock += 1;
+        let (keypair, last_use) = self.entries.get_mut(cache_key)?;
+        *last_use = self.clock;
+        Some(keypair.clone())
+    }
+
+    fn insert(&mut self, cache_key: &[u8], keypair: KeypairBytes) {
+        self.clock += 1;
+        self.entries.insert(cache_key.to_vec(), (keypair, self.clock));
+        self.shrink();
+    }
+
+    fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.shrink();
+    }
+
+    fn shrink(&mut self) {
+        while self.entries.len() > self.capacity {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, (_, last_use))| *last_use)
+                .map(|(cache_key, _)| cache_key.clone());
+            match oldest {
+                Some(cache_key) => self.entries.remove(&cache_key),
+                None => break,
+            };
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CacheStats {
+    pub memory_hits: u64,
+    pub disk_hits: u64,
+    pub misses: u64,
+    pub memory_entries: usize,
+}
+
+#[derive(Clone)]
+struct CacheConfig {
+    dir: String,
+    cipher: XChaCha20Poly1305,
+    limits: CacheLimits,
+}
+
+// Bounds on the caches; the least recently used entries go first
+#[derive(Clone, Copy, Debug)]
+pub struct CacheLimits {
+    pub max_entries: usize,
+    pub max_bytes: u64,
+    pub memory_entries: usize,
+}
+
+impl Default for CacheLimits {
+    fn default() -> Self {
+        CacheLimits {
+            max_entries: DEFAULT_MAX_ENTRIES,
+            max_bytes: DEFAULT_MAX_BYTES,
+            memory_entries: DEFAULT_MEMORY_ENTRIES,
+        }
+    }
+}
+
+pub enum CacheKey {
+    Raw(Zeroizing<Vec<u8>>),
+    Passphrase(Zeroizing<String>),
+}
+
+// Point the cache at `dir` (None disables it). Without an explicit key the
+// environment variables above are consulted, here and only here.
+pub fn configure(dir: Option<String>, key: Option<CacheKey>, limits: CacheLimits) -> Result<(), String> {
+    let config = match dir {
+        Some(dir) => {
+            let cipher = cache_cipher(&dir, key.or_else(cache_key_from_env))?;
+            migrate_plaintext_entries(&dir, &cipher)?;
+            Some(CacheConfig { dir, cipher, limits })
+        }
+        None => None,
+    };
+    *CONFIG.write().map_err(|_| "key cache lock poisoned")? = config;
+    DETERMINISTIC_CACHE
+        .lock()
+        .map_err(|_| "key cache lock poisoned")?
+        .set_capacity(limits.memory_entries);
+    Ok(())
+}
+
+/// Look a derived keypair up in memory, then on disk (promoting disk hits into memory).
+pub fn lookup(algorithm: Algorithm, cache_key: &[u8]) -> Option<KeypairBytes> {
+    if let Some(keypair) = DETERMINISTIC_CACHE.lock().ok()?.get(cache_key) {
+        MEMORY_HITS.fetch_add(1, Ordering::Relaxed);
+        return Some(keypair);
+    }
+
+    match load_persistent_cache(algorithm, cache_key) {
+        Some(keypair) => {
+            DISK_HITS.fetch_add(1, Ordering::Relaxed);
+            if let Ok(mut memory) = DETERMINISTIC_CACHE.lock() {
+                memory.insert(cache_key, keypair.clone());
+            }
+            Some(keypair)
+        }
+        None => {
+            MISSES.fetch_add(1, Ordering::Relaxed);
+            None
+        }
+    }
+}
+
+/// Remember a freshly derived keypair in memory and, when configured, on disk.
+pub fn store(algorithm: Algorithm, cache_key: &[u8], keypair: &KeypairBytes) -> Result<(), String> {
+    if let Ok(mut memory) = DETERMINISTIC_CACHE.lock() {
+        memory.insert(cache_key, keypair.clone());
+    }
+    save_persistent_cache(algorithm, cache_key, &keypair.0, &keypair.1)
+}
+
+pub fn stats() -> CacheStats {
+    CacheStats {
+        memory_hits: MEMORY_HITS.load(Ordering::Relaxed),
+        disk_hits: DISK_HITS.load(Ordering::Relaxed),
+        misses: MISSES.load(Ordering::Relaxed),
+        memory_entries: DETERMINISTIC_CACHE.lock().map(|memory| memory.entries.len()).unwrap_or(0),
+    }
+}
+
+fn current_config() -> Option<CacheConfig> {
+    CONFIG.read().ok()?.clone()
+}
+
+fn open_lock_file(path: &str) -> io::Result<File> {
+    OpenOptions::new().create(true).truncate(false).write(true).open(path)
+}
+
+// Advisory lock serializing writers of one cache file, across NIF calls and OS processes.
+// The lock is released when the returned file is dropped. Entries are locked with the
+// directory lock held shared, so removal can delete their lock files safely (the
+// salt's lock file is never removed).
+fn lock_entry(path: &str) -> io::Result<File> {
+    let lock = open_lock_file(&format!("{}.lock", path))?;
+    lock.lock()?;
+    Ok(lock)
+}
+
+// Directory-wide lock: shared while an entry lock is taken or held, exclusive while
+// entries and their lock files are removed. Never hold both in one process.
+fn lock_dir(cache_dir: &str, exclusive: bool) -> io::Result<File> {
+    let lock = open_lock_file(&format!("{}/{}", cache_dir, DIR_LOCK_FILE))?;
+    if exclusive {
+        lock.lock()?;
+    } else {
+        lock.lock_shared()?;
+    }
+    Ok(lock)
+}
+
+// Write to a temporary file, fsync it and rename it over `path`, so readers see
+// either the old contents or the new ones but never a torn file
+fn write_atomically(path: &str, data: &[u8]) -> io::Result<()> {
+    let tmp_path = format!("{}.tmp.{}", path, std::process::id());
+    let result = (|| {
+        let mut tmp = File::create(&tmp_path)?;
+        tmp.write_all(data)?;
+        tmp.sync_all()?;
+        fs::rename(&tmp_path, path)?;
+        // Persist the rename itself
+        if let Some(dir) = Path::new(path).parent() {
+            File::open(dir)?.sync_all()?;
+        }
+        Ok(())
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+    result
+}
+
+fn read_salt(salt_file: &str) -> Option<Vec<u8>> {
+    fs::read(salt_file).ok().filter(|salt| salt.len() == SALT_LEN)
+}
+
+fn load_or_create_salt(cache_dir: &str) -> Result<Vec<u8>, String> {
+    let salt_file = format!("{}/{}", cache_dir, SALT_FILE);
+    if let Some(salt) = read_salt(&salt_file) {
+        return Ok(salt);
+    }
+
+    fs::create_dir_all(cache_dir)
+        .map_err(|e| format!("Failed to create cache directory '{}': {}", cache_dir, e))?;
+    let _lock = lock_entry(&salt_file).map_err(|e| format!("Failed to lock cache salt '{}': {}", salt_file, e))?;
+    // Another process may have created it while we waited for the lock
+    if let Some(salt) = read_salt(&salt_file) {
+        return Ok(salt);
+    }
+
+    let mut salt = vec![0u8; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+    write_atomically(&salt_file, &salt).map_err(|e| format!("Failed to write cache salt '{}': {}", salt_file, e))?;
+    Ok(salt)
+}
+
+fn cache_key_from_env() -> Option<CacheKey> {
+    if let Ok(hex_key) = std::env::var("BASTILLE_KEY_CACHE_KEY") {
+        // A malformed hex key becomes an empty key, rejected by cache_cipher
+        return Some(CacheKey::Raw(Zeroizing::new(hex::decode(hex_key.trim()).unwrap_or_default())));
+    }
+    std::env::var("BASTILLE_KEY_CACHE_PASSPHRASE")
+        .ok()
+        .map(|passphrase| CacheKey::Passphrase(Zeroizing::new(passphrase)))
+}
+
+fn cache_cipher(cache_dir: &str, key: Option<CacheKey>) -> Result<XChaCha20Poly1305, String> {
+    match key {
+        Some(CacheKey::Raw(key)) => {
+            XChaCha20Poly1305::new_from_slice(&key).map_err(|_| "key cache key must be 32 bytes".to_string())
+        }
+        Some(CacheKey::Passphrase(passphrase)) => {
+            let salt = load_or_create_salt(cache_dir)?;
+            let mut key = Zeroizing::new([0u8; 32]);
+            Argon2::default()
+                .hash_password_into(passphrase.as_bytes(), &salt, &mut *key)
+                .map_err(|e| format!("Failed to derive cache key: {}", e))?;
+            Ok(XChaCha20Poly1305::new((&*key).into()))
+        }
+        None => Err("no key cache key: pass :key or :passphrase, or set BASTILLE_KEY_CACHE_KEY".to_string()),
+    }
+}
+
+// Entry layout (format version 2):
+//   magic "BKYC" | version u8 | algorithm id u8 | nonce 24 | ciphertext | blake3(all previous bytes)
+// The header is also bound into the AEAD associated data. Version 1 entries
+// are headerless (nonce | ciphertext) and get rewritten on first read.
+fn entry_aad(cache_key: &[u8], header: &[u8]) -> Vec<u8> {
+    [cache_key, header].concat()
+}
+
+fn seal_entry(
+    cipher: &XChaCha20Poly1305,
+    algorithm: Algorithm,
+    cache_key: &[u8],
+    pk_bytes: &[u8],
+    sk_bytes: &[u8],
+) -> Result<Vec<u8>, String> {
+    let mut plaintext = Zeroizing::new(Vec::with_capacity(4 + pk_bytes.len() + sk_bytes.len()));
+    plaintext.extend_from_slice(&(pk_bytes.len() as u32).to_le_bytes());
+    plaintext.extend_from_slice(pk_bytes);
+    plaintext.extend_from_slice(sk_bytes);
+
+    let header = [MAGIC[0], MAGIC[1], MAGIC[2], MAGIC[3], FORMAT_VERSION, algorithm.id()];
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let aad = entry_aad(cache_key, &header);
+    let ciphertext = cipher
+        .encrypt(&nonce, Payload { msg: &plaintext[..], aad: &aad })
+        .map_err(|_| "Failed to encrypt cache entry".to_string())?;
+
+    let mut data = Vec::with_capacity(HEADER_LEN + NONCE_LEN + ciphertext.len() + CHECKSUM_LEN);
+    data.extend_from_slice(&header);
+    data.extend_from_slice(&nonce);
+    data.extend_from_slice(&ciphertext);
+    let checksum = blake3::hash(&data);
+    data.extend_from_slice(checksum.as_bytes());
+    Ok(data)
+}
+
+fn open_entry(
+    cipher: &XChaCha20Poly1305,
+    algorithm: Algorithm,
+    cache_key: &[u8],
+    data: &[u8],
+) -> Option<Zeroizing<Vec<u8>>> {
+    if data.len() < HEADER_LEN + NONCE_LEN + CHECKSUM_LEN {
+        return None;
+    }
+    let (body, checksum) = data.split_at(data.len() - CHECKSUM_LEN);
+    if blake3::hash(body) != blake3::Hash::from_bytes(checksum.try_into().ok()?) {
+        return None;
+    }
+    let (header, sealed) = body.split_at(HEADER_LEN);
+    if header[4] != FORMAT_VERSION || header[5] != algorithm.id() {
+        return None;
+    }
+
+    let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+    let aad = entry_aad(cache_key, header);
+    cipher
+        .decrypt(XNonce::from_slice(nonce), Payload { msg: ciphertext, aad: &aad })
+        .map(Zeroizing::new)
+        .ok()
+}
+
+fn open_legacy_entry(cipher: &XChaCha20Poly1305, cache_key: &[u8], data: &[u8]) -> Option<Zeroizing<Vec<u8>>> {
+    if data.len() < NONCE_LEN {
+        return None;
+    }
+    let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+    cipher
+        .decrypt(XNonce::from_slice(nonce), Payload { msg: ciphertext, aad: cache_key })
+        .map(Zeroizing::new)
+        .ok()
+}
+
+// Sealed payload: [pk_len:4][pk_data][sk_data]
+fn split_keypair(plaintext: &[u8]) -> Option<KeypairBytes> {
+    if plaintext.len() >= 4 {
+        let pk_len = u32::from_le_bytes([plaintext[0], plaintext[1], plaintext[2], plaintext[3]]) as usize;
+        if plaintext.len() >= 4 + pk_len {
+            let pk_data = plaintext[4..4 + pk_len].to_vec();
+            let sk_data = Zeroizing::new(plaintext[4 + pk_len..].to_vec());
+            return Some((pk_data, sk_data));
+        }
+    }
+    None
+}
+
+// Load persistent cache on startup. Corrupt or foreign entries read as a miss,
+// so the keypair is re-derived and the entry overwritten.
+fn load_persistent_cache(algorithm: Algorithm, cache_key: &[u8]) -> Option<KeypairBytes> {
+    let CacheConfig { dir: cache_dir, cipher, .. } = current_config()?;
+
+    let cache_file = format!("{}/{}.keypair", cache_dir, hex::encode(cache_key));
+    let data = fs::read(&cache_file).ok()?;
+
+    if data.starts_with(&MAGIC) {
+        let keypair = split_keypair(&open_entry(&cipher, algorithm, cache_key, &data)?)?;
+        touch(&cache_file);
+        return Some(keypair);
+    }
+
+    let keypair = split_keypair(&open_legacy_entry(&cipher, cache_key, &data)?)?;
+    // Migrate once; a failed rewrite just means the legacy entry is read again
+    let _ = save_persistent_cache(algorithm, cache_key, &keypair.0, &keypair.1);
+    Some(keypair)
+}
+
+// Save to persistent cache (no-op while the cache is not configured)
+fn save_persistent_cache(algorithm: Algorithm, cache_key: &[u8], pk_bytes: &[u8], sk_bytes: &[u8]) -> Result<(), String> {
+    let Some(CacheConfig { dir: cache_dir, cipher, limits }) = current_config() else {
+        return Ok(());
+    };
+    if let Err(e) = fs::create_dir_all(&cache_dir) {
+        return Err(format!("Failed to create cache directory '{}': {}", cache_dir, e));
+    }
+
+    let cache_file = format!("{}/{}.keypair", cache_dir, hex::encode(cache_key));
+    let data = seal_entry(&cipher, algorithm, cache_key, pk_bytes, sk_bytes)?;
+
+    let dir_lock = lock_dir(&cache_dir, false)
+        .map_err(|e| format!("Failed to lock crypto cache '{}': {}", &cache_dir, e))?;
+    let lock = lock_entry(&cache_file).map_err(|e| format!("Failed to lock crypto cache '{}': {}", &cache_file, e))?;
+    if let Err(e) = write_atomically(&cache_file, &data) {
+        return Err(format!("Failed to write crypto cache '{}': {}", &cache_file, e));
+    }
+    drop(lock);
+    drop(dir_lock);
+
+    evict(&cache_dir, limits).map_err(|e| format!("Failed to evict crypto cache entries: {}", e))?;
+    Ok(())
+}
+
+// The modification time doubles as the last-use time for LRU eviction
+fn touch(path: &str) {
+    if let Ok(file) = OpenOptions::new().write(true).open(path) {
+        let _ = file.set_modified(SystemTime::now());
+    }
+}
+
+// (path, size, last use) of every entry in the cache directory
+fn list_entries(cache_dir: &str) -> io::Result<Vec<(PathBuf, u64, SystemTime)>> {
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(cache_dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == "keypair") {
+            // Entries removed concurrently are simply skipped
+            if let Ok(metadata) = fs::metadata(&path) {
+                entries.push((path, metadata.len(), metadata.modified()?));
+            }
+        }
+    }
+    Ok(entries)
+}
+
+// Only with the directory lock held exclusively: nobody else then holds or
+// waits for the entry's lock, so its lock file can go too
+fn remove_entry(path: &Path) -> io::Result<()> {
+    fs::remove_file(path)?;
+    let mut lock_file = path.as_os_str().to_owned();
+    lock_file.push(".lock");
+    let _ = fs::remove_file(lock_file);
+    Ok(())
+}
+
+// Algorithm of a plaintext [pk_len:4][pk][sk] entry of the original cache,
+// if `data` is one with a consistent keypair
+fn plaintext_entry_algorithm(data: &[u8]) -> Option<Algorithm> {
+    if data.starts_with(&MAGIC) {
+        return None;
+    }
+    let (pk, sk) = split_keypair(data)?;
+    Algorithm::ALL.into_iter().find(|algorithm| algorithm.keys_consistent(&pk, &sk))
+}
+
+// Overwrite the file with zeros, flush that to disk, then delete it
+fn wipe_file(path: &Path, len: usize) -> io::Result<()> {
+    let mut file = OpenOptions::new().write(true).open(path)?;
+    file.write_all(&vec![0u8; len])?;
+    file.sync_all()?;
+    drop(file);
+    fs::remove_file(path)
+}
+
+// A plaintext entry of the original cache: its path, algorithm and contents
+type PlaintextEntry = (PathBuf, Algorithm, Zeroizing<Vec<u8>>);
+
+fn plaintext_entries(cache_dir: &str) -> Result<Vec<PlaintextEntry>, String> {
+    let entries = match list_entries(cache_dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(format!("Failed to list crypto cache '{}': {}", cache_dir, e)),
+    };
+    let mut plaintext = Vec::new();
+    for (path, _, _) in entries {
+        let Ok(data) = fs::read(&path).map(Zeroizing::new) else {
+            continue;
+        };
+        if let Some(algorithm) = plaintext_entry_algorithm(&data) {
+            plaintext.push((path, algorithm, data));
+        }
+    }
+    Ok(plaintext)
+}
+
+/// How many plaintext entries of the original cache `cache_dir` still holds,
+/// left there until the cache is configured with a key.
+pub fn legacy_entries(cache_dir: &str) -> Result<usize, String> {
+    plaintext_entries(cache_dir).map(|entries| entries.len())
+}
+
+// Seal every plaintext entry into <name>.legacy under its original cache key,
+// check it reads back, then wipe the plaintext
+fn migrate_plaintext_entries(cache_dir: &str, cipher: &XChaCha20Poly1305) -> Result<(), String> {
+    for (path, algorithm, data) in plaintext_entries(cache_dir)? {
+        let Some(cache_key) = path.file_stem().and_then(|stem| hex::decode(stem.to_string_lossy().as_bytes()).ok())
+        else {
+            continue;
+        };
+        let (pk, sk) = split_keypair(&data).ok_or("plaintext cache entry changed while migrating")?;
+        let sealed = seal_entry(cipher, algorithm, &cache_key, &pk, &sk)?;
+        let legacy_file = path.with_extension("legacy");
+        let failed = |e: &dyn std::fmt::Display| format!("Failed to migrate cache entry '{}': {}", path.display(), e);
+
+        write_atomically(&legacy_file.to_string_lossy(), &sealed).map_err(|e| failed(&e))?;
+        let written = fs::read(&legacy_file).map_err(|e| failed(&e))?;
+        if open_entry(cipher, algorithm, &cache_key, &written).as_deref() != Some(&*data) {
+            return Err(failed(&"sealed entry doesn't read back"));
+        }
+        match wipe_file(&path, data.len()) {
+            Ok(()) => {}
+            // Migrated concurrently by another node process
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(failed(&e)),
+        }
+    }
+    Ok(())
+}
+
+fn evict(cache_dir: &str, limits: CacheLimits) -> io::Result<()> {
+    let mut entries = list_entries(cache_dir)?;
+    let mut count = entries.len();
+    let mut bytes: u64 = entries.iter().map(|(_, size, _)| size).sum();
+    if count <= limits.max_entries && bytes <= limits.max_bytes {
+        return Ok(());
+    }
+
+    let _dir_lock = lock_dir(cache_dir, true)?;
+    entries.sort_by_key(|(_, _, last_use)| *last_use);
+    for (path, size, _) in entries {
+        if count <= limits.max_entries && bytes <= limits.max_bytes {
+            break;
+        }
+        match remove_entry(&path) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+        count -= 1;
+        bytes -= size;
+    }
+    Ok(())
+}
+
+/// Remove every cached keypair (the passphrase salt is kept) and return how many
+/// files were removed. The in-memory cache is emptied as well.
+pub fn purge() -> Result<usize, String> {
+    if let Ok(mut memory) = DETERMINISTIC_CACHE.lock() {
+        memory.entries.clear();
+    }
+    let Some(CacheConfig { dir: cache_dir, .. }) = current_config() else {
+        return Ok(0);
+    };
+    let entries = match list_entries(&cache_dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(format!("Failed to list crypto cache '{}': {}", cache_dir, e)),
+    };
+
+    let _dir_lock =
+        lock_dir(&cache_dir, true).map_err(|e| format!("Failed to lock crypto cache '{}': {}", cache_dir, e))?;
+    let mut removed = 0;
+    for (path, _, _) in entries {
+        if remove_entry(&path).is_ok() {
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
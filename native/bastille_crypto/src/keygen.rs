@@ -0,0 +1,408 @@
+// Deterministic post-quantum key generation, derivation spec "bastille-keygen-v1".
+//
+//   seed (>= 16 bytes)
+//     -> BLAKE3 derive_key("bastille keygen v1 <algorithm>", seed) in XOF mode (the DRBG)
+//     -> exactly the bytes PQClean's crypto_sign_keypair() would draw from randombytes():
+//          dilithium2: 32 bytes, expanded with SHAKE256 into (rho, rho', key)
+//          falcon512:  48 bytes, injected into the SHAKE256 keygen PRNG
+//          sphincs+:   48 bytes, SK.seed || SK.prf || PK.seed
+//     -> the keypair construction of the PQClean "clean" implementation.
+//
+// The construction below mirrors crypto_sign_keypair() of each scheme line by
+// line, calling the same exported PQClean routines, so derived keys are
+// byte-compatible with the pqcrypto sign/verify functions. Changing anything
+// here changes every derived key: bump the spec version instead.
+
+use sha3::digest::{ExtendableOutput, Update, XofReader};
+use sha3::Shake256;
+use zeroize::{Zeroize, Zeroizing};
+
+pub const SPEC_VERSION: &str = "bastille-keygen-v1";
+pub const MIN_SEED_LEN: usize = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Algorithm {
+    Dilithium2,
+    Falcon512,
+    SphincsPlus,
+}
+
+impl Algorithm {
+    pub const ALL: [Algorithm; 3] = [Algorithm::Dilithium2, Algorithm::Falcon512, Algorithm::SphincsPlus];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Algorithm::Dilithium2 => "dilithium2",
+            Algorithm::Falcon512 => "falcon512",
+            Algorithm::SphincsPlus => "sphincsplus",
+        }
+    }
+
+    pub fn public_key_bytes(self) -> usize {
+        match self {
+            Algorithm::Dilithium2 => pqcrypto_dilithium::dilithium2::public_key_bytes(),
+            Algorithm::Falcon512 => pqcrypto_falcon::falcon512::public_key_bytes(),
+            Algorithm::SphincsPlus => pqcrypto_sphincsplus::sphincsshake128fsimple::public_key_bytes(),
+        }
+    }
+
+    pub fn secret_key_bytes(self) -> usize {
+        match self {
+            Algorithm::Dilithium2 => pqcrypto_dilithium::dilithium2::secret_key_bytes(),
+            Algorithm::Falcon512 => pqcrypto_falcon::falcon512::secret_key_bytes(),
+            Algorithm::SphincsPlus => pqcrypto_sphincsplus::sphincsshake128fsimple::secret_key_bytes(),
+        }
+    }
+
+    /// Cheap layout checks between a public and a secret key: lengths, plus the
+    /// public material each secret key encoding embeds (Dilithium's rho,
+    /// SPHINCS+'s full public key, Falcon's logn headers).
+    pub fn keys_consistent(self, pk: &[u8], sk: &[u8]) -> bool {
+        if pk.len() != self.public_key_bytes() || sk.len() != self.secret_key_bytes() {
+            return false;
+        }
+        match self {
+            Algorithm::Dilithium2 => pk[..32] == sk[..32],
+            Algorithm::Falcon512 => pk[0] == 0x09 && sk[0] == 0x59,
+            Algorithm::SphincsPlus => sk.ends_with(pk),
+        }
+    }
+
+    // Stable identifier used in on-disk and wire formats
+    pub fn id(self) -> u8 {
+        match self {
+            Algorithm::Dilithium2 => 1,
+            Algorithm::Falcon512 => 2,
+            Algorithm::SphincsPlus => 3,
+        }
+    }
+
+    pub fn from_id(id: u8) -> Option<Algorithm> {
+        Algorithm::ALL.into_iter().find(|algorithm| algorithm.id() == id)
+    }
+
+    fn drbg_context(self) -> &'static str {
+        match self {
+            Algorithm::Dilithium2 => "bastille keygen v1 dilithium2",
+            Algorithm::Falcon512 => "bastille keygen v1 falcon512",
+            Algorithm::SphincsPlus => "bastille keygen v1 sphincsplus",
+        }
+    }
+}
+
+/// Derive the (public key, secret key) pair for `algorithm` from `seed`.
+/// The secret key and every intermediate secret buffer are wiped on drop.
+pub fn keypair_from_seed(algorithm: Algorithm, seed: &[u8]) -> Result<(Vec<u8>, Zeroizing<Vec<u8>>), String> {
+    if seed.len() < MIN_SEED_LEN {
+        return Err(format!("seed must be at least {} bytes", MIN_SEED_LEN));
+    }
+
+    let mut drbg = blake3::Hasher::new_derive_key(algorithm.drbg_context());
+    drbg.update(seed);
+    let mut drbg = drbg.finalize_xof();
+
+    match algorithm {
+        Algorithm::Dilithium2 => {
+            let mut randomness = Zeroizing::new([0u8; dilithium2::SEEDBYTES]);
+            drbg.fill(&mut *randomness);
+            Ok(dilithium2::keypair(&randomness))
+        }
+        Algorithm::Falcon512 => {
+            let mut randomness = Zeroizing::new([0u8; falcon512::SEEDBYTES]);
+            drbg.fill(&mut *randomness);
+            falcon512::keypair(&randomness)
+        }
+        Algorithm::SphincsPlus => {
+            let mut randomness = Zeroizing::new([0u8; sphincsplus::SEEDBYTES]);
+            drbg.fill(&mut *randomness);
+            sphincsplus::keypair(&randomness)
+        }
+    }
+}
+
+mod dilithium2 {
+    // Mirrors PQCLEAN_DILITHIUM2_CLEAN_crypto_sign_keypair (sign.c)
+    use super::*;
+
+    pub const SEEDBYTES: usize = 32;
+    const CRHBYTES: usize = 64;
+    const TRBYTES: usize = 64;
+    const N: usize = 256;
+    const K: usize = 4;
+    const L: usize = 4;
+    const PUBLICKEYBYTES: usize = 1312;
+    const SECRETKEYBYTES: usize = 2560;
+
+    #[repr(C)]
+    #[derive(Clone, Copy)]
+    struct Poly {
+        coeffs: [i32; N],
+    }
+
+    #[repr(C)]
+    #[derive(Clone, Copy)]
+    struct PolyVecL {
+        vec: [Poly; L],
+    }
+
+    #[repr(C)]
+    #[derive(Clone, Copy)]
+    struct PolyVecK {
+        vec: [Poly; K],
+    }
+
+    impl Zeroize for PolyVecL {
+        fn zeroize(&mut self) {
+            self.vec.iter_mut().for_each(|poly| poly.coeffs.zeroize());
+        }
+    }
+
+    impl Zeroize for PolyVecK {
+        fn zeroize(&mut self) {
+            self.vec.iter_mut().for_each(|poly| poly.coeffs.zeroize());
+        }
+    }
+
+    const ZERO_POLY: Poly = Poly { coeffs: [0; N] };
+    const ZERO_L: PolyVecL = PolyVecL { vec: [ZERO_POLY; L] };
+    const ZERO_K: PolyVecK = PolyVecK { vec: [ZERO_POLY; K] };
+
+    extern "C" {
+        fn PQCLEAN_DILITHIUM2_CLEAN_polyvec_matrix_expand(mat: *mut PolyVecL, rho: *const u8);
+        fn PQCLEAN_DILITHIUM2_CLEAN_polyvecl_uniform_eta(v: *mut PolyVecL, seed: *const u8, nonce: u16);
+        fn PQCLEAN_DILITHIUM2_CLEAN_polyveck_uniform_eta(v: *mut PolyVecK, seed: *const u8, nonce: u16);
+        fn PQCLEAN_DILITHIUM2_CLEAN_polyvecl_ntt(v: *mut PolyVecL);
+        fn PQCLEAN_DILITHIUM2_CLEAN_polyvec_matrix_pointwise_montgomery(
+            t: *mut PolyVecK,
+            mat: *const PolyVecL,
+            v: *const PolyVecL,
+        );
+        fn PQCLEAN_DILITHIUM2_CLEAN_polyveck_reduce(v: *mut PolyVecK);
+        fn PQCLEAN_DILITHIUM2_CLEAN_polyveck_invntt_tomont(v: *mut PolyVecK);
+        fn PQCLEAN_DILITHIUM2_CLEAN_polyveck_add(w: *mut PolyVecK, u: *const PolyVecK, v: *const PolyVecK);
+        fn PQCLEAN_DILITHIUM2_CLEAN_polyveck_caddq(v: *mut PolyVecK);
+        fn PQCLEAN_DILITHIUM2_CLEAN_polyveck_power2round(v1: *mut PolyVecK, v0: *mut PolyVecK, v: *const PolyVecK);
+        fn PQCLEAN_DILITHIUM2_CLEAN_pack_pk(pk: *mut u8, rho: *const u8, t1: *const PolyVecK);
+        fn PQCLEAN_DILITHIUM2_CLEAN_pack_sk(
+            sk: *mut u8,
+            rho: *const u8,
+            tr: *const u8,
+            key: *const u8,
+            t0: *const PolyVecK,
+            s1: *const PolyVecL,
+            s2: *const PolyVecK,
+        );
+    }
+
+    fn shake256(input: &[u8], output: &mut [u8]) {
+        let mut xof = Shake256::default();
+        xof.update(input);
+        xof.finalize_xof().read(output);
+    }
+
+    pub fn keypair(randomness: &[u8; SEEDBYTES]) -> (Vec<u8>, Zeroizing<Vec<u8>>) {
+        let mut seedbuf = Zeroizing::new([0u8; 2 * SEEDBYTES + CRHBYTES]);
+        shake256(randomness, &mut *seedbuf);
+        let rho = &seedbuf[..SEEDBYTES];
+        let rhoprime = &seedbuf[SEEDBYTES..SEEDBYTES + CRHBYTES];
+        let key = &seedbuf[SEEDBYTES + CRHBYTES..];
+
+        // Boxed: the matrix alone is 16 KiB and NIF threads have small stacks
+        let mut mat = Box::new([ZERO_L; K]);
+        let mut s1 = Box::new(ZERO_L);
+        let mut s2 = Box::new(ZERO_K);
+        let mut t1 = Box::new(ZERO_K);
+        let mut t0 = Box::new(ZERO_K);
+        let mut pk = vec![0u8; PUBLICKEYBYTES];
+        let mut sk = Zeroizing::new(vec![0u8; SECRETKEYBYTES]);
+        let mut tr = [0u8; TRBYTES];
+        let mut s1hat = Box::new(ZERO_L);
+
+        // SAFETY: every pointer refers to a live, correctly sized buffer whose
+        // layout matches the PQClean structs (params.h: N = 256, K = L = 4).
+        unsafe {
+            PQCLEAN_DILITHIUM2_CLEAN_polyvec_matrix_expand(mat.as_mut_ptr(), rho.as_ptr());
+
+            PQCLEAN_DILITHIUM2_CLEAN_polyvecl_uniform_eta(&mut *s1, rhoprime.as_ptr(), 0);
+            PQCLEAN_DILITHIUM2_CLEAN_polyveck_uniform_eta(&mut *s2, rhoprime.as_ptr(), L as u16);
+
+            *s1hat = *s1;
+            PQCLEAN_DILITHIUM2_CLEAN_polyvecl_ntt(&mut *s1hat);
+            PQCLEAN_DILITHIUM2_CLEAN_polyvec_matrix_pointwise_montgomery(&mut *t1, mat.as_ptr(), &*s1hat);
+            PQCLEAN_DILITHIUM2_CLEAN_polyveck_reduce(&mut *t1);
+            PQCLEAN_DILITHIUM2_CLEAN_polyveck_invntt_tomont(&mut *t1);
+
+            let t1_ptr: *mut PolyVecK = &mut *t1;
+            PQCLEAN_DILITHIUM2_CLEAN_polyveck_add(t1_ptr, t1_ptr, &*s2);
+
+            PQCLEAN_DILITHIUM2_CLEAN_polyveck_caddq(t1_ptr);
+            PQCLEAN_DILITHIUM2_CLEAN_polyveck_power2round(t1_ptr, &mut *t0, t1_ptr);
+            PQCLEAN_DILITHIUM2_CLEAN_pack_pk(pk.as_mut_ptr(), rho.as_ptr(), &*t1);
+
+            shake256(&pk, &mut tr);
+            PQCLEAN_DILITHIUM2_CLEAN_pack_sk(
+                sk.as_mut_ptr(),
+                rho.as_ptr(),
+                tr.as_ptr(),
+                key.as_ptr(),
+                &*t0,
+                &*s1,
+                &*s2,
+            );
+        }
+
+        // s1, s2 and t0 are the secret key; s1hat is its NTT form
+        s1.zeroize();
+        s2.zeroize();
+        s1hat.zeroize();
+        t0.zeroize();
+
+        (pk, sk)
+    }
+}
+
+mod falcon512 {
+    // Mirrors PQCLEAN_FALCON512_CLEAN_crypto_sign_keypair (pqclean.c)
+    use zeroize::{Zeroize, Zeroizing};
+
+    pub const SEEDBYTES: usize = 48;
+    const LOGN: u32 = 9;
+    const N: usize = 1 << LOGN;
+    const PUBLICKEYBYTES: usize = 897;
+    const SECRETKEYBYTES: usize = 1281;
+    const KEYGEN_TEMP_BYTES: usize = 14336;
+
+    // shake256incctx from PQClean's common fips202.h
+    #[repr(C)]
+    struct Shake256IncCtx {
+        ctx: [u64; 26],
+    }
+
+    extern "C" {
+        fn shake256_inc_init(state: *mut Shake256IncCtx);
+        fn shake256_inc_absorb(state: *mut Shake256IncCtx, input: *const u8, inlen: usize);
+        fn shake256_inc_finalize(state: *mut Shake256IncCtx);
+        fn shake256_inc_ctx_release(state: *mut Shake256IncCtx);
+
+        fn PQCLEAN_FALCON512_CLEAN_keygen(
+            rng: *mut Shake256IncCtx,
+            f: *mut i8,
+            g: *mut i8,
+            big_f: *mut i8,
+            big_g: *mut i8,
+            h: *mut u16,
+            logn: u32,
+            tmp: *mut u8,
+        );
+        fn PQCLEAN_FALCON512_CLEAN_trim_i8_encode(
+            out: *mut u8,
+            max_out_len: usize,
+            x: *const i8,
+            logn: u32,
+            bits: u32,
+        ) -> usize;
+        fn PQCLEAN_FALCON512_CLEAN_modq_encode(out: *mut u8, max_out_len: usize, x: *const u16, logn: u32) -> usize;
+
+        static PQCLEAN_FALCON512_CLEAN_max_fg_bits: [u8; 11];
+        static PQCLEAN_FALCON512_CLEAN_max_FG_bits: [u8; 11];
+    }
+
+    pub fn keypair(randomness: &[u8; SEEDBYTES]) -> Result<(Vec<u8>, Zeroizing<Vec<u8>>), String> {
+        // Wrapped so the secret polynomials are wiped on every return path
+        let mut f = Zeroizing::new([0i8; N]);
+        let mut g = Zeroizing::new([0i8; N]);
+        let mut big_f = Zeroizing::new([0i8; N]);
+        let mut h = [0u16; N];
+        // keygen requires 64-bit alignment for its scratch space
+        let mut tmp = Zeroizing::new(vec![0u64; KEYGEN_TEMP_BYTES / 8]);
+        let mut pk = vec![0u8; PUBLICKEYBYTES];
+        let mut sk = Zeroizing::new(vec![0u8; SECRETKEYBYTES]);
+
+        // SAFETY: buffers are sized as in pqclean.c for logn = 9, and the
+        // SHAKE context is initialized before use and released afterwards.
+        unsafe {
+            let mut rng = Shake256IncCtx { ctx: [0; 26] };
+            shake256_inc_init(&mut rng);
+            shake256_inc_absorb(&mut rng, randomness.as_ptr(), randomness.len());
+            shake256_inc_finalize(&mut rng);
+            PQCLEAN_FALCON512_CLEAN_keygen(
+                &mut rng,
+                f.as_mut_ptr(),
+                g.as_mut_ptr(),
+                big_f.as_mut_ptr(),
+                std::ptr::null_mut(),
+                h.as_mut_ptr(),
+                LOGN,
+                tmp.as_mut_ptr() as *mut u8,
+            );
+            shake256_inc_ctx_release(&mut rng);
+            rng.ctx.zeroize();
+
+            let fg_bits = PQCLEAN_FALCON512_CLEAN_max_fg_bits[LOGN as usize] as u32;
+            let big_fg_bits = PQCLEAN_FALCON512_CLEAN_max_FG_bits[LOGN as usize] as u32;
+
+            sk[0] = 0x50 + LOGN as u8;
+            let mut u = 1;
+            for (poly, bits) in [(&*f, fg_bits), (&*g, fg_bits), (&*big_f, big_fg_bits)] {
+                let v = PQCLEAN_FALCON512_CLEAN_trim_i8_encode(
+                    sk.as_mut_ptr().add(u),
+                    SECRETKEYBYTES - u,
+                    poly.as_ptr(),
+                    LOGN,
+                    bits,
+                );
+                if v == 0 {
+                    return Err("falcon512 secret key encoding failed".to_string());
+                }
+                u += v;
+            }
+            if u != SECRETKEYBYTES {
+                return Err("falcon512 secret key has unexpected length".to_string());
+            }
+
+            pk[0] = LOGN as u8;
+            let v = PQCLEAN_FALCON512_CLEAN_modq_encode(pk.as_mut_ptr().add(1), PUBLICKEYBYTES - 1, h.as_ptr(), LOGN);
+            if v != PUBLICKEYBYTES - 1 {
+                return Err("falcon512 public key encoding failed".to_string());
+            }
+        }
+
+        Ok((pk, sk))
+    }
+}
+
+mod sphincsplus {
+    // PQClean exposes seeded key generation for SPHINCS+ directly
+    use zeroize::Zeroizing;
+
+    pub const SEEDBYTES: usize = 48;
+    const PUBLICKEYBYTES: usize = 32;
+    const SECRETKEYBYTES: usize = 64;
+
+    extern "C" {
+        fn PQCLEAN_SPHINCSSHAKE128FSIMPLE_CLEAN_crypto_sign_seed_keypair(
+            pk: *mut u8,
+            sk: *mut u8,
+            seed: *const u8,
+        ) -> i32;
+    }
+
+    pub fn keypair(randomness: &[u8; SEEDBYTES]) -> Result<(Vec<u8>, Zeroizing<Vec<u8>>), String> {
+        let mut pk = vec![0u8; PUBLICKEYBYTES];
+        let mut sk = Zeroizing::new(vec![0u8; SECRETKEYBYTES]);
+
+        // SAFETY: output buffers have the sizes declared in api.h
+        let rc = unsafe {
+            PQCLEAN_SPHINCSSHAKE128FSIMPLE_CLEAN_crypto_sign_seed_keypair(
+                pk.as_mut_ptr(),
+                sk.as_mut_ptr(),
+                randomness.as_ptr(),
+            )
+        };
+        if rc != 0 {
+            return Err("sphincs+ seed keypair failed".to_string());
+        }
+
+        Ok((pk, sk))
+    }
+}
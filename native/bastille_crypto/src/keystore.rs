@@ -0,0 +1,113 @@
+// Passphrase-protected container for moving a keypair between machines.
+//
+//   magic "BKEX" | version u8 | algorithm id u8 | argon2 m_cost u32 | t_cost u32 | p_cost u32
+//   | salt 16 | nonce 24 | XChaCha20-Poly1305([pk_len u32][pk][sk])
+//
+// All integers are little-endian. Everything before the ciphertext is bound as
+// associated data, and the Argon2id parameters travel with the blob so they can
+// be raised later without breaking old exports.
+
+use crate::keygen::Algorithm;
+use argon2::{Algorithm as Argon2Algorithm, Argon2, Params, Version};
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use rand::RngCore;
+use zeroize::Zeroizing;
+
+const MAGIC: [u8; 4] = *b"BKEX";
+const FORMAT_VERSION: u8 = 1;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+const HEADER_LEN: usize = 4 + 1 + 1 + 12 + SALT_LEN + NONCE_LEN;
+
+// 64 MiB, 3 passes: slow enough to resist offline guessing of a stolen export
+const M_COST_KIB: u32 = 64 * 1024;
+const T_COST: u32 = 3;
+const P_COST: u32 = 1;
+// Refuse imports asking for more than 1 GiB so a crafted blob can't exhaust memory
+const MAX_M_COST_KIB: u32 = 1024 * 1024;
+
+fn algorithm_for_keys(pk_len: usize, sk_len: usize) -> Option<Algorithm> {
+    Algorithm::ALL
+        .into_iter()
+        .find(|algorithm| algorithm.public_key_bytes() == pk_len && algorithm.secret_key_bytes() == sk_len)
+}
+
+fn derive_key(passphrase: &[u8], salt: &[u8], m_cost: u32, t_cost: u32, p_cost: u32) -> Result<XChaCha20Poly1305, String> {
+    let params = Params::new(m_cost, t_cost, p_cost, Some(32)).map_err(|e| format!("invalid Argon2 parameters: {}", e))?;
+    let mut key = Zeroizing::new([0u8; 32]);
+    Argon2::new(Argon2Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(passphrase, salt, &mut *key)
+        .map_err(|e| format!("key derivation failed: {}", e))?;
+    Ok(XChaCha20Poly1305::new((&*key).into()))
+}
+
+/// Seal `pk`/`sk` under `passphrase`. Fails for keys of an unsupported algorithm.
+pub fn export_keypair(pk: &[u8], sk: &[u8], passphrase: &[u8]) -> Result<Vec<u8>, String> {
+    let algorithm = algorithm_for_keys(pk.len(), sk.len()).ok_or("unsupported key lengths")?;
+
+    let mut salt = [0u8; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+
+    let mut blob = Vec::with_capacity(HEADER_LEN + 4 + pk.len() + sk.len() + 16);
+    blob.extend_from_slice(&MAGIC);
+    blob.push(FORMAT_VERSION);
+    blob.push(algorithm.id());
+    for param in [M_COST_KIB, T_COST, P_COST] {
+        blob.extend_from_slice(&param.to_le_bytes());
+    }
+    blob.extend_from_slice(&salt);
+    blob.extend_from_slice(&nonce);
+
+    let mut plaintext = Zeroizing::new(Vec::with_capacity(4 + pk.len() + sk.len()));
+    plaintext.extend_from_slice(&(pk.len() as u32).to_le_bytes());
+    plaintext.extend_from_slice(pk);
+    plaintext.extend_from_slice(sk);
+
+    let cipher = derive_key(passphrase, &salt, M_COST_KIB, T_COST, P_COST)?;
+    let ciphertext = cipher
+        .encrypt(&nonce, Payload { msg: &plaintext[..], aad: &blob })
+        .map_err(|_| "encryption failed".to_string())?;
+    blob.extend_from_slice(&ciphertext);
+    Ok(blob)
+}
+
+/// Open a blob from export_keypair, returning (pk, sk).
+pub fn import_keypair(blob: &[u8], passphrase: &[u8]) -> Result<(Vec<u8>, Zeroizing<Vec<u8>>), String> {
+    if blob.len() < HEADER_LEN || blob[..4] != MAGIC {
+        return Err("not a keypair export".to_string());
+    }
+    if blob[4] != FORMAT_VERSION {
+        return Err(format!("unsupported export version {}", blob[4]));
+    }
+    let (header, ciphertext) = blob.split_at(HEADER_LEN);
+    let param = |offset: usize| u32::from_le_bytes([header[offset], header[offset + 1], header[offset + 2], header[offset + 3]]);
+    let (m_cost, t_cost, p_cost) = (param(6), param(10), param(14));
+    if m_cost > MAX_M_COST_KIB {
+        return Err("Argon2 memory cost too large".to_string());
+    }
+    let salt = &header[18..18 + SALT_LEN];
+    let nonce = &header[18 + SALT_LEN..];
+
+    let cipher = derive_key(passphrase, salt, m_cost, t_cost, p_cost)?;
+    let plaintext = cipher
+        .decrypt(XNonce::from_slice(nonce), Payload { msg: ciphertext, aad: header })
+        .map(Zeroizing::new)
+        .map_err(|_| "wrong passphrase or corrupted export".to_string())?;
+
+    if plaintext.len() < 4 {
+        return Err("truncated keypair".to_string());
+    }
+    let pk_len = u32::from_le_bytes([plaintext[0], plaintext[1], plaintext[2], plaintext[3]]) as usize;
+    if plaintext.len() < 4 + pk_len {
+        return Err("truncated keypair".to_string());
+    }
+    let pk = plaintext[4..4 + pk_len].to_vec();
+    let sk = Zeroizing::new(plaintext[4 + pk_len..].to_vec());
+
+    match algorithm_for_keys(pk.len(), sk.len()) {
+        Some(algorithm) if algorithm.id() == header[5] => Ok((pk, sk)),
+        _ => Err("keypair does not match the exported algorithm".to_string()),
+    }
+}
@@ -1,348 +1,4756 @@
-use rustler::{Binary, Env, NewBinary, NifResult};
+use rustler::{Atom, Binary, Encoder, Env, LocalPid, NewBinary, NifResult, OwnedBinary, ResourceArc, Term};
 use pqcrypto_traits::sign::{PublicKey, SecretKey, DetachedSignature};
 use pqcrypto_dilithium::dilithium2;
 use pqcrypto_falcon::falcon512;
 use pqcrypto_sphincsplus::sphincsshake128fsimple as sphincsplus_shake_128f;
-use blake3;
-use std::collections::HashMap;
+use hmac::{Hmac, Mac};
+use sha2::{Sha256, Sha512};
+use sha3::{Digest, Keccak256, Sha3_256, Sha3_512, Shake128, Shake256};
+use sha3::digest::{ExtendableOutput, Update, XofReader};
+use rayon::prelude::*;
 use std::sync::Mutex;
+use std::time::Instant;
+use zeroize::{Zeroize, Zeroizing};
 use std::fs;
-use std::path::Path;
+use std::io::Read;
 
-// Global cache for deterministic key generation (in-memory)
-lazy_static::lazy_static! {
-    static ref DETERMINISTIC_CACHE: Mutex<HashMap<Vec<u8>, (Vec<u8>, Vec<u8>)>> = Mutex::new(HashMap::new());
+mod address;
+mod admission;
+mod archive;
+mod at_rest;
+mod attestation;
+mod backup;
+mod base16;
+mod base64url;
+mod beacon;
+mod bench;
+mod bloom;
+mod borsh;
+mod cbor;
+mod compression;
+mod cpu;
+mod cuckoo;
+mod dkg;
+mod evidence;
+mod finality;
+mod gossip;
+mod hd;
+mod header;
+mod incremental_merkle;
+mod jcs;
+mod jmt;
+mod k12;
+mod key_cache;
+mod keygen;
+mod keystore;
+mod light_client;
+#[cfg(feature = "lmdb")]
+mod lmdb;
+mod merkle;
+#[cfg(feature = "wasm")]
+mod metering;
+mod mmr;
+mod mpt;
+mod msgpack;
+mod multiformats;
+mod poseidon;
+mod pow;
+#[cfg(feature = "randomx")]
+mod randomx;
+mod public_key;
+mod qc;
+#[cfg(feature = "remote-signer")]
+mod remote_signer;
+mod rng;
+mod rlp;
+#[cfg(feature = "rocksdb")]
+mod rocks;
+mod rotation;
+mod scratch;
+mod secret_handle;
+mod sign_pool;
+mod smt;
+mod ssz;
+mod snapshot;
+mod state_diff;
+mod state_tree;
+mod stats;
+mod target;
+#[cfg(any(feature = "rocksdb", feature = "lmdb"))]
+mod storage;
+mod threads;
+mod tx;
+mod varint;
+mod vdf;
+mod verify_cache;
+mod verify_session;
+#[cfg(feature = "verkle")]
+mod verkle;
+mod vote;
+mod vrf;
+mod wal;
+#[cfg(feature = "wasm")]
+mod wasm;
+
+#[cfg(all(feature = "mimalloc", feature = "jemalloc"))]
+compile_error!("the mimalloc and jemalloc features are mutually exclusive");
+
+#[cfg(all(feature = "simd", feature = "portable"))]
+compile_error!("the simd and portable features are mutually exclusive; build portable with --no-default-features");
+
+#[cfg(feature = "mimalloc")]
+#[global_allocator]
+static GLOBAL: mimalloc::MiMalloc = mimalloc::MiMalloc;
+
+#[cfg(feature = "jemalloc")]
+#[global_allocator]
+static GLOBAL: tikv_jemallocator::Jemalloc = tikv_jemallocator::Jemalloc;
+
+use key_cache::KeypairBytes;
+use public_key::{ParsedPublicKey, PublicKeyHandle};
+use secret_handle::SecretKeyHandle;
+use verify_session::VerifySession;
+
+// Load resources function
+rustler::atoms! {
+    ok,
+    error,
+    cont,
+    key,
+    passphrase,
+    max_entries,
+    max_bytes,
+    memory_entries,
+    memory_hits,
+    disk_hits,
+    misses,
+    dilithium2,
+    falcon512,
+    sphincsplus,
+    algorithm,
+    old_public_key,
+    new_public_key,
+    effective_height,
+    reason,
+    failures,
+    parallel,
+    hits,
+    entries,
+    capacity,
+    busy,
+    avx2,
+    avx512,
+    neon,
+    simd,
+    backends,
+    blake3,
+    keygen,
+    sign,
+    verify,
+    iterations,
+    min_ns,
+    median_ns,
+    p99_ns,
+    ops_per_sec,
+    calls,
+    total_ns,
+    backend_key = "backend",
+    sparse,
+    jellyfish,
+    blocks,
+    state,
+    tx_index,
+    put,
+    delete,
+    from,
+    to,
+    reverse,
+    rocksdb,
+    lmdb,
+    map_size,
+    sync,
+    always,
+    manual,
+    interval,
+    archive_mode = "archive",
+    full,
+    light,
+    height,
+    compress,
+    encryption_key,
+    retired_keys,
+    text,
+    tag,
+    max_depth,
+    max_size,
+    boolean,
+    uint,
+    bytes,
+    byte_list,
+    bitvector,
+    bitlist,
+    vector,
+    list,
+    container,
+    bin,
+    ext,
+    hex,
+    case_ = "case",
+    lower,
+    upper,
+    mixed,
+    prefix,
+    optional,
+    padding,
+    strict,
+    sha2_256,
+    raw,
+    dag_pb,
+    dag_cbor,
+    base,
+    base32,
+    base58btc,
+    base16,
+    base64url,
+    chain_id,
+    amount,
+    fee,
+    nonce,
+    timestamp,
+    data,
+    signature_type,
+    signature,
+    post_quantum_2_of_3,
+    coinbase,
+    dilithium,
+    falcon,
+    sphincs,
+    type_ = "type",
+    exhausted,
+    max_factor,
+    max_target,
+    running,
+    done,
+    previous_hash,
+    merkle_root,
+    bits,
+    producer_algorithm,
+    producer_key,
+    hash,
+    now,
+    max_future_drift,
+    expected_bits,
+    require_signature,
+    min_fee,
+    nonces,
+    max_nonce_gap,
+    public_keys,
+    public_key,
+    round,
+    block_id,
+    block_ids,
+    header,
+    vote,
+    validator_set_root,
+    finalized,
+    head,
+    max_headers,
+    max_memory,
+    max_table_elements,
+    start_gas,
+    storage,
+    storage_timeout,
+    wasm_storage,
+    aborted,
+    get,
 }
 
-// Get cache directory path (environment-aware)
-fn get_cache_dir() -> String {
-    // Use same storage base as the rest of the application
-    std::env::var("BASTILLE_STORAGE_BASE_PATH")
-        .unwrap_or_else(|_| "data/test".to_string()) + "/key_cache"
+// Count calls, failures and time spent in a NIF (see stats.rs). The call is
+// recorded when the returned tracker drops, as a failure unless the NIF
+// returned through its ok(), finish() or done().
+macro_rules! tracked {
+    ($name:literal) => {{
+        static COUNTERS: stats::NifCounters = stats::NifCounters::new($name);
+        inventory::submit!(stats::Registered(&COUNTERS));
+        stats::track(&COUNTERS)
+    }};
 }
 
-// Load persistent cache on startup
-fn load_persistent_cache(cache_key: &[u8]) -> Option<(Vec<u8>, Vec<u8>)> {
-    let cache_dir = get_cache_dir();
-    if !Path::new(&cache_dir).exists() {
-        return None;
+// {:error, reason} for a failure reported by one of the modules as a message
+fn term_error(e: String) -> rustler::Error {
+    rustler::Error::Term(Box::new(e))
+}
+
+// Map the algorithm atoms used across the API (:dilithium2, :falcon512, :sphincsplus)
+fn algorithm_from_atom(algorithm: Atom) -> NifResult<keygen::Algorithm> {
+    if algorithm == dilithium2() {
+        Ok(keygen::Algorithm::Dilithium2)
+    } else if algorithm == falcon512() {
+        Ok(keygen::Algorithm::Falcon512)
+    } else if algorithm == sphincsplus() {
+        Ok(keygen::Algorithm::SphincsPlus)
+    } else {
+        Err(rustler::Error::BadArg)
     }
-    
-    let cache_file = format!("{}/{}.keypair", cache_dir, hex::encode(cache_key));
-    if let Ok(data) = fs::read(&cache_file) {
-        // Simple format: [pk_len:4][pk_data][sk_data]
-        if data.len() >= 4 {
-            let pk_len = u32::from_le_bytes([data[0], data[1], data[2], data[3]]) as usize;
-            if data.len() >= 4 + pk_len {
-                let pk_data = data[4..4+pk_len].to_vec();
-                let sk_data = data[4+pk_len..].to_vec();
-                return Some((pk_data, sk_data));
-            }
-        }
+}
+
+fn algorithm_atom(algorithm: keygen::Algorithm) -> Atom {
+    match algorithm {
+        keygen::Algorithm::Dilithium2 => dilithium2(),
+        keygen::Algorithm::Falcon512 => falcon512(),
+        keygen::Algorithm::SphincsPlus => sphincsplus(),
+    }
+}
+
+// Copy a byte slice into a freshly allocated BEAM binary
+fn make_binary<'a>(env: Env<'a>, bytes: &[u8]) -> Binary<'a> {
+    let mut binary = NewBinary::new(env, bytes.len());
+    binary.copy_from_slice(bytes);
+    binary.into()
+}
+
+// pqcrypto secret keys are `Copy` byte-array newtypes without a Drop impl,
+// so every temporary copy has to be wiped by hand
+fn wipe_secret_key<K: SecretKey>(sk: &mut K) {
+    // SAFETY: K wraps a plain [u8; N], so all of its bytes are initialized
+    // and the all-zero pattern is a valid value
+    let bytes = unsafe { std::slice::from_raw_parts_mut(sk as *mut K as *mut u8, std::mem::size_of::<K>()) };
+    bytes.zeroize();
+}
+
+// Accept a secret key either as raw bytes or as an opaque handle of the same algorithm
+fn secret_key_from_term<K: SecretKey>(term: Term, algorithm: keygen::Algorithm) -> NifResult<K> {
+    let result = match term.decode::<ResourceArc<SecretKeyHandle>>() {
+        Ok(handle) if handle.algorithm == algorithm => K::from_bytes(handle.bytes()),
+        Ok(_) => return Err(rustler::Error::BadArg),
+        Err(_) => K::from_bytes(term.decode::<Binary>()?.as_slice()),
+    };
+    result.map_err(|_| rustler::Error::BadArg)
+}
+
+// Signature from the external signer when `private_key` is a remote key handle,
+// None when it is a local key that the caller should sign with itself
+#[cfg(feature = "remote-signer")]
+fn remote_sign(private_key: Term, algorithm: keygen::Algorithm, message: &[u8]) -> Option<NifResult<Vec<u8>>> {
+    let handle = private_key.decode::<ResourceArc<remote_signer::RemoteKeyHandle>>().ok()?;
+    if handle.algorithm != algorithm {
+        return Some(Err(rustler::Error::BadArg));
     }
+    Some(handle.sign(message).map_err(term_error))
+}
+
+#[cfg(not(feature = "remote-signer"))]
+fn remote_sign(_private_key: Term, _algorithm: keygen::Algorithm, _message: &[u8]) -> Option<NifResult<Vec<u8>>> {
     None
 }
 
-// Save to persistent cache
-fn save_persistent_cache(cache_key: &[u8], pk_bytes: &[u8], sk_bytes: &[u8]) -> Result<(), String> {
-    let cache_dir = get_cache_dir();
-    if let Err(e) = fs::create_dir_all(&cache_dir) {
-        return Err(format!("Failed to create cache directory '{}': {}", cache_dir, e));
+// Detached signature with any supported algorithm, for NIFs that sign on the caller's behalf
+fn sign_detached(algorithm: keygen::Algorithm, message: &[u8], private_key: Term) -> NifResult<Vec<u8>> {
+    if let Some(signature) = remote_sign(private_key, algorithm, message) {
+        return signature;
     }
-    
-    let cache_file = format!("{}/{}.keypair", cache_dir, hex::encode(cache_key));
-    let mut data = Vec::new();
-    data.extend_from_slice(&(pk_bytes.len() as u32).to_le_bytes());
-    data.extend_from_slice(pk_bytes);
-    data.extend_from_slice(sk_bytes);
-    
-    if let Err(e) = fs::write(&cache_file, data) {
-        return Err(format!("Failed to write crypto cache '{}': {}", &cache_file, e));
+    match algorithm {
+        keygen::Algorithm::Dilithium2 => {
+            let mut sk: dilithium2::SecretKey = secret_key_from_term(private_key, algorithm)?;
+            let signature = dilithium2::detached_sign(message, &sk);
+            wipe_secret_key(&mut sk);
+            Ok(signature.as_bytes().to_vec())
+        }
+        keygen::Algorithm::Falcon512 => {
+            let mut sk: falcon512::SecretKey = secret_key_from_term(private_key, algorithm)?;
+            let signature = falcon512::detached_sign(message, &sk);
+            wipe_secret_key(&mut sk);
+            Ok(signature.as_bytes().to_vec())
+        }
+        keygen::Algorithm::SphincsPlus => {
+            let mut sk: sphincsplus_shake_128f::SecretKey = secret_key_from_term(private_key, algorithm)?;
+            let signature = sphincsplus_shake_128f::detached_sign(message, &sk);
+            wipe_secret_key(&mut sk);
+            Ok(signature.as_bytes().to_vec())
+        }
     }
-    
-    Ok(())
 }
 
-// Load resources function
-rustler::atoms! {
-    ok,
-    error,
+fn verify_detached(algorithm: keygen::Algorithm, signature: &[u8], message: &[u8], public_key: &[u8]) -> bool {
+    verify_cache::verify_with(algorithm, signature, message, public_key, || {
+        verify_uncached(algorithm, signature, message, public_key)
+    })
+}
+
+fn verify_uncached(algorithm: keygen::Algorithm, signature: &[u8], message: &[u8], public_key: &[u8]) -> bool {
+    public_key::verify_bytes(algorithm, public_key, signature, message)
+}
+
+// A public key passed as raw bytes or as a handle from load_public_key
+enum PublicKeyArg<'a> {
+    Bytes(&'a [u8]),
+    Handle(ResourceArc<PublicKeyHandle>),
+}
+
+impl PublicKeyArg<'_> {
+    fn verify(&self, algorithm: keygen::Algorithm, signature: &[u8], message: &[u8]) -> bool {
+        match self {
+            PublicKeyArg::Bytes(public_key) => verify_detached(algorithm, signature, message, public_key),
+            PublicKeyArg::Handle(handle) => {
+                verify_cache::verify_with(algorithm, signature, message, handle.key.as_bytes(), || {
+                    handle.key.verify(signature, message)
+                })
+            }
+        }
+    }
+}
+
+// Handles must match the algorithm; byte keys of the wrong length just fail to verify
+fn public_key_from_term(term: Term, algorithm: keygen::Algorithm) -> NifResult<PublicKeyArg> {
+    match term.decode::<ResourceArc<PublicKeyHandle>>() {
+        Ok(handle) if handle.key.algorithm() == algorithm => Ok(PublicKeyArg::Handle(handle)),
+        Ok(_) => Err(rustler::Error::BadArg),
+        Err(_) => Ok(PublicKeyArg::Bytes(term.decode::<Binary>()?.as_slice())),
+    }
 }
 
 #[rustler::nif]
 fn nifs_loaded() -> bool {
-    true
+    let tracker = tracked!("nifs_loaded");
+    tracker.done(true)
+}
+
+fn allocator_name() -> &'static str {
+    if cfg!(feature = "mimalloc") {
+        "mimalloc"
+    } else if cfg!(feature = "jemalloc") {
+        "jemalloc"
+    } else {
+        "system"
+    }
 }
 
 #[rustler::nif]
 fn get_algorithm_info() -> Vec<String> {
-    vec![
+    let tracker = tracked!("get_algorithm_info");
+    tracker.done(vec![
         "dilithium2".to_string(),
         "falcon512".to_string(),
         "sphincsplus_shake128f".to_string(),
-    ]
+    ])
 }
 
-// === Dilithium Functions ===
+#[rustler::nif]
+fn allocator_info(env: Env) -> NifResult<Atom> {
+    let tracker = tracked!("allocator_info");
+    tracker.finish(Atom::from_str(env, allocator_name()))
+}
 
 #[rustler::nif]
+fn cpu_features(env: Env) -> NifResult<Term> {
+    let tracker = tracked!("cpu_features");
+    let features = cpu::detect();
+    let backend = |name: &str| Atom::from_str(env, name).map(|atom| atom.encode(env));
+    let implementations = Term::map_from_pairs(
+        env,
+        &[
+            (blake3().encode(env), backend(cpu::blake3_backend(&features))?),
+            (dilithium2().encode(env), backend(cpu::pqcrypto_backend(&features, true))?),
+            (falcon512().encode(env), backend(cpu::pqcrypto_backend(&features, true))?),
+            (sphincsplus().encode(env), backend(cpu::pqcrypto_backend(&features, false))?),
+            (hex().encode(env), backend(cpu::hex_backend())?),
+        ],
+    )?;
+    tracker.finish(Term::map_from_pairs(
+        env,
+        &[
+            (avx2().encode(env), features.avx2.encode(env)),
+            (avx512().encode(env), features.avx512.encode(env)),
+            (neon().encode(env), features.neon.encode(env)),
+            (simd().encode(env), cfg!(feature = "simd").encode(env)),
+            (backends().encode(env), implementations),
+        ],
+    ))
+}
+
+// === Dilithium Functions ===
+
+#[rustler::nif(schedule = "DirtyCpu")]
 fn dilithium2_keypair<'a>(env: Env<'a>) -> NifResult<(Binary<'a>, Binary<'a>)> {
-    let (pk, sk) = dilithium2::keypair();
-    
+    let tracker = tracked!("dilithium2_keypair");
+    let (pk, mut sk) = dilithium2::keypair();
+
     let mut pk_binary = NewBinary::new(env, pk.as_bytes().len());
     pk_binary.copy_from_slice(pk.as_bytes());
-    
+
     let mut sk_binary = NewBinary::new(env, sk.as_bytes().len());
     sk_binary.copy_from_slice(sk.as_bytes());
-    
-    Ok((pk_binary.into(), sk_binary.into()))
+    wipe_secret_key(&mut sk);
+
+    tracker.ok((pk_binary.into(), sk_binary.into()))
 }
 
-#[rustler::nif]
-fn dilithium2_sign<'a>(env: Env<'a>, message: Binary, private_key: Binary) -> NifResult<Binary<'a>> {
-    match dilithium2::SecretKey::from_bytes(&private_key) {
-        Ok(sk) => {
-            let signature = dilithium2::detached_sign(&message, &sk);
-            let sig_bytes = signature.as_bytes();
-            
-            let mut sig_binary = NewBinary::new(env, sig_bytes.len());
-            sig_binary.copy_from_slice(sig_bytes);
-            
-            Ok(sig_binary.into())
-        }
-        Err(_) => Err(rustler::Error::BadArg)
-    }
+#[rustler::nif(schedule = "DirtyCpu")]
+fn dilithium2_keypair_handle<'a>(env: Env<'a>) -> NifResult<(Binary<'a>, ResourceArc<SecretKeyHandle>)> {
+    let tracker = tracked!("dilithium2_keypair_handle");
+    let (pk, mut sk) = dilithium2::keypair();
+    let handle = SecretKeyHandle::new(keygen::Algorithm::Dilithium2, sk.as_bytes());
+    wipe_secret_key(&mut sk);
+
+    tracker.ok((make_binary(env, pk.as_bytes()), ResourceArc::new(handle)))
 }
 
-#[rustler::nif]
-fn dilithium2_verify(signature: Binary, message: Binary, public_key: Binary) -> bool {
-    match (
-        dilithium2::DetachedSignature::from_bytes(&signature),
-        dilithium2::PublicKey::from_bytes(&public_key)
-    ) {
-        (Ok(sig), Ok(pk)) => {
-            dilithium2::verify_detached_signature(&sig, &message, &pk).is_ok()
-        }
-        _ => false
+#[rustler::nif(schedule = "DirtyCpu")]
+fn dilithium2_sign<'a>(env: Env<'a>, message: Binary, private_key: Term<'a>) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("dilithium2_sign");
+    if let Some(signature) = remote_sign(private_key, keygen::Algorithm::Dilithium2, &message) {
+        return tracker.ok(make_binary(env, &signature?));
     }
+    let mut sk: dilithium2::SecretKey = secret_key_from_term(private_key, keygen::Algorithm::Dilithium2)?;
+    let signature = dilithium2::detached_sign(&message, &sk);
+    wipe_secret_key(&mut sk);
+    let sig_bytes = signature.as_bytes();
+
+    let mut sig_binary = NewBinary::new(env, sig_bytes.len());
+    sig_binary.copy_from_slice(sig_bytes);
+
+    tracker.ok(sig_binary.into())
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn dilithium2_verify(signature: Binary, message: Binary, public_key: Term) -> NifResult<bool> {
+    let tracker = tracked!("dilithium2_verify");
+    let algorithm = keygen::Algorithm::Dilithium2;
+    tracker.ok(public_key_from_term(public_key, algorithm)?.verify(algorithm, &signature, &message))
 }
 
 // === Falcon Functions ===
 
-#[rustler::nif]
+#[rustler::nif(schedule = "DirtyCpu")]
 fn falcon512_keypair<'a>(env: Env<'a>) -> NifResult<(Binary<'a>, Binary<'a>)> {
-    let (pk, sk) = falcon512::keypair();
-    
+    let tracker = tracked!("falcon512_keypair");
+    let (pk, mut sk) = falcon512::keypair();
+
     let mut pk_binary = NewBinary::new(env, pk.as_bytes().len());
     pk_binary.copy_from_slice(pk.as_bytes());
-    
+
     let mut sk_binary = NewBinary::new(env, sk.as_bytes().len());
     sk_binary.copy_from_slice(sk.as_bytes());
-    
-    Ok((pk_binary.into(), sk_binary.into()))
+    wipe_secret_key(&mut sk);
+
+    tracker.ok((pk_binary.into(), sk_binary.into()))
 }
 
-#[rustler::nif]
-fn falcon512_sign<'a>(env: Env<'a>, message: Binary, private_key: Binary) -> NifResult<Binary<'a>> {
-    match falcon512::SecretKey::from_bytes(&private_key) {
-        Ok(sk) => {
-            let signature = falcon512::detached_sign(&message, &sk);
-            let sig_bytes = signature.as_bytes();
-            
-            let mut sig_binary = NewBinary::new(env, sig_bytes.len());
-            sig_binary.copy_from_slice(sig_bytes);
-            
-            Ok(sig_binary.into())
-        }
-        Err(_) => Err(rustler::Error::BadArg)
-    }
+#[rustler::nif(schedule = "DirtyCpu")]
+fn falcon512_keypair_handle<'a>(env: Env<'a>) -> NifResult<(Binary<'a>, ResourceArc<SecretKeyHandle>)> {
+    let tracker = tracked!("falcon512_keypair_handle");
+    let (pk, mut sk) = falcon512::keypair();
+    let handle = SecretKeyHandle::new(keygen::Algorithm::Falcon512, sk.as_bytes());
+    wipe_secret_key(&mut sk);
+
+    tracker.ok((make_binary(env, pk.as_bytes()), ResourceArc::new(handle)))
 }
 
-#[rustler::nif]
-fn falcon512_verify(signature: Binary, message: Binary, public_key: Binary) -> bool {
-    match (
-        falcon512::DetachedSignature::from_bytes(&signature),
-        falcon512::PublicKey::from_bytes(&public_key)
-    ) {
-        (Ok(sig), Ok(pk)) => {
-            falcon512::verify_detached_signature(&sig, &message, &pk).is_ok()
-        }
-        _ => false
+#[rustler::nif(schedule = "DirtyCpu")]
+fn falcon512_sign<'a>(env: Env<'a>, message: Binary, private_key: Term<'a>) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("falcon512_sign");
+    if let Some(signature) = remote_sign(private_key, keygen::Algorithm::Falcon512, &message) {
+        return tracker.ok(make_binary(env, &signature?));
     }
+    let mut sk: falcon512::SecretKey = secret_key_from_term(private_key, keygen::Algorithm::Falcon512)?;
+    let signature = falcon512::detached_sign(&message, &sk);
+    wipe_secret_key(&mut sk);
+    let sig_bytes = signature.as_bytes();
+
+    let mut sig_binary = NewBinary::new(env, sig_bytes.len());
+    sig_binary.copy_from_slice(sig_bytes);
+
+    tracker.ok(sig_binary.into())
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn falcon512_verify(signature: Binary, message: Binary, public_key: Term) -> NifResult<bool> {
+    let tracker = tracked!("falcon512_verify");
+    let algorithm = keygen::Algorithm::Falcon512;
+    tracker.ok(public_key_from_term(public_key, algorithm)?.verify(algorithm, &signature, &message))
 }
 
 // === SPHINCS+ Functions ===
 
-#[rustler::nif]
+#[rustler::nif(schedule = "DirtyCpu")]
 fn sphincsplus_shake_128f_keypair<'a>(env: Env<'a>) -> NifResult<(Binary<'a>, Binary<'a>)> {
-    let (pk, sk) = sphincsplus_shake_128f::keypair();
-    
+    let tracker = tracked!("sphincsplus_shake_128f_keypair");
+    let (pk, mut sk) = sphincsplus_shake_128f::keypair();
+
     let mut pk_binary = NewBinary::new(env, pk.as_bytes().len());
     pk_binary.copy_from_slice(pk.as_bytes());
-    
+
     let mut sk_binary = NewBinary::new(env, sk.as_bytes().len());
     sk_binary.copy_from_slice(sk.as_bytes());
-    
-    Ok((pk_binary.into(), sk_binary.into()))
+    wipe_secret_key(&mut sk);
+
+    tracker.ok((pk_binary.into(), sk_binary.into()))
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn sphincsplus_shake_128f_keypair_handle<'a>(env: Env<'a>) -> NifResult<(Binary<'a>, ResourceArc<SecretKeyHandle>)> {
+    let tracker = tracked!("sphincsplus_shake_128f_keypair_handle");
+    let (pk, mut sk) = sphincsplus_shake_128f::keypair();
+    let handle = SecretKeyHandle::new(keygen::Algorithm::SphincsPlus, sk.as_bytes());
+    wipe_secret_key(&mut sk);
+
+    tracker.ok((make_binary(env, pk.as_bytes()), ResourceArc::new(handle)))
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn sphincsplus_shake_128f_sign<'a>(env: Env<'a>, message: Binary, private_key: Term<'a>) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("sphincsplus_shake_128f_sign");
+    if let Some(signature) = remote_sign(private_key, keygen::Algorithm::SphincsPlus, &message) {
+        return tracker.ok(make_binary(env, &signature?));
+    }
+    let mut sk: sphincsplus_shake_128f::SecretKey = secret_key_from_term(private_key, keygen::Algorithm::SphincsPlus)?;
+    let signature = sphincsplus_shake_128f::detached_sign(&message, &sk);
+    wipe_secret_key(&mut sk);
+    let sig_bytes = signature.as_bytes();
+
+    let mut sig_binary = NewBinary::new(env, sig_bytes.len());
+    sig_binary.copy_from_slice(sig_bytes);
+
+    tracker.ok(sig_binary.into())
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn sphincsplus_shake_128f_verify(signature: Binary, message: Binary, public_key: Term) -> NifResult<bool> {
+    let tracker = tracked!("sphincsplus_shake_128f_verify");
+    let algorithm = keygen::Algorithm::SphincsPlus;
+    tracker.ok(public_key_from_term(public_key, algorithm)?.verify(algorithm, &signature, &message))
 }
 
+// === Public Key Handles ===
+
 #[rustler::nif]
-fn sphincsplus_shake_128f_sign<'a>(env: Env<'a>, message: Binary, private_key: Binary) -> NifResult<Binary<'a>> {
-    match sphincsplus_shake_128f::SecretKey::from_bytes(&private_key) {
-        Ok(sk) => {
-            let signature = sphincsplus_shake_128f::detached_sign(&message, &sk);
-            let sig_bytes = signature.as_bytes();
-            
-            let mut sig_binary = NewBinary::new(env, sig_bytes.len());
-            sig_binary.copy_from_slice(sig_bytes);
-            
-            Ok(sig_binary.into())
+fn load_public_key(algorithm: Atom, public_key: Binary) -> NifResult<ResourceArc<PublicKeyHandle>> {
+    let tracker = tracked!("load_public_key");
+    let algorithm = algorithm_from_atom(algorithm)?;
+    let key = ParsedPublicKey::parse(algorithm, &public_key).ok_or(rustler::Error::BadArg)?;
+    tracker.ok(ResourceArc::new(PublicKeyHandle { key }))
+}
+
+// === Batch Signatures ===
+
+// (signature, message, public key bytes or handle)
+type VerifyItem<'a> = (Binary<'a>, Binary<'a>, Term<'a>);
+
+// Indices of the items that fail to verify, checked across the rayon pool
+fn batch_failures(algorithm: keygen::Algorithm, items: &[VerifyItem]) -> NifResult<Vec<usize>> {
+    let items = items
+        .iter()
+        .map(|(signature, message, public_key)| {
+            Ok((signature.as_slice(), message.as_slice(), public_key_from_term(*public_key, algorithm)?))
+        })
+        .collect::<NifResult<Vec<_>>>()?;
+    Ok(threads::install(|| {
+        items
+            .par_iter()
+            .enumerate()
+            .filter(|(_, (signature, message, public_key))| !public_key.verify(algorithm, signature, message))
+            .map(|(index, _)| index)
+            .collect()
+    }))
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn batch_verify(algorithm: Atom, items: Vec<VerifyItem>) -> NifResult<bool> {
+    let tracker = tracked!("batch_verify");
+    let algorithm = algorithm_from_atom(algorithm)?;
+    tracker.ok(batch_failures(algorithm, &items)?.is_empty())
+}
+
+#[rustler::nif(name = "batch_verify", schedule = "DirtyCpu")]
+fn batch_verify_with_opts<'a>(env: Env<'a>, algorithm: Atom, items: Vec<VerifyItem>, opts: Vec<(Atom, Term)>) -> NifResult<Term<'a>> {
+    let tracker = tracked!("batch_verify");
+    let algorithm = algorithm_from_atom(algorithm)?;
+    let mut report_failures = false;
+    for (key, value) in opts {
+        if key == failures() {
+            report_failures = value.decode()?;
+        } else {
+            return Err(rustler::Error::BadArg);
+        }
+    }
+
+    let failed = batch_failures(algorithm, &items)?;
+    if report_failures {
+        tracker.ok(failed.encode(env))
+    } else {
+        tracker.ok(failed.is_empty().encode(env))
+    }
+}
+
+// Parse the secret key once and sign every message with it, in order. The
+// signatures stay as fixed-size structs until they are copied into binaries.
+fn sign_with_key<'a, K, S, F>(
+    env: Env<'a>,
+    private_key: Term,
+    algorithm: keygen::Algorithm,
+    messages: &[&[u8]],
+    parallel: bool,
+    sign: F,
+) -> NifResult<Vec<Binary<'a>>>
+where
+    K: SecretKey + Sync,
+    S: DetachedSignature + Send,
+    F: Fn(&[u8], &K) -> S + Sync,
+{
+    let mut sk: K = secret_key_from_term(private_key, algorithm)?;
+    let signatures: Vec<S> = if parallel {
+        threads::install(|| messages.par_iter().map(|message| sign(message, &sk)).collect())
+    } else {
+        messages.iter().map(|message| sign(message, &sk)).collect()
+    };
+    wipe_secret_key(&mut sk);
+    Ok(signatures.iter().map(|signature| make_binary(env, signature.as_bytes())).collect())
+}
+
+fn sign_messages<'a>(
+    env: Env<'a>,
+    algorithm: keygen::Algorithm,
+    private_key: Term,
+    messages: &[Binary],
+    parallel: bool,
+) -> NifResult<Vec<Binary<'a>>> {
+    let messages: Vec<&[u8]> = messages.iter().map(|message| message.as_slice()).collect();
+    if private_key.decode::<ResourceArc<SecretKeyHandle>>().is_err() && private_key.decode::<Binary>().is_err() {
+        // Remote keys sign one request at a time
+        return messages
+            .iter()
+            .map(|message| sign_detached(algorithm, message, private_key).map(|signature| make_binary(env, &signature)))
+            .collect();
+    }
+    match algorithm {
+        keygen::Algorithm::Dilithium2 => {
+            sign_with_key(env, private_key, algorithm, &messages, parallel, dilithium2::detached_sign)
+        }
+        keygen::Algorithm::Falcon512 => {
+            sign_with_key(env, private_key, algorithm, &messages, parallel, falcon512::detached_sign)
+        }
+        keygen::Algorithm::SphincsPlus => {
+            sign_with_key(env, private_key, algorithm, &messages, parallel, sphincsplus_shake_128f::detached_sign)
         }
-        Err(_) => Err(rustler::Error::BadArg)
     }
 }
 
+#[rustler::nif(schedule = "DirtyCpu")]
+fn sign_many<'a>(env: Env<'a>, algorithm: Atom, private_key: Term<'a>, messages: Vec<Binary>) -> NifResult<Vec<Binary<'a>>> {
+    let tracker = tracked!("sign_many");
+    let algorithm = algorithm_from_atom(algorithm)?;
+    tracker.finish(sign_messages(env, algorithm, private_key, &messages, false))
+}
+
+#[rustler::nif(name = "sign_many", schedule = "DirtyCpu")]
+fn sign_many_with_opts<'a>(
+    env: Env<'a>,
+    algorithm: Atom,
+    private_key: Term<'a>,
+    messages: Vec<Binary>,
+    opts: Vec<(Atom, Term)>,
+) -> NifResult<Vec<Binary<'a>>> {
+    let tracker = tracked!("sign_many");
+    let algorithm = algorithm_from_atom(algorithm)?;
+    let mut sign_in_parallel = false;
+    for (key, value) in opts {
+        if key == parallel() {
+            sign_in_parallel = value.decode()?;
+        } else {
+            return Err(rustler::Error::BadArg);
+        }
+    }
+
+    tracker.finish(sign_messages(env, algorithm, private_key, &messages, sign_in_parallel))
+}
+
+// === Aggregate Attestations ===
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn attestation_aggregate<'a>(
+    env: Env<'a>,
+    algorithm: Atom,
+    digest: Binary,
+    validator_count: usize,
+    signatures: Vec<(usize, Binary)>,
+) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("attestation_aggregate");
+    let algorithm = algorithm_from_atom(algorithm)?;
+    let digest: [u8; attestation::DIGEST_LEN] = digest.as_slice().try_into().map_err(|_| rustler::Error::BadArg)?;
+    let signatures = signatures.iter().map(|(index, signature)| (*index, signature.as_slice())).collect();
+    let aggregate = attestation::build(algorithm, &digest, validator_count, signatures).map_err(|_| rustler::Error::BadArg)?;
+    tracker.ok(make_binary(env, &aggregate))
+}
+
+// Every signature is checked, so the error names all the bad validators
+#[rustler::nif(schedule = "DirtyCpu")]
+fn attestation_verify<'a>(env: Env<'a>, aggregate: Binary, public_keys: Vec<Term<'a>>) -> NifResult<(Binary<'a>, Vec<usize>)> {
+    let tracker = tracked!("attestation_verify");
+    let aggregate = attestation::decode(&aggregate).map_err(term_error)?;
+    if public_keys.len() != aggregate.validator_count {
+        return Err(term_error(format!(
+            "aggregate for {} validators, {} public keys given",
+            aggregate.validator_count,
+            public_keys.len()
+        )));
+    }
+    let items = aggregate
+        .signatures
+        .iter()
+        .map(|(index, signature)| Ok((*index, *signature, public_key_from_term(public_keys[*index], aggregate.algorithm)?)))
+        .collect::<NifResult<Vec<_>>>()?;
+    let failed: Vec<String> = threads::install(|| {
+        items
+            .par_iter()
+            .filter(|(_, signature, public_key)| !public_key.verify(aggregate.algorithm, signature, &aggregate.digest))
+            .map(|(index, _, _)| index.to_string())
+            .collect()
+    });
+    if !failed.is_empty() {
+        return Err(term_error(format!("invalid signatures from validators {}", failed.join(", "))));
+    }
+    let participants = aggregate.signatures.iter().map(|(index, _)| *index).collect();
+    tracker.ok((make_binary(env, &aggregate.digest), participants))
+}
+
+#[rustler::nif]
+fn attestation_decode<'a>(env: Env<'a>, aggregate: Binary) -> NifResult<(Atom, Binary<'a>, usize, Vec<usize>)> {
+    let tracker = tracked!("attestation_decode");
+    let aggregate = attestation::decode(&aggregate).map_err(term_error)?;
+    let participants = aggregate.signatures.iter().map(|(index, _)| *index).collect();
+    let digest = make_binary(env, &aggregate.digest);
+    tracker.ok((algorithm_atom(aggregate.algorithm), digest, aggregate.validator_count, participants))
+}
+
+// === Yielding Verification ===
+
+// Verify items one at a time on a normal scheduler, stopping at the first
+// invalid signature. When the timeslice runs out the unverified tail of the
+// list is handed back as `{:cont, rest}` for the caller to pass in again, so
+// no call holds the scheduler for much more than one item past ~1ms.
 #[rustler::nif]
-fn sphincsplus_shake_128f_verify(signature: Binary, message: Binary, public_key: Binary) -> bool {
-    match (
-        sphincsplus_shake_128f::DetachedSignature::from_bytes(&signature),
-        sphincsplus_shake_128f::PublicKey::from_bytes(&public_key)
-    ) {
-        (Ok(sig), Ok(pk)) => {
-            sphincsplus_shake_128f::verify_detached_signature(&sig, &message, &pk).is_ok()
+fn batch_verify_slice<'a>(env: Env<'a>, algorithm: Atom, items: Term<'a>) -> NifResult<Term<'a>> {
+    let tracker = tracked!("batch_verify_slice");
+    let algorithm = algorithm_from_atom(algorithm)?;
+    let mut rest = items;
+    while let Ok((item, tail)) = rest.list_get_cell() {
+        let (signature, message, public_key): VerifyItem = item.decode()?;
+        let started = Instant::now();
+        if !public_key_from_term(public_key, algorithm)?.verify(algorithm, &signature, &message) {
+            return tracker.ok(false.encode(env));
+        }
+        rest = tail;
+
+        let percent = (started.elapsed().as_micros() * 100 / TIMESLICE_MICROS).clamp(1, 100) as i32;
+        if rustler::schedule::consume_timeslice(env, percent) && rest != Term::list_new_empty(env) {
+            return tracker.ok((cont(), rest).encode(env));
+        }
+    }
+    if rest != Term::list_new_empty(env) {
+        return Err(rustler::Error::BadArg);
+    }
+    tracker.ok(true.encode(env))
+}
+
+// === Verification Sessions ===
+
+// {message, signature, key_index}
+type SessionItemArg<'a> = (Binary<'a>, Binary<'a>, usize);
+
+fn session_failures(session: &VerifySession, items: &[SessionItemArg]) -> NifResult<Vec<usize>> {
+    let items = items
+        .iter()
+        .map(|(message, signature, index)| {
+            if *index >= session.size() {
+                return Err(rustler::Error::BadArg);
+            }
+            Ok((message.as_slice(), signature.as_slice(), *index))
+        })
+        .collect::<NifResult<Vec<_>>>()?;
+    Ok(session.failures(&items))
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn verify_session_new(algorithm: Atom, public_keys: Vec<Binary>) -> NifResult<ResourceArc<VerifySession>> {
+    let tracker = tracked!("verify_session_new");
+    let algorithm = algorithm_from_atom(algorithm)?;
+    let public_keys: Vec<&[u8]> = public_keys.iter().map(|public_key| public_key.as_slice()).collect();
+    let session = VerifySession::new(algorithm, &public_keys).ok_or(rustler::Error::BadArg)?;
+    tracker.ok(ResourceArc::new(session))
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn verify_session_verify(session: ResourceArc<VerifySession>, items: Vec<SessionItemArg>) -> NifResult<bool> {
+    let tracker = tracked!("verify_session_verify");
+    tracker.ok(session_failures(&session, &items)?.is_empty())
+}
+
+#[rustler::nif(name = "verify_session_verify", schedule = "DirtyCpu")]
+fn verify_session_verify_with_opts<'a>(
+    env: Env<'a>,
+    session: ResourceArc<VerifySession>,
+    items: Vec<SessionItemArg>,
+    opts: Vec<(Atom, Term)>,
+) -> NifResult<Term<'a>> {
+    let tracker = tracked!("verify_session_verify");
+    let mut report_failures = false;
+    for (key, value) in opts {
+        if key == failures() {
+            report_failures = value.decode()?;
+        } else {
+            return Err(rustler::Error::BadArg);
+        }
+    }
+
+    let failed = session_failures(&session, &items)?;
+    if report_failures {
+        tracker.ok(failed.encode(env))
+    } else {
+        tracker.ok(failed.is_empty().encode(env))
+    }
+}
+
+// === Async Signing ===
+
+fn sign_secret_bytes<K, S>(secret_key: &[u8], message: &[u8], sign: fn(&[u8], &K) -> S, out: &mut Vec<u8>) -> Result<(), String>
+where
+    K: SecretKey,
+    S: DetachedSignature,
+{
+    let mut sk = K::from_bytes(secret_key).map_err(|_| "invalid secret key".to_string())?;
+    let signature = sign(message, &sk);
+    wipe_secret_key(&mut sk);
+    out.extend_from_slice(signature.as_bytes());
+    Ok(())
+}
+
+fn sign_with_secret_bytes(algorithm: keygen::Algorithm, message: &[u8], secret_key: &[u8], out: &mut Vec<u8>) -> Result<(), String> {
+    match algorithm {
+        keygen::Algorithm::Dilithium2 => sign_secret_bytes(secret_key, message, dilithium2::detached_sign, out),
+        keygen::Algorithm::Falcon512 => sign_secret_bytes(secret_key, message, falcon512::detached_sign, out),
+        keygen::Algorithm::SphincsPlus => sign_secret_bytes(secret_key, message, sphincsplus_shake_128f::detached_sign, out),
+    }
+}
+
+// Capture the key for the worker so the job outlives the calling NIF. Handles
+// are shared rather than copied, so their bytes stay in locked memory.
+fn signing_task(algorithm: keygen::Algorithm, private_key: Term) -> NifResult<sign_pool::SignTask> {
+    #[cfg(feature = "remote-signer")]
+    if let Ok(remote) = private_key.decode::<ResourceArc<remote_signer::RemoteKeyHandle>>() {
+        if remote.algorithm != algorithm {
+            return Err(rustler::Error::BadArg);
+        }
+        return Ok(Box::new(move |message, out| remote.sign(message).map(|signature| out.extend_from_slice(&signature))));
+    }
+
+    match private_key.decode::<ResourceArc<SecretKeyHandle>>() {
+        Ok(handle) if handle.algorithm == algorithm => {
+            Ok(Box::new(move |message, out| sign_with_secret_bytes(algorithm, message, handle.bytes(), out)))
+        }
+        Ok(_) => Err(rustler::Error::BadArg),
+        Err(_) => {
+            let secret_key = private_key.decode::<Binary>()?;
+            if secret_key.len() != algorithm.secret_key_bytes() {
+                return Err(rustler::Error::BadArg);
+            }
+            let secret_key = Zeroizing::new(secret_key.to_vec());
+            Ok(Box::new(move |message, out| sign_with_secret_bytes(algorithm, message, &secret_key, out)))
         }
-        _ => false
     }
 }
 
+fn sign_async(algorithm: keygen::Algorithm, message: Binary, private_key: Term, caller: LocalPid, reference: Term) -> NifResult<Atom> {
+    let task = signing_task(algorithm, private_key)?;
+    if !sign_pool::submit(task, message.to_term(private_key.get_env()), caller, reference) {
+        return Err(rustler::Error::Term(Box::new(busy())));
+    }
+    Ok(ok())
+}
+
+#[rustler::nif]
+fn dilithium2_sign_async(message: Binary, private_key: Term, caller: LocalPid, reference: Term) -> NifResult<Atom> {
+    let tracker = tracked!("dilithium2_sign_async");
+    tracker.finish(sign_async(keygen::Algorithm::Dilithium2, message, private_key, caller, reference))
+}
+
+#[rustler::nif]
+fn falcon512_sign_async(message: Binary, private_key: Term, caller: LocalPid, reference: Term) -> NifResult<Atom> {
+    let tracker = tracked!("falcon512_sign_async");
+    tracker.finish(sign_async(keygen::Algorithm::Falcon512, message, private_key, caller, reference))
+}
+
+#[rustler::nif]
+fn sphincsplus_shake_128f_sign_async(message: Binary, private_key: Term, caller: LocalPid, reference: Term) -> NifResult<Atom> {
+    let tracker = tracked!("sphincsplus_shake_128f_sign_async");
+    tracker.finish(sign_async(keygen::Algorithm::SphincsPlus, message, private_key, caller, reference))
+}
+
+// === Verification Cache ===
+
+#[rustler::nif]
+fn configure_verify_cache(max_entries: usize) -> NifResult<Atom> {
+    let tracker = tracked!("configure_verify_cache");
+    if max_entries > verify_cache::MAX_CAPACITY {
+        return Err(rustler::Error::BadArg);
+    }
+    verify_cache::configure(max_entries).map_err(term_error)?;
+    tracker.ok(ok())
+}
+
+#[rustler::nif]
+fn clear_verify_cache() -> Atom {
+    let tracker = tracked!("clear_verify_cache");
+    verify_cache::clear();
+    tracker.done(ok())
+}
+
+#[rustler::nif]
+fn verify_cache_stats(env: Env) -> NifResult<Term> {
+    let tracker = tracked!("verify_cache_stats");
+    let stats = verify_cache::stats();
+    tracker.finish(Term::map_from_pairs(
+        env,
+        &[
+            (hits().encode(env), stats.hits.encode(env)),
+            (misses().encode(env), stats.misses.encode(env)),
+            (entries().encode(env), stats.entries.encode(env)),
+            (capacity().encode(env), stats.capacity.encode(env)),
+        ],
+    ))
+}
+
+// === Thread Pool ===
+
+// Spawning the replacement threads can take a moment on large hosts
+#[rustler::nif(schedule = "DirtyCpu")]
+fn set_thread_pool(size: usize) -> NifResult<Atom> {
+    let tracker = tracked!("set_thread_pool");
+    if size > threads::MAX_THREADS {
+        return Err(rustler::Error::BadArg);
+    }
+    threads::configure(size).map_err(term_error)?;
+    tracker.ok(ok())
+}
+
+#[rustler::nif]
+fn thread_pool_size() -> usize {
+    let tracker = tracked!("thread_pool_size");
+    tracker.done(threads::size())
+}
+
+// === Telemetry ===
+
+#[rustler::nif]
+fn crypto_stats(env: Env) -> NifResult<Term> {
+    let tracker = tracked!("crypto_stats");
+    let mut per_nif = Vec::new();
+    for (name, stats) in stats::snapshot() {
+        let counters = Term::map_from_pairs(
+            env,
+            &[
+                (calls().encode(env), stats.calls.encode(env)),
+                (failures().encode(env), stats.failures.encode(env)),
+                (total_ns().encode(env), stats.total_ns.encode(env)),
+            ],
+        )?;
+        per_nif.push((Atom::from_str(env, name)?.encode(env), counters));
+    }
+    tracker.finish(Term::map_from_pairs(env, &per_nif))
+}
+
 // === Blake3 Hash Function ===
 
+// Upper bound on XOF output so a bad length can't trigger a huge allocation
+const MAX_XOF_OUTPUT_LEN: usize = 1 << 20;
+
+// blake3_hash/1,2 (Elixir) route inputs of 128 KiB or more, and any they hash
+// across the rayon pool, to the dirty variant, so a normal scheduler only ever
+// hashes small inputs on its own thread
 #[rustler::nif]
-fn blake3_hash<'a>(env: Env<'a>, data: Binary) -> NifResult<Binary<'a>> {
+fn blake3_hash_inline<'a>(env: Env<'a>, data: Binary) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("blake3_hash_inline");
     let hash = blake3::hash(&data);
     let hash_bytes = hash.as_bytes();
-    
+
     let mut result_binary = NewBinary::new(env, hash_bytes.len());
     result_binary.copy_from_slice(hash_bytes);
-    
-    Ok(result_binary.into())
+
+    tracker.ok(result_binary.into())
 }
 
-// === Deterministic Key Generation Functions ===
+#[rustler::nif(schedule = "DirtyCpu")]
+fn blake3_hash_dirty<'a>(env: Env<'a>, data: Binary, parallel: bool) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("blake3_hash_dirty");
+    let data = data.as_slice();
+    let hash = if parallel {
+        threads::install(|| blake3::Hasher::new().update_rayon(data).finalize())
+    } else {
+        blake3::hash(data)
+    };
+    tracker.ok(make_binary(env, hash.as_bytes()))
+}
 
 #[rustler::nif]
-fn dilithium2_keypair_from_seed<'a>(env: Env<'a>, seed: Binary) -> NifResult<(Binary<'a>, Binary<'a>)> {
-    // Create cache key for this seed+algorithm combination
-    let mut cache_key = Vec::new();
-    cache_key.extend_from_slice(b"dilithium2_v1:");
-    cache_key.extend_from_slice(seed.as_slice());
-    let cache_key_hash = blake3::hash(&cache_key);
-    let cache_key_bytes = cache_key_hash.as_bytes().to_vec();
-    
-    // Check persistent cache first
-    if let Some((pk_bytes, sk_bytes)) = load_persistent_cache(&cache_key_bytes) {
-        let mut pk_binary = NewBinary::new(env, pk_bytes.len());
-        pk_binary.copy_from_slice(&pk_bytes);
-        
-        let mut sk_binary = NewBinary::new(env, sk_bytes.len());
-        sk_binary.copy_from_slice(&sk_bytes);
-        
-        return Ok((pk_binary.into(), sk_binary.into()));
-    }
-    
-    // Generate random keypair (deterministic via persistent caching)
-    let (pk, sk) = dilithium2::keypair();
-    let pk_bytes = pk.as_bytes().to_vec();
-    let sk_bytes = sk.as_bytes().to_vec();
-    
-    // Save to persistent cache for true determinism across restarts
-    save_persistent_cache(&cache_key_bytes, &pk_bytes, &sk_bytes)
-        .map_err(|e| rustler::Error::Term(Box::new(format!("Critical cache failure: {}", e))))?;
-    
-    let mut pk_binary = NewBinary::new(env, pk_bytes.len());
-    pk_binary.copy_from_slice(&pk_bytes);
-    
-    let mut sk_binary = NewBinary::new(env, sk_bytes.len());
-    sk_binary.copy_from_slice(&sk_bytes);
-    
-    Ok((pk_binary.into(), sk_binary.into()))
+fn blake3_hash_xof<'a>(env: Env<'a>, data: Binary, output_len: usize) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("blake3_hash_xof");
+    if output_len > MAX_XOF_OUTPUT_LEN {
+        return Err(rustler::Error::BadArg);
+    }
+
+    let mut reader = blake3::Hasher::new().update(&data).finalize_xof();
+    let mut result_binary = NewBinary::new(env, output_len);
+    reader.fill(result_binary.as_mut_slice());
+
+    tracker.ok(result_binary.into())
 }
 
 #[rustler::nif]
-fn falcon512_keypair_from_seed<'a>(env: Env<'a>, seed: Binary) -> NifResult<(Binary<'a>, Binary<'a>)> {
-    // Create cache key for this seed+algorithm combination
-    let mut cache_key = Vec::new();
-    cache_key.extend_from_slice(b"falcon512_v1:");
-    cache_key.extend_from_slice(seed.as_slice());
-    let cache_key_hash = blake3::hash(&cache_key);
-    let cache_key_bytes = cache_key_hash.as_bytes().to_vec();
-    
-    // Check persistent cache first
-    if let Some((pk_bytes, sk_bytes)) = load_persistent_cache(&cache_key_bytes) {
-        let mut pk_binary = NewBinary::new(env, pk_bytes.len());
-        pk_binary.copy_from_slice(&pk_bytes);
-        
-        let mut sk_binary = NewBinary::new(env, sk_bytes.len());
-        sk_binary.copy_from_slice(&sk_bytes);
-        
-        return Ok((pk_binary.into(), sk_binary.into()));
-    }
-    
-    // Generate random keypair (deterministic via persistent caching)
-    let (pk, sk) = falcon512::keypair();
-    let pk_bytes = pk.as_bytes().to_vec();
-    let sk_bytes = sk.as_bytes().to_vec();
-    
-    // Save to persistent cache for true determinism across restarts
-    save_persistent_cache(&cache_key_bytes, &pk_bytes, &sk_bytes)
-        .map_err(|e| rustler::Error::Term(Box::new(format!("Critical cache failure: {}", e))))?;
-    
-    let mut pk_binary = NewBinary::new(env, pk_bytes.len());
-    pk_binary.copy_from_slice(&pk_bytes);
-    
-    let mut sk_binary = NewBinary::new(env, sk_bytes.len());
-    sk_binary.copy_from_slice(&sk_bytes);
-    
-    Ok((pk_binary.into(), sk_binary.into()))
+fn blake3_keyed_hash<'a>(env: Env<'a>, key: Binary, data: Binary) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("blake3_keyed_hash");
+    // BLAKE3 keyed mode requires exactly 32 bytes of key material
+    let key: &[u8; blake3::KEY_LEN] = key.as_slice().try_into().map_err(|_| rustler::Error::BadArg)?;
+    let hash = blake3::keyed_hash(key, &data);
+
+    tracker.ok(make_binary(env, hash.as_bytes()))
 }
 
 #[rustler::nif]
-fn sphincsplus_keypair_from_seed<'a>(env: Env<'a>, seed: Binary) -> NifResult<(Binary<'a>, Binary<'a>)> {
-    // Create cache key for this seed+algorithm combination
-    let mut cache_key = Vec::new();
-    cache_key.extend_from_slice(b"sphincsplus_v1:");
-    cache_key.extend_from_slice(seed.as_slice());
-    let cache_key_hash = blake3::hash(&cache_key);
-    let cache_key_bytes = cache_key_hash.as_bytes().to_vec();
-    
-    // Check persistent cache first
-    if let Some((pk_bytes, sk_bytes)) = load_persistent_cache(&cache_key_bytes) {
-        let mut pk_binary = NewBinary::new(env, pk_bytes.len());
-        pk_binary.copy_from_slice(&pk_bytes);
-        
-        let mut sk_binary = NewBinary::new(env, sk_bytes.len());
-        sk_binary.copy_from_slice(&sk_bytes);
-        
-        return Ok((pk_binary.into(), sk_binary.into()));
-    }
-    
-    // Generate random keypair (deterministic via persistent caching)
-    let (pk, sk) = sphincsplus_shake_128f::keypair();
-    let pk_bytes = pk.as_bytes().to_vec();
-    let sk_bytes = sk.as_bytes().to_vec();
-    
-    // Save to persistent cache for true determinism across restarts
-    save_persistent_cache(&cache_key_bytes, &pk_bytes, &sk_bytes)
-        .map_err(|e| rustler::Error::Term(Box::new(format!("Critical cache failure: {}", e))))?;
-    
-    let mut pk_binary = NewBinary::new(env, pk_bytes.len());
-    pk_binary.copy_from_slice(&pk_bytes);
-    
-    let mut sk_binary = NewBinary::new(env, sk_bytes.len());
-    sk_binary.copy_from_slice(&sk_bytes);
-    
-    Ok((pk_binary.into(), sk_binary.into()))
+fn blake3_derive_key<'a>(env: Env<'a>, context: Binary, material: Binary) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("blake3_derive_key");
+    // Contexts are hardcoded, globally unique strings, e.g. "bastille 2025-01 tx signing"
+    let context = std::str::from_utf8(&context).map_err(|_| rustler::Error::BadArg)?;
+    let derived = blake3::derive_key(context, &material);
+
+    tracker.ok(make_binary(env, &derived))
+}
+
+// === BLAKE2b Hash Function ===
+
+#[rustler::nif]
+fn blake2b<'a>(
+    env: Env<'a>,
+    data: Binary,
+    digest_len: usize,
+    key: Binary,
+    salt: Binary,
+    personal: Binary,
+) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("blake2b");
+    // Enforce RFC 7693 limits here: blake2b_simd panics on out-of-range parameters
+    if !(1..=blake2b_simd::OUTBYTES).contains(&digest_len)
+        || key.len() > blake2b_simd::KEYBYTES
+        || salt.len() > blake2b_simd::SALTBYTES
+        || personal.len() > blake2b_simd::PERSONALBYTES
+    {
+        return Err(rustler::Error::BadArg);
+    }
+
+    // Short salt/personalization values are zero-padded to 16 bytes, as in the reference implementation
+    let hash = blake2b_simd::Params::new()
+        .hash_length(digest_len)
+        .key(&key)
+        .salt(&salt)
+        .personal(&personal)
+        .hash(&data);
+
+    tracker.ok(make_binary(env, hash.as_bytes()))
+}
+
+// === Domain-Separated Hashing ===
+
+// Domains are short ASCII labels such as "bastille/tx-id"; the 1-byte length prefix
+// keeps (domain, data) pairs unambiguous without a separator character.
+const MAX_DOMAIN_LEN: usize = 255;
+
+// blake3(len(domain) || domain || data)
+fn tagged_hash(domain: &[u8], data: &[u8]) -> Option<blake3::Hash> {
+    if domain.is_empty() || domain.len() > MAX_DOMAIN_LEN || !domain.is_ascii() {
+        return None;
+    }
+
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(&[domain.len() as u8]);
+    hasher.update(domain);
+    hasher.update(data);
+    Some(hasher.finalize())
+}
+
+#[rustler::nif(name = "tagged_hash")]
+fn tagged_hash_nif<'a>(env: Env<'a>, domain: Binary, data: Binary) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("tagged_hash");
+    let hash = tagged_hash(&domain, &data).ok_or(rustler::Error::BadArg)?;
+    tracker.ok(make_binary(env, hash.as_bytes()))
+}
+
+// === Blake3 Incremental (Yielding) Hashing ===
+
+// Amount of input hashed between two timeslice checks
+const YIELD_SLICE_SIZE: usize = 64 * 1024;
+
+// One BEAM timeslice is roughly 1ms of work
+const TIMESLICE_MICROS: u128 = 1_000;
+
+struct Blake3HasherResource {
+    hasher: Mutex<blake3::Hasher>,
+}
+
+#[rustler::resource_impl]
+impl rustler::Resource for Blake3HasherResource {}
+
+#[rustler::nif]
+fn blake3_hasher_new() -> ResourceArc<Blake3HasherResource> {
+    let tracker = tracked!("blake3_hasher_new");
+    tracker.done(ResourceArc::new(Blake3HasherResource {
+        hasher: Mutex::new(blake3::Hasher::new()),
+    }))
+}
+
+// Hash `data` from `offset` until done or the timeslice is used up; the caller
+// loops on `{:cont, offset}` so other processes get scheduled in between.
+#[rustler::nif]
+fn blake3_hasher_update<'a>(
+    env: Env<'a>,
+    resource: ResourceArc<Blake3HasherResource>,
+    data: Binary,
+    offset: usize,
+) -> NifResult<Term<'a>> {
+    let tracker = tracked!("blake3_hasher_update");
+    if offset > data.len() {
+        return Err(rustler::Error::BadArg);
+    }
+
+    let mut hasher = resource.hasher.lock().map_err(|_| rustler::Error::BadArg)?;
+    let mut position = offset;
+    while position < data.len() {
+        let end = (position + YIELD_SLICE_SIZE).min(data.len());
+        let started = Instant::now();
+        hasher.update(&data[position..end]);
+        position = end;
+
+        let percent = (started.elapsed().as_micros() * 100 / TIMESLICE_MICROS).clamp(1, 100) as i32;
+        if position < data.len() && rustler::schedule::consume_timeslice(env, percent) {
+            return tracker.ok((cont(), position).encode(env));
+        }
+    }
+
+    tracker.ok(ok().encode(env))
+}
+
+#[rustler::nif]
+fn blake3_hasher_finalize<'a>(env: Env<'a>, resource: ResourceArc<Blake3HasherResource>) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("blake3_hasher_finalize");
+    let hasher = resource.hasher.lock().map_err(|_| rustler::Error::BadArg)?;
+    tracker.ok(make_binary(env, hasher.finalize().as_bytes()))
+}
+
+// === Proof of Work ===
+
+fn pow_target(target: &Binary) -> NifResult<target::Target> {
+    target.as_slice().try_into().map_err(|_| rustler::Error::BadArg)
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn pow_mine<'a>(env: Env<'a>, header: Binary, target: Binary, start_nonce: u64, max_iterations: u64) -> NifResult<Term<'a>> {
+    let tracker = tracked!("pow_mine");
+    let target = pow_target(&target)?;
+    let header = header.as_slice();
+    let found = threads::install(|| pow::mine(header, &target, start_nonce, max_iterations));
+    tracker.ok(match found {
+        Some(nonce) => nonce.encode(env),
+        None => exhausted().encode(env),
+    })
+}
+
+#[rustler::nif]
+fn pow_verify(header: Binary, nonce: u64, target: Binary) -> NifResult<bool> {
+    let tracker = tracked!("pow_verify");
+    tracker.ok(pow::verify(&header, nonce, &pow_target(&target)?))
+}
+
+// === Difficulty Targets ===
+
+// Bitcoin's factor-of-4 limit on a single retarget
+const DEFAULT_MAX_RETARGET_FACTOR: u64 = 4;
+
+#[rustler::nif]
+fn target_from_compact(env: Env, bits: u32) -> NifResult<Binary> {
+    let tracker = tracked!("target_from_compact");
+    let decoded = target::from_compact(bits).map_err(term_error)?;
+    tracker.ok(make_binary(env, &decoded))
+}
+
+#[rustler::nif]
+fn target_to_compact(target: Binary) -> NifResult<u32> {
+    let tracker = tracked!("target_to_compact");
+    tracker.ok(target::to_compact(&pow_target(&target)?))
+}
+
+#[rustler::nif]
+fn target_meets(hash: Binary, target: Binary) -> NifResult<bool> {
+    let tracker = tracked!("target_meets");
+    tracker.ok(pow_target(&hash)? <= pow_target(&target)?)
+}
+
+fn retarget(
+    target: &Binary,
+    first_timestamp: i64,
+    last_timestamp: i64,
+    target_timespan: u64,
+    max_factor: u64,
+    max_target: target::Target,
+) -> NifResult<target::Target> {
+    if target_timespan == 0 || max_factor == 0 {
+        return Err(rustler::Error::BadArg);
+    }
+    let actual_timespan = last_timestamp.saturating_sub(first_timestamp);
+    Ok(target::retarget(&pow_target(target)?, actual_timespan, target_timespan, max_factor, &max_target))
+}
+
+#[rustler::nif]
+fn next_target<'a>(env: Env<'a>, target: Binary, first_timestamp: i64, last_timestamp: i64, target_timespan: u64) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("next_target");
+    let next = retarget(&target, first_timestamp, last_timestamp, target_timespan, DEFAULT_MAX_RETARGET_FACTOR, [0xff; 32])?;
+    tracker.ok(make_binary(env, &next))
+}
+
+#[rustler::nif(name = "next_target")]
+fn next_target_with_opts<'a>(
+    env: Env<'a>,
+    target: Binary,
+    first_timestamp: i64,
+    last_timestamp: i64,
+    target_timespan: u64,
+    opts: Vec<(Atom, Term)>,
+) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("next_target");
+    let mut factor = DEFAULT_MAX_RETARGET_FACTOR;
+    let mut limit = [0xff; 32];
+    for (key, value) in opts {
+        if key == max_factor() {
+            factor = value.decode()?;
+        } else if key == max_target() {
+            limit = pow_target(&value.decode()?)?;
+        } else {
+            return Err(rustler::Error::BadArg);
+        }
+    }
+    let next = retarget(&target, first_timestamp, last_timestamp, target_timespan, factor, limit)?;
+    tracker.ok(make_binary(env, &next))
+}
+
+// === RandomX ===
+// The CPU-mining proof of work (the `randomx` feature).
+
+// RandomX keys are at most 60 bytes
+#[cfg(feature = "randomx")]
+const RANDOMX_MAX_KEY_LEN: usize = 60;
+
+#[cfg(feature = "randomx")]
+#[rustler::nif(schedule = "DirtyCpu")]
+fn randomx_init_cache(key: Binary) -> NifResult<ResourceArc<randomx::RandomX>> {
+    let tracker = tracked!("randomx_init_cache");
+    if key.is_empty() || key.len() > RANDOMX_MAX_KEY_LEN {
+        return Err(rustler::Error::BadArg);
+    }
+    tracker.ok(ResourceArc::new(randomx::RandomX::new(&key).map_err(term_error)?))
+}
+
+#[cfg(feature = "randomx")]
+#[rustler::nif(schedule = "DirtyCpu")]
+fn randomx_init_dataset(cache: ResourceArc<randomx::RandomX>) -> NifResult<ResourceArc<randomx::RandomX>> {
+    let tracker = tracked!("randomx_init_dataset");
+    tracker.ok(ResourceArc::new(cache.with_dataset().map_err(term_error)?))
+}
+
+#[cfg(feature = "randomx")]
+#[rustler::nif(schedule = "DirtyCpu")]
+fn randomx_hash<'a>(env: Env<'a>, handle: ResourceArc<randomx::RandomX>, input: Binary) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("randomx_hash");
+    if input.is_empty() {
+        return Err(rustler::Error::BadArg);
+    }
+    tracker.ok(make_binary(env, &handle.hash(&input).map_err(term_error)?))
+}
+
+#[cfg(feature = "randomx")]
+#[rustler::nif(schedule = "DirtyCpu")]
+fn randomx_verify(handle: ResourceArc<randomx::RandomX>, header: Binary, nonce: u64, target: Binary) -> NifResult<bool> {
+    let tracker = tracked!("randomx_verify");
+    tracker.finish(handle.verify(&header, nonce, &pow_target(&target)?).map_err(term_error))
+}
+
+// === File Hashing ===
+
+fn file_error(path: &str, e: std::io::Error) -> rustler::Error {
+    rustler::Error::Term(Box::new(format!("Failed to hash file '{}': {}", path, e)))
+}
+
+// Memory-maps the file (falls back to buffered reads for small or special files)
+#[rustler::nif(schedule = "DirtyIo")]
+fn blake3_hash_file<'a>(env: Env<'a>, path: String) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("blake3_hash_file");
+    let mut hasher = blake3::Hasher::new();
+    threads::install(|| hasher.update_mmap_rayon(&path).map(|_| ())).map_err(|e| file_error(&path, e))?;
+
+    tracker.ok(make_binary(env, hasher.finalize().as_bytes()))
+}
+
+#[rustler::nif(schedule = "DirtyIo")]
+fn sha256_file<'a>(env: Env<'a>, path: String) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("sha256_file");
+    let mut file = fs::File::open(&path).map_err(|e| file_error(&path, e))?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; YIELD_SLICE_SIZE];
+    loop {
+        let read = file.read(&mut buffer).map_err(|e| file_error(&path, e))?;
+        if read == 0 {
+            break;
+        }
+        Digest::update(&mut hasher, &buffer[..read]);
+    }
+
+    tracker.ok(make_binary(env, &hasher.finalize()))
+}
+
+// === SHA-3 Hash Functions ===
+
+#[rustler::nif]
+fn sha3_256<'a>(env: Env<'a>, data: Binary) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("sha3_256");
+    tracker.ok(make_binary(env, &Sha3_256::digest(data.as_slice())))
+}
+
+#[rustler::nif]
+fn sha3_512<'a>(env: Env<'a>, data: Binary) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("sha3_512");
+    tracker.ok(make_binary(env, &Sha3_512::digest(data.as_slice())))
+}
+
+// Legacy Keccak padding (0x01) as used by Ethereum, not the FIPS 202 SHA3 padding (0x06)
+#[rustler::nif]
+fn keccak256<'a>(env: Env<'a>, data: Binary) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("keccak256");
+    tracker.ok(make_binary(env, &Keccak256::digest(data.as_slice())))
+}
+
+// Squeeze `output_len` bytes out of a SHAKE instance straight into a BEAM binary
+fn shake_into_binary<'a, X: ExtendableOutput + Update + Default>(
+    env: Env<'a>,
+    data: &[u8],
+    output_len: usize,
+) -> NifResult<Binary<'a>> {
+    if output_len > MAX_XOF_OUTPUT_LEN {
+        return Err(rustler::Error::BadArg);
+    }
+
+    let mut xof = X::default();
+    xof.update(data);
+    let mut result_binary = NewBinary::new(env, output_len);
+    xof.finalize_xof().read(result_binary.as_mut_slice());
+
+    Ok(result_binary.into())
+}
+
+#[rustler::nif]
+fn shake128<'a>(env: Env<'a>, data: Binary, output_len: usize) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("shake128");
+    tracker.finish(shake_into_binary::<Shake128>(env, &data, output_len))
+}
+
+#[rustler::nif]
+fn shake256<'a>(env: Env<'a>, data: Binary, output_len: usize) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("shake256");
+    tracker.finish(shake_into_binary::<Shake256>(env, &data, output_len))
+}
+
+// === SHA-2 Hash Functions ===
+
+#[rustler::nif]
+fn sha256<'a>(env: Env<'a>, data: Binary) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("sha256");
+    tracker.ok(make_binary(env, &Sha256::digest(data.as_slice())))
+}
+
+// SHA-256(SHA-256(data)), as used for Bitcoin block headers and Merkle nodes
+#[rustler::nif]
+fn sha256d<'a>(env: Env<'a>, data: Binary) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("sha256d");
+    tracker.ok(make_binary(env, &Sha256::digest(Sha256::digest(data.as_slice()))))
+}
+
+#[rustler::nif]
+fn sha512<'a>(env: Env<'a>, data: Binary) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("sha512");
+    tracker.ok(make_binary(env, &Sha512::digest(data.as_slice())))
+}
+
+// === KangarooTwelve ===
+
+// Dirty scheduler: meant for multi-megabyte snapshots, leaves are hashed on the rayon pool
+#[rustler::nif(schedule = "DirtyCpu")]
+fn k12_hash<'a>(env: Env<'a>, data: Binary, customization: Binary, output_len: usize) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("k12_hash");
+    if output_len > MAX_XOF_OUTPUT_LEN {
+        return Err(rustler::Error::BadArg);
+    }
+
+    let mut result_binary = NewBinary::new(env, output_len);
+    k12::k12(&data, &customization, result_binary.as_mut_slice());
+
+    tracker.ok(result_binary.into())
+}
+
+// === Poseidon Hash (BN254) ===
+
+#[rustler::nif]
+fn poseidon_hash<'a>(env: Env<'a>, inputs: Vec<Binary>) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("poseidon_hash");
+    if inputs.is_empty() || inputs.len() > poseidon::MAX_INPUTS {
+        return Err(rustler::Error::BadArg);
+    }
+
+    let slices: Vec<&[u8]> = inputs.iter().map(|input| input.as_slice()).collect();
+    let hash = poseidon::hash_elements(&slices).map_err(|_| rustler::Error::BadArg)?;
+
+    tracker.ok(make_binary(env, &hash))
+}
+
+#[rustler::nif]
+fn poseidon_sponge<'a>(env: Env<'a>, data: Binary) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("poseidon_sponge");
+    let hash = poseidon::sponge(&data)
+        .map_err(|e| rustler::Error::Term(Box::new(format!("Poseidon failure: {}", e))))?;
+
+    tracker.ok(make_binary(env, &hash))
+}
+
+// === Merkle Trees ===
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn merkle_root<'a>(env: Env<'a>, leaves: Vec<Binary>) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("merkle_root");
+    let leaves: Vec<&[u8]> = leaves.iter().map(|leaf| leaf.as_slice()).collect();
+    tracker.ok(make_binary(env, &merkle::root(&leaves)))
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn merkle_build<'a>(env: Env<'a>, leaves: Vec<Binary>) -> NifResult<(Binary<'a>, ResourceArc<merkle::MerkleTree>)> {
+    let tracker = tracked!("merkle_build");
+    let leaves: Vec<&[u8]> = leaves.iter().map(|leaf| leaf.as_slice()).collect();
+    let tree = merkle::MerkleTree::build(&leaves);
+    tracker.ok((make_binary(env, &tree.root()), ResourceArc::new(tree)))
+}
+
+#[rustler::nif]
+fn merkle_proof<'a>(env: Env<'a>, tree: ResourceArc<merkle::MerkleTree>, index: usize) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("merkle_proof");
+    let proof = tree.proof(index).ok_or(rustler::Error::BadArg)?;
+    tracker.ok(make_binary(env, &proof))
+}
+
+#[rustler::nif]
+fn merkle_verify(root: Binary, leaf: Binary, proof: Binary) -> bool {
+    let tracker = tracked!("merkle_verify");
+    tracker.done(merkle::verify_proof(&root, &leaf, &proof))
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn merkle_multiproof<'a>(env: Env<'a>, tree: ResourceArc<merkle::MerkleTree>, indices: Vec<usize>) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("merkle_multiproof");
+    let proof = tree.multiproof(&indices).ok_or(rustler::Error::BadArg)?;
+    tracker.ok(make_binary(env, &proof))
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn merkle_verify_multiproof(root: Binary, leaves: Vec<(usize, Binary)>, proof: Binary) -> bool {
+    let tracker = tracked!("merkle_verify_multiproof");
+    let leaves: Vec<(usize, &[u8])> = leaves.iter().map(|(index, leaf)| (*index, leaf.as_slice())).collect();
+    tracker.done(merkle::verify_multiproof(&root, &leaves, &proof))
+}
+
+// === Incremental Merkle Trees ===
+
+#[rustler::nif]
+fn imt_new(depth: usize) -> NifResult<ResourceArc<incremental_merkle::IncrementalMerkleTree>> {
+    let tracker = tracked!("imt_new");
+    let tree = incremental_merkle::IncrementalMerkleTree::new(depth).ok_or(rustler::Error::BadArg)?;
+    tracker.ok(ResourceArc::new(tree))
+}
+
+#[rustler::nif]
+fn imt_append(tree: ResourceArc<incremental_merkle::IncrementalMerkleTree>, leaf: Binary) -> NifResult<u64> {
+    let tracker = tracked!("imt_append");
+    tracker.finish(tree.append(&leaf).map_err(term_error))
+}
+
+#[rustler::nif]
+fn imt_root<'a>(env: Env<'a>, tree: ResourceArc<incremental_merkle::IncrementalMerkleTree>) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("imt_root");
+    tracker.ok(make_binary(env, &tree.root().map_err(term_error)?))
+}
+
+#[rustler::nif]
+fn imt_count(tree: ResourceArc<incremental_merkle::IncrementalMerkleTree>) -> NifResult<u64> {
+    let tracker = tracked!("imt_count");
+    tracker.finish(tree.count().map_err(term_error))
+}
+
+#[rustler::nif]
+fn imt_snapshot<'a>(env: Env<'a>, tree: ResourceArc<incremental_merkle::IncrementalMerkleTree>) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("imt_snapshot");
+    tracker.ok(make_binary(env, &tree.snapshot().map_err(term_error)?))
+}
+
+#[rustler::nif]
+fn imt_restore(snapshot: Binary) -> NifResult<ResourceArc<incremental_merkle::IncrementalMerkleTree>> {
+    let tracker = tracked!("imt_restore");
+    let tree = incremental_merkle::IncrementalMerkleTree::restore(&snapshot).ok_or(rustler::Error::BadArg)?;
+    tracker.ok(ResourceArc::new(tree))
+}
+
+// === Merkle Mountain Ranges ===
+
+#[rustler::nif]
+fn mmr_new() -> ResourceArc<mmr::MountainRange> {
+    let tracker = tracked!("mmr_new");
+    tracker.done(ResourceArc::new(mmr::MountainRange::new()))
+}
+
+#[rustler::nif]
+fn mmr_append(range: ResourceArc<mmr::MountainRange>, leaf: Binary) -> NifResult<u64> {
+    let tracker = tracked!("mmr_append");
+    tracker.finish(range.append(&leaf).map_err(term_error))
+}
+
+#[rustler::nif]
+fn mmr_root<'a>(env: Env<'a>, range: ResourceArc<mmr::MountainRange>) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("mmr_root");
+    tracker.ok(make_binary(env, &range.root().map_err(term_error)?))
+}
+
+#[rustler::nif]
+fn mmr_proof<'a>(env: Env<'a>, range: ResourceArc<mmr::MountainRange>, index: u64) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("mmr_proof");
+    let proof = range.proof(index).map_err(term_error)?.ok_or(rustler::Error::BadArg)?;
+    tracker.ok(make_binary(env, &proof))
+}
+
+#[rustler::nif]
+fn mmr_verify(root: Binary, index: u64, leaf: Binary, proof: Binary) -> bool {
+    let tracker = tracked!("mmr_verify");
+    tracker.done(mmr::verify_proof(&root, index, &leaf, &proof))
+}
+
+// === Sparse Merkle Tree ===
+
+type StateTreeArc = ResourceArc<state_tree::StateTree>;
+
+fn open_state_tree(path: &str, backend: state_tree::Backend) -> NifResult<StateTreeArc> {
+    let tree = state_tree::StateTree::open(std::path::Path::new(path), backend).map_err(term_error)?;
+    Ok(ResourceArc::new(tree))
+}
+
+#[rustler::nif(schedule = "DirtyIo")]
+fn smt_open(path: String) -> NifResult<StateTreeArc> {
+    let tracker = tracked!("smt_open");
+    tracker.finish(open_state_tree(&path, state_tree::Backend::Sparse))
+}
+
+#[rustler::nif(name = "smt_open", schedule = "DirtyIo")]
+fn smt_open_with_opts(path: String, opts: Vec<(Atom, Term)>) -> NifResult<StateTreeArc> {
+    let tracker = tracked!("smt_open");
+    let mut backend = state_tree::Backend::Sparse;
+    for (key, value) in opts {
+        if key != backend_key() {
+            return Err(rustler::Error::BadArg);
+        }
+        let value: Atom = value.decode()?;
+        backend = if value == sparse() {
+            state_tree::Backend::Sparse
+        } else if value == jellyfish() {
+            state_tree::Backend::Jellyfish
+        } else {
+            return Err(rustler::Error::BadArg);
+        };
+    }
+    tracker.finish(open_state_tree(&path, backend))
+}
+
+// Dirty like the writers: it waits on the same lock
+#[rustler::nif(schedule = "DirtyIo")]
+fn smt_get<'a>(env: Env<'a>, tree: StateTreeArc, key: Binary) -> NifResult<Option<Binary<'a>>> {
+    let tracker = tracked!("smt_get");
+    tracker.finish(tree.get(&key, None, |value| value.map(|value| make_binary(env, value))).map_err(term_error))
+}
+
+#[rustler::nif(name = "smt_get", schedule = "DirtyIo")]
+fn smt_get_at<'a>(env: Env<'a>, tree: StateTreeArc, key: Binary, version: u64) -> NifResult<Option<Binary<'a>>> {
+    let tracker = tracked!("smt_get");
+    tracker.finish(tree.get(&key, Some(version), |value| value.map(|value| make_binary(env, value))).map_err(term_error))
+}
+
+#[rustler::nif(schedule = "DirtyIo")]
+fn smt_put(tree: StateTreeArc, key: Binary, value: Binary) -> NifResult<Atom> {
+    let tracker = tracked!("smt_put");
+    if value.len() > smt::MAX_VALUE_LEN {
+        return Err(rustler::Error::BadArg);
+    }
+    tree.put(&key, &value).map_err(term_error)?;
+    tracker.ok(ok())
+}
+
+#[rustler::nif(schedule = "DirtyIo")]
+fn smt_delete(tree: StateTreeArc, key: Binary) -> NifResult<Atom> {
+    let tracker = tracked!("smt_delete");
+    tree.delete(&key).map_err(term_error)?;
+    tracker.ok(ok())
+}
+
+#[rustler::nif(schedule = "DirtyIo")]
+fn smt_commit<'a>(env: Env<'a>, tree: StateTreeArc, version: u64) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("smt_commit");
+    tracker.ok(make_binary(env, &tree.commit(version).map_err(term_error)?))
+}
+
+#[rustler::nif(schedule = "DirtyIo")]
+fn smt_flush(tree: StateTreeArc) -> NifResult<Atom> {
+    let tracker = tracked!("smt_flush");
+    tree.flush().map_err(term_error)?;
+    tracker.ok(ok())
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn smt_root<'a>(env: Env<'a>, tree: StateTreeArc) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("smt_root");
+    tracker.ok(make_binary(env, &tree.root(None).map_err(term_error)?))
+}
+
+#[rustler::nif(name = "smt_root", schedule = "DirtyCpu")]
+fn smt_root_at<'a>(env: Env<'a>, tree: StateTreeArc, version: u64) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("smt_root");
+    tracker.ok(make_binary(env, &tree.root(Some(version)).map_err(term_error)?))
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn smt_prove<'a>(env: Env<'a>, tree: StateTreeArc, key: Binary) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("smt_prove");
+    tracker.ok(make_binary(env, &tree.prove(&key, None).map_err(term_error)?))
+}
+
+#[rustler::nif(name = "smt_prove", schedule = "DirtyCpu")]
+fn smt_prove_at<'a>(env: Env<'a>, tree: StateTreeArc, key: Binary, version: u64) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("smt_prove");
+    tracker.ok(make_binary(env, &tree.prove(&key, Some(version)).map_err(term_error)?))
+}
+
+#[rustler::nif]
+fn smt_verify(root: Binary, key: Binary, value: Option<Binary>, proof: Binary) -> bool {
+    let tracker = tracked!("smt_verify");
+    tracker.done(smt::verify_proof(&root, &key, value.as_deref(), &proof))
+}
+
+// === Verkle Tree ===
+// Experimental, for comparing witness sizes with the sparse Merkle tree.
+
+#[cfg(feature = "verkle")]
+#[rustler::nif]
+fn verkle_new() -> ResourceArc<verkle::VerkleTree> {
+    let tracker = tracked!("verkle_new");
+    tracker.done(ResourceArc::new(verkle::VerkleTree::new()))
+}
+
+// Dirty like the writers: it waits on the same lock
+#[cfg(feature = "verkle")]
+#[rustler::nif(schedule = "DirtyCpu")]
+fn verkle_get<'a>(env: Env<'a>, tree: ResourceArc<verkle::VerkleTree>, key: Binary) -> NifResult<Option<Binary<'a>>> {
+    let tracker = tracked!("verkle_get");
+    tracker.finish(tree.get(&key, |value| value.map(|value| make_binary(env, value))).map_err(term_error))
+}
+
+#[cfg(feature = "verkle")]
+#[rustler::nif(schedule = "DirtyCpu")]
+fn verkle_put(tree: ResourceArc<verkle::VerkleTree>, key: Binary, value: Binary) -> NifResult<Atom> {
+    let tracker = tracked!("verkle_put");
+    if value.len() > smt::MAX_VALUE_LEN {
+        return Err(rustler::Error::BadArg);
+    }
+    tree.put(&key, &value).map_err(term_error)?;
+    tracker.ok(ok())
+}
+
+#[cfg(feature = "verkle")]
+#[rustler::nif(schedule = "DirtyCpu")]
+fn verkle_delete(tree: ResourceArc<verkle::VerkleTree>, key: Binary) -> NifResult<Atom> {
+    let tracker = tracked!("verkle_delete");
+    tree.delete(&key).map_err(term_error)?;
+    tracker.ok(ok())
+}
+
+#[cfg(feature = "verkle")]
+#[rustler::nif(schedule = "DirtyCpu")]
+fn verkle_root<'a>(env: Env<'a>, tree: ResourceArc<verkle::VerkleTree>) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("verkle_root");
+    tracker.ok(make_binary(env, &tree.root().map_err(term_error)?))
+}
+
+#[cfg(feature = "verkle")]
+#[rustler::nif(schedule = "DirtyCpu")]
+fn verkle_prove<'a>(env: Env<'a>, tree: ResourceArc<verkle::VerkleTree>, key: Binary) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("verkle_prove");
+    tracker.ok(make_binary(env, &tree.prove(&key).map_err(term_error)?))
+}
+
+#[cfg(feature = "verkle")]
+#[rustler::nif(schedule = "DirtyCpu")]
+fn verkle_verify(root: Binary, key: Binary, value: Option<Binary>, proof: Binary) -> bool {
+    let tracker = tracked!("verkle_verify");
+    tracker.done(verkle::verify_proof(&root, &key, value.as_deref(), &proof))
+}
+
+// === Merkle Patricia Trie ===
+
+#[rustler::nif]
+fn mpt_new() -> ResourceArc<mpt::PatriciaTrie> {
+    let tracker = tracked!("mpt_new");
+    tracker.done(ResourceArc::new(mpt::PatriciaTrie::new()))
+}
+
+// Ethereum stores an empty value as a deletion, which this trie doesn't do
+#[rustler::nif(schedule = "DirtyCpu")]
+fn mpt_insert(trie: ResourceArc<mpt::PatriciaTrie>, key: Binary, value: Binary) -> NifResult<Atom> {
+    let tracker = tracked!("mpt_insert");
+    if key.len() > mpt::MAX_KEY_LEN || value.is_empty() {
+        return Err(rustler::Error::BadArg);
+    }
+    trie.insert(&key, &value).map_err(term_error)?;
+    tracker.ok(ok())
+}
+
+// Dirty like the writers: it waits on the same lock
+#[rustler::nif(schedule = "DirtyCpu")]
+fn mpt_get<'a>(env: Env<'a>, trie: ResourceArc<mpt::PatriciaTrie>, key: Binary) -> NifResult<Option<Binary<'a>>> {
+    let tracker = tracked!("mpt_get");
+    tracker.finish(trie.get(&key, |value| value.map(|value| make_binary(env, value))).map_err(term_error))
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn mpt_root<'a>(env: Env<'a>, trie: ResourceArc<mpt::PatriciaTrie>) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("mpt_root");
+    tracker.ok(make_binary(env, &trie.root_hash().map_err(term_error)?))
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn mpt_proof<'a>(env: Env<'a>, trie: ResourceArc<mpt::PatriciaTrie>, key: Binary) -> NifResult<Vec<Binary<'a>>> {
+    let tracker = tracked!("mpt_proof");
+    let proof = trie.proof(&key).map_err(term_error)?;
+    tracker.ok(proof.iter().map(|node| make_binary(env, node)).collect())
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn mpt_verify_proof<'a>(env: Env<'a>, root: Binary, key: Binary, proof: Vec<Binary>) -> NifResult<Option<Binary<'a>>> {
+    let tracker = tracked!("mpt_verify_proof");
+    if root.len() != 32 || key.len() > mpt::MAX_KEY_LEN {
+        return Err(rustler::Error::BadArg);
+    }
+    let nodes: Vec<&[u8]> = proof.iter().map(|node| node.as_slice()).collect();
+    let value = mpt::verify_proof(&root, &key, &nodes).map_err(term_error)?;
+    tracker.ok(value.map(|value| make_binary(env, &value)))
+}
+
+// === RLP ===
+// Byte strings are binaries, lists are lists; rlp_encode also takes
+// non-negative integers, as their minimal big-endian bytes.
+
+fn rlp_encode_term(term: Term, depth_left: usize) -> NifResult<Vec<u8>> {
+    if let Ok(bytes) = term.decode::<Binary>() {
+        return Ok(rlp::encode_bytes(&bytes));
+    }
+    if let Ok(n) = term.decode::<u128>() {
+        let bytes = n.to_be_bytes();
+        let skip = bytes.iter().take_while(|byte| **byte == 0).count();
+        return Ok(rlp::encode_bytes(&bytes[skip..]));
+    }
+    let items = term.decode::<Vec<Term>>()?;
+    if depth_left == 0 {
+        return Err(rustler::Error::BadArg);
+    }
+    let items = items
+        .into_iter()
+        .map(|item| rlp_encode_term(item, depth_left - 1))
+        .collect::<NifResult<Vec<_>>>()?;
+    Ok(rlp::encode_list(&items))
+}
+
+fn rlp_tree_term<'a>(env: Env<'a>, tree: &rlp::Tree) -> Term<'a> {
+    match tree {
+        rlp::Tree::Bytes(bytes) => make_binary(env, bytes).encode(env),
+        rlp::Tree::List(items) => items.iter().map(|item| rlp_tree_term(env, item)).collect::<Vec<_>>().encode(env),
+    }
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn rlp_encode<'a>(env: Env<'a>, term: Term<'a>) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("rlp_encode");
+    tracker.ok(make_binary(env, &rlp_encode_term(term, rlp::MAX_DEPTH)?))
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn rlp_decode<'a>(env: Env<'a>, data: Binary) -> NifResult<Term<'a>> {
+    let tracker = tracked!("rlp_decode");
+    let tree = rlp::decode_tree(&data, rlp::MAX_DEPTH).map_err(term_error)?;
+    tracker.ok(rlp_tree_term(env, &tree))
+}
+
+#[rustler::nif(name = "rlp_decode", schedule = "DirtyCpu")]
+fn rlp_decode_with_opts<'a>(env: Env<'a>, data: Binary, opts: Vec<(Atom, Term)>) -> NifResult<Term<'a>> {
+    let tracker = tracked!("rlp_decode");
+    let mut depth = rlp::MAX_DEPTH;
+    let mut size = usize::MAX;
+    for (key, value) in opts {
+        if key == max_depth() {
+            depth = value.decode()?;
+            if depth > rlp::MAX_DEPTH {
+                return Err(rustler::Error::BadArg);
+            }
+        } else if key == max_size() {
+            size = value.decode()?;
+        } else {
+            return Err(rustler::Error::BadArg);
+        }
+    }
+    if data.len() > size {
+        return Err(term_error(format!("RLP input of {} bytes is over the {} byte limit", data.len(), size)));
+    }
+    let tree = rlp::decode_tree(&data, depth).map_err(term_error)?;
+    tracker.ok(rlp_tree_term(env, &tree))
+}
+
+// === Hex ===
+
+fn hex_case(value: Term, allowed: &[base16::Case]) -> NifResult<base16::Case> {
+    let atom = value.decode::<Atom>()?;
+    let case = match atom {
+        _ if atom == lower() => base16::Case::Lower,
+        _ if atom == upper() => base16::Case::Upper,
+        _ if atom == mixed() => base16::Case::Mixed,
+        _ => return Err(rustler::Error::BadArg),
+    };
+    if allowed.contains(&case) {
+        Ok(case)
+    } else {
+        Err(rustler::Error::BadArg)
+    }
+}
+
+fn hex_encode_to<'a>(env: Env<'a>, data: &[u8], case: base16::Case, prefix: bool) -> Binary<'a> {
+    let mut encoded = Vec::with_capacity(2 + data.len() * 2);
+    if prefix {
+        encoded.extend_from_slice(b"0x");
+    }
+    base16::encode(data, case, &mut encoded);
+    make_binary(env, &encoded)
+}
+
+#[rustler::nif]
+fn hex_encode<'a>(env: Env<'a>, data: Binary) -> Binary<'a> {
+    let tracker = tracked!("hex_encode");
+    tracker.done(hex_encode_to(env, &data, base16::Case::Lower, false))
+}
+
+#[rustler::nif(name = "hex_encode")]
+fn hex_encode_with_opts<'a>(env: Env<'a>, data: Binary, opts: Vec<(Atom, Term)>) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("hex_encode");
+    let mut case = base16::Case::Lower;
+    let mut with_prefix = false;
+    for (key, value) in opts {
+        if key == case_() {
+            case = hex_case(value, &[base16::Case::Lower, base16::Case::Upper])?;
+        } else if key == prefix() {
+            with_prefix = value.decode()?;
+        } else {
+            return Err(rustler::Error::BadArg);
+        }
+    }
+    tracker.ok(hex_encode_to(env, &data, case, with_prefix))
+}
+
+#[rustler::nif]
+fn hex_decode<'a>(env: Env<'a>, hex: Binary) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("hex_decode");
+    let decoded = base16::decode(&hex, base16::Case::Mixed).map_err(term_error)?;
+    tracker.ok(make_binary(env, &decoded))
+}
+
+#[rustler::nif(name = "hex_decode")]
+fn hex_decode_with_opts<'a>(env: Env<'a>, hex: Binary, opts: Vec<(Atom, Term)>) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("hex_decode");
+    let mut case = base16::Case::Mixed;
+    // Some(true): required, None: optional
+    let mut with_prefix = Some(false);
+    for (key, value) in opts {
+        if key == case_() {
+            case = hex_case(value, &[base16::Case::Lower, base16::Case::Upper, base16::Case::Mixed])?;
+        } else if key == prefix() {
+            with_prefix = match value.decode::<Atom>() {
+                Ok(atom) if atom == optional() => None,
+                _ => Some(value.decode()?),
+            };
+        } else {
+            return Err(rustler::Error::BadArg);
+        }
+    }
+    let digits = match (hex.strip_prefix(b"0x").or_else(|| hex.strip_prefix(b"0X")), with_prefix) {
+        (Some(digits), Some(true) | None) => digits,
+        (None, Some(true)) => return Err(term_error("hex is missing its 0x prefix".to_string())),
+        _ => &hex[..],
+    };
+    let decoded = base16::decode(digits, case).map_err(term_error)?;
+    tracker.ok(make_binary(env, &decoded))
+}
+
+// === Base64url ===
+
+#[rustler::nif]
+fn base64url_encode<'a>(env: Env<'a>, data: Binary) -> Binary<'a> {
+    let tracker = tracked!("base64url_encode");
+    let mut encoded = Vec::new();
+    base64url::encode(&data, true, &mut encoded);
+    tracker.done(make_binary(env, &encoded))
+}
+
+#[rustler::nif(name = "base64url_encode")]
+fn base64url_encode_with_opts<'a>(env: Env<'a>, data: Binary, opts: Vec<(Atom, Term)>) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("base64url_encode");
+    let mut padded = true;
+    for (key, value) in opts {
+        if key != padding() {
+            return Err(rustler::Error::BadArg);
+        }
+        padded = value.decode()?;
+    }
+    let mut encoded = Vec::new();
+    base64url::encode(&data, padded, &mut encoded);
+    tracker.ok(make_binary(env, &encoded))
+}
+
+#[rustler::nif]
+fn base64url_decode<'a>(env: Env<'a>, text: Binary) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("base64url_decode");
+    let decoded = base64url::decode(&text, base64url::Padding::Required, true).map_err(term_error)?;
+    tracker.ok(make_binary(env, &decoded))
+}
+
+#[rustler::nif(name = "base64url_decode")]
+fn base64url_decode_with_opts<'a>(env: Env<'a>, text: Binary, opts: Vec<(Atom, Term)>) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("base64url_decode");
+    let mut pad = base64url::Padding::Required;
+    let mut is_strict = true;
+    for (key, value) in opts {
+        if key == padding() {
+            pad = match value.decode::<Atom>()? {
+                atom if atom == optional() => base64url::Padding::Optional,
+                _ if value.decode::<bool>()? => base64url::Padding::Required,
+                _ => base64url::Padding::Refused,
+            };
+        } else if key == strict() {
+            is_strict = value.decode()?;
+        } else {
+            return Err(rustler::Error::BadArg);
+        }
+    }
+    let decoded = base64url::decode(&text, pad, is_strict).map_err(term_error)?;
+    tracker.ok(make_binary(env, &decoded))
+}
+
+// === Multiformats ===
+
+fn multihash_from_atom(hash: Atom) -> NifResult<multiformats::Hash> {
+    if hash == blake3() {
+        Ok(multiformats::Hash::Blake3)
+    } else if hash == sha2_256() {
+        Ok(multiformats::Hash::Sha2_256)
+    } else {
+        Err(rustler::Error::BadArg)
+    }
+}
+
+fn multihash_atom(hash: multiformats::Hash) -> Atom {
+    match hash {
+        multiformats::Hash::Blake3 => blake3(),
+        multiformats::Hash::Sha2_256 => sha2_256(),
+    }
+}
+
+fn multicodec_from_atom(codec: Atom) -> NifResult<multiformats::Codec> {
+    if codec == raw() {
+        Ok(multiformats::Codec::Raw)
+    } else if codec == dag_pb() {
+        Ok(multiformats::Codec::DagPb)
+    } else if codec == dag_cbor() {
+        Ok(multiformats::Codec::DagCbor)
+    } else {
+        Err(rustler::Error::BadArg)
+    }
+}
+
+fn multicodec_atom(codec: multiformats::Codec) -> Atom {
+    match codec {
+        multiformats::Codec::Raw => raw(),
+        multiformats::Codec::DagPb => dag_pb(),
+        multiformats::Codec::DagCbor => dag_cbor(),
+    }
+}
+
+fn multibase_from_atom(base: Atom) -> NifResult<multiformats::Base> {
+    if base == base32() {
+        Ok(multiformats::Base::Base32)
+    } else if base == base58btc() {
+        Ok(multiformats::Base::Base58btc)
+    } else if base == base16() {
+        Ok(multiformats::Base::Base16)
+    } else if base == base64url() {
+        Ok(multiformats::Base::Base64url)
+    } else {
+        Err(rustler::Error::BadArg)
+    }
+}
+
+fn multibase_atom(base: multiformats::Base) -> Atom {
+    match base {
+        multiformats::Base::Base32 => base32(),
+        multiformats::Base::Base58btc => base58btc(),
+        multiformats::Base::Base16 => base16(),
+        multiformats::Base::Base64url => base64url(),
+    }
+}
+
+#[rustler::nif]
+fn multihash_encode<'a>(env: Env<'a>, hash: Atom, digest: Binary) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("multihash_encode");
+    let mut encoded = Vec::new();
+    multiformats::put_multihash(&mut encoded, multihash_from_atom(hash)?, &digest).ok_or(rustler::Error::BadArg)?;
+    tracker.ok(make_binary(env, &encoded))
+}
+
+#[rustler::nif]
+fn multihash_decode<'a>(env: Env<'a>, multihash: Binary<'a>) -> NifResult<(Atom, Binary<'a>)> {
+    let tracker = tracked!("multihash_decode");
+    let (hash, digest) = multiformats::multihash(&multihash).map_err(term_error)?;
+    tracker.ok((multihash_atom(hash), make_binary(env, digest)))
+}
+
+fn cid_string(codec: Atom, hash: Atom, digest: &[u8], base: multiformats::Base) -> NifResult<String> {
+    let cid = multiformats::cid(multicodec_from_atom(codec)?, multihash_from_atom(hash)?, digest)
+        .ok_or(rustler::Error::BadArg)?;
+    // Every base's alphabet is ASCII
+    Ok(String::from_utf8_lossy(&multiformats::multibase_encode(base, &cid)).into_owned())
+}
+
+#[rustler::nif]
+fn cid_encode(codec: Atom, hash: Atom, digest: Binary) -> NifResult<String> {
+    let tracker = tracked!("cid_encode");
+    tracker.finish(cid_string(codec, hash, &digest, multiformats::Base::Base32))
+}
+
+#[rustler::nif(name = "cid_encode")]
+fn cid_encode_with_opts(codec: Atom, hash: Atom, digest: Binary, opts: Vec<(Atom, Atom)>) -> NifResult<String> {
+    let tracker = tracked!("cid_encode");
+    let mut encoding = multiformats::Base::Base32;
+    for (key, value) in opts {
+        if key != base() {
+            return Err(rustler::Error::BadArg);
+        }
+        encoding = multibase_from_atom(value)?;
+    }
+    tracker.finish(cid_string(codec, hash, &digest, encoding))
+}
+
+#[rustler::nif]
+fn cid_decode<'a>(env: Env<'a>, text: Binary) -> NifResult<(Atom, Atom, Binary<'a>)> {
+    let tracker = tracked!("cid_decode");
+    let (_, cid) = multiformats::multibase_decode(&text).map_err(term_error)?;
+    let (codec, hash, digest) = multiformats::read_cid(&cid).map_err(term_error)?;
+    tracker.ok((multicodec_atom(codec), multihash_atom(hash), make_binary(env, digest)))
+}
+
+#[rustler::nif]
+fn multibase_encode<'a>(env: Env<'a>, base: Atom, data: Binary) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("multibase_encode");
+    tracker.ok(make_binary(env, &multiformats::multibase_encode(multibase_from_atom(base)?, &data)))
+}
+
+#[rustler::nif]
+fn multibase_decode<'a>(env: Env<'a>, text: Binary) -> NifResult<(Atom, Binary<'a>)> {
+    let tracker = tracked!("multibase_decode");
+    let (base, data) = multiformats::multibase_decode(&text).map_err(term_error)?;
+    tracker.ok((multibase_atom(base), make_binary(env, &data)))
+}
+
+// === Varints ===
+
+// {value, rest} of what was read from the start of `data`, rest a sub-binary
+fn varint_term<'a>(env: Env<'a>, data: Binary<'a>, read: Result<(u64, usize), String>) -> NifResult<Term<'a>> {
+    let (n, len) = read.map_err(term_error)?;
+    Ok((n, data.make_subbinary(len, data.len() - len)?).encode(env))
+}
+
+#[rustler::nif]
+fn varint_encode(env: Env, n: u64) -> Binary {
+    let tracker = tracked!("varint_encode");
+    let mut encoded = Vec::new();
+    varint::put_uleb128(&mut encoded, n);
+    tracker.done(make_binary(env, &encoded))
+}
+
+#[rustler::nif]
+fn varint_decode<'a>(env: Env<'a>, data: Binary<'a>) -> NifResult<Term<'a>> {
+    let tracker = tracked!("varint_decode");
+    tracker.finish(varint_term(env, data, varint::read_uleb128(&data)))
+}
+
+#[rustler::nif]
+fn compact_size_encode(env: Env, n: u64) -> Binary {
+    let tracker = tracked!("compact_size_encode");
+    let mut encoded = Vec::new();
+    varint::put_compact_size(&mut encoded, n);
+    tracker.done(make_binary(env, &encoded))
+}
+
+#[rustler::nif]
+fn compact_size_decode<'a>(env: Env<'a>, data: Binary<'a>) -> NifResult<Term<'a>> {
+    let tracker = tracked!("compact_size_decode");
+    tracker.finish(varint_term(env, data, varint::read_compact_size(&data)))
+}
+
+// === SSZ ===
+// Schemas: :boolean, {:uint, bits} (8 to 128), {:bytes, n}, {:byte_list, limit},
+// {:bitvector, n}, {:bitlist, limit}, {:vector, schema, n},
+// {:list, schema, limit} and {:container, [field: schema, ...]}. Values are
+// booleans, integers, binaries, lists of booleans (bits), lists and maps keyed
+// by field name.
+
+fn ssz_type(term: Term) -> NifResult<ssz::Type> {
+    if let Ok(atom) = term.decode::<Atom>() {
+        return if atom == boolean() { Ok(ssz::Type::Bool) } else { Err(rustler::Error::BadArg) };
+    }
+    if let Ok((kind, item, len)) = term.decode::<(Atom, Term, usize)>() {
+        let item = Box::new(ssz_type(item)?);
+        return match kind {
+            _ if kind == vector() && len > 0 => Ok(ssz::Type::Vector(item, len)),
+            _ if kind == list() => Ok(ssz::Type::List(item, len)),
+            _ => Err(rustler::Error::BadArg),
+        };
+    }
+    let (kind, arg) = term.decode::<(Atom, Term)>()?;
+    if kind == container() {
+        let fields = arg
+            .decode::<Vec<(Atom, Term)>>()?
+            .into_iter()
+            .map(|(name, field)| Ok((name.to_term(term.get_env()).atom_to_string()?, ssz_type(field)?)))
+            .collect::<NifResult<Vec<_>>>()?;
+        return if fields.is_empty() { Err(rustler::Error::BadArg) } else { Ok(ssz::Type::Container(fields)) };
+    }
+    let n = arg.decode::<usize>()?;
+    match kind {
+        _ if kind == uint() && matches!(n, 8 | 16 | 32 | 64 | 128) => Ok(ssz::Type::Uint(n / 8)),
+        _ if kind == bytes() && n > 0 => Ok(ssz::Type::Bytes(n)),
+        _ if kind == byte_list() => Ok(ssz::Type::ByteList(n)),
+        _ if kind == bitvector() && n > 0 => Ok(ssz::Type::Bitvector(n)),
+        _ if kind == bitlist() => Ok(ssz::Type::Bitlist(n)),
+        _ => Err(rustler::Error::BadArg),
+    }
+}
+
+fn term_to_ssz(ty: &ssz::Type, term: Term) -> NifResult<ssz::Value> {
+    Ok(match ty {
+        ssz::Type::Bool => ssz::Value::Bool(term.decode()?),
+        ssz::Type::Uint(_) => ssz::Value::Uint(term.decode()?),
+        ssz::Type::Bytes(_) | ssz::Type::ByteList(_) => ssz::Value::Bytes(term.decode::<Binary>()?.to_vec()),
+        ssz::Type::Bitvector(_) | ssz::Type::Bitlist(_) => ssz::Value::Bits(term.decode()?),
+        ssz::Type::Vector(item, _) | ssz::Type::List(item, _) => ssz::Value::Items(
+            term.decode::<Vec<Term>>()?
+                .into_iter()
+                .map(|value| term_to_ssz(item, value))
+                .collect::<NifResult<_>>()?,
+        ),
+        // Other keys are ignored, so structs can be passed as they are
+        ssz::Type::Container(fields) => ssz::Value::Fields(
+            fields
+                .iter()
+                .map(|(name, field)| {
+                    let key = Atom::from_str(term.get_env(), name)?;
+                    term_to_ssz(field, term.map_get(key.encode(term.get_env()))?)
+                })
+                .collect::<NifResult<_>>()?,
+        ),
+    })
+}
+
+fn ssz_to_term<'a>(env: Env<'a>, ty: &ssz::Type, value: &ssz::Value) -> NifResult<Term<'a>> {
+    Ok(match (ty, value) {
+        (_, ssz::Value::Bool(value)) => value.encode(env),
+        (_, ssz::Value::Uint(n)) => n.encode(env),
+        (_, ssz::Value::Bytes(bytes)) => make_binary(env, bytes).encode(env),
+        (_, ssz::Value::Bits(bits)) => bits.encode(env),
+        (ssz::Type::Vector(item, _) | ssz::Type::List(item, _), ssz::Value::Items(items)) => items
+            .iter()
+            .map(|value| ssz_to_term(env, item, value))
+            .collect::<NifResult<Vec<_>>>()?
+            .encode(env),
+        (ssz::Type::Container(fields), ssz::Value::Fields(values)) => {
+            let pairs = fields
+                .iter()
+                .zip(values)
+                .map(|((name, field), value)| Ok((Atom::from_str(env, name)?.encode(env), ssz_to_term(env, field, value)?)))
+                .collect::<NifResult<Vec<_>>>()?;
+            Term::map_from_pairs(env, &pairs)?
+        }
+        _ => return Err(rustler::Error::BadArg),
+    })
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn ssz_encode<'a>(env: Env<'a>, schema: Term<'a>, value: Term<'a>) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("ssz_encode");
+    let ty = ssz_type(schema)?;
+    let mut encoded = Vec::new();
+    ssz::serialize(&ty, &term_to_ssz(&ty, value)?, &mut encoded).map_err(|_| rustler::Error::BadArg)?;
+    tracker.ok(make_binary(env, &encoded))
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn ssz_decode<'a>(env: Env<'a>, schema: Term<'a>, data: Binary) -> NifResult<Term<'a>> {
+    let tracker = tracked!("ssz_decode");
+    let ty = ssz_type(schema)?;
+    let value = ssz::deserialize(&ty, &data).map_err(term_error)?;
+    tracker.finish(ssz_to_term(env, &ty, &value))
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn ssz_hash_tree_root<'a>(env: Env<'a>, schema: Term<'a>, value: Term<'a>) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("ssz_hash_tree_root");
+    let ty = ssz_type(schema)?;
+    let root = ssz::hash_tree_root(&ty, &term_to_ssz(&ty, value)?).map_err(|_| rustler::Error::BadArg)?;
+    tracker.ok(make_binary(env, &root))
+}
+
+// === Gossip Messages ===
+// Messages are maps keyed by field name, as the Proto structs in envelope.ex
+// (which can be passed as they are); enums are their value names as atoms.
+
+fn term_to_gossip(message: &gossip::Message, term: Term) -> NifResult<gossip::Value> {
+    let env = term.get_env();
+    let mut values = Vec::with_capacity(message.fields.len());
+    for field in message.fields {
+        let value = match term.map_get(Atom::from_str(env, field.name)?.encode(env)) {
+            Ok(value) if value != rustler::types::atom::nil().encode(env) => value,
+            _ => {
+                values.push(None);
+                continue;
+            }
+        };
+        values.push(Some(match field.repeated {
+            Some(_) => gossip::Value::Repeated(
+                value
+                    .decode::<Vec<Term>>()?
+                    .into_iter()
+                    .map(|item| term_to_gossip_field(field, item))
+                    .collect::<NifResult<_>>()?,
+            ),
+            None => term_to_gossip_field(field, value)?,
+        }));
+    }
+    Ok(gossip::Value::Message(values))
+}
+
+fn term_to_gossip_field(field: &gossip::Field, term: Term) -> NifResult<gossip::Value> {
+    Ok(match field.kind {
+        gossip::Kind::Uint32 | gossip::Kind::Uint64 => gossip::Value::Int(term.decode()?),
+        gossip::Kind::Bool => gossip::Value::Int(term.decode::<bool>()? as u64),
+        gossip::Kind::Enum(names) => match term.decode::<Atom>() {
+            Ok(atom) => {
+                let name = atom.to_term(term.get_env()).atom_to_string()?;
+                let number = names.iter().position(|known| *known == name).ok_or(rustler::Error::BadArg)?;
+                gossip::Value::Int(number as u64)
+            }
+            Err(_) => gossip::Value::Int(term.decode()?),
+        },
+        gossip::Kind::String | gossip::Kind::Bytes => gossip::Value::Bytes(term.decode::<Binary>()?.to_vec()),
+        gossip::Kind::Message(message) => term_to_gossip(message, term)?,
+    })
+}
+
+// Unset fields come back as their proto3 defaults, and unset messages as nil
+fn gossip_to_term<'a>(env: Env<'a>, message: &gossip::Message, value: &gossip::Value) -> NifResult<Term<'a>> {
+    let gossip::Value::Message(values) = value else { return Err(rustler::Error::BadArg) };
+    let mut pairs = Vec::with_capacity(values.len());
+    for (field, value) in message.fields.iter().zip(values) {
+        let term = match (field.repeated, value) {
+            (_, Some(gossip::Value::Repeated(items))) => items
+                .iter()
+                .map(|item| gossip_field_to_term(env, field, Some(item)))
+                .collect::<NifResult<Vec<_>>>()?
+                .encode(env),
+            (Some(_), None) => Vec::<Term>::new().encode(env),
+            (Some(_), Some(_)) => return Err(rustler::Error::BadArg),
+            (None, value) => gossip_field_to_term(env, field, value.as_ref())?,
+        };
+        pairs.push((Atom::from_str(env, field.name)?.encode(env), term));
+    }
+    Term::map_from_pairs(env, &pairs)
+}
+
+fn gossip_field_to_term<'a>(env: Env<'a>, field: &gossip::Field, value: Option<&gossip::Value>) -> NifResult<Term<'a>> {
+    Ok(match (&field.kind, value) {
+        (gossip::Kind::Bool, value) => matches!(value, Some(gossip::Value::Int(1))).encode(env),
+        (gossip::Kind::Enum(names), value) => {
+            let number = match value {
+                Some(gossip::Value::Int(n)) => *n,
+                _ => 0,
+            };
+            match names.get(number as usize) {
+                Some(name) => Atom::from_str(env, name)?.encode(env),
+                None => number.encode(env),
+            }
+        }
+        (_, Some(gossip::Value::Int(n))) => n.encode(env),
+        (gossip::Kind::Uint32 | gossip::Kind::Uint64, None) => 0u64.encode(env),
+        (_, Some(gossip::Value::Bytes(bytes))) => make_binary(env, bytes).encode(env),
+        (gossip::Kind::String | gossip::Kind::Bytes, None) => make_binary(env, &[]).encode(env),
+        (gossip::Kind::Message(message), Some(value)) => gossip_to_term(env, message, value)?,
+        (gossip::Kind::Message(_), None) => rustler::types::atom::nil().encode(env),
+        _ => return Err(rustler::Error::BadArg),
+    })
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn gossip_encode<'a>(env: Env<'a>, command: Atom, message: Term<'a>) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("gossip_encode");
+    let name = command.to_term(env).atom_to_string()?;
+    let index = gossip::ENVELOPE.fields.iter().position(|field| field.name == name).ok_or(rustler::Error::BadArg)?;
+    let gossip::Kind::Message(schema) = gossip::ENVELOPE.fields[index].kind else { unreachable!() };
+    let mut encoded = Vec::new();
+    gossip::encode_envelope(index, term_to_gossip(schema, message)?, &mut encoded).map_err(|_| rustler::Error::BadArg)?;
+    tracker.ok(make_binary(env, &encoded))
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn gossip_decode<'a>(env: Env<'a>, data: Binary) -> NifResult<Term<'a>> {
+    let tracker = tracked!("gossip_decode");
+    let (index, value) = gossip::decode_envelope(&data).map_err(term_error)?;
+    let field = &gossip::ENVELOPE.fields[index];
+    let gossip::Kind::Message(schema) = field.kind else { unreachable!() };
+    tracker.ok((Atom::from_str(env, field.name)?, gossip_to_term(env, schema, &value)?).encode(env))
+}
+
+// === Borsh ===
+// Schemas: :u8 to :u128, :i8 to :i128, :bool, :unit, :string, :bytes,
+// {:bytes, n}, {:array, schema, n}, {:vec, schema}, {:option, schema},
+// {:struct, [field: schema, ...]}, {:enum, [variant: schema, ...]} and
+// {:map, key_schema, value_schema}. Values are integers, booleans, nil (unit,
+// and options that are None), binaries, lists, maps keyed by field name
+// (structs), variant atoms (unit variants) or {variant, value} and maps.
+
+fn borsh_named(term: Term) -> NifResult<Vec<(String, borsh::Type)>> {
+    term.decode::<Vec<(Atom, Term)>>()?
+        .into_iter()
+        .map(|(name, ty)| Ok((name.to_term(term.get_env()).atom_to_string()?, borsh_type(ty)?)))
+        .collect()
+}
+
+fn borsh_type(term: Term) -> NifResult<borsh::Type> {
+    if let Ok(atom) = term.decode::<Atom>() {
+        let name = atom.to_term(term.get_env()).atom_to_string()?;
+        return Ok(match name.as_str() {
+            "u8" | "u16" | "u32" | "u64" | "u128" => borsh::Type::Uint(name[1..].parse::<usize>().unwrap() / 8),
+            "i8" | "i16" | "i32" | "i64" | "i128" => borsh::Type::Int(name[1..].parse::<usize>().unwrap() / 8),
+            "bool" => borsh::Type::Bool,
+            "unit" => borsh::Type::Unit,
+            "string" => borsh::Type::String,
+            "bytes" => borsh::Type::Bytes,
+            _ => return Err(rustler::Error::BadArg),
+        });
+    }
+    let items = rustler::types::tuple::get_tuple(term)?;
+    let kind = items.first().ok_or(rustler::Error::BadArg)?.decode::<Atom>()?.to_term(term.get_env()).atom_to_string()?;
+    Ok(match (kind.as_str(), &items[1..]) {
+        ("bytes", [len]) => borsh::Type::FixedBytes(len.decode()?),
+        ("array", [item, len]) => borsh::Type::Array(Box::new(borsh_type(*item)?), len.decode()?),
+        ("vec", [item]) => borsh::Type::Vec(Box::new(borsh_type(*item)?)),
+        ("option", [item]) => borsh::Type::Option(Box::new(borsh_type(*item)?)),
+        ("struct", [fields]) => borsh::Type::Struct(borsh_named(*fields)?),
+        ("enum", [variants]) => {
+            let variants = borsh_named(*variants)?;
+            if variants.is_empty() || variants.len() > 256 {
+                return Err(rustler::Error::BadArg);
+            }
+            borsh::Type::Enum(variants)
+        }
+        ("map", [key, value]) => borsh::Type::Map(Box::new(borsh_type(*key)?), Box::new(borsh_type(*value)?)),
+        _ => return Err(rustler::Error::BadArg),
+    })
+}
+
+fn term_to_borsh(ty: &borsh::Type, term: Term) -> NifResult<borsh::Value> {
+    let env = term.get_env();
+    let is_nil = term == rustler::types::atom::nil().encode(env);
+    Ok(match ty {
+        borsh::Type::Uint(_) => borsh::Value::Uint(term.decode()?),
+        borsh::Type::Int(_) => borsh::Value::Int(term.decode()?),
+        borsh::Type::Bool => borsh::Value::Bool(term.decode()?),
+        borsh::Type::Unit if is_nil => borsh::Value::Unit,
+        borsh::Type::Unit => return Err(rustler::Error::BadArg),
+        borsh::Type::String | borsh::Type::Bytes | borsh::Type::FixedBytes(_) => {
+            borsh::Value::Bytes(term.decode::<Binary>()?.to_vec())
+        }
+        borsh::Type::Array(item, _) | borsh::Type::Vec(item) => borsh::Value::Items(
+            term.decode::<Vec<Term>>()?
+                .into_iter()
+                .map(|value| term_to_borsh(item, value))
+                .collect::<NifResult<_>>()?,
+        ),
+        borsh::Type::Option(_) if is_nil => borsh::Value::Option(None),
+        borsh::Type::Option(item) => borsh::Value::Option(Some(Box::new(term_to_borsh(item, term)?))),
+        // Other keys are ignored, so structs can be passed as they are
+        borsh::Type::Struct(fields) => borsh::Value::Items(
+            fields
+                .iter()
+                .map(|(name, field)| term_to_borsh(field, term.map_get(Atom::from_str(env, name)?.encode(env))?))
+                .collect::<NifResult<_>>()?,
+        ),
+        borsh::Type::Enum(variants) => {
+            let (name, value) = match term.decode::<(Atom, Term)>() {
+                Ok((name, value)) => (name, Some(value)),
+                Err(_) => (term.decode::<Atom>()?, None),
+            };
+            let name = name.to_term(env).atom_to_string()?;
+            let index = variants.iter().position(|(variant, _)| *variant == name).ok_or(rustler::Error::BadArg)?;
+            let value = match (&variants[index].1, value) {
+                (borsh::Type::Unit, None) => borsh::Value::Unit,
+                (variant, Some(value)) => term_to_borsh(variant, value)?,
+                (_, None) => return Err(rustler::Error::BadArg),
+            };
+            borsh::Value::Enum(index as u8, Box::new(value))
+        }
+        borsh::Type::Map(key_type, value_type) => borsh::Value::Map(
+            term.decode::<rustler::MapIterator>()?
+                .map(|(key, value)| Ok((term_to_borsh(key_type, key)?, term_to_borsh(value_type, value)?)))
+                .collect::<NifResult<_>>()?,
+        ),
+    })
+}
+
+fn borsh_to_term<'a>(env: Env<'a>, ty: &borsh::Type, value: &borsh::Value) -> NifResult<Term<'a>> {
+    Ok(match (ty, value) {
+        (_, borsh::Value::Uint(n)) => n.encode(env),
+        (_, borsh::Value::Int(n)) => n.encode(env),
+        (_, borsh::Value::Bool(value)) => value.encode(env),
+        (_, borsh::Value::Unit | borsh::Value::Option(None)) => rustler::types::atom::nil().encode(env),
+        (_, borsh::Value::Bytes(bytes)) => make_binary(env, bytes).encode(env),
+        (borsh::Type::Array(item, _) | borsh::Type::Vec(item), borsh::Value::Items(items)) => items
+            .iter()
+            .map(|value| borsh_to_term(env, item, value))
+            .collect::<NifResult<Vec<_>>>()?
+            .encode(env),
+        (borsh::Type::Option(item), borsh::Value::Option(Some(value))) => borsh_to_term(env, item, value)?,
+        (borsh::Type::Struct(fields), borsh::Value::Items(values)) => {
+            let pairs = fields
+                .iter()
+                .zip(values)
+                .map(|((name, field), value)| Ok((Atom::from_str(env, name)?.encode(env), borsh_to_term(env, field, value)?)))
+                .collect::<NifResult<Vec<_>>>()?;
+            Term::map_from_pairs(env, &pairs)?
+        }
+        (borsh::Type::Enum(variants), borsh::Value::Enum(index, value)) => {
+            let (name, variant) = &variants[*index as usize];
+            let name = Atom::from_str(env, name)?;
+            match variant {
+                borsh::Type::Unit => name.encode(env),
+                _ => (name, borsh_to_term(env, variant, value)?).encode(env),
+            }
+        }
+        (borsh::Type::Map(key_type, value_type), borsh::Value::Map(entries)) => {
+            let pairs = entries
+                .iter()
+                .map(|(key, value)| Ok((borsh_to_term(env, key_type, key)?, borsh_to_term(env, value_type, value)?)))
+                .collect::<NifResult<Vec<_>>>()?;
+            Term::map_from_pairs(env, &pairs)?
+        }
+        _ => return Err(rustler::Error::BadArg),
+    })
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn borsh_encode<'a>(env: Env<'a>, schema: Term<'a>, value: Term<'a>) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("borsh_encode");
+    let ty = borsh_type(schema)?;
+    let mut encoded = Vec::new();
+    borsh::encode(&ty, &term_to_borsh(&ty, value)?, &mut encoded).map_err(|_| rustler::Error::BadArg)?;
+    tracker.ok(make_binary(env, &encoded))
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn borsh_decode<'a>(env: Env<'a>, schema: Term<'a>, data: Binary) -> NifResult<Term<'a>> {
+    let tracker = tracked!("borsh_decode");
+    let ty = borsh_type(schema)?;
+    let value = borsh::decode(&ty, &data).map_err(term_error)?;
+    tracker.finish(borsh_to_term(env, &ty, &value))
+}
+
+// === Bloom Filters ===
+
+// Dirty: a large filter takes a while to allocate and clear
+#[rustler::nif(schedule = "DirtyCpu")]
+fn bloom_new(bits: u64, hashes: u8) -> NifResult<ResourceArc<bloom::BloomFilter>> {
+    let tracker = tracked!("bloom_new");
+    let filter = bloom::BloomFilter::new(bits, hashes).ok_or(rustler::Error::BadArg)?;
+    tracker.ok(ResourceArc::new(filter))
+}
+
+#[rustler::nif]
+fn bloom_insert(filter: ResourceArc<bloom::BloomFilter>, item: Binary) -> Atom {
+    let tracker = tracked!("bloom_insert");
+    filter.insert(&item);
+    tracker.done(ok())
+}
+
+#[rustler::nif]
+fn bloom_contains(filter: ResourceArc<bloom::BloomFilter>, item: Binary) -> bool {
+    let tracker = tracked!("bloom_contains");
+    tracker.done(filter.contains(&item))
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn bloom_serialize<'a>(env: Env<'a>, filter: ResourceArc<bloom::BloomFilter>) -> Binary<'a> {
+    let tracker = tracked!("bloom_serialize");
+    tracker.done(make_binary(env, &filter.serialize()))
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn bloom_deserialize(serialized: Binary) -> NifResult<ResourceArc<bloom::BloomFilter>> {
+    let tracker = tracked!("bloom_deserialize");
+    let filter = bloom::BloomFilter::deserialize(&serialized).ok_or(rustler::Error::BadArg)?;
+    tracker.ok(ResourceArc::new(filter))
+}
+
+// === Cuckoo Filters ===
+
+#[rustler::nif]
+fn cuckoo_new(capacity: usize) -> NifResult<ResourceArc<cuckoo::CuckooFilter>> {
+    let tracker = tracked!("cuckoo_new");
+    let filter = cuckoo::CuckooFilter::new(capacity).ok_or(rustler::Error::BadArg)?;
+    tracker.ok(ResourceArc::new(filter))
+}
+
+#[rustler::nif]
+fn cuckoo_insert(filter: ResourceArc<cuckoo::CuckooFilter>, item: Binary) -> NifResult<bool> {
+    let tracker = tracked!("cuckoo_insert");
+    tracker.finish(filter.insert(&item).map_err(term_error))
+}
+
+#[rustler::nif]
+fn cuckoo_contains(filter: ResourceArc<cuckoo::CuckooFilter>, item: Binary) -> NifResult<bool> {
+    let tracker = tracked!("cuckoo_contains");
+    tracker.finish(filter.contains(&item).map_err(term_error))
+}
+
+#[rustler::nif]
+fn cuckoo_delete(filter: ResourceArc<cuckoo::CuckooFilter>, item: Binary) -> NifResult<bool> {
+    let tracker = tracked!("cuckoo_delete");
+    tracker.finish(filter.delete(&item).map_err(term_error))
+}
+
+// === State Snapshots ===
+
+fn encode_chunks<'a>(env: Env<'a>, chunks: Vec<Vec<u8>>) -> Vec<Binary<'a>> {
+    chunks.iter().map(|chunk| make_binary(env, chunk)).collect()
+}
+
+#[rustler::nif]
+fn snapshot_encoder_new(chunk_size: usize) -> NifResult<ResourceArc<snapshot::SnapshotEncoder>> {
+    let tracker = tracked!("snapshot_encoder_new");
+    let encoder = snapshot::SnapshotEncoder::new(chunk_size).ok_or(rustler::Error::BadArg)?;
+    tracker.ok(ResourceArc::new(encoder))
+}
+
+// Values are stored in the external term format, so any term round-trips
+#[rustler::nif(schedule = "DirtyCpu")]
+fn snapshot_encode<'a>(
+    env: Env<'a>,
+    encoder: ResourceArc<snapshot::SnapshotEncoder>,
+    entries: Vec<(Binary, Term)>,
+) -> NifResult<Vec<Binary<'a>>> {
+    let tracker = tracked!("snapshot_encode");
+    let values: Vec<OwnedBinary> = entries.iter().map(|(_, value)| value.to_binary()).collect();
+    let entries: Vec<(&[u8], &[u8])> =
+        entries.iter().zip(&values).map(|((key, _), value)| (key.as_slice(), value.as_slice())).collect();
+    let chunks = encoder.append(&entries).map_err(term_error)?;
+    tracker.ok(encode_chunks(env, chunks))
+}
+
+#[rustler::nif]
+fn snapshot_encode_finish<'a>(env: Env<'a>, encoder: ResourceArc<snapshot::SnapshotEncoder>) -> NifResult<Vec<Binary<'a>>> {
+    let tracker = tracked!("snapshot_encode_finish");
+    let chunks = encoder.finish().map_err(term_error)?;
+    tracker.ok(encode_chunks(env, chunks))
+}
+
+#[rustler::nif]
+fn snapshot_decoder_new() -> ResourceArc<snapshot::SnapshotDecoder> {
+    let tracker = tracked!("snapshot_decoder_new");
+    tracker.done(ResourceArc::new(snapshot::SnapshotDecoder::new()))
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn snapshot_decode<'a>(
+    env: Env<'a>,
+    decoder: ResourceArc<snapshot::SnapshotDecoder>,
+    chunk: Binary,
+) -> NifResult<Vec<(Binary<'a>, Term<'a>)>> {
+    let tracker = tracked!("snapshot_decode");
+    let entries = decoder.decode(&chunk, |key, value| match env.binary_to_term(value) {
+        Some((term, len)) if len == value.len() => Ok((make_binary(env, key), term)),
+        _ => Err("invalid snapshot value".to_string()),
+    });
+    tracker.finish(entries.map_err(term_error))
+}
+
+#[rustler::nif]
+fn snapshot_decode_finish(decoder: ResourceArc<snapshot::SnapshotDecoder>) -> NifResult<u64> {
+    let tracker = tracked!("snapshot_decode_finish");
+    tracker.finish(decoder.finish().map_err(term_error))
+}
+
+// === State Diffs ===
+
+fn snapshot_chunks<'a>(chunks: &'a [Binary]) -> Vec<&'a [u8]> {
+    chunks.iter().map(|chunk| chunk.as_slice()).collect()
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn state_diff<'a>(env: Env<'a>, old: Vec<Binary>, new: Vec<Binary>) -> NifResult<Vec<Binary<'a>>> {
+    let tracker = tracked!("state_diff");
+    let chunks = state_diff::diff(&snapshot_chunks(&old), &snapshot_chunks(&new)).map_err(term_error)?;
+    tracker.ok(encode_chunks(env, chunks))
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn state_apply<'a>(env: Env<'a>, snapshot: Vec<Binary>, diff: Vec<Binary>) -> NifResult<Vec<Binary<'a>>> {
+    let tracker = tracked!("state_apply");
+    let chunks = state_diff::apply(&snapshot_chunks(&snapshot), &snapshot_chunks(&diff)).map_err(term_error)?;
+    tracker.ok(encode_chunks(env, chunks))
+}
+
+// === Encryption at Rest ===
+// Options shared by storage_open/2 and archive_open/2.
+
+type Opts<'a> = Vec<(Atom, Term<'a>)>;
+
+// Split :encryption_key and :retired_keys off `opts`, leaving the rest for the caller
+fn split_keyring(opts: Opts) -> NifResult<(Option<at_rest::Keyring>, Opts)> {
+    let mut current = None;
+    let mut retired = Vec::new();
+    let mut rest = Vec::with_capacity(opts.len());
+    for (key, value) in opts {
+        if key == encryption_key() {
+            current = Some(Zeroizing::new(value.decode::<Binary>()?.to_vec()));
+        } else if key == retired_keys() {
+            for key in value.decode::<Vec<Binary>>()? {
+                retired.push(Zeroizing::new(key.to_vec()));
+            }
+        } else {
+            rest.push((key, value));
+        }
+    }
+    let keys = match current {
+        Some(current) => Some(at_rest::Keyring::new(&current, &retired).map_err(|_| rustler::Error::BadArg)?),
+        None if retired.is_empty() => None,
+        None => return Err(rustler::Error::BadArg),
+    };
+    Ok((keys, rest))
+}
+
+// === Storage ===
+// Blocks, state and the transaction index in RocksDB (the `rocksdb` feature)
+// or LMDB (the `lmdb` feature).
+
+#[cfg(any(feature = "rocksdb", feature = "lmdb"))]
+fn column_from_atom(column: Atom) -> NifResult<storage::Column> {
+    if column == blocks() {
+        Ok(storage::Column::Blocks)
+    } else if column == state() {
+        Ok(storage::Column::State)
+    } else if column == tx_index() {
+        Ok(storage::Column::TxIndex)
+    } else {
+        Err(rustler::Error::BadArg)
+    }
+}
+
+#[cfg(any(feature = "rocksdb", feature = "lmdb"))]
+type StoreArc = ResourceArc<storage::Store>;
+
+#[cfg(any(feature = "rocksdb", feature = "lmdb"))]
+fn open_store(path: &str, backend: storage::Backend, keys: Option<at_rest::Keyring>) -> NifResult<StoreArc> {
+    let store = storage::Store::open(std::path::Path::new(path), backend, keys).map_err(term_error)?;
+    Ok(ResourceArc::new(store))
+}
+
+#[cfg(any(feature = "rocksdb", feature = "lmdb"))]
+#[rustler::nif(schedule = "DirtyIo")]
+fn storage_open(path: String) -> NifResult<StoreArc> {
+    let tracker = tracked!("storage_open");
+    tracker.finish(open_store(&path, storage::Backend::default_for_build(), None))
+}
+
+#[cfg(any(feature = "rocksdb", feature = "lmdb"))]
+#[rustler::nif(name = "storage_open", schedule = "DirtyIo")]
+fn storage_open_with_opts(path: String, opts: Vec<(Atom, Term)>) -> NifResult<StoreArc> {
+    let tracker = tracked!("storage_open");
+    let (keys, opts) = split_keyring(opts)?;
+    let mut use_lmdb = !cfg!(feature = "rocksdb");
+    let mut lmdb_map_size = storage::Backend::DEFAULT_LMDB_MAP_SIZE;
+    for (key, value) in opts {
+        if key == backend_key() {
+            let value: Atom = value.decode()?;
+            use_lmdb = if value == rocksdb() {
+                false
+            } else if value == lmdb() {
+                true
+            } else {
+                return Err(rustler::Error::BadArg);
+            };
+        } else if key == map_size() {
+            lmdb_map_size = value.decode()?;
+            if lmdb_map_size == 0 {
+                return Err(rustler::Error::BadArg);
+            }
+        } else {
+            return Err(rustler::Error::BadArg);
+        }
+    }
+    let backend = if use_lmdb {
+        storage::Backend::Lmdb { map_size: lmdb_map_size }
+    } else {
+        storage::Backend::Rocksdb
+    };
+    tracker.finish(open_store(&path, backend, keys))
+}
+
+#[cfg(any(feature = "rocksdb", feature = "lmdb"))]
+#[rustler::nif(schedule = "DirtyIo")]
+fn storage_close(store: StoreArc) -> NifResult<Atom> {
+    let tracker = tracked!("storage_close");
+    store.close().map_err(term_error)?;
+    tracker.ok(ok())
+}
+
+#[cfg(any(feature = "rocksdb", feature = "lmdb"))]
+#[rustler::nif(schedule = "DirtyIo")]
+fn storage_get<'a>(env: Env<'a>, store: StoreArc, column: Atom, key: Binary) -> NifResult<Option<Binary<'a>>> {
+    let tracker = tracked!("storage_get");
+    let column = column_from_atom(column)?;
+    tracker.finish(store.get(column, &key, |value| value.map(|value| make_binary(env, value))).map_err(term_error))
+}
+
+#[cfg(any(feature = "rocksdb", feature = "lmdb"))]
+#[rustler::nif(schedule = "DirtyIo")]
+fn storage_put(store: StoreArc, column: Atom, key: Binary, value: Binary) -> NifResult<Atom> {
+    let tracker = tracked!("storage_put");
+    store.put(column_from_atom(column)?, &key, &value).map_err(term_error)?;
+    tracker.ok(ok())
+}
+
+#[cfg(any(feature = "rocksdb", feature = "lmdb"))]
+#[rustler::nif(schedule = "DirtyIo")]
+fn storage_delete(store: StoreArc, column: Atom, key: Binary) -> NifResult<Atom> {
+    let tracker = tracked!("storage_delete");
+    store.delete(column_from_atom(column)?, &key).map_err(term_error)?;
+    tracker.ok(ok())
+}
+
+// Ops are {:put, column, key, value} and {:delete, column, key}
+#[cfg(any(feature = "rocksdb", feature = "lmdb"))]
+#[rustler::nif(schedule = "DirtyIo")]
+fn storage_write_batch(store: StoreArc, ops: Vec<Term>) -> NifResult<Atom> {
+    let tracker = tracked!("storage_write_batch");
+    let mut decoded = Vec::with_capacity(ops.len());
+    for op in ops {
+        if let Ok((tag, column, key, value)) = op.decode::<(Atom, Atom, Binary, Binary)>() {
+            if tag != put() {
+                return Err(rustler::Error::BadArg);
+            }
+            decoded.push((column_from_atom(column)?, key, Some(value)));
+        } else {
+            let (tag, column, key) = op.decode::<(Atom, Atom, Binary)>()?;
+            if tag != delete() {
+                return Err(rustler::Error::BadArg);
+            }
+            decoded.push((column_from_atom(column)?, key, None));
+        }
+    }
+    let ops: Vec<storage::Op> = decoded
+        .iter()
+        .map(|(column, key, value)| match value {
+            Some(value) => storage::Op::Put(*column, key, value),
+            None => storage::Op::Delete(*column, key),
+        })
+        .collect();
+    store.write(&ops).map_err(term_error)?;
+    tracker.ok(ok())
+}
+
+#[cfg(any(feature = "rocksdb", feature = "lmdb"))]
+#[rustler::nif(schedule = "DirtyIo")]
+fn storage_compact(store: StoreArc, column: Atom) -> NifResult<Atom> {
+    let tracker = tracked!("storage_compact");
+    store.compact(column_from_atom(column)?).map_err(term_error)?;
+    tracker.ok(ok())
+}
+
+#[cfg(any(feature = "rocksdb", feature = "lmdb"))]
+#[rustler::nif(schedule = "DirtyIo")]
+fn storage_rotate_key(store: StoreArc, key: Binary) -> NifResult<u64> {
+    let tracker = tracked!("storage_rotate_key");
+    tracker.finish(store.rotate_key(&key).map_err(term_error))
+}
+
+#[cfg(any(feature = "rocksdb", feature = "lmdb"))]
+#[rustler::nif]
+fn storage_iterator(store: StoreArc, column: Atom) -> NifResult<ResourceArc<storage::StoreIterator>> {
+    let tracker = tracked!("storage_iterator");
+    let iterator = storage::StoreIterator::new(store, column_from_atom(column)?, None, None, false);
+    tracker.ok(ResourceArc::new(iterator))
+}
+
+#[cfg(any(feature = "rocksdb", feature = "lmdb"))]
+#[rustler::nif(name = "storage_iterator")]
+fn storage_iterator_with_opts(
+    store: StoreArc,
+    column: Atom,
+    opts: Vec<(Atom, Term)>,
+) -> NifResult<ResourceArc<storage::StoreIterator>> {
+    let tracker = tracked!("storage_iterator");
+    let (mut lower, mut upper, mut descending) = (None, None, false);
+    for (key, value) in opts {
+        if key == from() {
+            lower = Some(value.decode::<Binary>()?.to_vec());
+        } else if key == to() {
+            upper = Some(value.decode::<Binary>()?.to_vec());
+        } else if key == reverse() {
+            descending = value.decode()?;
+        } else {
+            return Err(rustler::Error::BadArg);
+        }
+    }
+    let iterator = storage::StoreIterator::new(store, column_from_atom(column)?, lower, upper, descending);
+    tracker.ok(ResourceArc::new(iterator))
+}
+
+#[cfg(any(feature = "rocksdb", feature = "lmdb"))]
+#[rustler::nif]
+fn storage_iterator_seek(iterator: ResourceArc<storage::StoreIterator>, key: Binary) -> NifResult<Atom> {
+    let tracker = tracked!("storage_iterator_seek");
+    iterator.seek(&key).map_err(term_error)?;
+    tracker.ok(ok())
+}
+
+#[cfg(any(feature = "rocksdb", feature = "lmdb"))]
+#[rustler::nif(schedule = "DirtyIo")]
+fn storage_iterator_next<'a>(
+    env: Env<'a>,
+    iterator: ResourceArc<storage::StoreIterator>,
+    count: usize,
+) -> NifResult<Vec<(Binary<'a>, Binary<'a>)>> {
+    let tracker = tracked!("storage_iterator_next");
+    let entries = iterator.next(count).map_err(term_error)?;
+    tracker.ok(entries.iter().map(|(key, value)| (make_binary(env, key), make_binary(env, value))).collect())
+}
+
+// === Block Archive ===
+
+#[rustler::nif(schedule = "DirtyIo")]
+fn archive_open(path: String) -> NifResult<ResourceArc<archive::Archive>> {
+    let tracker = tracked!("archive_open");
+    let archive = archive::Archive::open(std::path::Path::new(&path), None).map_err(term_error)?;
+    tracker.ok(ResourceArc::new(archive))
+}
+
+#[rustler::nif(name = "archive_open", schedule = "DirtyIo")]
+fn archive_open_with_opts(path: String, opts: Vec<(Atom, Term)>) -> NifResult<ResourceArc<archive::Archive>> {
+    let tracker = tracked!("archive_open");
+    let (keys, opts) = split_keyring(opts)?;
+    if !opts.is_empty() {
+        return Err(rustler::Error::BadArg);
+    }
+    let archive = archive::Archive::open(std::path::Path::new(&path), keys).map_err(term_error)?;
+    tracker.ok(ResourceArc::new(archive))
+}
+
+#[rustler::nif(schedule = "DirtyIo")]
+fn archive_append(archive: ResourceArc<archive::Archive>, height: u64, block: Binary) -> NifResult<Atom> {
+    let tracker = tracked!("archive_append");
+    archive.append(height, &block).map_err(term_error)?;
+    tracker.ok(ok())
+}
+
+#[rustler::nif(schedule = "DirtyIo")]
+fn archive_read<'a>(env: Env<'a>, archive: ResourceArc<archive::Archive>, height: u64) -> NifResult<Option<Binary<'a>>> {
+    let tracker = tracked!("archive_read");
+    let block = archive.read(height).map_err(term_error)?;
+    tracker.ok(block.map(|block| make_binary(env, &block)))
+}
+
+#[rustler::nif(schedule = "DirtyIo")]
+fn archive_read_mapped<'a>(env: Env<'a>, archive: ResourceArc<archive::Archive>, height: u64) -> NifResult<Option<Binary<'a>>> {
+    let tracker = tracked!("archive_read_mapped");
+    // Encrypted blocks are decrypted into a binary of their own
+    if archive.is_encrypted().map_err(term_error)? {
+        let block = archive.read(height).map_err(term_error)?;
+        return tracker.ok(block.map(|block| make_binary(env, &block)));
+    }
+    let block = archive.read_mapped(height).map_err(term_error)?;
+    tracker.ok(block.map(|(map, range)| map.make_binary(env, |map| &map.bytes()[range])))
+}
+
+#[rustler::nif(schedule = "DirtyIo")]
+fn archive_last_height(archive: ResourceArc<archive::Archive>) -> NifResult<Option<u64>> {
+    let tracker = tracked!("archive_last_height");
+    tracker.finish(archive.last_height().map_err(term_error))
+}
+
+#[rustler::nif(schedule = "DirtyIo")]
+fn archive_rotate_key(archive: ResourceArc<archive::Archive>, key: Binary) -> NifResult<u64> {
+    let tracker = tracked!("archive_rotate_key");
+    tracker.finish(archive.rotate_key(&key).map_err(term_error))
+}
+
+#[rustler::nif(schedule = "DirtyIo")]
+fn archive_flush(archive: ResourceArc<archive::Archive>) -> NifResult<Atom> {
+    let tracker = tracked!("archive_flush");
+    archive.flush().map_err(term_error)?;
+    tracker.ok(ok())
+}
+
+// === Pruning ===
+
+// Targets are [blocks: archive, state: tree], either optional. Archive mode
+// keeps everything, full mode prunes state history and light mode block
+// bodies too.
+#[rustler::nif(schedule = "DirtyIo")]
+fn prune_below<'a>(env: Env<'a>, height: u64, mode: Atom, targets: Vec<(Atom, Term<'a>)>) -> NifResult<Term<'a>> {
+    let tracker = tracked!("prune_below");
+    let (prune_state, prune_blocks) = if mode == archive_mode() {
+        (false, false)
+    } else if mode == full() {
+        (true, false)
+    } else if mode == light() {
+        (true, true)
+    } else {
+        return Err(rustler::Error::BadArg);
+    };
+    let (mut archive, mut tree) = (None, None);
+    for (key, value) in targets {
+        if key == blocks() {
+            archive = Some(value.decode::<ResourceArc<archive::Archive>>()?);
+        } else if key == state() {
+            tree = Some(value.decode::<StateTreeArc>()?);
+        } else {
+            return Err(rustler::Error::BadArg);
+        }
+    }
+
+    let block_bytes = match archive {
+        Some(archive) if prune_blocks => archive.prune_below(height).map_err(term_error)?,
+        _ => 0,
+    };
+    let state_bytes = match tree {
+        Some(tree) if prune_state => tree.prune_below(height).map_err(term_error)?,
+        _ => 0,
+    };
+    tracker.finish(Term::map_from_pairs(
+        env,
+        &[(blocks().encode(env), block_bytes.encode(env)), (state().encode(env), state_bytes.encode(env))],
+    ))
+}
+
+// === Backups ===
+
+fn backup_summary<'a>(env: Env<'a>, summary: backup::Summary) -> NifResult<Term<'a>> {
+    Term::map_from_pairs(
+        env,
+        &[
+            (height().encode(env), summary.height.encode(env)),
+            (blocks().encode(env), summary.blocks.encode(env)),
+            (state().encode(env), summary.state.encode(env)),
+        ],
+    )
+}
+
+// Opts are [blocks: archive, state: tree, compress: boolean]
+#[rustler::nif(schedule = "DirtyIo")]
+fn backup_create<'a>(env: Env<'a>, path: String, up_to_height: u64, opts: Vec<(Atom, Term<'a>)>) -> NifResult<Term<'a>> {
+    let tracker = tracked!("backup_create");
+    let (mut archive, mut tree, mut compressed) = (None, None, false);
+    for (key, value) in opts {
+        if key == blocks() {
+            archive = Some(value.decode::<ResourceArc<archive::Archive>>()?);
+        } else if key == state() {
+            tree = Some(value.decode::<StateTreeArc>()?);
+        } else if key == compress() {
+            compressed = value.decode()?;
+        } else {
+            return Err(rustler::Error::BadArg);
+        }
+    }
+    let summary = backup::create(
+        std::path::Path::new(&path),
+        up_to_height,
+        archive.as_deref(),
+        tree.as_deref(),
+        compressed,
+    )
+    .map_err(term_error)?;
+    tracker.finish(backup_summary(env, summary))
+}
+
+#[rustler::nif(schedule = "DirtyIo")]
+fn backup_verify(env: Env, path: String) -> NifResult<Term> {
+    let tracker = tracked!("backup_verify");
+    let summary = backup::verify(std::path::Path::new(&path)).map_err(term_error)?;
+    tracker.finish(backup_summary(env, summary))
+}
+
+// Opts are [blocks: directory, state: directory]
+#[rustler::nif(schedule = "DirtyIo")]
+fn backup_restore<'a>(env: Env<'a>, path: String, opts: Vec<(Atom, Term<'a>)>) -> NifResult<Term<'a>> {
+    let tracker = tracked!("backup_restore");
+    let (mut blocks_dir, mut state_dir) = (None, None);
+    for (key, value) in opts {
+        if key == blocks() {
+            blocks_dir = Some(std::path::PathBuf::from(value.decode::<String>()?));
+        } else if key == state() {
+            state_dir = Some(std::path::PathBuf::from(value.decode::<String>()?));
+        } else {
+            return Err(rustler::Error::BadArg);
+        }
+    }
+    let summary = backup::restore(std::path::Path::new(&path), blocks_dir.as_deref(), state_dir.as_deref())
+        .map_err(term_error)?;
+    tracker.finish(backup_summary(env, summary))
+}
+
+// === Write-Ahead Log ===
+
+fn open_wal(path: &str, policy: wal::SyncPolicy) -> NifResult<ResourceArc<wal::Wal>> {
+    let wal = wal::Wal::open(std::path::Path::new(path), policy).map_err(term_error)?;
+    Ok(ResourceArc::new(wal))
+}
+
+#[rustler::nif(schedule = "DirtyIo")]
+fn wal_open(path: String) -> NifResult<ResourceArc<wal::Wal>> {
+    let tracker = tracked!("wal_open");
+    tracker.finish(open_wal(&path, wal::SyncPolicy::Always))
+}
+
+#[rustler::nif(name = "wal_open", schedule = "DirtyIo")]
+fn wal_open_with_opts(path: String, opts: Vec<(Atom, Term)>) -> NifResult<ResourceArc<wal::Wal>> {
+    let tracker = tracked!("wal_open");
+    let mut policy = wal::SyncPolicy::Always;
+    for (key, value) in opts {
+        if key != sync() {
+            return Err(rustler::Error::BadArg);
+        }
+        policy = if let Ok((tag, millis)) = value.decode::<(Atom, u64)>() {
+            if tag != interval() {
+                return Err(rustler::Error::BadArg);
+            }
+            wal::SyncPolicy::Interval(std::time::Duration::from_millis(millis))
+        } else {
+            let value: Atom = value.decode()?;
+            if value == always() {
+                wal::SyncPolicy::Always
+            } else if value == manual() {
+                wal::SyncPolicy::Manual
+            } else {
+                return Err(rustler::Error::BadArg);
+            }
+        };
+    }
+    tracker.finish(open_wal(&path, policy))
+}
+
+#[rustler::nif(schedule = "DirtyIo")]
+fn wal_append(wal: ResourceArc<wal::Wal>, record: Binary) -> NifResult<u64> {
+    let tracker = tracked!("wal_append");
+    tracker.finish(wal.append(&record).map_err(term_error))
+}
+
+#[rustler::nif(schedule = "DirtyIo")]
+fn wal_sync(wal: ResourceArc<wal::Wal>) -> NifResult<Atom> {
+    let tracker = tracked!("wal_sync");
+    wal.sync().map_err(term_error)?;
+    tracker.ok(ok())
+}
+
+#[rustler::nif(schedule = "DirtyIo")]
+fn wal_replay<'a>(env: Env<'a>, wal: ResourceArc<wal::Wal>) -> NifResult<Vec<(u64, Binary<'a>)>> {
+    let tracker = tracked!("wal_replay");
+    let records = wal.replay().map_err(term_error)?;
+    tracker.ok(records.iter().map(|(lsn, record)| (*lsn, make_binary(env, record))).collect())
+}
+
+#[rustler::nif(schedule = "DirtyIo")]
+fn wal_checkpoint(wal: ResourceArc<wal::Wal>, lsn: u64) -> NifResult<Atom> {
+    let tracker = tracked!("wal_checkpoint");
+    wal.checkpoint(lsn).map_err(term_error)?;
+    tracker.ok(ok())
+}
+
+// === Message Authentication Codes ===
+
+type HmacSha256 = Hmac<Sha256>;
+
+#[rustler::nif]
+fn hmac_sha256<'a>(env: Env<'a>, key: Binary, data: Binary) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("hmac_sha256");
+    let mut mac = HmacSha256::new_from_slice(&key).map_err(|_| rustler::Error::BadArg)?;
+    Mac::update(&mut mac, &data);
+
+    tracker.ok(make_binary(env, &mac.finalize().into_bytes()))
+}
+
+// Constant-time comparison; truncated tags are rejected
+#[rustler::nif]
+fn hmac_sha256_verify(key: Binary, data: Binary, tag: Binary) -> bool {
+    let tracker = tracked!("hmac_sha256_verify");
+    tracker.done(match HmacSha256::new_from_slice(&key) {
+        Ok(mut mac) => {
+            Mac::update(&mut mac, &data);
+            mac.verify_slice(&tag).is_ok()
+        }
+        Err(_) => false,
+    })
+}
+
+#[rustler::nif]
+fn blake3_mac_verify(key: Binary, data: Binary, tag: Binary) -> bool {
+    let tracker = tracked!("blake3_mac_verify");
+    let (Ok(key), Ok(tag)) = (
+        <&[u8; blake3::KEY_LEN]>::try_from(key.as_slice()),
+        <[u8; blake3::OUT_LEN]>::try_from(tag.as_slice()),
+    ) else {
+        return tracker.done(false);
+    };
+
+    // blake3::Hash equality is constant-time
+    tracker.done(blake3::keyed_hash(key, &data) == blake3::Hash::from_bytes(tag))
+}
+
+// === Non-Cryptographic Checksums ===
+// Corruption detection only: these offer no protection against deliberate tampering.
+
+#[rustler::nif]
+fn xxh3_64(data: Binary) -> u64 {
+    let tracker = tracked!("xxh3_64");
+    tracker.done(xxhash_rust::xxh3::xxh3_64(&data))
+}
+
+#[rustler::nif(name = "crc32c")]
+fn crc32c_checksum(data: Binary) -> u32 {
+    let tracker = tracked!("crc32c");
+    tracker.done(crc32c::crc32c(&data))
+}
+
+// === Canonical CBOR ===
+// Integers, binaries (byte strings), {:text, string}, lists, maps, true, false,
+// nil and {:tag, number, term}.
+
+fn term_to_cbor(term: Term, depth: usize) -> NifResult<cbor::Value> {
+    if depth > cbor::MAX_DEPTH {
+        return Err(rustler::Error::BadArg);
+    }
+    Ok(match term.get_type() {
+        rustler::TermType::Integer => cbor::Value::Integer(term.decode()?),
+        rustler::TermType::Binary => cbor::Value::Bytes(term.decode::<Binary>()?.to_vec()),
+        rustler::TermType::List => cbor::Value::Array(
+            term.decode::<Vec<Term>>()?
+                .into_iter()
+                .map(|item| term_to_cbor(item, depth + 1))
+                .collect::<NifResult<_>>()?,
+        ),
+        rustler::TermType::Map => cbor::Value::Map(
+            term.decode::<rustler::MapIterator>()?
+                .map(|(key, value)| Ok((term_to_cbor(key, depth + 1)?, term_to_cbor(value, depth + 1)?)))
+                .collect::<NifResult<_>>()?,
+        ),
+        rustler::TermType::Atom if term == rustler::types::atom::nil().encode(term.get_env()) => cbor::Value::Null,
+        rustler::TermType::Atom => cbor::Value::Bool(term.decode()?),
+        rustler::TermType::Tuple => {
+            if let Ok((tag_atom, value)) = term.decode::<(Atom, String)>() {
+                if tag_atom != text() {
+                    return Err(rustler::Error::BadArg);
+                }
+                cbor::Value::Text(value)
+            } else {
+                let (tag_atom, number, value) = term.decode::<(Atom, u64, Term)>()?;
+                if tag_atom != tag() {
+                    return Err(rustler::Error::BadArg);
+                }
+                cbor::Value::Tag(number, Box::new(term_to_cbor(value, depth + 1)?))
+            }
+        }
+        _ => return Err(rustler::Error::BadArg),
+    })
+}
+
+fn cbor_to_term<'a>(env: Env<'a>, value: &cbor::Value) -> NifResult<Term<'a>> {
+    Ok(match value {
+        cbor::Value::Integer(n) => n.encode(env),
+        cbor::Value::Bytes(bytes) => make_binary(env, bytes).encode(env),
+        cbor::Value::Text(string) => (text(), string).encode(env),
+        cbor::Value::Array(items) => {
+            items.iter().map(|item| cbor_to_term(env, item)).collect::<NifResult<Vec<_>>>()?.encode(env)
+        }
+        cbor::Value::Map(entries) => {
+            let pairs = entries
+                .iter()
+                .map(|(key, value)| Ok((cbor_to_term(env, key)?, cbor_to_term(env, value)?)))
+                .collect::<NifResult<Vec<_>>>()?;
+            Term::map_from_pairs(env, &pairs)?
+        }
+        cbor::Value::Tag(number, value) => (tag(), *number, cbor_to_term(env, value)?).encode(env),
+        cbor::Value::Bool(value) => value.encode(env),
+        cbor::Value::Null => rustler::types::atom::nil().encode(env),
+    })
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn cbor_encode<'a>(env: Env<'a>, term: Term<'a>) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("cbor_encode");
+    let mut encoded = Vec::new();
+    cbor::encode(&term_to_cbor(term, 0)?, &mut encoded).map_err(|_| rustler::Error::BadArg)?;
+    tracker.ok(make_binary(env, &encoded))
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn cbor_decode<'a>(env: Env<'a>, data: Binary) -> NifResult<Term<'a>> {
+    let tracker = tracked!("cbor_decode");
+    let value = cbor::decode(&data).map_err(term_error)?;
+    tracker.finish(cbor_to_term(env, &value))
+}
+
+// === MessagePack ===
+// Binaries are str (so must be UTF-8) and {:bin, binary} bin; other atoms than
+// nil, true and false are str too. Extensions are {:ext, type, data}.
+
+fn term_to_msgpack(term: Term, out: &mut Vec<u8>, depth: usize) -> NifResult<()> {
+    let bad_arg = |_| rustler::Error::BadArg;
+    match term.get_type() {
+        rustler::TermType::Integer => msgpack::put_int(out, term.decode()?).map_err(bad_arg)?,
+        rustler::TermType::Float => msgpack::put_f64(out, term.decode()?),
+        rustler::TermType::Binary => {
+            let text = term.decode::<Binary>()?;
+            std::str::from_utf8(&text).map_err(|_| rustler::Error::BadArg)?;
+            msgpack::put_str(out, &text).map_err(bad_arg)?
+        }
+        rustler::TermType::Atom => match term.decode::<bool>() {
+            Ok(value) => msgpack::put_bool(out, value),
+            Err(_) if term == rustler::types::atom::nil().encode(term.get_env()) => msgpack::put_nil(out),
+            Err(_) => msgpack::put_str(out, term.atom_to_string()?.as_bytes()).map_err(bad_arg)?,
+        },
+        rustler::TermType::List if depth < msgpack::MAX_DEPTH => {
+            let items = term.decode::<Vec<Term>>()?;
+            msgpack::put_array_len(out, items.len()).map_err(bad_arg)?;
+            for item in items {
+                term_to_msgpack(item, out, depth + 1)?;
+            }
+        }
+        rustler::TermType::Map if depth < msgpack::MAX_DEPTH => {
+            msgpack::put_map_len(out, term.map_size()?).map_err(bad_arg)?;
+            for (key, value) in term.decode::<rustler::MapIterator>()? {
+                term_to_msgpack(key, out, depth + 1)?;
+                term_to_msgpack(value, out, depth + 1)?;
+            }
+        }
+        rustler::TermType::Tuple => {
+            if let Ok((tag_atom, bytes)) = term.decode::<(Atom, Binary)>() {
+                if tag_atom != bin() {
+                    return Err(rustler::Error::BadArg);
+                }
+                msgpack::put_bin(out, &bytes).map_err(bad_arg)?
+            } else {
+                let (tag_atom, ext_type, data) = term.decode::<(Atom, i8, Binary)>()?;
+                if tag_atom != ext() {
+                    return Err(rustler::Error::BadArg);
+                }
+                msgpack::put_ext(out, ext_type, &data).map_err(bad_arg)?
+            }
+        }
+        _ => return Err(rustler::Error::BadArg),
+    }
+    Ok(())
+}
+
+// Strings, binaries and extensions are sub-binaries of `data`, not copies
+fn msgpack_to_term<'a>(env: Env<'a>, data: &Binary<'a>, value: &msgpack::Value) -> NifResult<Term<'a>> {
+    let slice = |range: &std::ops::Range<usize>| data.make_subbinary(range.start, range.len());
+    Ok(match value {
+        msgpack::Value::Nil => rustler::types::atom::nil().encode(env),
+        msgpack::Value::Bool(value) => value.encode(env),
+        msgpack::Value::Integer(n) => n.encode(env),
+        msgpack::Value::Float(value) => value.encode(env),
+        msgpack::Value::Str(range) => slice(range)?.encode(env),
+        msgpack::Value::Bin(range) => (bin(), slice(range)?).encode(env),
+        msgpack::Value::Ext(ext_type, range) => (ext(), *ext_type, slice(range)?).encode(env),
+        msgpack::Value::Array(items) => {
+            items.iter().map(|item| msgpack_to_term(env, data, item)).collect::<NifResult<Vec<_>>>()?.encode(env)
+        }
+        msgpack::Value::Map(entries) => {
+            let pairs = entries
+                .iter()
+                .map(|(key, value)| Ok((msgpack_to_term(env, data, key)?, msgpack_to_term(env, data, value)?)))
+                .collect::<NifResult<Vec<_>>>()?;
+            Term::map_from_pairs(env, &pairs).map_err(|_| term_error("duplicate MessagePack map key".to_string()))?
+        }
+    })
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn msgpack_encode<'a>(env: Env<'a>, term: Term<'a>) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("msgpack_encode");
+    let mut encoded = Vec::new();
+    term_to_msgpack(term, &mut encoded, 0)?;
+    tracker.ok(make_binary(env, &encoded))
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn msgpack_decode<'a>(env: Env<'a>, data: Binary<'a>) -> NifResult<Term<'a>> {
+    let tracker = tracked!("msgpack_decode");
+    let value = msgpack::decode(&data, msgpack::MAX_DEPTH).map_err(term_error)?;
+    tracker.finish(msgpack_to_term(env, &data, &value))
+}
+
+#[rustler::nif(name = "msgpack_decode", schedule = "DirtyCpu")]
+fn msgpack_decode_with_opts<'a>(env: Env<'a>, data: Binary<'a>, opts: Vec<(Atom, Term)>) -> NifResult<Term<'a>> {
+    let tracker = tracked!("msgpack_decode");
+    let mut depth = msgpack::MAX_DEPTH;
+    for (key, value) in opts {
+        if key != max_depth() {
+            return Err(rustler::Error::BadArg);
+        }
+        depth = value.decode()?;
+        if depth > msgpack::MAX_DEPTH {
+            return Err(rustler::Error::BadArg);
+        }
+    }
+    let value = msgpack::decode(&data, depth).map_err(term_error)?;
+    tracker.finish(msgpack_to_term(env, &data, &value))
+}
+
+// === Block Headers ===
+// Headers are maps of the header.rs fields; :producer_algorithm is nil for
+// unsigned headers, and :signature may be left out until the header is signed.
+
+// Milliseconds a timestamp may run ahead of the local clock: a dozen blocks
+const DEFAULT_MAX_FUTURE_DRIFT: i64 = 120_000;
+
+fn term_to_header(term: Term) -> NifResult<header::Header> {
+    let env = term.get_env();
+    let field = |name: Atom| term.map_get(name.encode(env));
+    let hash = |name: Atom| -> NifResult<[u8; header::HASH_LEN]> {
+        field(name)?.decode::<Binary>()?.as_slice().try_into().map_err(|_| rustler::Error::BadArg)
+    };
+    let nil = rustler::types::atom::nil().encode(env);
+
+    let producer = match field(producer_algorithm()) {
+        Ok(value) if value == nil => None,
+        Err(_) => None,
+        Ok(value) => Some((algorithm_from_atom(value.decode()?)?, field(producer_key())?.decode::<Binary>()?.to_vec())),
+    };
+    let signature = match field(signature()) {
+        Ok(value) if value == nil => Vec::new(),
+        Err(_) => Vec::new(),
+        Ok(value) => value.decode::<Binary>()?.to_vec(),
+    };
+    Ok(header::Header {
+        height: field(height())?.decode()?,
+        previous_hash: hash(previous_hash())?,
+        timestamp: field(timestamp())?.decode()?,
+        merkle_root: hash(merkle_root())?,
+        bits: field(bits())?.decode()?,
+        producer,
+        nonce: field(nonce())?.decode()?,
+        signature,
+    })
+}
+
+fn header_to_term<'a>(env: Env<'a>, header: &header::Header, header_hash: &[u8]) -> NifResult<Term<'a>> {
+    let binary = |bytes: &[u8]| make_binary(env, bytes).encode(env);
+    let nil = rustler::types::atom::nil().encode(env);
+    let (algorithm, key) = match &header.producer {
+        Some((algorithm, key)) => (algorithm_atom(*algorithm).encode(env), binary(key)),
+        None => (nil, nil),
+    };
+    Term::map_from_pairs(
+        env,
+        &[
+            (height().encode(env), header.height.encode(env)),
+            (previous_hash().encode(env), binary(&header.previous_hash)),
+            (timestamp().encode(env), header.timestamp.encode(env)),
+            (merkle_root().encode(env), binary(&header.merkle_root)),
+            (bits().encode(env), header.bits.encode(env)),
+            (producer_algorithm().encode(env), algorithm),
+            (producer_key().encode(env), key),
+            (nonce().encode(env), header.nonce.encode(env)),
+            (signature().encode(env), binary(&header.signature)),
+            (hash().encode(env), binary(header_hash)),
+        ],
+    )
+}
+
+#[rustler::nif]
+fn header_encode<'a>(env: Env<'a>, term: Term<'a>) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("header_encode");
+    let bytes = header::encode(&term_to_header(term)?).map_err(|_| rustler::Error::BadArg)?;
+    tracker.ok(make_binary(env, &bytes))
+}
+
+#[rustler::nif]
+fn header_decode<'a>(env: Env<'a>, bytes: Binary) -> NifResult<Term<'a>> {
+    let tracker = tracked!("header_decode");
+    let (decoded, header_hash) = header::decode(&bytes).map_err(term_error)?;
+    tracker.finish(header_to_term(env, &decoded, &header_hash))
+}
+
+#[rustler::nif]
+fn header_hash<'a>(env: Env<'a>, bytes: Binary) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("header_hash");
+    let (_, header_hash) = header::decode(&bytes).map_err(term_error)?;
+    tracker.ok(make_binary(env, &header_hash))
+}
+
+// Options of validate_header and light_client_add_header
+fn header_params(opts: Vec<(Atom, Term)>) -> NifResult<header::Params> {
+    let clock = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map_err(|_| rustler::Error::BadArg)?;
+    let mut params = header::Params {
+        now: clock.as_millis() as i64,
+        max_future_drift: DEFAULT_MAX_FUTURE_DRIFT,
+        expected_bits: None,
+        producer_key: None,
+        require_signature: true,
+    };
+    for (key, value) in opts {
+        if key == now() {
+            params.now = value.decode()?;
+        } else if key == max_future_drift() {
+            params.max_future_drift = value.decode()?;
+        } else if key == expected_bits() {
+            params.expected_bits = Some(value.decode()?);
+        } else if key == producer_key() {
+            params.producer_key = Some(value.decode::<Binary>()?.to_vec());
+        } else if key == require_signature() {
+            params.require_signature = value.decode()?;
+        } else {
+            return Err(rustler::Error::BadArg);
+        }
+    }
+    Ok(params)
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn validate_header<'a>(env: Env<'a>, header_bytes: Binary, parent_bytes: Binary, opts: Vec<(Atom, Term)>) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("validate_header");
+    let params = header_params(opts)?;
+    let header_hash = header::validate(&header_bytes, &parent_bytes, &params).map_err(term_error)?;
+    tracker.ok(make_binary(env, &header_hash))
+}
+
+// === Finality Proofs ===
+// Validators are {algorithm, public_key, weight} tuples, in set order.
+
+fn terms_to_validators(validators: Vec<(Atom, Binary, u64)>) -> NifResult<Vec<finality::Validator>> {
+    validators
+        .into_iter()
+        .map(|(algorithm, public_key, weight)| {
+            Ok(finality::Validator { algorithm: algorithm_from_atom(algorithm)?, public_key: public_key.to_vec(), weight })
+        })
+        .collect()
+}
+
+fn root_from_binary(root: Binary) -> NifResult<[u8; finality::ROOT_LEN]> {
+    root.as_slice().try_into().map_err(|_| rustler::Error::BadArg)
+}
+
+#[rustler::nif]
+fn finality_validator_set_root<'a>(env: Env<'a>, validators: Vec<(Atom, Binary, u64)>) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("finality_validator_set_root");
+    let root = finality::validator_set_root(&terms_to_validators(validators)?).map_err(|_| rustler::Error::BadArg)?;
+    tracker.ok(make_binary(env, &root))
+}
+
+#[rustler::nif]
+fn finality_message<'a>(env: Env<'a>, header_bytes: Binary, set_root: Binary, next_set_root: Binary) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("finality_message");
+    let (_, header_hash) = header::decode(&header_bytes).map_err(term_error)?;
+    let message = finality::message(&header_hash, &root_from_binary(set_root)?, &root_from_binary(next_set_root)?);
+    tracker.ok(make_binary(env, &message))
+}
+
+#[rustler::nif]
+fn finality_proof_build<'a>(
+    env: Env<'a>,
+    header_bytes: Binary,
+    validators: Vec<(Atom, Binary, u64)>,
+    next_set_root: Binary,
+    signatures: Vec<(usize, Binary)>,
+) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("finality_proof_build");
+    let validators = terms_to_validators(validators)?;
+    let signatures = signatures.iter().map(|(index, signature)| (*index, signature.as_slice())).collect();
+    let proof = finality::build(&header_bytes, &validators, &root_from_binary(next_set_root)?, signatures)
+        .map_err(|_| rustler::Error::BadArg)?;
+    tracker.ok(make_binary(env, &proof))
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn finality_proof_verify<'a>(env: Env<'a>, proof: Binary, set_root: Binary) -> NifResult<(Term<'a>, Binary<'a>, Vec<usize>)> {
+    let tracker = tracked!("finality_proof_verify");
+    let finalized = finality::verify(&proof, &root_from_binary(set_root)?).map_err(term_error)?;
+    tracker.ok((
+        header_to_term(env, &finalized.header, &finalized.hash)?,
+        make_binary(env, &finalized.next_set_root),
+        finalized.signers,
+    ))
+}
+
+// === Light Client ===
+
+#[rustler::nif]
+fn light_client_new(set_root: Binary, opts: Vec<(Atom, Term)>) -> NifResult<ResourceArc<light_client::LightClient>> {
+    let tracker = tracked!("light_client_new");
+    let mut max = light_client::DEFAULT_MAX_HEADERS;
+    for (key, value) in opts {
+        if key == max_headers() {
+            max = value.decode()?;
+        } else {
+            return Err(rustler::Error::BadArg);
+        }
+    }
+    let client = light_client::LightClient::new(root_from_binary(set_root)?, max).ok_or(rustler::Error::BadArg)?;
+    tracker.ok(ResourceArc::new(client))
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn light_client_add_finality_proof(client: ResourceArc<light_client::LightClient>, proof: Binary) -> NifResult<u64> {
+    let tracker = tracked!("light_client_add_finality_proof");
+    tracker.finish(client.add_finality_proof(&proof).map_err(term_error))
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn light_client_add_header<'a>(
+    env: Env<'a>,
+    client: ResourceArc<light_client::LightClient>,
+    header_bytes: Binary,
+    opts: Vec<(Atom, Term)>,
+) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("light_client_add_header");
+    let params = header_params(opts)?;
+    let header_hash = client.add_header(&header_bytes, &params).map_err(term_error)?;
+    tracker.ok(make_binary(env, &header_hash))
+}
+
+#[rustler::nif]
+fn light_client_verify_inclusion(
+    client: ResourceArc<light_client::LightClient>,
+    header_hash: Binary,
+    leaf: Binary,
+    proof: Binary,
+) -> NifResult<(u64, bool)> {
+    let tracker = tracked!("light_client_verify_inclusion");
+    tracker.finish(client.verify_inclusion(&header_hash, &leaf, &proof).map_err(term_error))
+}
+
+#[rustler::nif]
+fn light_client_status<'a>(env: Env<'a>, client: ResourceArc<light_client::LightClient>) -> NifResult<Term<'a>> {
+    let tracker = tracked!("light_client_status");
+    let status = client.status().map_err(term_error)?;
+    let point = |point: Option<(u64, [u8; header::HASH_LEN])>| match point {
+        Some((height, hash)) => (height, make_binary(env, &hash)).encode(env),
+        None => rustler::types::atom::nil().encode(env),
+    };
+    tracker.finish(Term::map_from_pairs(
+        env,
+        &[
+            (validator_set_root().encode(env), make_binary(env, &status.set_root).encode(env)),
+            (finalized().encode(env), point(status.finalized)),
+            (head().encode(env), point(status.head)),
+        ],
+    ))
+}
+
+// === Consensus Votes ===
+// Votes are maps of the vote.rs fields, :algorithm an algorithm atom.
+
+fn block_id_from_binary(block_id: Binary) -> NifResult<[u8; vote::BLOCK_ID_LEN]> {
+    block_id.as_slice().try_into().map_err(|_| rustler::Error::BadArg)
+}
+
+#[rustler::nif]
+fn vote_message<'a>(env: Env<'a>, height: u64, round: u32, block_id: Binary) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("vote_message");
+    tracker.ok(make_binary(env, &vote::message(height, round, &block_id_from_binary(block_id)?)))
+}
+
+#[rustler::nif]
+fn vote_encode<'a>(env: Env<'a>, term: Term<'a>) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("vote_encode");
+    let field = |name: Atom| term.map_get(name.encode(env));
+    let decoded = vote::Vote {
+        height: field(height())?.decode()?,
+        round: field(round())?.decode()?,
+        block_id: block_id_from_binary(field(block_id())?.decode()?)?,
+        algorithm: algorithm_from_atom(field(algorithm())?.decode()?)?,
+        public_key: field(public_key())?.decode::<Binary>()?.to_vec(),
+        signature: field(signature())?.decode::<Binary>()?.to_vec(),
+    };
+    let bytes = vote::encode(&decoded).map_err(|_| rustler::Error::BadArg)?;
+    tracker.ok(make_binary(env, &bytes))
+}
+
+#[rustler::nif]
+fn vote_decode<'a>(env: Env<'a>, bytes: Binary) -> NifResult<Term<'a>> {
+    let tracker = tracked!("vote_decode");
+    let decoded = vote::decode(&bytes).map_err(term_error)?;
+    tracker.finish(Term::map_from_pairs(
+        env,
+        &[
+            (height().encode(env), decoded.height.encode(env)),
+            (round().encode(env), decoded.round.encode(env)),
+            (block_id().encode(env), make_binary(env, &decoded.block_id).encode(env)),
+            (algorithm().encode(env), algorithm_atom(decoded.algorithm).encode(env)),
+            (public_key().encode(env), make_binary(env, &decoded.public_key).encode(env)),
+            (signature().encode(env), make_binary(env, &decoded.signature).encode(env)),
+        ],
+    ))
+}
+
+// === Equivocation Evidence ===
+// Evidence is of two :header or two :vote statements, encoded.
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn equivocation_evidence<'a>(env: Env<'a>, kind: Atom, first: Binary, second: Binary) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("equivocation_evidence");
+    let kind = if kind == header() {
+        evidence::Kind::Header
+    } else if kind == vote() {
+        evidence::Kind::Vote
+    } else {
+        return Err(rustler::Error::BadArg);
+    };
+    let evidence = evidence::build(kind, &first, &second).map_err(term_error)?;
+    tracker.ok(make_binary(env, &evidence))
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn verify_equivocation<'a>(env: Env<'a>, evidence: Binary) -> NifResult<Term<'a>> {
+    let tracker = tracked!("verify_equivocation");
+    let offence = evidence::verify(&evidence).map_err(term_error)?;
+    let kind = match offence.kind {
+        evidence::Kind::Header => header(),
+        evidence::Kind::Vote => vote(),
+    };
+    let ids: Vec<Term> = offence.block_ids.iter().map(|id| make_binary(env, id).encode(env)).collect();
+    tracker.finish(Term::map_from_pairs(
+        env,
+        &[
+            (type_().encode(env), kind.encode(env)),
+            (algorithm().encode(env), algorithm_atom(offence.algorithm).encode(env)),
+            (public_key().encode(env), make_binary(env, &offence.public_key).encode(env)),
+            (height().encode(env), offence.height.encode(env)),
+            (round().encode(env), offence.round.encode(env)),
+            (block_ids().encode(env), ids.encode(env)),
+        ],
+    ))
+}
+
+// === Quorum Certificates ===
+// Validator sets are given as for finality proofs.
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn qc_collector_new(
+    height: u64,
+    round: u32,
+    block_id: Binary,
+    validators: Vec<(Atom, Binary, u64)>,
+) -> NifResult<ResourceArc<qc::Collector>> {
+    let tracker = tracked!("qc_collector_new");
+    let validators = terms_to_validators(validators)?;
+    let collector = qc::Collector::new(height, round, block_id_from_binary(block_id)?, &validators).map_err(|_| rustler::Error::BadArg)?;
+    tracker.ok(ResourceArc::new(collector))
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn qc_add_votes(collector: ResourceArc<qc::Collector>, votes: Vec<(usize, Binary)>) -> NifResult<(u128, bool, Vec<usize>)> {
+    let tracker = tracked!("qc_add_votes");
+    let votes: Vec<(usize, &[u8])> = votes.iter().map(|(index, signature)| (*index, signature.as_slice())).collect();
+    tracker.finish(collector.add(&votes).map_err(term_error))
+}
+
+#[rustler::nif]
+fn qc_certificate<'a>(env: Env<'a>, collector: ResourceArc<qc::Collector>) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("qc_certificate");
+    tracker.ok(make_binary(env, &collector.certificate().map_err(term_error)?))
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn qc_verify<'a>(
+    env: Env<'a>,
+    certificate: Binary,
+    validators: Vec<(Atom, Binary, u64)>,
+) -> NifResult<(u64, u32, Binary<'a>, Vec<usize>)> {
+    let tracker = tracked!("qc_verify");
+    let validators = terms_to_validators(validators)?;
+    let verified = qc::verify(&certificate, &validators).map_err(term_error)?;
+    tracker.ok((verified.height, verified.round, make_binary(env, &verified.block_id), verified.signers))
+}
+
+// === Transactions ===
+// Transactions are maps with the fields of Transaction structs (extra keys
+// such as :hash are ignored) plus :chain_id. The signature is nil, %{type:
+// :coinbase} or %{dilithium: _, falcon: _, sphincs: _}.
+
+fn term_to_tx(term: Term) -> NifResult<tx::Transaction> {
+    let env = term.get_env();
+    let field = |name: Atom| term.map_get(name.encode(env));
+    let bytes = |name: Atom| Ok::<_, rustler::Error>(field(name)?.decode::<Binary>()?.to_vec());
+    let nil = rustler::types::atom::nil().encode(env);
+
+    let signature_type = match field(signature_type())?.decode::<Atom>()? {
+        atom if atom == post_quantum_2_of_3() => tx::SignatureType::PostQuantum2Of3,
+        atom if atom == coinbase() => tx::SignatureType::Coinbase,
+        _ => return Err(rustler::Error::BadArg),
+    };
+    let signature = match field(signature()) {
+        Ok(value) if value == nil => tx::Signature::None,
+        Err(_) => tx::Signature::None,
+        Ok(value) => match value.map_get(type_().encode(env)) {
+            Ok(tag) if tag.decode::<Atom>()? == coinbase() => tx::Signature::Coinbase,
+            Ok(_) => return Err(rustler::Error::BadArg),
+            Err(_) => tx::Signature::PostQuantum {
+                dilithium: value.map_get(dilithium().encode(env))?.decode::<Binary>()?.to_vec(),
+                falcon: value.map_get(falcon().encode(env))?.decode::<Binary>()?.to_vec(),
+                sphincs: value.map_get(sphincs().encode(env))?.decode::<Binary>()?.to_vec(),
+            },
+        },
+    };
+    let tx_data = match field(data()) {
+        Ok(value) if value == nil => Vec::new(),
+        Err(_) => Vec::new(),
+        Ok(value) => value.decode::<Binary>()?.to_vec(),
+    };
+    Ok(tx::Transaction {
+        chain_id: bytes(chain_id())?,
+        from: bytes(from())?,
+        to: bytes(to())?,
+        amount: field(amount())?.decode()?,
+        fee: field(fee())?.decode()?,
+        nonce: field(nonce())?.decode()?,
+        timestamp: field(timestamp())?.decode()?,
+        data: tx_data,
+        signature_type,
+        signature,
+    })
+}
+
+fn tx_to_term<'a>(env: Env<'a>, tx: &tx::Transaction) -> NifResult<Term<'a>> {
+    let binary = |bytes: &[u8]| make_binary(env, bytes).encode(env);
+    let signature_term = match &tx.signature {
+        tx::Signature::None => rustler::types::atom::nil().encode(env),
+        tx::Signature::Coinbase => Term::map_from_pairs(env, &[(type_().encode(env), coinbase().encode(env))])?,
+        tx::Signature::PostQuantum { dilithium: d, falcon: f, sphincs: s } => Term::map_from_pairs(
+            env,
+            &[(dilithium().encode(env), binary(d)), (falcon().encode(env), binary(f)), (sphincs().encode(env), binary(s))],
+        )?,
+    };
+    let signature_type_atom = match tx.signature_type {
+        tx::SignatureType::PostQuantum2Of3 => post_quantum_2_of_3(),
+        tx::SignatureType::Coinbase => coinbase(),
+    };
+    Term::map_from_pairs(
+        env,
+        &[
+            (chain_id().encode(env), binary(&tx.chain_id)),
+            (from().encode(env), binary(&tx.from)),
+            (to().encode(env), binary(&tx.to)),
+            (amount().encode(env), tx.amount.encode(env)),
+            (fee().encode(env), tx.fee.encode(env)),
+            (nonce().encode(env), tx.nonce.encode(env)),
+            (timestamp().encode(env), tx.timestamp.encode(env)),
+            (data().encode(env), binary(&tx.data)),
+            (signature_type().encode(env), signature_type_atom.encode(env)),
+            (signature().encode(env), signature_term),
+        ],
+    )
+}
+
+#[rustler::nif]
+fn tx_encode<'a>(env: Env<'a>, term: Term<'a>) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("tx_encode");
+    let encoded = tx::encode(&term_to_tx(term)?).map_err(|_| rustler::Error::BadArg)?;
+    tracker.ok(make_binary(env, &encoded))
+}
+
+#[rustler::nif]
+fn tx_decode<'a>(env: Env<'a>, bytes: Binary) -> NifResult<Term<'a>> {
+    let tracker = tracked!("tx_decode");
+    let decoded = tx::decode(&bytes).map_err(term_error)?;
+    tracker.finish(tx_to_term(env, &decoded))
+}
+
+#[rustler::nif]
+fn tx_signing_bytes<'a>(env: Env<'a>, term: Term<'a>) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("tx_signing_bytes");
+    let bytes = tx::signing_bytes(&term_to_tx(term)?).map_err(|_| rustler::Error::BadArg)?;
+    tracker.ok(make_binary(env, &bytes))
+}
+
+// === Mempool Admission ===
+// Options are :chain_id, :max_size, :min_fee, :nonces (sender address =>
+// next nonce), :max_nonce_gap and :public_keys (sender address => %{dilithium:
+// _, falcon: _, sphincs: _}).
+
+// Room for the largest data field with all three signatures
+const DEFAULT_MAX_TX_SIZE: usize = 131_072;
+// How many transactions a sender may queue past its next nonce
+const DEFAULT_MAX_NONCE_GAP: u64 = 64;
+
+fn admission_params(opts: Vec<(Atom, Term)>) -> NifResult<admission::Params> {
+    let mut params = admission::Params {
+        chain_id: None,
+        max_size: DEFAULT_MAX_TX_SIZE,
+        min_fee: 0,
+        nonces: std::collections::HashMap::new(),
+        max_nonce_gap: DEFAULT_MAX_NONCE_GAP,
+        public_keys: std::collections::HashMap::new(),
+    };
+    for (key, value) in opts {
+        if key == chain_id() {
+            params.chain_id = Some(value.decode::<Binary>()?.to_vec());
+        } else if key == max_size() {
+            params.max_size = value.decode()?;
+        } else if key == min_fee() {
+            params.min_fee = value.decode()?;
+        } else if key == nonces() {
+            for (address, nonce) in value.decode::<rustler::MapIterator>()? {
+                params.nonces.insert(address.decode::<Binary>()?.to_vec(), nonce.decode()?);
+            }
+        } else if key == max_nonce_gap() {
+            params.max_nonce_gap = value.decode()?;
+        } else if key == public_keys() {
+            let env = value.get_env();
+            for (address, keys) in value.decode::<rustler::MapIterator>()? {
+                let key = |name: Atom| Ok::<_, rustler::Error>(keys.map_get(name.encode(env))?.decode::<Binary>()?.to_vec());
+                params.public_keys.insert(address.decode::<Binary>()?.to_vec(), [key(dilithium())?, key(falcon())?, key(sphincs())?]);
+            }
+        } else {
+            return Err(rustler::Error::BadArg);
+        }
+    }
+    Ok(params)
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn precheck_transactions<'a>(env: Env<'a>, transactions: Vec<Binary>, opts: Vec<(Atom, Term)>) -> NifResult<Vec<Term<'a>>> {
+    let tracker = tracked!("precheck_transactions");
+    let params = admission_params(opts)?;
+    let transactions: Vec<&[u8]> = transactions.iter().map(|tx| tx.as_slice()).collect();
+    tracker.ok(admission::precheck(&transactions, &params)
+        .into_iter()
+        .map(|verdict| match verdict {
+            Ok(()) => ok().encode(env),
+            Err(reason) => (error(), reason).encode(env),
+        })
+        .collect())
+}
+
+// === Canonical JSON ===
+// Maps are objects, with binary or atom keys; lists are arrays; binaries are
+// strings (so must be UTF-8); nil, true and false are literals and other atoms
+// strings. Integers must be exact as doubles, within +/-2^53.
+
+fn jcs_string(term: Term) -> NifResult<String> {
+    match term.get_type() {
+        rustler::TermType::Atom => term.atom_to_string(),
+        _ => String::from_utf8(term.decode::<Binary>()?.to_vec()).map_err(|_| rustler::Error::BadArg),
+    }
+}
+
+fn term_to_jcs(term: Term, depth: usize) -> NifResult<jcs::Value> {
+    const MAX_SAFE_INTEGER: i64 = (1 << 53) - 1;
+    Ok(match term.get_type() {
+        rustler::TermType::Integer => match term.decode::<i64>()? {
+            n if n.abs() <= MAX_SAFE_INTEGER => jcs::Value::Number(n as f64),
+            _ => return Err(rustler::Error::BadArg),
+        },
+        rustler::TermType::Float => jcs::Value::Number(term.decode()?),
+        rustler::TermType::Binary => jcs::Value::String(jcs_string(term)?),
+        rustler::TermType::Atom => match term.decode::<bool>() {
+            Ok(value) => jcs::Value::Bool(value),
+            Err(_) if term == rustler::types::atom::nil().encode(term.get_env()) => jcs::Value::Null,
+            Err(_) => jcs::Value::String(term.atom_to_string()?),
+        },
+        rustler::TermType::List if depth < jcs::MAX_DEPTH => jcs::Value::Array(
+            term.decode::<Vec<Term>>()?.into_iter().map(|item| term_to_jcs(item, depth + 1)).collect::<NifResult<_>>()?,
+        ),
+        rustler::TermType::Map if depth < jcs::MAX_DEPTH => jcs::Value::Object(
+            term.decode::<rustler::MapIterator>()?
+                .map(|(key, value)| Ok((jcs_string(key)?, term_to_jcs(value, depth + 1)?)))
+                .collect::<NifResult<_>>()?,
+        ),
+        _ => return Err(rustler::Error::BadArg),
+    })
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn jcs_encode<'a>(env: Env<'a>, term: Term<'a>) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("jcs_encode");
+    let mut encoded = Vec::new();
+    // Only an atom key equal to a binary key of the same map fails here
+    jcs::serialize(&term_to_jcs(term, 0)?, &mut encoded).map_err(|_| rustler::Error::BadArg)?;
+    tracker.ok(make_binary(env, &encoded))
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn jcs_canonicalize<'a>(env: Env<'a>, json: Binary) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("jcs_canonicalize");
+    tracker.ok(make_binary(env, &jcs::canonicalize(&json).map_err(term_error)?))
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn jcs_verify(json: Binary) -> bool {
+    let tracker = tracked!("jcs_verify");
+    tracker.done(jcs::canonicalize(&json).is_ok_and(|canonical| canonical == json.as_slice()))
+}
+
+// === Compression ===
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn zstd_compress<'a>(env: Env<'a>, data: Binary, level: i32) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("zstd_compress");
+    if !compression::level_in_range(level) {
+        return Err(rustler::Error::BadArg);
+    }
+    let compressed = compression::compress(&data, level).map_err(term_error)?;
+    tracker.ok(make_binary(env, &compressed))
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn zstd_decompress<'a>(env: Env<'a>, data: Binary, max_size: usize) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("zstd_decompress");
+    let decompressed = compression::decompress(&data, max_size).map_err(term_error)?;
+    tracker.ok(make_binary(env, &decompressed))
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn zstd_train_dictionary<'a>(env: Env<'a>, samples: Vec<Binary>, max_size: usize) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("zstd_train_dictionary");
+    let samples: Vec<&[u8]> = samples.iter().map(|sample| sample.as_slice()).collect();
+    let dictionary = compression::train_dictionary(&samples, max_size).map_err(term_error)?;
+    tracker.ok(make_binary(env, &dictionary))
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn zstd_dictionary(dictionary: Binary, level: i32) -> NifResult<ResourceArc<compression::Dictionary>> {
+    let tracker = tracked!("zstd_dictionary");
+    if !compression::level_in_range(level) {
+        return Err(rustler::Error::BadArg);
+    }
+    tracker.ok(ResourceArc::new(compression::Dictionary::new(&dictionary, level)))
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn zstd_compress_with_dictionary<'a>(
+    env: Env<'a>,
+    dictionary: ResourceArc<compression::Dictionary>,
+    data: Binary,
+) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("zstd_compress_with_dictionary");
+    let compressed = dictionary.compress(&data).map_err(term_error)?;
+    tracker.ok(make_binary(env, &compressed))
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn zstd_decompress_with_dictionary<'a>(
+    env: Env<'a>,
+    dictionary: ResourceArc<compression::Dictionary>,
+    data: Binary,
+    max_size: usize,
+) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("zstd_decompress_with_dictionary");
+    let decompressed = dictionary.decompress(&data, max_size).map_err(term_error)?;
+    tracker.ok(make_binary(env, &decompressed))
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn lz4_compress<'a>(env: Env<'a>, data: Binary) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("lz4_compress");
+    let compressed = compression::lz4_compress(&data).map_err(term_error)?;
+    tracker.ok(make_binary(env, &compressed))
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn lz4_decompress<'a>(env: Env<'a>, data: Binary, max_size: usize) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("lz4_decompress");
+    let decompressed = compression::lz4_decompress(&data, max_size).map_err(term_error)?;
+    tracker.ok(make_binary(env, &decompressed))
+}
+
+// === Randomness ===
+
+#[rustler::nif]
+fn secure_random_bytes<'a>(env: Env<'a>, size: usize) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("secure_random_bytes");
+    if size > rng::MAX_REQUEST {
+        return Err(rustler::Error::BadArg);
+    }
+    let mut binary = NewBinary::new(env, size);
+    rng::fill(binary.as_mut_slice()).map_err(term_error)?;
+    tracker.ok(binary.into())
+}
+
+#[rustler::nif]
+fn rng_selftest() -> NifResult<Atom> {
+    let tracker = tracked!("rng_selftest");
+    rng::selftest().map_err(term_error)?;
+    tracker.ok(ok())
+}
+
+// === Keypair Validation ===
+
+// Layout checks, then a sign/verify round trip over a fixed message. Remote
+// signer failures surface as errors rather than `false`, since they say
+// nothing about the key itself.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn validate_keypair(algorithm: Atom, public_key: Binary, private_key: Term) -> NifResult<bool> {
+    let tracker = tracked!("validate_keypair");
+    let algorithm = algorithm_from_atom(algorithm)?;
+    let handle = private_key.decode::<ResourceArc<SecretKeyHandle>>().ok();
+    let binary = private_key.decode::<Binary>().ok();
+    let secret_bytes = match (&handle, &binary) {
+        (Some(handle), _) => Some(handle.bytes()),
+        (None, Some(binary)) => Some(binary.as_slice()),
+        (None, None) => None,
+    };
+    if let Some(sk) = secret_bytes {
+        if !algorithm.keys_consistent(&public_key, sk) {
+            return tracker.ok(false);
+        }
+    }
+
+    let message = b"Bastille keypair validation";
+    match sign_detached(algorithm, message, private_key) {
+        Ok(signature) => tracker.ok(verify_detached(algorithm, &signature, message, &public_key)),
+        Err(rustler::Error::BadArg) => tracker.ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+// === Benchmarks ===
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn benchmark(env: Env, algorithm: Atom, op: Atom, iterations: u32) -> NifResult<Term> {
+    let tracker = tracked!("benchmark");
+    let algorithm = algorithm_from_atom(algorithm)?;
+    let operation = if op == keygen() {
+        bench::Operation::Keygen
+    } else if op == sign() {
+        bench::Operation::Sign
+    } else if op == verify() {
+        bench::Operation::Verify
+    } else {
+        return Err(rustler::Error::BadArg);
+    };
+    if iterations == 0 || iterations > bench::MAX_ITERATIONS {
+        return Err(rustler::Error::BadArg);
+    }
+
+    let result = bench::run(algorithm, operation, iterations).map_err(term_error)?;
+    let nanos = |duration: std::time::Duration| duration.as_nanos() as u64;
+    tracker.finish(Term::map_from_pairs(
+        env,
+        &[
+            (self::iterations().encode(env), result.iterations.encode(env)),
+            (min_ns().encode(env), nanos(result.min).encode(env)),
+            (median_ns().encode(env), nanos(result.median).encode(env)),
+            (p99_ns().encode(env), nanos(result.p99).encode(env)),
+            (ops_per_sec().encode(env), result.ops_per_sec.encode(env)),
+        ],
+    ))
+}
+
+// === Addresses ===
+
+#[rustler::nif]
+fn derive_address<'a>(env: Env<'a>, algorithm: Atom, public_key: Binary) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("derive_address");
+    let algorithm = algorithm_from_atom(algorithm)?;
+    let address = address::derive_address(algorithm, &public_key).ok_or(rustler::Error::BadArg)?;
+    tracker.ok(make_binary(env, &address))
+}
+
+#[rustler::nif]
+fn valid_address(address: Binary) -> bool {
+    let tracker = tracked!("valid_address");
+    tracker.done(address::valid_address(&address))
+}
+
+// BIP-173 limit on the total string length, kept for wallet compatibility
+const BECH32_MAX_LEN: usize = 90;
+
+#[rustler::nif]
+fn bech32m_encode(hrp: String, data: Binary) -> NifResult<String> {
+    let tracker = tracked!("bech32m_encode");
+    let hrp = bech32::Hrp::parse(&hrp).map_err(|_| rustler::Error::BadArg)?;
+    let encoded = bech32::encode::<bech32::Bech32m>(hrp, &data).map_err(|_| rustler::Error::BadArg)?;
+    if encoded.len() > BECH32_MAX_LEN {
+        return Err(rustler::Error::BadArg);
+    }
+    tracker.ok(encoded)
+}
+
+// Strict decoding: Bech32m checksum only (plain Bech32 is rejected), no mixed
+// case, zero padding bits. Returns {hrp, data} with the hrp in lowercase.
+#[rustler::nif]
+fn bech32m_decode<'a>(env: Env<'a>, address: String) -> NifResult<(String, Binary<'a>)> {
+    let tracker = tracked!("bech32m_decode");
+    let invalid = |reason: String| rustler::Error::Term(Box::new(reason));
+    if address.len() > BECH32_MAX_LEN {
+        return Err(invalid(format!("address longer than {} characters", BECH32_MAX_LEN)));
+    }
+
+    let checked = bech32::primitives::decode::CheckedHrpstring::new::<bech32::Bech32m>(&address)
+        .map_err(|e| invalid(e.to_string()))?;
+    checked.validate_segwit_padding().map_err(|e| invalid(e.to_string()))?;
+    let data: Vec<u8> = checked.byte_iter().collect();
+    tracker.ok((checked.hrp().to_lowercase(), make_binary(env, &data)))
+}
+
+// Base58Check as used by Bitcoin tooling: version || payload || sha256d[..4]
+#[rustler::nif]
+fn base58check_encode(version: u8, payload: Binary) -> String {
+    let tracker = tracked!("base58check_encode");
+    tracker.done(bs58::encode(payload.as_slice()).with_check_version(version).into_string())
+}
+
+#[rustler::nif]
+fn base58check_decode<'a>(env: Env<'a>, encoded: String) -> NifResult<(u8, Binary<'a>)> {
+    let tracker = tracked!("base58check_decode");
+    let decoded = bs58::decode(&encoded)
+        .with_check(None)
+        .into_vec()
+        .map_err(|e| rustler::Error::Term(Box::new(e.to_string())))?;
+    tracker.finish(match decoded.split_first() {
+        Some((version, payload)) => Ok((*version, make_binary(env, payload))),
+        None => Err(rustler::Error::Term(Box::new("missing version byte".to_string()))),
+    })
+}
+
+// === Deterministic Key Generation Functions ===
+
+// Keys are derived from the seed (see keygen.rs); the persistent cache only saves
+// recomputation, mostly for Falcon. Entries are keyed by spec version so keys
+// cached by older schemes are never served.
+fn cached_keypair_from_seed(algorithm: keygen::Algorithm, seed: &[u8]) -> NifResult<KeypairBytes> {
+    if seed.len() < keygen::MIN_SEED_LEN {
+        return Err(rustler::Error::BadArg);
+    }
+
+    let cache_domain = format!("{}/{}", keygen::SPEC_VERSION, algorithm.name());
+    let cache_key_bytes = tagged_hash(cache_domain.as_bytes(), seed)
+        .ok_or(rustler::Error::BadArg)?
+        .as_bytes()
+        .to_vec();
+
+    if let Some(keypair) = key_cache::lookup(algorithm, &cache_key_bytes) {
+        return Ok(keypair);
+    }
+
+    let keypair = keygen::keypair_from_seed(algorithm, seed)
+        .map_err(|e| rustler::Error::Term(Box::new(format!("Key derivation failed: {}", e))))?;
+
+    // A failed cache write only costs a re-derivation next time
+    let _ = key_cache::store(algorithm, &cache_key_bytes, &keypair);
+
+    Ok(keypair)
+}
+
+#[rustler::nif]
+fn keygen_spec_version() -> &'static str {
+    let tracker = tracked!("keygen_spec_version");
+    tracker.done(keygen::SPEC_VERSION)
+}
+
+// Enable the encrypted persistent cache in `path` (nil disables it)
+#[rustler::nif(schedule = "DirtyCpu")]
+fn configure_key_cache(path: Option<String>, opts: Vec<(Atom, Term)>) -> NifResult<Atom> {
+    let tracker = tracked!("configure_key_cache");
+    let mut cache_key = None;
+    let mut limits = key_cache::CacheLimits::default();
+    for (name, value) in opts {
+        if name == max_entries() {
+            limits.max_entries = value.decode()?;
+        } else if name == max_bytes() {
+            limits.max_bytes = value.decode()?;
+        } else if name == memory_entries() {
+            limits.memory_entries = value.decode()?;
+        } else if name == key() {
+            let raw: Binary = value.decode()?;
+            cache_key = Some(key_cache::CacheKey::Raw(Zeroizing::new(raw.to_vec())));
+        } else if name == passphrase() {
+            let phrase: String = value.decode()?;
+            cache_key = Some(key_cache::CacheKey::Passphrase(Zeroizing::new(phrase)));
+        } else {
+            return Err(rustler::Error::BadArg);
+        }
+    }
+
+    key_cache::configure(path, cache_key, limits).map_err(term_error)?;
+    tracker.ok(ok())
+}
+
+// Operator escape hatch: drop every cached keypair, returning how many were removed
+#[rustler::nif(schedule = "DirtyIo")]
+fn purge_key_cache() -> NifResult<usize> {
+    let tracker = tracked!("purge_key_cache");
+    tracker.finish(key_cache::purge().map_err(term_error))
+}
+
+// Plaintext entries of the original cache still in `path`, waiting for a cache key
+#[rustler::nif(schedule = "DirtyIo")]
+fn key_cache_legacy_entries(path: String) -> NifResult<usize> {
+    let tracker = tracked!("key_cache_legacy_entries");
+    tracker.finish(key_cache::legacy_entries(&path).map_err(term_error))
+}
+
+#[rustler::nif]
+fn key_cache_stats(env: Env) -> NifResult<Term> {
+    let tracker = tracked!("key_cache_stats");
+    let stats = key_cache::stats();
+    tracker.finish(Term::map_from_pairs(
+        env,
+        &[
+            (memory_hits().encode(env), stats.memory_hits.encode(env)),
+            (disk_hits().encode(env), stats.disk_hits.encode(env)),
+            (misses().encode(env), stats.misses.encode(env)),
+            (memory_entries().encode(env), stats.memory_entries.encode(env)),
+        ],
+    ))
+}
+
+// Hardened child seed for `path`; feed it to the *_keypair_from_seed NIFs
+#[rustler::nif]
+fn derive_child_seed<'a>(env: Env<'a>, master_seed: Binary, path: Vec<u32>) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("derive_child_seed");
+    let child_seed = hd::derive_child_seed(&master_seed, &path).map_err(|_| rustler::Error::BadArg)?;
+    tracker.ok(make_binary(env, &child_seed[..]))
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn dilithium2_keypair_from_seed<'a>(env: Env<'a>, seed: Binary) -> NifResult<(Binary<'a>, Binary<'a>)> {
+    let tracker = tracked!("dilithium2_keypair_from_seed");
+    let (pk_bytes, sk_bytes) = cached_keypair_from_seed(keygen::Algorithm::Dilithium2, &seed)?;
+    tracker.ok((make_binary(env, &pk_bytes), make_binary(env, &sk_bytes)))
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn falcon512_keypair_from_seed<'a>(env: Env<'a>, seed: Binary) -> NifResult<(Binary<'a>, Binary<'a>)> {
+    let tracker = tracked!("falcon512_keypair_from_seed");
+    let (pk_bytes, sk_bytes) = cached_keypair_from_seed(keygen::Algorithm::Falcon512, &seed)?;
+    tracker.ok((make_binary(env, &pk_bytes), make_binary(env, &sk_bytes)))
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn sphincsplus_keypair_from_seed<'a>(env: Env<'a>, seed: Binary) -> NifResult<(Binary<'a>, Binary<'a>)> {
+    let tracker = tracked!("sphincsplus_keypair_from_seed");
+    let (pk_bytes, sk_bytes) = cached_keypair_from_seed(keygen::Algorithm::SphincsPlus, &seed)?;
+    tracker.ok((make_binary(env, &pk_bytes), make_binary(env, &sk_bytes)))
+}
+
+// === Key Rotation ===
+
+// The old key signs (new key, effective height, reason); the signature is
+// checked before returning so a mismatched old keypair is caught at build time.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn build_rotation_certificate<'a>(
+    env: Env<'a>,
+    algorithm: Atom,
+    old_public_key: Binary,
+    old_private_key: Term<'a>,
+    new_public_key: Binary,
+    effective_height: u64,
+    reason: String,
+) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("build_rotation_certificate");
+    let statement = rotation::RotationStatement {
+        algorithm: algorithm_from_atom(algorithm)?,
+        old_public_key: old_public_key.to_vec(),
+        new_public_key: new_public_key.to_vec(),
+        effective_height,
+        reason,
+    };
+    let mut certificate = statement.encode().map_err(|_| rustler::Error::BadArg)?;
+    let signature = sign_detached(statement.algorithm, &certificate, old_private_key)?;
+    if !verify_detached(statement.algorithm, &signature, &certificate, &statement.old_public_key) {
+        return Err(rustler::Error::BadArg);
+    }
+
+    certificate.extend_from_slice(&signature);
+    tracker.ok(make_binary(env, &certificate))
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn verify_rotation_certificate<'a>(env: Env<'a>, certificate: Binary) -> NifResult<(Atom, Term<'a>)> {
+    let tracker = tracked!("verify_rotation_certificate");
+    let invalid = |reason: String| rustler::Error::Term(Box::new(reason));
+    let (statement, signed, signature) = rotation::decode_certificate(&certificate).map_err(invalid)?;
+    if !verify_detached(statement.algorithm, signature, signed, &statement.old_public_key) {
+        return Err(invalid("invalid signature".to_string()));
+    }
+
+    let fields = Term::map_from_pairs(
+        env,
+        &[
+            (algorithm().encode(env), algorithm_atom(statement.algorithm).encode(env)),
+            (old_public_key().encode(env), make_binary(env, &statement.old_public_key).encode(env)),
+            (new_public_key().encode(env), make_binary(env, &statement.new_public_key).encode(env)),
+            (effective_height().encode(env), statement.effective_height.encode(env)),
+            (reason().encode(env), statement.reason.encode(env)),
+        ],
+    )?;
+    tracker.ok((ok(), fields))
+}
+
+// === Key Export ===
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn export_keypair<'a>(env: Env<'a>, public_key: Binary, secret_key: Binary, passphrase: Binary) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("export_keypair");
+    let blob = keystore::export_keypair(&public_key, &secret_key, &passphrase).map_err(|_| rustler::Error::BadArg)?;
+    tracker.ok(make_binary(env, &blob))
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn import_keypair<'a>(env: Env<'a>, blob: Binary, passphrase: Binary) -> NifResult<(Binary<'a>, Binary<'a>)> {
+    let tracker = tracked!("import_keypair");
+    let (pk, sk) = keystore::import_keypair(&blob, &passphrase).map_err(term_error)?;
+    tracker.ok((make_binary(env, &pk), make_binary(env, &sk)))
+}
+
+// === Distributed Key Generation ===
+
+// (recipient index, dealer share)
+type OutgoingShares<'a> = Vec<(u8, Binary<'a>)>;
+
+#[rustler::nif]
+fn dkg_new<'a>(
+    env: Env<'a>,
+    session_id: Binary,
+    threshold: u8,
+    parties: u8,
+    index: u8,
+) -> NifResult<(ResourceArc<dkg::DkgSession>, OutgoingShares<'a>)> {
+    let tracker = tracked!("dkg_new");
+    let (session, outgoing) =
+        dkg::DkgSession::new(&session_id, threshold, parties, index).map_err(|_| rustler::Error::BadArg)?;
+    let outgoing = outgoing
+        .into_iter()
+        .map(|(recipient, share)| (recipient, make_binary(env, &share)))
+        .collect();
+    tracker.ok((ResourceArc::new(session), outgoing))
+}
+
+#[rustler::nif]
+fn dkg_receive(session: ResourceArc<dkg::DkgSession>, share: Binary) -> NifResult<Atom> {
+    let tracker = tracked!("dkg_receive");
+    session.receive(&share).map_err(term_error)?;
+    tracker.ok(ok())
+}
+
+#[rustler::nif]
+fn dkg_finalize<'a>(env: Env<'a>, session: ResourceArc<dkg::DkgSession>) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("dkg_finalize");
+    let key_share = session.finalize().map_err(term_error)?;
+    tracker.ok(make_binary(env, &key_share))
+}
+
+// Reconstructs the seed only long enough to derive the keypair; the result
+// deliberately bypasses the deterministic key cache.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn dkg_combine<'a>(env: Env<'a>, algorithm: Atom, key_shares: Vec<Binary>) -> NifResult<(Binary<'a>, Binary<'a>)> {
+    let tracker = tracked!("dkg_combine");
+    let algorithm = algorithm_from_atom(algorithm)?;
+    let key_shares: Vec<&[u8]> = key_shares.iter().map(|share| share.as_slice()).collect();
+    let seed = dkg::combine(&key_shares).map_err(term_error)?;
+    let (pk, sk) = keygen::keypair_from_seed(algorithm, &seed[..]).map_err(term_error)?;
+    tracker.ok((make_binary(env, &pk), make_binary(env, &sk)))
+}
+
+// === Verifiable Random Function ===
+
+fn vrf_bytes<const N: usize>(binary: &Binary) -> NifResult<[u8; N]> {
+    binary.as_slice().try_into().map_err(|_| rustler::Error::BadArg)
+}
+
+#[rustler::nif]
+fn vrf_keypair<'a>(env: Env<'a>) -> NifResult<(Binary<'a>, Binary<'a>)> {
+    let tracker = tracked!("vrf_keypair");
+    let mut secret_key = Zeroizing::new([0u8; vrf::SECRET_KEY_LEN]);
+    rng::fill(&mut secret_key[..]).map_err(term_error)?;
+    tracker.ok((make_binary(env, &vrf::public_key(&secret_key)), make_binary(env, &secret_key[..])))
+}
+
+#[rustler::nif]
+fn vrf_public_key<'a>(env: Env<'a>, secret_key: Binary) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("vrf_public_key");
+    let secret_key = Zeroizing::new(vrf_bytes::<{ vrf::SECRET_KEY_LEN }>(&secret_key)?);
+    tracker.ok(make_binary(env, &vrf::public_key(&secret_key)))
+}
+
+#[rustler::nif]
+fn vrf_prove<'a>(env: Env<'a>, secret_key: Binary, input: Binary) -> NifResult<(Binary<'a>, Binary<'a>)> {
+    let tracker = tracked!("vrf_prove");
+    let secret_key = Zeroizing::new(vrf_bytes::<{ vrf::SECRET_KEY_LEN }>(&secret_key)?);
+    let proof = vrf::prove(&secret_key, &input).map_err(term_error)?;
+    let output = vrf::proof_to_hash(&proof).map_err(term_error)?;
+    tracker.ok((make_binary(env, &output), make_binary(env, &proof)))
+}
+
+#[rustler::nif]
+fn vrf_verify<'a>(env: Env<'a>, public_key: Binary, input: Binary, proof: Binary) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("vrf_verify");
+    let public_key = vrf_bytes::<{ vrf::PUBLIC_KEY_LEN }>(&public_key)?;
+    let proof = vrf_bytes::<{ vrf::PROOF_LEN }>(&proof)?;
+    let output = vrf::verify(&public_key, &input, &proof).map_err(term_error)?;
+    tracker.ok(make_binary(env, &output))
+}
+
+#[rustler::nif]
+fn vrf_proof_to_hash<'a>(env: Env<'a>, proof: Binary) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("vrf_proof_to_hash");
+    let output = vrf::proof_to_hash(&vrf_bytes::<{ vrf::PROOF_LEN }>(&proof)?).map_err(term_error)?;
+    tracker.ok(make_binary(env, &output))
+}
+
+// === Verifiable Delay Function ===
+
+#[rustler::nif]
+fn vdf_eval(seed: Binary, iterations: u64) -> NifResult<ResourceArc<vdf::Evaluation>> {
+    let tracker = tracked!("vdf_eval");
+    if seed.len() > vdf::MAX_SEED_LEN || iterations == 0 {
+        return Err(rustler::Error::BadArg);
+    }
+    let evaluation = vdf::Evaluation::start(seed.to_vec(), iterations).map_err(term_error)?;
+    tracker.ok(ResourceArc::new(evaluation))
+}
+
+#[rustler::nif]
+fn vdf_progress<'a>(env: Env<'a>, evaluation: ResourceArc<vdf::Evaluation>) -> Term<'a> {
+    let tracker = tracked!("vdf_progress");
+    tracker.done(match evaluation.status() {
+        vdf::Status::Running { done: squarings, total } => (running(), squarings, total).encode(env),
+        vdf::Status::Done(solution) => (done(), make_binary(env, &solution.0), make_binary(env, &solution.1)).encode(env),
+        vdf::Status::Failed(reason) => (error(), reason).encode(env),
+    })
+}
+
+#[rustler::nif]
+fn vdf_cancel(evaluation: ResourceArc<vdf::Evaluation>) -> Atom {
+    let tracker = tracked!("vdf_cancel");
+    evaluation.cancel();
+    tracker.done(ok())
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn vdf_verify(seed: Binary, iterations: u64, output: Binary, proof: Binary) -> NifResult<bool> {
+    let tracker = tracked!("vdf_verify");
+    if seed.len() > vdf::MAX_SEED_LEN || iterations == 0 || output.len() != vdf::FORM_LEN || proof.len() != vdf::FORM_LEN {
+        return Err(rustler::Error::BadArg);
+    }
+    tracker.ok(vdf::verify(&seed, iterations, &output, &proof))
+}
+
+// === Randomness Beacon ===
+// Reveals, salts and randomness are 32 bytes; other lengths are a BadArg.
+
+fn beacon_value(bytes: &Binary) -> NifResult<[u8; 32]> {
+    bytes.as_slice().try_into().map_err(|_| rustler::Error::BadArg)
+}
+
+#[rustler::nif]
+fn beacon_commit<'a>(env: Env<'a>, reveal: Binary, salt: Binary) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("beacon_commit");
+    tracker.ok(make_binary(env, &beacon::commitment(&beacon_value(&reveal)?, &beacon_value(&salt)?)))
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn beacon_verify_reveals(items: Vec<(Binary, Binary, Binary)>) -> NifResult<Vec<usize>> {
+    let tracker = tracked!("beacon_verify_reveals");
+    let arrays = items
+        .iter()
+        .map(|(commitment, reveal, salt)| Ok((commitment.as_slice(), beacon_value(reveal)?, beacon_value(salt)?)))
+        .collect::<NifResult<Vec<_>>>()?;
+    let items: Vec<_> = arrays.iter().map(|(commitment, reveal, salt)| (*commitment, reveal, salt)).collect();
+    tracker.ok(beacon::verify_reveals(&items))
+}
+
+#[rustler::nif]
+fn beacon_mix<'a>(env: Env<'a>, previous: Binary, epoch: u64, reveals: Vec<Binary>) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("beacon_mix");
+    let reveals = reveals.iter().map(beacon_value).collect::<NifResult<Vec<_>>>()?;
+    tracker.ok(make_binary(env, &beacon::mix(&beacon_value(&previous)?, epoch, &reveals)))
+}
+
+// === WebAssembly Contracts ===
+// The contract runtime (the `wasm` feature). Arguments and results are
+// integers for i32/i64 and floats for f32/f64. Modules stored on chain are
+// metered first (metering.rs).
+
+#[cfg(feature = "wasm")]
+#[rustler::nif(schedule = "DirtyCpu")]
+fn wasm_meter<'a>(env: Env<'a>, code: Binary) -> NifResult<Binary<'a>> {
+    let tracker = tracked!("wasm_meter");
+    let metered = metering::meter(&code).map_err(term_error)?;
+    tracker.ok(make_binary(env, &metered))
+}
+
+#[cfg(feature = "wasm")]
+#[rustler::nif(schedule = "DirtyCpu")]
+fn wasm_instantiate(env: Env, code: Binary, opts: Vec<(Atom, Term)>) -> NifResult<ResourceArc<wasm::Instance>> {
+    let tracker = tracked!("wasm_instantiate");
+    let mut limits = wasm::Limits {
+        max_memory: wasm::DEFAULT_MAX_MEMORY,
+        max_table_elements: wasm::DEFAULT_MAX_TABLE_ELEMENTS,
+        start_gas: wasm::DEFAULT_START_GAS,
+    };
+    let mut storage_pid = None;
+    let mut timeout = wasm::DEFAULT_STORAGE_TIMEOUT;
+    for (key, value) in opts {
+        if key == max_memory() {
+            limits.max_memory = value.decode()?;
+        } else if key == max_table_elements() {
+            limits.max_table_elements = value.decode()?;
+        } else if key == start_gas() {
+            limits.start_gas = value.decode()?;
+        } else if key == storage() {
+            storage_pid = Some(value.decode::<LocalPid>()?);
+        } else if key == storage_timeout() {
+            timeout = std::time::Duration::from_millis(value.decode()?);
+        } else {
+            return Err(rustler::Error::BadArg);
+        }
+    }
+    // The caller would wait on itself
+    if storage_pid.is_some_and(|pid| pid == env.pid()) {
+        return Err(term_error("storage process can't be the caller".to_string()));
+    }
+    let storage = storage_pid.map(|pid| wasm::Storage { pid, timeout });
+    tracker.ok(ResourceArc::new(wasm::Instance::new(&code, &limits, storage).map_err(term_error)?))
+}
+
+#[cfg(feature = "wasm")]
+#[rustler::nif]
+fn wasm_storage_reply(reply: ResourceArc<wasm::StorageReply>, value: Option<Binary>) -> NifResult<Atom> {
+    let tracker = tracked!("wasm_storage_reply");
+    if !reply.answer(value.map(|value| value.to_vec())).map_err(term_error)? {
+        return Err(term_error("storage request already answered".to_string()));
+    }
+    tracker.ok(ok())
+}
+
+#[cfg(feature = "wasm")]
+#[rustler::nif(schedule = "DirtyCpu")]
+fn wasm_call<'a>(
+    env: Env<'a>,
+    instance: ResourceArc<wasm::Instance>,
+    function: String,
+    args: Vec<Term<'a>>,
+    gas_limit: u64,
+) -> NifResult<Term<'a>> {
+    let tracker = tracked!("wasm_call");
+    let args = args
+        .iter()
+        .map(|arg| match arg.get_type() {
+            rustler::TermType::Integer => Ok(wasm::Value::Int(arg.decode()?)),
+            rustler::TermType::Float => Ok(wasm::Value::Float(arg.decode()?)),
+            _ => Err(rustler::Error::BadArg),
+        })
+        .collect::<NifResult<Vec<_>>>()?;
+    if instance.storage_pid().is_some_and(|pid| pid == env.pid()) {
+        return tracker.ok((aborted(), "storage process can't be the caller").encode(env));
+    }
+    let (outcome, gas_used) = match instance.call(&function, &args, gas_limit) {
+        Ok(outcome) => outcome,
+        Err(reason) => return tracker.ok((aborted(), reason).encode(env)),
+    };
+    tracker.ok(match outcome {
+        Ok(results) => {
+            let results: Vec<Term> = results
+                .iter()
+                .map(|value| match value {
+                    wasm::Value::Int(n) => n.encode(env),
+                    wasm::Value::Float(x) => x.encode(env),
+                })
+                .collect();
+            (results, gas_used).encode(env)
+        }
+        Err(reason) => (error(), reason, gas_used).encode(env),
+    })
+}
+
+// === Secret Hygiene ===
+
+// Overwrite the bytes of an Elixir binary in place. Best effort only: the BEAM
+// may already hold other copies (messages, GC moves of small heap binaries),
+// and every term sharing the same bytes, sub-binaries included, sees zeros.
+#[rustler::nif]
+fn secure_wipe(data: Binary) -> Atom {
+    let tracker = tracked!("secure_wipe");
+    // SAFETY: the pointer and length come from enif_inspect_binary and stay
+    // valid for the duration of the call; mutating a binary the caller owns is
+    // the whole point of this NIF (see the Elixir docs for the caveats).
+    let bytes = unsafe { std::slice::from_raw_parts_mut(data.as_slice().as_ptr() as *mut u8, data.len()) };
+    bytes.zeroize();
+    tracker.done(ok())
+}
+
+// === Remote Signing ===
+
+// Handle for a key held by the signer daemon listening on `socket_path`.
+// Accepted by the `*_sign` functions wherever a secret key is.
+#[cfg(feature = "remote-signer")]
+#[rustler::nif]
+fn remote_signer_key(
+    socket_path: String,
+    algorithm: Atom,
+    key_id: Binary,
+) -> NifResult<ResourceArc<remote_signer::RemoteKeyHandle>> {
+    let tracker = tracked!("remote_signer_key");
+    let algorithm = algorithm_from_atom(algorithm)?;
+    if socket_path.is_empty() || key_id.is_empty() || key_id.len() > remote_signer::MAX_KEY_ID_LEN {
+        return Err(rustler::Error::BadArg);
+    }
+    let handle = remote_signer::RemoteKeyHandle::new(socket_path.into(), algorithm, key_id.to_vec());
+    tracker.ok(ResourceArc::new(handle))
+}
+
+// The RNG health check runs once at load; a failure doesn't stop the library
+// loading (hashing and verification are unaffected) but keeps
+// secure_random_bytes returning errors until rng_selftest passes.
+fn load(_env: Env, _info: Term) -> bool {
+    let _ = rng::selftest();
+    true
 }
 
 // Register NIFs with the Elixir module name that mirrors the file location
-rustler::init!("Elixir.Bastille.Infrastructure.Crypto.CryptoNif");
\ No newline at end of file
+rustler::init!("Elixir.Bastille.Infrastructure.Crypto.CryptoNif", load = load);
\ No newline at end of file
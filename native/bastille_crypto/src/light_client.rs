@@ -0,0 +1,134 @@
+// A light client: it trusts one validator set root, follows the chain through
+// finality proofs (finality.rs) and headers that extend the last one it has
+// (header.rs), and answers whether a leaf is in the Merkle root of a header
+// it has verified. It holds header hashes and roots only, never blocks, so
+// the node can use it for checkpoint sync and an Elixir app embedding the
+// NIF can check payments without running a node.
+//
+// The headers held form one chain, oldest first: finalized up to the last
+// finality proof, then the headers fed since, whose proof of work and links
+// are checked but which a later proof may replace with another fork. At
+// most max_headers are kept, the oldest dropped first.
+
+use crate::header::{self, HASH_LEN};
+use crate::{finality, merkle};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+pub const DEFAULT_MAX_HEADERS: usize = 10_000;
+
+struct Entry {
+    hash: [u8; HASH_LEN],
+    height: u64,
+    merkle_root: [u8; HASH_LEN],
+}
+
+struct State {
+    set_root: [u8; finality::ROOT_LEN],
+    chain: VecDeque<Entry>,
+    // The newest header, which the next one must extend
+    head: Option<Vec<u8>>,
+    // (height, hash) of the last finalized header
+    finalized: Option<(u64, [u8; HASH_LEN])>,
+}
+
+pub struct LightClient {
+    max_headers: usize,
+    state: Mutex<State>,
+}
+
+#[rustler::resource_impl]
+impl rustler::Resource for LightClient {}
+
+pub struct Status {
+    pub set_root: [u8; finality::ROOT_LEN],
+    // (height, hash)
+    pub finalized: Option<(u64, [u8; HASH_LEN])>,
+    pub head: Option<(u64, [u8; HASH_LEN])>,
+}
+
+impl State {
+    fn push(&mut self, entry: Entry, bytes: &[u8], max_headers: usize) {
+        self.chain.push_back(entry);
+        while self.chain.len() > max_headers {
+            self.chain.pop_front();
+        }
+        self.head = Some(bytes.to_vec());
+    }
+
+    fn find(&self, hash: &[u8]) -> Option<&Entry> {
+        self.chain.iter().rev().find(|entry| entry.hash[..] == *hash)
+    }
+}
+
+impl LightClient {
+    /// None unless max_headers >= 1.
+    pub fn new(set_root: [u8; finality::ROOT_LEN], max_headers: usize) -> Option<Self> {
+        if max_headers == 0 {
+            return None;
+        }
+        let state = State { set_root, chain: VecDeque::new(), head: None, finalized: None };
+        Some(LightClient { max_headers, state: Mutex::new(state) })
+    }
+
+    fn lock(&self) -> Result<std::sync::MutexGuard<'_, State>, String> {
+        self.state.lock().map_err(|_| "light client lock poisoned".to_string())
+    }
+
+    /// Finalize the header of `proof`, signed by the current validator set,
+    /// and move to the set it names. Returns the header's height.
+    pub fn add_finality_proof(&self, proof: &[u8]) -> Result<u64, String> {
+        let mut state = self.lock()?;
+        let finalized = finality::verify(proof, &state.set_root)?;
+        let height = finalized.header.height;
+        if state.finalized.is_some_and(|(last, _)| height <= last) {
+            return Err(format!("height {} is not past the finalized height", height));
+        }
+
+        if state.find(&finalized.hash).is_none() {
+            // Another fork, or a jump ahead: keep the chain up to its parent
+            // if we hold that, and drop the rest.
+            let parent = state
+                .chain
+                .iter()
+                .position(|entry| entry.hash == finalized.header.previous_hash && entry.height + 1 == height);
+            let keep = parent.map_or(0, |index| index + 1);
+            state.chain.truncate(keep);
+            let bytes = header::encode(&finalized.header)?;
+            let entry = Entry { hash: finalized.hash, height, merkle_root: finalized.header.merkle_root };
+            state.push(entry, &bytes, self.max_headers);
+        }
+        state.finalized = Some((height, finalized.hash));
+        state.set_root = finalized.next_set_root;
+        Ok(height)
+    }
+
+    /// Append a header extending the newest one held, checked as
+    /// header::validate does. Returns its hash.
+    pub fn add_header(&self, bytes: &[u8], params: &header::Params) -> Result<[u8; HASH_LEN], String> {
+        let mut state = self.lock()?;
+        let parent = state.head.as_ref().ok_or("no finalized header to extend yet")?;
+        let hash = header::validate(bytes, parent, params)?;
+        let (header, _) = header::decode(bytes)?;
+        let entry = Entry { hash, height: header.height, merkle_root: header.merkle_root };
+        state.push(entry, bytes, self.max_headers);
+        Ok(hash)
+    }
+
+    /// Whether `leaf` is in the Merkle root of the header with `header_hash`,
+    /// following `proof`: (height, finalized) if so.
+    pub fn verify_inclusion(&self, header_hash: &[u8], leaf: &[u8], proof: &[u8]) -> Result<(u64, bool), String> {
+        let state = self.lock()?;
+        let entry = state.find(header_hash).ok_or("unknown header")?;
+        if !merkle::verify_proof(&entry.merkle_root, leaf, proof) {
+            return Err("leaf not in the header's Merkle root".to_string());
+        }
+        Ok((entry.height, state.finalized.is_some_and(|(height, _)| entry.height <= height)))
+    }
+
+    pub fn status(&self) -> Result<Status, String> {
+        let state = self.lock()?;
+        let head = state.chain.back().map(|entry| (entry.height, entry.hash));
+        Ok(Status { set_root: state.set_root, finalized: state.finalized, head })
+    }
+}
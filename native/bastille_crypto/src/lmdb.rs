@@ -0,0 +1,105 @@
+// LMDB backend of storage.rs, for small nodes and CI: a single memory-mapped
+// file with no background threads or compaction. Each column is a named
+// database in one environment, so a write transaction spanning several of
+// them is atomic, and every commit is synced to disk.
+//
+// The map size caps the database size; the file only grows as data is
+// written. Keys are limited to 511 bytes.
+
+use crate::storage::{Column, Entry, Op, Scan};
+use heed::types::Bytes;
+use heed::{Database, Env, EnvOpenOptions};
+use std::ops::Bound;
+use std::path::Path;
+
+type Table = Database<Bytes, Bytes>;
+
+pub struct LmdbStore {
+    env: Env,
+    tables: [Table; 3],
+}
+
+fn db_error(e: heed::Error) -> String {
+    e.to_string()
+}
+
+fn take<'t>(entries: impl Iterator<Item = heed::Result<(&'t [u8], &'t [u8])>>, count: usize) -> Result<Vec<Entry>, String> {
+    entries.take(count).map(|entry| entry.map(|(key, value)| (key.to_vec(), value.to_vec())).map_err(db_error)).collect()
+}
+
+impl LmdbStore {
+    pub fn open(dir: &Path, map_size: usize) -> Result<Self, String> {
+        std::fs::create_dir_all(dir).map_err(|e| format!("failed to create {}: {}", dir.display(), e))?;
+        let mut opts = EnvOpenOptions::new();
+        // A multiple of any page size in use
+        opts.map_size(map_size.next_multiple_of(1 << 16)).max_dbs(Column::ALL.len() as u32);
+        // SAFETY: the environment is only touched through heed, which refuses
+        // to open the same path twice in this process, and the files are not
+        // modified behind LMDB's lock by anything else
+        let env = unsafe { opts.open(dir) }.map_err(db_error)?;
+
+        let mut txn = env.write_txn().map_err(db_error)?;
+        let mut tables = Vec::with_capacity(Column::ALL.len());
+        for column in Column::ALL {
+            tables.push(env.create_database(&mut txn, Some(column.name())).map_err(db_error)?);
+        }
+        txn.commit().map_err(db_error)?;
+        let tables = tables.try_into().unwrap();
+        Ok(LmdbStore { env, tables })
+    }
+
+    fn table(&self, column: Column) -> &Table {
+        &self.tables[column as usize]
+    }
+
+    // Blocks until the environment is closed, so the directory can be
+    // reopened straight away
+    pub fn close(self) -> Result<(), String> {
+        self.env.force_sync().map_err(db_error)?;
+        self.env.prepare_for_closing().wait();
+        Ok(())
+    }
+
+    pub fn get<R>(&self, column: Column, key: &[u8], f: impl FnOnce(Option<&[u8]>) -> R) -> Result<R, String> {
+        let txn = self.env.read_txn().map_err(db_error)?;
+        let value = self.table(column).get(&txn, key).map_err(db_error)?;
+        Ok(f(value))
+    }
+
+    pub fn write(&self, ops: &[Op]) -> Result<(), String> {
+        let mut txn = self.env.write_txn().map_err(db_error)?;
+        for op in ops {
+            match op {
+                Op::Put(column, key, value) => self.table(*column).put(&mut txn, key, value).map_err(db_error)?,
+                Op::Delete(column, key) => {
+                    self.table(*column).delete(&mut txn, key).map_err(db_error)?;
+                }
+            }
+        }
+        // Dropping the transaction on an error above aborts it
+        txn.commit().map_err(db_error)
+    }
+
+    pub fn compact(&self, _column: Column) -> Result<(), String> {
+        Ok(())
+    }
+
+    pub fn scan(&self, column: Column, scan: &Scan, count: usize) -> Result<Vec<Entry>, String> {
+        let txn = self.env.read_txn().map_err(db_error)?;
+        let lower = scan.from.map_or(Bound::Unbounded, Bound::Included);
+        let upper = scan.to.map_or(Bound::Unbounded, Bound::Excluded);
+        let bounds = match (scan.after, scan.at, scan.reverse) {
+            (Some(after), _, false) => (Bound::Excluded(after), upper),
+            (Some(after), _, true) => (lower, Bound::Excluded(after)),
+            (None, Some(at), false) => (Bound::Included(at), upper),
+            (None, Some(at), true) => (lower, Bound::Included(at)),
+            (None, None, _) => (lower, upper),
+        };
+        let table = self.table(column);
+        if scan.reverse {
+            take(table.rev_range(&txn, &bounds).map_err(db_error)?, count)
+        } else {
+            take(table.range(&txn, &bounds).map_err(db_error)?, count)
+        }
+    }
+}
@@ -0,0 +1,227 @@
+// Binary Merkle trees over BLAKE3 with domain-separated hashing:
+//
+//   leaf = blake3(0x00 | data)
+//   node = blake3(0x01 | left | right)
+//
+// so a leaf can never be passed off as an interior node. An odd node at the
+// end of a level is promoted unchanged rather than paired with itself, which
+// keeps [a, b, c] and [a, b, c, c] from sharing a root. The empty tree's root
+// is 32 zero bytes, matching block headers without transactions.
+//
+// Large levels are hashed in parallel; below PARALLEL_THRESHOLD nodes the
+// rayon overhead outweighs the work.
+//
+// An inclusion proof lists, from the leaf up, the sibling of each node on the
+// path, as 33-byte steps: a side byte (SIBLING_LEFT or SIBLING_RIGHT) then the
+// sibling hash. Levels where the node was promoted have no step.
+//
+// A multiproof covers several leaves at once: the leaf count (u64 BE), then
+// only the sibling hashes the verifier can't compute from the proven leaves
+// themselves, level by level from the leaves up and left to right within a
+// level. Sides follow from the leaf indices, which the verifier is given.
+
+use rayon::prelude::*;
+
+pub type Hash = [u8; 32];
+
+pub const EMPTY_ROOT: Hash = [0u8; 32];
+
+const LEAF_TAG: u8 = 0x00;
+const NODE_TAG: u8 = 0x01;
+const PARALLEL_THRESHOLD: usize = 1024;
+
+const SIBLING_LEFT: u8 = 0x00;
+const SIBLING_RIGHT: u8 = 0x01;
+const PROOF_STEP_LEN: usize = 1 + 32;
+
+pub fn hash_leaf(data: &[u8]) -> Hash {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(&[LEAF_TAG]);
+    hasher.update(data);
+    *hasher.finalize().as_bytes()
+}
+
+pub fn hash_node(left: &Hash, right: &Hash) -> Hash {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(&[NODE_TAG]);
+    hasher.update(left);
+    hasher.update(right);
+    *hasher.finalize().as_bytes()
+}
+
+fn combine_pair(pair: &[Hash]) -> Hash {
+    match pair {
+        [left, right] => hash_node(left, right),
+        [single] => *single,
+        _ => unreachable!("chunks of two"),
+    }
+}
+
+fn hash_leaves<L: AsRef<[u8]> + Sync>(leaves: &[L]) -> Vec<Hash> {
+    if leaves.len() >= PARALLEL_THRESHOLD {
+        crate::threads::install(|| leaves.par_iter().map(|leaf| hash_leaf(leaf.as_ref())).collect())
+    } else {
+        leaves.iter().map(|leaf| hash_leaf(leaf.as_ref())).collect()
+    }
+}
+
+fn next_level(level: &[Hash]) -> Vec<Hash> {
+    if level.len() >= PARALLEL_THRESHOLD {
+        crate::threads::install(|| level.par_chunks(2).map(combine_pair).collect())
+    } else {
+        level.chunks(2).map(combine_pair).collect()
+    }
+}
+
+/// Root of the tree over `leaves`, in order.
+pub fn root<L: AsRef<[u8]> + Sync>(leaves: &[L]) -> Hash {
+    if leaves.is_empty() {
+        return EMPTY_ROOT;
+    }
+    let mut level = hash_leaves(leaves);
+    while level.len() > 1 {
+        level = next_level(&level);
+    }
+    level[0]
+}
+
+/// A tree kept in memory with every level, to serve inclusion proofs.
+pub struct MerkleTree {
+    // levels[0] holds the leaf hashes, the last level the root
+    levels: Vec<Vec<Hash>>,
+}
+
+#[rustler::resource_impl]
+impl rustler::Resource for MerkleTree {}
+
+impl MerkleTree {
+    pub fn build<L: AsRef<[u8]> + Sync>(leaves: &[L]) -> Self {
+        let mut levels = Vec::new();
+        if !leaves.is_empty() {
+            levels.push(hash_leaves(leaves));
+            while levels[levels.len() - 1].len() > 1 {
+                let next = next_level(&levels[levels.len() - 1]);
+                levels.push(next);
+            }
+        }
+        MerkleTree { levels }
+    }
+
+    pub fn root(&self) -> Hash {
+        self.levels.last().map_or(EMPTY_ROOT, |level| level[0])
+    }
+
+    /// Inclusion proof for the leaf at `index`, None if out of range.
+    pub fn proof(&self, mut index: usize) -> Option<Vec<u8>> {
+        if index >= self.levels.first()?.len() {
+            return None;
+        }
+        let mut proof = Vec::with_capacity(self.levels.len() * PROOF_STEP_LEN);
+        for level in &self.levels[..self.levels.len() - 1] {
+            let sibling = index ^ 1;
+            if sibling < level.len() {
+                proof.push(if sibling < index { SIBLING_LEFT } else { SIBLING_RIGHT });
+                proof.extend_from_slice(&level[sibling]);
+            }
+            index /= 2;
+        }
+        Some(proof)
+    }
+
+    /// Multiproof for the leaves at `indices` (in any order, duplicates
+    /// ignored), None if empty or any index is out of range.
+    pub fn multiproof(&self, indices: &[usize]) -> Option<Vec<u8>> {
+        let leaves = self.levels.first()?;
+        if indices.is_empty() || indices.iter().any(|index| *index >= leaves.len()) {
+            return None;
+        }
+        let mut indices = indices.to_vec();
+        indices.sort_unstable();
+        indices.dedup();
+        let known = indices.into_iter().map(|index| (index, leaves[index])).collect();
+
+        let mut proof = (leaves.len() as u64).to_be_bytes().to_vec();
+        walk_multiproof(leaves.len(), known, |level, index| {
+            let sibling = self.levels[level][index];
+            proof.extend_from_slice(&sibling);
+            Some(sibling)
+        })?;
+        Some(proof)
+    }
+}
+
+// Hash from the known nodes (sorted, unique leaf indices) up to the root,
+// calling `sibling(level, index)` for each node that must come from the proof,
+// in proof order. None if `sibling` does.
+fn walk_multiproof(
+    mut size: usize,
+    mut known: Vec<(usize, Hash)>,
+    mut sibling: impl FnMut(usize, usize) -> Option<Hash>,
+) -> Option<Hash> {
+    let mut level = 0;
+    while size > 1 {
+        let mut next = Vec::with_capacity(known.len());
+        let mut nodes = known.into_iter().peekable();
+        while let Some((index, hash)) = nodes.next() {
+            let parent = if index % 2 == 1 {
+                hash_node(&sibling(level, index - 1)?, &hash)
+            } else if index + 1 == size {
+                hash
+            } else if let Some((_, right)) = nodes.next_if(|(next, _)| *next == index + 1) {
+                hash_node(&hash, &right)
+            } else {
+                hash_node(&hash, &sibling(level, index + 1)?)
+            };
+            next.push((index / 2, parent));
+        }
+        known = next;
+        size = size.div_ceil(2);
+        level += 1;
+    }
+    known.first().map(|(_, root)| *root)
+}
+
+/// Check that `leaf` is in the tree with `root`, following `proof`.
+pub fn verify_proof(root: &[u8], leaf: &[u8], proof: &[u8]) -> bool {
+    if !proof.len().is_multiple_of(PROOF_STEP_LEN) {
+        return false;
+    }
+    let mut node = hash_leaf(leaf);
+    for step in proof.chunks_exact(PROOF_STEP_LEN) {
+        let mut sibling = [0u8; 32];
+        sibling.copy_from_slice(&step[1..]);
+        node = match step[0] {
+            SIBLING_LEFT => hash_node(&sibling, &node),
+            SIBLING_RIGHT => hash_node(&node, &sibling),
+            _ => return false,
+        };
+    }
+    node[..] == *root
+}
+
+/// Check that each `(index, leaf)` is in the tree with `root`, following a
+/// multiproof. Indices must be distinct.
+pub fn verify_multiproof(root: &[u8], leaves: &[(usize, &[u8])], proof: &[u8]) -> bool {
+    let Some((count, siblings)) = proof.split_first_chunk::<8>() else {
+        return false;
+    };
+    let Ok(count) = usize::try_from(u64::from_be_bytes(*count)) else {
+        return false;
+    };
+    if leaves.is_empty() || !siblings.len().is_multiple_of(32) {
+        return false;
+    }
+    let mut known: Vec<(usize, Hash)> = leaves.iter().map(|(index, leaf)| (*index, hash_leaf(leaf))).collect();
+    known.sort_unstable_by_key(|(index, _)| *index);
+    if known.windows(2).any(|pair| pair[0].0 == pair[1].0) || known[known.len() - 1].0 >= count {
+        return false;
+    }
+
+    let mut siblings = siblings.chunks_exact(32);
+    let computed = walk_multiproof(count, known, |_, _| {
+        let mut sibling = [0u8; 32];
+        sibling.copy_from_slice(siblings.next()?);
+        Some(sibling)
+    });
+    siblings.len() == 0 && computed.is_some_and(|computed| computed[..] == *root)
+}
@@ -0,0 +1,266 @@
+// Gas metering for contract modules (the `wasm` feature). The chain stores
+// what meter() makes of an uploaded module, not the upload itself, so the gas
+// a call uses is counted by the module's own code and every node charges the
+// same, whatever engine or engine version runs it.
+//
+// - Only deterministic code is accepted: the "lime1" feature set (the MVP
+//   plus multi-value, sign extension, memory.copy/fill and extended constant
+//   expressions) without floats, whose NaN bits vary with the CPU. SIMD,
+//   threads, reference types, memory64 and the rest fail validation.
+// - Custom sections (names, producers, debug info) are dropped, so the output
+//   depends on the code alone.
+// - A mutable i64 global exported as "bastille_gas" holds the gas left. Each
+//   run of instructions up to the next branch, block boundary or return
+//   first subtracts its length from it, then traps with unreachable if it
+//   went negative. A run is charged in full on entry, even if it traps
+//   halfway.
+// - memory.fill and memory.copy also cost one gas per byte, and memory.grow
+//   one per byte of new memory, charged from their length operand (moved
+//   through an extra i32 local) just before they run.
+//
+// The original code can't name the new global: its index comes after every
+// global it was validated with.
+
+use wasm_encoder::reencode::{Reencode, RoundtripReencoder};
+use wasm_encoder::{
+    BlockType, CodeSection, ConstExpr, ExportKind, ExportSection, Function, GlobalSection, GlobalType, Instruction,
+    RawSection, ValType,
+};
+use wasmparser::{Operator, Parser, Payload, TypeRef, Validator, WasmFeatures};
+
+pub const GAS_GLOBAL: &str = "bastille_gas";
+
+const GAS_PER_BYTE: i64 = 1;
+const PAGE_BYTES: i64 = 65536;
+
+fn validate(code: &[u8]) -> Result<(), String> {
+    let features = WasmFeatures::LIME1.difference(WasmFeatures::FLOATS | WasmFeatures::SATURATING_FLOAT_TO_INT);
+    Validator::new_with_features(features).validate_all(code).map(|_| ()).map_err(|e| e.to_string())
+}
+
+// Whether control may leave or enter a block right after `op`
+fn ends_run(op: &Operator) -> bool {
+    matches!(
+        op,
+        Operator::Block { .. }
+            | Operator::Loop { .. }
+            | Operator::If { .. }
+            | Operator::Else
+            | Operator::End
+            | Operator::Br { .. }
+            | Operator::BrIf { .. }
+            | Operator::BrTable { .. }
+            | Operator::Return
+            | Operator::Unreachable
+    )
+}
+
+fn charge(function: &mut Function, gas: u32, cost: usize) {
+    function
+        .instruction(&Instruction::GlobalGet(gas))
+        .instruction(&Instruction::I64Const(cost as i64))
+        .instruction(&Instruction::I64Sub)
+        .instruction(&Instruction::GlobalSet(gas))
+        .instruction(&Instruction::GlobalGet(gas))
+        .instruction(&Instruction::I64Const(0))
+        .instruction(&Instruction::I64LtS)
+        .instruction(&Instruction::If(BlockType::Empty))
+        .instruction(&Instruction::Unreachable)
+        .instruction(&Instruction::End);
+}
+
+// Gas per unit of the length operand of `op`, for operators whose work grows with it
+fn length_cost(op: &Operator) -> Option<i64> {
+    match op {
+        Operator::MemoryFill { .. } | Operator::MemoryCopy { .. } => Some(GAS_PER_BYTE),
+        Operator::MemoryGrow { .. } => Some(GAS_PER_BYTE * PAGE_BYTES),
+        _ => None,
+    }
+}
+
+// Charge `cost` per unit of the i32 on top of the stack, leaving it there
+fn charge_length(function: &mut Function, gas: u32, scratch: u32, cost: i64) {
+    function
+        .instruction(&Instruction::LocalTee(scratch))
+        .instruction(&Instruction::GlobalGet(gas))
+        .instruction(&Instruction::LocalGet(scratch))
+        .instruction(&Instruction::I64ExtendI32U)
+        .instruction(&Instruction::I64Const(cost))
+        .instruction(&Instruction::I64Mul)
+        .instruction(&Instruction::I64Sub)
+        .instruction(&Instruction::GlobalSet(gas))
+        .instruction(&Instruction::GlobalGet(gas))
+        .instruction(&Instruction::I64Const(0))
+        .instruction(&Instruction::I64LtS)
+        .instruction(&Instruction::If(BlockType::Empty))
+        .instruction(&Instruction::Unreachable)
+        .instruction(&Instruction::End);
+}
+
+fn meter_body(body: &wasmparser::FunctionBody, gas: u32, params: u32) -> Result<Function, String> {
+    let mut reencoder = RoundtripReencoder;
+    // The original locals, then the scratch local for length operands
+    let mut locals = Vec::new();
+    let mut scratch = params;
+    for local in body.get_locals_reader().map_err(|e| e.to_string())? {
+        let (count, ty) = local.map_err(|e| e.to_string())?;
+        locals.push((count, reencoder.val_type(ty).map_err(|e| e.to_string())?));
+        scratch += count;
+    }
+    locals.push((1, ValType::I32));
+    let mut function = Function::new(locals);
+    let mut operators = Vec::new();
+    let mut reader = body.get_operators_reader().map_err(|e| e.to_string())?;
+    while !reader.eof() {
+        operators.push(reader.read().map_err(|e| e.to_string())?);
+    }
+    for run in operators.split_inclusive(ends_run) {
+        charge(&mut function, gas, run.len());
+        for op in run {
+            if let Some(cost) = length_cost(op) {
+                charge_length(&mut function, gas, scratch, cost);
+            }
+            function.instruction(&reencoder.instruction(op.clone()).map_err(|e| e.to_string())?);
+        }
+    }
+    Ok(function)
+}
+
+struct Output {
+    module: wasm_encoder::Module,
+    imported_globals: u32,
+    // Index of the gas global, once written
+    gas: Option<u32>,
+    exported: bool,
+    code: Option<(CodeSection, u32)>,
+    // Parameter count of each type, and the type of each function, imports first
+    type_params: Vec<u32>,
+    function_types: Vec<u32>,
+    imported_functions: u32,
+}
+
+impl Output {
+    fn globals(&mut self, mut section: GlobalSection) {
+        let index = self.imported_globals + section.len();
+        let ty = GlobalType { val_type: ValType::I64, mutable: true, shared: false };
+        section.global(ty, &ConstExpr::i64_const(i64::MAX));
+        self.module.section(&section);
+        self.gas = Some(index);
+    }
+
+    fn exports(&mut self, mut section: ExportSection) -> Result<(), String> {
+        let gas = self.gas.ok_or("gas global missing")?;
+        section.export(GAS_GLOBAL, ExportKind::Global, gas);
+        self.module.section(&section);
+        self.exported = true;
+        Ok(())
+    }
+
+    // Write the gas global and its export, unless already done, if a section
+    // with id `id` comes after them (custom sections aside, the global
+    // section is 6 and the export section 7; 8 to 12 follow both).
+    fn before(&mut self, id: u8) -> Result<(), String> {
+        if (7..=12).contains(&id) && self.gas.is_none() {
+            self.globals(GlobalSection::new());
+        }
+        if (8..=12).contains(&id) && !self.exported {
+            self.exports(ExportSection::new())?;
+        }
+        Ok(())
+    }
+}
+
+/// The canonical metered form of `code` (binary, or text for tests and
+/// tooling), or why it can't run as a contract.
+pub fn meter(code: &[u8]) -> Result<Vec<u8>, String> {
+    let code = wat::parse_bytes(code).map_err(|e| format!("invalid module: {}", e))?;
+    validate(&code).map_err(|e| format!("invalid module: {}", e))?;
+
+    let mut reencoder = RoundtripReencoder;
+    let mut out = Output {
+        module: wasm_encoder::Module::new(),
+        imported_globals: 0,
+        gas: None,
+        exported: false,
+        code: None,
+        type_params: Vec::new(),
+        function_types: Vec::new(),
+        imported_functions: 0,
+    };
+    for payload in Parser::new(0).parse_all(&code) {
+        match payload.map_err(|e| e.to_string())? {
+            Payload::ImportSection(reader) => {
+                for import in reader.clone().into_imports() {
+                    match import.map_err(|e| e.to_string())?.ty {
+                        TypeRef::Global(_) => out.imported_globals += 1,
+                        TypeRef::Func(ty) => {
+                            out.function_types.push(ty);
+                            out.imported_functions += 1;
+                        }
+                        _ => {}
+                    }
+                }
+                out.module.section(&RawSection { id: 2, data: &code[reader.range()] });
+            }
+            Payload::TypeSection(reader) => {
+                for ty in reader.clone().into_iter_err_on_gc_types() {
+                    out.type_params.push(ty.map_err(|e| e.to_string())?.params().len() as u32);
+                }
+                out.module.section(&RawSection { id: 1, data: &code[reader.range()] });
+            }
+            Payload::FunctionSection(reader) => {
+                for ty in reader.clone() {
+                    out.function_types.push(ty.map_err(|e| e.to_string())?);
+                }
+                out.module.section(&RawSection { id: 3, data: &code[reader.range()] });
+            }
+            Payload::GlobalSection(reader) => {
+                let mut section = GlobalSection::new();
+                reencoder.parse_global_section(&mut section, reader).map_err(|e| e.to_string())?;
+                out.globals(section);
+            }
+            Payload::ExportSection(reader) => {
+                out.before(7)?;
+                let mut section = ExportSection::new();
+                for export in reader {
+                    let export = export.map_err(|e| e.to_string())?;
+                    if export.name == GAS_GLOBAL {
+                        return Err(format!("module already exports {}", GAS_GLOBAL));
+                    }
+                    reencoder.parse_export(&mut section, export).map_err(|e| e.to_string())?;
+                }
+                out.exports(section)?;
+            }
+            Payload::CodeSectionStart { count, .. } => {
+                out.before(10)?;
+                if count == 0 {
+                    out.module.section(&CodeSection::new());
+                } else {
+                    out.code = Some((CodeSection::new(), count));
+                }
+            }
+            Payload::CodeSectionEntry(body) => {
+                let gas = out.gas.ok_or("gas global missing")?;
+                let (section, count) = out.code.as_mut().ok_or("function body outside the code section")?;
+                let index = (out.imported_functions + section.len()) as usize;
+                let params = out.function_types.get(index).and_then(|&ty| out.type_params.get(ty as usize));
+                section.function(&meter_body(&body, gas, *params.ok_or("function without a type")?)?);
+                if section.len() == *count {
+                    let (section, _) = out.code.take().ok_or("function body outside the code section")?;
+                    out.module.section(&section);
+                }
+            }
+            Payload::CustomSection(_) | Payload::Version { .. } | Payload::End(_) => {}
+            payload => {
+                let (id, range) = payload.as_section().ok_or("unexpected section")?;
+                out.before(id)?;
+                out.module.section(&RawSection { id, data: &code[range] });
+            }
+        }
+    }
+    out.before(12)?;
+
+    let metered = out.module.finish();
+    validate(&metered).map_err(|e| format!("metered module invalid: {}", e))?;
+    Ok(metered)
+}
@@ -0,0 +1,152 @@
+// Merkle Mountain Range: an append-only accumulator over block headers, so a
+// light client holding one root can be shown that any earlier header is part
+// of the history it commits to.
+//
+// Leaves and nodes hash as in merkle.rs. The range is a list of perfect trees
+// ("peaks"), one per set bit of the leaf count, largest first; appending a
+// leaf merges equal-height peaks. The root commits to the leaf count and the
+// peaks bagged right to left:
+//
+//   root = blake3(0x02 | count (u64 BE) | bag)
+//   bag  = node(peak_0, node(peak_1, ... peak_last))
+//
+// and is 32 zero bytes while the range is empty.
+//
+// A proof is the leaf count (u64 BE), then the siblings from the leaf up to
+// its peak, then the other peaks left to right. Sides follow from the leaf
+// index, which the verifier is given, so the proof also pins the position.
+
+use crate::merkle::{hash_leaf, hash_node, Hash, EMPTY_ROOT};
+use std::sync::Mutex;
+
+const ROOT_TAG: u8 = 0x02;
+
+// Heights of the peaks of a range of `count` leaves, largest first
+fn peak_heights(count: u64) -> impl Iterator<Item = u32> {
+    (0..u64::BITS).rev().filter(move |height| count >> height & 1 == 1)
+}
+
+fn bag(count: u64, peaks: &[Hash]) -> Hash {
+    let Some((last, rest)) = peaks.split_last() else {
+        return EMPTY_ROOT;
+    };
+    let bagged = rest.iter().rev().fold(*last, |acc, peak| hash_node(peak, &acc));
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(&[ROOT_TAG]);
+    hasher.update(&count.to_be_bytes());
+    hasher.update(&bagged);
+    *hasher.finalize().as_bytes()
+}
+
+// (peak position, peak height, leaf index within the peak)
+fn locate(count: u64, index: u64) -> Option<(usize, u32, u64)> {
+    let mut start = 0u64;
+    for (position, height) in peak_heights(count).enumerate() {
+        let size = 1u64 << height;
+        if index < start + size {
+            return Some((position, height, index - start));
+        }
+        start += size;
+    }
+    None
+}
+
+pub struct MountainRange {
+    // levels[h] holds every completed node of height h, left to right
+    levels: Mutex<Vec<Vec<Hash>>>,
+}
+
+#[rustler::resource_impl]
+impl rustler::Resource for MountainRange {}
+
+fn peaks(levels: &[Vec<Hash>], count: u64) -> Vec<Hash> {
+    peak_heights(count)
+        .map(|height| levels[height as usize][(count >> height) as usize - 1])
+        .collect()
+}
+
+impl MountainRange {
+    pub fn new() -> Self {
+        MountainRange { levels: Mutex::new(vec![Vec::new()]) }
+    }
+
+    fn lock(&self) -> Result<std::sync::MutexGuard<'_, Vec<Vec<Hash>>>, String> {
+        self.levels.lock().map_err(|_| "mountain range lock poisoned".to_string())
+    }
+
+    /// Append a leaf, returning its index.
+    pub fn append(&self, leaf: &[u8]) -> Result<u64, String> {
+        let mut levels = self.lock()?;
+        let index = levels[0].len() as u64;
+        levels[0].push(hash_leaf(leaf));
+        let mut height = 0;
+        while levels[height].len().is_multiple_of(2) {
+            let level = &levels[height];
+            let parent = hash_node(&level[level.len() - 2], &level[level.len() - 1]);
+            if levels.len() == height + 1 {
+                levels.push(Vec::new());
+            }
+            levels[height + 1].push(parent);
+            height += 1;
+        }
+        Ok(index)
+    }
+
+    pub fn root(&self) -> Result<Hash, String> {
+        let levels = self.lock()?;
+        let count = levels[0].len() as u64;
+        Ok(bag(count, &peaks(&levels, count)))
+    }
+
+    /// Proof for the leaf at `index` against the current root, None if out
+    /// of range.
+    pub fn proof(&self, index: u64) -> Result<Option<Vec<u8>>, String> {
+        let levels = self.lock()?;
+        let count = levels[0].len() as u64;
+        let Some((position, height, _)) = locate(count, index) else {
+            return Ok(None);
+        };
+        let mut proof = count.to_be_bytes().to_vec();
+        for (level, nodes) in levels.iter().enumerate().take(height as usize) {
+            let sibling = (index >> level) ^ 1;
+            proof.extend_from_slice(&nodes[sibling as usize]);
+        }
+        for (other, peak) in peaks(&levels, count).iter().enumerate() {
+            if other != position {
+                proof.extend_from_slice(peak);
+            }
+        }
+        Ok(Some(proof))
+    }
+}
+
+/// Check that `leaf` is the leaf at `index` of the range with `root`.
+pub fn verify_proof(root: &[u8], index: u64, leaf: &[u8], proof: &[u8]) -> bool {
+    let Some((count, hashes)) = proof.split_first_chunk::<8>() else {
+        return false;
+    };
+    let count = u64::from_be_bytes(*count);
+    let Some((position, height, local)) = locate(count, index) else {
+        return false;
+    };
+    let peak_count = peak_heights(count).count();
+    if hashes.len() != 32 * (height as usize + peak_count - 1) {
+        return false;
+    }
+    let mut hashes = hashes.chunks_exact(32).map(|chunk| {
+        let mut hash = [0u8; 32];
+        hash.copy_from_slice(chunk);
+        hash
+    });
+
+    let mut node = hash_leaf(leaf);
+    for level in 0..height {
+        let Some(sibling) = hashes.next() else {
+            return false;
+        };
+        node = if local >> level & 1 == 1 { hash_node(&sibling, &node) } else { hash_node(&node, &sibling) };
+    }
+    let mut peaks: Vec<Hash> = hashes.collect();
+    peaks.insert(position, node);
+    bag(count, &peaks)[..] == *root
+}
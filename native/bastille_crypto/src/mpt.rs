@@ -0,0 +1,331 @@
+// Ethereum's Merkle Patricia Trie (yellow paper, appendix D), for
+// EVM-compatible state commitments and for checking eth_getProof results.
+//
+// Nodes are RLP-encoded leaf, extension and 17-item branch nodes with
+// hex-prefix paths; a child whose encoding is under 32 bytes is embedded in
+// its parent, anything larger is referenced by Keccak-256. Keys are used as
+// given: Ethereum's state and storage tries hash them (keccak256(address),
+// keccak256(slot)) before insertion, and so must callers.
+//
+// A proof is the list of hashed nodes on the key's path, root first, as
+// returned by eth_getProof. Node hashes aren't cached: root_hash and proof
+// rehash the whole trie, which is fine for per-block commitments but not for
+// serving proofs from a large trie in a loop.
+
+use crate::rlp::{self, Item};
+use sha3::{Digest, Keccak256};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+pub type Hash = [u8; 32];
+
+pub const MAX_KEY_LEN: usize = 256;
+
+// Upper bound on nodes walked while verifying, against cyclic proofs
+const MAX_PROOF_STEPS: usize = 2 * MAX_KEY_LEN + 2;
+
+fn keccak(data: &[u8]) -> Hash {
+    Keccak256::digest(data).into()
+}
+
+fn nibbles(key: &[u8]) -> Vec<u8> {
+    key.iter().flat_map(|byte| [byte >> 4, byte & 0x0f]).collect()
+}
+
+fn common_prefix(a: &[u8], b: &[u8]) -> usize {
+    a.iter().zip(b).take_while(|(x, y)| x == y).count()
+}
+
+fn hex_prefix(path: &[u8], leaf: bool) -> Vec<u8> {
+    let flag = if leaf { 2 } else { 0 } + (path.len() % 2) as u8;
+    let mut encoded = Vec::with_capacity(path.len() / 2 + 1);
+    let rest = if path.len() % 2 == 1 {
+        encoded.push((flag << 4) | path[0]);
+        &path[1..]
+    } else {
+        encoded.push(flag << 4);
+        path
+    };
+    encoded.extend(rest.chunks(2).map(|pair| (pair[0] << 4) | pair[1]));
+    encoded
+}
+
+// (nibbles, is_leaf)
+fn decode_hex_prefix(encoded: &[u8]) -> Result<(Vec<u8>, bool), String> {
+    let first = *encoded.first().ok_or("empty node path")?;
+    let flag = first >> 4;
+    if flag > 3 || (flag & 1 == 0 && first & 0x0f != 0) {
+        return Err("invalid hex-prefix path".to_string());
+    }
+    let mut path = Vec::with_capacity(encoded.len() * 2);
+    if flag & 1 == 1 {
+        path.push(first & 0x0f);
+    }
+    path.extend(nibbles(&encoded[1..]));
+    Ok((path, flag & 2 == 2))
+}
+
+enum Node {
+    Empty,
+    Leaf { path: Vec<u8>, value: Vec<u8> },
+    Extension { path: Vec<u8>, child: Box<Node> },
+    Branch { children: Box<[Node; 16]>, value: Option<Vec<u8>> },
+}
+
+fn empty_branch() -> Box<[Node; 16]> {
+    Box::new(std::array::from_fn(|_| Node::Empty))
+}
+
+fn with_extension(path: &[u8], branch: Node) -> Node {
+    if path.is_empty() {
+        branch
+    } else {
+        Node::Extension { path: path.to_vec(), child: Box::new(branch) }
+    }
+}
+
+fn insert(node: Node, path: &[u8], value: Vec<u8>) -> Node {
+    match node {
+        Node::Empty => Node::Leaf { path: path.to_vec(), value },
+        Node::Leaf { path: leaf_path, value: leaf_value } => {
+            let common = common_prefix(&leaf_path, path);
+            if common == leaf_path.len() && common == path.len() {
+                return Node::Leaf { path: leaf_path, value };
+            }
+            let mut children = empty_branch();
+            let mut branch_value = None;
+            for (node_path, node_value) in [(leaf_path.as_slice(), leaf_value), (path, value)] {
+                if node_path.len() == common {
+                    branch_value = Some(node_value);
+                } else {
+                    children[node_path[common] as usize] =
+                        Node::Leaf { path: node_path[common + 1..].to_vec(), value: node_value };
+                }
+            }
+            with_extension(&path[..common], Node::Branch { children, value: branch_value })
+        }
+        Node::Extension { path: extension_path, child } => {
+            let common = common_prefix(&extension_path, path);
+            if common == extension_path.len() {
+                let child = insert(*child, &path[common..], value);
+                return Node::Extension { path: extension_path, child: Box::new(child) };
+            }
+            let mut children = empty_branch();
+            let rest = &extension_path[common + 1..];
+            children[extension_path[common] as usize] = if rest.is_empty() {
+                *child
+            } else {
+                Node::Extension { path: rest.to_vec(), child }
+            };
+            let mut branch_value = None;
+            if path.len() == common {
+                branch_value = Some(value);
+            } else {
+                children[path[common] as usize] = Node::Leaf { path: path[common + 1..].to_vec(), value };
+            }
+            with_extension(&path[..common], Node::Branch { children, value: branch_value })
+        }
+        Node::Branch { mut children, value: branch_value } => match path.split_first() {
+            None => Node::Branch { children, value: Some(value) },
+            Some((nibble, rest)) => {
+                let slot = &mut children[*nibble as usize];
+                *slot = insert(std::mem::replace(slot, Node::Empty), rest, value);
+                Node::Branch { children, value: branch_value }
+            }
+        },
+    }
+}
+
+fn get<'a>(mut node: &'a Node, mut path: &[u8]) -> Option<&'a [u8]> {
+    loop {
+        match node {
+            Node::Empty => return None,
+            Node::Leaf { path: leaf_path, value } => return (leaf_path.as_slice() == path).then_some(value.as_slice()),
+            Node::Extension { path: extension_path, child } => {
+                path = path.strip_prefix(extension_path.as_slice())?;
+                node = child;
+            }
+            Node::Branch { children, value } => match path.split_first() {
+                None => return value.as_deref(),
+                Some((nibble, rest)) => {
+                    node = &children[*nibble as usize];
+                    path = rest;
+                }
+            },
+        }
+    }
+}
+
+fn encode(node: &Node) -> Vec<u8> {
+    match node {
+        Node::Empty => rlp::encode_bytes(&[]),
+        Node::Leaf { path, value } => {
+            rlp::encode_list(&[rlp::encode_bytes(&hex_prefix(path, true)), rlp::encode_bytes(value)])
+        }
+        Node::Extension { path, child } => {
+            rlp::encode_list(&[rlp::encode_bytes(&hex_prefix(path, false)), reference(child)])
+        }
+        Node::Branch { children, value } => {
+            let mut items: Vec<Vec<u8>> = children.iter().map(reference).collect();
+            items.push(rlp::encode_bytes(value.as_deref().unwrap_or(&[])));
+            rlp::encode_list(&items)
+        }
+    }
+}
+
+// How a parent refers to `node`: embedded when short, by hash otherwise
+fn reference(node: &Node) -> Vec<u8> {
+    if let Node::Empty = node {
+        return rlp::encode_bytes(&[]);
+    }
+    let encoded = encode(node);
+    if encoded.len() < 32 {
+        encoded
+    } else {
+        rlp::encode_bytes(&keccak(&encoded))
+    }
+}
+
+pub struct PatriciaTrie {
+    root: Mutex<Node>,
+}
+
+#[rustler::resource_impl]
+impl rustler::Resource for PatriciaTrie {}
+
+impl PatriciaTrie {
+    pub fn new() -> Self {
+        PatriciaTrie { root: Mutex::new(Node::Empty) }
+    }
+
+    fn root_node(&self) -> Result<std::sync::MutexGuard<'_, Node>, String> {
+        self.root.lock().map_err(|_| "trie lock poisoned".to_string())
+    }
+
+    pub fn insert(&self, key: &[u8], value: &[u8]) -> Result<(), String> {
+        let mut root = self.root_node()?;
+        let node = std::mem::replace(&mut *root, Node::Empty);
+        *root = insert(node, &nibbles(key), value.to_vec());
+        Ok(())
+    }
+
+    /// Run `f` on the value stored under `key`, if any.
+    pub fn get<R>(&self, key: &[u8], f: impl FnOnce(Option<&[u8]>) -> R) -> Result<R, String> {
+        let root = self.root_node()?;
+        Ok(f(get(&root, &nibbles(key))))
+    }
+
+    pub fn root_hash(&self) -> Result<Hash, String> {
+        Ok(keccak(&encode(&*self.root_node()?)))
+    }
+
+    /// The hashed nodes on `key`'s path, root first. Proves absence too.
+    pub fn proof(&self, key: &[u8]) -> Result<Vec<Vec<u8>>, String> {
+        let root = self.root_node()?;
+        let mut proof = Vec::new();
+        let mut node: &Node = &root;
+        let key = nibbles(key);
+        let mut path = key.as_slice();
+        loop {
+            let encoded = encode(node);
+            if proof.is_empty() || encoded.len() >= 32 {
+                proof.push(encoded);
+            }
+            match node {
+                Node::Empty | Node::Leaf { .. } => break,
+                Node::Extension { path: extension_path, child } => match path.strip_prefix(extension_path.as_slice()) {
+                    Some(rest) => {
+                        path = rest;
+                        node = child;
+                    }
+                    None => break,
+                },
+                Node::Branch { children, .. } => match path.split_first() {
+                    Some((nibble, rest)) => {
+                        node = &children[*nibble as usize];
+                        path = rest;
+                    }
+                    None => break,
+                },
+            }
+        }
+        Ok(proof)
+    }
+}
+
+// Where the next node on the path comes from
+enum NodeRef<'a> {
+    Hash(&'a [u8]),
+    Inline(&'a [u8]),
+    Empty,
+}
+
+fn node_ref<'a>(item: &Item<'a>) -> Result<NodeRef<'a>, String> {
+    match item {
+        Item::Bytes([]) => Ok(NodeRef::Empty),
+        Item::Bytes(bytes) if bytes.len() == 32 => Ok(NodeRef::Hash(bytes)),
+        Item::List { raw, .. } if raw.len() < 32 => Ok(NodeRef::Inline(raw)),
+        _ => Err("invalid child reference".to_string()),
+    }
+}
+
+fn bytes<'a>(item: &Item<'a>) -> Result<&'a [u8], String> {
+    match item {
+        Item::Bytes(bytes) => Ok(bytes),
+        Item::List { .. } => Err("expected a byte string".to_string()),
+    }
+}
+
+/// Value under `key` in the trie with `root`, None if the proof shows it
+/// absent, or an error if the proof is malformed or incomplete.
+pub fn verify_proof(root: &[u8], key: &[u8], proof: &[&[u8]]) -> Result<Option<Vec<u8>>, String> {
+    let nodes: HashMap<Hash, &[u8]> = proof.iter().map(|node| (keccak(node), *node)).collect();
+    let key = nibbles(key);
+    let mut path = key.as_slice();
+    let mut next = NodeRef::Hash(root);
+
+    for _ in 0..MAX_PROOF_STEPS {
+        let encoded = match next {
+            NodeRef::Empty => return Ok(None),
+            NodeRef::Inline(encoded) => encoded,
+            NodeRef::Hash(hash) => {
+                let hash: Hash = hash.try_into().map_err(|_| "root must be 32 bytes")?;
+                *nodes.get(&hash).ok_or("proof is missing a node")?
+            }
+        };
+        let (item, len) = rlp::decode(encoded)?;
+        let payload = match item {
+            Item::List { payload, .. } if len == encoded.len() => payload,
+            _ => return Err("node is not an RLP list".to_string()),
+        };
+        let items = rlp::decode_list(payload)?;
+        match items.len() {
+            17 => match path.split_first() {
+                None => {
+                    let value = bytes(&items[16])?;
+                    return Ok((!value.is_empty()).then(|| value.to_vec()));
+                }
+                Some((nibble, rest)) => {
+                    next = node_ref(&items[*nibble as usize])?;
+                    path = rest;
+                }
+            },
+            2 => {
+                let (node_path, leaf) = decode_hex_prefix(bytes(&items[0])?)?;
+                if leaf {
+                    let value = bytes(&items[1])?;
+                    return Ok((node_path == path).then(|| value.to_vec()));
+                }
+                match path.strip_prefix(node_path.as_slice()) {
+                    Some(rest) => {
+                        next = node_ref(&items[1])?;
+                        path = rest;
+                    }
+                    None => return Ok(None),
+                }
+            }
+            _ => return Err("node is neither a branch, an extension nor a leaf".to_string()),
+        }
+    }
+    Err("proof is too deep".to_string())
+}
@@ -0,0 +1,261 @@
+// MessagePack (msgpack.org), for RPC responses. Values are written in their
+// shortest form; decoding accepts any form a packer may choose but checks
+// structure strictly: truncated input, trailing bytes, invalid UTF-8 in
+// strings, floats that aren't finite and nesting over the depth limit are
+// refused.
+//
+// Decoded strings, binaries and extensions are byte ranges of the input, so
+// they can be handed out without copying.
+
+use std::ops::Range;
+
+/// Default and greatest nesting of arrays and maps
+pub const MAX_DEPTH: usize = 128;
+
+pub fn put_nil(out: &mut Vec<u8>) {
+    out.push(0xc0);
+}
+
+pub fn put_bool(out: &mut Vec<u8>, value: bool) {
+    out.push(if value { 0xc3 } else { 0xc2 });
+}
+
+/// Put an integer in -2^63..2^64.
+pub fn put_int(out: &mut Vec<u8>, n: i128) -> Result<(), String> {
+    match n {
+        0..=0x7f => out.push(n as u8),
+        -32..=-1 => out.push(n as i8 as u8),
+        0x80..=0xff => out.extend_from_slice(&[0xcc, n as u8]),
+        0x100..=0xffff => {
+            out.push(0xcd);
+            out.extend_from_slice(&(n as u16).to_be_bytes());
+        }
+        0x1_0000..=0xffff_ffff => {
+            out.push(0xce);
+            out.extend_from_slice(&(n as u32).to_be_bytes());
+        }
+        0x1_0000_0000..=0xffff_ffff_ffff_ffff => {
+            out.push(0xcf);
+            out.extend_from_slice(&(n as u64).to_be_bytes());
+        }
+        -0x80..=-33 => out.extend_from_slice(&[0xd0, n as i8 as u8]),
+        -0x8000..=-0x81 => {
+            out.push(0xd1);
+            out.extend_from_slice(&(n as i16).to_be_bytes());
+        }
+        -0x8000_0000..=-0x8001 => {
+            out.push(0xd2);
+            out.extend_from_slice(&(n as i32).to_be_bytes());
+        }
+        -0x8000_0000_0000_0000..=-0x8000_0001 => {
+            out.push(0xd3);
+            out.extend_from_slice(&(n as i64).to_be_bytes());
+        }
+        _ => return Err("integer out of MessagePack range".to_string()),
+    }
+    Ok(())
+}
+
+pub fn put_f64(out: &mut Vec<u8>, value: f64) {
+    out.push(0xcb);
+    out.extend_from_slice(&value.to_be_bytes());
+}
+
+// A length in the shortest of the forms `small` (fix form: base and its
+// largest length), 8 (None if there's no 8-bit form), 16 and 32-bit
+fn put_len(out: &mut Vec<u8>, len: usize, small: Option<(u8, usize)>, forms: [Option<u8>; 3]) -> Result<(), String> {
+    match (small, forms) {
+        (Some((base, max)), _) if len <= max => out.push(base | len as u8),
+        (_, [Some(form), _, _]) if len <= 0xff => out.extend_from_slice(&[form, len as u8]),
+        (_, [_, Some(form), _]) if len <= 0xffff => {
+            out.push(form);
+            out.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        (_, [_, _, Some(form)]) if len <= 0xffff_ffff => {
+            out.push(form);
+            out.extend_from_slice(&(len as u32).to_be_bytes());
+        }
+        _ => return Err("MessagePack length over 2^32 - 1".to_string()),
+    }
+    Ok(())
+}
+
+pub fn put_str(out: &mut Vec<u8>, text: &[u8]) -> Result<(), String> {
+    put_len(out, text.len(), Some((0xa0, 31)), [Some(0xd9), Some(0xda), Some(0xdb)])?;
+    out.extend_from_slice(text);
+    Ok(())
+}
+
+pub fn put_bin(out: &mut Vec<u8>, bytes: &[u8]) -> Result<(), String> {
+    put_len(out, bytes.len(), None, [Some(0xc4), Some(0xc5), Some(0xc6)])?;
+    out.extend_from_slice(bytes);
+    Ok(())
+}
+
+pub fn put_ext(out: &mut Vec<u8>, ext_type: i8, data: &[u8]) -> Result<(), String> {
+    match data.len() {
+        1 => out.push(0xd4),
+        2 => out.push(0xd5),
+        4 => out.push(0xd6),
+        8 => out.push(0xd7),
+        16 => out.push(0xd8),
+        len => put_len(out, len, None, [Some(0xc7), Some(0xc8), Some(0xc9)])?,
+    }
+    out.push(ext_type as u8);
+    out.extend_from_slice(data);
+    Ok(())
+}
+
+pub fn put_array_len(out: &mut Vec<u8>, len: usize) -> Result<(), String> {
+    put_len(out, len, Some((0x90, 15)), [None, Some(0xdc), Some(0xdd)])
+}
+
+pub fn put_map_len(out: &mut Vec<u8>, len: usize) -> Result<(), String> {
+    put_len(out, len, Some((0x80, 15)), [None, Some(0xde), Some(0xdf)])
+}
+
+pub enum Value {
+    Nil,
+    Bool(bool),
+    Integer(i128),
+    Float(f64),
+    // Byte ranges of the input
+    Str(Range<usize>),
+    Bin(Range<usize>),
+    Ext(i8, Range<usize>),
+    Array(Vec<Value>),
+    Map(Vec<(Value, Value)>),
+}
+
+fn float(value: f64) -> Result<Value, String> {
+    if !value.is_finite() {
+        return Err("MessagePack float is not finite".to_string());
+    }
+    Ok(Value::Float(value))
+}
+
+struct Decoder<'a> {
+    data: &'a [u8],
+    pos: usize,
+    max_depth: usize,
+}
+
+impl<'a> Decoder<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let end = self.pos.checked_add(len).filter(|end| *end <= self.data.len()).ok_or("truncated MessagePack")?;
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn take_range(&mut self, len: usize) -> Result<Range<usize>, String> {
+        let start = self.pos;
+        self.take(len)?;
+        Ok(start..self.pos)
+    }
+
+    fn uint(&mut self, size: usize) -> Result<u64, String> {
+        let mut bytes = [0u8; 8];
+        bytes[8 - size..].copy_from_slice(self.take(size)?);
+        Ok(u64::from_be_bytes(bytes))
+    }
+
+    fn int(&mut self, size: usize) -> Result<i64, String> {
+        let shift = 64 - size * 8;
+        Ok(((self.uint(size)? << shift) as i64) >> shift)
+    }
+
+    fn str(&mut self, len: usize) -> Result<Value, String> {
+        let range = self.take_range(len)?;
+        std::str::from_utf8(&self.data[range.clone()]).map_err(|_| "MessagePack string is not valid UTF-8".to_string())?;
+        Ok(Value::Str(range))
+    }
+
+    // A count of items at least a byte each, checked against the input left
+    // so a bogus one allocates nothing
+    fn count(&self, len: u64, min_item_len: u64) -> Result<usize, String> {
+        if len.saturating_mul(min_item_len) > (self.data.len() - self.pos) as u64 {
+            return Err("truncated MessagePack".to_string());
+        }
+        Ok(len as usize)
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Value, String> {
+        let marker = self.take(1)?[0];
+        Ok(match marker {
+            0x00..=0x7f => Value::Integer(marker as i128),
+            0xe0..=0xff => Value::Integer(marker as i8 as i128),
+            0x80..=0x8f => self.map((marker & 0x0f) as u64, depth)?,
+            0x90..=0x9f => self.array((marker & 0x0f) as u64, depth)?,
+            0xa0..=0xbf => self.str((marker & 0x1f) as usize)?,
+            0xc0 => Value::Nil,
+            0xc2 => Value::Bool(false),
+            0xc3 => Value::Bool(true),
+            0xc4..=0xc6 => {
+                let len = self.uint(1 << (marker - 0xc4))? as usize;
+                Value::Bin(self.take_range(len)?)
+            }
+            0xc7..=0xc9 => {
+                let len = self.uint(1 << (marker - 0xc7))? as usize;
+                let ext_type = self.take(1)?[0] as i8;
+                Value::Ext(ext_type, self.take_range(len)?)
+            }
+            0xca => float(f32::from_be_bytes(self.take(4)?.try_into().unwrap()) as f64)?,
+            0xcb => float(f64::from_be_bytes(self.take(8)?.try_into().unwrap()))?,
+            0xcc..=0xcf => Value::Integer(self.uint(1 << (marker - 0xcc))? as i128),
+            0xd0..=0xd3 => Value::Integer(self.int(1 << (marker - 0xd0))? as i128),
+            0xd4..=0xd8 => {
+                let ext_type = self.take(1)?[0] as i8;
+                Value::Ext(ext_type, self.take_range(1 << (marker - 0xd4))?)
+            }
+            0xd9..=0xdb => {
+                let len = self.uint(1 << (marker - 0xd9))? as usize;
+                self.str(len)?
+            }
+            0xdc | 0xdd => {
+                let len = self.uint(2 << (marker - 0xdc))?;
+                self.array(len, depth)?
+            }
+            0xde | 0xdf => {
+                let len = self.uint(2 << (marker - 0xde))?;
+                self.map(len, depth)?
+            }
+            _ => return Err(format!("invalid MessagePack marker 0x{:02x}", marker)),
+        })
+    }
+
+    fn array(&mut self, len: u64, depth: usize) -> Result<Value, String> {
+        if depth >= self.max_depth {
+            return Err("MessagePack nested too deeply".to_string());
+        }
+        let len = self.count(len, 1)?;
+        let mut items = Vec::with_capacity(len);
+        for _ in 0..len {
+            items.push(self.value(depth + 1)?);
+        }
+        Ok(Value::Array(items))
+    }
+
+    fn map(&mut self, len: u64, depth: usize) -> Result<Value, String> {
+        if depth >= self.max_depth {
+            return Err("MessagePack nested too deeply".to_string());
+        }
+        let len = self.count(len, 2)?;
+        let mut entries = Vec::with_capacity(len);
+        for _ in 0..len {
+            entries.push((self.value(depth + 1)?, self.value(depth + 1)?));
+        }
+        Ok(Value::Map(entries))
+    }
+}
+
+/// Decode `data`, which must hold exactly one value with arrays and maps
+/// nested at most `max_depth` deep.
+pub fn decode(data: &[u8], max_depth: usize) -> Result<Value, String> {
+    let mut decoder = Decoder { data, pos: 0, max_depth };
+    let value = decoder.value(0)?;
+    if decoder.pos != data.len() {
+        return Err("trailing bytes after MessagePack value".to_string());
+    }
+    Ok(value)
+}
@@ -0,0 +1,223 @@
+// Multihash, CIDv1 and multibase, so snapshot chunks can be referenced the way
+// IPFS-based distribution expects:
+//
+//   multihash  varint hash code | varint digest length | digest
+//   CIDv1      varint 1 | varint codec | multihash
+//   multibase  prefix character | text of the bytes in that base
+//
+// Varints are unsigned LEB128, minimal as varint.rs reads them. Only the hash
+// functions and codecs this node produces are known; anything else is refused
+// rather than passed through unchecked.
+
+use crate::{base16, base64url, varint};
+
+const CID_VERSION: u64 = 1;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Hash {
+    Blake3,
+    Sha2_256,
+}
+
+impl Hash {
+    fn code(self) -> u64 {
+        match self {
+            Hash::Blake3 => 0x1e,
+            Hash::Sha2_256 => 0x12,
+        }
+    }
+
+    fn from_code(code: u64) -> Result<Hash, String> {
+        match code {
+            0x1e => Ok(Hash::Blake3),
+            0x12 => Ok(Hash::Sha2_256),
+            _ => Err(format!("unsupported multihash code 0x{:x}", code)),
+        }
+    }
+
+    // Both are used at their 32-byte default
+    pub fn digest_len(self) -> usize {
+        32
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Codec {
+    Raw,
+    DagPb,
+    DagCbor,
+}
+
+impl Codec {
+    fn code(self) -> u64 {
+        match self {
+            Codec::Raw => 0x55,
+            Codec::DagPb => 0x70,
+            Codec::DagCbor => 0x71,
+        }
+    }
+
+    fn from_code(code: u64) -> Result<Codec, String> {
+        match code {
+            0x55 => Ok(Codec::Raw),
+            0x70 => Ok(Codec::DagPb),
+            0x71 => Ok(Codec::DagCbor),
+            _ => Err(format!("unsupported multicodec 0x{:x}", code)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Base {
+    // Lowercase, unpadded: the default for CIDv1
+    Base32,
+    Base58btc,
+    // Lowercase
+    Base16,
+    // Unpadded
+    Base64url,
+}
+
+impl Base {
+    fn prefix(self) -> u8 {
+        match self {
+            Base::Base32 => b'b',
+            Base::Base58btc => b'z',
+            Base::Base16 => b'f',
+            Base::Base64url => b'u',
+        }
+    }
+}
+
+/// Multihash of `digest`, appended to `out`; None if it isn't `hash`'s length.
+pub fn put_multihash(out: &mut Vec<u8>, hash: Hash, digest: &[u8]) -> Option<()> {
+    if digest.len() != hash.digest_len() {
+        return None;
+    }
+    varint::put_uleb128(out, hash.code());
+    varint::put_uleb128(out, digest.len() as u64);
+    out.extend_from_slice(digest);
+    Some(())
+}
+
+fn read_varint(data: &[u8], pos: &mut usize) -> Result<u64, String> {
+    let (n, len) = varint::read_uleb128(&data[*pos..])?;
+    *pos += len;
+    Ok(n)
+}
+
+// Hash function and digest of the multihash at data[*pos..], the whole rest
+fn read_multihash<'a>(data: &'a [u8], pos: &mut usize) -> Result<(Hash, &'a [u8]), String> {
+    let hash = Hash::from_code(read_varint(data, pos)?)?;
+    let len = read_varint(data, pos)?;
+    if len != hash.digest_len() as u64 {
+        return Err(format!("multihash digest length {} instead of {}", len, hash.digest_len()));
+    }
+    let digest = &data[*pos..];
+    if digest.len() != hash.digest_len() {
+        return Err("multihash length mismatch".to_string());
+    }
+    Ok((hash, digest))
+}
+
+/// Hash function and digest of the multihash `data`.
+pub fn multihash(data: &[u8]) -> Result<(Hash, &[u8]), String> {
+    read_multihash(data, &mut 0)
+}
+
+/// Binary CIDv1 of content hashed to `digest`; None for a wrong digest length.
+pub fn cid(codec: Codec, hash: Hash, digest: &[u8]) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(4 + digest.len());
+    varint::put_uleb128(&mut out, CID_VERSION);
+    varint::put_uleb128(&mut out, codec.code());
+    put_multihash(&mut out, hash, digest)?;
+    Some(out)
+}
+
+/// Codec, hash function and digest of the binary CIDv1 `data`.
+pub fn read_cid(data: &[u8]) -> Result<(Codec, Hash, &[u8]), String> {
+    // A CIDv0 is a bare sha2-256 multihash, starting 0x12 0x20
+    if data.starts_with(&[0x12, 0x20]) {
+        return Err("CIDv0 not supported".to_string());
+    }
+    let mut pos = 0;
+    let version = read_varint(data, &mut pos)?;
+    if version != CID_VERSION {
+        return Err(format!("unsupported CID version {}", version));
+    }
+    let codec = Codec::from_code(read_varint(data, &mut pos)?)?;
+    let (hash, digest) = read_multihash(data, &mut pos)?;
+    Ok((codec, hash, digest))
+}
+
+const BASE32: &[u8; 32] = b"abcdefghijklmnopqrstuvwxyz234567";
+
+fn base32_encode(data: &[u8], out: &mut Vec<u8>) {
+    let mut bits = 0u32;
+    let mut count = 0;
+    for byte in data {
+        bits = bits << 8 | *byte as u32;
+        count += 8;
+        while count >= 5 {
+            count -= 5;
+            out.push(BASE32[(bits >> count & 0x1f) as usize]);
+        }
+    }
+    if count > 0 {
+        out.push(BASE32[(bits << (5 - count) & 0x1f) as usize]);
+    }
+}
+
+// Strict: lowercase, no padding, zero unused bits, so one text per byte string
+fn base32_decode(text: &[u8]) -> Result<Vec<u8>, String> {
+    if matches!(text.len() % 8, 1 | 3 | 6) {
+        return Err("invalid base32 length".to_string());
+    }
+    let mut out = Vec::with_capacity(text.len() * 5 / 8);
+    let mut bits = 0u32;
+    let mut count = 0;
+    for (i, c) in text.iter().enumerate() {
+        let value = BASE32
+            .iter()
+            .position(|digit| digit == c)
+            .ok_or_else(|| format!("invalid base32 character at position {}", i))?;
+        bits = bits << 5 | value as u32;
+        count += 5;
+        if count >= 8 {
+            count -= 8;
+            out.push((bits >> count) as u8);
+        }
+    }
+    if bits & ((1 << count) - 1) != 0 {
+        return Err("non-canonical base32: unused bits set".to_string());
+    }
+    Ok(out)
+}
+
+/// Multibase text of `data` in `base`.
+pub fn multibase_encode(base: Base, data: &[u8]) -> Vec<u8> {
+    let mut out = vec![base.prefix()];
+    match base {
+        Base::Base32 => base32_encode(data, &mut out),
+        Base::Base58btc => out.extend_from_slice(bs58::encode(data).into_string().as_bytes()),
+        Base::Base16 => base16::encode(data, base16::Case::Lower, &mut out),
+        Base::Base64url => base64url::encode(data, false, &mut out),
+    }
+    out
+}
+
+/// Base and bytes of the multibase `text`.
+pub fn multibase_decode(text: &[u8]) -> Result<(Base, Vec<u8>), String> {
+    let (prefix, rest) = text.split_first().ok_or("empty multibase text")?;
+    let base = [Base::Base32, Base::Base58btc, Base::Base16, Base::Base64url]
+        .into_iter()
+        .find(|base| base.prefix() == *prefix)
+        .ok_or_else(|| format!("unsupported multibase prefix {:?}", *prefix as char))?;
+    let data = match base {
+        Base::Base32 => base32_decode(rest)?,
+        Base::Base58btc => bs58::decode(rest).into_vec().map_err(|e| e.to_string())?,
+        Base::Base16 => base16::decode(rest, base16::Case::Lower)?,
+        Base::Base64url => base64url::decode(rest, base64url::Padding::Refused, true)?,
+    };
+    Ok((base, data))
+}
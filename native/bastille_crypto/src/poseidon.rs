@@ -0,0 +1,57 @@
+// Poseidon hash over the BN254 scalar field.
+//
+// Parameter set: the circom x^5 instances shipped by light-poseidon
+// (width = inputs + 1, 8 full rounds, 56-68 partial rounds, domain tag 0),
+// i.e. the same constants as circomlib's `Poseidon(n)` for n in 1..=12, so
+// digests can be recomputed inside circom/snarkjs circuits.
+//
+// Field elements cross the NIF boundary as 32-byte big-endian integers that
+// must be strictly below the BN254 scalar modulus.
+//
+// Sponge mode (variable-length bytes):
+//   1. split the input into 31-byte chunks, each read as a big-endian integer
+//      (always below the modulus);
+//   2. start from state = byte length of the input;
+//   3. absorb 11 chunks at a time, missing ones as zero:
+//      state = Poseidon(12)(state, c1, ..., c11);
+//   4. the digest is the final state (empty input absorbs one all-zero block).
+
+use ark_bn254::Fr;
+use ark_ff::{BigInteger, PrimeField};
+use light_poseidon::{Poseidon, PoseidonBytesHasher, PoseidonError, PoseidonHasher};
+
+pub const MAX_INPUTS: usize = 12;
+const CHUNK_SIZE: usize = 31;
+const RATE: usize = MAX_INPUTS - 1;
+
+/// Hash 1..=12 field elements given as 32-byte big-endian encodings.
+pub fn hash_elements(inputs: &[&[u8]]) -> Result<[u8; 32], PoseidonError> {
+    let mut poseidon = Poseidon::<Fr>::new_circom(inputs.len())?;
+    poseidon.hash_bytes_be(inputs)
+}
+
+/// Hash an arbitrary byte string with the length-bound sponge described above.
+pub fn sponge(data: &[u8]) -> Result<[u8; 32], PoseidonError> {
+    let mut poseidon = Poseidon::<Fr>::new_circom(MAX_INPUTS)?;
+    let elements: Vec<Fr> = data.chunks(CHUNK_SIZE).map(Fr::from_be_bytes_mod_order).collect();
+
+    let mut state = Fr::from(data.len() as u64);
+    let mut absorb = |block: &[Fr]| -> Result<(), PoseidonError> {
+        let mut inputs = [Fr::from(0u64); MAX_INPUTS];
+        inputs[0] = state;
+        inputs[1..=block.len()].copy_from_slice(block);
+        state = poseidon.hash(&inputs)?;
+        Ok(())
+    };
+
+    if elements.is_empty() {
+        absorb(&[])?;
+    }
+    for block in elements.chunks(RATE) {
+        absorb(block)?;
+    }
+
+    let mut digest = [0u8; 32];
+    digest.copy_from_slice(&state.into_bigint().to_bytes_be());
+    Ok(digest)
+}
@@ -0,0 +1,40 @@
+// BLAKE3 proof of work, as Mining.calculate_block_hash/1 computes it: the
+// hash of the block bytes followed by the nonce as a little-endian u64 must
+// not exceed the 32-byte big-endian target.
+//
+// The search splits the nonce range into chunks spread over the thread pool,
+// each hashing from a copy of the hasher state after the block bytes, and
+// returns the lowest winning nonce, so the result doesn't depend on the
+// number of threads.
+
+use crate::target::Target;
+use rayon::prelude::*;
+
+// Nonces per unit of work, small enough for the search to stop soon after a
+// win, large enough to keep scheduling out of the profile
+const CHUNK: u64 = 4096;
+
+fn meets(hasher: &blake3::Hasher, nonce: u64, target: &Target) -> bool {
+    let mut hasher = hasher.clone();
+    hasher.update(&nonce.to_le_bytes());
+    hasher.finalize().as_bytes() <= target
+}
+
+pub fn verify(header: &[u8], nonce: u64, target: &Target) -> bool {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(header);
+    meets(&hasher, nonce, target)
+}
+
+/// The lowest nonce from `start`, within `iterations` of it, whose hash meets
+/// `target`. The range is cut short at u64::MAX rather than wrapping.
+pub fn mine(header: &[u8], target: &Target, start: u64, iterations: u64) -> Option<u64> {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(header);
+    let end = start.saturating_add(iterations);
+    let chunks = (end - start).div_ceil(CHUNK);
+    (0..chunks).into_par_iter().find_map_first(|chunk| {
+        let from = start + chunk * CHUNK;
+        (from..from.saturating_add(CHUNK).min(end)).find(|nonce| meets(&hasher, *nonce, target))
+    })
+}
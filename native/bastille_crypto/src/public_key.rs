@@ -0,0 +1,90 @@
+// Public keys parsed once and held behind a resource handle, so hot
+// validator-set keys aren't re-validated on every signature check.
+
+use crate::keygen::Algorithm;
+use pqcrypto_dilithium::dilithium2;
+use pqcrypto_falcon::falcon512;
+use pqcrypto_sphincsplus::sphincsshake128fsimple as sphincsplus_shake_128f;
+use pqcrypto_traits::sign::{DetachedSignature, PublicKey, VerificationError};
+
+// Boxed: the key types are fixed-size arrays of very different sizes
+pub enum ParsedPublicKey {
+    Dilithium2(Box<dilithium2::PublicKey>),
+    Falcon512(Box<falcon512::PublicKey>),
+    SphincsPlus(Box<sphincsplus_shake_128f::PublicKey>),
+}
+
+impl ParsedPublicKey {
+    /// None if `bytes` is not a public key of `algorithm`.
+    pub fn parse(algorithm: Algorithm, bytes: &[u8]) -> Option<Self> {
+        match algorithm {
+            Algorithm::Dilithium2 => dilithium2::PublicKey::from_bytes(bytes)
+                .ok()
+                .map(|pk| ParsedPublicKey::Dilithium2(Box::new(pk))),
+            Algorithm::Falcon512 => falcon512::PublicKey::from_bytes(bytes)
+                .ok()
+                .map(|pk| ParsedPublicKey::Falcon512(Box::new(pk))),
+            Algorithm::SphincsPlus => sphincsplus_shake_128f::PublicKey::from_bytes(bytes)
+                .ok()
+                .map(|pk| ParsedPublicKey::SphincsPlus(Box::new(pk))),
+        }
+    }
+
+    pub fn algorithm(&self) -> Algorithm {
+        match self {
+            ParsedPublicKey::Dilithium2(_) => Algorithm::Dilithium2,
+            ParsedPublicKey::Falcon512(_) => Algorithm::Falcon512,
+            ParsedPublicKey::SphincsPlus(_) => Algorithm::SphincsPlus,
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            ParsedPublicKey::Dilithium2(pk) => pk.as_bytes(),
+            ParsedPublicKey::Falcon512(pk) => pk.as_bytes(),
+            ParsedPublicKey::SphincsPlus(pk) => pk.as_bytes(),
+        }
+    }
+
+    pub fn verify(&self, signature: &[u8], message: &[u8]) -> bool {
+        match self {
+            ParsedPublicKey::Dilithium2(pk) => dilithium2::DetachedSignature::from_bytes(signature)
+                .is_ok_and(|sig| dilithium2::verify_detached_signature(&sig, message, pk).is_ok()),
+            ParsedPublicKey::Falcon512(pk) => falcon512::DetachedSignature::from_bytes(signature)
+                .is_ok_and(|sig| falcon512::verify_detached_signature(&sig, message, pk).is_ok()),
+            ParsedPublicKey::SphincsPlus(pk) => sphincsplus_shake_128f::DetachedSignature::from_bytes(signature)
+                .is_ok_and(|sig| sphincsplus_shake_128f::verify_detached_signature(&sig, message, pk).is_ok()),
+        }
+    }
+}
+
+fn verify_with<P: PublicKey, S: DetachedSignature>(
+    public_key: &[u8],
+    signature: &[u8],
+    message: &[u8],
+    verify: fn(&S, &[u8], &P) -> Result<(), VerificationError>,
+) -> bool {
+    match (P::from_bytes(public_key), S::from_bytes(signature)) {
+        (Ok(pk), Ok(sig)) => verify(&sig, message, &pk).is_ok(),
+        _ => false,
+    }
+}
+
+/// Verify against a public key given as bytes, parsed on the stack rather than
+/// boxed, since it is used once.
+pub fn verify_bytes(algorithm: Algorithm, public_key: &[u8], signature: &[u8], message: &[u8]) -> bool {
+    match algorithm {
+        Algorithm::Dilithium2 => verify_with(public_key, signature, message, dilithium2::verify_detached_signature),
+        Algorithm::Falcon512 => verify_with(public_key, signature, message, falcon512::verify_detached_signature),
+        Algorithm::SphincsPlus => {
+            verify_with(public_key, signature, message, sphincsplus_shake_128f::verify_detached_signature)
+        }
+    }
+}
+
+pub struct PublicKeyHandle {
+    pub key: ParsedPublicKey,
+}
+
+#[rustler::resource_impl]
+impl rustler::Resource for PublicKeyHandle {}
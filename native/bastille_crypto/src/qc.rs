@@ -0,0 +1,210 @@
+// Quorum certificates: the votes (vote.rs) of more than two thirds of a
+// validator set's weight for one block at a height and round.
+//
+//   version u8 | height u64 | round u32 | block id 32 | set root 32
+//     | validator count u16 | bitfield | signatures
+//
+// The set root is that of finality.rs, so a certificate names the set that
+// signed it. The bitfield and signatures are laid out as in attestation.rs.
+//
+// A Collector gathers the votes of one (height, round, block id) as they
+// arrive: it parses the set's keys once, checks each signature on the pool,
+// keeps the first valid vote of each validator and builds the certificate
+// once the quorum is reached.
+
+use crate::finality::{self, Validator};
+use crate::public_key::ParsedPublicKey;
+use crate::vote::{self, BLOCK_ID_LEN};
+use crate::{threads, varint};
+use rayon::prelude::*;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+pub const VERSION: u8 = 1;
+pub const MAX_SIGNATURE_LEN: usize = crate::tx::MAX_SIGNATURE_LEN;
+
+pub struct Certificate {
+    pub height: u64,
+    pub round: u32,
+    pub block_id: [u8; BLOCK_ID_LEN],
+    // Indices of the validators that voted, ascending
+    pub signers: Vec<usize>,
+}
+
+pub struct Collector {
+    height: u64,
+    round: u32,
+    block_id: [u8; BLOCK_ID_LEN],
+    message: [u8; 32],
+    set_root: [u8; finality::ROOT_LEN],
+    // (key, weight) by validator index
+    keys: Vec<(ParsedPublicKey, u64)>,
+    total: u128,
+    // Valid signatures by validator index
+    votes: Mutex<BTreeMap<usize, Vec<u8>>>,
+}
+
+#[rustler::resource_impl]
+impl rustler::Resource for Collector {}
+
+fn encode(
+    height: u64,
+    round: u32,
+    block_id: &[u8; BLOCK_ID_LEN],
+    set_root: &[u8; finality::ROOT_LEN],
+    validator_count: usize,
+    votes: &BTreeMap<usize, Vec<u8>>,
+) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.push(VERSION);
+    out.extend_from_slice(&height.to_be_bytes());
+    out.extend_from_slice(&round.to_be_bytes());
+    out.extend_from_slice(block_id);
+    out.extend_from_slice(set_root);
+    out.extend_from_slice(&(validator_count as u16).to_be_bytes());
+    let mut bitfield = vec![0u8; validator_count.div_ceil(8)];
+    for index in votes.keys() {
+        bitfield[index / 8] |= 1 << (index % 8);
+    }
+    out.extend_from_slice(&bitfield);
+    for signature in votes.values() {
+        varint::put_uleb128(&mut out, signature.len() as u64);
+        out.extend_from_slice(signature);
+    }
+    out
+}
+
+impl Collector {
+    pub fn new(height: u64, round: u32, block_id: [u8; BLOCK_ID_LEN], validators: &[Validator]) -> Result<Self, String> {
+        let set_root = finality::validator_set_root(validators)?;
+        let keys = validators
+            .iter()
+            .enumerate()
+            .map(|(index, validator)| {
+                let key = ParsedPublicKey::parse(validator.algorithm, &validator.public_key)
+                    .ok_or(format!("invalid public key for validator {}", index))?;
+                Ok((key, validator.weight))
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        let total = validators.iter().map(|validator| validator.weight as u128).sum();
+        let message = vote::message(height, round, &block_id);
+        Ok(Collector { height, round, block_id, message, set_root, keys, total, votes: Mutex::new(BTreeMap::new()) })
+    }
+
+    fn lock(&self) -> Result<std::sync::MutexGuard<'_, BTreeMap<usize, Vec<u8>>>, String> {
+        self.votes.lock().map_err(|_| "vote collector lock poisoned".to_string())
+    }
+
+    fn weight(&self, votes: &BTreeMap<usize, Vec<u8>>) -> u128 {
+        votes.keys().map(|index| self.keys[*index].1 as u128).sum()
+    }
+
+    /// Add (validator index, signature) votes, checked in parallel. Returns
+    /// the weight voted so far, whether it is a quorum, and the indices of
+    /// the votes rejected: out of range, already counted or invalid.
+    pub fn add(&self, votes: &[(usize, &[u8])]) -> Result<(u128, bool, Vec<usize>), String> {
+        let mut counted = self.lock()?;
+        let mut rejected = Vec::new();
+        let mut fresh = BTreeMap::new();
+        for (index, signature) in votes {
+            if *index >= self.keys.len() || counted.contains_key(index) || fresh.contains_key(index) {
+                rejected.push(*index);
+            } else {
+                fresh.insert(*index, *signature);
+            }
+        }
+        let valid: Vec<(usize, bool)> = threads::install(|| {
+            fresh
+                .par_iter()
+                .map(|(index, signature)| (*index, self.keys[*index].0.verify(signature, &self.message)))
+                .collect()
+        });
+        for (index, ok) in valid {
+            if ok {
+                counted.insert(index, fresh[&index].to_vec());
+            } else {
+                rejected.push(index);
+            }
+        }
+        let weight = self.weight(&counted);
+        Ok((weight, finality::is_quorum(weight, self.total), rejected))
+    }
+
+    /// The certificate of the votes counted so far, once they are a quorum.
+    pub fn certificate(&self) -> Result<Vec<u8>, String> {
+        let votes = self.lock()?;
+        let weight = self.weight(&votes);
+        if !finality::is_quorum(weight, self.total) {
+            return Err(format!("votes from {} of {} weight, not over 2/3", weight, self.total));
+        }
+        Ok(encode(self.height, self.round, &self.block_id, &self.set_root, self.keys.len(), &votes))
+    }
+}
+
+fn take<'a>(data: &'a [u8], pos: &mut usize, len: usize) -> Result<&'a [u8], String> {
+    let bytes = data.get(*pos..*pos + len).ok_or("truncated quorum certificate")?;
+    *pos += len;
+    Ok(bytes)
+}
+
+/// The vote `certificate` proves, if it is from the set `validators` and
+/// carries valid votes from more than 2/3 of its weight.
+pub fn verify(certificate: &[u8], validators: &[Validator]) -> Result<Certificate, String> {
+    let mut pos = 0;
+    let version = take(certificate, &mut pos, 1)?[0];
+    if version != VERSION {
+        return Err(format!("unsupported quorum certificate version {}", version));
+    }
+    let height = u64::from_be_bytes(take(certificate, &mut pos, 8)?.try_into().unwrap_or([0; 8]));
+    let round = u32::from_be_bytes(take(certificate, &mut pos, 4)?.try_into().unwrap_or([0; 4]));
+    let block_id: [u8; BLOCK_ID_LEN] = take(certificate, &mut pos, BLOCK_ID_LEN)?.try_into().unwrap_or([0; BLOCK_ID_LEN]);
+    let set_root = take(certificate, &mut pos, finality::ROOT_LEN)?;
+    if finality::validator_set_root(validators)?[..] != *set_root {
+        return Err("certificate from another validator set".to_string());
+    }
+    let count = take(certificate, &mut pos, 2)?;
+    let count = u16::from_be_bytes([count[0], count[1]]) as usize;
+    if count != validators.len() {
+        return Err(format!("certificate for {} validators, set of {}", count, validators.len()));
+    }
+
+    let bitfield = take(certificate, &mut pos, count.div_ceil(8))?;
+    if !count.is_multiple_of(8) && bitfield[bitfield.len() - 1] >> (count % 8) != 0 {
+        return Err("bits set past the validator set".to_string());
+    }
+    let mut signatures = Vec::new();
+    for index in (0..count).filter(|index| bitfield[index / 8] & (1 << (index % 8)) != 0) {
+        let (len, size) = varint::read_uleb128(&certificate[pos..])?;
+        pos += size;
+        if len == 0 || len > MAX_SIGNATURE_LEN as u64 {
+            return Err(format!("signature of validator {} has length {}", index, len));
+        }
+        signatures.push((index, take(certificate, &mut pos, len as usize)?));
+    }
+    if pos != certificate.len() {
+        return Err("trailing bytes after quorum certificate".to_string());
+    }
+
+    let total: u128 = validators.iter().map(|validator| validator.weight as u128).sum();
+    let signed: u128 = signatures.iter().map(|(index, _)| validators[*index].weight as u128).sum();
+    if !finality::is_quorum(signed, total) {
+        return Err(format!("votes from {} of {} weight, not over 2/3", signed, total));
+    }
+    let message = vote::message(height, round, &block_id);
+    let failed: Vec<String> = threads::install(|| {
+        signatures
+            .par_iter()
+            .filter(|(index, signature)| {
+                let validator = &validators[*index];
+                !crate::public_key::verify_bytes(validator.algorithm, &validator.public_key, signature, &message)
+            })
+            .map(|(index, _)| index.to_string())
+            .collect()
+    });
+    if !failed.is_empty() {
+        return Err(format!("invalid votes from validators {}", failed.join(", ")));
+    }
+
+    let signers = signatures.iter().map(|(index, _)| *index).collect();
+    Ok(Certificate { height, round, block_id, signers })
+}
@@ -0,0 +1,70 @@
+// RandomX, the CPU-oriented proof of work of the CPU-mining profile, as an
+// alternative to the Blake3 one in pow.rs (same header || nonce input, same
+// target comparison). A key, rotated by the chain, seeds a 256 MiB cache,
+// enough to verify ("light" mode, a few milliseconds per hash); miners expand
+// it into the 2 GiB dataset ("fast" mode), which takes about a minute.
+//
+// A VM isn't safe to share between threads, so each handle keeps a pool of
+// them: calls take one, or create one when all are busy, and put it back.
+
+use crate::target::Target;
+use randomx_rs::{RandomXCache, RandomXDataset, RandomXFlag, RandomXVM};
+use std::sync::Mutex;
+
+pub struct RandomX {
+    flags: RandomXFlag,
+    cache: RandomXCache,
+    dataset: Option<RandomXDataset>,
+    vms: Mutex<Vec<RandomXVM>>,
+}
+
+// SAFETY: the cache and dataset are only written while being built, before
+// the handle exists, and RandomX allows any number of VMs to read them from
+// any thread; each VM is used by one thread at a time, through the pool.
+unsafe impl Send for RandomX {}
+unsafe impl Sync for RandomX {}
+
+#[rustler::resource_impl]
+impl rustler::Resource for RandomX {}
+
+fn randomx_error(e: randomx_rs::RandomXError) -> String {
+    format!("RandomX: {}", e)
+}
+
+impl RandomX {
+    /// Light mode: the cache for `key`, for verifying.
+    pub fn new(key: &[u8]) -> Result<Self, String> {
+        let flags = RandomXFlag::get_recommended_flags();
+        let cache = RandomXCache::new(flags, key).map_err(randomx_error)?;
+        Ok(RandomX { flags, cache, dataset: None, vms: Mutex::new(Vec::new()) })
+    }
+
+    /// Fast mode: the dataset expanded from this handle's cache, for mining.
+    pub fn with_dataset(&self) -> Result<Self, String> {
+        let flags = self.flags | RandomXFlag::FLAG_FULL_MEM;
+        let dataset = RandomXDataset::new(flags, self.cache.clone(), 0).map_err(randomx_error)?;
+        Ok(RandomX { flags, cache: self.cache.clone(), dataset: Some(dataset), vms: Mutex::new(Vec::new()) })
+    }
+
+    pub fn hash(&self, input: &[u8]) -> Result<[u8; 32], String> {
+        let pooled = self.vms.lock().map_err(|_| "RandomX VM pool lock poisoned")?.pop();
+        let vm = match pooled {
+            Some(vm) => vm,
+            None => RandomXVM::new(self.flags, Some(self.cache.clone()), self.dataset.clone()).map_err(randomx_error)?,
+        };
+        let hash = vm.calculate_hash(input).map_err(randomx_error);
+        if let Ok(mut vms) = self.vms.lock() {
+            vms.push(vm);
+        }
+        hash?.try_into().map_err(|_| "RandomX hash not 32 bytes".to_string())
+    }
+
+    /// Whether `nonce` solves `header` for `target`, as pow::verify with
+    /// RandomX in place of Blake3.
+    pub fn verify(&self, header: &[u8], nonce: u64, target: &Target) -> Result<bool, String> {
+        let mut input = Vec::with_capacity(header.len() + 8);
+        input.extend_from_slice(header);
+        input.extend_from_slice(&nonce.to_le_bytes());
+        Ok(self.hash(&input)? <= *target)
+    }
+}
@@ -0,0 +1,72 @@
+// Signing delegated to an external signer daemon over a Unix socket, so hot
+// keys can live in an HSM (or any process fronting one) instead of NIF memory.
+//
+//   request  = version u8 | algorithm id u8 | key_id_len u16 | key_id
+//              | message_len u32 | message
+//   response = status u8 (0 = ok) | len u32 | signature, or UTF-8 error text
+//
+// Integers are big-endian, one request per connection. PKCS#11 has no
+// standard mechanisms for Dilithium, Falcon or SPHINCS+ in the token
+// versions deployed today, so a vendor module is expected to sit behind the
+// daemon rather than being loaded into the VM.
+
+use crate::keygen::Algorithm;
+use std::io::{Read, Write};
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::time::Duration;
+
+const PROTOCOL_VERSION: u8 = 1;
+const STATUS_OK: u8 = 0;
+const IO_TIMEOUT: Duration = Duration::from_secs(10);
+// Larger than any supported signature (SPHINCS+-SHAKE-128f is 17088 bytes)
+const MAX_RESPONSE_LEN: usize = 64 * 1024;
+pub const MAX_KEY_ID_LEN: usize = u16::MAX as usize;
+
+pub struct RemoteKeyHandle {
+    pub algorithm: Algorithm,
+    socket_path: PathBuf,
+    key_id: Vec<u8>,
+}
+
+#[rustler::resource_impl]
+impl rustler::Resource for RemoteKeyHandle {}
+
+impl RemoteKeyHandle {
+    pub fn new(socket_path: PathBuf, algorithm: Algorithm, key_id: Vec<u8>) -> Self {
+        RemoteKeyHandle { algorithm, socket_path, key_id }
+    }
+
+    /// Ask the daemon for a detached signature over `message`.
+    pub fn sign(&self, message: &[u8]) -> Result<Vec<u8>, String> {
+        let message_len = u32::try_from(message.len()).map_err(|_| "message too large".to_string())?;
+
+        let mut stream = UnixStream::connect(&self.socket_path)
+            .map_err(|e| format!("cannot reach signer at {}: {}", self.socket_path.display(), e))?;
+        stream.set_read_timeout(Some(IO_TIMEOUT)).map_err(|e| e.to_string())?;
+        stream.set_write_timeout(Some(IO_TIMEOUT)).map_err(|e| e.to_string())?;
+
+        let mut request = Vec::with_capacity(8 + self.key_id.len() + message.len());
+        request.push(PROTOCOL_VERSION);
+        request.push(self.algorithm.id());
+        request.extend_from_slice(&(self.key_id.len() as u16).to_be_bytes());
+        request.extend_from_slice(&self.key_id);
+        request.extend_from_slice(&message_len.to_be_bytes());
+        request.extend_from_slice(message);
+        stream.write_all(&request).map_err(|e| format!("signer write failed: {}", e))?;
+
+        let mut header = [0u8; 5];
+        stream.read_exact(&mut header).map_err(|e| format!("signer read failed: {}", e))?;
+        let len = u32::from_be_bytes([header[1], header[2], header[3], header[4]]) as usize;
+        if len > MAX_RESPONSE_LEN {
+            return Err("signer response too large".to_string());
+        }
+        let mut body = vec![0u8; len];
+        stream.read_exact(&mut body).map_err(|e| format!("signer read failed: {}", e))?;
+
+        if header[0] != STATUS_OK {
+            return Err(format!("signer refused: {}", String::from_utf8_lossy(&body)));
+        }
+        Ok(body)
+    }
+}
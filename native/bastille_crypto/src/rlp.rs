@@ -0,0 +1,125 @@
+// Recursive Length Prefix encoding, for the Merkle Patricia Trie and for
+// headers, receipts and proofs from EVM chains: byte strings and lists of
+// already-encoded items. Decoding rejects non-canonical forms, so one value has
+// exactly one accepted encoding.
+
+// Deepest list nesting decode_tree() may be allowed, bounding its recursion;
+// EVM data nests a few levels at most
+pub const MAX_DEPTH: usize = 64;
+
+pub enum Item<'a> {
+    Bytes(&'a [u8]),
+    // `raw` is the whole encoding, prefix included
+    List { raw: &'a [u8], payload: &'a [u8] },
+}
+
+/// An item with its lists decoded all the way down.
+pub enum Tree<'a> {
+    Bytes(&'a [u8]),
+    List(Vec<Tree<'a>>),
+}
+
+fn length_prefix(offset: u8, len: usize) -> Vec<u8> {
+    if len < 56 {
+        return vec![offset + len as u8];
+    }
+    let len_bytes = len.to_be_bytes();
+    let skip = len_bytes.iter().take_while(|byte| **byte == 0).count();
+    let mut prefix = vec![offset + 55 + (len_bytes.len() - skip) as u8];
+    prefix.extend_from_slice(&len_bytes[skip..]);
+    prefix
+}
+
+pub fn encode_bytes(bytes: &[u8]) -> Vec<u8> {
+    if bytes.len() == 1 && bytes[0] < 0x80 {
+        return bytes.to_vec();
+    }
+    let mut encoded = length_prefix(0x80, bytes.len());
+    encoded.extend_from_slice(bytes);
+    encoded
+}
+
+pub fn encode_list(items: &[Vec<u8>]) -> Vec<u8> {
+    let payload_len = items.iter().map(Vec::len).sum();
+    let mut encoded = length_prefix(0xc0, payload_len);
+    encoded.reserve(payload_len);
+    for item in items {
+        encoded.extend_from_slice(item);
+    }
+    encoded
+}
+
+// (payload offset, payload length) of a long-form prefix
+fn long_length(data: &[u8], len_of_len: usize) -> Result<(usize, usize), String> {
+    let len_bytes = data.get(1..1 + len_of_len).ok_or("truncated RLP length")?;
+    if len_of_len > 8 || len_bytes[0] == 0 {
+        return Err("non-canonical RLP length".to_string());
+    }
+    let len = len_bytes.iter().fold(0usize, |len, byte| (len << 8) | *byte as usize);
+    if len < 56 {
+        return Err("non-canonical RLP length".to_string());
+    }
+    Ok((1 + len_of_len, len))
+}
+
+/// Decode the item at the start of `data`, returning it and its encoded length.
+pub fn decode(data: &[u8]) -> Result<(Item<'_>, usize), String> {
+    let first = *data.first().ok_or("empty RLP input")?;
+    let (offset, len, is_list) = match first {
+        0x00..=0x7f => return Ok((Item::Bytes(&data[..1]), 1)),
+        0x80..=0xb7 => (1, (first - 0x80) as usize, false),
+        0xb8..=0xbf => {
+            let (offset, len) = long_length(data, (first - 0xb7) as usize)?;
+            (offset, len, false)
+        }
+        0xc0..=0xf7 => (1, (first - 0xc0) as usize, true),
+        0xf8..=0xff => {
+            let (offset, len) = long_length(data, (first - 0xf7) as usize)?;
+            (offset, len, true)
+        }
+    };
+    let end = offset.checked_add(len).ok_or("RLP length overflow")?;
+    let payload = data.get(offset..end).ok_or("truncated RLP item")?;
+    if is_list {
+        Ok((Item::List { raw: &data[..end], payload }, end))
+    } else {
+        if len == 1 && payload[0] < 0x80 {
+            return Err("non-canonical RLP byte".to_string());
+        }
+        Ok((Item::Bytes(payload), end))
+    }
+}
+
+/// Items of a list payload.
+pub fn decode_list(mut payload: &[u8]) -> Result<Vec<Item<'_>>, String> {
+    let mut items = Vec::new();
+    while !payload.is_empty() {
+        let (item, len) = decode(payload)?;
+        items.push(item);
+        payload = &payload[len..];
+    }
+    Ok(items)
+}
+
+/// Decode `data`, which must hold exactly one item, its lists nested at most
+/// `max_depth` deep.
+pub fn decode_tree(data: &[u8], max_depth: usize) -> Result<Tree<'_>, String> {
+    let (item, len) = decode(data)?;
+    if len != data.len() {
+        return Err("trailing bytes after RLP item".to_string());
+    }
+    item_tree(item, max_depth)
+}
+
+fn item_tree(item: Item<'_>, depth_left: usize) -> Result<Tree<'_>, String> {
+    match item {
+        Item::Bytes(bytes) => Ok(Tree::Bytes(bytes)),
+        Item::List { .. } if depth_left == 0 => Err("RLP lists nested too deeply".to_string()),
+        Item::List { payload, .. } => Ok(Tree::List(
+            decode_list(payload)?
+                .into_iter()
+                .map(|item| item_tree(item, depth_left - 1))
+                .collect::<Result<_, _>>()?,
+        )),
+    }
+}
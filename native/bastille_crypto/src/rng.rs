@@ -0,0 +1,104 @@
+// Randomness from the OS CSPRNG, gated by a start-up health check.
+//
+// The self-test draws 20,000 bits and applies the FIPS 140-2 statistical
+// tests (monobit, poker, runs, long run), plus a check that consecutive draws
+// differ. They can't prove the source is good, only catch a broken one
+// (stuck output, a failing getrandom, a badly seeded VM), in which case
+// `fill` refuses to hand out bytes until a later self-test passes.
+
+use rand::rngs::OsRng;
+use rand::RngCore;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+pub const MAX_REQUEST: usize = 1024 * 1024;
+
+const SAMPLE_BYTES: usize = 20_000 / 8;
+
+static HEALTHY: AtomicBool = AtomicBool::new(false);
+
+/// Fill `buf` from the OS CSPRNG. Fails if the last self-test failed.
+pub fn fill(buf: &mut [u8]) -> Result<(), String> {
+    if !HEALTHY.load(Ordering::Acquire) {
+        return Err("RNG self-test has not passed".to_string());
+    }
+    OsRng.try_fill_bytes(buf).map_err(|e| format!("OS RNG failed: {}", e))
+}
+
+/// Run the health checks and record the outcome for `fill`.
+pub fn selftest() -> Result<(), String> {
+    // A good source fails the statistical tests about 0.5% of the time, so one
+    // retry keeps spurious failures negligible while a broken source still fails
+    let result = run_checks().or_else(|_| run_checks());
+    HEALTHY.store(result.is_ok(), Ordering::Release);
+    result
+}
+
+fn run_checks() -> Result<(), String> {
+    let mut first = [0u8; 32];
+    let mut second = [0u8; 32];
+    OsRng.try_fill_bytes(&mut first).map_err(|e| format!("OS RNG failed: {}", e))?;
+    OsRng.try_fill_bytes(&mut second).map_err(|e| format!("OS RNG failed: {}", e))?;
+    if first == second {
+        return Err("repeated output".to_string());
+    }
+
+    let mut sample = vec![0u8; SAMPLE_BYTES];
+    OsRng.try_fill_bytes(&mut sample).map_err(|e| format!("OS RNG failed: {}", e))?;
+    monobit(&sample)?;
+    poker(&sample)?;
+    runs(&sample)
+}
+
+fn monobit(sample: &[u8]) -> Result<(), String> {
+    let ones: u32 = sample.iter().map(|byte| byte.count_ones()).sum();
+    if !(9_726..=10_274).contains(&ones) {
+        return Err(format!("monobit test failed ({} ones)", ones));
+    }
+    Ok(())
+}
+
+fn poker(sample: &[u8]) -> Result<(), String> {
+    let mut counts = [0u64; 16];
+    for byte in sample {
+        counts[(byte >> 4) as usize] += 1;
+        counts[(byte & 0x0f) as usize] += 1;
+    }
+    // X = 16/5000 * sum(f(i)^2) - 5000 must lie in (2.16, 46.17); scaled by 5000
+    let sum: u64 = counts.iter().map(|count| count * count).sum();
+    let x_times_5000 = 16 * sum as i64 - 5_000 * 5_000;
+    if x_times_5000 <= 10_800 || x_times_5000 >= 230_850 {
+        return Err("poker test failed".to_string());
+    }
+    Ok(())
+}
+
+fn runs(sample: &[u8]) -> Result<(), String> {
+    // Accepted counts of runs of length 1..=6 (6 meaning 6 or more), per bit value
+    const BOUNDS: [(u32, u32); 6] = [(2_343, 2_657), (1_135, 1_365), (542, 708), (251, 373), (111, 201), (111, 201)];
+
+    let mut counts = [[0u32; 6]; 2];
+    let mut current = sample[0] >> 7;
+    let mut length = 0usize;
+    let bits = sample.iter().flat_map(|byte| (0..8).rev().map(move |shift| (byte >> shift) & 1));
+    for bit in bits.chain(std::iter::once(2)) {
+        if bit == current {
+            length += 1;
+            continue;
+        }
+        if length >= 26 {
+            return Err("long run test failed".to_string());
+        }
+        counts[current as usize][length.min(6) - 1] += 1;
+        current = bit;
+        length = 1;
+    }
+
+    for bit_counts in counts {
+        for (count, (low, high)) in bit_counts.into_iter().zip(BOUNDS) {
+            if !(low..=high).contains(&count) {
+                return Err("runs test failed".to_string());
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,101 @@
+// RocksDB backend of storage.rs. Each column is a column family, so they are
+// compacted and tuned separately but share one write-ahead log, which makes a
+// write batch spanning several of them atomic.
+
+use crate::storage::{Column, Entry, Op, Scan};
+use rocksdb::{ColumnFamily, ColumnFamilyDescriptor, DBCompressionType, Options, ReadOptions, WriteBatch, DB};
+use std::path::Path;
+
+pub struct RocksStore {
+    db: DB,
+}
+
+fn db_error(e: rocksdb::Error) -> String {
+    e.into_string()
+}
+
+impl RocksStore {
+    pub fn open(dir: &Path) -> Result<Self, String> {
+        let mut opts = Options::default();
+        opts.create_if_missing(true);
+        opts.create_missing_column_families(true);
+        let columns = Column::ALL.map(|column| {
+            let mut cf_opts = Options::default();
+            cf_opts.set_compression_type(DBCompressionType::Lz4);
+            ColumnFamilyDescriptor::new(column.name(), cf_opts)
+        });
+        let db = DB::open_cf_descriptors(&opts, dir, columns).map_err(db_error)?;
+        Ok(RocksStore { db })
+    }
+
+    fn cf(&self, column: Column) -> Result<&ColumnFamily, String> {
+        self.db.cf_handle(column.name()).ok_or_else(|| format!("missing column family {}", column.name()))
+    }
+
+    pub fn close(self) -> Result<(), String> {
+        self.db.flush().map_err(db_error)
+    }
+
+    pub fn get<R>(&self, column: Column, key: &[u8], f: impl FnOnce(Option<&[u8]>) -> R) -> Result<R, String> {
+        let value = self.db.get_pinned_cf(self.cf(column)?, key).map_err(db_error)?;
+        Ok(f(value.as_deref()))
+    }
+
+    pub fn write(&self, ops: &[Op]) -> Result<(), String> {
+        let mut batch = WriteBatch::default();
+        for op in ops {
+            match op {
+                Op::Put(column, key, value) => batch.put_cf(self.cf(*column)?, *key, *value),
+                Op::Delete(column, key) => batch.delete_cf(self.cf(*column)?, *key),
+            }
+        }
+        self.db.write(batch).map_err(db_error)
+    }
+
+    pub fn compact(&self, column: Column) -> Result<(), String> {
+        self.db.compact_range_cf(self.cf(column)?, None::<&[u8]>, None::<&[u8]>);
+        Ok(())
+    }
+
+    pub fn scan(&self, column: Column, scan: &Scan, count: usize) -> Result<Vec<Entry>, String> {
+        let mut readopts = ReadOptions::default();
+        if let Some(from) = scan.from {
+            readopts.set_iterate_lower_bound(from);
+        }
+        if let Some(to) = scan.to {
+            readopts.set_iterate_upper_bound(to);
+        }
+        let mut iter = self.db.raw_iterator_cf_opt(self.cf(column)?, readopts);
+        match (scan.after.or(scan.at), scan.reverse) {
+            (Some(key), false) => {
+                iter.seek(key);
+                if scan.after.is_some() && iter.key() == Some(key) {
+                    iter.next();
+                }
+            }
+            (Some(key), true) => {
+                iter.seek_for_prev(key);
+                if scan.after.is_some() && iter.key() == Some(key) {
+                    iter.prev();
+                }
+            }
+            (None, false) => iter.seek_to_first(),
+            (None, true) => iter.seek_to_last(),
+        }
+
+        let mut entries = Vec::new();
+        while entries.len() < count {
+            let Some((key, value)) = iter.item() else {
+                iter.status().map_err(db_error)?;
+                break;
+            };
+            entries.push((key.to_vec(), value.to_vec()));
+            if scan.reverse {
+                iter.prev();
+            } else {
+                iter.next();
+            }
+        }
+        Ok(entries)
+    }
+}
@@ -0,0 +1,119 @@
+// Key rotation certificates: the outgoing key signs its successor.
+//
+//   statement   = magic "BROT" | version u8 | algorithm id u8
+//                 | old_pk_len u32 | old_pk | new_pk_len u32 | new_pk
+//                 | effective_height u64 | reason_len u16 | reason (UTF-8)
+//   certificate = statement | signature (by the old key over the statement)
+//
+// Integers are big-endian. The encoding is canonical: decoding rejects
+// anything that would not re-encode to the same bytes.
+
+use crate::keygen::Algorithm;
+
+const MAGIC: [u8; 4] = *b"BROT";
+const FORMAT_VERSION: u8 = 1;
+pub const MAX_REASON_LEN: usize = 256;
+
+pub struct RotationStatement {
+    pub algorithm: Algorithm,
+    pub old_public_key: Vec<u8>,
+    pub new_public_key: Vec<u8>,
+    pub effective_height: u64,
+    pub reason: String,
+}
+
+impl RotationStatement {
+    /// Canonical bytes signed by the old key. Fails on keys of the wrong length
+    /// or an oversized reason.
+    pub fn encode(&self) -> Result<Vec<u8>, String> {
+        let key_len = self.algorithm.public_key_bytes();
+        if self.old_public_key.len() != key_len || self.new_public_key.len() != key_len {
+            return Err("public key length does not match the algorithm".to_string());
+        }
+        if self.reason.len() > MAX_REASON_LEN {
+            return Err(format!("reason longer than {} bytes", MAX_REASON_LEN));
+        }
+
+        let mut out = Vec::with_capacity(4 + 2 + 8 + 2 * key_len + 8 + 2 + self.reason.len());
+        out.extend_from_slice(&MAGIC);
+        out.push(FORMAT_VERSION);
+        out.push(self.algorithm.id());
+        for key in [&self.old_public_key, &self.new_public_key] {
+            out.extend_from_slice(&(key.len() as u32).to_be_bytes());
+            out.extend_from_slice(key);
+        }
+        out.extend_from_slice(&self.effective_height.to_be_bytes());
+        out.extend_from_slice(&(self.reason.len() as u16).to_be_bytes());
+        out.extend_from_slice(self.reason.as_bytes());
+        Ok(out)
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        if self.data.len() < len {
+            return Err("truncated certificate".to_string());
+        }
+        let (head, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(head)
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        Ok(u16::from_be_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_be_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, String> {
+        Ok(u64::from_be_bytes(self.take(8)?.try_into().unwrap()))
+    }
+}
+
+/// Split a certificate into its statement, the signed statement bytes and the signature.
+pub fn decode_certificate(certificate: &[u8]) -> Result<(RotationStatement, &[u8], &[u8]), String> {
+    let mut reader = Reader { data: certificate };
+    if reader.take(4)? != MAGIC {
+        return Err("not a rotation certificate".to_string());
+    }
+    let version = reader.take(1)?[0];
+    if version != FORMAT_VERSION {
+        return Err(format!("unsupported certificate version {}", version));
+    }
+    let algorithm_id = reader.take(1)?[0];
+    let algorithm = Algorithm::ALL
+        .into_iter()
+        .find(|algorithm| algorithm.id() == algorithm_id)
+        .ok_or_else(|| format!("unknown algorithm id {}", algorithm_id))?;
+
+    let mut keys = Vec::with_capacity(2);
+    for _ in 0..2 {
+        let len = reader.u32()? as usize;
+        if len != algorithm.public_key_bytes() {
+            return Err("public key length does not match the algorithm".to_string());
+        }
+        keys.push(reader.take(len)?.to_vec());
+    }
+    let effective_height = reader.u64()?;
+    let reason_len = reader.u16()? as usize;
+    if reason_len > MAX_REASON_LEN {
+        return Err(format!("reason longer than {} bytes", MAX_REASON_LEN));
+    }
+    let reason = String::from_utf8(reader.take(reason_len)?.to_vec()).map_err(|_| "reason is not UTF-8".to_string())?;
+
+    let signature = reader.data;
+    if signature.is_empty() {
+        return Err("missing signature".to_string());
+    }
+    let new_public_key = keys.pop().unwrap();
+    let old_public_key = keys.pop().unwrap();
+    let statement_bytes = &certificate[..certificate.len() - signature.len()];
+    let statement = RotationStatement { algorithm, old_public_key, new_public_key, effective_height, reason };
+    Ok((statement, statement_bytes, signature))
+}
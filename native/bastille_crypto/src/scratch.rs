@@ -0,0 +1,26 @@
+// Per-thread scratch space for signatures on their way into a BEAM binary.
+//
+// Signatures are at most SPHINCS+'s 17,088 bytes, so one buffer per thread,
+// reused across calls, replaces an allocation per signature. It only ever
+// holds public data.
+
+use std::cell::RefCell;
+
+// sphincsshake128fsimple::signature_bytes(), the largest supported signature
+const SIGNATURE_CAPACITY: usize = 17_088;
+
+thread_local! {
+    static SIGNATURE: RefCell<Vec<u8>> = RefCell::new(Vec::with_capacity(SIGNATURE_CAPACITY));
+}
+
+/// Run `f` with this thread's signature buffer, emptied first.
+pub fn with_signature<R>(f: impl FnOnce(&mut Vec<u8>) -> R) -> R {
+    SIGNATURE.with(|buffer| match buffer.try_borrow_mut() {
+        Ok(mut buffer) => {
+            buffer.clear();
+            f(&mut buffer)
+        }
+        // Only reachable if `f` itself asks for the buffer
+        Err(_) => f(&mut Vec::with_capacity(SIGNATURE_CAPACITY)),
+    })
+}
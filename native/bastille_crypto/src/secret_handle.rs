@@ -0,0 +1,62 @@
+// Secret keys held in Rust memory behind an opaque resource handle.
+//
+// The bytes are locked into RAM where the OS allows it (so they never reach
+// swap) and wiped when the last reference to the handle is garbage collected.
+// Elixir only ever sees the reference, so the key stays out of process heaps,
+// messages and crash dumps.
+
+use crate::keygen::Algorithm;
+use zeroize::Zeroize;
+
+pub struct SecretKeyHandle {
+    pub algorithm: Algorithm,
+    bytes: Vec<u8>,
+    locked: bool,
+}
+
+#[rustler::resource_impl]
+impl rustler::Resource for SecretKeyHandle {}
+
+impl SecretKeyHandle {
+    pub fn new(algorithm: Algorithm, bytes: &[u8]) -> Self {
+        let bytes = bytes.to_vec();
+        let locked = lock_memory(&bytes);
+        SecretKeyHandle { algorithm, bytes, locked }
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+impl Drop for SecretKeyHandle {
+    fn drop(&mut self) {
+        self.bytes.zeroize();
+        if self.locked {
+            unlock_memory(&self.bytes);
+        }
+    }
+}
+
+// mlock can fail under a low RLIMIT_MEMLOCK; the key is still usable, just swappable
+#[cfg(unix)]
+fn lock_memory(bytes: &[u8]) -> bool {
+    // SAFETY: the range is a live allocation owned by the handle
+    unsafe { libc::mlock(bytes.as_ptr() as *const libc::c_void, bytes.len()) == 0 }
+}
+
+#[cfg(unix)]
+fn unlock_memory(bytes: &[u8]) {
+    // SAFETY: same range that was successfully locked in lock_memory
+    unsafe {
+        libc::munlock(bytes.as_ptr() as *const libc::c_void, bytes.len());
+    }
+}
+
+#[cfg(not(unix))]
+fn lock_memory(_bytes: &[u8]) -> bool {
+    false
+}
+
+#[cfg(not(unix))]
+fn unlock_memory(_bytes: &[u8]) {}
@@ -0,0 +1,66 @@
+// Signing jobs for the *_sign_async NIFs.
+//
+// Jobs run on the crypto thread pool (see threads.rs), outside the BEAM's
+// dirty schedulers, and each result is sent back to the caller as
+// `{ref, signature}` or `{ref, {:error, reason}}`. At most QUEUE_CAPACITY jobs
+// are in flight; past that, submit() fails immediately so callers see
+// backpressure instead of an ever-growing backlog.
+//
+// The message is kept as a term in the job's own environment rather than
+// copied, so large messages are signed in place, and the signature goes
+// through the worker thread's scratch buffer (see scratch.rs).
+
+use rustler::env::SavedTerm;
+use rustler::{Binary, Encoder, LocalPid, OwnedEnv, Term};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+const QUEUE_CAPACITY: usize = 1024;
+
+static IN_FLIGHT: AtomicUsize = AtomicUsize::new(0);
+
+// Signs the message, appending the signature to the buffer it is given
+pub type SignTask = Box<dyn FnOnce(&[u8], &mut Vec<u8>) -> Result<(), String> + Send>;
+
+struct Job {
+    task: SignTask,
+    caller: LocalPid,
+    env: OwnedEnv,
+    message: SavedTerm,
+    reference: SavedTerm,
+}
+
+fn run(job: Job) {
+    let Job { task, caller, mut env, message, reference } = job;
+    crate::scratch::with_signature(|signature| {
+        let result = env.run(|env| match message.load(env).decode::<Binary>() {
+            Ok(message) => task(message.as_slice(), signature),
+            Err(_) => Err("message is not a binary".to_string()),
+        });
+        // The caller may have exited meanwhile; nobody is left to tell
+        let _ = env.send_and_clear(&caller, |env| {
+            let reference = reference.load(env);
+            match result {
+                Ok(()) => (reference, crate::make_binary(env, signature)).encode(env),
+                Err(reason) => (reference, (crate::error(), reason)).encode(env),
+            }
+        });
+    });
+}
+
+/// Queue `task` over `message`, replying to `caller` tagged with `reference`.
+/// False when the queue is full.
+pub fn submit(task: SignTask, message: Term, caller: LocalPid, reference: Term) -> bool {
+    if IN_FLIGHT.fetch_add(1, Ordering::AcqRel) >= QUEUE_CAPACITY {
+        IN_FLIGHT.fetch_sub(1, Ordering::AcqRel);
+        return false;
+    }
+    let env = OwnedEnv::new();
+    let message = env.save(message);
+    let reference = env.save(reference);
+    let job = Job { task, caller, env, message, reference };
+    crate::threads::current().spawn(move || {
+        run(job);
+        IN_FLIGHT.fetch_sub(1, Ordering::AcqRel);
+    });
+    true
+}
@@ -0,0 +1,472 @@
+// Sparse Merkle tree over 256-bit key paths, persisted as an append-only log,
+// for a provable account-state root per block.
+//
+// Keys are hashed to their path with BLAKE3; values are arbitrary binaries:
+//
+//   leaf  = blake3(0x00 | path | blake3(value))
+//   node  = blake3(0x01 | left | right)
+//   empty = 32 zero bytes
+//
+// A subtree holding a single leaf is represented by that leaf's hash instead
+// of a chain of nodes down to depth 256, so proofs are only as long as the
+// depth at which a key's path parts from its neighbours'. Interior hashes are
+// cached per (depth, prefix) and dropped along a key's path when it changes,
+// so a new root costs a few hashes per changed key rather than a rebuild.
+//
+// A proof is
+//   u16 sibling count | siblings (32 bytes each, root side first) | terminal
+// where the terminal is 0x00 for an empty subtree, or 0x01 | path | blake3(value)
+// for the single leaf found there. An empty terminal, or a leaf with another
+// path, proves the key absent.
+//
+// <dir>/smt.log holds put and delete records, each followed by a CRC32C. It
+// is replayed on open; a torn final record (a crash mid-write) is cut off.
+// Records reach the OS on every write, and disk on flush(), which also
+// rewrites the log as a snapshot once stale records outweigh live ones.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+pub type Hash = [u8; 32];
+
+pub const EMPTY: Hash = [0u8; 32];
+pub const MAX_VALUE_LEN: usize = 16 << 20;
+
+const LEAF_TAG: u8 = 0x00;
+const NODE_TAG: u8 = 0x01;
+pub const TERMINAL_EMPTY: u8 = 0x00;
+pub const TERMINAL_LEAF: u8 = 0x01;
+const DEPTH: usize = 256;
+
+const LOG_MAGIC: [u8; 8] = *b"BSMTLOG1";
+const OP_PUT: u8 = 1;
+const OP_DELETE: u8 = 2;
+const RECORD_OVERHEAD: u64 = 1 + 32 + 4 + 4;
+// Stale bytes tolerated before flush() compacts the log
+const COMPACT_SLACK: u64 = 1 << 20;
+
+pub fn key_path(key: &[u8]) -> Hash {
+    *blake3::hash(key).as_bytes()
+}
+
+pub fn leaf_hash(path: &Hash, value_hash: &Hash) -> Hash {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(&[LEAF_TAG]);
+    hasher.update(path);
+    hasher.update(value_hash);
+    *hasher.finalize().as_bytes()
+}
+
+pub fn node_hash(left: &Hash, right: &Hash) -> Hash {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(&[NODE_TAG]);
+    hasher.update(left);
+    hasher.update(right);
+    *hasher.finalize().as_bytes()
+}
+
+fn bit(path: &Hash, depth: usize) -> bool {
+    path[depth / 8] & (0x80 >> (depth % 8)) != 0
+}
+
+fn with_bit(mut path: Hash, depth: usize) -> Hash {
+    path[depth / 8] |= 0x80 >> (depth % 8);
+    path
+}
+
+// The first `depth` bits of `path`, the rest cleared
+fn prefix(path: &Hash, depth: usize) -> Hash {
+    let mut masked = EMPTY;
+    let full = depth / 8;
+    masked[..full].copy_from_slice(&path[..full]);
+    if !depth.is_multiple_of(8) {
+        masked[full] = path[full] & (0xff << (8 - depth % 8));
+    }
+    masked
+}
+
+// The last path under `prefix` at `depth`
+fn prefix_end(prefix: &Hash, depth: usize) -> Hash {
+    let mut end = *prefix;
+    let mut full = depth / 8;
+    if !depth.is_multiple_of(8) {
+        end[full] |= 0xff >> (depth % 8);
+        full += 1;
+    }
+    end[full..].fill(0xff);
+    end
+}
+
+struct Leaf {
+    value: Vec<u8>,
+    value_hash: Hash,
+    hash: Hash,
+}
+
+// What a subtree holds, as far as hashing and proofs care
+enum Subtree<'a> {
+    Empty,
+    Single(&'a Hash, &'a Leaf),
+    Branch,
+}
+
+struct SmtState {
+    leaves: BTreeMap<Hash, Leaf>,
+    // Hashes of branching subtrees, by (depth, prefix)
+    nodes: HashMap<(u16, Hash), Hash>,
+    dir: PathBuf,
+    log: File,
+    log_len: u64,
+    live_len: u64,
+    // Held for the tree's lifetime so no other process writes the same log
+    _lock: File,
+}
+
+fn record_len(value: &[u8]) -> u64 {
+    RECORD_OVERHEAD + value.len() as u64
+}
+
+fn encode_record(op: u8, path: &Hash, value: &[u8]) -> Vec<u8> {
+    let mut record = Vec::with_capacity(record_len(value) as usize);
+    record.push(op);
+    record.extend_from_slice(path);
+    record.extend_from_slice(&(value.len() as u32).to_be_bytes());
+    record.extend_from_slice(value);
+    let crc = crc32c::crc32c(&record);
+    record.extend_from_slice(&crc.to_be_bytes());
+    record
+}
+
+// Next record, or None at the end of the log or at a torn or corrupt record
+fn read_record(reader: &mut impl Read) -> Option<(u8, Hash, Vec<u8>)> {
+    let mut header = [0u8; 1 + 32 + 4];
+    reader.read_exact(&mut header).ok()?;
+    let value_len = u32::from_be_bytes([header[33], header[34], header[35], header[36]]) as usize;
+    if value_len > MAX_VALUE_LEN {
+        return None;
+    }
+    let mut value = vec![0u8; value_len];
+    reader.read_exact(&mut value).ok()?;
+    let mut crc = [0u8; 4];
+    reader.read_exact(&mut crc).ok()?;
+
+    let expected = crc32c::crc32c_append(crc32c::crc32c(&header), &value);
+    if u32::from_be_bytes(crc) != expected || !matches!(header[0], OP_PUT | OP_DELETE) {
+        return None;
+    }
+    let mut path = EMPTY;
+    path.copy_from_slice(&header[1..33]);
+    Some((header[0], path, value))
+}
+
+fn make_leaf(path: &Hash, value: Vec<u8>) -> Leaf {
+    let value_hash = *blake3::hash(&value).as_bytes();
+    let hash = leaf_hash(path, &value_hash);
+    Leaf { value, value_hash, hash }
+}
+
+pub fn io_error(action: &str, path: &Path, e: io::Error) -> String {
+    format!("Failed to {} '{}': {}", action, path.display(), e)
+}
+
+impl SmtState {
+    fn open(dir: &Path) -> Result<Self, String> {
+        fs::create_dir_all(dir).map_err(|e| io_error("create state directory", dir, e))?;
+        let lock_path = dir.join("smt.lock");
+        let lock = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&lock_path)
+            .map_err(|e| io_error("open", &lock_path, e))?;
+        lock.try_lock().map_err(|_| format!("state tree '{}' is already open", dir.display()))?;
+
+        let log_path = dir.join("smt.log");
+        let mut log = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(&log_path)
+            .map_err(|e| io_error("open", &log_path, e))?;
+
+        let mut leaves = BTreeMap::new();
+        let mut live_len = 0u64;
+        let mut valid_len = LOG_MAGIC.len() as u64;
+        let mut reader = BufReader::new(&log);
+        let mut magic = [0u8; 8];
+        match reader.read_exact(&mut magic) {
+            Ok(()) if magic == LOG_MAGIC => {
+                while let Some((op, path, value)) = read_record(&mut reader) {
+                    valid_len += record_len(&value);
+                    let added = if op == OP_PUT { record_len(&value) } else { 0 };
+                    let replaced = match op {
+                        OP_PUT => leaves.insert(path, make_leaf(&path, value)),
+                        _ => leaves.remove(&path),
+                    };
+                    live_len = live_len + added - replaced.map_or(0, |leaf| record_len(&leaf.value));
+                }
+            }
+            Ok(()) => return Err(format!("'{}' is not a state tree log", log_path.display())),
+            // Empty, or cut off before the magic was complete
+            Err(_) => valid_len = 0,
+        }
+
+        let file_len = log.metadata().map_err(|e| io_error("read", &log_path, e))?.len();
+        if file_len > valid_len {
+            log.set_len(valid_len).map_err(|e| io_error("truncate", &log_path, e))?;
+        }
+        if valid_len == 0 {
+            log.write_all(&LOG_MAGIC).map_err(|e| io_error("write", &log_path, e))?;
+            valid_len = LOG_MAGIC.len() as u64;
+        }
+
+        Ok(SmtState {
+            leaves,
+            nodes: HashMap::new(),
+            dir: dir.to_path_buf(),
+            log,
+            log_len: valid_len,
+            live_len,
+            _lock: lock,
+        })
+    }
+
+    fn append(&mut self, record: &[u8]) -> Result<(), String> {
+        self.log
+            .write_all(record)
+            .map_err(|e| io_error("write", &self.dir.join("smt.log"), e))?;
+        self.log_len += record.len() as u64;
+        Ok(())
+    }
+
+    fn invalidate(&mut self, path: &Hash) {
+        for depth in 0..DEPTH {
+            self.nodes.remove(&(depth as u16, prefix(path, depth)));
+        }
+    }
+
+    fn put(&mut self, key: &[u8], value: &[u8]) -> Result<(), String> {
+        let path = key_path(key);
+        self.append(&encode_record(OP_PUT, &path, value))?;
+        if let Some(old) = self.leaves.insert(path, make_leaf(&path, value.to_vec())) {
+            self.live_len -= record_len(&old.value);
+        }
+        self.live_len += record_len(value);
+        self.invalidate(&path);
+        Ok(())
+    }
+
+    fn delete(&mut self, key: &[u8]) -> Result<(), String> {
+        let path = key_path(key);
+        if !self.leaves.contains_key(&path) {
+            return Ok(());
+        }
+        self.append(&encode_record(OP_DELETE, &path, &[]))?;
+        if let Some(old) = self.leaves.remove(&path) {
+            self.live_len -= record_len(&old.value);
+        }
+        self.invalidate(&path);
+        Ok(())
+    }
+
+    fn subtree(&self, depth: usize, prefix: &Hash) -> Subtree<'_> {
+        let mut range = self.leaves.range(*prefix..=prefix_end(prefix, depth));
+        match (range.next(), range.next()) {
+            (None, _) => Subtree::Empty,
+            (Some((path, leaf)), None) => Subtree::Single(path, leaf),
+            (Some(_), Some(_)) => Subtree::Branch,
+        }
+    }
+
+    fn subtree_hash(&mut self, depth: usize, prefix: Hash) -> Hash {
+        match self.subtree(depth, &prefix) {
+            Subtree::Empty => return EMPTY,
+            Subtree::Single(_, leaf) => return leaf.hash,
+            Subtree::Branch => {}
+        }
+        if let Some(hash) = self.nodes.get(&(depth as u16, prefix)) {
+            return *hash;
+        }
+        // Two distinct paths always part before depth 256
+        let left = self.subtree_hash(depth + 1, prefix);
+        let right = self.subtree_hash(depth + 1, with_bit(prefix, depth));
+        let hash = node_hash(&left, &right);
+        self.nodes.insert((depth as u16, prefix), hash);
+        hash
+    }
+
+    fn prove(&mut self, key: &[u8]) -> Vec<u8> {
+        let path = key_path(key);
+        let mut siblings: Vec<Hash> = Vec::new();
+        let mut depth = 0;
+        let terminal = loop {
+            match self.subtree(depth, &prefix(&path, depth)) {
+                Subtree::Empty => break vec![TERMINAL_EMPTY],
+                Subtree::Single(leaf_path, leaf) => {
+                    let mut terminal = vec![TERMINAL_LEAF];
+                    terminal.extend_from_slice(leaf_path);
+                    terminal.extend_from_slice(&leaf.value_hash);
+                    break terminal;
+                }
+                Subtree::Branch => {}
+            }
+            let mut sibling_path = path;
+            sibling_path[depth / 8] ^= 0x80 >> (depth % 8);
+            let sibling_prefix = prefix(&sibling_path, depth + 1);
+            siblings.push(self.subtree_hash(depth + 1, sibling_prefix));
+            depth += 1;
+        };
+
+        let mut proof = Vec::with_capacity(2 + siblings.len() * 32 + terminal.len());
+        proof.extend_from_slice(&(siblings.len() as u16).to_be_bytes());
+        for sibling in &siblings {
+            proof.extend_from_slice(sibling);
+        }
+        proof.extend_from_slice(&terminal);
+        proof
+    }
+
+    fn flush(&mut self) -> Result<(), String> {
+        let log_path = self.dir.join("smt.log");
+        self.log.sync_data().map_err(|e| io_error("sync", &log_path, e))?;
+        if self.log_len > 2 * (self.live_len + LOG_MAGIC.len() as u64) + COMPACT_SLACK {
+            self.compact()?;
+        }
+        Ok(())
+    }
+
+    // A log holding one put per live entry
+    fn snapshot(&self) -> Vec<u8> {
+        let mut snapshot = Vec::with_capacity(LOG_MAGIC.len() + self.live_len as usize);
+        snapshot.extend_from_slice(&LOG_MAGIC);
+        for (path, leaf) in &self.leaves {
+            snapshot.extend_from_slice(&encode_record(OP_PUT, path, &leaf.value));
+        }
+        snapshot
+    }
+
+    // Rewrite the log as one put per live entry, swapped in atomically, and
+    // return the bytes it shrank by
+    fn compact(&mut self) -> Result<u64, String> {
+        let log_path = self.dir.join("smt.log");
+        let tmp_path = self.dir.join("smt.log.tmp");
+        let result = (|| {
+            let mut tmp = File::create(&tmp_path)?;
+            let snapshot = self.snapshot();
+            tmp.write_all(&snapshot)?;
+            tmp.sync_all()?;
+            fs::rename(&tmp_path, &log_path)?;
+            File::open(&self.dir)?.sync_all()?;
+            Ok(snapshot.len() as u64)
+        })();
+        let len = result.map_err(|e| {
+            let _ = fs::remove_file(&tmp_path);
+            io_error("compact", &log_path, e)
+        })?;
+        self.log = OpenOptions::new()
+            .append(true)
+            .open(&log_path)
+            .map_err(|e| io_error("open", &log_path, e))?;
+        let reclaimed = self.log_len.saturating_sub(len);
+        self.log_len = len;
+        Ok(reclaimed)
+    }
+}
+
+/// A state tree open on one directory; at most one per directory at a time.
+pub struct SparseMerkleTree {
+    state: Mutex<SmtState>,
+}
+
+impl SparseMerkleTree {
+    pub fn open(dir: &Path) -> Result<Self, String> {
+        Ok(SparseMerkleTree { state: Mutex::new(SmtState::open(dir)?) })
+    }
+
+    fn state(&self) -> Result<std::sync::MutexGuard<'_, SmtState>, String> {
+        self.state.lock().map_err(|_| "state tree lock poisoned".to_string())
+    }
+
+    /// Run `f` on the value stored under `key`, if any.
+    pub fn get<R>(&self, key: &[u8], f: impl FnOnce(Option<&[u8]>) -> R) -> Result<R, String> {
+        let state = self.state()?;
+        Ok(f(state.leaves.get(&key_path(key)).map(|leaf| leaf.value.as_slice())))
+    }
+
+    pub fn put(&self, key: &[u8], value: &[u8]) -> Result<(), String> {
+        self.state()?.put(key, value)
+    }
+
+    pub fn delete(&self, key: &[u8]) -> Result<(), String> {
+        self.state()?.delete(key)
+    }
+
+    pub fn root(&self) -> Result<Hash, String> {
+        Ok(self.state()?.subtree_hash(0, EMPTY))
+    }
+
+    /// Proof that `key` holds its current value, or is absent.
+    pub fn prove(&self, key: &[u8]) -> Result<Vec<u8>, String> {
+        Ok(self.state()?.prove(key))
+    }
+
+    pub fn flush(&self) -> Result<(), String> {
+        self.state()?.flush()
+    }
+
+    /// Rewrite the log without stale records, returning the bytes reclaimed.
+    pub fn compact(&self) -> Result<u64, String> {
+        self.state()?.compact()
+    }
+
+    /// A log recreating the tree's current contents, for backups.
+    pub fn export_log(&self) -> Result<Vec<u8>, String> {
+        Ok(self.state()?.snapshot())
+    }
+}
+
+/// Check `proof` that `key` holds `value` (Some) or is absent (None) under `root`.
+pub fn verify_proof(root: &[u8], key: &[u8], value: Option<&[u8]>, proof: &[u8]) -> bool {
+    if proof.len() < 3 {
+        return false;
+    }
+    let count = u16::from_be_bytes([proof[0], proof[1]]) as usize;
+    let terminal_start = 2 + count * 32;
+    if count >= DEPTH || proof.len() <= terminal_start {
+        return false;
+    }
+
+    let path = key_path(key);
+    let mut node = match &proof[terminal_start..] {
+        [TERMINAL_EMPTY] if value.is_none() => EMPTY,
+        [TERMINAL_LEAF, leaf @ ..] if leaf.len() == 64 => {
+            let mut leaf_path = EMPTY;
+            leaf_path.copy_from_slice(&leaf[..32]);
+            let mut value_hash = EMPTY;
+            value_hash.copy_from_slice(&leaf[32..]);
+            // The leaf must sit in the subtree the key's path leads to
+            if prefix(&leaf_path, count) != prefix(&path, count) {
+                return false;
+            }
+            let consistent = match value {
+                Some(value) => leaf_path == path && value_hash == *blake3::hash(value).as_bytes(),
+                None => leaf_path != path,
+            };
+            if !consistent {
+                return false;
+            }
+            leaf_hash(&leaf_path, &value_hash)
+        }
+        _ => return false,
+    };
+
+    for depth in (0..count).rev() {
+        let mut sibling = EMPTY;
+        sibling.copy_from_slice(&proof[2 + depth * 32..2 + (depth + 1) * 32]);
+        node = if bit(&path, depth) { node_hash(&sibling, &node) } else { node_hash(&node, &sibling) };
+    }
+    node[..] == *root
+}
@@ -0,0 +1,236 @@
+// Chunked state snapshots for fast sync. The encoder packs key/value entries
+// into chunks of about `chunk_size` bytes as they are fed in, so a node can
+// stream its whole state to a peer without materializing it; the decoder
+// checks and unpacks chunks one at a time on the other side.
+//
+// Chunk: magic "BSNP" | kind (u8) | sequence (u32 BE) | payload length
+// (u32 BE) | payload | crc32c of everything before (u32 BE)
+//
+//   data payload: entries of key length (u32 BE) | key | value length
+//                 (u32 BE) | value
+//   end payload:  entry count (u64 BE) | blake3 of the data payloads in order
+//
+// Sequence numbers start at 0 and the end chunk comes last, so a missing,
+// repeated or reordered chunk is caught as soon as it arrives, and the end
+// chunk's digest ties all the chunks to one snapshot. A rejected chunk
+// leaves the decoder untouched, so it can be fetched again (e.g. from
+// another peer) and fed in its place.
+
+use std::sync::Mutex;
+
+pub const MAX_CHUNK_SIZE: usize = 64 << 20;
+
+const MAGIC: &[u8; 4] = b"BSNP";
+const KIND_DATA: u8 = 1;
+const KIND_END: u8 = 2;
+const HEADER_LEN: usize = 4 + 1 + 4 + 4;
+const END_PAYLOAD_LEN: usize = 8 + 32;
+
+fn chunk(kind: u8, sequence: u32, payload: &[u8]) -> Vec<u8> {
+    let mut chunk = Vec::with_capacity(HEADER_LEN + payload.len() + 4);
+    chunk.extend_from_slice(MAGIC);
+    chunk.push(kind);
+    chunk.extend_from_slice(&sequence.to_be_bytes());
+    chunk.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    chunk.extend_from_slice(payload);
+    let crc = crc32c::crc32c(&chunk);
+    chunk.extend_from_slice(&crc.to_be_bytes());
+    chunk
+}
+
+struct EncoderState {
+    sequence: u32,
+    payload: Vec<u8>,
+    entries: u64,
+    digest: blake3::Hasher,
+    finished: bool,
+}
+
+pub struct SnapshotEncoder {
+    chunk_size: usize,
+    state: Mutex<EncoderState>,
+}
+
+#[rustler::resource_impl]
+impl rustler::Resource for SnapshotEncoder {}
+
+impl EncoderState {
+    fn seal(&mut self, chunks: &mut Vec<Vec<u8>>) -> Result<(), String> {
+        self.digest.update(&self.payload);
+        chunks.push(chunk(KIND_DATA, self.sequence, &self.payload));
+        self.payload.clear();
+        self.sequence = self.sequence.checked_add(1).ok_or("snapshot has too many chunks")?;
+        Ok(())
+    }
+}
+
+impl SnapshotEncoder {
+    /// None unless 1 <= chunk_size <= MAX_CHUNK_SIZE.
+    pub fn new(chunk_size: usize) -> Option<Self> {
+        if !(1..=MAX_CHUNK_SIZE).contains(&chunk_size) {
+            return None;
+        }
+        let state = EncoderState {
+            sequence: 0,
+            payload: Vec::new(),
+            entries: 0,
+            digest: blake3::Hasher::new(),
+            finished: false,
+        };
+        Some(SnapshotEncoder { chunk_size, state: Mutex::new(state) })
+    }
+
+    fn lock(&self) -> Result<std::sync::MutexGuard<'_, EncoderState>, String> {
+        self.state.lock().map_err(|_| "snapshot encoder lock poisoned".to_string())
+    }
+
+    /// Add entries, returning the chunks they completed. An entry is never
+    /// split, so one larger than `chunk_size` gets a chunk of its own.
+    pub fn append(&self, entries: &[(&[u8], &[u8])]) -> Result<Vec<Vec<u8>>, String> {
+        let mut state = self.lock()?;
+        if state.finished {
+            return Err("snapshot encoder is finished".to_string());
+        }
+        let mut chunks = Vec::new();
+        for (key, value) in entries {
+            let len = 8 + key.len() + value.len();
+            if len > MAX_CHUNK_SIZE {
+                return Err("snapshot entry is too large".to_string());
+            }
+            if !state.payload.is_empty() && state.payload.len() + len > self.chunk_size {
+                state.seal(&mut chunks)?;
+            }
+            for field in [key, value] {
+                state.payload.extend_from_slice(&(field.len() as u32).to_be_bytes());
+                state.payload.extend_from_slice(field);
+            }
+            state.entries += 1;
+        }
+        Ok(chunks)
+    }
+
+    /// Flush the last data chunk, if any, and the end chunk.
+    pub fn finish(&self) -> Result<Vec<Vec<u8>>, String> {
+        let mut state = self.lock()?;
+        if state.finished {
+            return Err("snapshot encoder is finished".to_string());
+        }
+        let mut chunks = Vec::new();
+        if !state.payload.is_empty() {
+            state.seal(&mut chunks)?;
+        }
+        let mut end = state.entries.to_be_bytes().to_vec();
+        end.extend_from_slice(state.digest.finalize().as_bytes());
+        chunks.push(chunk(KIND_END, state.sequence, &end));
+        state.finished = true;
+        Ok(chunks)
+    }
+}
+
+struct DecoderState {
+    sequence: u32,
+    entries: u64,
+    digest: blake3::Hasher,
+    finished: bool,
+}
+
+pub struct SnapshotDecoder {
+    state: Mutex<DecoderState>,
+}
+
+#[rustler::resource_impl]
+impl rustler::Resource for SnapshotDecoder {}
+
+// (kind, sequence, payload) of a chunk whose framing and checksum are valid
+fn parse_chunk(chunk: &[u8]) -> Result<(u8, u32, &[u8]), String> {
+    if chunk.len() < HEADER_LEN + 4 || &chunk[..4] != MAGIC {
+        return Err("not a snapshot chunk".to_string());
+    }
+    let (body, crc) = chunk.split_at(chunk.len() - 4);
+    if crc32c::crc32c(body).to_be_bytes() != crc {
+        return Err("snapshot chunk checksum mismatch".to_string());
+    }
+    let kind = body[4];
+    let sequence = u32::from_be_bytes(body[5..9].try_into().unwrap());
+    let len = u32::from_be_bytes(body[9..13].try_into().unwrap()) as usize;
+    let payload = &body[HEADER_LEN..];
+    if payload.len() != len {
+        return Err("snapshot chunk length mismatch".to_string());
+    }
+    Ok((kind, sequence, payload))
+}
+
+fn take_field<'a>(payload: &mut &'a [u8]) -> Result<&'a [u8], String> {
+    let (len, rest) = payload.split_first_chunk::<4>().ok_or("truncated snapshot entry")?;
+    let len = u32::from_be_bytes(*len) as usize;
+    if rest.len() < len {
+        return Err("truncated snapshot entry".to_string());
+    }
+    let (field, rest) = rest.split_at(len);
+    *payload = rest;
+    Ok(field)
+}
+
+impl SnapshotDecoder {
+    pub fn new() -> Self {
+        let state = DecoderState { sequence: 0, entries: 0, digest: blake3::Hasher::new(), finished: false };
+        SnapshotDecoder { state: Mutex::new(state) }
+    }
+
+    fn lock(&self) -> Result<std::sync::MutexGuard<'_, DecoderState>, String> {
+        self.state.lock().map_err(|_| "snapshot decoder lock poisoned".to_string())
+    }
+
+    /// Check the next chunk and return its entries (none for the end chunk)
+    /// mapped through `convert`; if that fails, so does the chunk.
+    pub fn decode<'c, T>(
+        &self,
+        chunk: &'c [u8],
+        mut convert: impl FnMut(&'c [u8], &'c [u8]) -> Result<T, String>,
+    ) -> Result<Vec<T>, String> {
+        let mut state = self.lock()?;
+        if state.finished {
+            return Err("snapshot already ended".to_string());
+        }
+        let (kind, sequence, payload) = parse_chunk(chunk)?;
+        if sequence != state.sequence {
+            return Err(format!("expected snapshot chunk {}, got {}", state.sequence, sequence));
+        }
+        match kind {
+            KIND_DATA => {
+                let mut entries = Vec::new();
+                let mut rest = payload;
+                while !rest.is_empty() {
+                    let key = take_field(&mut rest)?;
+                    let value = take_field(&mut rest)?;
+                    entries.push(convert(key, value)?);
+                }
+                let next = state.sequence.checked_add(1).ok_or("snapshot has too many chunks")?;
+                state.digest.update(payload);
+                state.entries += entries.len() as u64;
+                state.sequence = next;
+                Ok(entries)
+            }
+            KIND_END => {
+                if payload.len() != END_PAYLOAD_LEN
+                    || payload[..8] != state.entries.to_be_bytes()
+                    || payload[8..] != *state.digest.finalize().as_bytes()
+                {
+                    return Err("snapshot doesn't match its end chunk".to_string());
+                }
+                state.finished = true;
+                Ok(Vec::new())
+            }
+            _ => Err("unknown snapshot chunk kind".to_string()),
+        }
+    }
+
+    /// Number of entries decoded, once the end chunk has been accepted.
+    pub fn finish(&self) -> Result<u64, String> {
+        let state = self.lock()?;
+        if !state.finished {
+            return Err("snapshot is incomplete".to_string());
+        }
+        Ok(state.entries)
+    }
+}
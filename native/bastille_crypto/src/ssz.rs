@@ -0,0 +1,359 @@
+// SimpleSerialize (SSZ), as specified for Ethereum consensus, and its
+// hash_tree_root merkleization over SHA-256, for containers described by a
+// schema at run time rather than declared in code.
+//
+// Supported types: booleans, unsigned integers of up to 128 bits, byte
+// vectors and lists, bitvectors and bitlists, vectors and lists of any type,
+// and containers. Decoding is strict: offsets must be in order and start right
+// after the fixed part, padding bits must be zero, and lengths must respect
+// the schema, so one value has exactly one accepted encoding.
+
+use sha2::{Digest, Sha256};
+
+const BYTES_PER_CHUNK: usize = 32;
+const OFFSET_LEN: usize = 4;
+
+type Chunk = [u8; 32];
+
+pub enum Type {
+    Bool,
+    // Size in bytes: 1, 2, 4, 8 or 16
+    Uint(usize),
+    Bytes(usize),
+    ByteList(usize),
+    Bitvector(usize),
+    Bitlist(usize),
+    Vector(Box<Type>, usize),
+    List(Box<Type>, usize),
+    Container(Vec<(String, Type)>),
+}
+
+pub enum Value {
+    Bool(bool),
+    Uint(u128),
+    // Of Bytes and ByteList
+    Bytes(Vec<u8>),
+    // Of Bitvector and Bitlist
+    Bits(Vec<bool>),
+    // Of Vector and List
+    Items(Vec<Value>),
+    // Of Container, in field order
+    Fields(Vec<Value>),
+}
+
+fn mismatch() -> String {
+    "value does not match its SSZ type".to_string()
+}
+
+impl Type {
+    fn is_fixed(&self) -> bool {
+        match self {
+            Type::Bool | Type::Uint(_) | Type::Bytes(_) | Type::Bitvector(_) => true,
+            Type::ByteList(_) | Type::Bitlist(_) | Type::List(..) => false,
+            Type::Vector(item, _) => item.is_fixed(),
+            Type::Container(fields) => fields.iter().all(|(_, field)| field.is_fixed()),
+        }
+    }
+
+    fn is_basic(&self) -> bool {
+        matches!(self, Type::Bool | Type::Uint(_))
+    }
+
+    // Size of a fixed-size type
+    fn fixed_size(&self) -> usize {
+        match self {
+            Type::Bool => 1,
+            Type::Uint(size) | Type::Bytes(size) => *size,
+            Type::Bitvector(len) => len.div_ceil(8),
+            Type::Vector(item, len) => item.fixed_size() * len,
+            Type::Container(fields) => fields.iter().map(|(_, field)| field.fixed_size()).sum(),
+            Type::ByteList(_) | Type::Bitlist(_) | Type::List(..) => unreachable!(),
+        }
+    }
+
+    // Size of the type's slot in the fixed part of a sequence or container
+    fn slot_size(&self) -> usize {
+        if self.is_fixed() {
+            self.fixed_size()
+        } else {
+            OFFSET_LEN
+        }
+    }
+}
+
+fn pack_bits(bits: &[bool], delimited: bool) -> Vec<u8> {
+    let len = bits.len() + delimited as usize;
+    let mut bytes = vec![0u8; len.div_ceil(8)];
+    for (i, bit) in bits.iter().copied().chain(delimited.then_some(true)).enumerate() {
+        bytes[i / 8] |= (bit as u8) << (i % 8);
+    }
+    bytes
+}
+
+/// Append the SSZ encoding of `value`, of type `ty`, to `out`.
+pub fn serialize(ty: &Type, value: &Value, out: &mut Vec<u8>) -> Result<(), String> {
+    match (ty, value) {
+        (Type::Bool, Value::Bool(value)) => out.push(*value as u8),
+        (Type::Uint(size), Value::Uint(n)) => {
+            if *size < 16 && *n >> (size * 8) != 0 {
+                return Err(format!("{} does not fit in uint{}", n, size * 8));
+            }
+            out.extend_from_slice(&n.to_le_bytes()[..*size]);
+        }
+        (Type::Bytes(len), Value::Bytes(bytes)) if bytes.len() == *len => out.extend_from_slice(bytes),
+        (Type::ByteList(limit), Value::Bytes(bytes)) if bytes.len() <= *limit => out.extend_from_slice(bytes),
+        (Type::Bitvector(len), Value::Bits(bits)) if bits.len() == *len => out.extend(pack_bits(bits, false)),
+        (Type::Bitlist(limit), Value::Bits(bits)) if bits.len() <= *limit => out.extend(pack_bits(bits, true)),
+        (Type::Vector(item, len), Value::Items(items)) if items.len() == *len => {
+            serialize_parts(items.iter().map(|value| (&**item, value)), out)?
+        }
+        (Type::List(item, limit), Value::Items(items)) if items.len() <= *limit => {
+            serialize_parts(items.iter().map(|value| (&**item, value)), out)?
+        }
+        (Type::Container(fields), Value::Fields(values)) if values.len() == fields.len() => {
+            serialize_parts(fields.iter().map(|(_, ty)| ty).zip(values), out)?
+        }
+        _ => return Err(mismatch()),
+    }
+    Ok(())
+}
+
+// Fixed-size parts in place, variable-size ones after all of them, each
+// pointed at by an offset in its place
+fn serialize_parts<'a>(parts: impl Iterator<Item = (&'a Type, &'a Value)> + Clone, out: &mut Vec<u8>) -> Result<(), String> {
+    let fixed_len: usize = parts.clone().map(|(ty, _)| ty.slot_size()).sum();
+    let mut variable = Vec::new();
+    for (ty, value) in parts {
+        if ty.is_fixed() {
+            serialize(ty, value, out)?;
+        } else {
+            let offset = u32::try_from(fixed_len + variable.len()).map_err(|_| "SSZ value too large".to_string())?;
+            out.extend_from_slice(&offset.to_le_bytes());
+            serialize(ty, value, &mut variable)?;
+        }
+    }
+    out.extend_from_slice(&variable);
+    Ok(())
+}
+
+fn bad(what: &str) -> String {
+    format!("invalid SSZ: {}", what)
+}
+
+/// Decode `data` as a value of type `ty`.
+pub fn deserialize(ty: &Type, data: &[u8]) -> Result<Value, String> {
+    if ty.is_fixed() && data.len() != ty.fixed_size() {
+        return Err(bad("wrong length for a fixed-size type"));
+    }
+    Ok(match ty {
+        Type::Bool => match data[0] {
+            0 => Value::Bool(false),
+            1 => Value::Bool(true),
+            _ => return Err(bad("boolean other than 0 or 1")),
+        },
+        Type::Uint(size) => {
+            let mut bytes = [0u8; 16];
+            bytes[..*size].copy_from_slice(data);
+            Value::Uint(u128::from_le_bytes(bytes))
+        }
+        Type::Bytes(_) => Value::Bytes(data.to_vec()),
+        Type::ByteList(limit) if data.len() <= *limit => Value::Bytes(data.to_vec()),
+        Type::ByteList(_) => return Err(bad("byte list over its limit")),
+        Type::Bitvector(len) => {
+            let bits = unpack_bits(data, *len);
+            if pack_bits(&bits, false) != data {
+                return Err(bad("bitvector padding bits set"));
+            }
+            Value::Bits(bits)
+        }
+        Type::Bitlist(limit) => {
+            let last = *data.last().ok_or_else(|| bad("bitlist without its delimiter"))?;
+            if last == 0 {
+                return Err(bad("bitlist without its delimiter"));
+            }
+            let len = (data.len() - 1) * 8 + 7 - last.leading_zeros() as usize;
+            if len > *limit {
+                return Err(bad("bitlist over its limit"));
+            }
+            Value::Bits(unpack_bits(data, len))
+        }
+        Type::Vector(item, len) => Value::Items(deserialize_parts(&vec![&**item; *len], data)?),
+        Type::List(item, limit) => {
+            let count = if item.is_fixed() {
+                let size = item.fixed_size();
+                if !data.len().is_multiple_of(size) {
+                    return Err(bad("list length not a multiple of its item size"));
+                }
+                data.len() / size
+            } else if data.is_empty() {
+                0
+            } else {
+                let first = read_offset(data, 0)?;
+                if !first.is_multiple_of(OFFSET_LEN) || first == 0 || first > data.len() {
+                    return Err(bad("misaligned first offset"));
+                }
+                first / OFFSET_LEN
+            };
+            if count > *limit {
+                return Err(bad("list over its limit"));
+            }
+            Value::Items(deserialize_parts(&vec![&**item; count], data)?)
+        }
+        Type::Container(fields) => {
+            let types: Vec<&Type> = fields.iter().map(|(_, ty)| ty).collect();
+            Value::Fields(deserialize_parts(&types, data)?)
+        }
+    })
+}
+
+fn unpack_bits(data: &[u8], len: usize) -> Vec<bool> {
+    (0..len).map(|i| data[i / 8] >> (i % 8) & 1 == 1).collect()
+}
+
+fn read_offset(data: &[u8], at: usize) -> Result<usize, String> {
+    let bytes = data.get(at..at + OFFSET_LEN).ok_or_else(|| bad("truncated offset"))?;
+    Ok(u32::from_le_bytes(bytes.try_into().unwrap()) as usize)
+}
+
+fn deserialize_parts(types: &[&Type], data: &[u8]) -> Result<Vec<Value>, String> {
+    let fixed_len: usize = types.iter().map(|ty| ty.slot_size()).sum();
+    if data.len() < fixed_len {
+        return Err(bad("truncated fixed-size part"));
+    }
+    // Where each part starts, variable ones by their offsets
+    let mut starts = Vec::with_capacity(types.len());
+    let mut pos = 0;
+    let mut last_offset = None;
+    for ty in types {
+        if ty.is_fixed() {
+            starts.push(pos);
+        } else {
+            let offset = read_offset(data, pos)?;
+            let misplaced = match last_offset {
+                None => offset != fixed_len,
+                Some(last) => offset < last || offset > data.len(),
+            };
+            if misplaced {
+                return Err(bad("offsets out of order or out of bounds"));
+            }
+            last_offset = Some(offset);
+            starts.push(offset);
+        }
+        pos += ty.slot_size();
+    }
+    if last_offset.is_none() && data.len() != fixed_len {
+        return Err(bad("trailing bytes"));
+    }
+
+    let mut values = Vec::with_capacity(types.len());
+    for (i, ty) in types.iter().enumerate() {
+        let part = if ty.is_fixed() {
+            &data[starts[i]..starts[i] + ty.fixed_size()]
+        } else {
+            // Up to the next variable part, or the end
+            let end = types[i + 1..]
+                .iter()
+                .zip(&starts[i + 1..])
+                .find(|(ty, _)| !ty.is_fixed())
+                .map_or(data.len(), |(_, start)| *start);
+            &data[starts[i]..end]
+        };
+        values.push(deserialize(ty, part)?);
+    }
+    Ok(values)
+}
+
+fn hash_pair(left: &Chunk, right: &Chunk) -> Chunk {
+    let mut hasher = Sha256::new();
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+fn chunks(bytes: &[u8]) -> Vec<Chunk> {
+    bytes
+        .chunks(BYTES_PER_CHUNK)
+        .map(|chunk| {
+            let mut padded = [0u8; 32];
+            padded[..chunk.len()].copy_from_slice(chunk);
+            padded
+        })
+        .collect()
+}
+
+// Root of `chunks` padded with zero chunks to the next power of two of `limit`
+fn merkleize(mut layer: Vec<Chunk>, limit: u64) -> Result<Chunk, String> {
+    if layer.len() as u64 > limit.max(1) {
+        return Err(mismatch());
+    }
+    let depth = limit.max(1).next_power_of_two().trailing_zeros();
+    let mut zero = [0u8; 32];
+    for _ in 0..depth {
+        if layer.len() % 2 == 1 {
+            layer.push(zero);
+        }
+        layer = layer.chunks(2).map(|pair| hash_pair(&pair[0], &pair[1])).collect();
+        zero = hash_pair(&zero, &zero);
+    }
+    Ok(layer.first().copied().unwrap_or(zero))
+}
+
+fn mix_in_length(root: Chunk, len: usize) -> Chunk {
+    let mut length = [0u8; 32];
+    length[..8].copy_from_slice(&(len as u64).to_le_bytes());
+    hash_pair(&root, &length)
+}
+
+fn chunk_count(len: usize, item_size: usize) -> u64 {
+    (len as u64 * item_size as u64).div_ceil(BYTES_PER_CHUNK as u64)
+}
+
+/// The hash_tree_root of `value`, of type `ty`.
+pub fn hash_tree_root(ty: &Type, value: &Value) -> Result<Chunk, String> {
+    match (ty, value) {
+        (Type::Bool | Type::Uint(_), _) => {
+            let mut bytes = Vec::new();
+            serialize(ty, value, &mut bytes)?;
+            merkleize(chunks(&bytes), 1)
+        }
+        (Type::Bytes(len), Value::Bytes(bytes)) if bytes.len() == *len => merkleize(chunks(bytes), chunk_count(*len, 1)),
+        (Type::ByteList(limit), Value::Bytes(bytes)) if bytes.len() <= *limit => {
+            Ok(mix_in_length(merkleize(chunks(bytes), chunk_count(*limit, 1))?, bytes.len()))
+        }
+        (Type::Bitvector(len), Value::Bits(bits)) if bits.len() == *len => {
+            merkleize(chunks(&pack_bits(bits, false)), len.div_ceil(256) as u64)
+        }
+        (Type::Bitlist(limit), Value::Bits(bits)) if bits.len() <= *limit => {
+            let root = merkleize(chunks(&pack_bits(bits, false)), limit.div_ceil(256) as u64)?;
+            Ok(mix_in_length(root, bits.len()))
+        }
+        (Type::Vector(item, len), Value::Items(items)) if items.len() == *len => items_root(item, items, *len),
+        (Type::List(item, limit), Value::Items(items)) if items.len() <= *limit => {
+            Ok(mix_in_length(items_root(item, items, *limit)?, items.len()))
+        }
+        (Type::Container(fields), Value::Fields(values)) if values.len() == fields.len() => {
+            let roots = fields
+                .iter()
+                .zip(values)
+                .map(|((_, ty), value)| hash_tree_root(ty, value))
+                .collect::<Result<Vec<_>, _>>()?;
+            merkleize(roots, fields.len() as u64)
+        }
+        _ => Err(mismatch()),
+    }
+}
+
+// Root of the items of a vector or list holding up to `limit` of them: basic
+// items packed into chunks, others by their own roots
+fn items_root(item: &Type, items: &[Value], limit: usize) -> Result<Chunk, String> {
+    if item.is_basic() {
+        let mut bytes = Vec::new();
+        for value in items {
+            serialize(item, value, &mut bytes)?;
+        }
+        merkleize(chunks(&bytes), chunk_count(limit, item.fixed_size()))
+    } else {
+        let roots = items.iter().map(|value| hash_tree_root(item, value)).collect::<Result<Vec<_>, _>>()?;
+        merkleize(roots, limit as u64)
+    }
+}
@@ -0,0 +1,71 @@
+// Deltas between state snapshots, so incremental sync between checkpoints
+// only ships what changed. A diff is itself a snapshot (see snapshot.rs), so
+// it is chunked and checked the same way: one entry per key that was added or
+// changed, holding its new value, and one with an empty value per key that was
+// removed. State snapshots never hold empty values (snapshot_encode stores
+// terms, which take at least two bytes), so the two can't be confused.
+//
+// Both sides are read whole and keyed in order; a key repeated within a
+// snapshot keeps its last value. Output entries come in key order, so the
+// same inputs always give the same chunks.
+
+use crate::snapshot::{SnapshotDecoder, SnapshotEncoder};
+use std::collections::BTreeMap;
+
+pub const CHUNK_SIZE: usize = 1 << 20;
+
+type Entries<'a> = BTreeMap<&'a [u8], &'a [u8]>;
+
+fn read<'a>(chunks: &[&'a [u8]]) -> Result<Entries<'a>, String> {
+    let decoder = SnapshotDecoder::new();
+    let mut entries = BTreeMap::new();
+    for chunk in chunks {
+        entries.extend(decoder.decode(chunk, |key, value| Ok((key, value)))?);
+    }
+    decoder.finish()?;
+    Ok(entries)
+}
+
+fn read_state<'a>(chunks: &[&'a [u8]]) -> Result<Entries<'a>, String> {
+    let entries = read(chunks)?;
+    if entries.values().any(|value| value.is_empty()) {
+        return Err("state snapshot holds an empty value".to_string());
+    }
+    Ok(entries)
+}
+
+fn write(entries: &[(&[u8], &[u8])]) -> Result<Vec<Vec<u8>>, String> {
+    let encoder = SnapshotEncoder::new(CHUNK_SIZE).unwrap();
+    let mut chunks = encoder.append(entries)?;
+    chunks.extend(encoder.finish()?);
+    Ok(chunks)
+}
+
+/// The diff taking the state in `old` to the state in `new`, both complete
+/// snapshots.
+pub fn diff(old: &[&[u8]], new: &[&[u8]]) -> Result<Vec<Vec<u8>>, String> {
+    let old = read_state(old)?;
+    let new = read_state(new)?;
+    let mut changes: Vec<(&[u8], &[u8])> = new
+        .iter()
+        .filter(|(key, value)| old.get(*key) != Some(*value))
+        .map(|(key, value)| (*key, *value))
+        .collect();
+    changes.extend(old.keys().filter(|key| !new.contains_key(*key)).map(|key| (*key, &[][..])));
+    changes.sort_unstable_by_key(|(key, _)| *key);
+    write(&changes)
+}
+
+/// The snapshot of `snapshot`'s state with `diff` applied. Fails if the diff
+/// removes a key the state doesn't have, a sign it was taken from another base.
+pub fn apply(snapshot: &[&[u8]], diff: &[&[u8]]) -> Result<Vec<Vec<u8>>, String> {
+    let mut state = read_state(snapshot)?;
+    for (key, value) in read(diff)? {
+        if value.is_empty() {
+            state.remove(key).ok_or("state diff removes a missing key")?;
+        } else {
+            state.insert(key, value);
+        }
+    }
+    write(&state.into_iter().collect::<Vec<_>>())
+}
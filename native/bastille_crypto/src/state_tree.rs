@@ -0,0 +1,129 @@
+// The resource behind the smt_* NIFs: a state tree on one of two backends,
+// chosen when it is opened. Both commit to the same root and proofs for the
+// same entries (see jmt.rs), so they differ only in what they keep:
+//
+//   Sparse     the latest state, durable write by write (smt.rs)
+//   Jellyfish  every committed version, durable commit by commit (jmt.rs)
+
+use crate::jmt::JellyfishMerkleTree;
+use crate::smt::{io_error, Hash, SparseMerkleTree};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+
+#[derive(Clone, Copy)]
+pub enum Backend {
+    Sparse,
+    Jellyfish,
+}
+
+pub enum StateTree {
+    Sparse(SparseMerkleTree),
+    Jellyfish(JellyfishMerkleTree),
+}
+
+#[rustler::resource_impl]
+impl rustler::Resource for StateTree {}
+
+fn unversioned() -> String {
+    "sparse state trees are not versioned".to_string()
+}
+
+impl StateTree {
+    pub fn open(dir: &Path, backend: Backend) -> Result<Self, String> {
+        Ok(match backend {
+            Backend::Sparse => StateTree::Sparse(SparseMerkleTree::open(dir)?),
+            Backend::Jellyfish => StateTree::Jellyfish(JellyfishMerkleTree::open(dir)?),
+        })
+    }
+
+    /// Run `f` on the value stored under `key`, currently (None) or at
+    /// `version`.
+    pub fn get<R>(&self, key: &[u8], version: Option<u64>, f: impl FnOnce(Option<&[u8]>) -> R) -> Result<R, String> {
+        match (self, version) {
+            (StateTree::Sparse(tree), None) => tree.get(key, f),
+            (StateTree::Sparse(_), Some(_)) => Err(unversioned()),
+            (StateTree::Jellyfish(tree), version) => tree.get(key, version, f),
+        }
+    }
+
+    pub fn put(&self, key: &[u8], value: &[u8]) -> Result<(), String> {
+        match self {
+            StateTree::Sparse(tree) => tree.put(key, value),
+            StateTree::Jellyfish(tree) => tree.put(key, value),
+        }
+    }
+
+    pub fn delete(&self, key: &[u8]) -> Result<(), String> {
+        match self {
+            StateTree::Sparse(tree) => tree.delete(key),
+            StateTree::Jellyfish(tree) => tree.delete(key),
+        }
+    }
+
+    pub fn commit(&self, version: u64) -> Result<Hash, String> {
+        match self {
+            StateTree::Sparse(_) => Err(unversioned()),
+            StateTree::Jellyfish(tree) => tree.commit(version),
+        }
+    }
+
+    pub fn root(&self, version: Option<u64>) -> Result<Hash, String> {
+        match (self, version) {
+            (StateTree::Sparse(tree), None) => tree.root(),
+            (StateTree::Sparse(_), Some(_)) => Err(unversioned()),
+            (StateTree::Jellyfish(tree), version) => tree.root(version),
+        }
+    }
+
+    pub fn prove(&self, key: &[u8], version: Option<u64>) -> Result<Vec<u8>, String> {
+        match (self, version) {
+            (StateTree::Sparse(tree), None) => tree.prove(key),
+            (StateTree::Sparse(_), Some(_)) => Err(unversioned()),
+            (StateTree::Jellyfish(tree), version) => tree.prove(key, version),
+        }
+    }
+
+    /// Drop state history below `version`: the versions before it on the
+    /// jellyfish backend, stale log records on the sparse one, which keeps
+    /// only the latest state. Returns the bytes reclaimed.
+    pub fn prune_below(&self, version: u64) -> Result<u64, String> {
+        match self {
+            StateTree::Sparse(tree) => tree.compact(),
+            StateTree::Jellyfish(tree) => tree.prune_below(version),
+        }
+    }
+
+    /// The backend and a log recreating the state at `version` (the current
+    /// state on the sparse backend), for backups.
+    pub fn export_log(&self, version: u64) -> Result<(Backend, Vec<u8>), String> {
+        match self {
+            StateTree::Sparse(tree) => Ok((Backend::Sparse, tree.export_log()?)),
+            StateTree::Jellyfish(tree) => Ok((Backend::Jellyfish, tree.export_log(version)?)),
+        }
+    }
+
+    pub fn flush(&self) -> Result<(), String> {
+        match self {
+            StateTree::Sparse(tree) => tree.flush(),
+            StateTree::Jellyfish(tree) => tree.flush(),
+        }
+    }
+}
+
+/// Write `log` from export_log() as the log of a new tree in `dir`.
+pub fn import_log(dir: &Path, backend: Backend, log: &[u8]) -> Result<(), String> {
+    let path = dir.join(match backend {
+        Backend::Sparse => "smt.log",
+        Backend::Jellyfish => "jmt.log",
+    });
+    fs::create_dir_all(dir).map_err(|e| io_error("create state directory", dir, e))?;
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&path)
+        .map_err(|e| io_error("create", &path, e))?;
+    file.write_all(log)
+        .and_then(|_| file.sync_all())
+        .map_err(|e| io_error("write", &path, e))
+}
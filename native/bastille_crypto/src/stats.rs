@@ -0,0 +1,96 @@
+// Call counters for the crypto NIFs, read by crypto_stats/0 so telemetry can
+// publish native metrics without wrapping every call site in Elixir.
+//
+// Every NIF owns a static NifCounters (see the `tracked!` macro in lib.rs),
+// collected at load time through `inventory`, so a NIF that was never called
+// still shows up with zero counts. Updates are relaxed atomics: totals may be
+// momentarily out of step with each other, but never lose counts.
+
+use rustler::NifResult;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+
+pub struct NifCounters {
+    name: &'static str,
+    calls: AtomicU64,
+    failures: AtomicU64,
+    total_ns: AtomicU64,
+}
+
+impl NifCounters {
+    pub const fn new(name: &'static str) -> Self {
+        NifCounters {
+            name,
+            calls: AtomicU64::new(0),
+            failures: AtomicU64::new(0),
+            total_ns: AtomicU64::new(0),
+        }
+    }
+}
+
+// Submitted by `tracked!` next to each NifCounters
+pub struct Registered(pub &'static NifCounters);
+
+inventory::collect!(Registered);
+
+#[derive(Default)]
+pub struct NifStats {
+    pub calls: u64,
+    pub failures: u64,
+    pub total_ns: u64,
+}
+
+/// One call of a NIF, counted with its duration when dropped. It counts as a
+/// failure (an error returned or `ArgumentError` raised, `?` included) unless
+/// the NIF returns through `ok` or a successful `finish`.
+pub struct Tracker {
+    counters: &'static NifCounters,
+    start: Instant,
+    failed: bool,
+}
+
+pub fn track(counters: &'static NifCounters) -> Tracker {
+    Tracker { counters, start: Instant::now(), failed: true }
+}
+
+impl Tracker {
+    pub fn ok<T>(mut self, value: T) -> NifResult<T> {
+        self.failed = false;
+        Ok(value)
+    }
+
+    pub fn finish<T>(mut self, result: NifResult<T>) -> NifResult<T> {
+        self.failed = result.is_err();
+        result
+    }
+
+    /// For NIFs that can't return an error.
+    pub fn done<T>(mut self, value: T) -> T {
+        self.failed = false;
+        value
+    }
+}
+
+impl Drop for Tracker {
+    fn drop(&mut self) {
+        let elapsed = u64::try_from(self.start.elapsed().as_nanos()).unwrap_or(u64::MAX);
+        self.counters.calls.fetch_add(1, Ordering::Relaxed);
+        self.counters.total_ns.fetch_add(elapsed, Ordering::Relaxed);
+        if self.failed {
+            self.counters.failures.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Totals per NIF name, with every arity of a NIF summed together.
+pub fn snapshot() -> BTreeMap<&'static str, NifStats> {
+    let mut totals: BTreeMap<&'static str, NifStats> = BTreeMap::new();
+    for Registered(counters) in inventory::iter::<Registered> {
+        let stats = totals.entry(counters.name).or_default();
+        stats.calls += counters.calls.load(Ordering::Relaxed);
+        stats.failures += counters.failures.load(Ordering::Relaxed);
+        stats.total_ns += counters.total_ns.load(Ordering::Relaxed);
+    }
+    totals
+}
@@ -0,0 +1,330 @@
+// Key-value store for the node's blocks, state and transaction index, over
+// RocksDB (the `rocksdb` feature) or LMDB (the `lmdb` feature), chosen when
+// the store is opened. Both keep the three kinds of data apart (column
+// families, named databases) and apply a batch spanning them atomically.
+//
+// Iterators don't pin the database: each call to `next` scans afresh from just
+// past the last key returned, so a scan sees writes made between batches,
+// never a half-applied batch within one.
+//
+// With a keyring (see at_rest.rs) every value is sealed before it reaches the
+// backend, bound to its column and key; keys stay in the clear, so lookups and
+// range scans work unchanged. Rotating the key reseals values in batches, each
+// holding the store to itself so no write can slip in between a value's read
+// and its rewrite.
+
+use crate::at_rest::{self, Keyring};
+#[cfg(feature = "lmdb")]
+use crate::lmdb::LmdbStore;
+#[cfg(feature = "rocksdb")]
+use crate::rocks::RocksStore;
+use std::path::Path;
+use std::sync::{Mutex, RwLock, RwLockReadGuard};
+
+// Values resealed per batch when rotating the data key
+const ROTATION_BATCH: usize = 256;
+
+#[derive(Clone, Copy)]
+pub enum Column {
+    Blocks,
+    State,
+    TxIndex,
+}
+
+impl Column {
+    pub const ALL: [Column; 3] = [Column::Blocks, Column::State, Column::TxIndex];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Column::Blocks => "blocks",
+            Column::State => "state",
+            Column::TxIndex => "tx_index",
+        }
+    }
+}
+
+pub type Entry = (Vec<u8>, Vec<u8>);
+
+pub enum Op<'a> {
+    Put(Column, &'a [u8], &'a [u8]),
+    Delete(Column, &'a [u8]),
+}
+
+/// Keys of a column from `from` (inclusive) to `to` (exclusive), either bound
+/// optional, strictly past `after` in the scan's direction if set, or else
+/// from `at` on. `at` lies within the bounds.
+pub struct Scan<'a> {
+    pub from: Option<&'a [u8]>,
+    pub to: Option<&'a [u8]>,
+    pub after: Option<&'a [u8]>,
+    pub at: Option<&'a [u8]>,
+    pub reverse: bool,
+}
+
+#[derive(Clone, Copy)]
+pub enum Backend {
+    Rocksdb,
+    // Maximum size of the memory map, and so of the database, in bytes
+    Lmdb {
+        #[cfg_attr(not(feature = "lmdb"), allow(dead_code))]
+        map_size: usize,
+    },
+}
+
+impl Backend {
+    pub const DEFAULT_LMDB_MAP_SIZE: usize = 64 << 30;
+
+    /// RocksDB if it is built in, LMDB otherwise.
+    pub fn default_for_build() -> Self {
+        if cfg!(feature = "rocksdb") {
+            Backend::Rocksdb
+        } else {
+            Backend::Lmdb { map_size: Self::DEFAULT_LMDB_MAP_SIZE }
+        }
+    }
+}
+
+enum Db {
+    #[cfg(feature = "rocksdb")]
+    Rocksdb(RocksStore),
+    #[cfg(feature = "lmdb")]
+    Lmdb(LmdbStore),
+}
+
+// Run `$body` with `$store` bound to whichever backend `$db` is
+macro_rules! dispatch {
+    ($db:expr, $store:ident => $body:expr) => {
+        match $db {
+            #[cfg(feature = "rocksdb")]
+            Db::Rocksdb($store) => $body,
+            #[cfg(feature = "lmdb")]
+            Db::Lmdb($store) => $body,
+        }
+    };
+}
+
+pub struct Store {
+    // None once closed
+    db: RwLock<Option<Db>>,
+    // Taken after `db` when both are held
+    keys: RwLock<Option<Keyring>>,
+}
+
+// What a sealed value is bound to
+fn value_aad(column: Column, key: &[u8]) -> Vec<u8> {
+    [column.name().as_bytes(), b"/", key].concat()
+}
+
+#[rustler::resource_impl]
+impl rustler::Resource for Store {}
+
+impl Store {
+    /// Open the store at `dir`, creating it and any missing column. A store
+    /// created with a keyring must always be opened with one.
+    pub fn open(dir: &Path, backend: Backend, keys: Option<Keyring>) -> Result<Self, String> {
+        let fresh = std::fs::read_dir(dir).map_or(true, |mut entries| entries.next().is_none());
+        if keys.is_some() {
+            std::fs::create_dir_all(dir).map_err(|e| format!("failed to create {}: {}", dir.display(), e))?;
+        }
+        at_rest::check_marker(dir, keys.as_ref(), fresh)?;
+        let db = match backend {
+            #[cfg(feature = "rocksdb")]
+            Backend::Rocksdb => Db::Rocksdb(RocksStore::open(dir)?),
+            #[cfg(feature = "lmdb")]
+            Backend::Lmdb { map_size } => Db::Lmdb(LmdbStore::open(dir, map_size)?),
+            #[cfg(not(feature = "rocksdb"))]
+            Backend::Rocksdb => return Err("the rocksdb backend is not built in".to_string()),
+            #[cfg(not(feature = "lmdb"))]
+            Backend::Lmdb { .. } => return Err("the lmdb backend is not built in".to_string()),
+        };
+        Ok(Store { db: RwLock::new(Some(db)), keys: RwLock::new(keys) })
+    }
+
+    fn db(&self) -> Result<RwLockReadGuard<'_, Option<Db>>, String> {
+        let db = self.db.read().map_err(|_| "store lock poisoned".to_string())?;
+        if db.is_none() {
+            return Err("store is closed".to_string());
+        }
+        Ok(db)
+    }
+
+    fn keys(&self) -> Result<RwLockReadGuard<'_, Option<Keyring>>, String> {
+        self.keys.read().map_err(|_| "store lock poisoned".to_string())
+    }
+
+    /// Flush and close the database; later calls fail. Waits for calls in
+    /// progress on other threads.
+    pub fn close(&self) -> Result<(), String> {
+        let mut db = self.db.write().map_err(|_| "store lock poisoned".to_string())?;
+        let db = db.take().ok_or("store is closed")?;
+        dispatch!(db, store => store.close())
+    }
+
+    pub fn get<R>(&self, column: Column, key: &[u8], f: impl FnOnce(Option<&[u8]>) -> R) -> Result<R, String> {
+        let db = self.db()?;
+        let keys = self.keys()?;
+        let Some(keys) = keys.as_ref() else {
+            return dispatch!(db.as_ref().unwrap(), store => store.get(column, key, f));
+        };
+        let open = |sealed: Option<&[u8]>| sealed.map(|sealed| keys.open(&value_aad(column, key), sealed)).transpose();
+        let value = dispatch!(db.as_ref().unwrap(), store => store.get(column, key, open))??;
+        Ok(f(value.as_ref().map(|value| value.as_slice())))
+    }
+
+    pub fn put(&self, column: Column, key: &[u8], value: &[u8]) -> Result<(), String> {
+        self.write(&[Op::Put(column, key, value)])
+    }
+
+    pub fn delete(&self, column: Column, key: &[u8]) -> Result<(), String> {
+        dispatch!(self.db()?.as_ref().unwrap(), store => store.write(&[Op::Delete(column, key)]))
+    }
+
+    /// Apply all of `ops` or none of them.
+    pub fn write(&self, ops: &[Op]) -> Result<(), String> {
+        let db = self.db()?;
+        let keys = self.keys()?;
+        let Some(keys) = keys.as_ref() else {
+            return dispatch!(db.as_ref().unwrap(), store => store.write(ops));
+        };
+        let sealed = ops
+            .iter()
+            .map(|op| match *op {
+                Op::Put(column, key, value) => keys.seal(&value_aad(column, key), value),
+                Op::Delete(..) => Ok(Vec::new()),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let ops: Vec<Op> = ops
+            .iter()
+            .zip(&sealed)
+            .map(|(op, sealed)| match *op {
+                Op::Put(column, key, _) => Op::Put(column, key, sealed),
+                Op::Delete(column, key) => Op::Delete(column, key),
+            })
+            .collect();
+        dispatch!(db.as_ref().unwrap(), store => store.write(&ops))
+    }
+
+    /// Make `key` the data key, then reseal every value under it, returning
+    /// how many were resealed. Values sealed under the old key stay readable
+    /// throughout; until this returns, the old key must be kept among the
+    /// retired ones the store is opened with.
+    pub fn rotate_key(&self, key: &[u8]) -> Result<u64, String> {
+        {
+            let mut keys = self.keys.write().map_err(|_| "store lock poisoned".to_string())?;
+            let rotated = keys.as_ref().ok_or("store is not encrypted")?.rotate(key)?;
+            *keys = Some(rotated);
+        }
+        let mut resealed = 0;
+        for column in Column::ALL {
+            let mut after: Option<Vec<u8>> = None;
+            loop {
+                let db = self.db.write().map_err(|_| "store lock poisoned".to_string())?;
+                let db = db.as_ref().ok_or("store is closed")?;
+                let keys = self.keys()?;
+                let keys = keys.as_ref().unwrap();
+                let scan = Scan { from: None, to: None, after: after.as_deref(), at: None, reverse: false };
+                let entries = dispatch!(db, store => store.scan(column, &scan, ROTATION_BATCH))?;
+                let sealed = entries
+                    .iter()
+                    .filter(|(_, value)| !keys.is_current(value))
+                    .map(|(key, value)| Ok((key, keys.reseal(&value_aad(column, key), value)?)))
+                    .collect::<Result<Vec<_>, String>>()?;
+                if !sealed.is_empty() {
+                    let ops: Vec<Op> = sealed.iter().map(|(key, value)| Op::Put(column, key, value)).collect();
+                    dispatch!(db, store => store.write(&ops))?;
+                    resealed += sealed.len() as u64;
+                }
+                match entries.last() {
+                    Some((key, _)) if entries.len() == ROTATION_BATCH => after = Some(key.clone()),
+                    _ => break,
+                }
+            }
+        }
+        Ok(resealed)
+    }
+
+    /// Compact the whole of `column` now rather than in the background, e.g.
+    /// after pruning. LMDB reuses freed pages in place, so this is a no-op there.
+    pub fn compact(&self, column: Column) -> Result<(), String> {
+        dispatch!(self.db()?.as_ref().unwrap(), store => store.compact(column))
+    }
+
+    fn scan(&self, column: Column, scan: &Scan, count: usize) -> Result<Vec<Entry>, String> {
+        let db = self.db()?;
+        let mut entries = dispatch!(db.as_ref().unwrap(), store => store.scan(column, scan, count))?;
+        if let Some(keys) = self.keys()?.as_ref() {
+            for (key, value) in &mut entries {
+                *value = keys.open(&value_aad(column, key), value)?.to_vec();
+            }
+        }
+        Ok(entries)
+    }
+}
+
+enum Cursor {
+    Start,
+    // Last key returned
+    After(Vec<u8>),
+    // Key sought, within the range
+    At(Vec<u8>),
+    Done,
+}
+
+pub struct StoreIterator {
+    store: rustler::ResourceArc<Store>,
+    column: Column,
+    from: Option<Vec<u8>>,
+    to: Option<Vec<u8>>,
+    reverse: bool,
+    cursor: Mutex<Cursor>,
+}
+
+#[rustler::resource_impl]
+impl rustler::Resource for StoreIterator {}
+
+impl StoreIterator {
+    /// Iterator over the keys of `column` in `from..to` (either bound
+    /// optional), in ascending order, or descending if `reverse`.
+    pub fn new(
+        store: rustler::ResourceArc<Store>,
+        column: Column,
+        from: Option<Vec<u8>>,
+        to: Option<Vec<u8>>,
+        reverse: bool,
+    ) -> Self {
+        StoreIterator { store, column, from, to, reverse, cursor: Mutex::new(Cursor::Start) }
+    }
+
+    /// Up to `count` further entries; fewer only at the end of the range.
+    pub fn next(&self, count: usize) -> Result<Vec<Entry>, String> {
+        let mut cursor = self.cursor.lock().map_err(|_| "store iterator lock poisoned".to_string())?;
+        let (after, at) = match &*cursor {
+            Cursor::Done => return Ok(Vec::new()),
+            _ if count == 0 => return Ok(Vec::new()),
+            Cursor::Start => (None, None),
+            Cursor::After(key) => (Some(key.as_slice()), None),
+            Cursor::At(key) => (None, Some(key.as_slice())),
+        };
+        let scan = Scan { from: self.from.as_deref(), to: self.to.as_deref(), after, at, reverse: self.reverse };
+        let entries = self.store.scan(self.column, &scan, count)?;
+        *cursor = match entries.last() {
+            Some((key, _)) if entries.len() == count => Cursor::After(key.clone()),
+            _ => Cursor::Done,
+        };
+        Ok(entries)
+    }
+
+    /// Move the iterator so the next entry is the first at or past `key` in
+    /// its direction, whether `key` was passed already or not.
+    pub fn seek(&self, key: &[u8]) -> Result<(), String> {
+        let mut cursor = self.cursor.lock().map_err(|_| "store iterator lock poisoned".to_string())?;
+        let before_from = self.from.as_deref().is_some_and(|from| key < from);
+        let past_to = self.to.as_deref().is_some_and(|to| key >= to);
+        *cursor = match (before_from, past_to, self.reverse) {
+            (true, _, false) | (_, true, true) => Cursor::Start,
+            (_, true, false) | (true, _, true) => Cursor::Done,
+            (false, false, _) => Cursor::At(key.to_vec()),
+        };
+        Ok(())
+    }
+}
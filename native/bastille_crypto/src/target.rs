@@ -0,0 +1,109 @@
+// Proof-of-work targets: 256-bit unsigned integers held as 32 big-endian
+// bytes, the form hashes are compared in (see pow.rs).
+//
+// Compact bits are Bitcoin's nBits: a size byte then a 23-bit mantissa, the
+// target being mantissa * 256^(size - 3). Decoding refuses negative and
+// overflowing values, and any encoding other than the one to_compact gives,
+// so a header's bits can't be rewritten without changing the target.
+
+pub const TARGET_LEN: usize = 32;
+
+pub type Target = [u8; TARGET_LEN];
+
+const SIGN_BIT: u32 = 0x0080_0000;
+
+pub fn to_compact(target: &Target) -> u32 {
+    let Some(first) = target.iter().position(|byte| *byte != 0) else {
+        return 0;
+    };
+    let mut size = (TARGET_LEN - first) as u32;
+    let mut mantissa = target[first..].iter().chain([0, 0].iter()).take(3).fold(0u32, |n, byte| n << 8 | *byte as u32);
+    // The top mantissa bit is the sign, so a value with it set takes one more byte
+    if mantissa & SIGN_BIT != 0 {
+        mantissa >>= 8;
+        size += 1;
+    }
+    size << 24 | mantissa
+}
+
+pub fn from_compact(bits: u32) -> Result<Target, String> {
+    let size = (bits >> 24) as usize;
+    let mantissa = bits & 0x007f_ffff;
+    if mantissa != 0 && bits & SIGN_BIT != 0 {
+        return Err("negative compact target".to_string());
+    }
+    let mut target = [0u8; TARGET_LEN];
+    for (i, byte) in mantissa.to_be_bytes()[1..].iter().enumerate() {
+        // Byte i of the mantissa has weight 256^(size - 1 - i)
+        match (TARGET_LEN + i).checked_sub(size) {
+            Some(index) if index < TARGET_LEN => target[index] = *byte,
+            Some(_) => {}
+            None if *byte != 0 => return Err("compact target overflows 256 bits".to_string()),
+            None => {}
+        }
+    }
+    if to_compact(&target) != bits {
+        return Err("non-canonical compact target".to_string());
+    }
+    Ok(target)
+}
+
+// Little-endian 64-bit limbs, one more than a target needs so a product fits
+type Wide = [u64; 5];
+
+fn widen(target: &Target) -> Wide {
+    let mut wide = [0u64; 5];
+    for (limb, chunk) in wide.iter_mut().zip(target.rchunks_exact(8)) {
+        *limb = u64::from_be_bytes(chunk.try_into().unwrap_or_default());
+    }
+    wide
+}
+
+// None if the value doesn't fit in 256 bits
+fn narrow(wide: &Wide) -> Option<Target> {
+    if wide[4] != 0 {
+        return None;
+    }
+    let mut target = [0u8; TARGET_LEN];
+    for (limb, chunk) in wide.iter().zip(target.rchunks_exact_mut(8)) {
+        chunk.copy_from_slice(&limb.to_be_bytes());
+    }
+    Some(target)
+}
+
+fn mul(wide: &Wide, factor: u64) -> Wide {
+    let mut out = [0u64; 5];
+    let mut carry = 0u128;
+    for (limb, result) in wide.iter().zip(out.iter_mut()) {
+        let product = *limb as u128 * factor as u128 + carry;
+        *result = product as u64;
+        carry = product >> 64;
+    }
+    out
+}
+
+fn div(wide: &Wide, divisor: u64) -> Wide {
+    let mut out = [0u64; 5];
+    let mut remainder = 0u128;
+    for (limb, result) in wide.iter().zip(out.iter_mut()).rev() {
+        let dividend = remainder << 64 | *limb as u128;
+        *result = (dividend / divisor as u128) as u64;
+        remainder = dividend % divisor as u128;
+    }
+    out
+}
+
+/// The target after a window that took `actual_timespan` instead of
+/// `target_timespan` (> 0): scaled by their ratio, with the ratio clamped to
+/// 1/max_factor..max_factor and the result to `max_target`.
+pub fn retarget(target: &Target, actual_timespan: i64, target_timespan: u64, max_factor: u64, max_target: &Target) -> Target {
+    let min = (target_timespan / max_factor).max(1) as i128;
+    let max = target_timespan as i128 * max_factor as i128;
+    // At least 1, and at most the larger of i64::MAX and min, so a u64
+    let actual = (actual_timespan as i128).clamp(min, max.max(min)) as u64;
+    let scaled = div(&mul(&widen(target), actual), target_timespan);
+    match narrow(&scaled) {
+        Some(next) if next <= *max_target => next,
+        _ => *max_target,
+    }
+}
@@ -0,0 +1,53 @@
+// The thread pool behind every parallel and asynchronous API: batch verify,
+// sign_many, parallel hashing, Merkle roots and the *_sign_async workers.
+//
+// rayon's global pool can only be sized once, before first use, so we keep our
+// own pool and swap it on resize. Work already running finishes on the old
+// pool, whose threads exit once the last reference to it is dropped.
+
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use std::sync::{Arc, RwLock};
+
+pub const MAX_THREADS: usize = 1024;
+
+lazy_static::lazy_static! {
+    static ref POOL: RwLock<Arc<ThreadPool>> =
+        RwLock::new(Arc::new(build(0).expect("failed to start crypto thread pool")));
+}
+
+// 0 lets rayon pick, one thread per logical CPU
+fn build(size: usize) -> Result<ThreadPool, String> {
+    ThreadPoolBuilder::new()
+        .num_threads(size)
+        .thread_name(|index| format!("bastille-crypto-{}", index))
+        .build()
+        .map_err(|e| format!("failed to start crypto thread pool: {}", e))
+}
+
+/// Replace the pool with one of `size` threads (0 for one per CPU).
+pub fn configure(size: usize) -> Result<(), String> {
+    let pool = Arc::new(build(size)?);
+    let mut current = POOL.write().map_err(|_| "thread pool lock poisoned")?;
+    *current = pool;
+    Ok(())
+}
+
+pub fn current() -> Arc<ThreadPool> {
+    match POOL.read() {
+        Ok(pool) => Arc::clone(&pool),
+        Err(poisoned) => Arc::clone(&poisoned.into_inner()),
+    }
+}
+
+pub fn size() -> usize {
+    current().current_num_threads()
+}
+
+/// Run `f` on the pool, so the rayon calls inside it use the pool's threads.
+pub fn install<R, F>(f: F) -> R
+where
+    R: Send,
+    F: FnOnce() -> R + Send,
+{
+    current().install(f)
+}
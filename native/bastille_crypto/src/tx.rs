@@ -0,0 +1,185 @@
+// Canonical binary format of transactions, so every node encodes, decodes
+// and signs the same bytes. The signing bytes are a prefix of the encoding,
+// everything but the signature:
+//
+//   version u8 | chain_id | from | to | amount u128 | fee u128 | nonce u64
+//     | timestamp i64 | data | signature type u8
+//   signature tag u8 | (post-quantum) dilithium | falcon | sphincs
+//
+// Integers are big-endian; chain_id, addresses, data and signatures are
+// prefixed with their length as an unsigned LEB128 varint. Amounts are u128
+// because juillet (10^-14 BAST) overflow a u64 past ~184k BAST.
+//
+// Decoding is strict: one version, minimal varints, lengths within the
+// limits below, a signature that matches the type and no trailing bytes, so
+// a transaction has exactly one encoding.
+
+use crate::varint;
+
+pub const VERSION: u8 = 1;
+
+pub const MAX_CHAIN_ID_LEN: usize = 32;
+pub const MAX_ADDRESS_LEN: usize = 64;
+pub const MAX_DATA_LEN: usize = 65_536;
+// SPHINCS+-128f signatures, the largest, are 17_088 bytes
+pub const MAX_SIGNATURE_LEN: usize = 65_536;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum SignatureType {
+    PostQuantum2Of3 = 0,
+    Coinbase = 1,
+}
+
+pub enum Signature {
+    // Unsigned, for post-quantum transactions only
+    None,
+    PostQuantum { dilithium: Vec<u8>, falcon: Vec<u8>, sphincs: Vec<u8> },
+    Coinbase,
+}
+
+const SIGNATURE_NONE: u8 = 0;
+const SIGNATURE_POST_QUANTUM: u8 = 1;
+const SIGNATURE_COINBASE: u8 = 2;
+
+pub struct Transaction {
+    pub chain_id: Vec<u8>,
+    pub from: Vec<u8>,
+    pub to: Vec<u8>,
+    pub amount: u128,
+    pub fee: u128,
+    pub nonce: u64,
+    pub timestamp: i64,
+    pub data: Vec<u8>,
+    pub signature_type: SignatureType,
+    pub signature: Signature,
+}
+
+fn check_len(what: &str, bytes: &[u8], min: usize, max: usize) -> Result<(), String> {
+    if bytes.len() < min || bytes.len() > max {
+        return Err(format!("{} length {} outside {}..{}", what, bytes.len(), min, max));
+    }
+    Ok(())
+}
+
+fn check(tx: &Transaction) -> Result<(), String> {
+    check_len("chain id", &tx.chain_id, 1, MAX_CHAIN_ID_LEN)?;
+    check_len("from address", &tx.from, 1, MAX_ADDRESS_LEN)?;
+    check_len("to address", &tx.to, 1, MAX_ADDRESS_LEN)?;
+    check_len("data", &tx.data, 0, MAX_DATA_LEN)?;
+    match (&tx.signature, tx.signature_type) {
+        (Signature::None, SignatureType::PostQuantum2Of3) | (Signature::Coinbase, SignatureType::Coinbase) => Ok(()),
+        (Signature::PostQuantum { dilithium, falcon, sphincs }, SignatureType::PostQuantum2Of3) => {
+            check_len("dilithium signature", dilithium, 1, MAX_SIGNATURE_LEN)?;
+            check_len("falcon signature", falcon, 1, MAX_SIGNATURE_LEN)?;
+            check_len("sphincs signature", sphincs, 1, MAX_SIGNATURE_LEN)
+        }
+        _ => Err("signature does not match the signature type".to_string()),
+    }
+}
+
+fn put_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    varint::put_uleb128(out, bytes.len() as u64);
+    out.extend_from_slice(bytes);
+}
+
+fn put_signing_bytes(tx: &Transaction, out: &mut Vec<u8>) {
+    out.push(VERSION);
+    put_bytes(out, &tx.chain_id);
+    put_bytes(out, &tx.from);
+    put_bytes(out, &tx.to);
+    out.extend_from_slice(&tx.amount.to_be_bytes());
+    out.extend_from_slice(&tx.fee.to_be_bytes());
+    out.extend_from_slice(&tx.nonce.to_be_bytes());
+    out.extend_from_slice(&tx.timestamp.to_be_bytes());
+    put_bytes(out, &tx.data);
+    out.push(tx.signature_type as u8);
+}
+
+/// The bytes a signature over `tx` covers.
+pub fn signing_bytes(tx: &Transaction) -> Result<Vec<u8>, String> {
+    check(tx)?;
+    let mut out = Vec::with_capacity(128 + tx.data.len());
+    put_signing_bytes(tx, &mut out);
+    Ok(out)
+}
+
+pub fn encode(tx: &Transaction) -> Result<Vec<u8>, String> {
+    check(tx)?;
+    let mut out = Vec::with_capacity(128 + tx.data.len());
+    put_signing_bytes(tx, &mut out);
+    match &tx.signature {
+        Signature::None => out.push(SIGNATURE_NONE),
+        Signature::PostQuantum { dilithium, falcon, sphincs } => {
+            out.push(SIGNATURE_POST_QUANTUM);
+            put_bytes(&mut out, dilithium);
+            put_bytes(&mut out, falcon);
+            put_bytes(&mut out, sphincs);
+        }
+        Signature::Coinbase => out.push(SIGNATURE_COINBASE),
+    }
+    Ok(out)
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl Reader<'_> {
+    fn take(&mut self, len: usize) -> Result<&[u8], String> {
+        let bytes = self.data.get(self.pos..self.pos + len).ok_or("truncated transaction")?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        Ok(self.take(N)?.try_into().unwrap_or([0; N]))
+    }
+
+    // Length-prefixed bytes, the length checked against `max` before reading
+    fn bytes(&mut self, what: &str, max: usize) -> Result<Vec<u8>, String> {
+        let (len, size) = varint::read_uleb128(&self.data[self.pos..])?;
+        self.pos += size;
+        if len > max as u64 {
+            return Err(format!("{} longer than {} bytes", what, max));
+        }
+        Ok(self.take(len as usize)?.to_vec())
+    }
+}
+
+pub fn decode(data: &[u8]) -> Result<Transaction, String> {
+    let mut reader = Reader { data, pos: 0 };
+    let version = reader.array::<1>()?[0];
+    if version != VERSION {
+        return Err(format!("unsupported transaction format version {}", version));
+    }
+    let chain_id = reader.bytes("chain id", MAX_CHAIN_ID_LEN)?;
+    let from = reader.bytes("from address", MAX_ADDRESS_LEN)?;
+    let to = reader.bytes("to address", MAX_ADDRESS_LEN)?;
+    let amount = u128::from_be_bytes(reader.array()?);
+    let fee = u128::from_be_bytes(reader.array()?);
+    let nonce = u64::from_be_bytes(reader.array()?);
+    let timestamp = i64::from_be_bytes(reader.array()?);
+    let tx_data = reader.bytes("data", MAX_DATA_LEN)?;
+    let signature_type = match reader.array::<1>()?[0] {
+        0 => SignatureType::PostQuantum2Of3,
+        1 => SignatureType::Coinbase,
+        other => return Err(format!("unknown signature type {}", other)),
+    };
+    let signature = match reader.array::<1>()?[0] {
+        SIGNATURE_NONE => Signature::None,
+        SIGNATURE_POST_QUANTUM => Signature::PostQuantum {
+            dilithium: reader.bytes("dilithium signature", MAX_SIGNATURE_LEN)?,
+            falcon: reader.bytes("falcon signature", MAX_SIGNATURE_LEN)?,
+            sphincs: reader.bytes("sphincs signature", MAX_SIGNATURE_LEN)?,
+        },
+        SIGNATURE_COINBASE => Signature::Coinbase,
+        other => return Err(format!("unknown signature tag {}", other)),
+    };
+    if reader.pos != data.len() {
+        return Err("trailing bytes after transaction".to_string());
+    }
+    let tx = Transaction { chain_id, from, to, amount, fee, nonce, timestamp, data: tx_data, signature_type, signature };
+    check(&tx)?;
+    Ok(tx)
+}
@@ -0,0 +1,73 @@
+// Variable-length integers of the block and network framing formats:
+//
+// - unsigned LEB128: 7 bits per byte, least significant group first, the high
+//   bit set on all bytes but the last
+// - compact size (Bitcoin's): below 0xfd one byte, else a 0xfd, 0xfe or 0xff
+//   marker followed by a little-endian u16, u32 or u64
+//
+// Readers only accept the minimal encoding of a value, so no value has two
+// encodings and a frame can't be altered without changing its bytes.
+
+pub fn put_uleb128(out: &mut Vec<u8>, mut n: u64) {
+    while n >= 0x80 {
+        out.push(n as u8 | 0x80);
+        n >>= 7;
+    }
+    out.push(n as u8);
+}
+
+/// The value at the start of `data` and the number of bytes it takes.
+pub fn read_uleb128(data: &[u8]) -> Result<(u64, usize), String> {
+    let mut n = 0u64;
+    for (i, byte) in data.iter().copied().enumerate().take(10) {
+        if i == 9 && byte > 1 {
+            return Err("LEB128 value overflows 64 bits".to_string());
+        }
+        n |= ((byte & 0x7f) as u64) << (7 * i);
+        if byte < 0x80 {
+            // A last byte of 0 adds nothing: the value had a shorter encoding
+            if byte == 0 && i > 0 {
+                return Err("LEB128 value not minimally encoded".to_string());
+            }
+            return Ok((n, i + 1));
+        }
+    }
+    Err("truncated LEB128 value".to_string())
+}
+
+pub fn put_compact_size(out: &mut Vec<u8>, n: u64) {
+    match n {
+        0..=0xfc => out.push(n as u8),
+        0xfd..=0xffff => {
+            out.push(0xfd);
+            out.extend_from_slice(&(n as u16).to_le_bytes());
+        }
+        0x1_0000..=0xffff_ffff => {
+            out.push(0xfe);
+            out.extend_from_slice(&(n as u32).to_le_bytes());
+        }
+        _ => {
+            out.push(0xff);
+            out.extend_from_slice(&n.to_le_bytes());
+        }
+    }
+}
+
+/// The value at the start of `data` and the number of bytes it takes.
+pub fn read_compact_size(data: &[u8]) -> Result<(u64, usize), String> {
+    let marker = *data.first().ok_or("truncated compact size")?;
+    let (len, min) = match marker {
+        0..=0xfc => return Ok((marker as u64, 1)),
+        0xfd => (2, 0xfd),
+        0xfe => (4, 0x1_0000),
+        _ => (8, 0x1_0000_0000),
+    };
+    let bytes = data.get(1..1 + len).ok_or("truncated compact size")?;
+    let mut value = [0u8; 8];
+    value[..len].copy_from_slice(bytes);
+    let n = u64::from_le_bytes(value);
+    if n < min {
+        return Err("compact size not minimally encoded".to_string());
+    }
+    Ok((n, 1 + len))
+}
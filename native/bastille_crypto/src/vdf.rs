@@ -0,0 +1,330 @@
+// Wesolowski verifiable delay function in the class group of binary
+// quadratic forms of discriminant -p, the randomness beacon's delay: nobody
+// can compute the output of a seed faster than `iterations` sequential
+// squarings, and anybody can check it in a few hundred.
+//
+// The 1024-bit prime p is derived from the seed, so there is no trusted
+// setup and no group order to know; p = 7 mod 8 makes (2, 1, (p + 1) / 8) a
+// form, the starting point x. Then
+//
+//   output y = x^(2^T)
+//   proof  π = x^floor(2^T / l), l a 256-bit prime hashed from (seed, T, y)
+//   valid iff π^l * x^(2^T mod l) = y
+//
+// Forms are reduced, so each class has one: a | b, 65-byte two's-complement
+// big-endian each (|b| <= a < 2^512). Evaluation costs 2T squarings, T for y
+// and T more for π once l is known.
+
+use num_bigint::{BigInt, BigUint, Sign};
+use num_integer::Integer;
+use num_traits::{One, Signed, Zero};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+pub const DISCRIMINANT_BITS: u64 = 1024;
+const PRIME_BITS: u64 = 256;
+const ELEMENT_LEN: usize = 65;
+pub const FORM_LEN: usize = 2 * ELEMENT_LEN;
+pub const MAX_SEED_LEN: usize = 1024;
+
+/// (output, proof)
+pub type Solution = ([u8; FORM_LEN], [u8; FORM_LEN]);
+
+// Miller-Rabin rounds, each with a base hashed from the candidate
+const PRIMALITY_ROUNDS: usize = 32;
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+struct Form {
+    a: BigInt,
+    b: BigInt,
+    c: BigInt,
+}
+
+fn small_primes() -> impl Iterator<Item = u32> {
+    (3u32..2000).step_by(2).filter(|n| (3..).step_by(2).take_while(|d| d * d <= *n).all(|d| n % d != 0))
+}
+
+// Miller-Rabin with bases drawn from `context` and the candidate, so they
+// can't be chosen ahead of the candidate
+fn is_probable_prime(n: &BigUint, context: &str) -> bool {
+    let two = BigUint::from(2u32);
+    if *n < two {
+        return false;
+    }
+    for p in small_primes().chain([2]) {
+        if *n == BigUint::from(p) {
+            return true;
+        }
+        if (n % p).is_zero() {
+            return false;
+        }
+    }
+    let n_minus_1 = n - 1u32;
+    let s = n_minus_1.trailing_zeros().unwrap_or(0);
+    let d = &n_minus_1 >> s;
+    let mut bases = blake3::Hasher::new_derive_key(context);
+    bases.update(&n.to_bytes_be());
+    let mut bases = bases.finalize_xof();
+    let mut buf = vec![0u8; n.to_bytes_be().len() + 8];
+    (0..PRIMALITY_ROUNDS).all(|_| {
+        bases.fill(&mut buf);
+        // A base in 2..n-2
+        let base = BigUint::from_bytes_be(&buf) % (n - 3u32) + 2u32;
+        let mut x = base.modpow(&d, n);
+        if x.is_one() || x == n_minus_1 {
+            return true;
+        }
+        for _ in 1..s {
+            x = x.modpow(&two, n);
+            if x == n_minus_1 {
+                return true;
+            }
+        }
+        false
+    })
+}
+
+// The first prime p = 7 mod 8 from a DISCRIMINANT_BITS-bit number hashed
+// from the seed
+fn discriminant(seed: &[u8]) -> BigInt {
+    let mut bytes = [0u8; (DISCRIMINANT_BITS / 8) as usize];
+    blake3::Hasher::new_derive_key("bastille vdf v1 discriminant").update(seed).finalize_xof().fill(&mut bytes);
+    bytes[0] |= 0x80;
+    let mut p = BigUint::from_bytes_be(&bytes) | BigUint::from(7u32);
+    while !is_probable_prime(&p, "bastille vdf v1 discriminant base") {
+        p += 8u32;
+    }
+    -BigInt::from(p)
+}
+
+fn challenge_prime(seed: &[u8], iterations: u64, output: &[u8]) -> BigUint {
+    let mut hasher = blake3::Hasher::new_derive_key("bastille vdf v1 prime");
+    hasher.update(&(seed.len() as u64).to_le_bytes());
+    hasher.update(seed);
+    hasher.update(&iterations.to_le_bytes());
+    hasher.update(output);
+    let mut bytes = [0u8; (PRIME_BITS / 8) as usize];
+    hasher.finalize_xof().fill(&mut bytes);
+    bytes[0] |= 0x80;
+    let mut l = BigUint::from_bytes_be(&bytes) | BigUint::one();
+    while !is_probable_prime(&l, "bastille vdf v1 prime base") {
+        l += 2u32;
+    }
+    l
+}
+
+// x, y with x*a + y*m = gcd; solves a*mu = b (mod m) as (mu, m / gcd)
+fn solve_linear_congruence(a: &BigInt, b: &BigInt, m: &BigInt) -> (BigInt, BigInt) {
+    let gcd = a.extended_gcd(m);
+    let mu = (b / &gcd.gcd * gcd.x).mod_floor(m);
+    (mu, m / gcd.gcd)
+}
+
+impl Form {
+    fn with_ab(a: BigInt, b: BigInt, discriminant: &BigInt) -> Option<Form> {
+        let (c, remainder) = (&b * &b - discriminant).div_rem(&(&a * 4));
+        remainder.is_zero().then_some(Form { a, b, c })
+    }
+
+    fn identity(discriminant: &BigInt) -> Form {
+        Form { a: BigInt::one(), b: BigInt::one(), c: (BigInt::one() - discriminant) / 4 }
+    }
+
+    fn generator(discriminant: &BigInt) -> Form {
+        let mut form = Form { a: BigInt::from(2), b: BigInt::one(), c: (BigInt::one() - discriminant) / 8 };
+        form.reduce();
+        form
+    }
+
+    fn normalize(&mut self) {
+        if -&self.a < self.b && self.b <= self.a {
+            return;
+        }
+        let r = (&self.a - &self.b).div_floor(&(&self.a * 2));
+        let ra = &r * &self.a;
+        self.c += (&ra + &self.b) * &r;
+        self.b += ra * 2;
+    }
+
+    fn reduce(&mut self) {
+        self.normalize();
+        while self.a > self.c || (self.a == self.c && self.b.is_negative()) {
+            let s = (&self.c + &self.b).div_floor(&(&self.c * 2));
+            std::mem::swap(&mut self.a, &mut self.c);
+            let b = &s * &self.a * 2 - &self.b;
+            self.c += &s * &s * &self.a - &s * &self.b;
+            self.b = b;
+        }
+        self.normalize();
+    }
+
+    fn is_reduced(&self) -> bool {
+        -&self.a < self.b && self.b <= self.a && self.a <= self.c && !(self.a == self.c && self.b.is_negative())
+    }
+
+    fn compose(&self, other: &Form) -> Form {
+        let g = (&self.b + &other.b) / 2;
+        let h = (&other.b - &self.b) / 2;
+        let w = self.a.gcd(&other.a).gcd(&g);
+        let (s, t, u) = (&self.a / &w, &other.a / &w, &g / &w);
+        let (mu, v) = solve_linear_congruence(&(&t * &u), &(&h * &u + &s * &self.c), &(&s * &t));
+        let (lambda, _) = solve_linear_congruence(&(&t * &v), &(&h - &t * &mu), &s);
+        let k = &mu + &v * &lambda;
+        let l = Integer::div_floor(&(&k * &t - &h), &s);
+        let m = Integer::div_floor(&(&t * &u * &k - &h * &u - &self.c * &s), &(&s * &t));
+        let mut form = Form { a: &s * &t, b: &w * &u - (&k * &t + &l * &s), c: &k * &l - &w * &m };
+        form.reduce();
+        form
+    }
+
+    fn square(&self) -> Form {
+        let (mu, _) = solve_linear_congruence(&self.b, &self.c, &self.a);
+        let m = (&self.b * &mu - &self.c).div_floor(&self.a);
+        let mut form = Form { a: &self.a * &self.a, b: &self.b - &mu * &self.a * 2, c: &mu * &mu - m };
+        form.reduce();
+        form
+    }
+
+    fn pow(&self, exponent: &BigUint, discriminant: &BigInt) -> Form {
+        let mut result = Form::identity(discriminant);
+        for i in (0..exponent.bits()).rev() {
+            result = result.square();
+            if exponent.bit(i) {
+                result = result.compose(self);
+            }
+        }
+        result
+    }
+
+    fn to_bytes(&self) -> [u8; FORM_LEN] {
+        let mut bytes = [0u8; FORM_LEN];
+        for (value, chunk) in [&self.a, &self.b].into_iter().zip(bytes.chunks_exact_mut(ELEMENT_LEN)) {
+            let be = value.to_signed_bytes_be();
+            let fill = if value.is_negative() { 0xff } else { 0 };
+            chunk[..ELEMENT_LEN - be.len()].fill(fill);
+            chunk[ELEMENT_LEN - be.len()..].copy_from_slice(&be);
+        }
+        bytes
+    }
+
+    // Only the reduced form of each class, so an output has one encoding
+    fn from_bytes(bytes: &[u8], discriminant: &BigInt) -> Option<Form> {
+        if bytes.len() != FORM_LEN {
+            return None;
+        }
+        let a = BigInt::from_signed_bytes_be(&bytes[..ELEMENT_LEN]);
+        let b = BigInt::from_signed_bytes_be(&bytes[ELEMENT_LEN..]);
+        if a.sign() != Sign::Plus {
+            return None;
+        }
+        Form::with_ab(a, b, discriminant).filter(Form::is_reduced)
+    }
+}
+
+/// (output, proof) of `iterations` squarings from the seed's starting form.
+/// `tick` is called after each squaring, and stops the evaluation if it
+/// returns false.
+pub fn eval(seed: &[u8], iterations: u64, mut tick: impl FnMut() -> bool) -> Option<Solution> {
+    let discriminant = discriminant(seed);
+    let x = Form::generator(&discriminant);
+    let mut y = x.clone();
+    for _ in 0..iterations {
+        y = y.square();
+        if !tick() {
+            return None;
+        }
+    }
+    let output = y.to_bytes();
+
+    // Long division of 2^T by l, one quotient bit per squaring
+    let l = challenge_prime(seed, iterations, &output);
+    let mut proof = Form::identity(&discriminant);
+    let mut remainder = BigUint::one();
+    for _ in 0..iterations {
+        remainder <<= 1;
+        proof = proof.square();
+        if remainder >= l {
+            remainder -= &l;
+            proof = proof.compose(&x);
+        }
+        if !tick() {
+            return None;
+        }
+    }
+    Some((output, proof.to_bytes()))
+}
+
+pub fn verify(seed: &[u8], iterations: u64, output: &[u8], proof: &[u8]) -> bool {
+    let discriminant = discriminant(seed);
+    let (Some(y), Some(proof)) = (Form::from_bytes(output, &discriminant), Form::from_bytes(proof, &discriminant)) else {
+        return false;
+    };
+    let x = Form::generator(&discriminant);
+    let l = challenge_prime(seed, iterations, output);
+    let r = BigUint::from(2u32).modpow(&BigUint::from(iterations), &l);
+    proof.pow(&l, &discriminant).compose(&x.pow(&r, &discriminant)) == y
+}
+
+// Shared between an Evaluation handle and its thread
+struct Progress {
+    done: AtomicU64,
+    cancelled: AtomicBool,
+    result: Mutex<Option<Result<Solution, String>>>,
+}
+
+pub enum Status {
+    Running { done: u64, total: u64 },
+    Done(Box<Solution>),
+    Failed(String),
+}
+
+/// An evaluation on its own thread, which can run for minutes: longer than
+/// a dirty scheduler should be held, and than the crypto pool can spare.
+/// Dropping the handle cancels it.
+pub struct Evaluation {
+    total: u64,
+    progress: Arc<Progress>,
+}
+
+#[rustler::resource_impl]
+impl rustler::Resource for Evaluation {}
+
+impl Evaluation {
+    pub fn start(seed: Vec<u8>, iterations: u64) -> Result<Self, String> {
+        let progress = Arc::new(Progress { done: AtomicU64::new(0), cancelled: AtomicBool::new(false), result: Mutex::new(None) });
+        let shared = progress.clone();
+        std::thread::Builder::new()
+            .name("bastille-vdf".to_string())
+            .spawn(move || {
+                let result = eval(&seed, iterations, || {
+                    shared.done.fetch_add(1, Ordering::Relaxed);
+                    !shared.cancelled.load(Ordering::Relaxed)
+                })
+                .ok_or_else(|| "cancelled".to_string());
+                if let Ok(mut slot) = shared.result.lock() {
+                    *slot = Some(result);
+                }
+            })
+            .map_err(|e| format!("cannot start VDF thread: {}", e))?;
+        Ok(Evaluation { total: iterations.saturating_mul(2), progress })
+    }
+
+    pub fn status(&self) -> Status {
+        match self.progress.result.lock().map(|slot| slot.clone()) {
+            Ok(Some(Ok(solution))) => Status::Done(Box::new(solution)),
+            Ok(Some(Err(reason))) => Status::Failed(reason),
+            Ok(None) => Status::Running { done: self.progress.done.load(Ordering::Relaxed), total: self.total },
+            Err(_) => Status::Failed("VDF result lock poisoned".to_string()),
+        }
+    }
+
+    pub fn cancel(&self) {
+        self.progress.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
+impl Drop for Evaluation {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
@@ -0,0 +1,110 @@
+// Bounded LRU of signatures already verified as valid, so a transaction seen in
+// gossip, the mempool, block validation and re-org replay is checked once.
+//
+// Entries are keyed by a BLAKE3 digest of
+//   algorithm id | sig_len u32 | signature | blake3(message) | public key
+// and only valid results are stored: invalid signatures are cheap to produce,
+// and caching them would let anyone flush the useful entries.
+//
+// The cache is off (capacity 0) until configure() is called.
+
+use crate::keygen::Algorithm;
+use lru::LruCache;
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+
+// Upper bound on entries so a bad setting can't preallocate gigabytes
+pub const MAX_CAPACITY: usize = 1 << 22;
+
+lazy_static::lazy_static! {
+    static ref CACHE: Mutex<Option<LruCache<[u8; 32], ()>>> = Mutex::new(None);
+}
+
+// Skips hashing and locking entirely while the cache is off
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static HITS: AtomicU64 = AtomicU64::new(0);
+static MISSES: AtomicU64 = AtomicU64::new(0);
+
+pub struct VerifyCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub entries: usize,
+    pub capacity: usize,
+}
+
+/// Resize the cache, keeping the most recent entries. 0 turns it off and empties it.
+pub fn configure(capacity: usize) -> Result<(), String> {
+    let mut cache = CACHE.lock().map_err(|_| "verify cache lock poisoned")?;
+    match NonZeroUsize::new(capacity) {
+        Some(capacity) => match cache.as_mut() {
+            Some(cache) => cache.resize(capacity),
+            None => *cache = Some(LruCache::new(capacity)),
+        },
+        None => *cache = None,
+    }
+    ENABLED.store(cache.is_some(), Ordering::Release);
+    Ok(())
+}
+
+/// Drop every cached result, e.g. after a validator key is revoked.
+pub fn clear() {
+    if let Ok(mut cache) = CACHE.lock() {
+        if let Some(cache) = cache.as_mut() {
+            cache.clear();
+        }
+    }
+}
+
+pub fn stats() -> VerifyCacheStats {
+    let (entries, capacity) = match CACHE.lock() {
+        Ok(cache) => cache.as_ref().map_or((0, 0), |cache| (cache.len(), cache.cap().get())),
+        Err(_) => (0, 0),
+    };
+    VerifyCacheStats {
+        hits: HITS.load(Ordering::Relaxed),
+        misses: MISSES.load(Ordering::Relaxed),
+        entries,
+        capacity,
+    }
+}
+
+fn entry_key(algorithm: Algorithm, signature: &[u8], message: &[u8], public_key: &[u8]) -> [u8; 32] {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(&[algorithm.id()]);
+    hasher.update(&(signature.len() as u32).to_be_bytes());
+    hasher.update(signature);
+    hasher.update(blake3::hash(message).as_bytes());
+    hasher.update(public_key);
+    *hasher.finalize().as_bytes()
+}
+
+/// Run `verify` unless this exact (signature, message, key) already passed.
+pub fn verify_with<F>(algorithm: Algorithm, signature: &[u8], message: &[u8], public_key: &[u8], verify: F) -> bool
+where
+    F: FnOnce() -> bool,
+{
+    if !ENABLED.load(Ordering::Acquire) {
+        return verify();
+    }
+
+    let key = entry_key(algorithm, signature, message, public_key);
+    if let Ok(mut cache) = CACHE.lock() {
+        if cache.as_mut().is_some_and(|cache| cache.get(&key).is_some()) {
+            HITS.fetch_add(1, Ordering::Relaxed);
+            return true;
+        }
+    }
+    MISSES.fetch_add(1, Ordering::Relaxed);
+
+    // Verify outside the lock so batch verification still runs in parallel
+    let valid = verify();
+    if valid {
+        if let Ok(mut cache) = CACHE.lock() {
+            if let Some(cache) = cache.as_mut() {
+                cache.put(key, ());
+            }
+        }
+    }
+    valid
+}
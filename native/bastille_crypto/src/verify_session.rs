@@ -0,0 +1,52 @@
+// A validator set pinned for block-level verification: the algorithm and the
+// public keys are parsed once when the session is opened, and each item then
+// names its signer by index, so thousands of attestations can be checked
+// without re-sending or re-parsing a key per signature.
+
+use crate::keygen::Algorithm;
+use crate::public_key::ParsedPublicKey;
+use crate::{threads, verify_cache};
+use rayon::prelude::*;
+
+// (message, signature, key index)
+pub type SessionItem<'a> = (&'a [u8], &'a [u8], usize);
+
+pub struct VerifySession {
+    algorithm: Algorithm,
+    keys: Vec<ParsedPublicKey>,
+}
+
+#[rustler::resource_impl]
+impl rustler::Resource for VerifySession {}
+
+impl VerifySession {
+    /// None if any key is not a public key of `algorithm`.
+    pub fn new(algorithm: Algorithm, public_keys: &[&[u8]]) -> Option<Self> {
+        let keys = public_keys
+            .iter()
+            .map(|public_key| ParsedPublicKey::parse(algorithm, public_key))
+            .collect::<Option<Vec<_>>>()?;
+        Some(VerifySession { algorithm, keys })
+    }
+
+    pub fn size(&self) -> usize {
+        self.keys.len()
+    }
+
+    fn verify(&self, (message, signature, index): &SessionItem) -> bool {
+        let key = &self.keys[*index];
+        verify_cache::verify_with(self.algorithm, signature, message, key.as_bytes(), || key.verify(signature, message))
+    }
+
+    /// Indices of the items that fail to verify. Key indices must be in range.
+    pub fn failures(&self, items: &[SessionItem]) -> Vec<usize> {
+        threads::install(|| {
+            items
+                .par_iter()
+                .enumerate()
+                .filter(|(_, item)| !self.verify(item))
+                .map(|(position, _)| position)
+                .collect()
+        })
+    }
+}
@@ -0,0 +1,420 @@
+// Experimental Verkle tree, for comparing witness sizes against the sparse
+// Merkle tree's proofs. Built only with the `verkle` feature; in memory only,
+// and its commitments and proofs are a format of its own, not EIP-6800's.
+//
+// A width-256 trie over the same BLAKE3 key paths as smt.rs, one path byte
+// per level. Every internal node is a Pedersen vector commitment to its 256
+// children, C = sum(v_i * G_i), where v_i is 0 for an empty slot and the
+// field element of the child otherwise. A leaf is the commitment to
+// [1, path, blake3(value)] (each hash split into two 128-bit halves), and
+// sits directly below the shortest prefix of its path no other key shares.
+// A point's field element is blake3 of its compressed encoding, reduced.
+//
+// The group is BN254's G1, whose scalar field the Poseidon code already
+// uses; Ethereum's Verkle design uses Bandersnatch instead. Generators are
+// hashed to the curve by try-and-increment, so nobody knows their discrete
+// logs relative to each other.
+//
+// The root is the compressed root commitment (32 bytes). A proof is
+//   u8 opening count | per opening: the node's commitment (32 bytes, omitted
+//   for the root) | IPA proof | terminal
+// where the terminal is 0x00 for an empty slot, or 0x01 | path | blake3(value)
+// for the leaf found there, as in smt.rs. Each opening shows that the node's
+// slot on the key's path holds the next node's field element, or the
+// terminal's. An IPA proof (Bulletproofs inner product argument, without
+// blinding, Fiat-Shamir over BLAKE3) is 8 rounds of L | R (32 bytes each)
+// and the final scalar (32 bytes LE). Openings aren't aggregated into one
+// multiproof, so witnesses come out larger than a production Verkle tree's.
+
+use crate::smt::{key_path, Hash, TERMINAL_EMPTY, TERMINAL_LEAF};
+use ark_bn254::{Fq, Fr, G1Affine, G1Projective};
+use ark_ec::{CurveGroup, VariableBaseMSM};
+use ark_ff::{Field, PrimeField, Zero};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use std::sync::Mutex;
+
+const WIDTH: usize = 256;
+const ROUNDS: usize = 8;
+const POINT_LEN: usize = 32;
+const IPA_PROOF_LEN: usize = ROUNDS * 2 * POINT_LEN + 32;
+
+lazy_static::lazy_static! {
+    // G_0..G_255 for the children, then Q's base for the inner product
+    static ref GENERATORS: Vec<G1Affine> = (0..=WIDTH as u32).map(generator).collect();
+}
+
+fn generator(index: u32) -> G1Affine {
+    let mut counter = 0u32;
+    loop {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(b"bastille verkle generator");
+        hasher.update(&index.to_be_bytes());
+        hasher.update(&counter.to_be_bytes());
+        let x = Fq::from_be_bytes_mod_order(hasher.finalize().as_bytes());
+        // The cofactor is 1, so any point on the curve will do
+        if let Some(point) = G1Affine::get_point_from_x_unchecked(x, true) {
+            return point;
+        }
+        counter += 1;
+    }
+}
+
+fn compress(point: &G1Affine) -> [u8; POINT_LEN] {
+    let mut bytes = [0u8; POINT_LEN];
+    point.serialize_compressed(&mut bytes[..]).unwrap();
+    bytes
+}
+
+fn decompress(bytes: &[u8]) -> Option<G1Affine> {
+    G1Affine::deserialize_compressed(bytes).ok()
+}
+
+fn point_field(point: &G1Affine) -> Fr {
+    Fr::from_le_bytes_mod_order(blake3::hash(&compress(point)).as_bytes())
+}
+
+// A 32-byte hash as two field elements, low half first
+fn hash_fields(hash: &Hash) -> [Fr; 2] {
+    [Fr::from_le_bytes_mod_order(&hash[..16]), Fr::from_le_bytes_mod_order(&hash[16..])]
+}
+
+fn leaf_field(path: &Hash, value_hash: &Hash) -> Fr {
+    let [path_lo, path_hi] = hash_fields(path);
+    let [value_lo, value_hi] = hash_fields(value_hash);
+    let scalars = [Fr::from(1u8), path_lo, path_hi, value_lo, value_hi];
+    let commitment = G1Projective::msm_unchecked(&GENERATORS[..scalars.len()], &scalars);
+    point_field(&commitment.into_affine())
+}
+
+// Inner product argument that <a, e_index> = a[index] = y for the vector a
+// behind a commitment
+
+fn transcript(commitment: &G1Affine, index: u8, y: &Fr) -> blake3::Hasher {
+    let mut transcript = blake3::Hasher::new();
+    transcript.update(b"bastille verkle ipa");
+    transcript.update(&compress(commitment));
+    transcript.update(&[index]);
+    let mut y_bytes = [0u8; 32];
+    y.serialize_compressed(&mut y_bytes[..]).unwrap();
+    transcript.update(&y_bytes);
+    transcript
+}
+
+fn challenge(transcript: &blake3::Hasher) -> Fr {
+    Fr::from_le_bytes_mod_order(transcript.finalize().as_bytes())
+}
+
+fn ipa_prove(a: &[Fr], commitment: &G1Affine, index: u8) -> Vec<u8> {
+    let mut transcript = transcript(commitment, index, &a[index as usize]);
+    let q = GENERATORS[WIDTH] * challenge(&transcript);
+    let mut a = a.to_vec();
+    let mut b = vec![Fr::zero(); WIDTH];
+    b[index as usize] = Fr::from(1u8);
+    let mut g: Vec<G1Affine> = GENERATORS[..WIDTH].to_vec();
+
+    let mut proof = Vec::with_capacity(IPA_PROOF_LEN);
+    while a.len() > 1 {
+        let half = a.len() / 2;
+        let (a_lo, a_hi) = a.split_at(half);
+        let (b_lo, b_hi) = b.split_at(half);
+        let (g_lo, g_hi) = g.split_at(half);
+        let z_l: Fr = a_hi.iter().zip(b_lo).map(|(a, b)| *a * b).sum();
+        let z_r: Fr = a_lo.iter().zip(b_hi).map(|(a, b)| *a * b).sum();
+        let l = (G1Projective::msm_unchecked(g_lo, a_hi) + q * z_l).into_affine();
+        let r = (G1Projective::msm_unchecked(g_hi, a_lo) + q * z_r).into_affine();
+        for point in [l, r] {
+            let bytes = compress(&point);
+            transcript.update(&bytes);
+            proof.extend_from_slice(&bytes);
+        }
+        let x = challenge(&transcript);
+        let x_inv = x.inverse().unwrap();
+        a = a_lo.iter().zip(a_hi).map(|(lo, hi)| *lo + x * hi).collect();
+        b = b_lo.iter().zip(b_hi).map(|(lo, hi)| *lo + x_inv * hi).collect();
+        let folded: Vec<G1Projective> = g_lo.iter().zip(g_hi).map(|(lo, hi)| *hi * x_inv + lo).collect();
+        g = G1Projective::normalize_batch(&folded);
+    }
+    let mut final_a = [0u8; 32];
+    a[0].serialize_compressed(&mut final_a[..]).unwrap();
+    proof.extend_from_slice(&final_a);
+    proof
+}
+
+fn ipa_verify(commitment: &G1Affine, index: u8, y: &Fr, proof: &[u8]) -> bool {
+    if proof.len() != IPA_PROOF_LEN {
+        return false;
+    }
+    let mut transcript = transcript(commitment, index, y);
+    let q = GENERATORS[WIDTH] * challenge(&transcript);
+    let mut folded = q * y + commitment;
+    let mut inverses = [Fr::zero(); ROUNDS];
+    for (round, pair) in proof[..ROUNDS * 2 * POINT_LEN].chunks_exact(2 * POINT_LEN).enumerate() {
+        let (Some(l), Some(r)) = (decompress(&pair[..POINT_LEN]), decompress(&pair[POINT_LEN..])) else {
+            return false;
+        };
+        transcript.update(pair);
+        let x = challenge(&transcript);
+        let Some(x_inv) = x.inverse() else {
+            return false;
+        };
+        folded += l * x + r * x_inv;
+        inverses[round] = x_inv;
+    }
+    let Ok(a) = Fr::deserialize_compressed(&proof[ROUNDS * 2 * POINT_LEN..]) else {
+        return false;
+    };
+
+    // Generator i ends up weighted by the inverse challenge of every round
+    // in which it sat in the upper half, and so does b = e_index
+    let weights: Vec<Fr> = (0..WIDTH)
+        .map(|i| {
+            (0..ROUNDS)
+                .filter(|round| i >> (ROUNDS - 1 - round) & 1 == 1)
+                .map(|round| inverses[round])
+                .product()
+        })
+        .collect();
+    let g = G1Projective::msm_unchecked(&GENERATORS[..WIDTH], &weights);
+    folded == g * a + q * (a * weights[index as usize])
+}
+
+struct Leaf {
+    path: Hash,
+    value: Vec<u8>,
+    value_hash: Hash,
+    field: Fr,
+}
+
+struct Internal {
+    children: Vec<Option<Node>>,
+    commitment: G1Projective,
+}
+
+enum Node {
+    Leaf(Leaf),
+    Internal(Box<Internal>),
+}
+
+impl Leaf {
+    fn new(path: Hash, value: &[u8]) -> Self {
+        let value_hash = *blake3::hash(value).as_bytes();
+        Leaf { path, value: value.to_vec(), value_hash, field: leaf_field(&path, &value_hash) }
+    }
+}
+
+impl Node {
+    fn field(&self) -> Fr {
+        match self {
+            Node::Leaf(leaf) => leaf.field,
+            Node::Internal(internal) => point_field(&internal.commitment.into_affine()),
+        }
+    }
+}
+
+fn slot_field(slot: &Option<Node>) -> Fr {
+    slot.as_ref().map_or(Fr::zero(), Node::field)
+}
+
+impl Internal {
+    fn new() -> Self {
+        Internal { children: (0..WIDTH).map(|_| None).collect(), commitment: G1Projective::zero() }
+    }
+
+    // Replace the child at `index` through `f`, keeping the commitment in
+    // step by the change in that one slot
+    fn update<R>(&mut self, index: u8, f: impl FnOnce(&mut Option<Node>) -> R) -> R {
+        let slot = &mut self.children[index as usize];
+        let before = slot_field(slot);
+        let result = f(slot);
+        let after = slot_field(slot);
+        self.commitment += GENERATORS[index as usize] * (after - before);
+        result
+    }
+
+    fn insert(&mut self, depth: usize, leaf: Leaf) {
+        self.update(leaf.path[depth], |slot| match slot.take() {
+            None => *slot = Some(Node::Leaf(leaf)),
+            Some(Node::Leaf(existing)) if existing.path == leaf.path => *slot = Some(Node::Leaf(leaf)),
+            Some(Node::Leaf(existing)) => {
+                let mut internal = Internal::new();
+                internal.insert(depth + 1, existing);
+                internal.insert(depth + 1, leaf);
+                *slot = Some(Node::Internal(Box::new(internal)));
+            }
+            Some(Node::Internal(mut internal)) => {
+                internal.insert(depth + 1, leaf);
+                *slot = Some(Node::Internal(internal));
+            }
+        })
+    }
+
+    // Returns whether the key was present. A node left with a single leaf
+    // below it is replaced by that leaf, so the shape, and the root, only
+    // depend on the keys present.
+    fn delete(&mut self, depth: usize, path: &Hash) -> bool {
+        self.update(path[depth], |slot| match slot {
+            None => false,
+            Some(Node::Leaf(leaf)) => {
+                let found = leaf.path == *path;
+                if found {
+                    *slot = None;
+                }
+                found
+            }
+            Some(Node::Internal(internal)) => {
+                let found = internal.delete(depth + 1, path);
+                if let Some(only) = internal.sole_leaf() {
+                    *slot = Some(Node::Leaf(only));
+                }
+                found
+            }
+        })
+    }
+
+    fn sole_leaf(&mut self) -> Option<Leaf> {
+        let mut occupied = self.children.iter().enumerate().filter(|(_, child)| child.is_some());
+        let (index, child) = occupied.next()?;
+        if occupied.next().is_some() || !matches!(child, Some(Node::Leaf(_))) {
+            return None;
+        }
+        match self.children[index].take() {
+            Some(Node::Leaf(leaf)) => Some(leaf),
+            _ => None,
+        }
+    }
+}
+
+pub struct VerkleTree {
+    root: Mutex<Internal>,
+}
+
+#[rustler::resource_impl]
+impl rustler::Resource for VerkleTree {}
+
+impl VerkleTree {
+    pub fn new() -> Self {
+        VerkleTree { root: Mutex::new(Internal::new()) }
+    }
+
+    fn lock(&self) -> Result<std::sync::MutexGuard<'_, Internal>, String> {
+        self.root.lock().map_err(|_| "verkle tree lock poisoned".to_string())
+    }
+
+    pub fn get<R>(&self, key: &[u8], f: impl FnOnce(Option<&[u8]>) -> R) -> Result<R, String> {
+        let path = key_path(key);
+        let root = self.lock()?;
+        let mut node = &*root;
+        for byte in path {
+            match &node.children[byte as usize] {
+                Some(Node::Internal(internal)) => node = internal,
+                Some(Node::Leaf(leaf)) if leaf.path == path => return Ok(f(Some(&leaf.value))),
+                _ => break,
+            }
+        }
+        Ok(f(None))
+    }
+
+    pub fn put(&self, key: &[u8], value: &[u8]) -> Result<(), String> {
+        let leaf = Leaf::new(key_path(key), value);
+        self.lock()?.insert(0, leaf);
+        Ok(())
+    }
+
+    pub fn delete(&self, key: &[u8]) -> Result<(), String> {
+        self.lock()?.delete(0, &key_path(key));
+        Ok(())
+    }
+
+    pub fn root(&self) -> Result<[u8; POINT_LEN], String> {
+        Ok(compress(&self.lock()?.commitment.into_affine()))
+    }
+
+    pub fn prove(&self, key: &[u8]) -> Result<Vec<u8>, String> {
+        let path = key_path(key);
+        let root = self.lock()?;
+        let mut proof = vec![0u8];
+        let mut node = &*root;
+        for (depth, byte) in path.into_iter().enumerate() {
+            let commitment = node.commitment.into_affine();
+            if depth > 0 {
+                proof.extend_from_slice(&compress(&commitment));
+            }
+            let values: Vec<Fr> = node.children.iter().map(slot_field).collect();
+            proof.extend_from_slice(&ipa_prove(&values, &commitment, byte));
+            proof[0] += 1;
+            match &node.children[byte as usize] {
+                Some(Node::Internal(internal)) => node = internal,
+                Some(Node::Leaf(leaf)) => {
+                    proof.push(TERMINAL_LEAF);
+                    proof.extend_from_slice(&leaf.path);
+                    proof.extend_from_slice(&leaf.value_hash);
+                    return Ok(proof);
+                }
+                None => break,
+            }
+        }
+        proof.push(TERMINAL_EMPTY);
+        Ok(proof)
+    }
+}
+
+/// Check `proof` that `key` holds `value` (Some) or is absent (None) under `root`.
+pub fn verify_proof(root: &[u8], key: &[u8], value: Option<&[u8]>, proof: &[u8]) -> bool {
+    let (Some(root), Some((&count, mut rest))) = (decompress(root), proof.split_first()) else {
+        return false;
+    };
+    let count = count as usize;
+    if count == 0 || count > 32 {
+        return false;
+    }
+    let path = key_path(key);
+
+    // Commitments and openings, root first
+    let mut openings = Vec::with_capacity(count);
+    for depth in 0..count {
+        let commitment = if depth == 0 {
+            root
+        } else {
+            let Some(commitment) = rest.get(..POINT_LEN).and_then(decompress) else {
+                return false;
+            };
+            rest = &rest[POINT_LEN..];
+            commitment
+        };
+        let Some(ipa) = rest.get(..IPA_PROOF_LEN) else {
+            return false;
+        };
+        rest = &rest[IPA_PROOF_LEN..];
+        openings.push((commitment, ipa));
+    }
+
+    let terminal = match rest {
+        [TERMINAL_EMPTY] if value.is_none() => Fr::zero(),
+        [TERMINAL_LEAF, leaf @ ..] if leaf.len() == 64 => {
+            let leaf_path: Hash = leaf[..32].try_into().unwrap();
+            let value_hash: Hash = leaf[32..].try_into().unwrap();
+            // The leaf must sit below the slot the key's path leads to
+            if leaf_path[..count] != path[..count] {
+                return false;
+            }
+            let consistent = match value {
+                Some(value) => leaf_path == path && value_hash == *blake3::hash(value).as_bytes(),
+                None => leaf_path != path,
+            };
+            if !consistent {
+                return false;
+            }
+            leaf_field(&leaf_path, &value_hash)
+        }
+        _ => return false,
+    };
+
+    (0..count).all(|depth| {
+        let (commitment, ipa) = &openings[depth];
+        let y = match openings.get(depth + 1) {
+            Some((next, _)) => point_field(next),
+            None => terminal,
+        };
+        ipa_verify(commitment, path[depth], &y, ipa)
+    })
+}
@@ -0,0 +1,103 @@
+// Consensus votes: a validator's signature for a block at a height and round,
+//
+//   version u8 | height u64 | round u32 | block id 32 | algorithm u8
+//     | public key | signature
+//
+// with integers big-endian and the public key and signature prefixed with
+// their length as an unsigned LEB128 varint. The validator signs message(),
+// a domain-separated hash of the height, round and block id, never the raw
+// block id, so a vote can't be replayed as a header or finality signature.
+//
+// Decoding is strict, so a vote has exactly one encoding.
+
+use crate::keygen::Algorithm;
+use crate::varint;
+
+pub const VERSION: u8 = 1;
+pub const BLOCK_ID_LEN: usize = 32;
+pub const MAX_PUBLIC_KEY_LEN: usize = crate::header::MAX_PUBLIC_KEY_LEN;
+pub const MAX_SIGNATURE_LEN: usize = crate::tx::MAX_SIGNATURE_LEN;
+
+const MESSAGE_CONTEXT: &str = "bastille consensus v1 vote";
+
+pub struct Vote {
+    pub height: u64,
+    pub round: u32,
+    pub block_id: [u8; BLOCK_ID_LEN],
+    pub algorithm: Algorithm,
+    pub public_key: Vec<u8>,
+    pub signature: Vec<u8>,
+}
+
+/// What a validator signs to vote for `block_id` at `height` and `round`.
+pub fn message(height: u64, round: u32, block_id: &[u8; BLOCK_ID_LEN]) -> [u8; 32] {
+    let mut hasher = blake3::Hasher::new_derive_key(MESSAGE_CONTEXT);
+    hasher.update(&height.to_be_bytes());
+    hasher.update(&round.to_be_bytes());
+    hasher.update(block_id);
+    *hasher.finalize().as_bytes()
+}
+
+fn put_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    varint::put_uleb128(out, bytes.len() as u64);
+    out.extend_from_slice(bytes);
+}
+
+pub fn encode(vote: &Vote) -> Result<Vec<u8>, String> {
+    if vote.public_key.is_empty() || vote.public_key.len() > MAX_PUBLIC_KEY_LEN {
+        return Err(format!("public key length {} outside 1..{}", vote.public_key.len(), MAX_PUBLIC_KEY_LEN));
+    }
+    if vote.signature.is_empty() || vote.signature.len() > MAX_SIGNATURE_LEN {
+        return Err(format!("signature length {} outside 1..{}", vote.signature.len(), MAX_SIGNATURE_LEN));
+    }
+    let mut out = Vec::with_capacity(64 + vote.public_key.len() + vote.signature.len());
+    out.push(VERSION);
+    out.extend_from_slice(&vote.height.to_be_bytes());
+    out.extend_from_slice(&vote.round.to_be_bytes());
+    out.extend_from_slice(&vote.block_id);
+    out.push(vote.algorithm.id());
+    put_bytes(&mut out, &vote.public_key);
+    put_bytes(&mut out, &vote.signature);
+    Ok(out)
+}
+
+fn take<'a>(data: &'a [u8], pos: &mut usize, len: usize) -> Result<&'a [u8], String> {
+    let bytes = data.get(*pos..*pos + len).ok_or("truncated vote")?;
+    *pos += len;
+    Ok(bytes)
+}
+
+fn take_bytes<'a>(data: &'a [u8], pos: &mut usize, what: &str, max: usize) -> Result<&'a [u8], String> {
+    let (len, size) = varint::read_uleb128(&data[*pos..])?;
+    *pos += size;
+    if len == 0 || len > max as u64 {
+        return Err(format!("{} length {} outside 1..{}", what, len, max));
+    }
+    take(data, pos, len as usize)
+}
+
+pub fn decode(data: &[u8]) -> Result<Vote, String> {
+    let mut pos = 0;
+    let version = take(data, &mut pos, 1)?[0];
+    if version != VERSION {
+        return Err(format!("unsupported vote format version {}", version));
+    }
+    let height = u64::from_be_bytes(take(data, &mut pos, 8)?.try_into().unwrap_or([0; 8]));
+    let round = u32::from_be_bytes(take(data, &mut pos, 4)?.try_into().unwrap_or([0; 4]));
+    let block_id = take(data, &mut pos, BLOCK_ID_LEN)?.try_into().unwrap_or([0; BLOCK_ID_LEN]);
+    let id = take(data, &mut pos, 1)?[0];
+    let algorithm = Algorithm::from_id(id).ok_or(format!("unknown signature algorithm {}", id))?;
+    let public_key = take_bytes(data, &mut pos, "public key", MAX_PUBLIC_KEY_LEN)?.to_vec();
+    let signature = take_bytes(data, &mut pos, "signature", MAX_SIGNATURE_LEN)?.to_vec();
+    if pos != data.len() {
+        return Err("trailing bytes after vote".to_string());
+    }
+    Ok(Vote { height, round, block_id, algorithm, public_key, signature })
+}
+
+impl Vote {
+    pub fn verify(&self) -> bool {
+        let message = message(self.height, self.round, &self.block_id);
+        crate::public_key::verify_bytes(self.algorithm, &self.public_key, &self.signature, &message)
+    }
+}
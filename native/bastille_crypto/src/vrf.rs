@@ -0,0 +1,139 @@
+// ECVRF-EDWARDS25519-SHA512-TAI (RFC 9381, suite 0x03): a validator proves,
+// from its Ed25519 secret key and a public input such as the epoch seed, a
+// 64-byte output anyone holding the public key can check, and that nobody
+// can predict without the key or bias by choosing among several.
+//
+//   proof = Gamma 32 | c 16 | s 32
+//
+// Keys are RFC 8032 ones (the secret a 32-byte seed), but a VRF key shouldn't
+// also sign: keep it apart from any Ed25519 signing key.
+
+use curve25519_dalek::edwards::{CompressedEdwardsY, EdwardsPoint};
+use curve25519_dalek::scalar::{clamp_integer, Scalar};
+use sha2::{Digest, Sha512};
+use zeroize::Zeroizing;
+
+pub const SECRET_KEY_LEN: usize = 32;
+pub const PUBLIC_KEY_LEN: usize = 32;
+pub const PROOF_LEN: usize = 80;
+pub const OUTPUT_LEN: usize = 64;
+
+const SUITE: u8 = 0x03;
+const CHALLENGE_LEN: usize = 16;
+
+// The secret scalar x and the nonce prefix, halves of SHA-512(secret key)
+fn expand(secret_key: &[u8; SECRET_KEY_LEN]) -> (Zeroizing<Scalar>, Zeroizing<[u8; 32]>) {
+    let digest = Zeroizing::new(<[u8; 64]>::from(Sha512::digest(secret_key)));
+    let mut low = Zeroizing::new([0u8; 32]);
+    let mut prefix = Zeroizing::new([0u8; 32]);
+    low.copy_from_slice(&digest[..32]);
+    prefix.copy_from_slice(&digest[32..]);
+    let x = Zeroizing::new(Scalar::from_bytes_mod_order(clamp_integer(*low)));
+    (x, prefix)
+}
+
+pub fn public_key(secret_key: &[u8; SECRET_KEY_LEN]) -> [u8; PUBLIC_KEY_LEN] {
+    let (x, _) = expand(secret_key);
+    EdwardsPoint::mul_base(&x).compress().to_bytes()
+}
+
+// RFC 8032 decoding, which refuses the non-canonical encodings dalek accepts
+fn decode_point(bytes: &[u8]) -> Option<EdwardsPoint> {
+    let compressed = CompressedEdwardsY::from_slice(bytes).ok()?;
+    let point = compressed.decompress()?;
+    (point.compress() == compressed).then_some(point)
+}
+
+// Try-and-increment: the first counter whose hash decodes as a point, that
+// point's multiple by the cofactor. Half of all counters succeed, so failing
+// all 256 doesn't happen in practice.
+fn encode_to_curve(public_key: &[u8], alpha: &[u8]) -> Result<EdwardsPoint, String> {
+    (0..=u8::MAX)
+        .find_map(|ctr| {
+            let hash = Sha512::new()
+                .chain_update([SUITE, 0x01])
+                .chain_update(public_key)
+                .chain_update(alpha)
+                .chain_update([ctr, 0x00])
+                .finalize();
+            decode_point(&hash[..32]).map(|point| point.mul_by_cofactor())
+        })
+        .ok_or_else(|| "no curve point for this input".to_string())
+}
+
+fn challenge(points: [&EdwardsPoint; 5]) -> [u8; CHALLENGE_LEN] {
+    let mut hasher = Sha512::new().chain_update([SUITE, 0x02]);
+    for point in points {
+        hasher.update(point.compress().as_bytes());
+    }
+    let hash = hasher.chain_update([0x00]).finalize();
+    let mut c = [0u8; CHALLENGE_LEN];
+    c.copy_from_slice(&hash[..CHALLENGE_LEN]);
+    c
+}
+
+fn challenge_scalar(c: &[u8; CHALLENGE_LEN]) -> Scalar {
+    let mut bytes = [0u8; 32];
+    bytes[..CHALLENGE_LEN].copy_from_slice(c);
+    Scalar::from_bytes_mod_order(bytes)
+}
+
+fn output(gamma: &EdwardsPoint) -> [u8; OUTPUT_LEN] {
+    Sha512::new()
+        .chain_update([SUITE, 0x03])
+        .chain_update(gamma.mul_by_cofactor().compress().as_bytes())
+        .chain_update([0x00])
+        .finalize()
+        .into()
+}
+
+pub fn prove(secret_key: &[u8; SECRET_KEY_LEN], alpha: &[u8]) -> Result<[u8; PROOF_LEN], String> {
+    let (x, prefix) = expand(secret_key);
+    let y = EdwardsPoint::mul_base(&x);
+    let h = encode_to_curve(y.compress().as_bytes(), alpha)?;
+    let nonce = Zeroizing::new(<[u8; 64]>::from(Sha512::new().chain_update(*prefix).chain_update(h.compress().as_bytes()).finalize()));
+    let k = Zeroizing::new(Scalar::from_bytes_mod_order_wide(&nonce));
+    let gamma = h * *x;
+    let c = challenge([&y, &h, &gamma, &EdwardsPoint::mul_base(&k), &(h * *k)]);
+    let s = *k + challenge_scalar(&c) * *x;
+
+    let mut proof = [0u8; PROOF_LEN];
+    proof[..32].copy_from_slice(gamma.compress().as_bytes());
+    proof[32..48].copy_from_slice(&c);
+    proof[48..].copy_from_slice(s.as_bytes());
+    Ok(proof)
+}
+
+fn decode_proof(proof: &[u8; PROOF_LEN]) -> Result<(EdwardsPoint, [u8; CHALLENGE_LEN], Scalar), String> {
+    let gamma = decode_point(&proof[..32]).ok_or("invalid proof point")?;
+    let mut c = [0u8; CHALLENGE_LEN];
+    c.copy_from_slice(&proof[32..48]);
+    let mut s = [0u8; 32];
+    s.copy_from_slice(&proof[48..]);
+    let s = Option::from(Scalar::from_canonical_bytes(s)).ok_or("non-canonical proof scalar")?;
+    Ok((gamma, c, s))
+}
+
+/// The output a proof commits to, without checking the proof.
+pub fn proof_to_hash(proof: &[u8; PROOF_LEN]) -> Result<[u8; OUTPUT_LEN], String> {
+    let (gamma, _, _) = decode_proof(proof)?;
+    Ok(output(&gamma))
+}
+
+/// The output, if `proof` was made for `alpha` with the key of `public_key`.
+pub fn verify(public_key: &[u8; PUBLIC_KEY_LEN], alpha: &[u8], proof: &[u8; PROOF_LEN]) -> Result<[u8; OUTPUT_LEN], String> {
+    let y = decode_point(public_key).ok_or("invalid public key")?;
+    // A small-order key would let its owner make many proofs for one input
+    if y.is_small_order() {
+        return Err("small-order public key".to_string());
+    }
+    let (gamma, c, s) = decode_proof(proof)?;
+    let h = encode_to_curve(public_key, alpha)?;
+    let minus_c = -challenge_scalar(&c);
+    let u = EdwardsPoint::vartime_double_scalar_mul_basepoint(&minus_c, &y, &s);
+    let v = h * s + gamma * minus_c;
+    if challenge([&y, &h, &gamma, &u, &v]) != c {
+        return Err("invalid proof".to_string());
+    }
+    Ok(output(&gamma))
+}
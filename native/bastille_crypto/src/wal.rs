@@ -0,0 +1,370 @@
+// Write-ahead log for crash-safe state application: the caller appends an
+// intent record before mutating state, and after a crash replays the records
+// not yet covered by a checkpoint to redo (or undo) the interrupted work.
+//
+// Records get consecutive log sequence numbers (LSNs) from 1, and are stored
+// in segment files named after their first LSN:
+//
+//   <dir>/<first lsn>.wal  magic "BWALSEG1" | records
+//   record                 length (u32 BE) | lsn (u64 BE) | payload |
+//                          crc32c of everything before (u32 BE)
+//   <dir>/checkpoint       lsn (u64 BE) | crc32c of it (u32 BE)
+//
+// A checkpoint at LSN n means state reflects every record up to n, so
+// segments holding nothing later are deleted; the checkpoint file is replaced
+// atomically. On open, a torn record at the end of the last segment (a crash
+// mid-append) is cut off; damage anywhere else is an error, as records after
+// it would be lost.
+
+use crate::smt::io_error;
+use std::fs::{self, File, OpenOptions};
+use std::os::unix::fs::FileExt;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+pub const MAX_RECORD_LEN: usize = 64 << 20;
+
+const MAGIC: [u8; 8] = *b"BWALSEG1";
+const RECORD_OVERHEAD: usize = 4 + 8 + 4;
+// Size past which appends start a new segment
+const SEGMENT_SIZE: u64 = 64 << 20;
+
+#[derive(Clone, Copy)]
+pub enum SyncPolicy {
+    /// fsync after every append.
+    Always,
+    /// fsync on an append at least this long after the last sync.
+    Interval(Duration),
+    /// fsync only on sync(), checkpoints and segment changes.
+    Manual,
+}
+
+fn encode_record(lsn: u64, payload: &[u8]) -> Vec<u8> {
+    let mut record = Vec::with_capacity(RECORD_OVERHEAD + payload.len());
+    record.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    record.extend_from_slice(&lsn.to_be_bytes());
+    record.extend_from_slice(payload);
+    let crc = crc32c::crc32c(&record);
+    record.extend_from_slice(&crc.to_be_bytes());
+    record
+}
+
+// Feed the records of a segment's contents after the magic, from `lsn` on,
+// to `f`, up to the first torn, corrupt or out-of-sequence one. Returns the
+// length of the valid prefix (magic included) and the LSN following it.
+fn read_records(contents: &[u8], mut lsn: u64, mut f: impl FnMut(u64, &[u8])) -> (usize, u64) {
+    let mut pos = MAGIC.len();
+    while let Some(header) = contents.get(pos..pos + 12) {
+        let len = u32::from_be_bytes(header[0..4].try_into().unwrap()) as usize;
+        let record_lsn = u64::from_be_bytes(header[4..12].try_into().unwrap());
+        if len > MAX_RECORD_LEN || record_lsn != lsn {
+            break;
+        }
+        let Some(record) = contents.get(pos..pos + RECORD_OVERHEAD + len) else {
+            break;
+        };
+        let (body, crc) = record.split_at(record.len() - 4);
+        if crc32c::crc32c(body) != u32::from_be_bytes(crc.try_into().unwrap()) {
+            break;
+        }
+        f(lsn, &body[12..]);
+        pos += record.len();
+        lsn += 1;
+    }
+    (pos, lsn)
+}
+
+fn segment_path(dir: &Path, first_lsn: u64) -> PathBuf {
+    dir.join(format!("{:020}.wal", first_lsn))
+}
+
+fn sync_dir(dir: &Path) -> Result<(), String> {
+    File::open(dir).and_then(|dir| dir.sync_all()).map_err(|e| io_error("sync", dir, e))
+}
+
+fn read_checkpoint(dir: &Path) -> Result<u64, String> {
+    let path = dir.join("checkpoint");
+    let contents = match fs::read(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(io_error("read", &path, e)),
+    };
+    match contents.split_at_checked(8) {
+        Some((lsn, crc)) if crc == crc32c::crc32c(lsn).to_be_bytes() => Ok(u64::from_be_bytes(lsn.try_into().unwrap())),
+        _ => Err(format!("'{}' is corrupt", path.display())),
+    }
+}
+
+fn write_checkpoint(dir: &Path, lsn: u64) -> Result<(), String> {
+    let path = dir.join("checkpoint");
+    let tmp_path = dir.join("checkpoint.tmp");
+    let mut contents = lsn.to_be_bytes().to_vec();
+    contents.extend_from_slice(&crc32c::crc32c(&contents).to_be_bytes());
+    let tmp = File::create(&tmp_path).map_err(|e| io_error("create", &tmp_path, e))?;
+    tmp.write_all_at(&contents, 0)
+        .and_then(|_| tmp.sync_data())
+        .map_err(|e| io_error("write", &tmp_path, e))?;
+    fs::rename(&tmp_path, &path).map_err(|e| io_error("replace", &path, e))?;
+    sync_dir(dir)
+}
+
+struct Segment {
+    first_lsn: u64,
+    path: PathBuf,
+    file: File,
+    len: u64,
+}
+
+impl Segment {
+    fn create(dir: &Path, first_lsn: u64) -> Result<Self, String> {
+        let path = segment_path(dir, first_lsn);
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(true)
+            .read(true)
+            .write(true)
+            .open(&path)
+            .map_err(|e| io_error("create", &path, e))?;
+        file.write_all_at(&MAGIC, 0)
+            .and_then(|_| file.sync_data())
+            .map_err(|e| io_error("write", &path, e))?;
+        sync_dir(dir)?;
+        Ok(Segment { first_lsn, path, file, len: MAGIC.len() as u64 })
+    }
+
+    fn sync(&self) -> Result<(), String> {
+        self.file.sync_data().map_err(|e| io_error("sync", &self.path, e))
+    }
+}
+
+struct WalState {
+    dir: PathBuf,
+    policy: SyncPolicy,
+    // First LSNs of the segments before the active one, oldest first
+    sealed: Vec<u64>,
+    active: Segment,
+    next_lsn: u64,
+    checkpoint: u64,
+    last_sync: Instant,
+    _lock: File,
+}
+
+pub struct Wal {
+    state: Mutex<WalState>,
+}
+
+#[rustler::resource_impl]
+impl rustler::Resource for Wal {}
+
+impl WalState {
+    fn open(dir: &Path, policy: SyncPolicy) -> Result<Self, String> {
+        fs::create_dir_all(dir).map_err(|e| io_error("create WAL directory", dir, e))?;
+        let lock_path = dir.join("wal.lock");
+        let lock = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&lock_path)
+            .map_err(|e| io_error("open", &lock_path, e))?;
+        lock.try_lock().map_err(|_| format!("WAL '{}' is already open", dir.display()))?;
+
+        let checkpoint = read_checkpoint(dir)?;
+        let mut segments = Vec::new();
+        for entry in fs::read_dir(dir).map_err(|e| io_error("list", dir, e))? {
+            let path = entry.map_err(|e| io_error("list", dir, e))?.path();
+            if path.extension().is_some_and(|ext| ext == "wal") {
+                if let Some(first_lsn) = path.file_stem().and_then(|stem| stem.to_str()?.parse::<u64>().ok()) {
+                    segments.push(first_lsn);
+                }
+            }
+        }
+        segments.sort_unstable();
+
+        let Some(&last) = segments.last() else {
+            let active = Segment::create(dir, checkpoint + 1)?;
+            return Ok(WalState {
+                dir: dir.to_path_buf(),
+                policy,
+                sealed: Vec::new(),
+                active,
+                next_lsn: checkpoint + 1,
+                checkpoint,
+                last_sync: Instant::now(),
+                _lock: lock,
+            });
+        };
+        if segments[0] > checkpoint + 1 {
+            return Err(format!("WAL '{}' is missing records after its checkpoint", dir.display()));
+        }
+
+        // Every segment must run intact into the next; the last may end torn
+        let (mut next_lsn, mut valid_len, mut file_len) = (segments[0], 0, 0);
+        for &first_lsn in &segments {
+            let path = segment_path(dir, first_lsn);
+            if first_lsn != next_lsn {
+                return Err(format!("WAL segment '{}' is out of sequence", path.display()));
+            }
+            let contents = fs::read(&path).map_err(|e| io_error("read", &path, e))?;
+            (valid_len, next_lsn) = if contents.starts_with(&MAGIC) {
+                read_records(&contents, first_lsn, |_, _| {})
+            } else {
+                (0, first_lsn)
+            };
+            file_len = contents.len();
+            if valid_len < file_len && first_lsn != last {
+                return Err(format!("WAL segment '{}' is corrupt", path.display()));
+            }
+        }
+        if next_lsn <= checkpoint {
+            return Err(format!("WAL '{}' ends before its checkpoint", dir.display()));
+        }
+
+        let path = segment_path(dir, last);
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&path)
+            .map_err(|e| io_error("open", &path, e))?;
+        if valid_len == 0 {
+            // Cut short before its magic was written
+            file.write_all_at(&MAGIC, 0).map_err(|e| io_error("write", &path, e))?;
+            valid_len = MAGIC.len();
+        }
+        if file_len > valid_len {
+            file.set_len(valid_len as u64)
+                .and_then(|_| file.sync_data())
+                .map_err(|e| io_error("truncate", &path, e))?;
+        }
+
+        segments.pop();
+        Ok(WalState {
+            dir: dir.to_path_buf(),
+            policy,
+            sealed: segments,
+            active: Segment { first_lsn: last, path, file, len: valid_len as u64 },
+            next_lsn,
+            checkpoint,
+            last_sync: Instant::now(),
+            _lock: lock,
+        })
+    }
+
+    fn append(&mut self, payload: &[u8]) -> Result<u64, String> {
+        if payload.len() > MAX_RECORD_LEN {
+            return Err(format!("WAL record exceeds {} bytes", MAX_RECORD_LEN));
+        }
+        if self.active.len >= SEGMENT_SIZE {
+            self.roll()?;
+        }
+        let lsn = self.next_lsn;
+        let record = encode_record(lsn, payload);
+        // Written at the recorded end, so a failed write is overwritten by the next
+        self.active
+            .file
+            .write_all_at(&record, self.active.len)
+            .map_err(|e| io_error("write", &self.active.path, e))?;
+        self.active.len += record.len() as u64;
+        self.next_lsn += 1;
+
+        let due = match self.policy {
+            SyncPolicy::Always => true,
+            SyncPolicy::Interval(interval) => self.last_sync.elapsed() >= interval,
+            SyncPolicy::Manual => false,
+        };
+        if due {
+            self.sync()?;
+        }
+        Ok(lsn)
+    }
+
+    // Seal the active segment and start another at the next LSN
+    fn roll(&mut self) -> Result<(), String> {
+        self.sync()?;
+        let segment = Segment::create(&self.dir, self.next_lsn)?;
+        let sealed = std::mem::replace(&mut self.active, segment);
+        self.sealed.push(sealed.first_lsn);
+        Ok(())
+    }
+
+    fn sync(&mut self) -> Result<(), String> {
+        self.active.sync()?;
+        self.last_sync = Instant::now();
+        Ok(())
+    }
+
+    fn replay(&self) -> Result<Vec<(u64, Vec<u8>)>, String> {
+        let mut records = Vec::new();
+        for &first_lsn in self.sealed.iter().chain([&self.active.first_lsn]) {
+            let path = segment_path(&self.dir, first_lsn);
+            let contents = fs::read(&path).map_err(|e| io_error("read", &path, e))?;
+            read_records(&contents, first_lsn, |lsn, payload| {
+                if lsn > self.checkpoint {
+                    records.push((lsn, payload.to_vec()));
+                }
+            });
+        }
+        Ok(records)
+    }
+
+    fn checkpoint(&mut self, lsn: u64) -> Result<(), String> {
+        if lsn < self.checkpoint || lsn >= self.next_lsn {
+            return Err(format!(
+                "checkpoint {} is outside the WAL's records {}..{}",
+                lsn,
+                self.checkpoint,
+                self.next_lsn - 1
+            ));
+        }
+        self.sync()?;
+        write_checkpoint(&self.dir, lsn)?;
+        self.checkpoint = lsn;
+
+        // A segment is done with once the next one starts at or before lsn + 1
+        if self.next_lsn == lsn + 1 && self.active.len > MAGIC.len() as u64 {
+            self.roll()?;
+        }
+        let firsts: Vec<u64> = self.sealed.iter().copied().chain([self.active.first_lsn]).collect();
+        let done = firsts.windows(2).take_while(|pair| pair[1] <= lsn + 1).count();
+        for first_lsn in self.sealed.drain(..done) {
+            let path = segment_path(&self.dir, first_lsn);
+            fs::remove_file(&path).map_err(|e| io_error("remove", &path, e))?;
+        }
+        if done > 0 {
+            sync_dir(&self.dir)?;
+        }
+        Ok(())
+    }
+}
+
+impl Wal {
+    /// Open the log in `dir`, creating it if needed, and cut off a torn final record.
+    pub fn open(dir: &Path, policy: SyncPolicy) -> Result<Self, String> {
+        Ok(Wal { state: Mutex::new(WalState::open(dir, policy)?) })
+    }
+
+    fn state(&self) -> Result<MutexGuard<'_, WalState>, String> {
+        self.state.lock().map_err(|_| "WAL lock poisoned".to_string())
+    }
+
+    /// Append `payload` as the next record, returning its LSN.
+    pub fn append(&self, payload: &[u8]) -> Result<u64, String> {
+        self.state()?.append(payload)
+    }
+
+    /// Sync appended records to disk.
+    pub fn sync(&self) -> Result<(), String> {
+        self.state()?.sync()
+    }
+
+    /// The records after the last checkpoint, in LSN order.
+    pub fn replay(&self) -> Result<Vec<(u64, Vec<u8>)>, String> {
+        self.state()?.replay()
+    }
+
+    /// Record that state reflects every record up to `lsn`, and drop the
+    /// segments no longer needed.
+    pub fn checkpoint(&self, lsn: u64) -> Result<(), String> {
+        self.state()?.checkpoint(lsn)
+    }
+}
@@ -0,0 +1,450 @@
+// The smart-contract runtime: WebAssembly on wasmtime, configured so every
+// node computes the same results and charges the same gas (the `wasm`
+// feature).
+//
+// - Gas is wasmtime fuel, one unit per instruction or so. The charge is
+//   fixed by the wasmtime version, which is pinned like any consensus rule.
+//   Modules metered by metering.rs count their own gas instead, in their
+//   "bastille_gas" global; fuel is then only a backstop, FUEL_PER_GAS times
+//   the limit, against a module exporting that global without being metered.
+// - NaNs are canonicalized, so float results don't depend on the CPU; SIMD,
+//   relaxed SIMD and threads, the other sources of nondeterminism, are off.
+// - Memory and tables are capped per instance; memory.grow past the cap
+//   returns -1, as the spec allows, rather than trapping.
+// - Calls run on their own thread with a large stack, never on a BEAM
+//   scheduler's small one, and the wasm stack is capped well below it.
+//   Traps are explicit checks rather than signal handlers, leaving the
+//   BEAM's signals alone.
+//
+// An instance holds its store behind a mutex, so calls on it are serialized,
+// and keeps its memory between calls.
+//
+// Contracts may import these host functions from "bastille", taking offsets
+// and lengths into their exported "memory" and charged fixed gas plus gas
+// per byte, whether or not they succeed:
+//
+//   blake3(ptr, len, out)                          32-byte hash to out
+//   verify(algorithm, key, key_len, msg, msg_len, sig, sig_len) -> 1 | 0
+//   storage_get(key, key_len, out, out_cap) -> value length | -1 if unset
+//   storage_put(key, key_len, value, value_len)
+//
+// verify takes the algorithm ids of keygen.rs, Dilithium2 or Falcon-512.
+// storage_get copies at most out_cap bytes and returns the full length.
+// Storage is an Elixir process: a get sends it {:wasm_storage, :get, reply,
+// key} and waits for wasm_storage_reply(reply, value | nil), a put sends it
+// {:wasm_storage, :put, key, value} and goes on. Messages arrive in order,
+// so a get sees the puts before it.
+//
+// A contract's result may depend only on its code, arguments, gas limit and
+// the storage it reads, never on how fast a node answers. So a storage get
+// that isn't answered within the timeout, or a storage process that is gone,
+// isn't a trap: it aborts the whole call, which reports no result and no
+// gas, and must not be committed. The aborted instance, whose memory the
+// call left half-changed, refuses further calls. A waiting get holds its
+// dirty scheduler, for at most the timeout; the storage process should
+// answer from memory (an ETS table, a map) rather than go to disk.
+
+use crate::keygen::Algorithm;
+use crate::metering;
+use rustler::{Encoder, Env, LocalPid, OwnedEnv, ResourceArc, Term};
+use std::sync::{Condvar, Mutex};
+use std::time::Duration;
+use wasmtime::{
+    Caller, Config, Engine, Extern, Linker, Module, Store, StoreLimits, StoreLimitsBuilder, Trap, Val, ValType,
+};
+
+pub const DEFAULT_MAX_MEMORY: usize = 16 << 20;
+pub const DEFAULT_MAX_TABLE_ELEMENTS: usize = 10_000;
+// Gas for the start function, if the module has one
+pub const DEFAULT_START_GAS: u64 = 1_000_000;
+
+// Fuel a metered module burns per gas: a run of n instructions costs n gas
+// and n plus the 8 of its charge in fuel at most
+const FUEL_PER_GAS: u64 = 16;
+
+pub const DEFAULT_STORAGE_TIMEOUT: Duration = Duration::from_secs(5);
+pub const MAX_STORAGE_KEY: usize = 256;
+pub const MAX_STORAGE_VALUE: usize = 64 << 10;
+
+// Host function gas, on top of GAS_PER_BYTE for each byte read or written
+const BLAKE3_GAS: u64 = 100;
+const VERIFY_GAS: u64 = 100_000;
+const STORAGE_GAS: u64 = 10_000;
+const GAS_PER_BYTE: u64 = 1;
+
+const MAX_WASM_STACK: usize = 1 << 20;
+const THREAD_STACK: usize = 8 << 20;
+
+lazy_static::lazy_static! {
+    static ref ENGINE: Result<Engine, String> = {
+        let mut config = Config::new();
+        config
+            .consume_fuel(true)
+            .cranelift_nan_canonicalization(true)
+            .wasm_simd(false)
+            .wasm_relaxed_simd(false)
+            .wasm_multi_memory(false)
+            .wasm_memory64(false)
+            .wasm_backtrace_max_frames(None)
+            .signals_based_traps(false)
+            .max_wasm_stack(MAX_WASM_STACK);
+        Engine::new(&config).map_err(|e| format!("failed to start the wasm engine: {}", e))
+    };
+}
+
+fn engine() -> Result<&'static Engine, String> {
+    ENGINE.as_ref().map_err(|e| e.clone())
+}
+
+pub struct Limits {
+    pub max_memory: usize,
+    pub max_table_elements: usize,
+    pub start_gas: u64,
+}
+
+// A call's results, or why it failed, and the gas it used
+pub type Outcome = (Result<Vec<Value>, String>, u64);
+
+pub enum Value {
+    Int(i64),
+    Float(f64),
+}
+
+/// The Elixir process serving storage_get and storage_put.
+pub struct Storage {
+    pub pid: LocalPid,
+    pub timeout: Duration,
+}
+
+struct Host {
+    limits: StoreLimits,
+    storage: Option<Storage>,
+    // Why a call was aborted, once one was
+    aborted: Option<String>,
+}
+
+pub struct Instance {
+    storage_pid: Option<LocalPid>,
+    state: Mutex<(Store<Host>, wasmtime::Instance)>,
+}
+
+/// The answer to one storage get, filled in by wasm_storage_reply.
+#[derive(Default)]
+pub struct StorageReply {
+    // None until answered, then the value or None if unset
+    value: Mutex<Option<Option<Vec<u8>>>>,
+    answered: Condvar,
+}
+
+#[rustler::resource_impl]
+impl rustler::Resource for StorageReply {}
+
+#[rustler::resource_impl]
+impl rustler::Resource for Instance {}
+
+fn describe(e: wasmtime::Error) -> String {
+    match e.downcast_ref::<Trap>() {
+        Some(Trap::OutOfFuel) => "out of gas".to_string(),
+        Some(trap) => trap.to_string(),
+        None => e.to_string(),
+    }
+}
+
+// Run `f` on a thread with a stack large enough for MAX_WASM_STACK
+fn on_wasm_thread<R: Send>(f: impl FnOnce() -> R + Send) -> Result<R, String> {
+    std::thread::scope(|scope| {
+        std::thread::Builder::new()
+            .name("bastille-wasm".to_string())
+            .stack_size(THREAD_STACK)
+            .spawn_scoped(scope, f)
+            .map_err(|e| format!("failed to start a wasm thread: {}", e))?
+            .join()
+            .map_err(|_| "wasm thread panicked".to_string())
+    })
+}
+
+fn to_val(value: &Value, ty: &ValType) -> Result<Val, String> {
+    match (value, ty) {
+        (Value::Int(n), ValType::I32) if (i32::MIN as i64..=u32::MAX as i64).contains(n) => Ok(Val::I32(*n as i32)),
+        (Value::Int(n), ValType::I64) => Ok(Val::I64(*n)),
+        (Value::Float(x), ValType::F32) => Ok(Val::F32((*x as f32).to_bits())),
+        (Value::Float(x), ValType::F64) => Ok(Val::F64(x.to_bits())),
+        _ => Err(format!("argument does not fit a parameter of type {}", ty)),
+    }
+}
+
+// Erlang floats are finite, so NaN and infinite results are an error
+fn from_val(val: &Val) -> Result<Value, String> {
+    let value = match val {
+        Val::I32(n) => Value::Int(*n as i64),
+        Val::I64(n) => Value::Int(*n),
+        Val::F32(bits) => Value::Float(f32::from_bits(*bits) as f64),
+        Val::F64(bits) => Value::Float(f64::from_bits(*bits)),
+        _ => return Err("unsupported result type".to_string()),
+    };
+    match value {
+        Value::Float(x) if !x.is_finite() => Err("non-finite float result".to_string()),
+        value => Ok(value),
+    }
+}
+
+impl StorageReply {
+    /// False if already answered.
+    pub fn answer(&self, value: Option<Vec<u8>>) -> Result<bool, String> {
+        let mut slot = self.value.lock().map_err(|_| "storage reply lock poisoned".to_string())?;
+        if slot.is_some() {
+            return Ok(false);
+        }
+        *slot = Some(value);
+        self.answered.notify_all();
+        Ok(true)
+    }
+
+    fn wait(&self, timeout: Duration) -> Result<Option<Vec<u8>>, String> {
+        let slot = self.value.lock().map_err(|_| "storage reply lock poisoned".to_string())?;
+        let (mut slot, _) = self
+            .answered
+            .wait_timeout_while(slot, timeout, |slot| slot.is_none())
+            .map_err(|_| "storage reply lock poisoned".to_string())?;
+        slot.take().ok_or("storage timeout".to_string())
+    }
+}
+
+impl Storage {
+    // Calls come from wasm threads, which the BEAM doesn't manage, so they
+    // can send
+    fn send(&self, message: impl for<'a> FnOnce(Env<'a>) -> Term<'a>) -> Result<(), String> {
+        OwnedEnv::new().send_and_clear(&self.pid, message).map_err(|_| "storage process is gone".to_string())
+    }
+
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, String> {
+        let reply = ResourceArc::new(StorageReply::default());
+        let sent = reply.clone();
+        self.send(|env| (crate::wasm_storage(), crate::get(), sent, crate::make_binary(env, key)).encode(env))?;
+        reply.wait(self.timeout)
+    }
+
+    fn put(&self, key: &[u8], value: &[u8]) -> Result<(), String> {
+        self.send(|env| {
+            (crate::wasm_storage(), crate::put(), crate::make_binary(env, key), crate::make_binary(env, value)).encode(env)
+        })
+    }
+}
+
+// Charge host gas: to the gas global of a metered module, and its fuel
+// equivalent to the fuel, so the backstop keeps its margin
+fn charge(caller: &mut Caller<'_, Host>, gas: u64) -> wasmtime::Result<()> {
+    let mut fuel = gas;
+    if let Some(Extern::Global(global)) = caller.get_export(metering::GAS_GLOBAL) {
+        let left = global.get(&mut *caller).i64().unwrap_or(-1).saturating_sub(gas.min(i64::MAX as u64) as i64);
+        global.set(&mut *caller, Val::I64(left))?;
+        if left < 0 {
+            return Err(Trap::OutOfFuel.into());
+        }
+        fuel = gas.saturating_mul(FUEL_PER_GAS);
+    }
+    let left = caller.get_fuel()?;
+    caller.set_fuel(left.saturating_sub(fuel))?;
+    if left < fuel {
+        return Err(Trap::OutOfFuel.into());
+    }
+    Ok(())
+}
+
+fn memory(caller: &mut Caller<'_, Host>) -> wasmtime::Result<wasmtime::Memory> {
+    match caller.get_export("memory") {
+        Some(Extern::Memory(memory)) => Ok(memory),
+        _ => Err(wasmtime::Error::msg("contract exports no memory")),
+    }
+}
+
+fn read(caller: &mut Caller<'_, Host>, ptr: i32, len: i32) -> wasmtime::Result<Vec<u8>> {
+    let memory = memory(caller)?;
+    let (start, len) = (ptr as u32 as usize, len as u32 as usize);
+    let bytes = memory.data(&*caller).get(start..start + len);
+    bytes.map(|bytes| bytes.to_vec()).ok_or_else(|| wasmtime::Error::msg("out of bounds memory access"))
+}
+
+fn write(caller: &mut Caller<'_, Host>, ptr: i32, bytes: &[u8]) -> wasmtime::Result<()> {
+    let memory = memory(caller)?;
+    memory.write(&mut *caller, ptr as u32 as usize, bytes).map_err(|_| wasmtime::Error::msg("out of bounds memory access"))
+}
+
+fn storage<'a>(caller: &'a Caller<'_, Host>) -> wasmtime::Result<&'a Storage> {
+    caller.data().storage.as_ref().ok_or_else(|| wasmtime::Error::msg("no storage"))
+}
+
+// Abort the call on a storage failure, which says nothing about the contract
+fn abort(caller: &mut Caller<'_, Host>, reason: String) -> wasmtime::Error {
+    caller.data_mut().aborted = Some(reason.clone());
+    wasmtime::Error::msg(reason)
+}
+
+fn storage_key(caller: &mut Caller<'_, Host>, ptr: i32, len: i32) -> wasmtime::Result<Vec<u8>> {
+    if len as u32 as usize > MAX_STORAGE_KEY {
+        return Err(wasmtime::Error::msg(format!("storage key over {} bytes", MAX_STORAGE_KEY)));
+    }
+    read(caller, ptr, len)
+}
+
+fn storage_value_len(len: usize) -> wasmtime::Result<()> {
+    if len > MAX_STORAGE_VALUE {
+        return Err(wasmtime::Error::msg(format!("storage value over {} bytes", MAX_STORAGE_VALUE)));
+    }
+    Ok(())
+}
+
+fn define_host_functions(linker: &mut Linker<Host>) -> wasmtime::Result<()> {
+    linker.func_wrap("bastille", "blake3", |mut caller: Caller<'_, Host>, ptr: i32, len: i32, out: i32| {
+        charge(&mut caller, BLAKE3_GAS + GAS_PER_BYTE * len as u32 as u64)?;
+        let data = read(&mut caller, ptr, len)?;
+        write(&mut caller, out, blake3::hash(&data).as_bytes())
+    })?;
+
+    linker.func_wrap(
+        "bastille",
+        "verify",
+        |mut caller: Caller<'_, Host>, algorithm: i32, key: i32, key_len: i32, msg: i32, msg_len: i32, sig: i32, sig_len: i32| {
+            let bytes: u64 = [key_len, msg_len, sig_len].iter().map(|len| *len as u32 as u64).sum();
+            charge(&mut caller, VERIFY_GAS + GAS_PER_BYTE * bytes)?;
+            let algorithm = match u8::try_from(algorithm).ok().and_then(Algorithm::from_id) {
+                Some(algorithm @ (Algorithm::Dilithium2 | Algorithm::Falcon512)) => algorithm,
+                _ => return Err(wasmtime::Error::msg(format!("unsupported algorithm {}", algorithm))),
+            };
+            let public_key = read(&mut caller, key, key_len)?;
+            let message = read(&mut caller, msg, msg_len)?;
+            let signature = read(&mut caller, sig, sig_len)?;
+            Ok(crate::public_key::verify_bytes(algorithm, &public_key, &signature, &message) as i32)
+        },
+    )?;
+
+    linker.func_wrap(
+        "bastille",
+        "storage_get",
+        |mut caller: Caller<'_, Host>, key: i32, key_len: i32, out: i32, out_cap: i32| {
+            charge(&mut caller, STORAGE_GAS + GAS_PER_BYTE * key_len as u32 as u64)?;
+            let key = storage_key(&mut caller, key, key_len)?;
+            let value = match storage(&caller)?.get(&key) {
+                Ok(Some(value)) => value,
+                Ok(None) => return Ok(-1),
+                Err(e) => return Err(abort(&mut caller, e)),
+            };
+            storage_value_len(value.len())?;
+            let copied = value.len().min(out_cap as u32 as usize);
+            charge(&mut caller, GAS_PER_BYTE * copied as u64)?;
+            write(&mut caller, out, &value[..copied])?;
+            Ok(value.len() as i32)
+        },
+    )?;
+
+    linker.func_wrap(
+        "bastille",
+        "storage_put",
+        |mut caller: Caller<'_, Host>, key: i32, key_len: i32, value: i32, value_len: i32| {
+            charge(&mut caller, STORAGE_GAS + GAS_PER_BYTE * (key_len as u32 as u64 + value_len as u32 as u64))?;
+            let key = storage_key(&mut caller, key, key_len)?;
+            storage_value_len(value_len as u32 as usize)?;
+            let value = read(&mut caller, value, value_len)?;
+            let sent = storage(&caller)?.put(&key, &value);
+            sent.map_err(|e| abort(&mut caller, e))
+        },
+    )?;
+    Ok(())
+}
+
+impl Instance {
+    /// Compile `code` (binary, or text for tests and tooling) and instantiate
+    /// it with the host functions, running its start function with
+    /// `limits.start_gas`. Without `storage`, storage calls trap.
+    pub fn new(code: &[u8], limits: &Limits, storage: Option<Storage>) -> Result<Self, String> {
+        let storage_pid = storage.as_ref().map(|storage| storage.pid);
+        let engine = engine()?;
+        let module = Module::new(engine, code).map_err(|e| format!("invalid module: {}", e))?;
+        let store_limits = StoreLimitsBuilder::new()
+            .memory_size(limits.max_memory)
+            .table_elements(limits.max_table_elements)
+            .instances(1)
+            .build();
+        let mut store = Store::new(engine, Host { limits: store_limits, storage, aborted: None });
+        store.limiter(|host| &mut host.limits);
+        store.set_fuel(limits.start_gas).map_err(describe)?;
+
+        let mut linker = Linker::new(engine);
+        define_host_functions(&mut linker).map_err(|e| format!("failed to define host functions: {}", e))?;
+        let instance = on_wasm_thread(|| linker.instantiate(&mut store, &module).map_err(describe))??;
+        Ok(Instance { storage_pid, state: Mutex::new((store, instance)) })
+    }
+
+    pub fn storage_pid(&self) -> Option<LocalPid> {
+        self.storage_pid
+    }
+
+    /// Call the exported function `name` with at most `gas_limit` gas.
+    /// Returns its results, or why it failed, and the gas used either way;
+    /// Err if the call was aborted (see the top of this file).
+    pub fn call(&self, name: &str, args: &[Value], gas_limit: u64) -> Result<Outcome, String> {
+        let mut state = self.state.lock().map_err(|_| "wasm instance lock poisoned".to_string())?;
+        let (store, instance) = &mut *state;
+        if let Some(reason) = &store.data().aborted {
+            return Err(format!("instance aborted by an earlier call: {}", reason));
+        }
+        let outcome = Self::run(store, instance, name, args, gas_limit);
+        match &store.data().aborted {
+            Some(reason) => Err(reason.clone()),
+            None => Ok(outcome),
+        }
+    }
+
+    fn run(
+        store: &mut Store<Host>,
+        instance: &wasmtime::Instance,
+        name: &str,
+        args: &[Value],
+        gas_limit: u64,
+    ) -> Outcome {
+        let func = match instance.get_func(&mut *store, name) {
+            Some(func) => func,
+            None => return (Err(format!("no exported function {}", name)), 0),
+        };
+        let ty = func.ty(&*store);
+        if ty.params().len() != args.len() {
+            return (Err(format!("{} takes {} arguments, {} given", name, ty.params().len(), args.len())), 0);
+        }
+        let params = match args.iter().zip(ty.params()).map(|(arg, ty)| to_val(arg, &ty)).collect::<Result<Vec<_>, _>>() {
+            Ok(params) => params,
+            Err(e) => return (Err(e), 0),
+        };
+        let gas = instance.get_global(&mut *store, metering::GAS_GLOBAL);
+        let (gas_limit, fuel) = match gas {
+            Some(global) => {
+                let gas_limit = gas_limit.min(i64::MAX as u64);
+                if let Err(e) = global.set(&mut *store, Val::I64(gas_limit as i64)) {
+                    return (Err(format!("invalid {} global: {}", metering::GAS_GLOBAL, e)), 0);
+                }
+                (gas_limit, gas_limit.saturating_mul(FUEL_PER_GAS))
+            }
+            None => (gas_limit, gas_limit),
+        };
+        if let Err(e) = store.set_fuel(fuel) {
+            return (Err(describe(e)), 0);
+        }
+
+        let mut results = vec![Val::I32(0); ty.results().len()];
+        let outcome = on_wasm_thread(|| func.call(&mut *store, &params, &mut results).map_err(describe));
+        let outcome = outcome.and_then(|call| call);
+        let (outcome, gas_used) = match gas {
+            Some(global) => {
+                let left = global.get(&mut *store).i64().unwrap_or(-1);
+                let out_of_fuel = matches!(&outcome, Err(e) if e == "out of gas");
+                if left < 0 || out_of_fuel {
+                    (Err("out of gas".to_string()), gas_limit)
+                } else {
+                    (outcome, gas_limit - (left as u64).min(gas_limit))
+                }
+            }
+            None => (outcome, gas_limit - store.get_fuel().unwrap_or(0)),
+        };
+        let results = outcome.and_then(|()| results.iter().map(from_val).collect());
+        (results, gas_used)
+    }
+}
@@ -13,6 +13,366 @@ defmodule Bastille.Infrastructure.Crypto.CryptoNifTest do
   @moduletag :crypto_nif
   @moduletag timeout: 30_000
 
+  describe "native benchmarks" do
+    test "reports ordered timings for each operation" do
+      for op <- [:keygen, :sign, :verify] do
+        result = CryptoNif.benchmark(:falcon512, op, 20)
+
+        assert result.iterations == 20
+        assert result.min_ns <= result.median_ns and result.median_ns <= result.p99_ns
+        assert result.ops_per_sec > 0
+      end
+    end
+
+    test "rejects unknown operations and out-of-range iteration counts" do
+      assert_raise ArgumentError, fn -> CryptoNif.benchmark(:dilithium2, :encrypt, 10) end
+      assert_raise ArgumentError, fn -> CryptoNif.benchmark(:dilithium2, :sign, 0) end
+      assert_raise ArgumentError, fn -> CryptoNif.benchmark(:dilithium2, :sign, 1_000_000) end
+    end
+  end
+
+  describe "sub-binary inputs" do
+    test "hash and verify a slice of a larger binary like a copy" do
+      block = :crypto.strong_rand_bytes(100_000)
+      slice = binary_part(block, 1_234, 50_000)
+      copy = :binary.copy(slice)
+
+      assert CryptoNif.blake3_hash(slice) == CryptoNif.blake3_hash(copy)
+      assert CryptoNif.k12_hash(slice, "block", 32) == CryptoNif.k12_hash(copy, "block", 32)
+
+      {pk, sk} = CryptoNif.dilithium2_keypair()
+      signature = CryptoNif.dilithium2_sign(copy, sk)
+      assert CryptoNif.dilithium2_verify(signature, slice, pk)
+
+      ref = make_ref()
+      :ok = CryptoNif.dilithium2_sign_async(slice, sk, self(), ref)
+      assert_receive {^ref, async_signature}, 5_000
+      assert CryptoNif.dilithium2_verify(async_signature, copy, pk)
+    end
+  end
+
+  describe "library info" do
+    test "lists the algorithms and the allocator in use" do
+      assert "dilithium2" in CryptoNif.get_algorithm_info()
+      assert CryptoNif.allocator_info() in [:system, :mimalloc, :jemalloc]
+    end
+  end
+
+  describe "CPU features" do
+    test "reports detected features and the implementation in use" do
+      features = CryptoNif.cpu_features()
+
+      assert is_boolean(features.avx2) and is_boolean(features.avx512) and is_boolean(features.neon)
+      assert %{blake3: _, dilithium2: pq_backend, falcon512: pq_backend, sphincsplus: _, hex: _} = features.backends
+
+      if features.simd and features.avx2 do
+        assert pq_backend == :avx2
+      end
+
+      unless features.simd do
+        assert pq_backend == :clean
+      end
+    end
+  end
+
+  describe "public key handles" do
+    test "verify with a pre-parsed public key" do
+      {pk, sk} = CryptoNif.sphincsplus_shake_128f_keypair()
+      handle = CryptoNif.load_public_key(:sphincsplus, pk)
+      signature = CryptoNif.sphincsplus_shake_128f_sign("epoch 42", sk)
+
+      assert CryptoNif.sphincsplus_shake_128f_verify(signature, "epoch 42", handle)
+      refute CryptoNif.sphincsplus_shake_128f_verify(signature, "epoch 43", handle)
+      assert CryptoNif.batch_verify(:sphincsplus, [{signature, "epoch 42", handle}, {signature, "epoch 42", pk}])
+    end
+
+    test "rejects malformed keys and mismatched algorithms" do
+      {pk, _sk} = CryptoNif.dilithium2_keypair()
+      handle = CryptoNif.load_public_key(:dilithium2, pk)
+
+      assert_raise ArgumentError, fn -> CryptoNif.load_public_key(:falcon512, pk) end
+      assert_raise ArgumentError, fn -> CryptoNif.load_public_key(:dilithium2, "short") end
+      assert_raise ArgumentError, fn -> CryptoNif.falcon512_verify("sig", "msg", handle) end
+    end
+  end
+
+  describe "batch verification" do
+    test "verifies every item and reports failing indices" do
+      {pk, sk} = CryptoNif.falcon512_keypair()
+      {other_pk, _} = CryptoNif.falcon512_keypair()
+      items = for i <- 1..20, do: {CryptoNif.falcon512_sign("tx #{i}", sk), "tx #{i}", pk}
+
+      assert CryptoNif.batch_verify(:falcon512, items)
+      assert CryptoNif.batch_verify(:falcon512, items, failures: true) == []
+      assert CryptoNif.batch_verify(:falcon512, [], failures: true) == []
+
+      {sig, msg, _} = Enum.at(items, 3)
+      {sig7, _, _} = Enum.at(items, 7)
+      bad_items =
+        items
+        |> List.replace_at(3, {sig, msg, other_pk})
+        |> List.replace_at(7, {sig7, "tampered", pk})
+
+      refute CryptoNif.batch_verify(:falcon512, bad_items)
+      assert CryptoNif.batch_verify(:falcon512, bad_items, failures: true) == [3, 7]
+      refute CryptoNif.batch_verify(:dilithium2, items)
+    end
+
+    test "sign_many signs each message in order" do
+      {pk, sk} = CryptoNif.dilithium2_keypair()
+      {handle_pk, handle} = CryptoNif.sphincsplus_shake_128f_keypair_handle()
+      messages = for i <- 1..8, do: "vote #{i}"
+
+      signatures = CryptoNif.sign_many(:dilithium2, sk, messages)
+      assert length(signatures) == 8
+      assert CryptoNif.batch_verify(:dilithium2, Enum.zip_with(signatures, messages, &{&1, &2, pk}))
+
+      parallel = CryptoNif.sign_many(:sphincsplus, handle, messages, parallel: true)
+      assert CryptoNif.batch_verify(:sphincsplus, Enum.zip_with(parallel, messages, &{&1, &2, handle_pk}))
+      assert CryptoNif.sign_many(:falcon512, CryptoNif.falcon512_keypair() |> elem(1), []) == []
+    end
+
+    test "rejects unknown algorithms and options" do
+      assert_raise ArgumentError, fn -> CryptoNif.batch_verify(:rsa, []) end
+      assert_raise ArgumentError, fn -> CryptoNif.batch_verify(:falcon512, [], unknown: true) end
+      assert_raise ArgumentError, fn -> CryptoNif.sign_many(:falcon512, "not a key", ["msg"]) end
+    end
+  end
+
+  describe "aggregate attestations" do
+    test "aggregates and verifies signatures over a block digest" do
+      keys = for _ <- 1..10, do: CryptoNif.falcon512_keypair()
+      public_keys = Enum.map(keys, &elem(&1, 0))
+      digest = CryptoNif.blake3_hash("block 42")
+
+      signatures =
+        for index <- [7, 0, 3, 9], do: {index, CryptoNif.falcon512_sign(digest, keys |> Enum.at(index) |> elem(1))}
+
+      aggregate = CryptoNif.attestation_aggregate(:falcon512, digest, 10, signatures)
+      assert CryptoNif.attestation_verify(aggregate, public_keys) == {digest, [0, 3, 7, 9]}
+      assert CryptoNif.attestation_decode(aggregate) == {:falcon512, digest, 10, [0, 3, 7, 9]}
+
+      handles = Enum.map(public_keys, &CryptoNif.load_public_key(:falcon512, &1))
+      assert {^digest, [0, 3, 7, 9]} = CryptoNif.attestation_verify(aggregate, handles)
+    end
+
+    test "names the validators whose signatures fail" do
+      keys = for _ <- 1..4, do: CryptoNif.dilithium2_keypair()
+      public_keys = Enum.map(keys, &elem(&1, 0))
+      digest = CryptoNif.blake3_hash("block 43")
+      sign = fn index, message -> {index, CryptoNif.dilithium2_sign(message, keys |> Enum.at(index) |> elem(1))} end
+
+      signatures = [sign.(0, digest), sign.(1, "other"), sign.(3, "other")]
+      aggregate = CryptoNif.attestation_aggregate(:dilithium2, digest, 4, signatures)
+      assert CryptoNif.attestation_verify(aggregate, public_keys) == {:error, "invalid signatures from validators 1, 3"}
+      assert {:error, _} = CryptoNif.attestation_verify(aggregate, Enum.take(public_keys, 3))
+    end
+
+    test "rejects malformed input" do
+      digest = CryptoNif.blake3_hash("block")
+      assert_raise ArgumentError, fn -> CryptoNif.attestation_aggregate(:falcon512, digest, 4, []) end
+      assert_raise ArgumentError, fn -> CryptoNif.attestation_aggregate(:falcon512, digest, 4, [{4, "sig"}]) end
+      assert_raise ArgumentError, fn -> CryptoNif.attestation_aggregate(:falcon512, digest, 4, [{1, "a"}, {1, "b"}]) end
+      assert_raise ArgumentError, fn -> CryptoNif.attestation_aggregate(:falcon512, "short", 4, [{1, "sig"}]) end
+
+      aggregate = CryptoNif.attestation_aggregate(:falcon512, digest, 4, [{1, "sig"}])
+      assert {:error, _} = CryptoNif.attestation_decode(aggregate <> <<0>>)
+      assert {:error, _} = CryptoNif.attestation_decode(binary_part(aggregate, 0, byte_size(aggregate) - 1))
+    end
+  end
+
+  describe "yielding verification" do
+    test "verifies long batches across several timeslices" do
+      {pk, sk} = CryptoNif.dilithium2_keypair()
+      messages = for i <- 1..200, do: "attestation #{i}"
+      signatures = CryptoNif.sign_many(:dilithium2, sk, messages, parallel: true)
+      items = Enum.zip([signatures, messages, List.duplicate(pk, 200)])
+
+      assert CryptoNif.batch_verify_yielding(:dilithium2, items)
+      assert CryptoNif.batch_verify_yielding(:dilithium2, [])
+
+      tampered = List.replace_at(items, 150, {hd(signatures), "forged", pk})
+      refute CryptoNif.batch_verify_yielding(:dilithium2, tampered)
+    end
+
+    test "returns the unverified tail when it yields" do
+      {pk, sk} = CryptoNif.dilithium2_keypair()
+      items = for i <- 1..500, do: {CryptoNif.dilithium2_sign("vote #{i}", sk), "vote #{i}", pk}
+
+      case CryptoNif.batch_verify_slice(:dilithium2, items) do
+        {:cont, rest} -> assert length(rest) < 500 and rest == Enum.take(items, -length(rest))
+        true -> :ok
+      end
+    end
+
+    test "rejects malformed items" do
+      assert_raise ArgumentError, fn -> CryptoNif.batch_verify_yielding(:dilithium2, [:nope]) end
+    end
+  end
+
+  describe "verification sessions" do
+    test "verifies attestations by validator index" do
+      validators = for _ <- 1..4, do: CryptoNif.falcon512_keypair()
+      session = CryptoNif.verify_session_new(:falcon512, Enum.map(validators, &elem(&1, 0)))
+
+      items =
+        for {{_pk, sk}, index} <- Enum.with_index(validators), round <- 1..3 do
+          message = "round #{round}"
+          {message, CryptoNif.falcon512_sign(message, sk), index}
+        end
+
+      assert CryptoNif.verify_session_verify(session, items)
+      assert CryptoNif.verify_session_verify(session, Enum.take(items, 2))
+
+      # Attributed to the wrong validator
+      {message, signature, _} = hd(items)
+      assert CryptoNif.verify_session_verify(session, [hd(items), {message, signature, 3}], failures: true) == [1]
+      refute CryptoNif.verify_session_verify(session, [{message, signature, 1}])
+    end
+
+    test "rejects invalid keys and out-of-range indices" do
+      {pk, sk} = CryptoNif.dilithium2_keypair()
+      assert_raise ArgumentError, fn -> CryptoNif.verify_session_new(:dilithium2, [pk, "short"]) end
+
+      session = CryptoNif.verify_session_new(:dilithium2, [pk])
+      signature = CryptoNif.dilithium2_sign("m", sk)
+      assert_raise ArgumentError, fn -> CryptoNif.verify_session_verify(session, [{"m", signature, 1}]) end
+    end
+  end
+
+  describe "async signing" do
+    test "sends each signature back tagged with its reference" do
+      {pk, sk} = CryptoNif.falcon512_keypair()
+      refs =
+        for i <- 1..10 do
+          ref = make_ref()
+          assert CryptoNif.falcon512_sign_async("block #{i}", sk, self(), ref) == :ok
+          {ref, "block #{i}"}
+        end
+
+      for {ref, message} <- refs do
+        assert_receive {^ref, signature}, 5_000
+        assert CryptoNif.falcon512_verify(signature, message, pk)
+      end
+    end
+
+    test "accepts secret key handles" do
+      {pk, handle} = CryptoNif.dilithium2_keypair_handle()
+      ref = make_ref()
+
+      assert CryptoNif.dilithium2_sign_async("vote", handle, self(), ref) == :ok
+      assert_receive {^ref, signature}, 5_000
+      assert CryptoNif.dilithium2_verify(signature, "vote", pk)
+    end
+
+    test "rejects malformed keys up front" do
+      {_pk, sk} = CryptoNif.dilithium2_keypair()
+      assert_raise ArgumentError, fn -> CryptoNif.sphincsplus_shake_128f_sign_async("msg", sk, self(), make_ref()) end
+      assert_raise ArgumentError, fn -> CryptoNif.dilithium2_sign_async("msg", "short", self(), make_ref()) end
+    end
+  end
+
+  describe "thread pool" do
+    setup do
+      on_exit(fn -> CryptoNif.set_thread_pool(0) end)
+    end
+
+    test "resizes the pool used by batch and async APIs" do
+      assert CryptoNif.set_thread_pool(2) == :ok
+      assert CryptoNif.thread_pool_size() == 2
+
+      {pk, sk} = CryptoNif.falcon512_keypair()
+      messages = for i <- 1..8, do: "vote #{i}"
+      signatures = CryptoNif.sign_many(:falcon512, sk, messages, parallel: true)
+      assert CryptoNif.batch_verify(:falcon512, Enum.zip([signatures, messages, List.duplicate(pk, 8)]))
+
+      ref = make_ref()
+      assert CryptoNif.falcon512_sign_async("vote", sk, self(), ref) == :ok
+      assert_receive {^ref, signature}, 5_000
+      assert CryptoNif.falcon512_verify(signature, "vote", pk)
+    end
+
+    test "0 sizes the pool to the machine" do
+      assert CryptoNif.set_thread_pool(0) == :ok
+      assert CryptoNif.thread_pool_size() >= 1
+    end
+
+    test "rejects oversized pools" do
+      assert_raise ArgumentError, fn -> CryptoNif.set_thread_pool(100_000) end
+    end
+  end
+
+  describe "native call counters" do
+    test "count calls, failures and time per NIF" do
+      before = Map.get(CryptoNif.crypto_stats(), :sha3_256, %{calls: 0, failures: 0, total_ns: 0})
+
+      CryptoNif.sha3_256("one")
+      CryptoNif.sha3_256("two")
+      assert_raise ArgumentError, fn -> CryptoNif.load_public_key(:dilithium2, "short") end
+
+      stats = CryptoNif.crypto_stats()
+      # Other tests run concurrently, so only lower bounds hold
+      assert stats.sha3_256.calls >= before.calls + 2
+      assert stats.sha3_256.total_ns >= before.total_ns
+      assert stats.load_public_key.failures >= 1
+    end
+
+    test "cover every NIF" do
+      stats = CryptoNif.crypto_stats()
+
+      # NIFs of features this build may lack (see :crypto_nif_features) only
+      # have counters when compiled in
+      untracked =
+        for name <- nif_stub_names(),
+            not Map.has_key?(stats, name),
+            not String.starts_with?(Atom.to_string(name), ~w(randomx_ verkle_ storage_ wasm_ remote_signer_)),
+            do: name
+
+      assert untracked == []
+    end
+  end
+
+  describe "verification cache" do
+    setup do
+      :ok = CryptoNif.configure_verify_cache(1024)
+      on_exit(fn -> CryptoNif.configure_verify_cache(0) end)
+    end
+
+    test "serves repeated verifications from the cache" do
+      {pk, sk} = CryptoNif.dilithium2_keypair()
+      signature = CryptoNif.dilithium2_sign("gossiped tx", sk)
+
+      assert CryptoNif.dilithium2_verify(signature, "gossiped tx", pk)
+      %{hits: hits} = CryptoNif.verify_cache_stats()
+      assert CryptoNif.dilithium2_verify(signature, "gossiped tx", pk)
+      assert CryptoNif.batch_verify(:dilithium2, [{signature, "gossiped tx", pk}])
+
+      stats = CryptoNif.verify_cache_stats()
+      assert stats.hits >= hits + 2
+      assert stats.capacity == 1024
+      assert stats.entries >= 1
+    end
+
+    test "never caches invalid signatures" do
+      {pk, sk} = CryptoNif.falcon512_keypair()
+      signature = CryptoNif.falcon512_sign("tx", sk)
+
+      refute CryptoNif.falcon512_verify(signature, "other tx", pk)
+      refute CryptoNif.falcon512_verify(signature, "other tx", pk)
+      assert CryptoNif.falcon512_verify(signature, "tx", pk)
+    end
+
+    test "can be cleared and turned off" do
+      {pk, sk} = CryptoNif.falcon512_keypair()
+      assert CryptoNif.falcon512_verify(CryptoNif.falcon512_sign("tx", sk), "tx", pk)
+
+      assert CryptoNif.clear_verify_cache() == :ok
+      assert :ok = CryptoNif.configure_verify_cache(0)
+      assert %{entries: 0, capacity: 0} = CryptoNif.verify_cache_stats()
+      assert_raise ArgumentError, fn -> CryptoNif.configure_verify_cache(1_000_000_000) end
+    end
+  end
+
   describe "Blake3 hashing" do
     test "produces consistent hashes" do
       test_data = "Hello, Bastille Blockchain!"
@@ -25,42 +385,2660 @@ defmodule Bastille.Infrastructure.Crypto.CryptoNifTest do
       assert byte_size(hash1) == 32
       assert is_binary(hash1)
     end
-    
-    test "produces different hashes for different data" do
-      data1 = "Hello, World!"
-      data2 = "Hello, Bastille!"
-      
-      hash1 = CryptoNif.blake3_hash(data1)
-      hash2 = CryptoNif.blake3_hash(data2)
-      
-      assert hash1 != hash2
-      assert byte_size(hash1) == 32
-      assert byte_size(hash2) == 32
+    
+    test "produces different hashes for different data" do
+      data1 = "Hello, World!"
+      data2 = "Hello, Bastille!"
+      
+      hash1 = CryptoNif.blake3_hash(data1)
+      hash2 = CryptoNif.blake3_hash(data2)
+      
+      assert hash1 != hash2
+      assert byte_size(hash1) == 32
+      assert byte_size(hash2) == 32
+    end
+    
+    test "handles empty data" do
+      hash = CryptoNif.blake3_hash("")
+      
+      assert byte_size(hash) == 32
+      assert is_binary(hash)
+      
+      # Empty string should produce specific hash
+      assert hash != <<0::256>>
+    end
+    
+    test "multi-threaded hashing matches single-threaded hashing" do
+      data = :crypto.strong_rand_bytes(1_024 * 1_024)
+
+      parallel = CryptoNif.blake3_hash(data, parallel_threshold: 0)
+      sequential = CryptoNif.blake3_hash(data, parallel_threshold: byte_size(data) + 1)
+
+      assert parallel == sequential
+      assert parallel == CryptoNif.blake3_hash(data)
+      assert parallel == CryptoNif.blake3_hash_inline(data)
+      assert parallel == CryptoNif.blake3_hash_dirty(data, false)
+    end
+
+    test "rejects unknown options" do
+      assert_raise ArgumentError, fn -> CryptoNif.blake3_hash("data", threads: 4) end
+      assert_raise ArgumentError, fn -> CryptoNif.blake3_hash("data", parallel_threshold: -1) end
+    end
+
+    test "handles large data efficiently" do
+      # Create large test data (1MB)
+      large_data = String.duplicate("A", 1_024 * 1_024)
+      
+      start_time = System.monotonic_time(:millisecond)
+      hash = CryptoNif.blake3_hash(large_data)
+      end_time = System.monotonic_time(:millisecond)
+      
+      duration = end_time - start_time
+      
+      # Should complete quickly (less than 1 second for 1MB)
+      assert duration < 1_000
+      assert byte_size(hash) == 32
+    end
+  end
+
+  describe "domain-separated hashing" do
+    test "uses a length-prefixed domain" do
+      domain = "bastille/tx-id"
+      data = "transaction bytes"
+
+      assert CryptoNif.tagged_hash(domain, data) ==
+               CryptoNif.blake3_hash(<<byte_size(domain)>> <> domain <> data)
+    end
+
+    test "domain boundaries can't be shifted into the data" do
+      refute CryptoNif.tagged_hash("bastille/tx", "-id" <> "payload") ==
+               CryptoNif.tagged_hash("bastille/tx-id", "payload")
+    end
+
+    test "rejects empty, oversized or non-ASCII domains" do
+      assert_raise ArgumentError, fn -> CryptoNif.tagged_hash("", "data") end
+      assert_raise ArgumentError, fn -> CryptoNif.tagged_hash(:binary.copy("d", 256), "data") end
+      assert_raise ArgumentError, fn -> CryptoNif.tagged_hash("bastille/vöte", "data") end
+    end
+  end
+
+  describe "Blake3 yielding hashing" do
+    test "matches the one-shot hash for large inputs" do
+      data = :crypto.strong_rand_bytes(8 * 1_024 * 1_024)
+
+      assert CryptoNif.blake3_hash_yielding(data) == CryptoNif.blake3_hash(data)
+    end
+
+    test "incremental updates across several binaries" do
+      hasher = CryptoNif.blake3_hasher_new()
+
+      for part <- ["Hello, ", "Bastille ", "Blockchain!"] do
+        :ok = CryptoNif.blake3_hasher_update(hasher, part, 0)
+      end
+
+      assert CryptoNif.blake3_hasher_finalize(hasher) ==
+               CryptoNif.blake3_hash("Hello, Bastille Blockchain!")
+    end
+
+    test "rejects offsets past the end of the data" do
+      hasher = CryptoNif.blake3_hasher_new()
+
+      assert_raise ArgumentError, fn -> CryptoNif.blake3_hasher_update(hasher, "abc", 4) end
+    end
+  end
+
+  describe "Blake3 extendable output" do
+    test "produces digests of the requested length" do
+      digest = CryptoNif.blake3_hash_xof("commitment", 64)
+
+      assert byte_size(digest) == 64
+      assert binary_part(digest, 0, 32) == CryptoNif.blake3_hash("commitment")
+    end
+
+    test "shorter outputs are prefixes of longer ones" do
+      long = CryptoNif.blake3_hash_xof("key material", 128)
+      short = CryptoNif.blake3_hash_xof("key material", 16)
+
+      assert binary_part(long, 0, 16) == short
+    end
+
+    test "rejects oversized output lengths" do
+      assert_raise ArgumentError, fn ->
+        CryptoNif.blake3_hash_xof("data", 2 * 1024 * 1024)
+      end
+    end
+  end
+
+  describe "Blake3 keyed hashing and key derivation" do
+    test "keyed hash depends on the key" do
+      key1 = :binary.copy(<<1>>, 32)
+      key2 = :binary.copy(<<2>>, 32)
+
+      mac1 = CryptoNif.blake3_keyed_hash(key1, "payload")
+      mac2 = CryptoNif.blake3_keyed_hash(key2, "payload")
+
+      assert byte_size(mac1) == 32
+      assert mac1 != mac2
+      assert mac1 != CryptoNif.blake3_hash("payload")
+    end
+
+    test "keyed hash rejects keys that are not 32 bytes" do
+      assert_raise ArgumentError, fn ->
+        CryptoNif.blake3_keyed_hash("short key", "payload")
+      end
+    end
+
+    test "derive_key separates contexts" do
+      material = "master seed material"
+
+      key1 = CryptoNif.blake3_derive_key("bastille test dilithium2", material)
+      key2 = CryptoNif.blake3_derive_key("bastille test falcon512", material)
+
+      assert byte_size(key1) == 32
+      assert key1 != key2
+      assert key1 == CryptoNif.blake3_derive_key("bastille test dilithium2", material)
+    end
+  end
+
+  describe "proof of work" do
+    test "finds the lowest nonce meeting the target, as Mining hashes blocks" do
+      header = "block header"
+      target = <<0, 0x0F>> <> :binary.copy(<<0xFF>>, 30)
+
+      nonce = CryptoNif.pow_mine(header, target, 0, 1_000_000)
+      assert is_integer(nonce)
+      assert CryptoNif.blake3_hash(header <> <<nonce::little-64>>) <= target
+      assert CryptoNif.pow_verify(header, nonce, target)
+
+      for earlier <- 0..(nonce - 1)//1 do
+        refute CryptoNif.pow_verify(header, earlier, target)
+      end
+
+      assert CryptoNif.pow_mine(header, target, nonce, 1) == nonce
+      assert CryptoNif.pow_mine(header, target, nonce + 1, 1_000_000) > nonce
+    end
+
+    test "returns :exhausted without a solution in range" do
+      assert CryptoNif.pow_mine("header", <<0::256>>, 0, 10_000) == :exhausted
+      assert CryptoNif.pow_mine("header", :binary.copy(<<0xFF>>, 32), 0, 0) == :exhausted
+      assert CryptoNif.pow_mine("header", :binary.copy(<<0xFF>>, 32), 18_446_744_073_709_551_615, 10) == :exhausted
+      refute CryptoNif.pow_verify("header", 0, <<0::256>>)
+
+      assert_raise ArgumentError, fn -> CryptoNif.pow_mine("header", <<0>>, 0, 1) end
+    end
+  end
+
+  describe "difficulty targets" do
+    test "converts compact bits as Bitcoin does, canonical encodings only" do
+      assert CryptoNif.target_from_compact(0x1D00FFFF) == <<0, 0, 0, 0, 0xFF, 0xFF>> <> <<0::208>>
+      assert CryptoNif.target_from_compact(0x05009234) == <<0x92340000::256>>
+      assert CryptoNif.target_to_compact(<<0x80::256>>) == 0x02008000
+      assert CryptoNif.target_to_compact(<<0::256>>) == 0
+
+      for bits <- [0x04923456, 0xFF123456, 0x01003456, 0x01123456] do
+        assert {:error, _} = CryptoNif.target_from_compact(bits)
+      end
+    end
+
+    test "compares hashes against targets" do
+      target = CryptoNif.target_from_compact(0x1D00FFFF)
+      assert CryptoNif.target_meets(target, target)
+      assert CryptoNif.target_meets(<<0::256>>, target)
+      refute CryptoNif.target_meets(<<1::256>>, <<0::256>>)
+      assert_raise ArgumentError, fn -> CryptoNif.target_meets("short", target) end
+    end
+
+    test "retargets as Bitcoin's get_next_work cases do" do
+      limit = CryptoNif.target_from_compact(0x1D00FFFF)
+      two_weeks = 14 * 24 * 60 * 60
+
+      for {first, last, bits, expected} <- [
+            {1_261_130_161, 1_262_152_739, 0x1D00FFFF, 0x1D00D86A},
+            {1_231_006_505, 1_233_061_996, 0x1D00FFFF, 0x1D00FFFF},
+            {1_279_008_237, 1_279_297_671, 0x1C05A3F4, 0x1C0168FD},
+            {1_263_163_443, 1_269_211_443, 0x1C387F6F, 0x1D00E1FD}
+          ] do
+        target = CryptoNif.target_from_compact(bits)
+        next = CryptoNif.next_target(target, first, last, two_weeks, max_target: limit)
+        assert CryptoNif.target_to_compact(next) == expected
+      end
+
+      target = <<1000::256>>
+      assert CryptoNif.next_target(target, 0, 10, 10) == target
+      assert CryptoNif.next_target(target, 0, 1_000, 10, max_factor: 2) == <<2000::256>>
+      assert_raise ArgumentError, fn -> CryptoNif.next_target(target, 0, 10, 0) end
+    end
+  end
+
+  describe "RandomX" do
+    # Needs the NIF built with the randomx feature: mix test --include randomx
+    @describetag :randomx
+
+    test "matches the RandomX reference vectors in light mode" do
+      cache = CryptoNif.randomx_init_cache("test key 000")
+      other = CryptoNif.randomx_init_cache("test key 001")
+      lorem = "sed do eiusmod tempor incididunt ut labore et dolore magna aliqua"
+
+      assert Base.encode16(CryptoNif.randomx_hash(cache, "This is a test"), case: :lower) ==
+               "639183aae1bf4c9a35884cb46b09cad9175f04efd7684e7262a0ac1c2f0b4e3f"
+
+      assert Base.encode16(CryptoNif.randomx_hash(cache, lorem), case: :lower) ==
+               "c36d4ed4191e617309867ed66a443be4075014e2b061bcdaf9ce7b721d2b77a8"
+
+      assert Base.encode16(CryptoNif.randomx_hash(other, lorem), case: :lower) ==
+               "e9ff4503201c0c2cca26d285c93ae883f9b1d30c9eb240b820756f2d5a7905fc"
+
+      assert_raise ArgumentError, fn -> CryptoNif.randomx_init_cache("") end
+      assert_raise ArgumentError, fn -> CryptoNif.randomx_hash(cache, "") end
+    end
+
+    test "verifies nonces against targets, in parallel" do
+      cache = CryptoNif.randomx_init_cache("test key 000")
+      header = "block header"
+      hash = CryptoNif.randomx_hash(cache, header <> <<42::little-64>>)
+
+      assert CryptoNif.randomx_verify(cache, header, 42, hash)
+      assert CryptoNif.randomx_verify(cache, header, 42, :binary.copy(<<0xFF>>, 32))
+      refute CryptoNif.randomx_verify(cache, header, 42, <<0::256>>)
+
+      hashes =
+        1..8
+        |> Task.async_stream(fn nonce -> CryptoNif.randomx_hash(cache, header <> <<nonce::little-64>>) end)
+        |> Enum.map(fn {:ok, hash} -> hash end)
+
+      assert hashes == Enum.map(1..8, &CryptoNif.randomx_hash(cache, header <> <<&1::little-64>>))
+    end
+  end
+
+  describe "BLAKE2b hashing" do
+    test "unkeyed 64-byte digest agrees with :crypto" do
+      data = "Bastille BLAKE2b interop"
+
+      assert CryptoNif.blake2b(data, 64, "", "", "") == :crypto.hash(:blake2b, data)
+    end
+
+    test "personalization and salt change the digest" do
+      plain = CryptoNif.blake2b("note", 32, "", "", "")
+      personalized = CryptoNif.blake2b("note", 32, "", "", "ZcashComputehSig")
+      salted = CryptoNif.blake2b("note", 32, "", "0123456789abcdef", "ZcashComputehSig")
+
+      assert byte_size(personalized) == 32
+      assert length(Enum.uniq([plain, personalized, salted])) == 3
+    end
+
+    test "rejects out-of-range parameters" do
+      assert_raise ArgumentError, fn -> CryptoNif.blake2b("x", 65, "", "", "") end
+      assert_raise ArgumentError, fn -> CryptoNif.blake2b("x", 32, "", "", :binary.copy("p", 17)) end
+    end
+  end
+
+  describe "SHA-3 hashing" do
+    test "matches the FIPS 202 test vectors for the empty string" do
+      assert Base.encode16(CryptoNif.sha3_256(""), case: :lower) ==
+               "a7ffc6f8bf1ed76651c14756a061d662f580ff4de43b49fa82d80a4b80f8434a"
+
+      assert Base.encode16(CryptoNif.shake128("", 16), case: :lower) ==
+               "7f9c2ba4e88f827d616045507605853e"
+    end
+
+    test "agrees with :crypto for SHA3-512" do
+      data = "Bastille SHA-3 interop"
+
+      assert CryptoNif.sha3_512(data) == :crypto.hash(:sha3_512, data)
+    end
+
+    test "Keccak-256 uses the legacy padding" do
+      assert Base.encode16(CryptoNif.keccak256(""), case: :lower) ==
+               "c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470"
+
+      assert CryptoNif.keccak256("") != CryptoNif.sha3_256("")
+    end
+
+    test "SHAKE256 supports arbitrary output lengths" do
+      assert byte_size(CryptoNif.shake256("seed", 100)) == 100
+      assert binary_part(CryptoNif.shake256("seed", 100), 0, 32) == CryptoNif.shake256("seed", 32)
+    end
+  end
+
+  describe "SHA-2 hashing" do
+    test "agrees with :crypto" do
+      data = "Bastille SHA-2 interop"
+
+      assert CryptoNif.sha256(data) == :crypto.hash(:sha256, data)
+      assert CryptoNif.sha512(data) == :crypto.hash(:sha512, data)
+    end
+
+    test "double SHA-256 hashes twice" do
+      data = "block header"
+
+      assert CryptoNif.sha256d(data) == :crypto.hash(:sha256, :crypto.hash(:sha256, data))
+    end
+  end
+
+  describe "KangarooTwelve hashing" do
+    test "matches the RFC 9861 test vector for the empty message" do
+      assert Base.encode16(CryptoNif.k12_hash("", "", 32), case: :lower) ==
+               "1ac2d450fc3b4205d19da7bfca1b37513c0803577ac7167f06fe2ce1f0ef39e5"
+    end
+
+    test "customization separates digests" do
+      data = :binary.copy("snapshot", 10_000)
+
+      assert CryptoNif.k12_hash(data, "state", 32) != CryptoNif.k12_hash(data, "blocks", 32)
+      assert CryptoNif.k12_hash(data, "state", 32) == CryptoNif.k12_hash(data, "state", 32)
+    end
+  end
+
+  describe "Merkle roots" do
+    test "hashes leaves and nodes under distinct tags" do
+      leaf = fn data -> CryptoNif.blake3_hash(<<0>> <> data) end
+      node = fn left, right -> CryptoNif.blake3_hash(<<1>> <> left <> right) end
+
+      assert CryptoNif.merkle_root([]) == <<0::256>>
+      assert CryptoNif.merkle_root(["a"]) == leaf.("a")
+      assert CryptoNif.merkle_root(["a", "b"]) == node.(leaf.("a"), leaf.("b"))
+      assert CryptoNif.merkle_root(["a", "b", "c"]) == node.(node.(leaf.("a"), leaf.("b")), leaf.("c"))
+      assert CryptoNif.merkle_root(["a", "b", "c"]) != CryptoNif.merkle_root(["a", "b", "c", "c"])
+    end
+
+    test "parallel hashing of large trees matches a sequential reference" do
+      leaves = for i <- 1..5_001, do: "tx #{i}"
+
+      assert CryptoNif.merkle_root(leaves) == reference_merkle_root(leaves)
+    end
+  end
+
+  describe "Merkle proofs" do
+    test "every leaf proves its inclusion under the root" do
+      leaves = for i <- 1..13, do: "tx #{i}"
+      {root, tree} = CryptoNif.merkle_build(leaves)
+
+      assert root == CryptoNif.merkle_root(leaves)
+
+      for {leaf, index} <- Enum.with_index(leaves) do
+        proof = CryptoNif.merkle_proof(tree, index)
+        assert CryptoNif.merkle_verify(root, leaf, proof)
+        refute CryptoNif.merkle_verify(root, "tx 99", proof)
+      end
+    end
+
+    test "rejects tampered proofs and out-of-range indices" do
+      {root, tree} = CryptoNif.merkle_build(["a", "b", "c", "d"])
+      <<side, sibling::binary-size(32), rest::binary>> = CryptoNif.merkle_proof(tree, 0)
+
+      refute CryptoNif.merkle_verify(root, "a", <<1 - side, sibling::binary, rest::binary>>)
+      refute CryptoNif.merkle_verify(root, "a", binary_part(rest, 0, 32))
+      assert_raise ArgumentError, fn -> CryptoNif.merkle_proof(tree, 4) end
+    end
+
+    test "one multiproof covers several leaves in fewer bytes" do
+      leaves = for i <- 1..64, do: "tx #{i}"
+      {root, tree} = CryptoNif.merkle_build(leaves)
+      indices = [3, 4, 5, 6, 40]
+      proven = for index <- indices, do: {index, Enum.at(leaves, index)}
+
+      proof = CryptoNif.merkle_multiproof(tree, Enum.reverse(indices))
+      assert CryptoNif.merkle_verify_multiproof(root, proven, proof)
+
+      separate = indices |> Enum.map(&byte_size(CryptoNif.merkle_proof(tree, &1))) |> Enum.sum()
+      assert byte_size(proof) < separate / 2
+
+      refute CryptoNif.merkle_verify_multiproof(root, [{3, "forged"} | tl(proven)], proof)
+      refute CryptoNif.merkle_verify_multiproof(root, tl(proven), proof)
+      refute CryptoNif.merkle_verify_multiproof(root, [{3, "tx 4"}, {3, "tx 4"} | tl(proven)], proof)
+    end
+
+    test "rejects empty and out-of-range multiproof requests" do
+      {_root, tree} = CryptoNif.merkle_build(["a", "b", "c"])
+      assert_raise ArgumentError, fn -> CryptoNif.merkle_multiproof(tree, []) end
+      assert_raise ArgumentError, fn -> CryptoNif.merkle_multiproof(tree, [0, 3]) end
+    end
+  end
+
+  describe "incremental Merkle trees" do
+    test "the root matches the full fixed-depth tree over the appended leaves" do
+      tree = CryptoNif.imt_new(4)
+      deposits = for i <- 1..5, do: "deposit #{i}"
+      assert Enum.map(deposits, &CryptoNif.imt_append(tree, &1)) == Enum.to_list(0..4)
+      assert CryptoNif.imt_count(tree) == 5
+
+      padded = Enum.map(deposits, &CryptoNif.blake3_hash(<<0>> <> &1)) ++ List.duplicate(<<0::256>>, 11)
+      tree_root = reduce_merkle_levels(padded)
+      count_block = <<5::little-64, 0::192>>
+
+      assert CryptoNif.imt_root(tree) == CryptoNif.blake3_hash(<<1>> <> tree_root <> count_block)
+    end
+
+    test "snapshots resume appending with the same roots" do
+      tree = CryptoNif.imt_new(32)
+      for i <- 1..10, do: CryptoNif.imt_append(tree, "deposit #{i}")
+
+      restored = CryptoNif.imt_restore(CryptoNif.imt_snapshot(tree))
+      assert CryptoNif.imt_root(restored) == CryptoNif.imt_root(tree)
+
+      for t <- [tree, restored], do: 10 = CryptoNif.imt_append(t, "deposit 11")
+      assert CryptoNif.imt_root(restored) == CryptoNif.imt_root(tree)
+
+      assert_raise ArgumentError, fn -> CryptoNif.imt_restore(binary_part(CryptoNif.imt_snapshot(tree), 0, 40)) end
+    end
+
+    test "fills up and rejects invalid depths" do
+      tree = CryptoNif.imt_new(2)
+      for i <- 0..2, do: ^i = CryptoNif.imt_append(tree, "leaf #{i}")
+      assert {:error, _} = CryptoNif.imt_append(tree, "leaf 3")
+
+      assert_raise ArgumentError, fn -> CryptoNif.imt_new(0) end
+      assert_raise ArgumentError, fn -> CryptoNif.imt_new(64) end
+    end
+  end
+
+  describe "Merkle mountain ranges" do
+    test "every appended header proves its position under the latest root" do
+      mmr = CryptoNif.mmr_new()
+      assert CryptoNif.mmr_root(mmr) == <<0::256>>
+
+      headers = for i <- 0..20, do: "header #{i}"
+      assert Enum.map(headers, &CryptoNif.mmr_append(mmr, &1)) == Enum.to_list(0..20)
+      root = CryptoNif.mmr_root(mmr)
+
+      for {header, index} <- Enum.with_index(headers) do
+        proof = CryptoNif.mmr_proof(mmr, index)
+        assert CryptoNif.mmr_verify(root, index, header, proof)
+        refute CryptoNif.mmr_verify(root, rem(index + 1, 21), header, proof)
+        refute CryptoNif.mmr_verify(root, index, "forged header", proof)
+      end
+    end
+
+    test "the root moves with every append and out-of-range proofs raise" do
+      mmr = CryptoNif.mmr_new()
+      0 = CryptoNif.mmr_append(mmr, "genesis")
+      before = CryptoNif.mmr_root(mmr)
+      proof = CryptoNif.mmr_proof(mmr, 0)
+
+      1 = CryptoNif.mmr_append(mmr, "block 1")
+      refute CryptoNif.mmr_root(mmr) == before
+      refute CryptoNif.mmr_verify(CryptoNif.mmr_root(mmr), 0, "genesis", proof)
+      assert_raise ArgumentError, fn -> CryptoNif.mmr_proof(mmr, 2) end
+    end
+  end
+
+  describe "sparse Merkle tree" do
+    @describetag :tmp_dir
+
+    test "proves inclusion and non-inclusion against the root", %{tmp_dir: tmp_dir} do
+      tree = CryptoNif.smt_open(tmp_dir)
+      empty_root = CryptoNif.smt_root(tree)
+      assert empty_root == <<0::256>>
+
+      for i <- 1..50, do: :ok = CryptoNif.smt_put(tree, "account #{i}", <<i::64>>)
+      root = CryptoNif.smt_root(tree)
+
+      assert CryptoNif.smt_get(tree, "account 7") == <<7::64>>
+      assert CryptoNif.smt_get(tree, "account 99") == nil
+
+      proof = CryptoNif.smt_prove(tree, "account 7")
+      assert CryptoNif.smt_verify(root, "account 7", <<7::64>>, proof)
+      refute CryptoNif.smt_verify(root, "account 7", <<8::64>>, proof)
+      refute CryptoNif.smt_verify(root, "account 7", nil, proof)
+
+      absent = CryptoNif.smt_prove(tree, "account 99")
+      assert CryptoNif.smt_verify(root, "account 99", nil, absent)
+      refute CryptoNif.smt_verify(root, "account 99", <<99::64>>, absent)
+
+      :ok = CryptoNif.smt_delete(tree, "account 7")
+      assert CryptoNif.smt_get(tree, "account 7") == nil
+      assert CryptoNif.smt_verify(CryptoNif.smt_root(tree), "account 7", nil, CryptoNif.smt_prove(tree, "account 7"))
+    end
+
+    test "the root depends only on the contents", %{tmp_dir: tmp_dir} do
+      a = CryptoNif.smt_open(Path.join(tmp_dir, "a"))
+      b = CryptoNif.smt_open(Path.join(tmp_dir, "b"))
+
+      for i <- 1..20, do: CryptoNif.smt_put(a, "k#{i}", "v#{i}")
+      for i <- 25..1//-1, do: CryptoNif.smt_put(b, "k#{i}", "v#{i}")
+      for i <- 21..25, do: CryptoNif.smt_delete(b, "k#{i}")
+
+      assert CryptoNif.smt_root(a) == CryptoNif.smt_root(b)
+    end
+
+    test "survives reopening and refuses a second opener", %{tmp_dir: tmp_dir} do
+      # The tree lives in a short-lived process, so its lock goes with it
+      root =
+        Task.async(fn ->
+          tree = CryptoNif.smt_open(tmp_dir)
+          :ok = CryptoNif.smt_put(tree, "balance", "100")
+          :ok = CryptoNif.smt_flush(tree)
+          assert {:error, _} = CryptoNif.smt_open(tmp_dir)
+          CryptoNif.smt_root(tree)
+        end)
+        |> Task.await()
+
+      reopened = wait_for_reopen(tmp_dir, 50)
+      assert CryptoNif.smt_get(reopened, "balance") == "100"
+      assert CryptoNif.smt_root(reopened) == root
+    end
+
+    test "the jellyfish backend keeps every committed version", %{tmp_dir: tmp_dir} do
+      tree = CryptoNif.smt_open(Path.join(tmp_dir, "jmt"), backend: :jellyfish)
+      sparse = CryptoNif.smt_open(Path.join(tmp_dir, "smt"))
+
+      roots =
+        for height <- 1..5 do
+          for t <- [tree, sparse], i <- 1..10, do: :ok = CryptoNif.smt_put(t, "account #{i}", "#{i * height}")
+          if height == 3, do: for(t <- [tree, sparse], do: CryptoNif.smt_delete(t, "account 1"))
+
+          root = CryptoNif.smt_commit(tree, height * 100)
+          assert root == CryptoNif.smt_root(sparse)
+          root
+        end
+
+      for {root, height} <- Enum.with_index(roots, 1) do
+        assert CryptoNif.smt_root(tree, height * 100 + 50) == root
+        value = CryptoNif.smt_get(tree, "account 2", height * 100)
+        assert value == "#{2 * height}"
+        assert CryptoNif.smt_verify(root, "account 2", value, CryptoNif.smt_prove(tree, "account 2", height * 100))
+      end
+
+      assert CryptoNif.smt_get(tree, "account 1", 200) == "2"
+      assert CryptoNif.smt_get(tree, "account 1", 300) == nil
+      assert {:error, _} = CryptoNif.smt_root(tree, 99)
+      assert {:error, _} = CryptoNif.smt_commit(tree, 500)
+    end
+
+    test "versions are only available on the jellyfish backend", %{tmp_dir: tmp_dir} do
+      tree = CryptoNif.smt_open(tmp_dir, backend: :sparse)
+      :ok = CryptoNif.smt_put(tree, "balance", "100")
+
+      assert {:error, _} = CryptoNif.smt_commit(tree, 1)
+      assert {:error, _} = CryptoNif.smt_root(tree, 1)
+      assert_raise ArgumentError, fn -> CryptoNif.smt_open(tmp_dir, backend: :avl) end
+    end
+  end
+
+  describe "Verkle tree" do
+    # Needs the NIF built with the verkle feature: mix test --include verkle
+    @describetag :verkle
+
+    test "proves inclusion and non-inclusion against the root" do
+      tree = CryptoNif.verkle_new()
+      empty_root = CryptoNif.verkle_root(tree)
+
+      for i <- 1..50, do: :ok = CryptoNif.verkle_put(tree, "account #{i}", <<i::64>>)
+      root = CryptoNif.verkle_root(tree)
+      assert byte_size(root) == 32
+
+      assert CryptoNif.verkle_get(tree, "account 7") == <<7::64>>
+      assert CryptoNif.verkle_get(tree, "account 99") == nil
+
+      proof = CryptoNif.verkle_prove(tree, "account 7")
+      assert CryptoNif.verkle_verify(root, "account 7", <<7::64>>, proof)
+      refute CryptoNif.verkle_verify(root, "account 7", <<8::64>>, proof)
+      refute CryptoNif.verkle_verify(root, "account 7", nil, proof)
+      refute CryptoNif.verkle_verify(empty_root, "account 7", <<7::64>>, proof)
+
+      absent = CryptoNif.verkle_prove(tree, "account 99")
+      assert CryptoNif.verkle_verify(root, "account 99", nil, absent)
+      refute CryptoNif.verkle_verify(root, "account 99", <<99::64>>, absent)
+
+      for i <- 1..50, do: :ok = CryptoNif.verkle_delete(tree, "account #{i}")
+      assert CryptoNif.verkle_root(tree) == empty_root
+    end
+
+    test "the root depends only on the contents" do
+      a = CryptoNif.verkle_new()
+      b = CryptoNif.verkle_new()
+
+      for i <- 1..20, do: CryptoNif.verkle_put(a, "k#{i}", "v#{i}")
+      for i <- 25..1//-1, do: CryptoNif.verkle_put(b, "k#{i}", "v#{i}")
+      for i <- 21..25, do: CryptoNif.verkle_delete(b, "k#{i}")
+
+      assert CryptoNif.verkle_root(a) == CryptoNif.verkle_root(b)
+    end
+  end
+
+  describe "Merkle Patricia Trie" do
+    test "matches Ethereum's trie test vectors" do
+      empty = CryptoNif.mpt_new()
+
+      assert Base.encode16(CryptoNif.mpt_root(empty), case: :lower) ==
+               "56e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b421"
+
+      trie = CryptoNif.mpt_new()
+      for {key, value} <- [{"doe", "reindeer"}, {"dog", "puppy"}, {"dogglesworth", "cat"}],
+          do: :ok = CryptoNif.mpt_insert(trie, key, value)
+
+      assert Base.encode16(CryptoNif.mpt_root(trie), case: :lower) ==
+               "8aad789dff2f538bca5d8ea56e8abe10f4c7ba3a5dea95fea4cd6e7c3a1168d3"
+
+      assert CryptoNif.mpt_get(trie, "dog") == "puppy"
+      assert CryptoNif.mpt_get(trie, "do") == nil
+    end
+
+    test "proves inclusion and absence against the root" do
+      trie = CryptoNif.mpt_new()
+      keys = for i <- 1..200, do: CryptoNif.sha3_256(<<i::32>>)
+      for {key, i} <- Enum.with_index(keys, 1), do: :ok = CryptoNif.mpt_insert(trie, key, <<i::32>>)
+      root = CryptoNif.mpt_root(trie)
+
+      key = Enum.at(keys, 41)
+      proof = CryptoNif.mpt_proof(trie, key)
+      assert CryptoNif.mpt_verify_proof(root, key, proof) == <<42::32>>
+
+      absent = CryptoNif.sha3_256("absent")
+      assert CryptoNif.mpt_verify_proof(root, absent, CryptoNif.mpt_proof(trie, absent)) == nil
+
+      assert {:error, _} = CryptoNif.mpt_verify_proof(:crypto.strong_rand_bytes(32), key, proof)
+      assert {:error, _} = CryptoNif.mpt_verify_proof(root, key, Enum.drop(proof, -1))
+    end
+
+    test "rejects empty values and malformed roots" do
+      trie = CryptoNif.mpt_new()
+      assert_raise ArgumentError, fn -> CryptoNif.mpt_insert(trie, "key", "") end
+      assert_raise ArgumentError, fn -> CryptoNif.mpt_verify_proof(<<0::128>>, "key", []) end
+    end
+  end
+
+  describe "Poseidon hashing" do
+    test "matches circomlib Poseidon([1, 2])" do
+      assert Base.encode16(CryptoNif.poseidon_hash([<<1::256>>, <<2::256>>]), case: :lower) ==
+               "115cc0f5e7d690413df64c6b9662e9cf2a3617f2743245519e19607a4417189a"
+    end
+
+    test "rejects elements outside the field" do
+      assert_raise ArgumentError, fn -> CryptoNif.poseidon_hash([:binary.copy(<<255>>, 32)]) end
+      assert_raise ArgumentError, fn -> CryptoNif.poseidon_hash([<<1>>]) end
+      assert_raise ArgumentError, fn -> CryptoNif.poseidon_hash([]) end
+    end
+
+    test "sponge binds the input length" do
+      assert byte_size(CryptoNif.poseidon_sponge("")) == 32
+      assert CryptoNif.poseidon_sponge(<<0>>) != CryptoNif.poseidon_sponge(<<0, 0>>)
+      assert CryptoNif.poseidon_sponge(:binary.copy("a", 500)) ==
+               CryptoNif.poseidon_sponge(:binary.copy("a", 500))
+    end
+  end
+
+  describe "file hashing" do
+    @tag :tmp_dir
+    test "hashes files on disk like in-memory data", %{tmp_dir: tmp_dir} do
+      path = Path.join(tmp_dir, "archive.bin")
+      data = :crypto.strong_rand_bytes(3 * 1_024 * 1_024 + 17)
+      File.write!(path, data)
+
+      assert CryptoNif.blake3_hash_file(path) == CryptoNif.blake3_hash(data)
+      assert CryptoNif.sha256_file(path) == :crypto.hash(:sha256, data)
+    end
+
+    test "returns an error for missing files" do
+      assert {:error, _reason} = CryptoNif.blake3_hash_file("/nonexistent/bastille.bin")
+      assert {:error, _reason} = CryptoNif.sha256_file("/nonexistent/bastille.bin")
+    end
+  end
+
+  describe "RLP" do
+    test "round-trips the Ethereum wiki examples" do
+      examples = [
+        {"dog", "83646F67"},
+        {["cat", "dog"], "C88363617483646F67"},
+        {"", "80"},
+        {[[], [[]], [[], [[]]]], "C7C0C1C0C3C0C1C0"},
+        {:binary.copy("a", 56), "B838" <> String.duplicate("61", 56)}
+      ]
+
+      for {term, hex} <- examples do
+        assert CryptoNif.rlp_encode(term) == Base.decode16!(hex)
+        assert CryptoNif.rlp_decode(Base.decode16!(hex)) == term
+      end
+
+      assert CryptoNif.rlp_encode(0) == <<0x80>>
+      assert CryptoNif.rlp_encode(15) == <<0x0F>>
+      assert CryptoNif.rlp_encode([1024]) == <<0xC3, 0x82, 0x04, 0x00>>
+      assert_raise ArgumentError, fn -> CryptoNif.rlp_encode(-1) end
+      assert_raise ArgumentError, fn -> CryptoNif.rlp_encode([:atom]) end
+    end
+
+    test "rejects non-canonical input and input over the limits" do
+      for hex <- ["8100", "B80401020304", "C0C0", "C1"] do
+        assert {:error, _} = CryptoNif.rlp_decode(Base.decode16!(hex))
+      end
+
+      nested = Base.decode16!("C7C0C1C0C3C0C1C0")
+      assert {:error, _} = CryptoNif.rlp_decode(nested, max_depth: 3)
+      assert CryptoNif.rlp_decode(nested, max_depth: 4) == [[], [[]], [[], [[]]]]
+      assert {:error, _} = CryptoNif.rlp_decode(nested, max_size: 7)
+      assert_raise ArgumentError, fn -> CryptoNif.rlp_decode(nested, max_depth: 65) end
+    end
+  end
+
+  describe "hex" do
+    test "matches Base.encode16 and Base.decode16 on hashes and odd lengths" do
+      for len <- [0, 1, 15, 16, 17, 32, 33, 100] do
+        data = :crypto.strong_rand_bytes(len)
+        lower = Base.encode16(data, case: :lower)
+
+        assert CryptoNif.hex_encode(data) == lower
+        assert CryptoNif.hex_encode(data, case: :upper) == Base.encode16(data)
+        assert CryptoNif.hex_encode(data, prefix: true) == "0x" <> lower
+        assert CryptoNif.hex_decode(lower) == data
+        assert CryptoNif.hex_decode(Base.encode16(data)) == data
+        assert CryptoNif.hex_decode("0x" <> lower, prefix: :optional) == data
+        assert CryptoNif.hex_decode(lower, prefix: :optional) == data
+      end
+    end
+
+    test "rejects odd lengths, non-digits, the wrong case and prefix" do
+      hash = String.duplicate("ab", 32)
+      assert {:error, _} = CryptoNif.hex_decode("abc")
+      assert {:error, "invalid hex digit at position 40"} = CryptoNif.hex_decode(String.replace_at(hash, 40, "g"))
+      assert {:error, _} = CryptoNif.hex_decode(hash, case: :upper)
+      assert {:error, _} = CryptoNif.hex_decode("0x" <> hash)
+      assert {:error, _} = CryptoNif.hex_decode(hash, prefix: true)
+      assert CryptoNif.hex_decode("0X" <> hash, prefix: true) == :binary.copy(<<0xAB>>, 32)
+
+      assert_raise ArgumentError, fn -> CryptoNif.hex_encode("data", case: :mixed) end
+    end
+  end
+
+  describe "base64url" do
+    test "matches Base.url_encode64 and Base.url_decode64, padded or not" do
+      for len <- [0, 1, 2, 3, 4, 5, 32, 100] do
+        data = :crypto.strong_rand_bytes(len)
+        padded = Base.url_encode64(data)
+        unpadded = Base.url_encode64(data, padding: false)
+
+        assert CryptoNif.base64url_encode(data) == padded
+        assert CryptoNif.base64url_encode(data, padding: false) == unpadded
+        assert CryptoNif.base64url_decode(padded) == data
+        assert CryptoNif.base64url_decode(unpadded, padding: false) == data
+        assert CryptoNif.base64url_decode(padded, padding: :optional) == data
+        assert CryptoNif.base64url_decode(unpadded, padding: :optional) == data
+      end
+    end
+
+    test "rejects bad padding, characters and non-canonical encodings" do
+      assert {:error, _} = CryptoNif.base64url_decode("YQ")
+      assert {:error, _} = CryptoNif.base64url_decode("YQ==", padding: false)
+      assert {:error, "invalid base64url character at position 2"} = CryptoNif.base64url_decode("ab+/")
+
+      for text <- ["a", "a===", "abcd=", "ab=c", "abc==", "===="] do
+        assert {:error, _} = CryptoNif.base64url_decode(text, padding: :optional)
+      end
+
+      assert {:error, _} = CryptoNif.base64url_decode("YR==")
+      assert CryptoNif.base64url_decode("YR==", strict: false) == "a"
+
+      assert_raise ArgumentError, fn -> CryptoNif.base64url_encode("data", padding: :optional) end
+    end
+  end
+
+  describe "multiformats" do
+    test "writes the CIDs IPFS gives raw content" do
+      digest = :crypto.hash(:sha256, "hello world")
+      cid = "bafkreifzjut3te2nhyekklss27nh3k72ysco7y32koao5eei66wof36n5e"
+
+      assert CryptoNif.cid_encode(:raw, :sha2_256, digest) == cid
+      assert CryptoNif.cid_encode(:raw, :sha2_256, digest, base: :base58btc) == "zb2rhj7crUKTQYRGCRATFaQ6YFLTde2YzdqbbhAASkL9uRDXn"
+      assert CryptoNif.cid_decode(cid) == {:raw, :sha2_256, digest}
+    end
+
+    test "round-trips blake3 multihashes and CIDs in every base" do
+      digest = CryptoNif.blake3_hash("snapshot chunk")
+      multihash = CryptoNif.multihash_encode(:blake3, digest)
+
+      assert multihash == <<0x1E, 32>> <> digest
+      assert CryptoNif.multihash_decode(multihash) == {:blake3, digest}
+
+      for base <- [:base32, :base58btc, :base16, :base64url] do
+        cid = CryptoNif.cid_encode(:dag_cbor, :blake3, digest, base: base)
+        assert CryptoNif.cid_decode(cid) == {:dag_cbor, :blake3, digest}
+        assert {^base, <<1, 0x71>> <> ^multihash} = CryptoNif.multibase_decode(cid)
+        assert CryptoNif.multibase_encode(base, <<1, 0x71>> <> multihash) == cid
+      end
+    end
+
+    test "rejects unknown codes, wrong lengths and CIDv0" do
+      digest = :binary.copy(<<7>>, 32)
+      assert {:error, _} = CryptoNif.multihash_decode(<<0x13, 32>> <> digest)
+      assert {:error, _} = CryptoNif.multihash_decode(<<0x1E, 31>> <> binary_part(digest, 0, 31))
+      assert {:error, _} = CryptoNif.cid_decode("zQmYwAPJzv5CZsnA625s3Xf2nemtYgPpHdWEz79ojWnPbdG")
+      assert {:error, _} = CryptoNif.cid_decode("Bafkreifzjut3te2nhyekklss27nh3k72ysco7y32koao5eei66wof36n5e")
+      assert {:error, _} = CryptoNif.multibase_decode("bab")
+
+      assert_raise ArgumentError, fn -> CryptoNif.multihash_encode(:blake3, "short") end
+      assert_raise ArgumentError, fn -> CryptoNif.cid_encode(:json, :blake3, digest) end
+    end
+  end
+
+  describe "varints" do
+    test "round-trips LEB128 and compact sizes, leaving the rest" do
+      for {n, leb, compact} <- [
+            {0, "00", "00"},
+            {300, "AC02", "FD2C01"},
+            {624_485, "E58E26", "FE65870900"},
+            {18_446_744_073_709_551_615, "FFFFFFFFFFFFFFFFFF01", "FFFFFFFFFFFFFFFFFF"}
+          ] do
+        assert CryptoNif.varint_encode(n) == Base.decode16!(leb)
+        assert CryptoNif.varint_decode(Base.decode16!(leb) <> "rest") == {n, "rest"}
+        assert CryptoNif.compact_size_encode(n) == Base.decode16!(compact)
+        assert CryptoNif.compact_size_decode(Base.decode16!(compact) <> "rest") == {n, "rest"}
+      end
+    end
+
+    test "rejects truncated, overflowing and non-minimal encodings" do
+      for hex <- ["", "80", "8000", "FFFFFFFFFFFFFFFFFF02"] do
+        assert {:error, _} = CryptoNif.varint_decode(Base.decode16!(hex))
+      end
+
+      for hex <- ["", "FD01", "FDFC00", "FEFFFF0000", "FFFFFFFFFF00000000"] do
+        assert {:error, _} = CryptoNif.compact_size_decode(Base.decode16!(hex))
+      end
+
+      assert_raise ArgumentError, fn -> CryptoNif.varint_encode(-1) end
+    end
+  end
+
+  describe "gossip messages" do
+    test "encodes envelopes in the protobuf wire format" do
+      assert CryptoNif.gossip_encode(:ping, %{nonce: 150}) == Base.decode16!("4203089601")
+      assert CryptoNif.gossip_encode(:verack, %{}) == Base.decode16!("1200")
+
+      inv = CryptoNif.gossip_encode(:inv, %{items: [%{type: :TX, hash: "ab"}]})
+      assert inv == Base.decode16!("1A080A06080112026162")
+      assert CryptoNif.gossip_decode(inv) == {:inv, %{items: [%{type: :TX, hash: "ab"}]}}
+
+      locators = CryptoNif.gossip_encode(:getheaders, %{block_locator_hashes: [1, 2]})
+      assert locators == Base.decode16!("5A041A020102")
+    end
+
+    test "round-trips proto structs with defaults filled in" do
+      tx = %Bastille.P2P.Proto.Transaction{from: "alice", to: "bob", amount: 5, signature: <<1, 2>>}
+      {:tx, decoded} = CryptoNif.gossip_decode(CryptoNif.gossip_encode(:tx, tx))
+
+      assert decoded.from == "alice"
+      assert decoded.amount == 5
+      assert decoded.signature == <<1, 2>>
+      assert decoded.fee == 0
+      assert decoded.data == ""
+
+      {:block, block} = CryptoNif.gossip_decode(CryptoNif.gossip_encode(:block, %{hash: "h", transactions: [tx]}))
+      assert block.header == nil
+      assert [%{to: "bob"}] = block.transactions
+    end
+
+    test "rejects malformed envelopes and messages over the limits" do
+      for hex <- ["", "4203089601FF", "0B", "4A0108"] do
+        assert {:error, _} = CryptoNif.gossip_decode(Base.decode16!(hex))
+      end
+
+      assert_raise ArgumentError, fn -> CryptoNif.gossip_encode(:unknown, %{}) end
+      assert_raise ArgumentError, fn -> CryptoNif.gossip_encode(:ping, %{nonce: -1}) end
+      assert_raise ArgumentError, fn -> CryptoNif.gossip_encode(:getblocks, %{max_count: 4_294_967_296}) end
+
+      entries = for _ <- 1..1_001, do: %{ip: "10.0.0.1", port: 8333}
+      assert_raise ArgumentError, fn -> CryptoNif.gossip_encode(:addr, %{entries: entries}) end
+    end
+  end
+
+  describe "SSZ" do
+    @header {:container,
+             [
+               slot: {:uint, 64},
+               proposer_index: {:uint, 64},
+               parent_root: {:bytes, 32},
+               state_root: {:bytes, 32},
+               body_root: {:bytes, 32}
+             ]}
+
+    test "hashes containers and lists as the consensus specs do" do
+      zero_root = <<0::256>>
+      header = %{slot: 0, proposer_index: 0, parent_root: zero_root, state_root: zero_root, body_root: zero_root}
+
+      assert CryptoNif.ssz_hash_tree_root(@header, header) ==
+               Base.decode16!("C78009FDF07FC56A11F122370658A353AAA542ED63E44C4BC15FF4CD105AB33C")
+
+      assert CryptoNif.ssz_hash_tree_root({:list, {:uint, 64}, 4}, []) ==
+               Base.decode16!("F5A5FD42D16A20302798EF6ED309979B43003D2320D9F0E8EA9831A92759FB4B")
+
+      assert CryptoNif.ssz_hash_tree_root({:uint, 64}, 0) == zero_root
+    end
+
+    test "round-trips fixed and variable-size values" do
+      schema = {:container, [a: {:uint, 16}, b: {:byte_list, 10}]}
+      encoded = CryptoNif.ssz_encode(schema, %{a: 0x0102, b: <<3, 4>>})
+      assert encoded == Base.decode16!("0201060000000304")
+      assert CryptoNif.ssz_decode(schema, encoded) == %{a: 0x0102, b: <<3, 4>>}
+
+      assert CryptoNif.ssz_encode({:bitlist, 8}, [true, false, true]) == <<0x0D>>
+      assert CryptoNif.ssz_decode({:bitlist, 8}, <<0x0D>>) == [true, false, true]
+
+      lists = {:list, {:byte_list, 4}, 3}
+      encoded = CryptoNif.ssz_encode(lists, [<<1>>, "", <<2, 3>>])
+      assert encoded == Base.decode16!("0C0000000D0000000D000000010203")
+      assert CryptoNif.ssz_decode(lists, encoded) == [<<1>>, "", <<2, 3>>]
+    end
+
+    test "rejects malformed input and values not matching the schema" do
+      schema = {:container, [a: {:uint, 16}, b: {:byte_list, 10}]}
+      assert {:error, _} = CryptoNif.ssz_decode(schema, Base.decode16!("0201070000000304"))
+      assert {:error, _} = CryptoNif.ssz_decode({:bitlist, 8}, <<0>>)
+      assert {:error, _} = CryptoNif.ssz_decode({:bitvector, 3}, <<0x08>>)
+      assert {:error, _} = CryptoNif.ssz_decode({:uint, 64}, <<0::56>>)
+
+      assert_raise ArgumentError, fn -> CryptoNif.ssz_encode({:uint, 8}, 256) end
+      assert_raise ArgumentError, fn -> CryptoNif.ssz_encode({:bytes, 32}, <<0>>) end
+      assert_raise ArgumentError, fn -> CryptoNif.ssz_encode({:list, {:uint, 8}, 1}, [1, 2]) end
+      assert_raise ArgumentError, fn -> CryptoNif.ssz_encode({:uint, 256}, 0) end
+    end
+  end
+
+  describe "Borsh" do
+    test "encodes structs, options, enums and maps as borsh-rs does" do
+      schema = {:struct, [amount: :u64, memo: {:option, :string}, delta: :i16]}
+      value = %{amount: 1, memo: "abc", delta: -2}
+      encoded = CryptoNif.borsh_encode(schema, value)
+
+      assert encoded == Base.decode16!("01000000000000000103000000616263FEFF")
+      assert CryptoNif.borsh_decode(schema, encoded) == value
+      assert CryptoNif.borsh_encode(schema, %{value | memo: nil}) == Base.decode16!("010000000000000000FEFF")
+
+      enum = {:enum, [none: :unit, some: :u8]}
+      assert CryptoNif.borsh_encode(enum, {:some, 7}) == <<1, 7>>
+      assert CryptoNif.borsh_decode(enum, <<0>>) == :none
+
+      map = {:map, :u8, :string}
+      encoded = CryptoNif.borsh_encode(map, %{2 => "b", 1 => "a"})
+      assert encoded == Base.decode16!("02000000010100000061020100000062")
+      assert CryptoNif.borsh_decode(map, encoded) == %{1 => "a", 2 => "b"}
+    end
+
+    test "rejects non-canonical input and values not matching the schema" do
+      map = {:map, :u8, :string}
+      assert {:error, _} = CryptoNif.borsh_decode(map, Base.decode16!("02000000020100000062010100000061"))
+      assert {:error, _} = CryptoNif.borsh_decode(:bool, <<2>>)
+      assert {:error, _} = CryptoNif.borsh_decode({:enum, [a: :unit]}, <<1>>)
+      assert {:error, _} = CryptoNif.borsh_decode({:vec, :u64}, <<255, 255, 255, 255>>)
+      assert {:error, _} = CryptoNif.borsh_decode(:u8, <<1, 2>>)
+
+      assert_raise ArgumentError, fn -> CryptoNif.borsh_encode(:i8, 128) end
+      assert_raise ArgumentError, fn -> CryptoNif.borsh_encode(:string, <<0xFF>>) end
+      assert_raise ArgumentError, fn -> CryptoNif.borsh_encode({:bytes, 32}, <<0>>) end
+      assert_raise ArgumentError, fn -> CryptoNif.borsh_encode(:f64, 1.0) end
+    end
+  end
+
+  describe "Bloom filters" do
+    test "never misses an inserted topic and rarely matches others" do
+      filter = CryptoNif.bloom_new(2048, 3)
+      topics = for i <- 1..100, do: CryptoNif.keccak256("Transfer(#{i})")
+      for topic <- topics, do: :ok = CryptoNif.bloom_insert(filter, topic)
+
+      assert Enum.all?(topics, &CryptoNif.bloom_contains(filter, &1))
+
+      false_positives = Enum.count(1..1000, &CryptoNif.bloom_contains(filter, "other #{&1}"))
+      assert false_positives < 50
+    end
+
+    test "round-trips through serialization" do
+      filter = CryptoNif.bloom_new(1001, 4)
+      :ok = CryptoNif.bloom_insert(filter, "topic")
+      serialized = CryptoNif.bloom_serialize(filter)
+      assert byte_size(serialized) == 9 + 126
+
+      restored = CryptoNif.bloom_deserialize(serialized)
+      assert CryptoNif.bloom_contains(restored, "topic")
+      assert CryptoNif.bloom_serialize(restored) == serialized
+
+      assert_raise ArgumentError, fn -> CryptoNif.bloom_deserialize(binary_part(serialized, 0, 100)) end
+    end
+
+    test "rejects invalid parameters" do
+      assert_raise ArgumentError, fn -> CryptoNif.bloom_new(0, 3) end
+      assert_raise ArgumentError, fn -> CryptoNif.bloom_new(1024, 0) end
+      assert_raise ArgumentError, fn -> CryptoNif.bloom_new(1024, 33) end
+    end
+  end
+
+  describe "cuckoo filters" do
+    test "dedupes message IDs in a single call" do
+      filter = CryptoNif.cuckoo_new(1000)
+      id = CryptoNif.blake3_hash("gossip message")
+
+      assert CryptoNif.cuckoo_insert(filter, id)
+      refute CryptoNif.cuckoo_insert(filter, id)
+      assert CryptoNif.cuckoo_contains(filter, id)
+
+      assert CryptoNif.cuckoo_delete(filter, id)
+      refute CryptoNif.cuckoo_contains(filter, id)
+      refute CryptoNif.cuckoo_delete(filter, id)
+    end
+
+    test "remembers the latest insertions while older ones age out" do
+      filter = CryptoNif.cuckoo_new(500)
+      for i <- 1..5_000, do: CryptoNif.cuckoo_insert(filter, <<i::32>>)
+
+      assert Enum.all?(4_501..5_000, &CryptoNif.cuckoo_contains(filter, <<&1::32>>))
+      assert Enum.count(1..1_000, &CryptoNif.cuckoo_contains(filter, <<&1::32>>)) < 10
+    end
+
+    test "rejects invalid capacities" do
+      assert_raise ArgumentError, fn -> CryptoNif.cuckoo_new(0) end
+      assert_raise ArgumentError, fn -> CryptoNif.cuckoo_new(33_554_432) end
+    end
+  end
+
+  describe "state snapshots" do
+    test "streams entries through chunks and back" do
+      entries = for i <- 1..500, do: {"balance:#{i}", %{balance: i * 1_000, nonce: rem(i, 7)}}
+      encoder = CryptoNif.snapshot_encoder_new(4096)
+
+      chunks =
+        entries
+        |> Enum.chunk_every(64)
+        |> Enum.flat_map(&CryptoNif.snapshot_encode(encoder, &1))
+        |> Kernel.++(CryptoNif.snapshot_encode_finish(encoder))
+
+      assert length(chunks) > 2
+
+      decoder = CryptoNif.snapshot_decoder_new()
+      assert Enum.flat_map(chunks, &CryptoNif.snapshot_decode(decoder, &1)) == entries
+      assert CryptoNif.snapshot_decode_finish(decoder) == 500
+    end
+
+    test "rejects corrupt and out-of-order chunks without losing its place" do
+      encoder = CryptoNif.snapshot_encoder_new(64)
+      first = CryptoNif.snapshot_encode(encoder, for(i <- 1..20, do: {"k#{i}", i}))
+      [chunk0, chunk1 | _] = chunks = first ++ CryptoNif.snapshot_encode_finish(encoder)
+
+      decoder = CryptoNif.snapshot_decoder_new()
+      <<head::binary-size(20), byte, tail::binary>> = chunk0
+      assert {:error, _} = CryptoNif.snapshot_decode(decoder, head <> <<Bitwise.bxor(byte, 1)>> <> tail)
+      assert {:error, _} = CryptoNif.snapshot_decode(decoder, chunk1)
+
+      assert Enum.flat_map(chunks, &CryptoNif.snapshot_decode(decoder, &1)) == for(i <- 1..20, do: {"k#{i}", i})
+      assert CryptoNif.snapshot_decode_finish(decoder) == 20
+    end
+
+    test "an unfinished snapshot is incomplete" do
+      encoder = CryptoNif.snapshot_encoder_new(16)
+      [chunk | _] = CryptoNif.snapshot_encode(encoder, [{"a", 1}, {"b", 2}])
+
+      decoder = CryptoNif.snapshot_decoder_new()
+      assert [{"a", 1}] = CryptoNif.snapshot_decode(decoder, chunk)
+      assert {:error, _} = CryptoNif.snapshot_decode_finish(decoder)
+    end
+  end
+
+  describe "state diffs" do
+    test "a diff takes the old state to the new one" do
+      old = snapshot(for i <- 1..200, do: {"account:#{i}", %{balance: i}})
+
+      # Every 10th account removed, every 3rd changed, 20 added
+      kept = for i <- 1..200, rem(i, 10) != 0, do: {"account:#{i}", %{balance: i + if(rem(i, 3) == 0, do: 1, else: 0)}}
+      new = snapshot(kept ++ for(i <- 201..220, do: {"account:#{i}", %{balance: i}}))
+
+      diff = CryptoNif.state_diff(old, new)
+      assert Enum.sum(Enum.map(diff, &byte_size/1)) < Enum.sum(Enum.map(new, &byte_size/1))
+
+      patched = CryptoNif.state_apply(old, diff)
+      assert Enum.sort(snapshot_entries(patched)) == Enum.sort(snapshot_entries(new))
+      assert CryptoNif.state_diff(patched, new) == CryptoNif.state_diff(new, new)
+    end
+
+    test "rejects incomplete snapshots and diffs from another base" do
+      old = snapshot([{"a", 1}, {"b", 2}])
+      new = snapshot([{"a", 1}])
+      diff = CryptoNif.state_diff(old, new)
+
+      assert {:error, _} = CryptoNif.state_diff(Enum.drop(old, -1), new)
+      assert {:error, _} = CryptoNif.state_apply(new, diff)
+      assert snapshot_entries(CryptoNif.state_apply(old, diff)) == [{"a", 1}]
+    end
+  end
+
+  describe "RocksDB storage" do
+    # Needs the NIF built with the rocksdb feature: mix test --include rocksdb
+    @describetag :rocksdb
+    @describetag :tmp_dir
+
+    test "column families are separate and batches apply together", %{tmp_dir: tmp_dir} do
+      store = CryptoNif.storage_open(tmp_dir)
+      :ok = CryptoNif.storage_put(store, :blocks, "height:1", "block one")
+      assert CryptoNif.storage_get(store, :blocks, "height:1") == "block one"
+      assert CryptoNif.storage_get(store, :state, "height:1") == nil
+
+      :ok =
+        CryptoNif.storage_write_batch(store, [
+          {:put, :blocks, "height:2", "block two"},
+          {:put, :tx_index, "tx:ab", "height:2"},
+          {:delete, :blocks, "height:1"}
+        ])
+
+      assert CryptoNif.storage_get(store, :blocks, "height:1") == nil
+      assert CryptoNif.storage_get(store, :tx_index, "tx:ab") == "height:2"
+      assert_raise ArgumentError, fn -> CryptoNif.storage_write_batch(store, [{:put, :logs, "k", "v"}]) end
+
+      :ok = CryptoNif.storage_close(store)
+      assert {:error, _} = CryptoNif.storage_get(store, :blocks, "height:2")
+
+      reopened = CryptoNif.storage_open(tmp_dir)
+      assert CryptoNif.storage_get(reopened, :blocks, "height:2") == "block two"
+    end
+
+    test "iterators page through a key range in either direction", %{tmp_dir: tmp_dir} do
+      store = CryptoNif.storage_open(tmp_dir)
+      keys = for i <- 1..30, do: "k" <> String.pad_leading("#{i}", 2, "0")
+      :ok = CryptoNif.storage_write_batch(store, for(key <- keys, do: {:put, :state, key, key}))
+
+      iterator = CryptoNif.storage_iterator(store, :state, from: "k05", to: "k25")
+      first = CryptoNif.storage_iterator_next(iterator, 8)
+      rest = CryptoNif.storage_iterator_next(iterator, 100)
+      assert Enum.map(first ++ rest, &elem(&1, 0)) == Enum.slice(keys, 4..23)
+      assert CryptoNif.storage_iterator_next(iterator, 10) == []
+
+      reverse = CryptoNif.storage_iterator(store, :state, reverse: true)
+      assert [{"k30", "k30"}, {"k29", "k29"}] = CryptoNif.storage_iterator_next(reverse, 2)
+      assert [{"k28", "k28"} | _] = CryptoNif.storage_iterator_next(reverse, 2)
+    end
+  end
+
+  describe "LMDB storage" do
+    # Needs the NIF built with the lmdb feature: mix test --include lmdb
+    @describetag :lmdb
+    @describetag :tmp_dir
+
+    test "batches apply together or not at all", %{tmp_dir: tmp_dir} do
+      store = CryptoNif.storage_open(tmp_dir, backend: :lmdb, map_size: 16_777_216)
+      :ok = CryptoNif.storage_put(store, :blocks, "height:1", "block one")
+      assert {:error, _} = CryptoNif.storage_open(tmp_dir, backend: :lmdb)
+
+      # LMDB keys are limited to 511 bytes
+      assert {:error, _} =
+               CryptoNif.storage_write_batch(store, [
+                 {:delete, :blocks, "height:1"},
+                 {:put, :tx_index, :binary.copy("k", 600), "height:1"}
+               ])
+
+      assert CryptoNif.storage_get(store, :blocks, "height:1") == "block one"
+      :ok = CryptoNif.storage_compact(store, :blocks)
+
+      :ok = CryptoNif.storage_close(store)
+      reopened = CryptoNif.storage_open(tmp_dir, backend: :lmdb)
+      assert CryptoNif.storage_get(reopened, :blocks, "height:1") == "block one"
+      assert CryptoNif.storage_get(reopened, :state, "height:1") == nil
+    end
+
+    test "iterators page through a key range in either direction", %{tmp_dir: tmp_dir} do
+      store = CryptoNif.storage_open(tmp_dir, backend: :lmdb)
+      keys = for i <- 1..30, do: "k" <> String.pad_leading("#{i}", 2, "0")
+      :ok = CryptoNif.storage_write_batch(store, for(key <- keys, do: {:put, :state, key, key}))
+
+      iterator = CryptoNif.storage_iterator(store, :state, from: "k05", to: "k25", reverse: true)
+      first = CryptoNif.storage_iterator_next(iterator, 8)
+      rest = CryptoNif.storage_iterator_next(iterator, 100)
+      assert Enum.map(first ++ rest, &elem(&1, 0)) == Enum.reverse(Enum.slice(keys, 4..23))
+      assert CryptoNif.storage_iterator_next(iterator, 10) == []
+    end
+
+    test "iterators seek within their range", %{tmp_dir: tmp_dir} do
+      store = CryptoNif.storage_open(tmp_dir, backend: :lmdb)
+      keys = for i <- 1..30, do: "k" <> String.pad_leading("#{i}", 2, "0")
+      :ok = CryptoNif.storage_write_batch(store, for(key <- keys, do: {:put, :state, key, key}))
+      first_keys = fn iterator, count -> Enum.map(CryptoNif.storage_iterator_next(iterator, count), &elem(&1, 0)) end
+
+      iterator = CryptoNif.storage_iterator(store, :state, from: "k05", to: "k25")
+      :ok = CryptoNif.storage_iterator_seek(iterator, "k10a")
+      assert first_keys.(iterator, 2) == ["k11", "k12"]
+      :ok = CryptoNif.storage_iterator_seek(iterator, "k01")
+      assert first_keys.(iterator, 1) == ["k05"]
+      :ok = CryptoNif.storage_iterator_seek(iterator, "k25")
+      assert first_keys.(iterator, 1) == []
+
+      reversed = CryptoNif.storage_iterator(store, :state, from: "k05", to: "k25", reverse: true)
+      :ok = CryptoNif.storage_iterator_seek(reversed, "k10a")
+      assert first_keys.(reversed, 2) == ["k10", "k09"]
+      :ok = CryptoNif.storage_iterator_seek(reversed, "k99")
+      assert first_keys.(reversed, 1) == ["k24"]
+      :ok = CryptoNif.storage_iterator_seek(reversed, "k04")
+      assert first_keys.(reversed, 1) == []
+    end
+
+    test "encrypts values at rest and rotates the data key", %{tmp_dir: tmp_dir} do
+      [old_key, new_key] = for _ <- 1..2, do: :crypto.strong_rand_bytes(32)
+      store = CryptoNif.storage_open(tmp_dir, backend: :lmdb, encryption_key: old_key)
+      ops = for i <- 1..600, do: {:put, :state, "account #{i}", "balance #{i}"}
+      :ok = CryptoNif.storage_write_batch(store, [{:put, :blocks, "height:1", "block one"} | ops])
+
+      refute File.read!(Path.join(tmp_dir, "data.mdb")) =~ "block one"
+      assert CryptoNif.storage_rotate_key(store, new_key) == 601
+      assert CryptoNif.storage_get(store, :state, "account 42") == "balance 42"
+      :ok = CryptoNif.storage_close(store)
+
+      assert {:error, _} = CryptoNif.storage_open(tmp_dir, backend: :lmdb)
+      stale = CryptoNif.storage_open(tmp_dir, backend: :lmdb, encryption_key: old_key)
+      assert {:error, _} = CryptoNif.storage_get(stale, :blocks, "height:1")
+      :ok = CryptoNif.storage_close(stale)
+
+      reopened = CryptoNif.storage_open(tmp_dir, backend: :lmdb, encryption_key: new_key)
+      assert CryptoNif.storage_get(reopened, :blocks, "height:1") == "block one"
+      iterator = CryptoNif.storage_iterator(reopened, :state, from: "account 599")
+      assert CryptoNif.storage_iterator_next(iterator, 2) == [{"account 599", "balance 599"}, {"account 6", "balance 6"}]
+    end
+  end
+
+  describe "block archive" do
+    @describetag :tmp_dir
+
+    test "reads back blocks across segments", %{tmp_dir: tmp_dir} do
+      archive = CryptoNif.archive_open(tmp_dir)
+      assert CryptoNif.archive_last_height(archive) == nil
+
+      heights = Enum.to_list(0..9000//3)
+      for height <- heights, do: :ok = CryptoNif.archive_append(archive, height, "block #{height}")
+
+      assert CryptoNif.archive_read(archive, 3) == "block 3"
+      assert CryptoNif.archive_read(archive, 8997) == "block 8997"
+      assert CryptoNif.archive_read(archive, 4) == nil
+      assert CryptoNif.archive_read(archive, 100_000) == nil
+      assert CryptoNif.archive_last_height(archive) == 9000
+
+      assert {:error, _} = CryptoNif.archive_append(archive, 9000, "again")
+      assert {:error, _} = CryptoNif.archive_append(archive, 12, "late")
+      :ok = CryptoNif.archive_append(archive, 20_000, "")
+      assert CryptoNif.archive_read(archive, 20_000) == ""
+    end
+
+    test "mapped reads match copied reads", %{tmp_dir: tmp_dir} do
+      archive = CryptoNif.archive_open(tmp_dir)
+      block = :crypto.strong_rand_bytes(2_000_000)
+      :ok = CryptoNif.archive_append(archive, 1, block)
+      mapped = CryptoNif.archive_read_mapped(archive, 1)
+
+      # Appends after the map was taken are mapped afresh
+      for height <- 2..9_000, do: :ok = CryptoNif.archive_append(archive, height, "block #{height}")
+
+      assert mapped == block
+      assert CryptoNif.archive_read_mapped(archive, 8_999) == CryptoNif.archive_read(archive, 8_999)
+      assert CryptoNif.archive_read_mapped(archive, 9_001) == nil
+      assert CryptoNif.archive_read_mapped(archive, 0) == nil
+    end
+
+    test "survives reopening and refuses a second opener", %{tmp_dir: tmp_dir} do
+      # The archive lives in a short-lived process, so its lock goes with it
+      Task.async(fn ->
+        archive = CryptoNif.archive_open(tmp_dir)
+        for height <- 1..10, do: :ok = CryptoNif.archive_append(archive, height, <<height::64>>)
+        :ok = CryptoNif.archive_flush(archive)
+        assert {:error, _} = CryptoNif.archive_open(tmp_dir)
+      end)
+      |> Task.await()
+
+      reopened = wait_for_reopen(tmp_dir, 50, &CryptoNif.archive_open/1)
+      assert CryptoNif.archive_last_height(reopened) == 10
+      assert CryptoNif.archive_read(reopened, 7) == <<7::64>>
+      :ok = CryptoNif.archive_append(reopened, 11, <<11::64>>)
+    end
+
+    test "rejects a block that fails its checksum", %{tmp_dir: tmp_dir} do
+      Task.async(fn ->
+        archive = CryptoNif.archive_open(tmp_dir)
+        :ok = CryptoNif.archive_append(archive, 1, "first block")
+        :ok = CryptoNif.archive_append(archive, 2, "second block")
+        :ok = CryptoNif.archive_flush(archive)
+      end)
+      |> Task.await()
+
+      # The first block starts right after the segment's 8-byte magic
+      segment = Path.join(tmp_dir, "0000000000.blk")
+      {:ok, file} = :file.open(segment, [:read, :write, :binary])
+      :ok = :file.pwrite(file, 8, "F")
+      :ok = :file.close(file)
+
+      archive = wait_for_reopen(tmp_dir, 50, &CryptoNif.archive_open/1)
+      assert {:error, _} = CryptoNif.archive_read(archive, 1)
+      assert CryptoNif.archive_read(archive, 2) == "second block"
+    end
+
+    test "encrypts blocks at rest and rotates the data key", %{tmp_dir: tmp_dir} do
+      [old_key, new_key] = for _ <- 1..2, do: :crypto.strong_rand_bytes(32)
+
+      Task.async(fn ->
+        archive = CryptoNif.archive_open(tmp_dir, encryption_key: old_key)
+        for height <- 1..9000, do: :ok = CryptoNif.archive_append(archive, height, "block #{height}")
+        assert CryptoNif.archive_read_mapped(archive, 12) == "block 12"
+        refute File.read!(Path.join(tmp_dir, "0000000000.blk")) =~ "block 12"
+
+        assert CryptoNif.archive_rotate_key(archive, new_key) == 9000
+        assert CryptoNif.archive_rotate_key(archive, new_key) == 0
+        :ok = CryptoNif.archive_flush(archive)
+      end)
+      |> Task.await(30_000)
+
+      open = &CryptoNif.archive_open(&1, encryption_key: new_key)
+      archive = wait_for_reopen(tmp_dir, 50, open)
+      assert CryptoNif.archive_read(archive, 8999) == "block 8999"
+    end
+
+    test "refuses to open an archive the other way it was created", %{tmp_dir: tmp_dir} do
+      key = :crypto.strong_rand_bytes(32)
+      encrypted = Path.join(tmp_dir, "encrypted")
+      plain = Path.join(tmp_dir, "plain")
+
+      Task.async(fn ->
+        :ok = CryptoNif.archive_append(CryptoNif.archive_open(encrypted, encryption_key: key), 1, "block")
+        :ok = CryptoNif.archive_append(CryptoNif.archive_open(plain), 1, "block")
+      end)
+      |> Task.await()
+
+      assert {:error, _} = wait_for_reopen(encrypted, 50, &CryptoNif.archive_open/1)
+      assert {:error, _} = wait_for_reopen(plain, 50, &CryptoNif.archive_open(&1, encryption_key: key))
+      assert_raise ArgumentError, fn -> CryptoNif.archive_open(plain, encryption_key: "short") end
+      assert_raise ArgumentError, fn -> CryptoNif.archive_open(plain, retired_keys: [key]) end
+    end
+  end
+
+  describe "pruning" do
+    @describetag :tmp_dir
+
+    test "retention modes decide what is reclaimed", %{tmp_dir: tmp_dir} do
+      archive = CryptoNif.archive_open(Path.join(tmp_dir, "blocks"))
+      tree = CryptoNif.smt_open(Path.join(tmp_dir, "state"), backend: :jellyfish)
+
+      roots =
+        for height <- 0..20_000//100 do
+          :ok = CryptoNif.archive_append(archive, height, :binary.copy(<<height::32>>, 64))
+          for i <- 1..5, do: :ok = CryptoNif.smt_put(tree, "account #{i}", "#{height}")
+          CryptoNif.smt_commit(tree, height)
+        end
+
+      targets = [blocks: archive, state: tree]
+      assert CryptoNif.prune_below(10_050, :archive, targets) == %{blocks: 0, state: 0}
+
+      %{blocks: 0, state: state} = CryptoNif.prune_below(10_050, :full, targets)
+      assert state > 0
+      assert CryptoNif.archive_read(archive, 0) != nil
+      assert {:error, _} = CryptoNif.smt_root(tree, 9_999)
+      assert CryptoNif.smt_root(tree, 10_050) == Enum.at(roots, 100)
+
+      %{blocks: blocks} = CryptoNif.prune_below(17_000, :light, targets)
+      assert blocks > 0
+      assert CryptoNif.archive_read(archive, 0) == nil
+      assert CryptoNif.archive_read(archive, 16_300) == nil
+      assert CryptoNif.archive_read(archive, 16_400) == :binary.copy(<<16_400::32>>, 64)
+      assert CryptoNif.archive_read(archive, 20_000) != nil
+      assert CryptoNif.smt_root(tree) == List.last(roots)
+    end
+
+    test "takes only known modes and targets", %{tmp_dir: tmp_dir} do
+      archive = CryptoNif.archive_open(tmp_dir)
+      assert CryptoNif.prune_below(100, :light, []) == %{blocks: 0, state: 0}
+      assert_raise ArgumentError, fn -> CryptoNif.prune_below(100, :everything, blocks: archive) end
+      assert_raise ArgumentError, fn -> CryptoNif.prune_below(100, :light, wal: archive) end
+    end
+  end
+
+  describe "backups" do
+    @describetag :tmp_dir
+
+    test "restores blocks and state as of the backup height", %{tmp_dir: tmp_dir} do
+      archive = CryptoNif.archive_open(Path.join(tmp_dir, "blocks"))
+      tree = CryptoNif.smt_open(Path.join(tmp_dir, "state"), backend: :jellyfish)
+
+      roots =
+        for height <- 0..50 do
+          :ok = CryptoNif.archive_append(archive, height, "block #{height}")
+          :ok = CryptoNif.smt_put(tree, "account #{rem(height, 7)}", "#{height}")
+          CryptoNif.smt_commit(tree, height)
+        end
+
+      path = Path.join(tmp_dir, "node.backup")
+      summary = %{height: 40, blocks: 41, state: true}
+
+      assert CryptoNif.backup_create(path, 40, blocks: archive, state: tree, compress: true) == summary
+      assert CryptoNif.backup_verify(path) == summary
+
+      restored = [blocks: Path.join(tmp_dir, "restored_blocks"), state: Path.join(tmp_dir, "restored_state")]
+      assert CryptoNif.backup_restore(path, restored) == summary
+
+      restored_archive = CryptoNif.archive_open(restored[:blocks])
+      assert CryptoNif.archive_last_height(restored_archive) == 40
+      assert CryptoNif.archive_read(restored_archive, 12) == "block 12"
+
+      restored_tree = CryptoNif.smt_open(restored[:state], backend: :jellyfish)
+      assert CryptoNif.smt_root(restored_tree) == Enum.at(roots, 40)
+      assert CryptoNif.smt_get(restored_tree, "account 5") == "40"
+    end
+
+    test "rejects damaged backups", %{tmp_dir: tmp_dir} do
+      archive = CryptoNif.archive_open(Path.join(tmp_dir, "blocks"))
+      for height <- 1..10, do: :ok = CryptoNif.archive_append(archive, height, "block #{height}")
+
+      path = Path.join(tmp_dir, "blocks.backup")
+      %{blocks: 10, state: false} = CryptoNif.backup_create(path, 100, blocks: archive)
+      backup = File.read!(path)
+
+      <<head::binary-size(40), byte, rest::binary>> = backup
+      File.write!(path, <<head::binary, Bitwise.bxor(byte, 1), rest::binary>>)
+      assert {:error, _} = CryptoNif.backup_verify(path)
+      assert {:error, _} = CryptoNif.backup_restore(path, blocks: Path.join(tmp_dir, "restored"))
+      refute File.exists?(Path.join(tmp_dir, "restored"))
+
+      File.write!(path, binary_part(backup, 0, byte_size(backup) - 1))
+      assert {:error, _} = CryptoNif.backup_verify(path)
+      assert_raise ArgumentError, fn -> CryptoNif.backup_create(path, 10, wal: archive) end
+    end
+  end
+
+  describe "write-ahead log" do
+    @describetag :tmp_dir
+
+    test "replays the records after the last checkpoint", %{tmp_dir: tmp_dir} do
+      wal = CryptoNif.wal_open(tmp_dir, sync: :manual)
+      assert CryptoNif.wal_replay(wal) == []
+
+      lsns = for height <- 1..5, do: CryptoNif.wal_append(wal, "apply block #{height}")
+      assert lsns == [1, 2, 3, 4, 5]
+      :ok = CryptoNif.wal_sync(wal)
+
+      :ok = CryptoNif.wal_checkpoint(wal, 3)
+      assert CryptoNif.wal_replay(wal) == [{4, "apply block 4"}, {5, "apply block 5"}]
+      assert {:error, _} = CryptoNif.wal_checkpoint(wal, 2)
+      assert {:error, _} = CryptoNif.wal_checkpoint(wal, 6)
+
+      :ok = CryptoNif.wal_checkpoint(wal, 5)
+      assert CryptoNif.wal_replay(wal) == []
+      assert CryptoNif.wal_append(wal, "apply block 6") == 6
+      assert_raise ArgumentError, fn -> CryptoNif.wal_open(tmp_dir, sync: :sometimes) end
+    end
+
+    test "survives reopening and refuses a second opener", %{tmp_dir: tmp_dir} do
+      # The log lives in a short-lived process, so its lock goes with it
+      Task.async(fn ->
+        wal = CryptoNif.wal_open(tmp_dir, sync: {:interval, 100})
+        for height <- 1..3, do: CryptoNif.wal_append(wal, "apply block #{height}")
+        :ok = CryptoNif.wal_checkpoint(wal, 1)
+        assert {:error, _} = CryptoNif.wal_open(tmp_dir)
+      end)
+      |> Task.await()
+
+      reopened = wait_for_reopen(tmp_dir, 50, &CryptoNif.wal_open/1)
+      assert CryptoNif.wal_replay(reopened) == [{2, "apply block 2"}, {3, "apply block 3"}]
+      assert CryptoNif.wal_append(reopened, "apply block 4") == 4
+    end
+
+    test "discards a record torn by a crash", %{tmp_dir: tmp_dir} do
+      Task.async(fn ->
+        wal = CryptoNif.wal_open(tmp_dir)
+        for height <- 1..3, do: CryptoNif.wal_append(wal, "apply block #{height}")
+      end)
+      |> Task.await()
+
+      [segment] = Path.wildcard(Path.join(tmp_dir, "*.wal"))
+      {:ok, %File.Stat{size: size}} = File.stat(segment)
+      {:ok, file} = :file.open(segment, [:read, :write, :binary])
+      {:ok, _} = :file.position(file, size - 3)
+      :ok = :file.truncate(file)
+      :ok = :file.close(file)
+
+      wal = wait_for_reopen(tmp_dir, 50, &CryptoNif.wal_open/1)
+      assert Enum.map(CryptoNif.wal_replay(wal), &elem(&1, 0)) == [1, 2]
+      assert CryptoNif.wal_append(wal, "apply block 3 again") == 3
+    end
+  end
+
+  describe "message authentication" do
+    test "HMAC-SHA256 agrees with :crypto and verifies" do
+      key = "rpc-secret"
+      data = "token payload"
+      tag = CryptoNif.hmac_sha256(key, data)
+
+      assert tag == :crypto.mac(:hmac, :sha256, key, data)
+      assert CryptoNif.hmac_sha256_verify(key, data, tag)
+      refute CryptoNif.hmac_sha256_verify(key, "tampered payload", tag)
+      refute CryptoNif.hmac_sha256_verify(key, data, binary_part(tag, 0, 16))
+    end
+
+    test "Blake3 MAC verifies keyed hashes" do
+      key = :binary.copy(<<7>>, 32)
+      tag = CryptoNif.blake3_keyed_hash(key, "gossip message")
+
+      assert CryptoNif.blake3_mac_verify(key, "gossip message", tag)
+      refute CryptoNif.blake3_mac_verify(key, "other message", tag)
+      refute CryptoNif.blake3_mac_verify("short key", "gossip message", tag)
+    end
+  end
+
+  describe "checksums" do
+    test "CRC-32C matches the standard check value" do
+      assert CryptoNif.crc32c("123456789") == 0xE3069283
+    end
+
+    test "XXH3 matches the reference value for empty input" do
+      assert CryptoNif.xxh3_64("") == 0x2D06800538D394C2
+    end
+
+    test "checksums detect single-byte corruption" do
+      frame = :crypto.strong_rand_bytes(4_096)
+      <<first, rest::binary>> = frame
+      corrupted = <<Bitwise.bxor(first, 1), rest::binary>>
+
+      assert CryptoNif.xxh3_64(frame) != CryptoNif.xxh3_64(corrupted)
+      assert CryptoNif.crc32c(frame) != CryptoNif.crc32c(corrupted)
+    end
+  end
+
+  describe "canonical CBOR" do
+    test "encodes RFC 8949 examples and sorts map keys by their encoding" do
+      assert CryptoNif.cbor_encode(1_000_000) == Base.decode16!("1A000F4240")
+      assert CryptoNif.cbor_encode(-18_446_744_073_709_551_616) == Base.decode16!("3BFFFFFFFFFFFFFFFF")
+      assert CryptoNif.cbor_encode([1, [2, 3], [4, 5]]) == Base.decode16!("8301820203820405")
+      assert CryptoNif.cbor_encode({:tag, 1, 1_363_896_240}) == Base.decode16!("C11A514B67B0")
+
+      map = %{{:text, "aa"} => nil, {:text, "z"} => true, -1 => false, 100 => <<1, 2>>, 10 => []}
+      encoded = CryptoNif.cbor_encode(map)
+      assert encoded == Base.decode16!("A50A80186442010220F4617AF5626161F6")
+      assert CryptoNif.cbor_decode(encoded) == map
+    end
+
+    test "rejects terms and encodings outside the deterministic subset" do
+      for term <- [1.5, :other, {:text, <<0xFF>>}, 18_446_744_073_709_551_616, {:tag, -1, 0}] do
+        assert_raise ArgumentError, fn -> CryptoNif.cbor_encode(term) end
+      end
+
+      # Indefinite length, non-shortest integer, unsorted and repeated map keys,
+      # a half-precision float, trailing bytes
+      for hex <- ["9F01FF", "1817", "A203040102", "A201020103", "F93C00", "0000"] do
+        assert {:error, _} = CryptoNif.cbor_decode(Base.decode16!(hex))
+      end
+    end
+  end
+
+  describe "MessagePack" do
+    test "encodes values in their shortest form and round-trips them" do
+      assert CryptoNif.msgpack_encode(%{"compact" => true, "schema" => 0}) ==
+               Base.decode16!("82A7636F6D70616374C3A6736368656D6100")
+
+      for {term, hex} <- [{-33, "D0DF"}, {65_536, "CE00010000"}, {nil, "C0"}, {1.5, "CB3FF8000000000000"}] do
+        assert CryptoNif.msgpack_encode(term) == Base.decode16!(hex)
+      end
+
+      response = %{"id" => 7, "result" => [{:bin, <<0, 255>>}, "ok", -1, 2.5, nil, {:ext, -1, <<0::32>>}]}
+      assert CryptoNif.msgpack_decode(CryptoNif.msgpack_encode(response)) == response
+      assert CryptoNif.msgpack_decode(CryptoNif.msgpack_encode(%{status: :ok})) == %{"status" => "ok"}
+    end
+
+    test "rejects malformed input and nesting over the limit" do
+      for hex <- ["A2FFFF", "DDFFFFFFFF", "C0C0", "C1", "CB7FF8000000000000", "82A161C0A161C0"] do
+        assert {:error, _} = CryptoNif.msgpack_decode(Base.decode16!(hex))
+      end
+
+      nested = Base.decode16!("919190")
+      assert {:error, _} = CryptoNif.msgpack_decode(nested, max_depth: 2)
+      assert CryptoNif.msgpack_decode(nested, max_depth: 3) == [[[]]]
+      assert_raise ArgumentError, fn -> CryptoNif.msgpack_decode(nested, max_depth: 129) end
+
+      assert_raise ArgumentError, fn -> CryptoNif.msgpack_encode(<<0xFF>>) end
+      assert_raise ArgumentError, fn -> CryptoNif.msgpack_encode(18_446_744_073_709_551_616) end
+      assert_raise ArgumentError, fn -> CryptoNif.msgpack_encode({1, 2}) end
+    end
+  end
+
+  describe "block headers" do
+    @easy_bits 0x2100FFFF
+
+    test "encodes and decodes headers" do
+      bytes = CryptoNif.header_encode(genesis_header())
+      decoded = CryptoNif.header_decode(bytes)
+
+      assert Map.delete(decoded, :hash) == genesis_header()
+      assert decoded.hash == CryptoNif.header_hash(bytes)
+      assert CryptoNif.header_encode(Map.delete(genesis_header(), :signature)) == bytes
+
+      assert {:error, _} = CryptoNif.header_decode(bytes <> <<0>>)
+      assert {:error, _} = CryptoNif.header_decode(binary_part(bytes, 0, byte_size(bytes) - 1))
+      assert_raise ArgumentError, fn -> CryptoNif.header_encode(%{genesis_header() | previous_hash: "short"}) end
+      assert_raise ArgumentError, fn -> CryptoNif.header_encode(%{genesis_header() | signature: "sig"}) end
+    end
+
+    test "validates a signed header against its parent" do
+      {pk, _sk} = keypair = CryptoNif.falcon512_keypair()
+      parent = CryptoNif.header_encode(genesis_header())
+      header = child_header(parent, keypair)
+      now = genesis_header().timestamp + 10_000
+      hash = CryptoNif.header_hash(header)
+
+      assert CryptoNif.validate_header(header, parent, now: now) == hash
+      assert CryptoNif.validate_header(header, parent, now: now, expected_bits: @easy_bits, producer_key: pk) == hash
+      assert CryptoNif.validate_header(child_header(header, keypair), header, now: now + 10_000) ==
+               CryptoNif.header_hash(child_header(header, keypair))
+    end
+
+    test "reports the first failing check" do
+      keypair = CryptoNif.falcon512_keypair()
+      parent = CryptoNif.header_encode(genesis_header())
+      now = genesis_header().timestamp + 10_000
+      validate = fn header, opts -> CryptoNif.validate_header(header, parent, [now: now] ++ opts) end
+
+      assert {:error, "height" <> _} = validate.(child_header(parent, keypair, %{height: 2}), [])
+      assert {:error, "previous hash" <> _} = validate.(child_header(parent, keypair, %{previous_hash: <<1::256>>}), [])
+      assert {:error, "timestamp not after" <> _} = validate.(child_header(parent, keypair, %{timestamp: now - 10_000}), [])
+      assert {:error, "timestamp too far" <> _} = validate.(child_header(parent, keypair, %{timestamp: now + 121_000}), [])
+      assert {:error, "bits" <> _} = validate.(child_header(parent, keypair), expected_bits: 0x1D00FFFF)
+      assert {:error, "header from another producer"} = validate.(child_header(parent, keypair), producer_key: "other")
+
+      unsigned = with_work(%{genesis_header() | height: 1, previous_hash: CryptoNif.header_hash(parent), timestamp: now})
+      assert {:error, "unsigned header"} = validate.(CryptoNif.header_encode(unsigned), [])
+      assert is_binary(validate.(CryptoNif.header_encode(unsigned), require_signature: false))
+
+      forged = child_header(parent, keypair) |> CryptoNif.header_decode() |> Map.put(:signature, "forged")
+      assert {:error, "invalid producer signature"} = validate.(CryptoNif.header_encode(forged), [])
+
+      hard = child_header(parent, keypair) |> CryptoNif.header_decode() |> Map.put(:bits, 0x01010000)
+      assert {:error, "hash above the target"} = validate.(CryptoNif.header_encode(hard), [])
+      assert_raise ArgumentError, fn -> validate.(CryptoNif.header_encode(hard), unknown: 1) end
+    end
+  end
+
+  describe "finality proofs" do
+    setup do
+      keypairs = for _ <- 1..4, do: CryptoNif.dilithium2_keypair()
+      validators = for {pk, _sk} <- keypairs, do: {:dilithium2, pk, 10}
+      root = CryptoNif.finality_validator_set_root(validators)
+      header = CryptoNif.header_encode(genesis_header())
+      message = CryptoNif.finality_message(header, root, root)
+      signatures = for {{_pk, sk}, i} <- Enum.with_index(keypairs), do: {i, CryptoNif.dilithium2_sign(message, sk)}
+
+      {:ok, validators: validators, root: root, header: header, signatures: signatures}
+    end
+
+    test "verifies a proof signed by over 2/3 of the weight", ctx do
+      proof = CryptoNif.finality_proof_build(ctx.header, ctx.validators, ctx.root, Enum.take(ctx.signatures, -3))
+
+      assert {header, root, [1, 2, 3]} = CryptoNif.finality_proof_verify(proof, ctx.root)
+      assert header == CryptoNif.header_decode(ctx.header)
+      assert root == ctx.root
+    end
+
+    test "rejects short quorums, forged signatures and other sets", ctx do
+      [first, second, third, fourth] = ctx.signatures
+      short = CryptoNif.finality_proof_build(ctx.header, ctx.validators, ctx.root, [first, second])
+      {index, signature} = fourth
+      forged = CryptoNif.finality_proof_build(ctx.header, ctx.validators, ctx.root, [first, second, {index - 1, signature}])
+      proof = CryptoNif.finality_proof_build(ctx.header, ctx.validators, ctx.root, [first, second, third])
+
+      assert {:error, _} = CryptoNif.finality_proof_verify(short, ctx.root)
+      assert {:error, "invalid signatures from validators 2"} = CryptoNif.finality_proof_verify(forged, ctx.root)
+      assert {:error, _} = CryptoNif.finality_proof_verify(proof, CryptoNif.blake3_hash("other set"))
+      assert {:error, _} = CryptoNif.finality_proof_verify(proof <> <<0>>, ctx.root)
+
+      # Signatures are bound to the next set root
+      next_root = CryptoNif.blake3_hash("next set")
+      moved = CryptoNif.finality_proof_build(ctx.header, ctx.validators, next_root, [first, second, third])
+      assert {:error, _} = CryptoNif.finality_proof_verify(moved, ctx.root)
+    end
+
+    test "raises on invalid sets and signatures", ctx do
+      assert_raise ArgumentError, fn -> CryptoNif.finality_validator_set_root([]) end
+      assert_raise ArgumentError, fn -> CryptoNif.finality_validator_set_root([{:dilithium2, "pk", 0}]) end
+      assert_raise ArgumentError, fn -> CryptoNif.finality_proof_build(ctx.header, ctx.validators, ctx.root, []) end
+      assert_raise ArgumentError, fn -> CryptoNif.finality_proof_build(ctx.header, ctx.validators, ctx.root, [{4, "sig"}]) end
+      assert {:error, _} = CryptoNif.finality_message("garbage", ctx.root, ctx.root)
+    end
+  end
+
+  describe "light client" do
+    setup do
+      {pk, sk} = CryptoNif.dilithium2_keypair()
+      root = CryptoNif.finality_validator_set_root([{:dilithium2, pk, 1}])
+      leaves = for i <- 1..5, do: "tx #{i}"
+      {merkle_root, tree} = CryptoNif.merkle_build(leaves)
+      header = CryptoNif.header_encode(%{genesis_header() | merkle_root: merkle_root})
+
+      finalize = fn header, next_root ->
+        message = CryptoNif.finality_message(header, root, next_root)
+        signature = CryptoNif.dilithium2_sign(message, sk)
+        CryptoNif.finality_proof_build(header, [{:dilithium2, pk, 1}], next_root, [{0, signature}])
+      end
+
+      {:ok, root: root, tree: tree, header: header, finalize: finalize}
+    end
+
+    test "follows finality proofs and headers, and checks inclusion", ctx do
+      client = CryptoNif.light_client_new(ctx.root, [])
+      hash = CryptoNif.header_hash(ctx.header)
+
+      assert %{validator_set_root: root, finalized: nil, head: nil} = CryptoNif.light_client_status(client)
+      assert root == ctx.root
+      assert {:error, _} = CryptoNif.light_client_add_header(client, ctx.header, [])
+
+      assert CryptoNif.light_client_add_finality_proof(client, ctx.finalize.(ctx.header, ctx.root)) == 0
+      proof = CryptoNif.merkle_proof(ctx.tree, 2)
+      assert CryptoNif.light_client_verify_inclusion(client, hash, "tx 3", proof) == {0, true}
+      assert {:error, _} = CryptoNif.light_client_verify_inclusion(client, hash, "tx 4", proof)
+
+      child = child_header(ctx.header, CryptoNif.falcon512_keypair())
+      child_hash = CryptoNif.light_client_add_header(client, child, [])
+      assert child_hash == CryptoNif.header_hash(child)
+      assert %{finalized: {0, ^hash}, head: {1, ^child_hash}} = CryptoNif.light_client_status(client)
+      assert {:error, _} = CryptoNif.light_client_verify_inclusion(client, <<0::256>>, "tx 3", proof)
+    end
+
+    test "rejects proofs from other sets and stale heights", ctx do
+      client = CryptoNif.light_client_new(CryptoNif.blake3_hash("other set"), [])
+      assert {:error, _} = CryptoNif.light_client_add_finality_proof(client, ctx.finalize.(ctx.header, ctx.root))
+
+      client = CryptoNif.light_client_new(ctx.root, max_headers: 10)
+      next_root = CryptoNif.blake3_hash("next set")
+      assert CryptoNif.light_client_add_finality_proof(client, ctx.finalize.(ctx.header, next_root)) == 0
+      assert %{validator_set_root: ^next_root} = CryptoNif.light_client_status(client)
+      assert {:error, _} = CryptoNif.light_client_add_finality_proof(client, ctx.finalize.(ctx.header, next_root))
+
+      assert_raise ArgumentError, fn -> CryptoNif.light_client_new(ctx.root, max_headers: 0) end
+    end
+  end
+
+  describe "equivocation evidence" do
+    setup do
+      {pk, sk} = CryptoNif.falcon512_keypair()
+
+      vote = fn height, round, block_id ->
+        CryptoNif.vote_encode(%{
+          height: height,
+          round: round,
+          block_id: block_id,
+          algorithm: :falcon512,
+          public_key: pk,
+          signature: CryptoNif.falcon512_sign(CryptoNif.vote_message(height, round, block_id), sk)
+        })
+      end
+
+      {:ok, keypair: {pk, sk}, vote: vote}
+    end
+
+    test "encodes and decodes votes", %{keypair: {pk, _sk}, vote: vote} do
+      bytes = vote.(12, 1, <<1::256>>)
+
+      assert %{height: 12, round: 1, block_id: <<1::256>>, algorithm: :falcon512, public_key: ^pk} =
+               CryptoNif.vote_decode(bytes)
+
+      assert {:error, _} = CryptoNif.vote_decode(bytes <> <<0>>)
+      assert_raise ArgumentError, fn -> CryptoNif.vote_encode(%{CryptoNif.vote_decode(bytes) | block_id: "short"}) end
+    end
+
+    test "proves a double vote", %{keypair: {pk, _sk}, vote: vote} do
+      first = vote.(12, 1, <<1::256>>)
+      second = vote.(12, 1, <<2::256>>)
+      evidence = CryptoNif.equivocation_evidence(:vote, second, first)
+
+      assert evidence == CryptoNif.equivocation_evidence(:vote, first, second)
+
+      assert %{type: :vote, algorithm: :falcon512, public_key: ^pk, height: 12, round: 1} =
+               offence = CryptoNif.verify_equivocation(evidence)
+
+      assert Enum.sort(offence.block_ids) == [<<1::256>>, <<2::256>>]
+
+      assert {:error, _} = CryptoNif.equivocation_evidence(:vote, first, vote.(12, 2, <<2::256>>))
+      assert {:error, _} = CryptoNif.equivocation_evidence(:vote, first, vote.(12, 1, <<1::256>>))
+      assert {:error, _} = CryptoNif.verify_equivocation(evidence <> <<0>>)
+    end
+
+    test "proves a double proposal and rejects other producers", %{keypair: keypair} do
+      parent = CryptoNif.header_encode(genesis_header())
+      first = child_header(parent, keypair)
+      second = child_header(parent, keypair, %{merkle_root: CryptoNif.blake3_hash("fork")})
+      other = child_header(parent, CryptoNif.falcon512_keypair(), %{merkle_root: CryptoNif.blake3_hash("fork")})
+
+      evidence = CryptoNif.equivocation_evidence(:header, first, second)
+      assert %{type: :header, height: 1, round: nil} = CryptoNif.verify_equivocation(evidence)
+
+      assert {:error, "statements from different keys"} = CryptoNif.equivocation_evidence(:header, first, other)
+      assert_raise ArgumentError, fn -> CryptoNif.equivocation_evidence(:block, first, second) end
+    end
+  end
+
+  describe "quorum certificates" do
+    setup do
+      keypairs = for _ <- 1..4, do: CryptoNif.falcon512_keypair()
+      validators = for {{pk, _sk}, weight} <- Enum.zip(keypairs, [1, 2, 3, 4]), do: {:falcon512, pk, weight}
+      block_id = CryptoNif.blake3_hash("block")
+      message = CryptoNif.vote_message(7, 2, block_id)
+      votes = for {{_pk, sk}, i} <- Enum.with_index(keypairs), do: {i, CryptoNif.falcon512_sign(message, sk)}
+
+      {:ok, validators: validators, block_id: block_id, votes: votes}
+    end
+
+    test "collects votes up to a quorum and certifies them", ctx do
+      [v0, v1, v2, v3] = ctx.votes
+      collector = CryptoNif.qc_collector_new(7, 2, ctx.block_id, ctx.validators)
+
+      {_, forged} = v0
+      assert CryptoNif.qc_add_votes(collector, [v3, v3, {9, forged}, {1, forged}]) == {4, false, [3, 9, 1]}
+      assert {:error, _} = CryptoNif.qc_certificate(collector)
+      assert CryptoNif.qc_add_votes(collector, [v2, v3]) == {7, true, [3]}
+
+      certificate = CryptoNif.qc_certificate(collector)
+      assert CryptoNif.qc_verify(certificate, ctx.validators) == {7, 2, ctx.block_id, [2, 3]}
+
+      assert CryptoNif.qc_add_votes(collector, [v1, v0]) == {10, true, []}
+      assert {7, 2, _, [0, 1, 2, 3]} = CryptoNif.qc_verify(CryptoNif.qc_certificate(collector), ctx.validators)
+    end
+
+    test "rejects certificates from other sets", ctx do
+      collector = CryptoNif.qc_collector_new(7, 2, ctx.block_id, ctx.validators)
+      CryptoNif.qc_add_votes(collector, ctx.votes)
+      certificate = CryptoNif.qc_certificate(collector)
+      [{alg, pk, _} | rest] = ctx.validators
+
+      assert {:error, _} = CryptoNif.qc_verify(certificate, [{alg, pk, 5} | rest])
+      assert {:error, _} = CryptoNif.qc_verify(certificate <> <<0>>, ctx.validators)
+      assert_raise ArgumentError, fn -> CryptoNif.qc_collector_new(7, 2, ctx.block_id, [{:falcon512, "bad key", 1}]) end
+    end
+  end
+
+  describe "randomness beacon" do
+    test "verifies reveals against commitments" do
+      openings = for i <- 1..5, do: {CryptoNif.blake3_hash("reveal #{i}"), CryptoNif.blake3_hash("salt #{i}")}
+      items = for {reveal, salt} <- openings, do: {CryptoNif.beacon_commit(reveal, salt), reveal, salt}
+
+      assert CryptoNif.beacon_verify_reveals(items) == []
+
+      {commitment, reveal, _salt} = Enum.at(items, 3)
+      tampered = List.replace_at(items, 3, {commitment, reveal, <<0::256>>})
+      assert CryptoNif.beacon_verify_reveals(tampered) == [3]
+
+      assert_raise ArgumentError, fn -> CryptoNif.beacon_commit("short", <<0::256>>) end
+    end
+
+    test "mixes reveals independently of their order" do
+      previous = CryptoNif.blake3_hash("epoch 41")
+      reveals = for i <- 1..5, do: CryptoNif.blake3_hash("reveal #{i}")
+      randomness = CryptoNif.beacon_mix(previous, 42, reveals)
+
+      assert byte_size(randomness) == 32
+      assert CryptoNif.beacon_mix(previous, 42, Enum.reverse(reveals)) == randomness
+      refute CryptoNif.beacon_mix(previous, 43, reveals) == randomness
+      refute CryptoNif.beacon_mix(previous, 42, tl(reveals)) == randomness
+      refute CryptoNif.beacon_mix(previous, 42, [hd(reveals) | reveals]) == randomness
+    end
+  end
+
+  describe "wasm runtime" do
+    # Needs the NIF built with the wasm feature: mix test --include wasm
+    @describetag :wasm
+
+    @counter_wat """
+    (module
+      (memory 1)
+      (global $count (mut i64) (i64.const 0))
+      (func (export "add") (param i32 i32) (result i32) local.get 0 local.get 1 i32.add)
+      (func (export "bump") (result i64)
+        global.get $count i64.const 1 i64.add global.set $count global.get $count)
+      (func (export "spin") (loop br 0))
+      (func (export "div") (param i32) (result i32) i32.const 1 local.get 0 i32.div_s)
+      (func (export "grow") (param i32) (result i32) local.get 0 memory.grow)
+      (func (export "nan") (result f64) f64.const 0 f64.const 0 f64.div)
+      (func (export "nan_bits") (result i64) f64.const 0 f64.const 0 f64.div i64.reinterpret_f64))
+    """
+
+    test "calls exports with gas, keeping state between calls" do
+      instance = CryptoNif.wasm_instantiate(@counter_wat, [])
+
+      assert {[5], gas} = CryptoNif.wasm_call(instance, "add", [2, 3], 100)
+      assert gas > 0
+      assert {[1], _} = CryptoNif.wasm_call(instance, "bump", [], 100)
+      assert {[2], _} = CryptoNif.wasm_call(instance, "bump", [], 100)
+      # NaNs are canonical, whatever the CPU
+      assert {[0x7FF8000000000000], _} = CryptoNif.wasm_call(instance, "nan_bits", [], 100)
+      assert {:error, "non-finite float result", _} = CryptoNif.wasm_call(instance, "nan", [], 100)
+    end
+
+    test "charges all the gas of a call that runs out" do
+      instance = CryptoNif.wasm_instantiate(@counter_wat, [])
+
+      assert CryptoNif.wasm_call(instance, "spin", [], 10_000) == {:error, "out of gas", 10_000}
+      assert {:error, "wasm trap: integer divide by zero", _} = CryptoNif.wasm_call(instance, "div", [0], 100)
+      assert {:error, _, 0} = CryptoNif.wasm_call(instance, "missing", [], 100)
+      assert {:error, _, 0} = CryptoNif.wasm_call(instance, "add", [1], 100)
+    end
+
+    test "caps memory and rejects imports" do
+      instance = CryptoNif.wasm_instantiate(@counter_wat, max_memory: 2 * 65_536)
+
+      assert {[1], _} = CryptoNif.wasm_call(instance, "grow", [1], 100)
+      assert {[-1], _} = CryptoNif.wasm_call(instance, "grow", [1], 100)
+
+      assert {:error, _} = CryptoNif.wasm_instantiate(~s|(module (import "env" "f" (func)))|, [])
+      assert {:error, _} = CryptoNif.wasm_instantiate("(module (memory 4))", max_memory: 65_536)
+      assert {:error, "out of gas"} = CryptoNif.wasm_instantiate("(module (func $s (loop br 0)) (start $s))", [])
+      assert {:error, _} = CryptoNif.wasm_instantiate("not wasm", [])
+    end
+
+    test "meters modules canonically and charges the metered gas" do
+      wat = """
+      (module
+        (func (export "sum") (param i32) (result i32) (local i32)
+          block
+            loop
+              local.get 0 i32.eqz br_if 1
+              local.get 1 local.get 0 i32.add local.set 1
+              local.get 0 i32.const 1 i32.sub local.set 0
+              br 0
+            end
+          end
+          local.get 1)
+        (func (export "spin") (loop br 0)))
+      """
+
+      metered = CryptoNif.wasm_meter(wat)
+      assert <<0, "asm", 1, 0, 0, 0, _::binary>> = metered
+      # A metered module can't be metered again
+      assert CryptoNif.wasm_meter(metered) == {:error, "module already exports bastille_gas"}
+
+      instance = CryptoNif.wasm_instantiate(metered, [])
+      assert {[55], gas} = CryptoNif.wasm_call(instance, "sum", [10], 1_000_000)
+      assert {[55], ^gas} = CryptoNif.wasm_call(instance, "sum", [10], gas)
+      assert CryptoNif.wasm_call(instance, "sum", [10], gas - 1) == {:error, "out of gas", gas - 1}
+      assert CryptoNif.wasm_call(instance, "spin", [], 10_000) == {:error, "out of gas", 10_000}
+    end
+
+    test "charges bulk memory operations by length" do
+      wat = """
+      (module
+        (memory 1)
+        (func (export "fill") (param i32) (result i32)
+          i32.const 0 i32.const 7 local.get 0 memory.fill
+          i32.const 0 i32.load8_u)
+        (func (export "grow") (param i32) (result i32)
+          local.get 0 memory.grow))
+      """
+
+      instance = CryptoNif.wasm_instantiate(CryptoNif.wasm_meter(wat), max_memory: 4 * 65_536)
+      assert {[7], small} = CryptoNif.wasm_call(instance, "fill", [16], 100_000)
+      assert {[7], large} = CryptoNif.wasm_call(instance, "fill", [65_536], 100_000)
+      assert large - small == 65_536 - 16
+      assert CryptoNif.wasm_call(instance, "fill", [65_536], 60_000) == {:error, "out of gas", 60_000}
+      assert CryptoNif.wasm_call(instance, "grow", [1], 60_000) == {:error, "out of gas", 60_000}
+      assert {[1], _} = CryptoNif.wasm_call(instance, "grow", [1], 100_000)
+    end
+
+    test "exposes blake3 and signature verification to contracts" do
+      {pk, sk} = CryptoNif.falcon512_keypair()
+      signature = CryptoNif.falcon512_sign("hello", sk)
+
+      wat = """
+      (module
+        (import "bastille" "blake3" (func $blake3 (param i32 i32 i32)))
+        (import "bastille" "verify" (func $verify (param i32 i32 i32 i32 i32 i32 i32) (result i32)))
+        (memory (export "memory") 1)
+        (data (i32.const 0) "hello")
+        (data (i32.const 1024) "#{wat_bytes(pk)}")
+        (data (i32.const 4096) "#{wat_bytes(signature)}")
+        (func (export "hash") (result i64)
+          i32.const 0 i32.const 5 i32.const 64 call $blake3 i32.const 64 i64.load)
+        (func (export "verify") (param i32 i32) (result i32)
+          local.get 0 i32.const 1024 i32.const #{byte_size(pk)} i32.const 0 local.get 1
+          i32.const 4096 i32.const #{byte_size(signature)} call $verify))
+      """
+
+      instance = CryptoNif.wasm_instantiate(CryptoNif.wasm_meter(wat), [])
+      <<hash::little-signed-64, _::binary>> = CryptoNif.blake3_hash("hello")
+
+      assert {[^hash], gas} = CryptoNif.wasm_call(instance, "hash", [], 10_000)
+      assert gas > 100
+      assert {[1], _} = CryptoNif.wasm_call(instance, "verify", [2, 5], 1_000_000)
+      assert {[0], _} = CryptoNif.wasm_call(instance, "verify", [2, 4], 1_000_000)
+      assert {:error, "unsupported algorithm 3", _} = CryptoNif.wasm_call(instance, "verify", [3, 5], 1_000_000)
+      assert CryptoNif.wasm_call(instance, "verify", [2, 5], 50_000) == {:error, "out of gas", 50_000}
+    end
+
+    test "bridges contract storage to an Elixir process" do
+      wat = """
+      (module
+        (import "bastille" "storage_get" (func $get (param i32 i32 i32 i32) (result i32)))
+        (import "bastille" "storage_put" (func $put (param i32 i32 i32 i32)))
+        (memory (export "memory") 1)
+        (data (i32.const 0) "n")
+        (func (export "incr") (result i64) (local i64)
+          i32.const 0 i32.const 1 i32.const 8 i32.const 8 call $get
+          i32.const 8 i32.eq
+          if i32.const 8 i64.load local.set 0 end
+          local.get 0 i64.const 1 i64.add local.set 0
+          i32.const 8 local.get 0 i64.store
+          i32.const 0 i32.const 1 i32.const 8 i32.const 8 call $put
+          local.get 0))
+      """
+
+      metered = CryptoNif.wasm_meter(wat)
+      test_pid = self()
+      storage = spawn_link(fn -> serve_storage(%{}, test_pid) end)
+      instance = CryptoNif.wasm_instantiate(metered, storage: storage)
+
+      assert {[1], _} = CryptoNif.wasm_call(instance, "incr", [], 100_000)
+      assert {[2], _} = CryptoNif.wasm_call(instance, "incr", [], 100_000)
+      assert_receive {:stored, "n", <<1::little-64>>}
+      assert_receive {:stored, "n", <<2::little-64>>}
+
+      instance = CryptoNif.wasm_instantiate(metered, [])
+      assert {:error, "no storage", _} = CryptoNif.wasm_call(instance, "incr", [], 100_000)
+      assert {:error, _} = CryptoNif.wasm_instantiate(metered, storage: self())
+    end
+
+    test "aborts calls whose storage fails rather than trapping" do
+      metered =
+        CryptoNif.wasm_meter("""
+        (module
+          (import "bastille" "storage_get" (func $get (param i32 i32 i32 i32) (result i32)))
+          (memory (export "memory") 1)
+          (func (export "get") (result i32) i32.const 0 i32.const 1 i32.const 8 i32.const 8 call $get))
+        """)
+
+      silent = spawn_link(fn -> Process.sleep(:infinity) end)
+      instance = CryptoNif.wasm_instantiate(metered, storage: silent, storage_timeout: 50)
+      assert CryptoNif.wasm_call(instance, "get", [], 100_000) == {:aborted, "storage timeout"}
+      # The aborted instance is done with
+      assert {:aborted, _} = CryptoNif.wasm_call(instance, "get", [], 100_000)
+
+      gone = spawn(fn -> :ok end)
+      ref = Process.monitor(gone)
+      assert_receive {:DOWN, ^ref, _, _, _}
+      instance = CryptoNif.wasm_instantiate(metered, storage: gone)
+      assert CryptoNif.wasm_call(instance, "get", [], 100_000) == {:aborted, "storage process is gone"}
+    end
+
+    test "rejects non-deterministic modules" do
+      assert {:error, _} = CryptoNif.wasm_meter("(module (func (result f64) f64.const 1))")
+      assert {:error, _} = CryptoNif.wasm_meter("(module (func (result v128) v128.const i64x2 0 0))")
+      assert {:error, _} = CryptoNif.wasm_meter("(module (memory 1 1 shared))")
+      assert {:error, _} = CryptoNif.wasm_meter(~s|(module (global (export "bastille_gas") i64 (i64.const 0)))|)
+      assert {:error, _} = CryptoNif.wasm_meter("not wasm")
+    end
+  end
+
+  describe "transactions" do
+    setup do
+      tx = %{
+        chain_id: "testnet",
+        from: "1789" <> String.duplicate("a", 40),
+        to: "1789" <> String.duplicate("b", 40),
+        amount: 300_000 * 100_000_000_000_000,
+        fee: 100_000,
+        nonce: 7,
+        timestamp: 1_700_000_000,
+        data: "memo",
+        signature_type: :post_quantum_2_of_3,
+        signature: %{dilithium: "d", falcon: "f", sphincs: "s"}
+      }
+
+      {:ok, tx: tx}
+    end
+
+    test "round-trips with signing bytes as a prefix", %{tx: tx} do
+      encoded = CryptoNif.tx_encode(Map.put(tx, :hash, "ignored"))
+      signing = CryptoNif.tx_signing_bytes(tx)
+
+      assert CryptoNif.tx_decode(encoded) == tx
+      assert binary_part(encoded, 0, byte_size(signing)) == signing
+      assert <<1, 7, "testnet", 44, _::binary>> = signing
+      assert CryptoNif.tx_signing_bytes(%{tx | signature: nil}) == signing
+      refute CryptoNif.tx_signing_bytes(%{tx | fee: 100_001}) == signing
+      refute CryptoNif.tx_signing_bytes(%{tx | chain_id: "mainnet"}) == signing
+
+      coinbase = %{tx | signature_type: :coinbase, signature: %{type: :coinbase}}
+      assert CryptoNif.tx_decode(CryptoNif.tx_encode(coinbase)) == coinbase
+    end
+
+    test "rejects other versions, trailing bytes and mismatched signatures", %{tx: tx} do
+      <<_version, rest::binary>> = encoded = CryptoNif.tx_encode(tx)
+
+      assert {:error, _} = CryptoNif.tx_decode(<<2>> <> rest)
+      assert {:error, _} = CryptoNif.tx_decode(encoded <> <<0>>)
+      assert {:error, _} = CryptoNif.tx_decode(binary_part(encoded, 0, byte_size(encoded) - 1))
+
+      assert_raise ArgumentError, fn -> CryptoNif.tx_encode(%{tx | signature_type: :coinbase}) end
+      assert_raise ArgumentError, fn -> CryptoNif.tx_encode(%{tx | nonce: -1}) end
+      assert_raise ArgumentError, fn -> CryptoNif.tx_encode(Map.delete(tx, :chain_id)) end
+      assert_raise ArgumentError, fn -> CryptoNif.tx_signing_bytes(%{tx | data: :binary.copy("x", 65_537)}) end
+    end
+  end
+
+  describe "mempool admission" do
+    setup do
+      {dilithium_pk, dilithium_sk} = CryptoNif.dilithium2_keypair()
+      {falcon_pk, falcon_sk} = CryptoNif.falcon512_keypair()
+      {sphincs_pk, sphincs_sk} = CryptoNif.sphincsplus_shake_128f_keypair()
+      from = "1789" <> String.duplicate("a", 40)
+
+      sign = fn tx ->
+        message = CryptoNif.tx_signing_bytes(tx)
+
+        signature = %{
+          dilithium: CryptoNif.dilithium2_sign(message, dilithium_sk),
+          falcon: CryptoNif.falcon512_sign(message, falcon_sk),
+          sphincs: CryptoNif.sphincsplus_shake_128f_sign(message, sphincs_sk)
+        }
+
+        CryptoNif.tx_encode(%{tx | signature: signature})
+      end
+
+      tx = %{
+        chain_id: "testnet",
+        from: from,
+        to: "1789" <> String.duplicate("b", 40),
+        amount: 1_000,
+        fee: 100_000,
+        nonce: 5,
+        timestamp: 1_700_000_000,
+        data: "",
+        signature_type: :post_quantum_2_of_3,
+        signature: nil
+      }
+
+      params = [
+        chain_id: "testnet",
+        min_fee: 1_000,
+        nonces: %{from => 5},
+        public_keys: %{from => %{dilithium: dilithium_pk, falcon: falcon_pk, sphincs: sphincs_pk}}
+      ]
+
+      {:ok, tx: tx, sign: sign, params: params}
+    end
+
+    test "returns a verdict per transaction, in order", %{tx: tx, sign: sign, params: params} do
+      signed = sign.(tx)
+      later = sign.(%{tx | nonce: 6})
+
+      assert CryptoNif.precheck_transactions([signed, later], params) == [:ok, :ok]
+      assert [:ok, {:error, _}] = CryptoNif.precheck_transactions([signed, signed], params)
+      assert [{:error, _}, :ok] = CryptoNif.precheck_transactions(["garbage", later], params)
+      assert CryptoNif.precheck_transactions([], params) == []
+    end
+
+    test "rejects each failed check", %{tx: tx, sign: sign, params: params} do
+      cases = [
+        sign.(%{tx | chain_id: "mainnet"}),
+        sign.(%{tx | fee: 999}),
+        sign.(%{tx | nonce: 4}),
+        sign.(%{tx | nonce: 70}),
+        sign.(%{tx | from: "1789" <> String.duplicate("c", 40)}),
+        CryptoNif.tx_encode(tx),
+        CryptoNif.tx_encode(%{tx | signature_type: :coinbase, signature: %{type: :coinbase}})
+      ]
+
+      verdicts = CryptoNif.precheck_transactions(cases, params)
+      assert Enum.all?(verdicts, &match?({:error, reason} when is_binary(reason), &1))
+
+      signed = sign.(tx)
+      assert [{:error, _}] = CryptoNif.precheck_transactions([signed], Keyword.put(params, :max_size, byte_size(signed) - 1))
+    end
+
+    test "needs two of the three signatures", %{tx: tx, sign: sign, params: params} do
+      decoded = CryptoNif.tx_decode(sign.(tx))
+      one_bad = put_in(decoded, [:signature, :sphincs], :binary.copy(<<0>>, 17_088))
+      two_bad = put_in(one_bad, [:signature, :falcon], "forged")
+
+      assert [:ok, {:error, _}] =
+               CryptoNif.precheck_transactions([CryptoNif.tx_encode(one_bad), CryptoNif.tx_encode(two_bad)], params)
+    end
+
+    test "raises on unknown options" do
+      assert_raise ArgumentError, fn -> CryptoNif.precheck_transactions([], bogus: 1) end
+    end
+  end
+
+  describe "canonical JSON" do
+    test "canonicalizes the RFC 8785 examples" do
+      json = ~S"""
+      {
+        "numbers": [333333333.33333329, 1E30, 4.50, 2e-3, 0.000000000000000000000000001],
+        "string": "\u20ac$\u000F\u000aA'\u0042\u0022\u005c\\\"\/",
+        "literals": [null, true, false]
+      }
+      """
+
+      canonical =
+        ~S({"literals":[null,true,false],"numbers":[333333333.3333333,1e+30,4.5,0.002,1e-27],"string":"€$\u000f\nA'B\"\\\\\"/"})
+
+      assert CryptoNif.jcs_canonicalize(json) == canonical
+      assert CryptoNif.jcs_verify(canonical)
+      refute CryptoNif.jcs_verify(json)
+
+      sorting = ~S({"\u20ac":1,"\r":2,"\ufb33":3,"1":4,"\ud83d\ude00":5,"\u0080":6,"\u00f6":7})
+      assert CryptoNif.jcs_canonicalize(sorting) == ~s({"\\r":2,"1":4,"\u0080":6,"\u00F6":7,"\u20AC":1,"\u{1F600}":5,"\uFB33":3})
+    end
+
+    test "encodes terms to the canonical form of their JSON" do
+      term = %{"b" => [1, 2.5, -0.0, 1.0e21], a: nil, c: %{"d" => true, "e" => "x\ny"}}
+      assert CryptoNif.jcs_encode(term) == ~S({"a":null,"b":[1,2.5,0,1e+21],"c":{"d":true,"e":"x\ny"}})
+      assert CryptoNif.jcs_verify(CryptoNif.jcs_encode(term))
+
+      assert_raise ArgumentError, fn -> CryptoNif.jcs_encode(9_007_199_254_740_992) end
+      assert_raise ArgumentError, fn -> CryptoNif.jcs_encode(%{"a" => 1, a: 2}) end
+      assert_raise ArgumentError, fn -> CryptoNif.jcs_encode({:tuple}) end
+    end
+
+    test "rejects invalid JSON and JSON outside I-JSON" do
+      for json <- ["", "[1,]", "01", "1e400", ~S("\ud800"), ~S({"a":1,"a":2}), "[1] x", ~S({"a":1 "b":2})] do
+        assert {:error, _} = CryptoNif.jcs_canonicalize(json)
+        refute CryptoNif.jcs_verify(json)
+      end
+    end
+  end
+
+  describe "compression" do
+    test "round-trips through zstd and caps the decompressed size" do
+      data = String.duplicate("bastille block payload ", 1_000)
+      compressed = CryptoNif.zstd_compress(data, 3)
+      assert byte_size(compressed) < byte_size(data) / 10
+
+      assert CryptoNif.zstd_decompress(compressed, byte_size(data)) == data
+      assert {:error, _} = CryptoNif.zstd_decompress(compressed, byte_size(data) - 1)
+      assert {:error, _} = CryptoNif.zstd_decompress("not zstd", 1_000)
+      assert CryptoNif.zstd_decompress(CryptoNif.zstd_compress("", 1), 0) == ""
+      assert_raise ArgumentError, fn -> CryptoNif.zstd_compress(data, 99) end
+    end
+
+    test "LZ4 frames round-trip and refuse decompression bombs" do
+      data = String.duplicate("gossip frame ", 500)
+      frame = CryptoNif.lz4_compress(data)
+      assert <<6_500::little-32, _::binary>> = frame
+      assert byte_size(frame) < byte_size(data)
+
+      assert CryptoNif.lz4_decompress(frame, 65_536) == data
+      assert {:error, _} = CryptoNif.lz4_decompress(frame, 6_499)
+      assert CryptoNif.lz4_decompress(CryptoNif.lz4_compress(""), 0) == ""
+
+      <<_::little-32, block::binary>> = frame
+      assert {:error, _} = CryptoNif.lz4_decompress(<<0xFFFFFFFF::little-32, block::binary>>, 65_536)
+      assert {:error, _} = CryptoNif.lz4_decompress(<<7_000::little-32, block::binary>>, 65_536)
+      assert {:error, _} = CryptoNif.lz4_decompress(binary_part(frame, 0, 10), 65_536)
+      assert {:error, _} = CryptoNif.lz4_decompress(<<1, 2>>, 65_536)
+    end
+
+    test "a trained dictionary shrinks small, similar payloads" do
+      blocks = for height <- 1..500, do: sample_block(height)
+      dictionary = CryptoNif.zstd_train_dictionary(blocks, 16_384)
+      assert byte_size(dictionary) <= 16_384
+      prepared = CryptoNif.zstd_dictionary(dictionary, 3)
+
+      block = sample_block(1_000)
+      plain = CryptoNif.zstd_compress(block, 3)
+      with_dictionary = CryptoNif.zstd_compress_with_dictionary(prepared, block)
+      assert byte_size(with_dictionary) < byte_size(plain)
+
+      assert CryptoNif.zstd_decompress_with_dictionary(prepared, with_dictionary, 1_000_000) == block
+      assert {:error, _} = CryptoNif.zstd_decompress(with_dictionary, 1_000_000)
+      assert {:error, _} = CryptoNif.zstd_train_dictionary(Enum.take(blocks, 2), 16_384)
+    end
+  end
+
+  describe "secret key handles" do
+    test "sign with handles for every algorithm" do
+      message = "signed through a handle"
+
+      {pk, handle} = CryptoNif.dilithium2_keypair_handle()
+      assert is_reference(handle)
+      assert CryptoNif.dilithium2_verify(CryptoNif.dilithium2_sign(message, handle), message, pk)
+
+      {pk, handle} = CryptoNif.falcon512_keypair_handle()
+      assert CryptoNif.falcon512_verify(CryptoNif.falcon512_sign(message, handle), message, pk)
+
+      {pk, handle} = CryptoNif.sphincsplus_shake_128f_keypair_handle()
+      assert CryptoNif.sphincsplus_shake_128f_verify(CryptoNif.sphincsplus_shake_128f_sign(message, handle), message, pk)
+    end
+
+    test "rejects a handle of another algorithm" do
+      {_pk, handle} = CryptoNif.falcon512_keypair_handle()
+
+      assert_raise ArgumentError, fn -> CryptoNif.dilithium2_sign("message", handle) end
+    end
+  end
+
+  describe "secure randomness" do
+    test "returns fresh bytes of the requested size" do
+      assert byte_size(CryptoNif.secure_random_bytes(32)) == 32
+      assert CryptoNif.secure_random_bytes(0) == ""
+      refute CryptoNif.secure_random_bytes(32) == CryptoNif.secure_random_bytes(32)
+    end
+
+    test "passes the health checks" do
+      assert CryptoNif.rng_selftest() == :ok
+    end
+
+    test "rejects oversized requests" do
+      assert_raise ArgumentError, fn -> CryptoNif.secure_random_bytes(1024 * 1024 + 1) end
+      assert_raise ArgumentError, fn -> CryptoNif.secure_random_bytes(-1) end
+    end
+  end
+
+  describe "keypair validation" do
+    test "accepts matching keypairs and handles" do
+      {pk, sk} = CryptoNif.dilithium2_keypair()
+      assert CryptoNif.validate_keypair(:dilithium2, pk, sk)
+
+      {pk, sk} = CryptoNif.sphincsplus_shake_128f_keypair()
+      assert CryptoNif.validate_keypair(:sphincsplus, pk, sk)
+
+      {pk, handle} = CryptoNif.falcon512_keypair_handle()
+      assert CryptoNif.validate_keypair(:falcon512, pk, handle)
+    end
+
+    test "rejects mismatched, corrupted or truncated keys" do
+      {pk, sk} = CryptoNif.falcon512_keypair()
+      {other_pk, _other_sk} = CryptoNif.falcon512_keypair()
+      refute CryptoNif.validate_keypair(:falcon512, other_pk, sk)
+
+      <<head::binary-size(100), byte, rest::binary>> = sk
+      refute CryptoNif.validate_keypair(:falcon512, pk, <<head::binary, Bitwise.bxor(byte, 0xFF), rest::binary>>)
+
+      {pk, sk} = CryptoNif.dilithium2_keypair()
+      refute CryptoNif.validate_keypair(:dilithium2, pk, binary_part(sk, 0, 100))
+      refute CryptoNif.validate_keypair(:falcon512, pk, sk)
     end
-    
-    test "handles empty data" do
-      hash = CryptoNif.blake3_hash("")
-      
-      assert byte_size(hash) == 32
-      assert is_binary(hash)
-      
-      # Empty string should produce specific hash
-      assert hash != <<0::256>>
+
+    test "raises on an unknown algorithm" do
+      {pk, sk} = CryptoNif.dilithium2_keypair()
+      assert_raise ArgumentError, fn -> CryptoNif.validate_keypair(:rsa, pk, sk) end
     end
-    
-    test "handles large data efficiently" do
-      # Create large test data (1MB)
-      large_data = String.duplicate("A", 1_024 * 1_024)
-      
-      start_time = System.monotonic_time(:millisecond)
-      hash = CryptoNif.blake3_hash(large_data)
-      end_time = System.monotonic_time(:millisecond)
-      
-      duration = end_time - start_time
-      
-      # Should complete quickly (less than 1 second for 1MB)
-      assert duration < 1_000
-      assert byte_size(hash) == 32
+  end
+
+  describe "addresses" do
+    test "derives checksummed address bytes from a public key" do
+      {pk, _sk} = CryptoNif.dilithium2_keypair()
+      address = CryptoNif.derive_address(:dilithium2, pk)
+
+      <<hash::binary-20, _::binary>> = CryptoNif.blake3_hash(pk)
+      <<checksum::binary-4, _::binary>> = CryptoNif.blake3_hash(<<1, hash::binary>>)
+      assert address == <<1, hash::binary, checksum::binary>>
+      assert CryptoNif.valid_address(address)
+    end
+
+    test "detects corrupted addresses" do
+      {pk, _sk} = CryptoNif.sphincsplus_shake_128f_keypair()
+      <<version, hash::binary-20, checksum::binary>> = CryptoNif.derive_address(:sphincsplus, pk)
+
+      assert version == 3
+      refute CryptoNif.valid_address(<<version, Bitwise.bxor(:binary.first(hash), 1), binary_part(hash, 1, 19)::binary, checksum::binary>>)
+      refute CryptoNif.valid_address(<<9, hash::binary, checksum::binary>>)
+      refute CryptoNif.valid_address(<<version, hash::binary>>)
+    end
+
+    test "rejects keys that do not match the algorithm" do
+      {pk, _sk} = CryptoNif.falcon512_keypair()
+
+      assert_raise ArgumentError, fn -> CryptoNif.derive_address(:dilithium2, pk) end
+      assert_raise ArgumentError, fn -> CryptoNif.derive_address(:rsa, pk) end
+    end
+
+    test "round-trips addresses through bech32m" do
+      {pk, _sk} = CryptoNif.falcon512_keypair()
+      address = CryptoNif.derive_address(:falcon512, pk)
+      encoded = CryptoNif.bech32m_encode("bast", address)
+
+      assert String.starts_with?(encoded, "bast1")
+      assert CryptoNif.bech32m_decode(encoded) == {"bast", address}
+      assert CryptoNif.bech32m_decode(String.upcase(encoded)) == {"bast", address}
+    end
+
+    test "decodes the BIP-350 test vectors" do
+      assert CryptoNif.bech32m_decode("A1LQFN3A") == {"a", ""}
+
+      assert CryptoNif.bech32m_decode("abcdef1l7aum6echk45nj3s0wdvt2fg8x9yrzpqzd3ryx") ==
+               {"abcdef", Base.decode16!("FFBBCDEB38BDAB49CA307B9AC5A928398A418820")}
+    end
+
+    test "rejects invalid bech32m strings" do
+      # Valid Bech32, but not Bech32m
+      assert {:error, _} = CryptoNif.bech32m_decode("a12uel5l")
+      assert {:error, _} = CryptoNif.bech32m_decode("A1lqfn3a")
+      assert {:error, _} = CryptoNif.bech32m_decode("a1lqfn3b")
+      assert {:error, _} = CryptoNif.bech32m_decode("no separator")
+      assert_raise ArgumentError, fn -> CryptoNif.bech32m_encode("", "data") end
+    end
+
+    test "encodes and decodes Base58Check" do
+      hash160 = Base.decode16!("010966776006953D5567439E5E39F86A0D273BEE")
+
+      assert CryptoNif.base58check_encode(0, hash160) == "16UwLL9Risc3QfPqBUvKofHmBQ7wMtjvM"
+      assert CryptoNif.base58check_decode("16UwLL9Risc3QfPqBUvKofHmBQ7wMtjvM") == {0, hash160}
+      assert CryptoNif.base58check_encode(0, <<0::160>>) == "1111111111111111111114oLvT2"
+    end
+
+    test "rejects invalid Base58Check strings" do
+      assert {:error, _} = CryptoNif.base58check_decode("16UwLL9Risc3QfPqBUvKofHmBQ7wMtjvN")
+      assert {:error, _} = CryptoNif.base58check_decode("0OIl")
+      assert {:error, _} = CryptoNif.base58check_decode("")
+    end
+  end
+
+  describe "key rotation certificates" do
+    test "the old key certifies its successor" do
+      {old_pk, old_sk} = CryptoNif.dilithium2_keypair()
+      {new_pk, _new_sk} = CryptoNif.dilithium2_keypair()
+
+      certificate = CryptoNif.build_rotation_certificate(:dilithium2, old_pk, old_sk, new_pk, 42_000, "scheduled")
+
+      assert {:ok, statement} = CryptoNif.verify_rotation_certificate(certificate)
+      assert statement.algorithm == :dilithium2
+      assert statement.old_public_key == old_pk
+      assert statement.new_public_key == new_pk
+      assert statement.effective_height == 42_000
+      assert statement.reason == "scheduled"
+    end
+
+    test "signs with a secret key handle" do
+      {old_pk, handle} = CryptoNif.falcon512_keypair_handle()
+      {new_pk, _} = CryptoNif.falcon512_keypair()
+
+      certificate = CryptoNif.build_rotation_certificate(:falcon512, old_pk, handle, new_pk, 7, "")
+      assert {:ok, %{new_public_key: ^new_pk}} = CryptoNif.verify_rotation_certificate(certificate)
+    end
+
+    test "rejects tampered certificates and mismatched keys" do
+      {old_pk, old_sk} = CryptoNif.dilithium2_keypair()
+      {new_pk, _} = CryptoNif.dilithium2_keypair()
+      {other_pk, _} = CryptoNif.dilithium2_keypair()
+      certificate = CryptoNif.build_rotation_certificate(:dilithium2, old_pk, old_sk, new_pk, 1, "compromise")
+
+      tampered = :binary.replace(certificate, new_pk, other_pk)
+      assert {:error, "invalid signature"} = CryptoNif.verify_rotation_certificate(tampered)
+      assert {:error, _} = CryptoNif.verify_rotation_certificate("BROT")
+
+      assert_raise ArgumentError, fn ->
+        CryptoNif.build_rotation_certificate(:dilithium2, other_pk, old_sk, new_pk, 1, "wrong old key")
+      end
+    end
+  end
+
+  describe "key export" do
+    test "round-trips a keypair through an encrypted blob" do
+      {pk, sk} = CryptoNif.falcon512_keypair()
+      blob = CryptoNif.export_keypair(pk, sk, "validator passphrase")
+
+      assert <<"BKEX", 1, 2, _::binary>> = blob
+      assert :binary.match(blob, sk) == :nomatch
+      assert CryptoNif.import_keypair(blob, "validator passphrase") == {pk, sk}
+    end
+
+    test "rejects a wrong passphrase or a tampered blob" do
+      {pk, sk} = CryptoNif.sphincsplus_shake_128f_keypair()
+      blob = CryptoNif.export_keypair(pk, sk, "right")
+
+      assert {:error, _} = CryptoNif.import_keypair(blob, "wrong")
+
+      <<head::binary-size(byte_size(blob) - 1), last>> = blob
+      assert {:error, _} = CryptoNif.import_keypair(<<head::binary, Bitwise.bxor(last, 1)>>, "right")
+      assert {:error, _} = CryptoNif.import_keypair("garbage", "right")
+    end
+
+    test "rejects keys of unknown algorithms" do
+      assert_raise ArgumentError, fn -> CryptoNif.export_keypair("pk", "sk", "passphrase") end
+    end
+  end
+
+  describe "remote signing" do
+    # Needs the NIF built with the remote-signer feature: mix test --include remote_signer
+    @describetag :remote_signer
+
+    setup do
+      # Unix socket paths are limited to ~100 bytes, so stay out of the deep tmp_dir
+      socket_path = Path.join(System.tmp_dir!(), "bastille-signer-#{System.unique_integer([:positive])}.sock")
+      {pk, sk} = CryptoNif.dilithium2_keypair()
+      {:ok, listener} = :gen_tcp.listen(0, [:binary, ifaddr: {:local, socket_path}, active: false, packet: :raw])
+      signer = spawn_link(fn -> serve_signer(listener, %{"validator-1" => sk}) end)
+
+      on_exit(fn ->
+        Process.exit(signer, :kill)
+        File.rm(socket_path)
+      end)
+
+      %{socket_path: socket_path, public_key: pk}
+    end
+
+    test "*_sign delegates to the signer daemon", %{socket_path: socket_path, public_key: pk} do
+      handle = CryptoNif.remote_signer_key(socket_path, :dilithium2, "validator-1")
+      signature = CryptoNif.dilithium2_sign("block header", handle)
+
+      assert CryptoNif.dilithium2_verify("block header", signature, pk)
+    end
+
+    test "surfaces signer refusals and unreachable daemons", %{socket_path: socket_path} do
+      unknown = CryptoNif.remote_signer_key(socket_path, :dilithium2, "validator-2")
+      assert {:error, "signer refused: unknown key"} = CryptoNif.dilithium2_sign("msg", unknown)
+
+      missing = CryptoNif.remote_signer_key(socket_path <> ".missing", :dilithium2, "validator-1")
+      assert {:error, _reason} = CryptoNif.dilithium2_sign("msg", missing)
+    end
+
+    test "rejects a handle for another algorithm", %{socket_path: socket_path} do
+      handle = CryptoNif.remote_signer_key(socket_path, :falcon512, "validator-1")
+      assert_raise ArgumentError, fn -> CryptoNif.dilithium2_sign("msg", handle) end
+      assert_raise ArgumentError, fn -> CryptoNif.remote_signer_key(socket_path, :dilithium2, "") end
+    end
+  end
+
+  describe "distributed key generation" do
+    test "any threshold of key shares recovers the same keypair" do
+      [a, b, c, d, e] = run_ceremony("treasury-2026", 3, 5)
+
+      {pk, sk} = CryptoNif.dkg_combine(:dilithium2, [a, b, c])
+      assert CryptoNif.dkg_combine(:dilithium2, [e, c, a]) == {pk, sk}
+      assert CryptoNif.dkg_combine(:dilithium2, [b, d, e, a]) == {pk, sk}
+      assert CryptoNif.dilithium2_verify("treasury", CryptoNif.dilithium2_sign("treasury", sk), pk)
+
+      assert {:error, _} = CryptoNif.dkg_combine(:dilithium2, [a, b])
+      assert {:error, _} = CryptoNif.dkg_combine(:dilithium2, [a, a, b])
+    end
+
+    test "ceremonies yield independent keys" do
+      shares_1 = run_ceremony("ceremony", 2, 2)
+      shares_2 = run_ceremony("ceremony", 2, 2)
+
+      refute CryptoNif.dkg_combine(:falcon512, shares_1) == CryptoNif.dkg_combine(:falcon512, shares_2)
+    end
+
+    test "rejects misrouted, duplicate and foreign shares" do
+      {session_1, [{2, share_for_2}, {3, share_for_3}]} = CryptoNif.dkg_new("ceremony", 2, 3, 1)
+      {session_2, _shares} = CryptoNif.dkg_new("ceremony", 2, 3, 2)
+      {_other, [{2, foreign}, _]} = CryptoNif.dkg_new("other ceremony", 2, 3, 1)
+
+      assert {:error, _} = CryptoNif.dkg_receive(session_2, share_for_3)
+      assert {:error, _} = CryptoNif.dkg_receive(session_2, foreign)
+      assert CryptoNif.dkg_receive(session_2, share_for_2) == :ok
+      assert {:error, _} = CryptoNif.dkg_receive(session_2, share_for_2)
+      assert {:error, "missing shares from parties 3"} = CryptoNif.dkg_finalize(session_2)
+      assert {:error, "missing shares from parties 2, 3"} = CryptoNif.dkg_finalize(session_1)
+    end
+
+    test "rejects invalid parameters" do
+      assert_raise ArgumentError, fn -> CryptoNif.dkg_new("ceremony", 1, 3, 1) end
+      assert_raise ArgumentError, fn -> CryptoNif.dkg_new("ceremony", 4, 3, 1) end
+      assert_raise ArgumentError, fn -> CryptoNif.dkg_new("ceremony", 2, 3, 4) end
+      assert_raise ArgumentError, fn -> CryptoNif.dkg_new("", 2, 3, 1) end
+    end
+  end
+
+  describe "verifiable random function" do
+    # RFC 9381, appendix B.3, example 16
+    @vrf_sk Base.decode16!("9D61B19DEFFD5A60BA844AF492EC2CC44449C5697B326919703BAC031CAE7F60")
+    @vrf_pk Base.decode16!("D75A980182B10AB7D54BFED3C964073A0EE172F3DAA62325AF021A68F707511A")
+    @vrf_proof Base.decode16!(
+                 "8657106690B5526245A92B003BB079CCD1A92130477671F6FC01AD16F26F723F26F8A57CCAED74EE1B190BED1F479D97" <>
+                   "27D2D0F9B005A6E456A35D4FB0DAAB1268A1B0DB10836D9826A528CA76567805"
+               )
+    @vrf_output Base.decode16!(
+                  "90CF1DF3B703CCE59E2A35B925D411164068269D7B2D29F3301C03DD757876FF" <>
+                    "66B71DDA49D2DE59D03450451AF026798E8F81CD2E333DE5CDF4F3E140FDD8AE"
+                )
+
+    test "matches the RFC 9381 test vector" do
+      assert CryptoNif.vrf_public_key(@vrf_sk) == @vrf_pk
+      assert CryptoNif.vrf_prove(@vrf_sk, "") == {@vrf_output, @vrf_proof}
+      assert CryptoNif.vrf_verify(@vrf_pk, "", @vrf_proof) == @vrf_output
+      assert CryptoNif.vrf_proof_to_hash(@vrf_proof) == @vrf_output
+    end
+
+    test "proofs verify only for their key and input" do
+      {pk, sk} = CryptoNif.vrf_keypair()
+      {other_pk, _other_sk} = CryptoNif.vrf_keypair()
+      input = "epoch seed" <> <<42::64>>
+
+      {output, proof} = CryptoNif.vrf_prove(sk, input)
+      assert byte_size(output) == 64
+      assert CryptoNif.vrf_prove(sk, input) == {output, proof}
+      assert CryptoNif.vrf_verify(pk, input, proof) == output
+
+      assert {:error, _} = CryptoNif.vrf_verify(pk, "epoch seed" <> <<43::64>>, proof)
+      assert {:error, _} = CryptoNif.vrf_verify(other_pk, input, proof)
+      <<head::binary-size(40), byte, rest::binary>> = proof
+      assert {:error, _} = CryptoNif.vrf_verify(pk, input, head <> <<Bitwise.bxor(byte, 1)>> <> rest)
+    end
+
+    test "rejects small-order keys and malformed arguments" do
+      identity = <<1>> <> :binary.copy(<<0>>, 31)
+      assert {:error, _} = CryptoNif.vrf_verify(identity, "", @vrf_proof)
+
+      assert_raise ArgumentError, fn -> CryptoNif.vrf_public_key(<<0::248>>) end
+      assert_raise ArgumentError, fn -> CryptoNif.vrf_verify(@vrf_pk, "", binary_part(@vrf_proof, 0, 79)) end
+    end
+  end
+
+  describe "verifiable delay function" do
+    test "evaluates and verifies" do
+      evaluation = CryptoNif.vdf_eval("epoch 7 seed", 500)
+      assert {:done, output, proof} = await_vdf(evaluation)
+      assert byte_size(output) == 130 and byte_size(proof) == 130
+
+      assert CryptoNif.vdf_verify("epoch 7 seed", 500, output, proof)
+      refute CryptoNif.vdf_verify("epoch 7 seed", 501, output, proof)
+      refute CryptoNif.vdf_verify("epoch 8 seed", 500, output, proof)
+      refute CryptoNif.vdf_verify("epoch 7 seed", 500, proof, output)
+
+      <<head::binary-size(100), byte, rest::binary>> = proof
+      refute CryptoNif.vdf_verify("epoch 7 seed", 500, output, head <> <<Bitwise.bxor(byte, 1)>> <> rest)
+    end
+
+    test "reports progress and can be cancelled" do
+      evaluation = CryptoNif.vdf_eval("long seed", 100_000_000)
+      assert {:running, done, 200_000_000} = CryptoNif.vdf_progress(evaluation)
+      assert done < 200_000_000
+
+      assert CryptoNif.vdf_cancel(evaluation) == :ok
+      assert await_vdf(evaluation) == {:error, "cancelled"}
+    end
+
+    test "rejects invalid arguments" do
+      assert_raise ArgumentError, fn -> CryptoNif.vdf_eval("seed", 0) end
+      assert_raise ArgumentError, fn -> CryptoNif.vdf_eval(:binary.copy("s", 1025), 10) end
+      assert_raise ArgumentError, fn -> CryptoNif.vdf_verify("seed", 10, <<0::1040>>, <<0::1032>>) end
+      refute CryptoNif.vdf_verify("seed", 10, <<0::1040>>, <<0::1040>>)
+    end
+  end
+
+  describe "secret hygiene" do
+    test "secure_wipe zeroes a binary in place" do
+      {_pk, sk} = CryptoNif.dilithium2_keypair()
+
+      assert CryptoNif.secure_wipe(sk) == :ok
+      assert sk == :binary.copy(<<0>>, byte_size(sk))
     end
   end
 
@@ -135,6 +3113,230 @@ defmodule Bastille.Infrastructure.Crypto.CryptoNifTest do
     end
   end
 
+  describe "deterministic key derivation" do
+    @seed :binary.copy(<<7>>, 32)
+
+    test "reports the derivation spec version" do
+      assert CryptoNif.keygen_spec_version() == "bastille-keygen-v1"
+    end
+
+    # These pin the PQClean internals keygen.rs calls: rerun them on any pqcrypto bump
+    test "derived keys match the bastille-keygen-v1 known answers" do
+      {dil_pk, dil_sk} = CryptoNif.dilithium2_keypair_from_seed(@seed)
+      {fal_pk, fal_sk} = CryptoNif.falcon512_keypair_from_seed(@seed)
+      {sph_pk, sph_sk} = CryptoNif.sphincsplus_keypair_from_seed(@seed)
+      hex_hash = &Base.encode16(CryptoNif.blake3_hash(&1), case: :lower)
+
+      assert hex_hash.(dil_pk) == "7b7ee353ce25e661e42dbf49f278d41860ff0b51e55fc9f193e7078cc1784d2b"
+      assert hex_hash.(dil_sk) == "53b0097e5d443873ebd4aef0252ca03e31d487022e532e707bfcbb967291e7af"
+
+      assert hex_hash.(fal_pk) == "3bdc2a9a78ec4508c0d97700f56d32b5bbcf2e01fc0bc88724fbfb1daa4f8b67"
+      assert hex_hash.(fal_sk) == "a79dc90955cacb975d4cca8a2ce4b323aaffb24368fabd73c5b5ebdfff4f7b06"
+
+      assert hex_hash.(sph_pk) == "89ba50c15cc2ffe5364cea234f3cf79c70262edbddfdd988bd969ab822722114"
+      assert hex_hash.(sph_sk) == "2508efbf0d9456743f2c7b2fb8b197a49236ebf73b52b80488e9ed6cdb30b556"
+    end
+
+    test "derived keys sign and verify" do
+      message = "derived key signature"
+
+      {pk, sk} = CryptoNif.dilithium2_keypair_from_seed(@seed)
+      assert CryptoNif.dilithium2_verify(CryptoNif.dilithium2_sign(message, sk), message, pk)
+
+      {pk, sk} = CryptoNif.falcon512_keypair_from_seed(@seed)
+      assert CryptoNif.falcon512_verify(CryptoNif.falcon512_sign(message, sk), message, pk)
+
+      {pk, sk} = CryptoNif.sphincsplus_keypair_from_seed(@seed)
+      assert CryptoNif.sphincsplus_shake_128f_verify(CryptoNif.sphincsplus_shake_128f_sign(message, sk), message, pk)
+    end
+
+    test "different seeds give different keys" do
+      {pk1, _} = CryptoNif.dilithium2_keypair_from_seed(:binary.copy(<<1>>, 32))
+      {pk2, _} = CryptoNif.dilithium2_keypair_from_seed(:binary.copy(<<2>>, 32))
+
+      assert pk1 != pk2
+    end
+
+    test "rejects seeds shorter than 16 bytes" do
+      assert_raise ArgumentError, fn -> CryptoNif.dilithium2_keypair_from_seed("short") end
+    end
+
+    test "derives hardened child seeds" do
+      <<key::binary-32, chain_code::binary-32>> = :crypto.mac(:hmac, :sha512, "Bastille PQ seed", @seed)
+      assert CryptoNif.derive_child_seed(@seed, []) == key
+
+      <<child::binary-32, _::binary>> =
+        :crypto.mac(:hmac, :sha512, chain_code, <<0, key::binary, 0x80000000 + 44::32>>)
+
+      assert CryptoNif.derive_child_seed(@seed, [44]) == child
+      assert CryptoNif.derive_child_seed(@seed, [44, 0]) != CryptoNif.derive_child_seed(@seed, [44, 1])
+
+      {pk1, _} = CryptoNif.falcon512_keypair_from_seed(CryptoNif.derive_child_seed(@seed, [1, 0]))
+      {pk2, _} = CryptoNif.falcon512_keypair_from_seed(CryptoNif.derive_child_seed(@seed, [1, 0]))
+      assert pk1 == pk2
+    end
+
+    test "rejects invalid child derivation input" do
+      assert_raise ArgumentError, fn -> CryptoNif.derive_child_seed("short", [0]) end
+      assert_raise ArgumentError, fn -> CryptoNif.derive_child_seed(@seed, [0x80000000]) end
+    end
+  end
+
+  describe "persistent key cache" do
+    @describetag :tmp_dir
+    @seed :binary.copy(<<7>>, 32)
+
+    setup %{tmp_dir: tmp_dir} do
+      cache_dir = Path.join(tmp_dir, "key_cache")
+      # No in-memory layer, so every lookup below exercises the files
+      :ok = CryptoNif.configure_key_cache(cache_dir, key: :binary.copy(<<0xAB>>, 32), memory_entries: 0)
+      on_exit(fn -> CryptoNif.configure_key_cache(nil, []) end)
+
+      {:ok, cache_dir: cache_dir}
+    end
+
+    test "caches keypairs encrypted at rest", %{cache_dir: cache_dir} do
+      {pk, sk} = CryptoNif.dilithium2_keypair_from_seed(@seed)
+      [entry] = Path.wildcard(Path.join(cache_dir, "*.keypair"))
+      stored = File.read!(entry)
+
+      assert :binary.match(stored, sk) == :nomatch
+      assert :binary.match(stored, pk) == :nomatch
+      assert CryptoNif.dilithium2_keypair_from_seed(@seed) == {pk, sk}
+    end
+
+    test "cache entries carry a versioned header and recover from corruption", %{cache_dir: cache_dir} do
+      keypair = CryptoNif.sphincsplus_keypair_from_seed(@seed)
+      [entry] = Path.wildcard(Path.join(cache_dir, "*.keypair"))
+      assert <<"BKYC", 2, 3, _::binary>> = File.read!(entry)
+
+      <<head::binary-40, byte, rest::binary>> = File.read!(entry)
+      File.write!(entry, <<head::binary, Bitwise.bxor(byte, 1), rest::binary>>)
+
+      assert CryptoNif.sphincsplus_keypair_from_seed(@seed) == keypair
+      assert <<"BKYC", 2, 3, _::binary>> = File.read!(entry)
+    end
+
+    test "concurrent cache writes leave one complete entry", %{cache_dir: cache_dir} do
+      keypairs =
+        1..8
+        |> Task.async_stream(fn _ -> CryptoNif.falcon512_keypair_from_seed(@seed) end)
+        |> Enum.map(fn {:ok, keypair} -> keypair end)
+
+      assert keypairs |> Enum.uniq() |> length() == 1
+      assert [_entry] = Path.wildcard(Path.join(cache_dir, "*.keypair"))
+      assert Path.wildcard(Path.join(cache_dir, "*.tmp.*")) == []
+      assert CryptoNif.falcon512_keypair_from_seed(@seed) == hd(keypairs)
+    end
+
+    test "passphrase keys are salted per directory", %{tmp_dir: tmp_dir} do
+      other_dir = Path.join(tmp_dir, "other_cache")
+      assert CryptoNif.configure_key_cache(other_dir, passphrase: "correct horse") == :ok
+
+      keypair = CryptoNif.falcon512_keypair_from_seed(@seed)
+      assert File.exists?(Path.join(other_dir, "cache.salt"))
+      assert CryptoNif.falcon512_keypair_from_seed(@seed) == keypair
+    end
+
+    test "writes nothing once disabled", %{cache_dir: cache_dir} do
+      assert CryptoNif.configure_key_cache(nil, []) == :ok
+
+      CryptoNif.dilithium2_keypair_from_seed(@seed)
+      assert Path.wildcard(Path.join(cache_dir, "*.keypair")) == []
+    end
+
+    test "evicts the least recently used entries", %{cache_dir: cache_dir} do
+      :ok =
+        CryptoNif.configure_key_cache(cache_dir,
+          key: :binary.copy(<<0xAB>>, 32),
+          max_entries: 2,
+          memory_entries: 0
+        )
+
+      first = CryptoNif.sphincsplus_keypair_from_seed(:binary.copy(<<1>>, 32))
+      Process.sleep(20)
+      CryptoNif.sphincsplus_keypair_from_seed(:binary.copy(<<2>>, 32))
+      Process.sleep(20)
+      # Reading the first entry makes the second one the least recently used
+      assert CryptoNif.sphincsplus_keypair_from_seed(:binary.copy(<<1>>, 32)) == first
+      Process.sleep(20)
+      CryptoNif.sphincsplus_keypair_from_seed(:binary.copy(<<3>>, 32))
+
+      entry = fn seed ->
+        name = Base.encode16(CryptoNif.tagged_hash("bastille-keygen-v1/sphincsplus", seed), case: :lower)
+        Path.join(cache_dir, name <> ".keypair")
+      end
+
+      assert length(Path.wildcard(Path.join(cache_dir, "*.keypair"))) == 2
+      refute File.exists?(entry.(:binary.copy(<<2>>, 32)))
+      refute File.exists?(entry.(:binary.copy(<<2>>, 32)) <> ".lock")
+      assert File.exists?(entry.(:binary.copy(<<1>>, 32)))
+    end
+
+    test "purges every entry", %{cache_dir: cache_dir} do
+      CryptoNif.dilithium2_keypair_from_seed(@seed)
+      CryptoNif.falcon512_keypair_from_seed(@seed)
+      # Lock files outlive writes and only go with their entries
+      assert length(Path.wildcard(Path.join(cache_dir, "*.keypair.lock"))) == 2
+
+      assert CryptoNif.purge_key_cache() == 2
+      assert Path.wildcard(Path.join(cache_dir, "*.keypair")) == []
+      assert Path.wildcard(Path.join(cache_dir, "*.keypair.lock")) == []
+    end
+
+    test "serves repeated derivations from memory", %{cache_dir: cache_dir} do
+      :ok = CryptoNif.configure_key_cache(cache_dir, key: :binary.copy(<<0xAB>>, 32), memory_entries: 1)
+      seed = :binary.copy(<<9>>, 32)
+      before = CryptoNif.key_cache_stats()
+
+      keypair = CryptoNif.dilithium2_keypair_from_seed(seed)
+      assert CryptoNif.dilithium2_keypair_from_seed(seed) == keypair
+
+      stats = CryptoNif.key_cache_stats()
+      assert stats.memory_hits > before.memory_hits
+      assert stats.misses > before.misses
+      assert stats.memory_entries <= 1
+    end
+
+    test "migrates plaintext entries from before encryption", %{tmp_dir: tmp_dir, cache_dir: cache_dir} do
+      plant = fn dir ->
+        {pk, sk} = CryptoNif.dilithium2_keypair()
+        entry = Path.join(dir, Base.encode16(:crypto.strong_rand_bytes(32), case: :lower) <> ".keypair")
+        File.mkdir_p!(dir)
+        File.write!(entry, <<byte_size(pk)::little-32, pk::binary, sk::binary>>)
+        {entry, sk}
+      end
+
+      # Left alone while the cache can't be enabled for want of a key
+      keyless_dir = Path.join(tmp_dir, "keyless_cache")
+      {entry, sk} = plant.(keyless_dir)
+      assert {:error, _} = CryptoNif.configure_key_cache(keyless_dir, key: "short")
+      assert File.exists?(entry)
+      assert CryptoNif.key_cache_legacy_entries(keyless_dir) == 1
+
+      # Sealed into a .legacy file once there is a key, then wiped
+      :ok = CryptoNif.configure_key_cache(keyless_dir, key: :binary.copy(<<0xAB>>, 32), memory_entries: 0)
+      refute File.exists?(entry)
+      legacy = String.replace_suffix(entry, ".keypair", ".legacy")
+      sealed = File.read!(legacy)
+      assert <<"BKYC", 2, _algorithm, _::binary>> = sealed
+      assert :binary.match(sealed, sk) == :nomatch
+      assert CryptoNif.key_cache_legacy_entries(keyless_dir) == 0
+
+      # Purging and evicting the cache never touch migrated entries
+      {entry, _sk} = plant.(cache_dir)
+      :ok = CryptoNif.configure_key_cache(cache_dir, key: :binary.copy(<<0xAB>>, 32), memory_entries: 0)
+      CryptoNif.falcon512_keypair_from_seed(@seed)
+      assert CryptoNif.purge_key_cache() == 1
+      assert File.exists?(String.replace_suffix(entry, ".keypair", ".legacy"))
+    end
+
+    test "rejects a malformed cache key", %{tmp_dir: tmp_dir} do
+      assert {:error, _reason} = CryptoNif.configure_key_cache(tmp_dir, key: "short")
+      assert_raise ArgumentError, fn -> CryptoNif.configure_key_cache(tmp_dir, unknown: 1) end
+    end
+  end
+
   describe "post-quantum signatures" do
     test "signs and verifies with Dilithium" do
       try do
@@ -372,4 +3574,175 @@ defmodule Bastille.Infrastructure.Crypto.CryptoNifTest do
       end
     end
   end
+
+  # Functions CryptoNif stubs out for the NIF library to replace
+  defp nif_stub_names do
+    CryptoNif.module_info(:compile)[:source]
+    |> File.read!()
+    |> then(&Regex.scan(~r/def (\w+)(?:\([^)]*\))?,\s*do: :erlang\.nif_error\(:nif_not_loaded\)/, &1))
+    |> Enum.map(fn [_, name] -> String.to_atom(name) end)
+    |> Enum.uniq()
+  end
+
+  defp sample_block(height) do
+    txs =
+      for i <- 1..5 do
+        ~s({"from":"1B#{rem(height * 7 + i, 97)}","to":"1B#{rem(height + i * 13, 89)}","amount":#{height * i},"nonce":#{i}})
+      end
+
+    ~s({"height":#{height},"version":1,"timestamp":#{1_700_000_000 + height * 12},"transactions":[#{Enum.join(txs, ",")}]})
+  end
+
+  defp reference_merkle_root(leaves) do
+    leaves
+    |> Enum.map(&CryptoNif.blake3_hash(<<0>> <> &1))
+    |> reduce_merkle_levels()
+  end
+
+  defp reduce_merkle_levels([root]), do: root
+
+  defp reduce_merkle_levels(level) do
+    level
+    |> Enum.chunk_every(2)
+    |> Enum.map(fn
+      [left, right] -> CryptoNif.blake3_hash(<<1>> <> left <> right)
+      [single] -> single
+    end)
+    |> reduce_merkle_levels()
+  end
+
+  # Runs a full ceremony, returning every party's key share
+  defp run_ceremony(session_id, threshold, parties) do
+    started = for index <- 1..parties, do: CryptoNif.dkg_new(session_id, threshold, parties, index)
+    sessions = Enum.map(started, &elem(&1, 0))
+
+    for {_session, shares} <- started, {recipient, share} <- shares do
+      assert CryptoNif.dkg_receive(Enum.at(sessions, recipient - 1), share) == :ok
+    end
+
+    Enum.map(sessions, &CryptoNif.dkg_finalize/1)
+  end
+
+  # Opens a store once its previous owner's resource is released, retrying up to `attempts` times
+  defp wait_for_reopen(dir, attempts, open \\ &CryptoNif.smt_open/1) do
+    case open.(dir) do
+      {:error, _} when attempts > 0 ->
+        Process.sleep(10)
+        wait_for_reopen(dir, attempts - 1, open)
+
+      tree ->
+        tree
+    end
+  end
+
+  defp genesis_header do
+    %{
+      height: 0,
+      previous_hash: <<0::256>>,
+      timestamp: 1_700_000_000_000,
+      merkle_root: CryptoNif.blake3_hash("genesis"),
+      bits: @easy_bits,
+      nonce: 0,
+      producer_algorithm: nil,
+      producer_key: nil,
+      signature: ""
+    }
+  end
+
+  defp child_header(parent_bytes, {pk, sk}, changes \\ %{}) do
+    parent = CryptoNif.header_decode(parent_bytes)
+
+    header =
+      Map.merge(
+        %{
+          height: parent.height + 1,
+          previous_hash: parent.hash,
+          timestamp: parent.timestamp + 10_000,
+          merkle_root: CryptoNif.blake3_hash("block #{parent.height + 1}"),
+          bits: @easy_bits,
+          nonce: 0,
+          producer_algorithm: :falcon512,
+          producer_key: pk
+        },
+        changes
+      )
+
+    header = with_work(header)
+    hash = CryptoNif.header_hash(CryptoNif.header_encode(header))
+    CryptoNif.header_encode(Map.put(header, :signature, CryptoNif.falcon512_sign(hash, sk)))
+  end
+
+  defp with_work(header) do
+    {:ok, target} = CryptoNif.target_from_compact(header.bits)
+
+    if CryptoNif.target_meets(CryptoNif.header_hash(CryptoNif.header_encode(header)), target) do
+      header
+    else
+      with_work(%{header | nonce: header.nonce + 1})
+    end
+  end
+
+  defp await_vdf(evaluation) do
+    case CryptoNif.vdf_progress(evaluation) do
+      {:running, _done, _total} ->
+        Process.sleep(10)
+        await_vdf(evaluation)
+
+      result ->
+        result
+    end
+  end
+
+  # Minimal signer daemon for the remote signing tests
+  defp serve_signer(listener, keys) do
+    {:ok, socket} = :gen_tcp.accept(listener)
+    {:ok, <<1, 1, key_id_len::16>>} = :gen_tcp.recv(socket, 4)
+    {:ok, key_id} = :gen_tcp.recv(socket, key_id_len)
+    {:ok, <<message_len::32>>} = :gen_tcp.recv(socket, 4)
+    {:ok, message} = if message_len > 0, do: :gen_tcp.recv(socket, message_len), else: {:ok, ""}
+
+    reply =
+      case Map.fetch(keys, key_id) do
+        {:ok, sk} ->
+          signature = CryptoNif.dilithium2_sign(message, sk)
+          <<0, byte_size(signature)::32, signature::binary>>
+
+        :error ->
+          <<1, byte_size("unknown key")::32, "unknown key">>
+      end
+
+    :ok = :gen_tcp.send(socket, reply)
+    :gen_tcp.close(socket)
+    serve_signer(listener, keys)
+  end
+
+  # Serves contract storage from a map, telling `observer` of each put
+  defp serve_storage(state, observer) do
+    receive do
+      {:wasm_storage, :get, reply, key} ->
+        :ok = CryptoNif.wasm_storage_reply(reply, Map.get(state, key))
+        assert {:error, _} = CryptoNif.wasm_storage_reply(reply, nil)
+        serve_storage(state, observer)
+
+      {:wasm_storage, :put, key, value} ->
+        send(observer, {:stored, key, value})
+        serve_storage(Map.put(state, key, value), observer)
+    end
+  end
+
+  # Bytes as a WebAssembly text string
+  defp wat_bytes(bytes), do: for(<<byte <- bytes>>, into: "", do: "\\" <> Base.encode16(<<byte>>))
+
+  # Complete snapshot of `entries`, and the entries back out of one
+  defp snapshot(entries) do
+    encoder = CryptoNif.snapshot_encoder_new(256)
+    CryptoNif.snapshot_encode(encoder, entries) ++ CryptoNif.snapshot_encode_finish(encoder)
+  end
+
+  defp snapshot_entries(chunks) do
+    decoder = CryptoNif.snapshot_decoder_new()
+    entries = Enum.flat_map(chunks, &CryptoNif.snapshot_decode(decoder, &1))
+    assert is_integer(CryptoNif.snapshot_decode_finish(decoder))
+    entries
+  end
 end
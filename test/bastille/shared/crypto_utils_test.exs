@@ -52,6 +52,22 @@ defmodule Bastille.Shared.CryptoUtilsTest do
     end
   end
 
+  describe "random bytes" do
+    test "random returns the requested number of bytes" do
+      assert byte_size(CryptoUtils.random()) == 32
+      assert CryptoUtils.random(0) == ""
+      assert CryptoUtils.random(16) != CryptoUtils.random(16)
+    end
+
+    test "random serves requests over the native 1 MiB limit" do
+      bytes = CryptoUtils.random(3 * 1_048_576 + 5)
+
+      assert byte_size(bytes) == 3 * 1_048_576 + 5
+      # Chunks are independent draws, not repeats
+      refute binary_part(bytes, 0, 32) == binary_part(bytes, 1_048_576, 32)
+    end
+  end
+
   describe "edge cases" do
     test "sha256 with very large input" do
       large_input = String.duplicate("a", 10000)
@@ -6,7 +6,13 @@
 # rest of the suite when run alongside unit tests. Run them on demand with:
 #   mix test --include integration
 # or in a dedicated CI step.
-ExUnit.start(exclude: [:integration])
+# Remote signer tests need the NIF built with the remote-signer feature
+# (see :crypto_nif_features) and run with --include remote_signer; likewise
+# the Verkle tree tests with the verkle feature and --include verkle, and the
+# storage tests with the rocksdb or lmdb feature and --include rocksdb/lmdb,
+# the RandomX tests with the randomx feature and --include randomx, and the
+# WebAssembly tests with the wasm feature and --include wasm.
+ExUnit.start(exclude: [:integration, :remote_signer, :verkle, :rocksdb, :lmdb, :randomx, :wasm])
 
 # Configure test logger
 Logger.configure(level: :warning)